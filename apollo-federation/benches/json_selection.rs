@@ -0,0 +1,84 @@
+//! Parse a JSON selection mapping a REST response to GraphQL fields, and apply it to large
+//! lists.
+//!
+//! Run with `cargo bench -p apollo-federation --features benchmarks --bench json_selection`
+
+use apollo_federation::benchmarks::ApplyTo;
+use apollo_federation::benchmarks::JSONSelection;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use serde_json_bytes::json;
+use serde_json_bytes::Value;
+
+const SELECTION: &str = r#"
+products: items {
+  upc: id
+  name: title
+  price
+  inStock: in_stock
+  reviews {
+    id
+    body: text
+    author: user {
+      id
+      name: display_name
+    }
+  }
+}
+"#;
+
+/// A REST response listing products, with fields the selection doesn't map
+fn rest_response(items: usize) -> Value {
+    let items: Vec<Value> = (0..items)
+        .map(|id| {
+            let reviews: Vec<Value> = (0..3)
+                .map(|i| {
+                    json!({
+                        "id": format!("{id}-{i}"),
+                        "text": format!("Review {id}-{i}"),
+                        "rating": i,
+                        "user": { "id": i.to_string(), "display_name": format!("User {i}"), "email": "user@example.com" },
+                    })
+                })
+                .collect();
+            json!({
+                "id": id.to_string(),
+                "title": format!("Product {id}"),
+                "price": 100 + id % 900,
+                "in_stock": id % 3 != 0,
+                "created_at": "2024-01-01T00:00:00Z",
+                "reviews": reviews,
+            })
+        })
+        .collect();
+    json!({ "items": items, "next_page": null })
+}
+
+fn json_selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("json_selection");
+    group.bench_function("parse", |b| {
+        b.iter(|| JSONSelection::parse_checked(SELECTION).unwrap())
+    });
+
+    let selection = JSONSelection::parse_checked(SELECTION).unwrap();
+    for items in [100, 1000] {
+        let response = rest_response(items);
+        group.bench_with_input(
+            BenchmarkId::new("apply_to", items),
+            &response,
+            |b, response| {
+                b.iter(|| {
+                    let (value, errors) = selection.apply_to(response);
+                    assert!(errors.is_empty());
+                    value
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, json_selection);
+criterion_main!(benches);
@@ -28,9 +28,18 @@ pub(crate) mod operation;
 pub mod query_graph;
 pub mod query_plan;
 pub mod schema;
+pub(crate) mod sources;
 pub mod subgraph;
 pub(crate) mod utils;
 
+/// The connectors sources, for the benchmarks of the crate only
+#[cfg(feature = "benchmarks")]
+#[doc(hidden)]
+pub mod benchmarks {
+    pub use crate::sources::connect::ApplyTo;
+    pub use crate::sources::connect::JSONSelection;
+}
+
 use apollo_compiler::ast::NamedType;
 use apollo_compiler::validation::Valid;
 use apollo_compiler::Schema;
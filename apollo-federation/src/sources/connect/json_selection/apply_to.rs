@@ -11,6 +11,8 @@ use serde_json_bytes::Map;
 use serde_json_bytes::Value as JSON;
 
 use super::helpers::json_type_name;
+use super::methods::lookup_arrow_method;
+use super::methods::try_fallback_arg;
 use super::parser::*;
 
 pub trait ApplyTo {
@@ -85,7 +87,7 @@ impl Hash for ApplyToError {
 }
 
 impl ApplyToError {
-    fn new(message: &str, path: &[JSON]) -> Self {
+    pub(super) fn new(message: &str, path: &[JSON]) -> Self {
         Self(json!({
             "message": message,
             "path": JSON::Array(path.to_vec()),
@@ -93,7 +95,7 @@ impl ApplyToError {
     }
 
     #[cfg(test)]
-    fn from_json(json: &JSON) -> Self {
+    pub(super) fn from_json(json: &JSON) -> Self {
         if let JSON::Object(error) = json {
             if let Some(JSON::String(message)) = error.get("message") {
                 if let Some(JSON::Array(path)) = error.get("path") {
@@ -251,18 +253,162 @@ impl ApplyTo for PathSelection {
         errors: &mut IndexSet<ApplyToError>,
     ) -> Option<JSON> {
         if let JSON::Array(array) = data {
-            return self.apply_to_array(array, vars, input_path, errors);
+            if !self.applies_to_whole_array() {
+                return self.apply_to_array(array, vars, input_path, errors);
+            }
+        }
+
+        // At the start of a path, $ refers to the data the path is applied to.
+        self.apply_to_path_with_try(data, data, vars, input_path, errors)
+    }
+}
+
+impl PathSelection {
+    // Splits the path at its first ->try or ->catch step, returning a copy of
+    // the steps before it, the name and arguments of the step, and the rest of
+    // the path after it.
+    fn split_at_try(&self) -> Option<(Self, &str, Option<&MethodArgs>, &Self)> {
+        let (prefix, method_name, method_args, rest) = match self {
+            Self::Method(method_name, method_args, tail)
+                if method_name == "try" || method_name == "catch" =>
+            {
+                return Some((Self::Empty, method_name, method_args.as_ref(), tail));
+            }
+            Self::Var(var_name, tail) => {
+                let (prefix, method_name, method_args, rest) = tail.split_at_try()?;
+                (
+                    Self::Var(var_name.clone(), Box::new(prefix)),
+                    method_name,
+                    method_args,
+                    rest,
+                )
+            }
+            Self::Key(key, tail) => {
+                let (prefix, method_name, method_args, rest) = tail.split_at_try()?;
+                (
+                    Self::Key(key.clone(), Box::new(prefix)),
+                    method_name,
+                    method_args,
+                    rest,
+                )
+            }
+            Self::Method(other_name, other_args, tail) => {
+                let (prefix, method_name, method_args, rest) = tail.split_at_try()?;
+                (
+                    Self::Method(other_name.clone(), other_args.clone(), Box::new(prefix)),
+                    method_name,
+                    method_args,
+                    rest,
+                )
+            }
+            Self::Selection(_) | Self::Empty => return None,
+        };
+        Some((prefix, method_name, method_args, rest))
+    }
+
+    // Unlike other methods, ->try and ->catch handle errors from the steps
+    // that precede them, so they cannot be applied to the value produced by
+    // those steps. Instead, the steps before ->try are applied with their own
+    // error set, and if they fail (or produce no value), the errors are
+    // discarded and the fallback argument (if any) takes their place.
+    fn apply_to_path_with_try(
+        &self,
+        data: &JSON,
+        dollar: &JSON,
+        vars: &IndexMap<String, JSON>,
+        input_path: &mut Vec<JSON>,
+        errors: &mut IndexSet<ApplyToError>,
+    ) -> Option<JSON> {
+        let Some((prefix, method_name, method_args, rest)) = self.split_at_try() else {
+            return self.apply_to_path_with_dollar(data, dollar, vars, input_path, errors);
+        };
+        let fallback = try_fallback_arg(method_name, method_args, input_path, errors)?;
+
+        let mut prefix_errors = IndexSet::default();
+        let value = match prefix.apply_to_path_with_dollar(
+            data,
+            dollar,
+            vars,
+            input_path,
+            &mut prefix_errors,
+        ) {
+            Some(value) if prefix_errors.is_empty() => value,
+            _ => fallback?.apply_to_path_with_dollar(data, dollar, vars, input_path, errors)?,
+        };
+
+        // Errors in the rest of the path are reported at the location of the
+        // recovered value, as if the ->try step were not there.
+        let mut rest_path = input_path.clone();
+        prefix.extend_input_path(&mut rest_path);
+        rest.apply_to_path_with_try(&value, dollar, vars, &mut rest_path, errors)
+    }
+
+    // Extends an input path with the keys of the steps of this path, like
+    // apply_to_path_with_dollar does while applying them.
+    fn extend_input_path(&self, input_path: &mut Vec<JSON>) {
+        match self {
+            Self::Var(var_name, tail) => {
+                if var_name != "$" && var_name != "@" {
+                    *input_path = vec![json!(var_name)];
+                }
+                tail.extend_input_path(input_path);
+            }
+            Self::Key(key, tail) => {
+                input_path.push(key.to_json());
+                tail.extend_input_path(input_path);
+            }
+            Self::Method(_, _, tail) => tail.extend_input_path(input_path),
+            Self::Selection(_) | Self::Empty => {}
         }
+    }
 
+    // Methods receive arrays as a whole, rather than being mapped over the
+    // elements, which also holds for $->method and @->method since $ and @
+    // refer to the array itself.
+    fn applies_to_whole_array(&self) -> bool {
         match self {
+            Self::Method(..) => true,
             Self::Var(var_name, tail) => {
-                if var_name == "$" {
-                    // Because $ refers to the current value, we keep using
+                (var_name == "$" || var_name == "@") && matches!(**tail, Self::Method(..))
+            }
+            _ => false,
+        }
+    }
+
+    // Applies the rest of the path to data, while remembering the value of $
+    // from the start of the path, so method arguments like ->get($.key) can
+    // refer to it even after the path has descended into nested data.
+    pub(super) fn apply_to_path_with_dollar(
+        &self,
+        data: &JSON,
+        dollar: &JSON,
+        vars: &IndexMap<String, JSON>,
+        input_path: &mut Vec<JSON>,
+        errors: &mut IndexSet<ApplyToError>,
+    ) -> Option<JSON> {
+        if let JSON::Array(array) = data {
+            if !self.applies_to_whole_array() {
+                let mut output = Vec::with_capacity(array.len());
+                for (i, element) in array.iter().enumerate() {
+                    input_path.push(JSON::Number(i.into()));
+                    let value =
+                        self.apply_to_path_with_dollar(element, dollar, vars, input_path, errors);
+                    input_path.pop();
+                    output.push(value.unwrap_or(JSON::Null));
+                }
+                return Some(JSON::Array(output));
+            }
+        }
+
+        match self {
+            Self::Var(var_name, tail) => {
+                if var_name == "$" || var_name == "@" {
+                    // Because $ and @ refer to the current value, we keep using
                     // input_path instead of creating a new var_path here.
-                    tail.apply_to_path(data, vars, input_path, errors)
+                    tail.apply_to_path_with_dollar(data, dollar, vars, input_path, errors)
                 } else if let Some(var_data) = vars.get(var_name) {
                     let mut var_path = vec![json!(var_name)];
-                    tail.apply_to_path(var_data, vars, &mut var_path, errors)
+                    tail.apply_to_path_with_dollar(var_data, dollar, vars, &mut var_path, errors)
                 } else {
                     errors.insert(ApplyToError::new(
                         format!("Variable {} not found", var_name).as_str(),
@@ -293,7 +439,7 @@ impl ApplyTo for PathSelection {
                     Key::Quoted(name) => data.get(name),
                     Key::Index(index) => data.get(index),
                 } {
-                    tail.apply_to_path(child, vars, input_path, errors)
+                    tail.apply_to_path_with_dollar(child, dollar, vars, input_path, errors)
                 } else {
                     errors.insert(ApplyToError::new(
                         format!(
@@ -311,6 +457,26 @@ impl ApplyTo for PathSelection {
 
                 result
             }
+            Self::Method(method_name, method_args, tail) => {
+                if let Some(method) = lookup_arrow_method(method_name) {
+                    let result = method(
+                        method_name,
+                        method_args.as_ref(),
+                        data,
+                        dollar,
+                        vars,
+                        input_path,
+                        errors,
+                    )?;
+                    tail.apply_to_path_with_dollar(&result, dollar, vars, input_path, errors)
+                } else {
+                    errors.insert(ApplyToError::new(
+                        format!("Method ->{} not found", method_name).as_str(),
+                        input_path,
+                    ));
+                    None
+                }
+            }
             Self::Selection(selection) => {
                 // If data is not an object here, this recursive apply_to_path
                 // call will handle the error.
@@ -325,6 +491,83 @@ impl ApplyTo for PathSelection {
     }
 }
 
+impl ApplyTo for JSLiteral {
+    fn apply_to_path(
+        &self,
+        data: &JSON,
+        vars: &IndexMap<String, JSON>,
+        input_path: &mut Vec<JSON>,
+        errors: &mut IndexSet<ApplyToError>,
+    ) -> Option<JSON> {
+        self.apply_to_path_with_dollar(data, data, vars, input_path, errors)
+    }
+}
+
+impl JSLiteral {
+    // Literal values evaluate to the equivalent JSON, while any PathSelection
+    // values embedded within the literal are applied to the value of $ where
+    // the method was called, except for @ paths, which are applied to data
+    // (the input value of the method, or the element being processed).
+    pub(super) fn apply_to_path_with_dollar(
+        &self,
+        data: &JSON,
+        dollar: &JSON,
+        vars: &IndexMap<String, JSON>,
+        input_path: &mut Vec<JSON>,
+        errors: &mut IndexSet<ApplyToError>,
+    ) -> Option<JSON> {
+        match self {
+            Self::String(string) => Some(JSON::String(string.clone().into())),
+            Self::Number(number) => {
+                let parsed = if let Ok(int) = number.parse::<i64>() {
+                    Some(serde_json::Number::from(int))
+                } else {
+                    number
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(serde_json::Number::from_f64)
+                };
+                if parsed.is_none() {
+                    errors.insert(ApplyToError::new(
+                        format!("Invalid number literal {}", number).as_str(),
+                        input_path,
+                    ));
+                }
+                parsed.map(JSON::Number)
+            }
+            Self::Bool(b) => Some(JSON::Bool(*b)),
+            Self::Null => Some(JSON::Null),
+            Self::Object(properties) => {
+                let mut output = Map::new();
+                for (key, value) in properties {
+                    if let Some(value) =
+                        value.apply_to_path_with_dollar(data, dollar, vars, input_path, errors)
+                    {
+                        output.insert(key.clone(), value);
+                    }
+                }
+                Some(JSON::Object(output))
+            }
+            Self::Array(items) => Some(JSON::Array(
+                items
+                    .iter()
+                    .map(|item| {
+                        item.apply_to_path_with_dollar(data, dollar, vars, input_path, errors)
+                            .unwrap_or(JSON::Null)
+                    })
+                    .collect(),
+            )),
+            Self::Path(path) => {
+                let data = match path {
+                    PathSelection::Var(var_name, _) if var_name == "@" => data,
+                    _ => dollar,
+                };
+                path.apply_to_path_with_try(data, dollar, vars, input_path, errors)
+            }
+        }
+    }
+}
+
 impl ApplyTo for SubSelection {
     fn apply_to_path(
         &self,
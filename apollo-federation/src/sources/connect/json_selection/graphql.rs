@@ -98,7 +98,7 @@ impl From<PathSelection> for Vec<GraphQLSelection> {
                 // Variable references do not correspond to GraphQL fields.
                 vec![]
             }
-            PathSelection::Key(_, tail) => {
+            PathSelection::Key(_, tail) | PathSelection::Method(_, _, tail) => {
                 let tail = *tail;
                 tail.into()
             }
@@ -9,13 +9,9 @@ use serde_json_bytes::Value as JSON;
 #[macro_export]
 macro_rules! selection {
     ($input:expr) => {
-        if let Ok((remainder, parsed)) =
-            $crate::sources::connect::json_selection::JSONSelection::parse($input)
-        {
-            assert_eq!(remainder, "");
-            parsed
-        } else {
-            panic!("invalid selection: {:?}", $input);
+        match $crate::sources::connect::json_selection::JSONSelection::parse_checked($input) {
+            Ok(parsed) => parsed,
+            Err(error) => panic!("invalid selection {:?}: {}", $input, error),
         }
     };
 }
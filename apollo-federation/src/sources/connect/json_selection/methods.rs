@@ -0,0 +1,2011 @@
+//! Implementations of the `->method` path steps supported by the JSONSelection
+//! syntax. Each method receives the (unevaluated) arguments passed to it, the
+//! input value preceding the `->` arrow, and the value of `$` against which
+//! the arguments are evaluated. The caller applies the remaining tail of the
+//! path to the value the method returns.
+
+use std::ops::RangeInclusive;
+
+use apollo_compiler::collections::IndexMap;
+use apollo_compiler::collections::IndexSet;
+use base64::alphabet;
+use base64::engine::general_purpose::GeneralPurpose;
+use base64::engine::general_purpose::GeneralPurposeConfig;
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use percent_encoding::utf8_percent_encode;
+use percent_encoding::AsciiSet;
+use percent_encoding::NON_ALPHANUMERIC;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value as JSON;
+use sha2::Digest;
+use sha2::Sha256;
+
+use super::helpers::json_type_name;
+use super::parser::JSLiteral;
+use super::parser::MethodArgs;
+use super::ApplyTo;
+use super::ApplyToError;
+
+pub(super) type ArrowMethod = fn(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON>;
+
+pub(super) fn lookup_arrow_method(method_name: &str) -> Option<ArrowMethod> {
+    match method_name {
+        "has" => Some(has_method),
+        "hasKey" => Some(has_key_method),
+        "get" => Some(get_method),
+        "merge" => Some(merge_method),
+        "deepMerge" => Some(merge_method),
+        "pick" => Some(pick_method),
+        "omit" => Some(omit_method),
+        "keyBy" => Some(key_by_method),
+        "groupBy" => Some(group_by_method),
+        "joinOn" => Some(join_on_method),
+        "round" => Some(round_method),
+        "floor" | "ceil" | "abs" => Some(unary_number_method),
+        "toFixed" => Some(to_fixed_method),
+        "base64Encode" | "base64Decode" => Some(base64_method),
+        "urlEncode" | "urlDecode" => Some(url_method),
+        "jsonParse" => Some(json_parse_method),
+        "jsonStringify" => Some(json_stringify_method),
+        "echo" => Some(echo_method),
+        "sha256" | "md5" => Some(hash_method),
+        _ => None,
+    }
+}
+
+// Returns the unevaluated arguments of a method invocation, reporting an error
+// (and returning None) if the number of arguments is outside the expected range.
+fn expect_args<'a>(
+    method_name: &str,
+    method_args: Option<&'a MethodArgs>,
+    expected: RangeInclusive<usize>,
+    input_path: &[JSON],
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<&'a [JSLiteral]> {
+    let args = method_args.map(MethodArgs::args).unwrap_or_default();
+
+    if !expected.contains(&args.len()) {
+        let count = if expected.start() == expected.end() {
+            format!("{}", expected.start())
+        } else if *expected.end() == usize::MAX {
+            format!("at least {}", expected.start())
+        } else {
+            format!("{} to {}", expected.start(), expected.end())
+        };
+        errors.insert(ApplyToError::new(
+            format!(
+                "Method ->{} requires {} argument(s), but received {}",
+                method_name,
+                count,
+                args.len(),
+            )
+            .as_str(),
+            input_path,
+        ));
+        return None;
+    }
+
+    Some(args)
+}
+
+// Evaluates the arguments of a method invocation against the value of $ (or
+// the method's input data, for @ paths), reporting an error (and returning
+// None) if the number of arguments is outside the expected range, or if any
+// argument fails to evaluate.
+#[allow(clippy::too_many_arguments)]
+fn evaluate_args(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    expected: RangeInclusive<usize>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<Vec<JSON>> {
+    let args = expect_args(method_name, method_args, expected, input_path, errors)?;
+
+    let mut values = Vec::with_capacity(args.len());
+    for arg in args {
+        values.push(arg.apply_to_path_with_dollar(data, dollar, vars, input_path, errors)?);
+    }
+    Some(values)
+}
+
+// ->try and ->catch are not ArrowMethods, since they are applied to the steps
+// preceding them rather than to the value those steps produce (see
+// PathSelection::apply_to_path_with_try), but their arguments are validated
+// here like other methods. They take an optional fallback value, returned in
+// place of the failed path. Without a fallback, the path produces no value.
+pub(super) fn try_fallback_arg<'a>(
+    method_name: &str,
+    method_args: Option<&'a MethodArgs>,
+    input_path: &[JSON],
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<Option<&'a JSLiteral>> {
+    let args = expect_args(method_name, method_args, 0..=1, input_path, errors)?;
+    Some(args.first())
+}
+
+// Looks up a property of an object or an element of an array, returning Ok(None)
+// when the key is well-formed but absent, and Err when the key has the wrong
+// type for the data (for example, a string key used with an array).
+fn lookup_key<'a>(data: &'a JSON, key: &JSON) -> Result<Option<&'a JSON>, String> {
+    match (data, key) {
+        (JSON::Object(map), JSON::String(name)) => Ok(map.get(name.as_str())),
+        (JSON::Array(array), JSON::Number(index)) => {
+            let Some(index) = index.as_i64() else {
+                return Err(format!("Array index {} must be an integer", index));
+            };
+            // Negative indexes count backwards from the end of the array.
+            let len = array.len() as i64;
+            let index = if index < 0 { index + len } else { index };
+            if (0..len).contains(&index) {
+                Ok(array.get(index as usize))
+            } else {
+                Ok(None)
+            }
+        }
+        (JSON::Object(_), _) | (JSON::Array(_), _) => Err(format!(
+            "Cannot use {} key to access {}",
+            json_type_name(key),
+            json_type_name(data),
+        )),
+        _ => Ok(None),
+    }
+}
+
+// ->has(key) returns true if the input object has the given property (even if
+// its value is null), or if the input array has an element at the given index.
+// Any other input value has no keys, so ->has returns false.
+fn has_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let args = evaluate_args(
+        method_name,
+        method_args,
+        1..=1,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    match lookup_key(data, &args[0]) {
+        Ok(found) => Some(JSON::Bool(found.is_some())),
+        Err(message) => {
+            errors.insert(ApplyToError::new(
+                format!("Method ->{}: {}", method_name, message).as_str(),
+                input_path,
+            ));
+            None
+        }
+    }
+}
+
+// ->hasKey(name) is the stricter, object-only version of ->has, which requires
+// a string argument and returns false for any non-object input.
+fn has_key_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let args = evaluate_args(
+        method_name,
+        method_args,
+        1..=1,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let JSON::String(name) = &args[0] else {
+        errors.insert(ApplyToError::new(
+            format!(
+                "Method ->{} requires a string argument, but received {}",
+                method_name,
+                json_type_name(&args[0]),
+            )
+            .as_str(),
+            input_path,
+        ));
+        return None;
+    };
+
+    let found = matches!(data, JSON::Object(map) if map.contains_key(name.as_str()));
+    Some(JSON::Bool(found))
+}
+
+// ->get(key) returns the value of the given property or array element, and
+// reports an error if it is absent. Passing a second argument, as in
+// ->get(key, default), returns the default instead of reporting an error.
+// Note that a property that is present with a null value is not absent, so
+// ->get returns null in that case rather than the default.
+fn get_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let mut args = evaluate_args(
+        method_name,
+        method_args,
+        1..=2,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+    let default = if args.len() > 1 { args.pop() } else { None };
+    let key = &args[0];
+
+    match lookup_key(data, key) {
+        Ok(Some(value)) => Some(value.clone()),
+        Ok(None) => {
+            if default.is_some() {
+                default
+            } else {
+                let key_display = match key {
+                    JSON::String(name) => format!("\"{}\"", name.as_str()),
+                    other => other.to_string(),
+                };
+                errors.insert(ApplyToError::new(
+                    format!(
+                        "Method ->{} could not find key {} in {}",
+                        method_name,
+                        key_display,
+                        json_type_name(data),
+                    )
+                    .as_str(),
+                    input_path,
+                ));
+                None
+            }
+        }
+        Err(message) => {
+            errors.insert(ApplyToError::new(
+                format!("Method ->{}: {}", method_name, message).as_str(),
+                input_path,
+            ));
+            None
+        }
+    }
+}
+
+// Reports an error unless the method's input data is an object, so methods
+// that only make sense for objects can share the same error message.
+fn expect_object<'a>(
+    method_name: &str,
+    data: &'a JSON,
+    input_path: &[JSON],
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<&'a Map<ByteString, JSON>> {
+    if let JSON::Object(map) = data {
+        Some(map)
+    } else {
+        errors.insert(ApplyToError::new(
+            format!(
+                "Method ->{} requires an object input, but received {}",
+                method_name,
+                json_type_name(data),
+            )
+            .as_str(),
+            input_path,
+        ));
+        None
+    }
+}
+
+// Evaluates the arguments of a method that accepts any number of property
+// names, such as ->pick and ->omit.
+fn evaluate_key_args(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<Vec<ByteString>> {
+    let args = evaluate_args(
+        method_name,
+        method_args,
+        1..=usize::MAX,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let mut keys = Vec::with_capacity(args.len());
+    for arg in args {
+        if let JSON::String(key) = arg {
+            keys.push(key);
+        } else {
+            errors.insert(ApplyToError::new(
+                format!(
+                    "Method ->{} requires string arguments, but received {}",
+                    method_name,
+                    json_type_name(&arg),
+                )
+                .as_str(),
+                input_path,
+            ));
+            return None;
+        }
+    }
+    Some(keys)
+}
+
+// Merges the properties of source into target. When deep is true, nested
+// objects present in both target and source are merged recursively rather
+// than replaced.
+fn merge_into(target: &mut Map<ByteString, JSON>, source: Map<ByteString, JSON>, deep: bool) {
+    for (key, value) in source {
+        match (target.get_mut(key.as_str()), value) {
+            (Some(JSON::Object(target_child)), JSON::Object(source_child)) if deep => {
+                merge_into(target_child, source_child, deep);
+            }
+            (_, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+// ->merge(...objects) shallowly merges the properties of each argument object
+// into a copy of the input object, with later properties taking precedence.
+// ->deepMerge(...objects) works the same way, except nested objects present in
+// both are merged recursively instead of being replaced wholesale.
+fn merge_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let deep = method_name == "deepMerge";
+    let mut output = expect_object(method_name, data, input_path, errors)?.clone();
+    let args = evaluate_args(
+        method_name,
+        method_args,
+        1..=usize::MAX,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    for arg in args {
+        match arg {
+            JSON::Object(source) => merge_into(&mut output, source, deep),
+            // Merging a null argument is a no-op, which makes it easy to merge
+            // optional values like $args.overrides.
+            JSON::Null => {}
+            other => {
+                errors.insert(ApplyToError::new(
+                    format!(
+                        "Method ->{} requires object arguments, but received {}",
+                        method_name,
+                        json_type_name(&other),
+                    )
+                    .as_str(),
+                    input_path,
+                ));
+                return None;
+            }
+        }
+    }
+
+    Some(JSON::Object(output))
+}
+
+// ->pick(...names) returns a copy of the input object containing only the named
+// properties, in the order they were named. Absent properties are skipped.
+fn pick_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let map = expect_object(method_name, data, input_path, errors)?;
+    let keys = evaluate_key_args(
+        method_name,
+        method_args,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let mut output = Map::new();
+    for key in keys {
+        if let Some(value) = map.get(key.as_str()) {
+            output.insert(key, value.clone());
+        }
+    }
+
+    Some(JSON::Object(output))
+}
+
+// ->omit(...names) returns a copy of the input object without the named
+// properties, preserving the order of the remaining properties.
+fn omit_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let map = expect_object(method_name, data, input_path, errors)?;
+    let keys = evaluate_key_args(
+        method_name,
+        method_args,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let output = map
+        .iter()
+        .filter(|(key, _)| !keys.contains(key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    Some(JSON::Object(output))
+}
+
+// Reports an error unless the method's input data is an array.
+fn expect_array<'a>(
+    method_name: &str,
+    data: &'a JSON,
+    input_path: &[JSON],
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<&'a [JSON]> {
+    if let JSON::Array(elements) = data {
+        Some(elements)
+    } else {
+        errors.insert(ApplyToError::new(
+            format!(
+                "Method ->{} requires an array input, but received {}",
+                method_name,
+                json_type_name(data),
+            )
+            .as_str(),
+            input_path,
+        ));
+        None
+    }
+}
+
+// Evaluates a key selector against each element of an array, with @ bound to
+// the element, returning the elements paired with their (stringified) keys.
+// Elements whose key fails to evaluate are paired with None, with an error
+// reported.
+fn element_keys<'a>(
+    method_name: &str,
+    selector: &JSLiteral,
+    elements: &'a [JSON],
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Vec<(Option<ByteString>, &'a JSON)> {
+    let mut keyed = Vec::with_capacity(elements.len());
+    for (i, element) in elements.iter().enumerate() {
+        input_path.push(JSON::Number(i.into()));
+        let key =
+            match selector.apply_to_path_with_dollar(element, dollar, vars, input_path, errors) {
+                Some(JSON::String(key)) => Some(key),
+                // Numeric IDs are common enough that we allow them as keys, using
+                // their JSON representation as the property name.
+                Some(JSON::Number(number)) => Some(number.to_string().into()),
+                Some(other) => {
+                    errors.insert(ApplyToError::new(
+                        format!(
+                            "Method ->{} requires string or number keys, but received {}",
+                            method_name,
+                            json_type_name(&other),
+                        )
+                        .as_str(),
+                        input_path,
+                    ));
+                    None
+                }
+                None => None,
+            };
+        input_path.pop();
+        keyed.push((key, element));
+    }
+    keyed
+}
+
+// Evaluates the single key selector argument of ->keyBy or ->groupBy against
+// each element of the input array, skipping elements without a valid key.
+fn evaluate_element_keys<'a>(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &'a JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<Vec<(ByteString, &'a JSON)>> {
+    let elements = expect_array(method_name, data, input_path, errors)?;
+    let selector = &expect_args(method_name, method_args, 1..=1, input_path, errors)?[0];
+
+    Some(
+        element_keys(
+            method_name,
+            selector,
+            elements,
+            dollar,
+            vars,
+            input_path,
+            errors,
+        )
+        .into_iter()
+        .filter_map(|(key, element)| key.map(|key| (key, element)))
+        .collect(),
+    )
+}
+
+// ->keyBy(selector) converts an array into an object whose properties are the
+// elements of the array, keyed by the result of applying the selector to each
+// element, as in items->keyBy(@.id). When several elements have the same key,
+// the last one wins.
+fn key_by_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let keyed = evaluate_element_keys(
+        method_name,
+        method_args,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let mut output = Map::new();
+    for (key, element) in keyed {
+        output.insert(key, element.clone());
+    }
+    Some(JSON::Object(output))
+}
+
+// ->groupBy(selector) works like ->keyBy, except each property holds an array
+// of all the elements with that key, in their original order.
+fn group_by_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let keyed = evaluate_element_keys(
+        method_name,
+        method_args,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let mut groups: IndexMap<ByteString, Vec<JSON>> = IndexMap::default();
+    for (key, element) in keyed {
+        groups.entry(key).or_default().push(element.clone());
+    }
+    Some(JSON::Object(
+        groups
+            .into_iter()
+            .map(|(key, group)| (key, JSON::Array(group)))
+            .collect(),
+    ))
+}
+
+// ->joinOn(other, key, otherKey) performs a hash join of the input array with
+// another array, as in $.users->joinOn($.accounts, @.id, @.userId). The key
+// selectors are evaluated with @ bound to each element of the input array and
+// the other array, respectively, and each pair of elements with equal keys
+// produces a copy of the input element with the properties of the other
+// element merged in. By default this is an inner join, so input elements
+// without a match are dropped, but passing 'left' as the optional fourth
+// argument keeps them unchanged.
+fn join_on_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let elements = expect_array(method_name, data, input_path, errors)?;
+    let args = expect_args(method_name, method_args, 3..=4, input_path, errors)?;
+
+    let other = args[0].apply_to_path_with_dollar(data, dollar, vars, input_path, errors)?;
+    let JSON::Array(other_elements) = &other else {
+        errors.insert(ApplyToError::new(
+            format!(
+                "Method ->{} requires an array to join with, but received {}",
+                method_name,
+                json_type_name(&other),
+            )
+            .as_str(),
+            input_path,
+        ));
+        return None;
+    };
+
+    let left_join = match args.get(3) {
+        None => false,
+        Some(arg) => match arg.apply_to_path_with_dollar(data, dollar, vars, input_path, errors)? {
+            JSON::String(join_type) if join_type.as_str() == "inner" => false,
+            JSON::String(join_type) if join_type.as_str() == "left" => true,
+            other => {
+                errors.insert(ApplyToError::new(
+                    format!(
+                        "Method ->{} join type must be 'inner' or 'left', but received {}",
+                        method_name, other,
+                    )
+                    .as_str(),
+                    input_path,
+                ));
+                return None;
+            }
+        },
+    };
+
+    let mut other_by_key: IndexMap<ByteString, Vec<&Map<ByteString, JSON>>> = IndexMap::default();
+    for (key, other_element) in element_keys(
+        method_name,
+        &args[2],
+        other_elements,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    ) {
+        let Some(key) = key else { continue };
+        if let JSON::Object(other_map) = other_element {
+            other_by_key.entry(key).or_default().push(other_map);
+        } else {
+            errors.insert(ApplyToError::new(
+                format!(
+                    "Method ->{} requires an array of objects to join with, but found {}",
+                    method_name,
+                    json_type_name(other_element),
+                )
+                .as_str(),
+                input_path,
+            ));
+        }
+    }
+
+    let mut output = Vec::new();
+    for (i, (key, element)) in element_keys(
+        method_name,
+        &args[1],
+        elements,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )
+    .into_iter()
+    .enumerate()
+    {
+        let matches = key.and_then(|key| other_by_key.get(&key));
+        match (matches, element) {
+            (Some(matches), JSON::Object(map)) => {
+                for other_map in matches {
+                    let mut joined = map.clone();
+                    merge_into(&mut joined, (*other_map).clone(), false);
+                    output.push(JSON::Object(joined));
+                }
+            }
+            (Some(_), _) => {
+                input_path.push(JSON::Number(i.into()));
+                errors.insert(ApplyToError::new(
+                    format!(
+                        "Method ->{} requires an array of objects, but found {}",
+                        method_name,
+                        json_type_name(element),
+                    )
+                    .as_str(),
+                    input_path,
+                ));
+                input_path.pop();
+            }
+            (None, _) => {
+                if left_join {
+                    output.push(element.clone());
+                }
+            }
+        }
+    }
+
+    Some(JSON::Array(output))
+}
+
+// Reports an error unless the method's input data is a number.
+fn expect_number<'a>(
+    method_name: &str,
+    data: &'a JSON,
+    input_path: &[JSON],
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<&'a serde_json::Number> {
+    if let JSON::Number(number) = data {
+        Some(number)
+    } else {
+        errors.insert(ApplyToError::new(
+            format!(
+                "Method ->{} requires a number input, but received {}",
+                method_name,
+                json_type_name(data),
+            )
+            .as_str(),
+            input_path,
+        ));
+        None
+    }
+}
+
+// Evaluates the optional number-of-digits argument of ->round and ->toFixed,
+// which must be an integer within the given range.
+#[allow(clippy::too_many_arguments)]
+fn evaluate_digits_arg(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    allowed: RangeInclusive<i64>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<i64> {
+    let args = evaluate_args(
+        method_name,
+        method_args,
+        0..=1,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+    let Some(arg) = args.first() else {
+        return Some(0);
+    };
+
+    match arg.as_i64() {
+        Some(digits) if allowed.contains(&digits) => Some(digits),
+        _ => {
+            errors.insert(ApplyToError::new(
+                format!(
+                    "Method ->{} requires an integer number of digits between {} and {}, but received {}",
+                    method_name,
+                    allowed.start(),
+                    allowed.end(),
+                    arg,
+                )
+                .as_str(),
+                input_path,
+            ));
+            None
+        }
+    }
+}
+
+// Converts the result of a numeric method back to JSON, preferring an integer
+// representation for whole numbers when the result is meant to be integral.
+fn float_to_json(
+    method_name: &str,
+    value: f64,
+    integral: bool,
+    input_path: &[JSON],
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    if integral && value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        return Some(JSON::Number((value as i64).into()));
+    }
+    if let Some(number) = serde_json::Number::from_f64(value) {
+        Some(JSON::Number(number))
+    } else {
+        errors.insert(ApplyToError::new(
+            format!("Method ->{} produced a non-finite number", method_name).as_str(),
+            input_path,
+        ));
+        None
+    }
+}
+
+// ->round(digits?) rounds the input number to the given number of decimal
+// places (zero by default), rounding halfway cases away from zero. Negative
+// digits round to the left of the decimal point, so ->round(-2) rounds to the
+// nearest hundred.
+fn round_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let number = expect_number(method_name, data, input_path, errors)?;
+    let digits = evaluate_digits_arg(
+        method_name,
+        method_args,
+        -15..=15,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    if !number.is_f64() && digits >= 0 {
+        return Some(data.clone());
+    }
+    let value = number.as_f64().unwrap_or_default();
+    let scale = 10f64.powi(digits as i32);
+    float_to_json(
+        method_name,
+        (value * scale).round() / scale,
+        digits <= 0,
+        input_path,
+        errors,
+    )
+}
+
+// ->floor and ->ceil round the input number down or up to the nearest integer,
+// and ->abs returns its absolute value. Integers are returned unchanged, except
+// by ->abs.
+fn unary_number_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let number = expect_number(method_name, data, input_path, errors)?;
+    evaluate_args(
+        method_name,
+        method_args,
+        0..=0,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let (operation, integral): (fn(f64) -> f64, bool) = match method_name {
+        "floor" => (f64::floor, true),
+        "ceil" => (f64::ceil, true),
+        _ => {
+            if let Some(int) = number.as_i64() {
+                return Some(JSON::Number(int.unsigned_abs().into()));
+            }
+            (f64::abs, false)
+        }
+    };
+
+    if !number.is_f64() {
+        return Some(data.clone());
+    }
+    float_to_json(
+        method_name,
+        operation(number.as_f64().unwrap_or_default()),
+        integral,
+        input_path,
+        errors,
+    )
+}
+
+// ->toFixed(digits?) formats the input number as a string with exactly the
+// given number of decimal places (zero by default), like the JavaScript method
+// of the same name.
+fn to_fixed_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let number = expect_number(method_name, data, input_path, errors)?;
+    let digits = evaluate_digits_arg(
+        method_name,
+        method_args,
+        0..=100,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let value = number.as_f64().unwrap_or_default();
+    Some(JSON::String(
+        format!("{:.*}", digits as usize, value).into(),
+    ))
+}
+
+// Reports an error unless the method's input data is a string.
+fn expect_string<'a>(
+    method_name: &str,
+    data: &'a JSON,
+    input_path: &[JSON],
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<&'a str> {
+    if let JSON::String(string) = data {
+        Some(string.as_str())
+    } else {
+        errors.insert(ApplyToError::new(
+            format!(
+                "Method ->{} requires a string input, but received {}",
+                method_name,
+                json_type_name(data),
+            )
+            .as_str(),
+            input_path,
+        ));
+        None
+    }
+}
+
+// Base64 engines that encode with padding but accept input with or without it,
+// since cursor tokens often have their padding stripped.
+const BASE64_STANDARD: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+const BASE64_URL_SAFE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// ->base64Encode(alphabet?) encodes the UTF-8 bytes of the input string as
+// base64, and ->base64Decode(alphabet?) decodes base64 input into a (UTF-8)
+// string. The optional alphabet argument is either 'standard' (the default) or
+// 'url', for the URL-safe alphabet that uses - and _ instead of + and /.
+fn base64_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let string = expect_string(method_name, data, input_path, errors)?;
+    let args = evaluate_args(
+        method_name,
+        method_args,
+        0..=1,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let engine = match args.first().and_then(JSON::as_str) {
+        None if args.is_empty() => &BASE64_STANDARD,
+        Some("standard") => &BASE64_STANDARD,
+        Some("url") => &BASE64_URL_SAFE,
+        _ => {
+            errors.insert(ApplyToError::new(
+                format!(
+                    "Method ->{} alphabet must be 'standard' or 'url', but received {}",
+                    method_name, args[0],
+                )
+                .as_str(),
+                input_path,
+            ));
+            return None;
+        }
+    };
+
+    if method_name == "base64Encode" {
+        return Some(JSON::String(engine.encode(string).into()));
+    }
+
+    let decoded = engine
+        .decode(string)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|_| "invalid UTF-8".to_string()));
+    match decoded {
+        Ok(decoded) => Some(JSON::String(decoded.into())),
+        Err(message) => {
+            errors.insert(ApplyToError::new(
+                format!(
+                    "Method ->{} failed to decode input: {}",
+                    method_name, message
+                )
+                .as_str(),
+                input_path,
+            ));
+            None
+        }
+    }
+}
+
+// The characters left unescaped by ->urlEncode, matching the unreserved
+// characters of RFC 3986 (and JavaScript's encodeURIComponent, minus !'()*).
+const URL_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+// ->urlEncode percent-encodes the input so it can be used as a single URL path
+// segment or query parameter value, and ->urlDecode reverses the encoding.
+// Numbers and booleans are encoded using their JSON representation.
+fn url_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    evaluate_args(
+        method_name,
+        method_args,
+        0..=0,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    if method_name == "urlEncode" {
+        let scalar = match data {
+            JSON::Number(_) | JSON::Bool(_) => data.to_string(),
+            _ => expect_string(method_name, data, input_path, errors)?.to_string(),
+        };
+        return Some(JSON::String(
+            utf8_percent_encode(&scalar, URL_COMPONENT)
+                .to_string()
+                .into(),
+        ));
+    }
+
+    let string = expect_string(method_name, data, input_path, errors)?;
+    match percent_decode_str(string).decode_utf8() {
+        Ok(decoded) => Some(JSON::String(decoded.into_owned().into())),
+        Err(_) => {
+            errors.insert(ApplyToError::new(
+                format!(
+                    "Method ->{} failed to decode input: invalid UTF-8",
+                    method_name
+                )
+                .as_str(),
+                input_path,
+            ));
+            None
+        }
+    }
+}
+
+// ->jsonParse parses the input string as JSON, which is useful for structured
+// values like cursors that arrive encoded, as in $.cursor->base64Decode->jsonParse.
+fn json_parse_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let string = expect_string(method_name, data, input_path, errors)?;
+    evaluate_args(
+        method_name,
+        method_args,
+        0..=0,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    match serde_json::from_str::<JSON>(string) {
+        Ok(parsed) => Some(parsed),
+        Err(err) => {
+            errors.insert(ApplyToError::new(
+                format!("Method ->{} failed to parse input: {}", method_name, err).as_str(),
+                input_path,
+            ));
+            None
+        }
+    }
+}
+
+// ->jsonStringify serializes the input value as a compact JSON string.
+fn json_stringify_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    evaluate_args(
+        method_name,
+        method_args,
+        0..=0,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+    Some(JSON::String(data.to_string().into()))
+}
+
+// ->echo(value) returns its argument, evaluated with @ bound to the input value,
+// which allows building new values from the input, as in
+// $->echo([@.type, @.number]).
+fn echo_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let mut args = evaluate_args(
+        method_name,
+        method_args,
+        1..=1,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+    args.pop()
+}
+
+// ->sha256 and ->md5 hash the UTF-8 bytes of the input string, returning the
+// digest as a lowercase hexadecimal string. Non-string values can be hashed by
+// serializing them first, as in $->echo([@.type, @.number])->jsonStringify->sha256.
+fn hash_method(
+    method_name: &str,
+    method_args: Option<&MethodArgs>,
+    data: &JSON,
+    dollar: &JSON,
+    vars: &IndexMap<String, JSON>,
+    input_path: &mut Vec<JSON>,
+    errors: &mut IndexSet<ApplyToError>,
+) -> Option<JSON> {
+    let string = expect_string(method_name, data, input_path, errors)?;
+    evaluate_args(
+        method_name,
+        method_args,
+        0..=0,
+        data,
+        dollar,
+        vars,
+        input_path,
+        errors,
+    )?;
+
+    let digest = if method_name == "md5" {
+        hex::encode(md5::compute(string).0)
+    } else {
+        hex::encode(Sha256::digest(string))
+    };
+    Some(JSON::String(digest.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::selection;
+
+    #[test]
+    fn test_has_methods() {
+        let data = json!({
+            "present": 1,
+            "nullish": null,
+            "list": [1, 2, 3],
+        });
+
+        assert_eq!(
+            selection!("$->has('present')").apply_to(&data),
+            (Some(json!(true)), vec![]),
+        );
+        assert_eq!(
+            selection!("$->has('nullish')").apply_to(&data),
+            (Some(json!(true)), vec![]),
+        );
+        assert_eq!(
+            selection!("$->has('missing')").apply_to(&data),
+            (Some(json!(false)), vec![]),
+        );
+        assert_eq!(
+            selection!("$.list->has(2)").apply_to(&data),
+            (Some(json!(true)), vec![]),
+        );
+        assert_eq!(
+            selection!("$.list->has(-3)").apply_to(&data),
+            (Some(json!(true)), vec![]),
+        );
+        assert_eq!(
+            selection!("$.list->has(3)").apply_to(&data),
+            (Some(json!(false)), vec![]),
+        );
+        assert_eq!(
+            selection!("$.present->has('anything')").apply_to(&data),
+            (Some(json!(false)), vec![]),
+        );
+
+        assert_eq!(
+            selection!("$->hasKey('nullish')").apply_to(&data),
+            (Some(json!(true)), vec![]),
+        );
+        assert_eq!(
+            selection!("$.list->hasKey('length')").apply_to(&data),
+            (Some(json!(false)), vec![]),
+        );
+
+        assert_eq!(
+            selection!(
+                r#"
+                hasPresent: $->has('present')
+                hasMissing: $->hasKey("missing")
+            "#
+            )
+            .apply_to(&data),
+            (
+                Some(json!({
+                    "hasPresent": true,
+                    "hasMissing": false,
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$->hasKey(1)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->hasKey requires a string argument, but received number",
+                    "path": [],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$->has").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->has requires 1 argument(s), but received 0",
+                    "path": [],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_get_method() {
+        let data = json!({
+            "user": {
+                "name": "Ada",
+                "nickname": null,
+            },
+            "list": ["a", "b", "c"],
+        });
+
+        assert_eq!(
+            selection!("$.user->get('name')").apply_to(&data),
+            (Some(json!("Ada")), vec![]),
+        );
+        assert_eq!(
+            selection!("$.user->get('nickname', 'none')").apply_to(&data),
+            (Some(json!(null)), vec![]),
+        );
+        assert_eq!(
+            selection!("$.user->get('email', 'none')").apply_to(&data),
+            (Some(json!("none")), vec![]),
+        );
+        assert_eq!(
+            selection!("$.list->get(-1)").apply_to(&data),
+            (Some(json!("c")), vec![]),
+        );
+        assert_eq!(
+            selection!("$->get('user').name").apply_to(&data),
+            (Some(json!("Ada")), vec![]),
+        );
+        assert_eq!(
+            selection!("$->get('user', {}) { name }").apply_to(&data),
+            (Some(json!({ "name": "Ada" })), vec![]),
+        );
+
+        assert_eq!(
+            selection!("$.user->get('email')").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->get could not find key \"email\" in object",
+                    "path": ["user"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.list->get('a')").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->get: Cannot use string key to access array",
+                    "path": ["list"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.user->nonexistent").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->nonexistent not found",
+                    "path": ["user"],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_method_args_with_vars() {
+        let data = json!({ "a": 1, "b": null });
+        let mut vars = IndexMap::default();
+        vars.insert("$args".to_string(), json!({ "key": "b", "fallback": 0 }));
+
+        assert_eq!(
+            selection!("$->has($args.key)").apply_with_vars(&data, &vars),
+            (Some(json!(true)), vec![]),
+        );
+        assert_eq!(
+            selection!("$->get('c', $args.fallback)").apply_with_vars(&data, &vars),
+            (Some(json!(0)), vec![]),
+        );
+        assert_eq!(
+            selection!("$->get('c', [$.a, { nested: $args.key }])").apply_with_vars(&data, &vars),
+            (Some(json!([1, { "nested": "b" }])), vec![]),
+        );
+    }
+
+    #[test]
+    fn test_merge_methods() {
+        let data = json!({
+            "defaults": {
+                "limit": 10,
+                "sort": { "field": "name", "order": "asc" },
+            },
+            "payload": {
+                "limit": 25,
+                "sort": { "order": "desc" },
+            },
+        });
+
+        assert_eq!(
+            selection!("$.defaults->merge($.payload)").apply_to(&data),
+            (
+                Some(json!({
+                    "limit": 25,
+                    "sort": { "order": "desc" },
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.defaults->deepMerge($.payload)").apply_to(&data),
+            (
+                Some(json!({
+                    "limit": 25,
+                    "sort": { "field": "name", "order": "desc" },
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.defaults->merge({ limit: 5 }, null, { extra: true }).limit")
+                .apply_to(&data),
+            (Some(json!(5)), vec![]),
+        );
+
+        assert_eq!(
+            selection!("$.defaults->merge([1])").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->merge requires object arguments, but received array",
+                    "path": ["defaults"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.defaults.limit->merge({})").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->merge requires an object input, but received number",
+                    "path": ["defaults", "limit"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.defaults->merge").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->merge requires at least 1 argument(s), but received 0",
+                    "path": ["defaults"],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_pick_omit_methods() {
+        let data = json!({
+            "user": {
+                "id": 1,
+                "name": "Ada",
+                "password": "hunter2",
+                "email": null,
+            },
+        });
+
+        assert_eq!(
+            selection!("$.user->pick('name', 'id', 'missing')").apply_to(&data),
+            (Some(json!({ "name": "Ada", "id": 1 })), vec![]),
+        );
+
+        assert_eq!(
+            selection!("$.user->omit('password')").apply_to(&data),
+            (
+                Some(json!({ "id": 1, "name": "Ada", "email": null })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("user: $.user->omit('password', 'email') { id name }").apply_to(&data),
+            (Some(json!({ "user": { "id": 1, "name": "Ada" } })), vec![]),
+        );
+
+        assert_eq!(
+            selection!("$.user->pick(1)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->pick requires string arguments, but received number",
+                    "path": ["user"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.user.name->omit('a')").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->omit requires an object input, but received string",
+                    "path": ["user", "name"],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_key_by_group_by_methods() {
+        let data = json!({
+            "prefix": "sku-",
+            "items": [
+                { "id": 1, "sku": "a", "category": "tools" },
+                { "id": 2, "sku": "b", "category": "toys" },
+                { "id": 3, "sku": "c", "category": "tools" },
+            ],
+        });
+
+        assert_eq!(
+            selection!("$.items->keyBy(@.sku)").apply_to(&data),
+            (
+                Some(json!({
+                    "a": { "id": 1, "sku": "a", "category": "tools" },
+                    "b": { "id": 2, "sku": "b", "category": "toys" },
+                    "c": { "id": 3, "sku": "c", "category": "tools" },
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("byId: items->keyBy(@.id) { two: \"2\" { sku } }").apply_to(&data),
+            (Some(json!({ "byId": { "two": { "sku": "b" } } })), vec![]),
+        );
+
+        assert_eq!(
+            selection!("$.items->groupBy(@.category)").apply_to(&data),
+            (
+                Some(json!({
+                    "tools": [
+                        { "id": 1, "sku": "a", "category": "tools" },
+                        { "id": 3, "sku": "c", "category": "tools" },
+                    ],
+                    "toys": [
+                        { "id": 2, "sku": "b", "category": "toys" },
+                    ],
+                })),
+                vec![],
+            ),
+        );
+
+        // Within the selector, $ still refers to the value at the start of the
+        // path, while @ refers to each element.
+        assert_eq!(
+            selection!("$.items->keyBy($.prefix)").apply_to(&data),
+            (
+                Some(json!({
+                    "sku-": { "id": 3, "sku": "c", "category": "tools" },
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.items->keyBy(@.missing)").apply_to(&data),
+            (
+                Some(json!({})),
+                vec![
+                    ApplyToError::from_json(&json!({
+                        "message": "Property .missing not found in object",
+                        "path": ["items", 0, "missing"],
+                    })),
+                    ApplyToError::from_json(&json!({
+                        "message": "Property .missing not found in object",
+                        "path": ["items", 1, "missing"],
+                    })),
+                    ApplyToError::from_json(&json!({
+                        "message": "Property .missing not found in object",
+                        "path": ["items", 2, "missing"],
+                    })),
+                ],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.items->groupBy(@)").apply_to(&data),
+            (
+                Some(json!({})),
+                vec![
+                    ApplyToError::from_json(&json!({
+                        "message": "Method ->groupBy requires string or number keys, but received object",
+                        "path": ["items", 0],
+                    })),
+                    ApplyToError::from_json(&json!({
+                        "message": "Method ->groupBy requires string or number keys, but received object",
+                        "path": ["items", 1],
+                    })),
+                    ApplyToError::from_json(&json!({
+                        "message": "Method ->groupBy requires string or number keys, but received object",
+                        "path": ["items", 2],
+                    })),
+                ],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.prefix->keyBy(@)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->keyBy requires an array input, but received string",
+                    "path": ["prefix"],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_join_on_method() {
+        let data = json!({
+            "users": [
+                { "id": 1, "name": "Ada" },
+                { "id": 2, "name": "Grace" },
+                { "id": 3, "name": "Alan" },
+            ],
+            "accounts": [
+                { "userId": 2, "balance": 20 },
+                { "userId": 1, "balance": 10 },
+                { "userId": 2, "balance": 25 },
+            ],
+        });
+
+        assert_eq!(
+            selection!("$.users->joinOn($.accounts, @.id, @.userId)").apply_to(&data),
+            (
+                Some(json!([
+                    { "id": 1, "name": "Ada", "userId": 1, "balance": 10 },
+                    { "id": 2, "name": "Grace", "userId": 2, "balance": 20 },
+                    { "id": 2, "name": "Grace", "userId": 2, "balance": 25 },
+                ])),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.users->joinOn($.accounts, @.id, @.userId, 'left') { name balance }")
+                .apply_to(&data),
+            (
+                Some(json!([
+                    { "name": "Ada", "balance": 10 },
+                    { "name": "Grace", "balance": 20 },
+                    { "name": "Grace", "balance": 25 },
+                    { "name": "Alan" },
+                ])),
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Property .balance not found in object",
+                    "path": ["users", 3, "balance"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.users->joinOn($.accounts, @.id, @.userId, 'outer')").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->joinOn join type must be 'inner' or 'left', but received \"outer\"",
+                    "path": ["users"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.users->joinOn({ id: 1 }, @.id, @.id)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->joinOn requires an array to join with, but received object",
+                    "path": ["users"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.users->joinOn($.accounts, @.id)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->joinOn requires 3 to 4 argument(s), but received 2",
+                    "path": ["users"],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_try_catch_methods() {
+        let data = json!({
+            "user": { "name": "Ada", "tags": ["a", "b"] },
+            "items": [
+                { "id": 1, "price": { "amount": 10 } },
+                { "id": 2, "price": null },
+            ],
+        });
+
+        assert_eq!(
+            selection!("$.user.email->try('unknown')").apply_to(&data),
+            (Some(json!("unknown")), vec![]),
+        );
+        assert_eq!(
+            selection!("$.user.name->try('unknown')").apply_to(&data),
+            (Some(json!("Ada")), vec![]),
+        );
+        assert_eq!(
+            selection!("$.user.tags->get(5)->catch($.user.tags->get(0))").apply_to(&data),
+            (Some(json!("a")), vec![]),
+        );
+
+        // Errors in the rest of the path after ->try are still reported.
+        assert_eq!(
+            selection!("$.user.email->try({}).address").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Property .address not found in object",
+                    "path": ["user", "email", "address"],
+                }))],
+            ),
+        );
+
+        // Without a fallback, the failing field is omitted silently, instead of
+        // nulling out the enclosing object.
+        assert_eq!(
+            selection!("items { id amount: price.amount->try }").apply_to(&data),
+            (
+                Some(json!({
+                    "items": [
+                        { "id": 1, "amount": 10 },
+                        { "id": 2 },
+                    ],
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.user.email->try(1, 2)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->try requires 0 to 1 argument(s), but received 2",
+                    "path": [],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_number_methods() {
+        let data = json!({
+            "price": 12.3456,
+            "negative": -2.5,
+            "count": -7,
+            "big": 1234,
+        });
+
+        assert_eq!(
+            selection!(
+                r#"
+                rounded: price->round
+                cents: price->round(2)
+                hundreds: big->round(-2)
+                halfway: negative->round
+                floor: price->floor
+                ceil: negative->ceil
+                abs: negative->abs
+                absInt: count->abs
+                fixed: price->toFixed(1)
+                fixedInt: big->toFixed(2)
+                unchanged: count->floor
+            "#
+            )
+            .apply_to(&data),
+            (
+                Some(json!({
+                    "rounded": 12,
+                    "cents": 12.35,
+                    "hundreds": 1200,
+                    "halfway": -3,
+                    "floor": 12,
+                    "ceil": -2,
+                    "abs": 2.5,
+                    "absInt": 7,
+                    "fixed": "12.3",
+                    "fixedInt": "1234.00",
+                    "unchanged": -7,
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.price->round(1.5)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->round requires an integer number of digits between -15 and 15, but received 1.5",
+                    "path": ["price"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$->abs").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->abs requires a number input, but received object",
+                    "path": [],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.price->floor(1)").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->floor requires 0 argument(s), but received 1",
+                    "path": ["price"],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_encoding_methods() {
+        let data = json!({
+            "text": "a/b c?",
+            "cursor": "eyJwYWdlIjoyLCJzaXplIjoxMH0",
+            "id": 42,
+            "binary": "/w==",
+        });
+
+        assert_eq!(
+            selection!(
+                r#"
+                standard: text->base64Encode
+                url: text->base64Encode('url')
+                roundTrip: text->base64Encode->base64Decode
+                page: cursor->base64Decode->jsonParse.page
+                encoded: text->urlEncode
+                decoded: text->urlEncode->urlDecode
+                idSegment: id->urlEncode
+            "#
+            )
+            .apply_to(&data),
+            (
+                Some(json!({
+                    "standard": "YS9iIGM/",
+                    "url": "YS9iIGM_",
+                    "roundTrip": "a/b c?",
+                    "page": 2,
+                    "encoded": "a%2Fb%20c%3F",
+                    "decoded": "a/b c?",
+                    "idSegment": "42",
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.binary->base64Decode").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->base64Decode failed to decode input: invalid UTF-8",
+                    "path": ["binary"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.text->base64Encode('hex')").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->base64Encode alphabet must be 'standard' or 'url', but received \"hex\"",
+                    "path": ["text"],
+                }))],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.id->base64Encode").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->base64Encode requires a string input, but received number",
+                    "path": ["id"],
+                }))],
+            ),
+        );
+    }
+
+    #[test]
+    fn test_hash_methods() {
+        let data = json!({
+            "type": "invoice",
+            "number": 1001,
+            "empty": "",
+        });
+
+        assert_eq!(
+            selection!(
+                r#"
+                key: $->echo([@.type, @.number])->jsonStringify
+                id: $->echo([@.type, @.number])->jsonStringify->sha256
+                emptySha256: empty->sha256
+                emptyMd5: empty->md5
+            "#
+            )
+            .apply_to(&data),
+            (
+                Some(json!({
+                    "key": "[\"invoice\",1001]",
+                    "id": "fe30ba14e631e7c927f5cb3f0b0493a0dde562f3dbd5c082c8bc7fd569562eec",
+                    "emptySha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                    "emptyMd5": "d41d8cd98f00b204e9800998ecf8427e",
+                })),
+                vec![],
+            ),
+        );
+
+        assert_eq!(
+            selection!("$.number->md5").apply_to(&data),
+            (
+                None,
+                vec![ApplyToError::from_json(&json!({
+                    "message": "Method ->md5 requires a string input, but received number",
+                    "path": ["number"],
+                }))],
+            ),
+        );
+    }
+}
@@ -1,6 +1,7 @@
 mod apply_to;
 mod graphql;
 mod helpers;
+mod methods;
 mod parser;
 mod pretty;
 
@@ -1,13 +1,18 @@
 use std::fmt::Display;
 
 use nom::branch::alt;
+use nom::bytes::complete::tag;
 use nom::character::complete::char;
+use nom::character::complete::digit0;
+use nom::character::complete::digit1;
 use nom::character::complete::one_of;
 use nom::combinator::all_consuming;
 use nom::combinator::map;
 use nom::combinator::opt;
 use nom::combinator::recognize;
+use nom::combinator::value;
 use nom::multi::many0;
+use nom::multi::separated_list0;
 use nom::sequence::delimited;
 use nom::sequence::pair;
 use nom::sequence::preceded;
@@ -69,6 +74,310 @@ impl JSONSelection {
             JSONSelection::Path(path) => path.next_mut_subselection(),
         }
     }
+
+    /// Parses the complete input string, returning a [`JSONSelectionParseError`]
+    /// that pinpoints the first syntax error when the input is malformed.
+    pub fn parse_checked(input: &str) -> Result<Self, JSONSelectionParseError> {
+        match Self::parse(input) {
+            Ok((_, selection)) => Ok(selection),
+            Err(_) => {
+                let (_, mut errors) = Self::parse_with_recovery(input);
+                // Recovery always reports at least one error for input that
+                // failed to parse, but we fall back to a generic error at the
+                // start of the input just in case.
+                Err(if errors.is_empty() {
+                    JSONSelectionParseError::new(input, input, "invalid selection", &[])
+                } else {
+                    errors.swap_remove(0)
+                })
+            }
+        }
+    }
+
+    /// Parses as much of the input as possible, skipping over malformed
+    /// selections so every syntax error can be reported at once. The returned
+    /// selection contains whatever parsed successfully, which is useful for
+    /// tooling that wants to keep working with a partially valid selection.
+    pub fn parse_with_recovery(input: &str) -> (Self, Vec<JSONSelectionParseError>) {
+        if let Ok((_, selection)) = Self::parse(input) {
+            return (selection, vec![]);
+        }
+
+        let mut errors = vec![];
+
+        // If the input makes more progress when parsed as a PathSelection
+        // than as a NakedSubSelection, report errors in terms of the path.
+        let naked_remainder = many0(NamedSelection::parse)(input)
+            .map(|(remainder, _)| remainder)
+            .unwrap_or(input);
+        if let Ok((path_remainder, path)) = PathSelection::parse(input) {
+            if path_remainder.len() < naked_remainder.len() {
+                let (path_remainder, _) =
+                    spaces_or_comments(path_remainder).unwrap_or((path_remainder, ""));
+                errors.push(diagnose(input, path_remainder));
+                return (Self::Path(path), errors);
+            }
+        }
+
+        let mut selections = vec![];
+        let mut star = None;
+        let mut remainder = input;
+        // While recovering, we skip malformed text without reporting further
+        // errors until something parses successfully again, so one mistake
+        // does not produce a cascade of follow-on errors.
+        let mut recovering = false;
+
+        loop {
+            remainder = spaces_or_comments(remainder)
+                .map(|(suffix, _)| suffix)
+                .unwrap_or(remainder);
+            if remainder.is_empty() {
+                break;
+            }
+
+            if star.is_none() {
+                if let Ok((suffix, named)) = NamedSelection::parse(remainder) {
+                    selections.push(named);
+                    remainder = suffix;
+                    recovering = false;
+                    continue;
+                }
+                if let Ok((suffix, star_selection)) = StarSelection::parse(remainder) {
+                    star = Some(star_selection);
+                    remainder = suffix;
+                    recovering = false;
+                    continue;
+                }
+            }
+
+            if !recovering {
+                errors.push(if star.is_some() && !remainder.starts_with('{') {
+                    JSONSelectionParseError::new(
+                        input,
+                        remainder,
+                        "a `*` selection must be the last selection",
+                        &["end of input"],
+                    )
+                } else {
+                    diagnose(input, remainder)
+                });
+                recovering = true;
+            }
+            remainder = skip_malformed(remainder);
+        }
+
+        (Self::Named(SubSelection { selections, star }), errors)
+    }
+}
+
+/// A syntax error encountered while parsing a JSONSelection string, with
+/// enough positional information to show authors exactly where their
+/// selection is malformed.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, thiserror::Error)]
+#[error("{message} (line {line}, column {column})")]
+pub struct JSONSelectionParseError {
+    /// A human-readable description of the problem.
+    pub message: String,
+    /// The rest of the line starting at the error location.
+    pub fragment: String,
+    /// The byte offset of the error within the input string.
+    pub offset: usize,
+    /// The 1-based line number of the error.
+    pub line: usize,
+    /// The 1-based column number of the error, counted in characters.
+    pub column: usize,
+    /// Descriptions of the tokens that would have been accepted instead.
+    pub expected: Vec<&'static str>,
+}
+
+impl JSONSelectionParseError {
+    // The remainder must be a suffix of the input, which holds for all the
+    // nom parsers in this module, since they only ever consume a prefix.
+    fn new(input: &str, remainder: &str, message: &str, expected: &[&'static str]) -> Self {
+        let offset = input.len() - remainder.len();
+        let before = &input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+        let fragment = remainder.split('\n').next().unwrap_or_default().to_string();
+        Self {
+            message: message.to_string(),
+            fragment,
+            offset,
+            line,
+            column,
+            expected: expected.to_vec(),
+        }
+    }
+}
+
+const EXPECTED_SELECTION: &[&str] = &["identifier", "string literal", "alias", "*"];
+
+// Explains why parsing stopped at the given remainder of the input, descending
+// into unclosed or malformed { ... } subselections when necessary.
+fn diagnose(input: &str, remainder: &str) -> JSONSelectionParseError {
+    let Some(first) = remainder.chars().next() else {
+        return JSONSelectionParseError::new(
+            input,
+            remainder,
+            "unexpected end of input",
+            EXPECTED_SELECTION,
+        );
+    };
+
+    match first {
+        '{' => diagnose_subselection(input, remainder),
+        '}' => JSONSelectionParseError::new(
+            input,
+            remainder,
+            "unexpected `}` without a matching `{`",
+            EXPECTED_SELECTION,
+        ),
+        '.' => {
+            let next_key = preceded(tuple((char('.'), spaces_or_comments)), Key::parse)(remainder);
+            if next_key.is_ok() {
+                JSONSelectionParseError::new(
+                    input,
+                    remainder,
+                    "path selections must be given an alias, as in `alias: a.b`",
+                    &["alias"],
+                )
+            } else {
+                JSONSelectionParseError::new(
+                    input,
+                    &remainder[1..],
+                    "expected a property name after `.`",
+                    &["identifier", "string literal"],
+                )
+            }
+        }
+        '-' if remainder.starts_with("->") => JSONSelectionParseError::new(
+            input,
+            remainder,
+            "path selections must be given an alias, as in `alias: a->method`",
+            &["alias"],
+        ),
+        '$' | '@' => JSONSelectionParseError::new(
+            input,
+            remainder,
+            "variable paths must be given an alias unless they are the entire selection",
+            &["alias"],
+        ),
+        ':' => JSONSelectionParseError::new(
+            input,
+            remainder,
+            "expected an identifier before `:`",
+            &["identifier"],
+        ),
+        _ => {
+            if let Ok((suffix, _)) = Alias::parse(remainder) {
+                if suffix.starts_with('{') {
+                    return diagnose_subselection(input, suffix);
+                }
+                if suffix.starts_with(['.', '\'', '"']) {
+                    return diagnose(input, suffix);
+                }
+                return JSONSelectionParseError::new(
+                    input,
+                    suffix,
+                    "expected a field name, string literal, path, or subselection after alias",
+                    &["identifier", "string literal", "$", "{"],
+                );
+            }
+            if parse_string_literal(remainder).is_err() && matches!(first, '\'' | '"') {
+                return JSONSelectionParseError::new(
+                    input,
+                    remainder,
+                    "unterminated string literal",
+                    &[if first == '"' { "\"" } else { "'" }],
+                );
+            }
+            let message = format!("unexpected character `{first}`");
+            JSONSelectionParseError::new(input, remainder, message.as_str(), EXPECTED_SELECTION)
+        }
+    }
+}
+
+// Diagnoses a remainder starting with `{`, which failed to parse as a complete
+// SubSelection, by walking its contents one NamedSelection at a time.
+fn diagnose_subselection(input: &str, remainder: &str) -> JSONSelectionParseError {
+    let mut inner = &remainder[1..];
+    let mut seen_star = false;
+
+    loop {
+        inner = spaces_or_comments(inner)
+            .map(|(suffix, _)| suffix)
+            .unwrap_or(inner);
+
+        if inner.is_empty() {
+            let opening = JSONSelectionParseError::new(input, remainder, "", &[]);
+            let message = format!(
+                "expected `}}` to close the `{{` from line {}, column {}",
+                opening.line, opening.column,
+            );
+            return JSONSelectionParseError::new(input, inner, message.as_str(), &["}"]);
+        }
+
+        if inner.starts_with('}') {
+            // The braces are balanced and everything inside parsed, so the
+            // problem must be the subselection's placement.
+            return JSONSelectionParseError::new(
+                input,
+                remainder,
+                "unexpected subselection",
+                EXPECTED_SELECTION,
+            );
+        }
+
+        if seen_star && !inner.starts_with('{') {
+            return JSONSelectionParseError::new(
+                input,
+                inner,
+                "a `*` selection must be the last selection in its group",
+                &["}"],
+            );
+        }
+
+        if let Ok((suffix, _)) = NamedSelection::parse(inner) {
+            inner = suffix;
+        } else if let Ok((suffix, _)) = StarSelection::parse(inner) {
+            inner = suffix;
+            seen_star = true;
+        } else {
+            return diagnose(input, inner);
+        }
+    }
+}
+
+// Skips past the malformed text at the beginning of the remainder, treating a
+// { ... } group (including any nested groups) as a single unit.
+fn skip_malformed(remainder: &str) -> &str {
+    match remainder.chars().next() {
+        Some('{') => {
+            let mut depth = 0;
+            for (i, c) in remainder.char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return &remainder[i + 1..];
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            ""
+        }
+        Some(c) if c.is_ascii_alphanumeric() || c == '_' => {
+            let end = remainder
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(remainder.len());
+            &remainder[end..]
+        }
+        Some(c) => &remainder[c.len_utf8()..],
+        None => remainder,
+    }
 }
 
 // NamedSelection       ::= NamedPathSelection | NamedFieldSelection | NamedQuotedSelection | NamedGroupSelection
@@ -193,7 +502,7 @@ impl NamedSelection {
 }
 
 // PathSelection ::= (VarPath | KeyPath) SubSelection?
-// VarPath       ::= "$" (NO_SPACE Identifier)? PathStep*
+// VarPath       ::= ("$" (NO_SPACE Identifier)? | "@") PathStep*
 // KeyPath       ::= Key PathStep+
 // PathStep      ::= "." Key | "->" Identifier MethodArgs?
 
@@ -203,6 +512,7 @@ pub enum PathSelection {
     // the selection to a JSON value easier.
     Var(String, Box<PathSelection>),
     Key(Key, Box<PathSelection>),
+    Method(String, Option<MethodArgs>, Box<PathSelection>),
     Selection(SubSelection),
     Empty,
 }
@@ -236,6 +546,13 @@ impl PathSelection {
                 return Ok((input, Self::Var(dollar_var, Box::new(rest))));
             }
 
+            // The @ variable refers to the input value of the method whose
+            // arguments contain it, such as each element in items->keyBy(@.id).
+            if let Ok((suffix, _)) = tuple((spaces_or_comments, char('@')))(input) {
+                let (input, rest) = Self::parse_with_depth(suffix, depth + 1)?;
+                return Ok((input, Self::Var("@".to_string(), Box::new(rest))));
+            }
+
             if let Ok((suffix, key)) = Key::parse(input) {
                 let (input, rest) = Self::parse_with_depth(suffix, depth + 1)?;
                 return match rest {
@@ -260,6 +577,19 @@ impl PathSelection {
             return Ok((input, Self::Key(key, Box::new(rest))));
         }
 
+        // Like .key steps, ->method steps may appear anywhere after the first
+        // step of the path, but a path cannot begin with a method.
+        if depth > 0 {
+            if let Ok((suffix, (method, args))) = preceded(
+                tuple((spaces_or_comments, tag("->"))),
+                pair(parse_identifier, opt(MethodArgs::parse)),
+            )(input)
+            {
+                let (input, rest) = Self::parse_with_depth(suffix, depth + 1)?;
+                return Ok((input, Self::Method(method, args, Box::new(rest))));
+            }
+        }
+
         if depth == 0 {
             // If the PathSelection does not start with a $var, a key., or a
             // .key, it is not a valid PathSelection.
@@ -312,6 +642,7 @@ impl PathSelection {
         match self {
             PathSelection::Var(_, path) => path.next_subselection(),
             PathSelection::Key(_, path) => path.next_subselection(),
+            PathSelection::Method(_, _, path) => path.next_subselection(),
             PathSelection::Selection(sub) => Some(sub),
             PathSelection::Empty => None,
         }
@@ -322,12 +653,118 @@ impl PathSelection {
         match self {
             PathSelection::Var(_, path) => path.next_mut_subselection(),
             PathSelection::Key(_, path) => path.next_mut_subselection(),
+            PathSelection::Method(_, _, path) => path.next_mut_subselection(),
             PathSelection::Selection(sub) => Some(sub),
             PathSelection::Empty => None,
         }
     }
 }
 
+// MethodArgs ::= "(" (JSLiteral ("," JSLiteral)*)? ")"
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct MethodArgs(pub(super) Vec<JSLiteral>);
+
+impl MethodArgs {
+    fn parse(input: &str) -> IResult<&str, Self> {
+        delimited(
+            tuple((spaces_or_comments, char('('), spaces_or_comments)),
+            separated_list0(char(','), JSLiteral::parse),
+            tuple((spaces_or_comments, char(')'), spaces_or_comments)),
+        )(input)
+        .map(|(input, args)| (input, Self(args)))
+    }
+
+    pub(super) fn args(&self) -> &[JSLiteral] {
+        self.0.as_slice()
+    }
+}
+
+// JSLiteral   ::= JSPrimitive | JSObject | JSArray | PathSelection
+// JSPrimitive ::= StringLiteral | JSNumber | "true" | "false" | "null"
+// JSNumber    ::= "-"? (UnsignedInt ("." [0-9]*)? | "." [0-9]+)
+// UnsignedInt ::= "0" | [1-9] NO_SPACE [0-9]*
+// JSObject    ::= "{" (JSProperty ("," JSProperty)*)? "}"
+// JSProperty  ::= Key ":" JSLiteral
+// JSArray     ::= "[" (JSLiteral ("," JSLiteral)*)? "]"
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub enum JSLiteral {
+    String(String),
+    // Numbers are kept in their original source form, so they can be
+    // reprinted exactly, and converted to JSON only when applied.
+    Number(String),
+    Bool(bool),
+    Null,
+    Object(Vec<(String, JSLiteral)>),
+    Array(Vec<JSLiteral>),
+    Path(PathSelection),
+}
+
+impl JSLiteral {
+    pub(crate) fn parse(input: &str) -> IResult<&str, Self> {
+        delimited(
+            spaces_or_comments,
+            alt((
+                // PathSelection must be attempted before the keyword literals,
+                // so a path like `nullable.value` is not mistaken for `null`
+                // followed by unparseable text.
+                map(PathSelection::parse, Self::Path),
+                map(parse_string_literal, Self::String),
+                Self::parse_number,
+                Self::parse_object,
+                Self::parse_array,
+                value(Self::Bool(true), tag("true")),
+                value(Self::Bool(false), tag("false")),
+                value(Self::Null, tag("null")),
+            )),
+            spaces_or_comments,
+        )(input)
+    }
+
+    fn parse_number(input: &str) -> IResult<&str, Self> {
+        recognize(tuple((
+            opt(char('-')),
+            alt((
+                recognize(pair(
+                    alt((tag("0"), recognize(pair(one_of("123456789"), digit0)))),
+                    opt(pair(char('.'), digit0)),
+                )),
+                recognize(pair(char('.'), digit1)),
+            )),
+        )))(input)
+        .map(|(input, number)| (input, Self::Number(number.to_string())))
+    }
+
+    fn parse_object(input: &str) -> IResult<&str, Self> {
+        delimited(
+            tuple((char('{'), spaces_or_comments)),
+            separated_list0(
+                char(','),
+                map(
+                    tuple((
+                        delimited(spaces_or_comments, Key::parse, spaces_or_comments),
+                        char(':'),
+                        Self::parse,
+                    )),
+                    |(key, _, value)| (key.as_string(), value),
+                ),
+            ),
+            tuple((spaces_or_comments, char('}'))),
+        )(input)
+        .map(|(input, properties)| (input, Self::Object(properties)))
+    }
+
+    fn parse_array(input: &str) -> IResult<&str, Self> {
+        delimited(
+            tuple((char('['), spaces_or_comments)),
+            separated_list0(char(','), Self::parse),
+            tuple((spaces_or_comments, char(']'))),
+        )(input)
+        .map(|(input, items)| (input, Self::Array(items)))
+    }
+}
+
 // SubSelection ::= "{" NakedSubSelection "}"
 
 #[derive(Debug, PartialEq, Clone, Serialize, Default)]
@@ -1600,4 +2037,249 @@ mod tests {
             }),
         );
     }
+
+    #[test]
+    fn test_parse_checked_errors() {
+        assert_eq!(
+            JSONSelection::parse_checked("a { b c"),
+            Err(JSONSelectionParseError {
+                message: "expected `}` to close the `{` from line 1, column 3".to_string(),
+                fragment: "".to_string(),
+                offset: 7,
+                line: 1,
+                column: 8,
+                expected: vec!["}"],
+            }),
+        );
+
+        assert_eq!(
+            JSONSelection::parse_checked("a b.c d"),
+            Err(JSONSelectionParseError {
+                message: "path selections must be given an alias, as in `alias: a.b`".to_string(),
+                fragment: ".c d".to_string(),
+                offset: 3,
+                line: 1,
+                column: 4,
+                expected: vec!["alias"],
+            }),
+        );
+
+        let error = JSONSelection::parse_checked("id\nname: !").unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected a field name, string literal, path, or subselection after alias",
+        );
+        assert_eq!((error.offset, error.line, error.column), (9, 2, 7));
+        assert_eq!(
+            error.expected,
+            vec!["identifier", "string literal", "$", "{"]
+        );
+
+        let error = JSONSelection::parse_checked("x: 'unterminated").unwrap_err();
+        assert_eq!(error.message, "unterminated string literal");
+        assert_eq!((error.line, error.column), (1, 4));
+
+        let error = JSONSelection::parse_checked("a {\n  b\n  c: { d ! }\n}").unwrap_err();
+        assert_eq!(error.message, "unexpected character `!`");
+        assert_eq!(error.fragment, "! }");
+        assert_eq!((error.line, error.column), (3, 10));
+
+        let error = JSONSelection::parse_checked("a } b").unwrap_err();
+        assert_eq!(error.message, "unexpected `}` without a matching `{`");
+        assert_eq!(
+            error.to_string(),
+            "unexpected `}` without a matching `{` (line 1, column 3)"
+        );
+
+        let error = JSONSelection::parse_checked("rest: * extra").unwrap_err();
+        assert_eq!(error.message, "a `*` selection must be the last selection");
+        assert_eq!(error.fragment, "extra");
+
+        let error = JSONSelection::parse_checked("$.data.").unwrap_err();
+        assert_eq!(error.message, "expected a property name after `.`");
+        assert_eq!(error.offset, 7);
+    }
+
+    #[test]
+    fn test_parse_with_recovery() {
+        assert_eq!(
+            JSONSelection::parse_with_recovery("a b { c }"),
+            (selection!("a b { c }"), vec![]),
+        );
+
+        let (partial, errors) = JSONSelection::parse_with_recovery("a ?? b { c } d");
+        assert_eq!(partial, selection!("a b { c } d"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unexpected character `?`");
+        assert_eq!(errors[0].offset, 2);
+
+        let (partial, errors) = JSONSelection::parse_with_recovery("a: { b ! } c } d: e.");
+        assert_eq!(partial, selection!("c d: e"));
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.message.as_str(), error.column))
+                .collect::<Vec<_>>(),
+            vec![
+                ("unexpected character `!`", 8),
+                ("unexpected `}` without a matching `{`", 14),
+                ("expected a property name after `.`", 21),
+            ],
+        );
+
+        let (partial, errors) = JSONSelection::parse_with_recovery("$.data { id");
+        assert_eq!(partial, selection!("$.data"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].expected, vec!["}"]);
+    }
+
+    #[test]
+    fn test_path_selection_methods() {
+        check_path_selection(
+            "$->has('id')",
+            PathSelection::Var(
+                "$".to_string(),
+                Box::new(PathSelection::Method(
+                    "has".to_string(),
+                    Some(MethodArgs(vec![JSLiteral::String("id".to_string())])),
+                    Box::new(PathSelection::Empty),
+                )),
+            ),
+        );
+
+        check_path_selection(
+            "data.items->get(0).name",
+            PathSelection::Key(
+                Key::Field("data".to_string()),
+                Box::new(PathSelection::Key(
+                    Key::Field("items".to_string()),
+                    Box::new(PathSelection::Method(
+                        "get".to_string(),
+                        Some(MethodArgs(vec![JSLiteral::Number("0".to_string())])),
+                        Box::new(PathSelection::Key(
+                            Key::Field("name".to_string()),
+                            Box::new(PathSelection::Empty),
+                        )),
+                    )),
+                )),
+            ),
+        );
+
+        check_path_selection(
+            "$.a -> hasKey ( $args.key ) { b }",
+            PathSelection::Var(
+                "$".to_string(),
+                Box::new(PathSelection::Key(
+                    Key::Field("a".to_string()),
+                    Box::new(PathSelection::Method(
+                        "hasKey".to_string(),
+                        Some(MethodArgs(vec![JSLiteral::Path(PathSelection::Var(
+                            "$args".to_string(),
+                            Box::new(PathSelection::Key(
+                                Key::Field("key".to_string()),
+                                Box::new(PathSelection::Empty),
+                            )),
+                        ))])),
+                        Box::new(PathSelection::Selection(SubSelection {
+                            selections: vec![NamedSelection::Field(None, "b".to_string(), None)],
+                            star: None,
+                        })),
+                    )),
+                )),
+            ),
+        );
+
+        // A key followed only by a method still counts as a KeyPath.
+        check_path_selection(
+            "a->has",
+            PathSelection::Key(
+                Key::Field("a".to_string()),
+                Box::new(PathSelection::Method(
+                    "has".to_string(),
+                    None,
+                    Box::new(PathSelection::Empty),
+                )),
+            ),
+        );
+
+        check_path_selection(
+            "items->keyBy(@.id)",
+            PathSelection::Key(
+                Key::Field("items".to_string()),
+                Box::new(PathSelection::Method(
+                    "keyBy".to_string(),
+                    Some(MethodArgs(vec![JSLiteral::Path(PathSelection::Var(
+                        "@".to_string(),
+                        Box::new(PathSelection::Key(
+                            Key::Field("id".to_string()),
+                            Box::new(PathSelection::Empty),
+                        )),
+                    ))])),
+                    Box::new(PathSelection::Empty),
+                )),
+            ),
+        );
+
+        // A path cannot begin with a method.
+        assert!(PathSelection::parse("->has('a')").is_err());
+
+        assert_eq!(
+            JSONSelection::parse_checked("id a->has('b')")
+                .unwrap_err()
+                .message,
+            "path selections must be given an alias, as in `alias: a->method`",
+        );
+    }
+
+    #[test]
+    fn test_js_literal() {
+        assert_eq!(
+            JSLiteral::parse("'hello'"),
+            Ok(("", JSLiteral::String("hello".to_string()))),
+        );
+        assert_eq!(
+            JSLiteral::parse(" -1.5 "),
+            Ok(("", JSLiteral::Number("-1.5".to_string()))),
+        );
+        assert_eq!(
+            JSLiteral::parse(".5"),
+            Ok(("", JSLiteral::Number(".5".to_string()))),
+        );
+        assert_eq!(
+            JSLiteral::parse("0"),
+            Ok(("", JSLiteral::Number("0".to_string()))),
+        );
+        assert_eq!(JSLiteral::parse("true"), Ok(("", JSLiteral::Bool(true))));
+        assert_eq!(JSLiteral::parse("false"), Ok(("", JSLiteral::Bool(false))));
+        assert_eq!(JSLiteral::parse("null"), Ok(("", JSLiteral::Null)));
+        assert_eq!(
+            JSLiteral::parse("nullable.value"),
+            Ok((
+                "",
+                JSLiteral::Path(PathSelection::Key(
+                    Key::Field("nullable".to_string()),
+                    Box::new(PathSelection::Key(
+                        Key::Field("value".to_string()),
+                        Box::new(PathSelection::Empty),
+                    )),
+                )),
+            )),
+        );
+        assert_eq!(
+            JSLiteral::parse("{ a: [1, 'two'], \"b c\": {} }"),
+            Ok((
+                "",
+                JSLiteral::Object(vec![
+                    (
+                        "a".to_string(),
+                        JSLiteral::Array(vec![
+                            JSLiteral::Number("1".to_string()),
+                            JSLiteral::String("two".to_string()),
+                        ]),
+                    ),
+                    ("b c".to_string(), JSLiteral::Object(vec![])),
+                ]),
+            )),
+        );
+    }
 }
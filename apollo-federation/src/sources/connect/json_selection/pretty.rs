@@ -5,7 +5,9 @@
 //! pretty printing trait which is then implemented on the various sub types
 //! of the JSONSelection tree.
 
+use crate::sources::connect::json_selection::JSLiteral;
 use crate::sources::connect::json_selection::JSONSelection;
+use crate::sources::connect::json_selection::MethodArgs;
 use crate::sources::connect::json_selection::NamedSelection;
 use crate::sources::connect::json_selection::PathSelection;
 use crate::sources::connect::json_selection::StarSelection;
@@ -101,6 +103,16 @@ impl PrettyPrintable for PathSelection {
                 result.push_str(key.dotted().as_str());
                 result.push_str(rest.as_str());
             }
+            PathSelection::Method(method, args, path) => {
+                result.push_str("->");
+                result.push_str(method.as_str());
+                if let Some(args) = args {
+                    let args = args.pretty_print_with_indentation(true, indentation);
+                    result.push_str(args.as_str());
+                }
+                let rest = path.pretty_print_with_indentation(true, indentation);
+                result.push_str(rest.as_str());
+            }
             PathSelection::Selection(sub) => {
                 let sub = sub.pretty_print_with_indentation(true, indentation);
                 result.push(' ');
@@ -113,6 +125,64 @@ impl PrettyPrintable for PathSelection {
     }
 }
 
+impl PrettyPrintable for MethodArgs {
+    fn pretty_print_with_indentation(&self, _inline: bool, indentation: usize) -> String {
+        let args = self
+            .args()
+            .iter()
+            .map(|arg| arg.pretty_print_with_indentation(true, indentation))
+            .collect::<Vec<_>>();
+        format!("({})", args.join(", "))
+    }
+}
+
+impl PrettyPrintable for JSLiteral {
+    fn pretty_print_with_indentation(&self, _inline: bool, indentation: usize) -> String {
+        match self {
+            JSLiteral::String(string) => {
+                serde_json_bytes::Value::String(string.clone().into()).to_string()
+            }
+            JSLiteral::Number(number) => number.clone(),
+            JSLiteral::Bool(b) => b.to_string(),
+            JSLiteral::Null => "null".to_string(),
+            JSLiteral::Object(properties) => {
+                if properties.is_empty() {
+                    return "{}".to_string();
+                }
+                let properties = properties
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = value.pretty_print_with_indentation(true, indentation);
+                        // Keys that are valid identifiers can be printed without
+                        // quotes, as they would typically be written.
+                        let is_identifier = key
+                            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                        if is_identifier {
+                            format!("{key}: {value}")
+                        } else {
+                            let quoted = serde_json_bytes::Value::String(key.clone().into());
+                            format!("{quoted}: {value}")
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                format!("{{ {} }}", properties.join(", "))
+            }
+            JSLiteral::Array(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| item.pretty_print_with_indentation(true, indentation))
+                    .collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }
+            JSLiteral::Path(path) => path
+                .pretty_print_with_indentation(true, indentation)
+                .trim_start()
+                .to_string(),
+        }
+    }
+}
+
 impl PrettyPrintable for NamedSelection {
     fn pretty_print_with_indentation(&self, inline: bool, indentation: usize) -> String {
         let mut result = String::new();
@@ -287,6 +357,11 @@ mod tests {
             ".first",
             ".a.b.c.d.e",
             ".one.two.three {\n  a\n  b\n}",
+            // Method
+            "$->has(\"id\")",
+            "$.list->get(-1, null).name",
+            "$->get($args.key, { a: [1, 2.5, true], \"b c\": $.fallback })",
+            ".items->hasKey(\"id\") {\n  a\n}",
         ];
         for path in paths {
             let (unmatched, path_selection) = PathSelection::parse(path).unwrap();
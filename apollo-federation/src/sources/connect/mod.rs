@@ -6,6 +6,7 @@ mod url_path_template;
 pub use json_selection::ApplyTo;
 pub use json_selection::ApplyToError;
 pub use json_selection::JSONSelection;
+pub use json_selection::JSONSelectionParseError;
 pub use json_selection::Key;
 pub use json_selection::PathSelection;
 pub use json_selection::SubSelection;
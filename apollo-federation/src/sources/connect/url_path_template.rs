@@ -694,7 +694,7 @@ mod tests {
                         })],
                     },
                 ],
-                query: IndexMap::from([(
+                query: IndexMap::from_iter([(
                     "a".to_string(),
                     ParameterValue {
                         parts: vec![ValuePart::Text("b".to_string())],
@@ -718,7 +718,7 @@ mod tests {
                         })],
                     },
                 ],
-                query: IndexMap::from([
+                query: IndexMap::from_iter([
                     (
                         "e".to_string(),
                         ParameterValue {
@@ -766,7 +766,7 @@ mod tests {
                         })],
                     },
                 ],
-                query: IndexMap::from([(
+                query: IndexMap::from_iter([(
                     "a".to_string(),
                     ParameterValue {
                         parts: vec![
@@ -837,7 +837,7 @@ mod tests {
                         ],
                     },
                 ],
-                query: IndexMap::from([(
+                query: IndexMap::from_iter([(
                     "a".to_string(),
                     ParameterValue {
                         parts: vec![ValuePart::Var(VariableExpression {
@@ -979,7 +979,7 @@ mod tests {
                 path: vec![ParameterValue {
                     parts: vec![ValuePart::Text("users".to_string())],
                 }],
-                query: IndexMap::from([(
+                query: IndexMap::from_iter([(
                     "ids".to_string(),
                     ParameterValue {
                         parts: vec![ValuePart::Var(VariableExpression {
@@ -1003,7 +1003,7 @@ mod tests {
                         parts: vec![ValuePart::Text("products".to_string())]
                     },
                 ],
-                query: IndexMap::from([
+                query: IndexMap::from_iter([
                     (
                         "ids".to_string(),
                         ParameterValue {
@@ -1034,7 +1034,7 @@ mod tests {
                 path: vec![ParameterValue {
                     parts: vec![ValuePart::Text("people".to_string())],
                 }],
-                query: IndexMap::from([(
+                query: IndexMap::from_iter([(
                     "ids".to_string(),
                     ParameterValue {
                         parts: vec![ValuePart::Var(VariableExpression {
@@ -1065,7 +1065,7 @@ mod tests {
                         parts: vec![ValuePart::Text("notes".to_string())],
                     },
                 ],
-                query: IndexMap::from([(
+                query: IndexMap::from_iter([(
                     "ids".to_string(),
                     ParameterValue {
                         parts: vec![ValuePart::Var(VariableExpression {
@@ -1100,7 +1100,7 @@ mod tests {
                     },
                 ],
 
-                query: IndexMap::from([(
+                query: IndexMap::from_iter([(
                     "ids".to_string(),
                     ParameterValue {
                         parts: vec![
@@ -1 +1 @@
-mod connect;
+pub(crate) mod connect;
@@ -0,0 +1,68 @@
+//! Apply header propagation rules to the subgraph requests of an operation, for a client
+//! request with many headers.
+//!
+//! Run with `cargo bench -p apollo-router-benchmarks --bench header_processing`
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use serde_json::json;
+use workloads::run_workload;
+use workloads::workload_harness;
+use workloads::LARGE_LIST;
+
+#[allow(dead_code)]
+mod workloads {
+    include!("../src/workloads.rs");
+}
+
+/// Client headers of the requests
+const CLIENT_HEADERS: usize = 50;
+
+fn header_processing(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let headers: Vec<(String, String)> = (0..CLIENT_HEADERS)
+        .map(|i| (format!("x-bench-{i}"), format!("value-{i}")))
+        .chain([("authorization".to_string(), "Bearer token".to_string())])
+        .collect();
+
+    let without_rules = runtime.block_on(workload_harness().build_router()).unwrap();
+    let with_rules = runtime
+        .block_on(
+            workload_harness()
+                .configuration_json(json!({
+                    "headers": {
+                        "all": {
+                            "request": [
+                                { "propagate": { "matching": "^x-bench-.*" } },
+                                { "remove": { "named": "x-bench-0" } },
+                                { "insert": { "name": "x-router", "value": "benchmark" } }
+                            ]
+                        },
+                        "subgraphs": {
+                            "reviews": {
+                                "request": [
+                                    { "propagate": { "named": "authorization", "rename": "x-authorization" } },
+                                    { "insert": { "name": "x-operation-name", "path": ".operationName", "default": "UNKNOWN" } }
+                                ]
+                            }
+                        }
+                    }
+                }))
+                .unwrap()
+                .build_router(),
+        )
+        .unwrap();
+
+    let mut group = c.benchmark_group("header_processing");
+    for (name, router) in [("without_rules", without_rules), ("with_rules", with_rules)] {
+        group.bench_function(name, |b| {
+            b.to_async(&runtime)
+                .iter(|| run_workload(router.clone(), LARGE_LIST, 10, &headers))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, header_processing);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+//! Plan the operations of the federated workloads with the query planner, without caching.
+//!
+//! Run with `cargo bench -p apollo-router-benchmarks --bench query_planning`
+
+use apollo_compiler::ExecutableDocument;
+use apollo_federation::query_plan::query_planner::QueryPlanner;
+use apollo_federation::query_plan::query_planner::QueryPlannerConfig;
+use apollo_federation::Supergraph;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use workloads::DEEP_ENTITY_JOINS;
+use workloads::LARGE_LIST;
+use workloads::SUPERGRAPH;
+
+#[allow(dead_code)]
+mod workloads {
+    include!("../src/workloads.rs");
+}
+
+fn query_planning(c: &mut Criterion) {
+    let supergraph = Supergraph::new(SUPERGRAPH).unwrap();
+    let planner = QueryPlanner::new(&supergraph, QueryPlannerConfig::default()).unwrap();
+
+    let mut group = c.benchmark_group("query_planning");
+    for (name, query) in [
+        ("deep_entity_joins", DEEP_ENTITY_JOINS),
+        ("large_list", LARGE_LIST),
+    ] {
+        let document = ExecutableDocument::parse_and_validate(
+            supergraph.schema.schema(),
+            query,
+            "query.graphql",
+        )
+        .unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| planner.build_query_plan(&document, None).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, query_planning);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+//! Format the responses of the large list operation to its shape, as the execution service does
+//! once the subgraph responses are merged.
+//!
+//! Run with `cargo bench -p apollo-router-benchmarks --bench response_formatting`
+
+use apollo_router::_private::ResponseFormatter;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BatchSize;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use workloads::large_list_response;
+use workloads::LARGE_LIST;
+use workloads::SUPERGRAPH;
+
+#[allow(dead_code)]
+mod workloads {
+    include!("../src/workloads.rs");
+}
+
+fn response_formatting(c: &mut Criterion) {
+    let formatter = ResponseFormatter::new(SUPERGRAPH, LARGE_LIST).unwrap();
+
+    let mut group = c.benchmark_group("response_formatting");
+    for first in [100, 1000] {
+        let response = large_list_response(first);
+        group.bench_with_input(
+            BenchmarkId::new("large_list", first),
+            &response,
+            |b, response| {
+                b.iter_batched(
+                    || response.clone(),
+                    |mut response| {
+                        formatter.format(&mut response);
+                        response
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, response_formatting);
+criterion_main!(benches);
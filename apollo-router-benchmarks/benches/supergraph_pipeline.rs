@@ -0,0 +1,39 @@
+//! Run the federated workloads through the whole router pipeline, with mocked subgraphs: query
+//! analysis, cached query planning, subgraph fetches and response formatting.
+//!
+//! Run with `cargo bench -p apollo-router-benchmarks --bench supergraph_pipeline`
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use workloads::run_workload;
+use workloads::workload_harness;
+use workloads::DEEP_ENTITY_JOINS;
+use workloads::LARGE_LIST;
+
+#[allow(dead_code)]
+mod workloads {
+    include!("../src/workloads.rs");
+}
+
+fn supergraph_pipeline(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let router = runtime.block_on(workload_harness().build_router()).unwrap();
+
+    let mut group = c.benchmark_group("supergraph_pipeline");
+    for (name, query, first) in [
+        ("deep_entity_joins", DEEP_ENTITY_JOINS, 10),
+        ("large_list", LARGE_LIST, 100),
+        ("large_list", LARGE_LIST, 1000),
+    ] {
+        group.bench_with_input(BenchmarkId::new(name, first), &first, |b, &first| {
+            b.to_async(&runtime)
+                .iter(|| run_workload(router.clone(), query, first, &[]))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, supergraph_pipeline);
+criterion_main!(benches);
@@ -11,3 +11,52 @@ pub mod tests {
         runtime.block_on(async move { basic_composition_benchmark(router).await });
     }
 }
+
+#[cfg(test)]
+pub mod workloads_tests {
+    include!("workloads.rs");
+
+    #[test]
+    fn workloads_resolve_every_entity() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let router = runtime
+            .block_on(workload_harness().build_router())
+            .unwrap();
+        for (query, first) in [(DEEP_ENTITY_JOINS, 2), (LARGE_LIST, 5)] {
+            let response = runtime.block_on(run_workload(router.clone(), query, first, &[]));
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+            let products = response
+                .data
+                .as_ref()
+                .and_then(|data| data.get("topProducts"))
+                .and_then(Value::as_array)
+                .unwrap();
+            assert_eq!(products.len(), first);
+        }
+    }
+
+    #[test]
+    fn large_list_responses_are_formatted() {
+        let formatter =
+            apollo_router::_private::ResponseFormatter::new(SUPERGRAPH, LARGE_LIST).unwrap();
+        let mut response = large_list_response(1);
+        formatter.format(&mut response);
+        assert_eq!(
+            response.data,
+            Some(json!({
+                "topProducts": [{
+                    "upc": "0",
+                    "name": "Product 0",
+                    "price": 100,
+                    "weight": 1,
+                    "inStock": false,
+                    "reviews": [
+                        { "id": "p0-0", "body": "Review p0-0", "author": { "id": "0", "name": "User 0" } },
+                        { "id": "p0-1", "body": "Review p0-1", "author": { "id": "1", "name": "User 1" } },
+                        { "id": "p0-2", "body": "Review p0-2", "author": { "id": "2", "name": "User 2" } }
+                    ]
+                }]
+            }))
+        );
+    }
+}
@@ -0,0 +1,235 @@
+// Federated workloads shared between the benchmarks and the tests, included with include!() like
+// shared.rs. The subgraphs are mocked by resolvers generating an entity for any representation,
+// so the workloads don't depend on the exact subgraph operations of the query plans.
+use apollo_router::graphql;
+use apollo_router::services::router;
+use apollo_router::services::subgraph;
+use apollo_router::services::supergraph;
+use apollo_router::TestHarness;
+use serde_json_bytes::json;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value;
+use tower::Service;
+use tower::ServiceExt;
+
+pub static SUPERGRAPH: &str = include_str!("../benches/fixtures/supergraph-fed2.graphql");
+
+/// Products, with reviews whose authors wrote reviews of other products, joining entities of the
+/// four subgraphs at each level
+pub static DEEP_ENTITY_JOINS: &str = r#"query DeepEntityJoins($first: Int) { topProducts(first: $first) { upc name price inStock shippingEstimate reviews { id body author { id name username reviews { id body product { upc name inStock reviews { id author { id name } } } } } } } }"#;
+
+/// A long list of products, with a few fields from each subgraph
+pub static LARGE_LIST: &str = r#"query LargeList($first: Int) { topProducts(first: $first) { upc name price weight inStock reviews { id body author { id name } } } }"#;
+
+/// Reviews of each product, and of each user
+const REVIEWS_PER_ENTITY: usize = 3;
+
+/// Products and users referenced by the reviews
+const ENTITY_COUNT: usize = 1000;
+
+fn key(representation: &Value, field: &str) -> usize {
+    representation
+        .get(field)
+        .and_then(Value::as_str)
+        .and_then(|key| key.parse().ok())
+        .unwrap_or_default()
+}
+
+fn product(upc: usize) -> Value {
+    json!({
+        "__typename": "Product",
+        "upc": upc.to_string(),
+        "name": format!("Product {upc}"),
+        "price": 100 + upc % 900,
+        "weight": 1 + upc % 20,
+    })
+}
+
+fn user(id: usize) -> Value {
+    json!({
+        "__typename": "User",
+        "id": id.to_string(),
+        "name": format!("User {id}"),
+        "username": format!("user{id}"),
+    })
+}
+
+fn review(id: String, upc: usize, author: usize) -> Value {
+    json!({
+        "__typename": "Review",
+        "body": format!("Review {id}"),
+        "id": id,
+        "product": { "__typename": "Product", "upc": upc.to_string() },
+        "author": { "__typename": "User", "id": author.to_string(), "username": format!("user{author}") },
+    })
+}
+
+fn product_reviews(upc: usize) -> Vec<Value> {
+    (0..REVIEWS_PER_ENTITY)
+        .map(|i| {
+            review(
+                format!("p{upc}-{i}"),
+                upc,
+                (upc * REVIEWS_PER_ENTITY + i) % ENTITY_COUNT,
+            )
+        })
+        .collect()
+}
+
+fn user_reviews(id: usize) -> Vec<Value> {
+    (0..REVIEWS_PER_ENTITY)
+        .map(|i| {
+            review(
+                format!("u{id}-{i}"),
+                (id * REVIEWS_PER_ENTITY + i) % ENTITY_COUNT,
+                id,
+            )
+        })
+        .collect()
+}
+
+/// The entity a subgraph resolves for a representation, with all the fields it can resolve
+fn entity(subgraph_name: &str, representation: &Value) -> Value {
+    let typename = representation
+        .get("__typename")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    match (subgraph_name, typename) {
+        ("accounts", "User") => user(key(representation, "id")),
+        ("inventory", "Product") => {
+            let upc = key(representation, "upc");
+            json!({
+                "__typename": "Product",
+                "upc": upc.to_string(),
+                "inStock": upc % 3 != 0,
+                "shippingEstimate": upc % 50,
+            })
+        }
+        ("products", "Product") => product(key(representation, "upc")),
+        ("reviews", "Product") => {
+            let upc = key(representation, "upc");
+            json!({
+                "__typename": "Product",
+                "upc": upc.to_string(),
+                "reviews": product_reviews(upc),
+            })
+        }
+        ("reviews", "User") => {
+            let id = key(representation, "id");
+            json!({
+                "__typename": "User",
+                "id": id.to_string(),
+                "reviews": user_reviews(id),
+            })
+        }
+        _ => Value::Null,
+    }
+}
+
+/// The data of a subgraph response, for entity and root fetches
+fn resolve(subgraph_name: &str, variables: &Map<ByteString, Value>) -> Value {
+    if let Some(representations) = variables.get("representations").and_then(Value::as_array) {
+        let entities: Vec<Value> = representations
+            .iter()
+            .map(|representation| entity(subgraph_name, representation))
+            .collect();
+        return json!({ "_entities": entities });
+    }
+    match subgraph_name {
+        "products" => {
+            let first = variables.get("first").and_then(Value::as_u64).unwrap_or(5) as usize;
+            let products: Vec<Value> = (0..first).map(product).collect();
+            json!({ "topProducts": products })
+        }
+        "accounts" => json!({ "me": user(1) }),
+        _ => json!({}),
+    }
+}
+
+/// A router over the supergraph, with the subgraphs generating their entities
+pub fn workload_harness() -> TestHarness<'static> {
+    TestHarness::builder()
+        .schema(SUPERGRAPH)
+        .subgraph_hook(|subgraph_name, _| {
+            let subgraph_name = subgraph_name.to_string();
+            tower::service_fn(move |request: subgraph::Request| {
+                let data = resolve(&subgraph_name, &request.subgraph_request.body().variables);
+                async move {
+                    Ok::<_, tower::BoxError>(
+                        subgraph::Response::fake_builder()
+                            .data(data)
+                            .context(request.context)
+                            .build(),
+                    )
+                }
+            })
+            .boxed()
+        })
+}
+
+/// Sends an operation with its `first` variable and client headers through the router
+pub async fn run_workload(
+    mut router_service: router::BoxCloneService,
+    query: &str,
+    first: usize,
+    headers: &[(String, String)],
+) -> graphql::Response {
+    let mut request = supergraph::Request::fake_builder()
+        .query(query.to_string())
+        .variable("first", first);
+    for (name, value) in headers {
+        request = request.header(name.clone(), value.clone());
+    }
+    let request = request
+        .build()
+        .expect("expecting valid request")
+        .try_into()
+        .unwrap();
+
+    serde_json::from_slice(
+        &router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap()
+            .unwrap(),
+    )
+    .unwrap()
+}
+
+/// The response of the large list operation before formatting, with the typenames and the
+/// unselected fields the subgraphs return
+pub fn large_list_response(first: usize) -> graphql::Response {
+    let products: Vec<Value> = (0..first)
+        .map(|upc| {
+            let mut product = product(upc);
+            let fields = product.as_object_mut().unwrap();
+            fields.insert("inStock", Value::Bool(upc % 3 != 0));
+            let reviews: Vec<Value> = product_reviews(upc)
+                .into_iter()
+                .map(|mut review| {
+                    let author = review
+                        .get("author")
+                        .map(|author| key(author, "id"))
+                        .unwrap_or_default();
+                    review
+                        .as_object_mut()
+                        .unwrap()
+                        .insert("author", user(author));
+                    review
+                })
+                .collect();
+            fields.insert("reviews", Value::Array(reviews));
+            product
+        })
+        .collect();
+    graphql::Response::builder()
+        .data(json!({ "topProducts": products }))
+        .build()
+}
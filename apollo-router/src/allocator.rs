@@ -0,0 +1,180 @@
+//! Statistics of the global allocator, exported as metrics.
+//!
+//! With the `global-allocator` feature, the router uses jemalloc on Linux, or mimalloc on all
+//! platforms with the `mimalloc-allocator` feature. The memory they hold and the fragmentation of
+//! their heap are read when the metrics are collected, so that the heap growth of long running
+//! instances is visible.
+
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_api::metrics::ObservableGauge;
+use opentelemetry_api::metrics::Unit;
+use opentelemetry_api::KeyValue;
+
+use crate::metrics::meter_provider;
+
+/// Memory held by the global allocator
+struct AllocatorStats {
+    allocator: &'static str,
+    /// Bytes of physical memory mapped by the allocator
+    resident: u64,
+    /// Bytes of the pages holding allocations
+    active: u64,
+    /// Bytes allocated by the router, when the allocator reports them
+    allocated: Option<u64>,
+}
+
+impl AllocatorStats {
+    /// Active bytes per allocated byte, 1.0 without fragmentation
+    fn fragmentation(&self) -> Option<f64> {
+        let allocated = self.allocated?;
+        (allocated > 0).then(|| self.active as f64 / allocated as f64)
+    }
+
+    #[cfg(all(
+        feature = "global-allocator",
+        not(feature = "mimalloc-allocator"),
+        not(feature = "dhat-heap"),
+        target_os = "linux"
+    ))]
+    fn read() -> Option<Self> {
+        use tikv_jemalloc_ctl::epoch;
+        use tikv_jemalloc_ctl::stats;
+
+        // jemalloc caches its statistics until the epoch advances
+        epoch::advance().ok()?;
+        Some(Self {
+            allocator: "jemalloc",
+            resident: stats::resident::read().ok()? as u64,
+            active: stats::active::read().ok()? as u64,
+            allocated: Some(stats::allocated::read().ok()? as u64),
+        })
+    }
+
+    #[cfg(all(feature = "mimalloc-allocator", not(feature = "dhat-heap")))]
+    fn read() -> Option<Self> {
+        let mut elapsed_msecs = 0;
+        let mut user_msecs = 0;
+        let mut system_msecs = 0;
+        let mut current_rss = 0;
+        let mut peak_rss = 0;
+        let mut current_commit = 0;
+        let mut peak_commit = 0;
+        let mut page_faults = 0;
+        // SAFETY: mimalloc only writes to the pointers, which are valid during the call
+        unsafe {
+            libmimalloc_sys::mi_process_info(
+                &mut elapsed_msecs,
+                &mut user_msecs,
+                &mut system_msecs,
+                &mut current_rss,
+                &mut peak_rss,
+                &mut current_commit,
+                &mut peak_commit,
+                &mut page_faults,
+            );
+        }
+        Some(Self {
+            allocator: "mimalloc",
+            resident: current_rss as u64,
+            active: current_commit as u64,
+            // mimalloc only reports the allocated bytes in its printed statistics
+            allocated: None,
+        })
+    }
+
+    #[cfg(not(any(
+        all(
+            feature = "global-allocator",
+            not(feature = "mimalloc-allocator"),
+            not(feature = "dhat-heap"),
+            target_os = "linux"
+        ),
+        all(feature = "mimalloc-allocator", not(feature = "dhat-heap"))
+    )))]
+    fn read() -> Option<Self> {
+        None
+    }
+}
+
+/// Gauges of the statistics of the global allocator, observed as long as they're alive
+pub(crate) struct AllocatorMetrics {
+    _memory: Option<ObservableGauge<u64>>,
+    _fragmentation: Option<ObservableGauge<f64>>,
+}
+
+impl AllocatorMetrics {
+    pub(crate) fn new() -> Self {
+        // without a known global allocator, there is nothing to observe
+        let Some(stats) = AllocatorStats::read() else {
+            return Self {
+                _memory: None,
+                _fragmentation: None,
+            };
+        };
+
+        let meter = meter_provider().meter("apollo/router");
+        let memory = meter
+            .u64_observable_gauge("apollo.router.allocator.memory")
+            .with_description("Memory held by the global allocator")
+            .with_unit(Unit::new("bytes"))
+            .with_callback(|gauge| {
+                let Some(stats) = AllocatorStats::read() else {
+                    return;
+                };
+                let allocator = KeyValue::new("allocator", stats.allocator);
+                gauge.observe(
+                    stats.resident,
+                    &[allocator.clone(), KeyValue::new("state", "resident")],
+                );
+                gauge.observe(
+                    stats.active,
+                    &[allocator.clone(), KeyValue::new("state", "active")],
+                );
+                if let Some(allocated) = stats.allocated {
+                    gauge.observe(allocated, &[allocator, KeyValue::new("state", "allocated")]);
+                }
+            })
+            .init();
+        let fragmentation = stats.allocated.is_some().then(|| {
+            meter
+                .f64_observable_gauge("apollo.router.allocator.fragmentation")
+                .with_description(
+                    "Active bytes of the global allocator per allocated byte, 1.0 without fragmentation",
+                )
+                .with_callback(|gauge| {
+                    if let Some(stats) = AllocatorStats::read() {
+                        if let Some(fragmentation) = stats.fragmentation() {
+                            gauge.observe(
+                                fragmentation,
+                                &[KeyValue::new("allocator", stats.allocator)],
+                            );
+                        }
+                    }
+                })
+                .init()
+        });
+
+        Self {
+            _memory: Some(memory),
+            _fragmentation: fragmentation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragmentation_is_active_bytes_per_allocated_byte() {
+        let stats = |allocated| AllocatorStats {
+            allocator: "jemalloc",
+            resident: 300,
+            active: 200,
+            allocated,
+        };
+        assert_eq!(stats(Some(100)).fragmentation(), Some(2.0));
+        assert_eq!(stats(Some(0)).fragmentation(), None);
+        assert_eq!(stats(None).fragmentation(), None);
+    }
+}
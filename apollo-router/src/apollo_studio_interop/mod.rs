@@ -32,6 +32,7 @@ use serde::Serialize;
 
 use crate::json_ext::Object;
 use crate::json_ext::Value as JsonValue;
+use crate::plugins::telemetry::config::ApolloSignatureCustomization;
 use crate::plugins::telemetry::config::ApolloSignatureNormalizationAlgorithm;
 use crate::spec::Fragments;
 use crate::spec::Query;
@@ -237,6 +238,7 @@ pub(crate) fn generate_usage_reporting(
     operation_name: &Option<String>,
     schema: &Valid<Schema>,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    signature_customization: &ApolloSignatureCustomization,
 ) -> ComparableUsageReporting {
     let mut generator = UsageGenerator {
         signature_doc,
@@ -244,6 +246,7 @@ pub(crate) fn generate_usage_reporting(
         operation_name,
         schema,
         normalization_algorithm,
+        signature_customization,
         variables: &Object::new(),
         fragments_map: HashMap::new(),
         fields_by_type: HashMap::new(),
@@ -268,6 +271,7 @@ pub(crate) fn generate_extended_references(
         operation_name: &operation_name,
         schema,
         normalization_algorithm: &ApolloSignatureNormalizationAlgorithm::default(),
+        signature_customization: &ApolloSignatureCustomization::default(),
         variables,
         fragments_map: HashMap::new(),
         fields_by_type: HashMap::new(),
@@ -395,6 +399,7 @@ struct UsageGenerator<'a> {
     operation_name: &'a Option<String>,
     schema: &'a Valid<Schema>,
     normalization_algorithm: &'a ApolloSignatureNormalizationAlgorithm,
+    signature_customization: &'a ApolloSignatureCustomization,
     variables: &'a Object,
     fragments_map: HashMap<String, Node<Fragment>>,
     fields_by_type: HashMap<String, HashSet<String>>,
@@ -473,6 +478,7 @@ impl UsageGenerator<'_> {
             let formatter = SignatureFormatterWithAlgorithm {
                 formatter: &ApolloReportingSignatureFormatter::Fragment(f),
                 normalization_algorithm: self.normalization_algorithm,
+                customization: self.signature_customization,
             };
             write!(&mut result, "{formatter}").expect("infallible");
         });
@@ -481,6 +487,7 @@ impl UsageGenerator<'_> {
         let formatter = SignatureFormatterWithAlgorithm {
             formatter: &ApolloReportingSignatureFormatter::Operation(operation),
             normalization_algorithm: self.normalization_algorithm,
+            customization: self.signature_customization,
         };
         write!(&mut result, "{formatter}").expect("infallible");
 
@@ -801,30 +808,48 @@ impl UsageGenerator<'_> {
 enum ApolloReportingSignatureFormatter<'a> {
     Operation(&'a Node<Operation>),
     Fragment(&'a Node<Fragment>),
-    Argument(&'a Node<Argument>),
-    Field(&'a Node<Field>),
+    /// An argument, with the coordinate of its field like `Query.products` or of its directive
+    /// like `@include`
+    Argument(&'a Node<Argument>, &'a str),
+    /// A field, with the type it is selected on
+    Field(&'a Node<Field>, &'a str),
 }
 
 struct SignatureFormatterWithAlgorithm<'a> {
     formatter: &'a ApolloReportingSignatureFormatter<'a>,
     normalization_algorithm: &'a ApolloSignatureNormalizationAlgorithm,
+    customization: &'a ApolloSignatureCustomization,
 }
 
 impl<'a> fmt::Display for SignatureFormatterWithAlgorithm<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self.formatter {
-            ApolloReportingSignatureFormatter::Operation(operation) => {
-                format_operation(operation, self.normalization_algorithm, f)
-            }
-            ApolloReportingSignatureFormatter::Fragment(fragment) => {
-                format_fragment(fragment, self.normalization_algorithm, f)
-            }
-            ApolloReportingSignatureFormatter::Argument(argument) => {
-                format_argument(argument, self.normalization_algorithm, f)
-            }
-            ApolloReportingSignatureFormatter::Field(field) => {
-                format_field(field, self.normalization_algorithm, f)
-            }
+            ApolloReportingSignatureFormatter::Operation(operation) => format_operation(
+                operation,
+                self.normalization_algorithm,
+                self.customization,
+                f,
+            ),
+            ApolloReportingSignatureFormatter::Fragment(fragment) => format_fragment(
+                fragment,
+                self.normalization_algorithm,
+                self.customization,
+                f,
+            ),
+            ApolloReportingSignatureFormatter::Argument(argument, parent) => format_argument(
+                argument,
+                parent,
+                self.normalization_algorithm,
+                self.customization,
+                f,
+            ),
+            ApolloReportingSignatureFormatter::Field(field, parent_type) => format_field(
+                field,
+                parent_type,
+                self.normalization_algorithm,
+                self.customization,
+                f,
+            ),
         }
     }
 }
@@ -836,9 +861,37 @@ fn is_enhanced(normalization_algorithm: &ApolloSignatureNormalizationAlgorithm)
     )
 }
 
+/// The coordinate of an argument, like `Query.products(first:)` or `@include(if:)`
+fn argument_coordinate(parent: &str, argument: &Name) -> String {
+    format!("{parent}({argument}:)")
+}
+
+fn is_stripped(
+    customization: &ApolloSignatureCustomization,
+    parent: &str,
+    argument: &Name,
+) -> bool {
+    !customization.strip_arguments.is_empty()
+        && customization
+            .strip_arguments
+            .contains(&argument_coordinate(parent, argument))
+}
+
+fn keeps_literal(
+    customization: &ApolloSignatureCustomization,
+    parent: &str,
+    argument: &Name,
+) -> bool {
+    !customization.keep_literals.is_empty()
+        && customization
+            .keep_literals
+            .contains(&argument_coordinate(parent, argument))
+}
+
 fn format_operation(
     operation: &Node<Operation>,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     let shorthand = operation.operation_type == OperationType::Query
@@ -861,21 +914,33 @@ fn format_operation(
                 if index != 0 {
                     f.write_str(",")?;
                 }
-                format_variable(variable, normalization_algorithm, f)?;
+                format_variable(variable, normalization_algorithm, customization, f)?;
             }
             f.write_str(")")?;
         }
 
         // In the JS implementation, only the fragment directives are sorted (this is overridden in enhanced mode)
-        format_directives(&operation.directives, false, normalization_algorithm, f)?;
+        format_directives(
+            &operation.directives,
+            false,
+            normalization_algorithm,
+            customization,
+            f,
+        )?;
     }
 
-    format_selection_set(&operation.selection_set, normalization_algorithm, f)
+    format_selection_set(
+        &operation.selection_set,
+        normalization_algorithm,
+        customization,
+        f,
+    )
 }
 
 fn format_selection_set(
     selection_set: &SelectionSet,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     // print selection set sorted by name with fields followed by named fragments followed by inline fragments
@@ -929,8 +994,9 @@ fn format_selection_set(
 
         for (i, &field) in fields.iter().enumerate() {
             let formatter = SignatureFormatterWithAlgorithm {
-                formatter: &ApolloReportingSignatureFormatter::Field(field),
+                formatter: &ApolloReportingSignatureFormatter::Field(field, &selection_set.ty),
                 normalization_algorithm,
+                customization,
             };
             let field_str = format!("{}", formatter);
             f.write_str(&field_str)?;
@@ -946,11 +1012,11 @@ fn format_selection_set(
         }
 
         for &frag in named_fragments.iter() {
-            format_fragment_spread(frag, normalization_algorithm, f)?;
+            format_fragment_spread(frag, normalization_algorithm, customization, f)?;
         }
 
         for &frag in inline_fragments.iter() {
-            format_inline_fragment(frag, normalization_algorithm, f)?;
+            format_inline_fragment(frag, normalization_algorithm, customization, f)?;
         }
 
         f.write_str("}")?;
@@ -962,6 +1028,7 @@ fn format_selection_set(
 fn format_variable(
     arg: &Node<VariableDefinition>,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     write!(f, "${}:{}", arg.name, arg.ty)?;
@@ -971,21 +1038,35 @@ fn format_variable(
     }
 
     // The JS implementation doesn't sort directives (this is overridden in enhanced mode)
-    format_directives(&arg.directives, false, normalization_algorithm, f)
+    format_directives(
+        &arg.directives,
+        false,
+        normalization_algorithm,
+        customization,
+        f,
+    )
 }
 
 fn format_argument(
     arg: &Node<Argument>,
+    parent: &str,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     write!(f, "{}:", arg.name)?;
-    format_value(&arg.value, normalization_algorithm, f)
+    if keeps_literal(customization, parent, &arg.name) {
+        write!(f, "{}", arg.value)
+    } else {
+        format_value(&arg.value, normalization_algorithm, f)
+    }
 }
 
 fn format_field(
     field: &Node<Field>,
+    parent_type: &str,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     if is_enhanced(normalization_algorithm) {
@@ -996,7 +1077,12 @@ fn format_field(
 
     f.write_str(&field.name)?;
 
-    let mut sorted_args = field.arguments.clone();
+    let field_coordinate = format!("{parent_type}.{}", field.name);
+    let mut sorted_args: Vec<_> = field
+        .arguments
+        .iter()
+        .filter(|arg| !is_stripped(customization, &field_coordinate, &arg.name))
+        .collect();
     if !sorted_args.is_empty() {
         sorted_args.sort_by(|a, b| a.name.cmp(&b.name));
 
@@ -1006,8 +1092,9 @@ fn format_field(
             .iter()
             .map(|a| {
                 let formatter = SignatureFormatterWithAlgorithm {
-                    formatter: &ApolloReportingSignatureFormatter::Argument(a),
+                    formatter: &ApolloReportingSignatureFormatter::Argument(a, &field_coordinate),
                     normalization_algorithm,
+                    customization,
                 };
                 format!("{}", formatter)
             })
@@ -1034,13 +1121,25 @@ fn format_field(
     }
 
     // In the JS implementation, only the fragment directives are sorted (this is overridden in enhanced mode)
-    format_directives(&field.directives, false, normalization_algorithm, f)?;
-    format_selection_set(&field.selection_set, normalization_algorithm, f)
+    format_directives(
+        &field.directives,
+        false,
+        normalization_algorithm,
+        customization,
+        f,
+    )?;
+    format_selection_set(
+        &field.selection_set,
+        normalization_algorithm,
+        customization,
+        f,
+    )
 }
 
 fn format_inline_fragment(
     inline_fragment: &Node<InlineFragment>,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     if let Some(type_name) = &inline_fragment.type_condition {
@@ -1053,14 +1152,21 @@ fn format_inline_fragment(
         &inline_fragment.directives,
         true,
         normalization_algorithm,
+        customization,
         f,
     )?;
-    format_selection_set(&inline_fragment.selection_set, normalization_algorithm, f)
+    format_selection_set(
+        &inline_fragment.selection_set,
+        normalization_algorithm,
+        customization,
+        f,
+    )
 }
 
 fn format_fragment(
     fragment: &Node<Fragment>,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     write!(
@@ -1069,14 +1175,26 @@ fn format_fragment(
         &fragment.name.to_string(),
         &fragment.selection_set.ty.to_string()
     )?;
-    format_directives(&fragment.directives, true, normalization_algorithm, f)?;
-    format_selection_set(&fragment.selection_set, normalization_algorithm, f)
+    format_directives(
+        &fragment.directives,
+        true,
+        normalization_algorithm,
+        customization,
+        f,
+    )?;
+    format_selection_set(
+        &fragment.selection_set,
+        normalization_algorithm,
+        customization,
+        f,
+    )
 }
 
 fn format_directives(
     directives: &DirectiveList,
     sorted: bool,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     let mut sorted_directives = directives.clone();
@@ -1089,7 +1207,12 @@ fn format_directives(
     for directive in sorted_directives.iter() {
         write!(f, "@{}", directive.name)?;
 
-        let mut sorted_args = directive.arguments.clone();
+        let directive_coordinate = format!("@{}", directive.name);
+        let mut sorted_args: Vec<_> = directive
+            .arguments
+            .iter()
+            .filter(|arg| !is_stripped(customization, &directive_coordinate, &arg.name))
+            .collect();
         if !sorted_args.is_empty() {
             sorted_args.sort_by(|a, b| a.name.cmp(&b.name));
 
@@ -1100,8 +1223,12 @@ fn format_directives(
                     f.write_str(",")?;
                 }
                 let formatter = SignatureFormatterWithAlgorithm {
-                    formatter: &ApolloReportingSignatureFormatter::Argument(argument),
+                    formatter: &ApolloReportingSignatureFormatter::Argument(
+                        argument,
+                        &directive_coordinate,
+                    ),
                     normalization_algorithm,
+                    customization,
                 };
                 write!(f, "{}", formatter)?;
             }
@@ -1179,6 +1306,7 @@ fn get_arg_separator(
 fn format_fragment_spread(
     fragment_spread: &Node<FragmentSpread>,
     normalization_algorithm: &ApolloSignatureNormalizationAlgorithm,
+    customization: &ApolloSignatureCustomization,
     f: &mut fmt::Formatter,
 ) -> fmt::Result {
     write!(f, "...{}", fragment_spread.fragment_name)?;
@@ -1186,6 +1314,7 @@ fn format_fragment_spread(
         &fragment_spread.directives,
         true,
         normalization_algorithm,
+        customization,
         f,
     )
 }
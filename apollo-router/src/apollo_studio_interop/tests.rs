@@ -85,6 +85,7 @@ fn generate_legacy(
         operation_name,
         schema,
         &ApolloSignatureNormalizationAlgorithm::Legacy,
+        &ApolloSignatureCustomization::default(),
     )
 }
 
@@ -100,6 +101,7 @@ fn generate_enhanced(
         operation_name,
         schema,
         &ApolloSignatureNormalizationAlgorithm::Enhanced,
+        &ApolloSignatureCustomization::default(),
     )
 }
 
@@ -613,6 +615,55 @@ async fn test_enhanced_alias_preservation() {
     assert_expected_signature(&generated, expected_sig);
 }
 
+#[test(tokio::test)]
+async fn test_signature_customization() {
+    let schema_str = r#"
+        type Query {
+            products(category: String, first: Int, trackingId: String): [Product]
+        }
+        type Product {
+            id: ID!
+            name(locale: String): String
+        }
+        directive @feature(name: String!) on INLINE_FRAGMENT
+    "#;
+    let query_str = r#"
+        query Products {
+            products(category: "shoes", first: 10, trackingId: "abc") {
+                id
+                ... @feature(name: "new-names") {
+                    name(locale: "fr")
+                }
+            }
+        }
+    "#;
+
+    let schema = Schema::parse_and_validate(schema_str, "schema.graphql").unwrap();
+    let doc = ExecutableDocument::parse(&schema, query_str, "query.graphql").unwrap();
+
+    let customization = ApolloSignatureCustomization {
+        strip_arguments: vec!["Query.products(trackingId:)".to_string()],
+        keep_literals: vec![
+            "Query.products(category:)".to_string(),
+            "@feature(name:)".to_string(),
+        ],
+    };
+    let generated = generate_usage_reporting(
+        &doc,
+        &doc,
+        &Some("Products".into()),
+        &schema,
+        &ApolloSignatureNormalizationAlgorithm::Legacy,
+        &customization,
+    );
+    let expected_sig = "# Products\nquery Products{products(category:\"shoes\",first:0){id...@feature(name:\"new-names\"){name(locale:\"\")}}}";
+    assert_expected_signature(&generated, expected_sig);
+
+    let generated = generate_legacy(&doc, &Some("Products".into()), &schema);
+    let expected_sig = "# Products\nquery Products{products(category:\"\",first:0,trackingId:\"\"){id...@feature(name:\"\"){name(locale:\"\")}}}";
+    assert_expected_signature(&generated, expected_sig);
+}
+
 #[test(tokio::test)]
 async fn test_extended_references_inline_enums() {
     let schema_str = include_str!("testdata/schema_interop.graphql");
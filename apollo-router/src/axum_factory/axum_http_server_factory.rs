@@ -1,6 +1,8 @@
 //! Axum http server factory. Axum provides routing capability on top of Hyper HTTP.
+use std::collections::BTreeSet;
 use std::fmt::Display;
 use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
@@ -14,6 +16,7 @@ use axum::http::StatusCode;
 use axum::middleware;
 use axum::middleware::Next;
 use axum::response::*;
+use axum::routing::any;
 use axum::routing::get;
 use axum::Router;
 use futures::channel::oneshot;
@@ -27,7 +30,6 @@ use http_body::combinators::UnsyncBoxBody;
 use hyper::Body;
 use itertools::Itertools;
 use multimap::MultiMap;
-use serde::Serialize;
 use serde_json::json;
 #[cfg(unix)]
 use tokio::net::UnixListener;
@@ -42,19 +44,27 @@ use tower_http::trace::TraceLayer;
 use tracing::instrument::WithSubscriber;
 use tracing::Instrument;
 
+use super::health::probe_endpoints;
+use super::health::Health;
+use super::health::HealthStatus;
 use super::listeners::ensure_endpoints_consistency;
 use super::listeners::ensure_listenaddrs_consistency;
 use super::listeners::extra_endpoints;
 use super::listeners::ListenersAndRouters;
+use super::utils::ConnectionInfo;
 use super::utils::PropagatingMakeSpan;
+use super::websocket;
 use super::ListenAddrAndRouter;
 use super::ENDPOINT_CALLBACK;
 use crate::axum_factory::compression::Compressor;
 use crate::axum_factory::listeners::get_extra_listeners;
 use crate::axum_factory::listeners::serve_router_on_listen_addr;
+use crate::configuration::diff::diff;
+use crate::configuration::tls::TlsClientVerification;
 use crate::configuration::Configuration;
 use crate::configuration::ListenAddr;
 use crate::graphql;
+use crate::http_ext;
 use crate::http_server_factory::HttpServerFactory;
 use crate::http_server_factory::HttpServerHandle;
 use crate::http_server_factory::Listener;
@@ -64,6 +74,7 @@ use crate::router_factory::Endpoint;
 use crate::router_factory::RouterFactory;
 use crate::services::http::service::BodyStream;
 use crate::services::router;
+use crate::services::router::body::get_body_bytes;
 use crate::uplink::license_enforcement::LicenseState;
 use crate::uplink::license_enforcement::APOLLO_ROUTER_LICENSE_EXPIRED;
 use crate::uplink::license_enforcement::LICENSE_EXPIRED_SHORT_MESSAGE;
@@ -104,19 +115,6 @@ impl AxumHttpServerFactory {
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "UPPERCASE")]
-#[allow(dead_code)]
-enum HealthStatus {
-    Up,
-    Down,
-}
-
-#[derive(Debug, Serialize)]
-struct Health {
-    status: HealthStatus,
-}
-
 pub(crate) fn make_axum_router<RF>(
     live: Arc<AtomicBool>,
     ready: Arc<AtomicBool>,
@@ -131,6 +129,9 @@ where
     ensure_listenaddrs_consistency(configuration, &endpoints)?;
 
     if configuration.health_check.enabled {
+        for endpoint in probe_endpoints(configuration, live.clone(), ready.clone()) {
+            endpoints.insert(configuration.health_check.listen.clone(), endpoint);
+        }
         tracing::info!(
             "Health check exposed at {}{}",
             configuration.health_check.listen,
@@ -155,7 +156,7 @@ where
                                 status_code = StatusCode::SERVICE_UNAVAILABLE;
                                 HealthStatus::Down
                             };
-                            Health { status }
+                            Health::new(status)
                         } else if query_upper.starts_with("LIVE") {
                             let status = if live.load(Ordering::SeqCst) {
                                 HealthStatus::Up
@@ -166,16 +167,12 @@ where
                                 status_code = StatusCode::SERVICE_UNAVAILABLE;
                                 HealthStatus::Down
                             };
-                            Health { status }
+                            Health::new(status)
                         } else {
-                            Health {
-                                status: HealthStatus::Up,
-                            }
+                            Health::new(HealthStatus::Up)
                         }
                     } else {
-                        Health {
-                            status: HealthStatus::Up,
-                        }
+                        Health::new(HealthStatus::Up)
                     };
                     tracing::trace!(?health, request = ?req.router_request, "health check");
                     async move {
@@ -192,6 +189,80 @@ where
         );
     }
 
+    if configuration.experimental_config_diff.enabled {
+        tracing::info!(
+            "Configuration diff exposed at {}{}",
+            configuration.experimental_config_diff.listen,
+            configuration.experimental_config_diff.path
+        );
+        let running_configuration = Arc::new(configuration.clone());
+        endpoints.insert(
+            configuration.experimental_config_diff.listen.clone(),
+            Endpoint::from_router_service(
+                configuration.experimental_config_diff.path.clone(),
+                service_fn(move |req: router::Request| {
+                    let running_configuration = running_configuration.clone();
+                    async move {
+                        let (parts, body) = req.router_request.into_parts();
+                        if !http_ext::has_shared_key(
+                            &parts.headers,
+                            &running_configuration.experimental_config_diff.shared_key,
+                        ) {
+                            return Ok(router::Response {
+                                response: http::Response::builder()
+                                    .status(StatusCode::UNAUTHORIZED)
+                                    .body::<Body>("Invalid authorization header".into())?,
+                                context: req.context,
+                            });
+                        }
+                        let body = match get_body_bytes(http_body::Limited::new(
+                            body,
+                            running_configuration.limits.http_max_request_bytes,
+                        ))
+                        .await
+                        {
+                            Ok(body) => body,
+                            Err(e) if e.is::<http_body::LengthLimitError>() => {
+                                return Ok(router::Response {
+                                    response: http::Response::builder()
+                                        .status(StatusCode::PAYLOAD_TOO_LARGE)
+                                        .body::<Body>(
+                                            "payload too large for the `http_max_request_bytes` configuration"
+                                                .into(),
+                                        )?,
+                                    context: req.context,
+                                });
+                            }
+                            Err(e) => return Err(e),
+                        };
+                        let (status_code, body) = match std::str::from_utf8(&body)
+                            .map_err(|e| e.to_string())
+                            .and_then(|yaml| {
+                                Configuration::from_str(yaml).map_err(|e| e.to_string())
+                            })
+                            .and_then(|new_configuration| {
+                                diff(&running_configuration, &new_configuration)
+                                    .map_err(|e| e.to_string())
+                            }) {
+                            Ok(changes) => (StatusCode::OK, json!({ "changes": changes })),
+                            Err(error) => (StatusCode::BAD_REQUEST, json!({ "error": error })),
+                        };
+                        Ok(router::Response {
+                            response: http::Response::builder()
+                                .status(status_code)
+                                .header(http::header::CONTENT_TYPE, "application/json")
+                                .body::<Body>(
+                                    serde_json::to_vec(&body).map_err(BoxError::from)?.into(),
+                                )?,
+                            context: req.context,
+                        })
+                    }
+                })
+                .boxed(),
+            ),
+        );
+    }
+
     ensure_endpoints_consistency(configuration, &endpoints)?;
 
     let mut main_endpoint = main_endpoint(
@@ -211,6 +282,22 @@ where
             .fold(main_endpoint.1, |acc, r| acc.merge(r));
     }
 
+    // certificates required for every connection are enforced during the TLS handshake
+    if let Some(client_authentication) = configuration
+        .tls
+        .supergraph
+        .as_ref()
+        .and_then(|tls| tls.client_authentication.clone())
+        .filter(|client_authentication| {
+            !client_authentication.required && !client_authentication.required_paths.is_empty()
+        })
+    {
+        main_endpoint.1 = main_endpoint.1.layer(middleware::from_fn_with_state(
+            Arc::new(client_authentication),
+            client_certificate_handler,
+        ));
+    }
+
     Ok(ListenersAndRouters {
         main: main_endpoint,
         extra: extra_endpoints,
@@ -498,6 +585,22 @@ async fn metrics_handler<B>(request: Request<B>, next: Next<B>) -> Response {
     resp
 }
 
+async fn client_certificate_handler<B>(
+    State(client_authentication): State<Arc<TlsClientVerification>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let has_certificate = request
+        .extensions()
+        .get::<ConnectionInfo>()
+        .is_some_and(|connection_info| connection_info.client_certificate.is_some());
+    if !has_certificate && client_authentication.is_required_for(request.uri().path()) {
+        (StatusCode::UNAUTHORIZED, "a client certificate is required").into_response()
+    } else {
+        next.run(request).await
+    }
+}
+
 async fn license_handler<B>(
     State((license, start, delta)): State<(LicenseState, Instant, Arc<AtomicU64>)>,
     request: Request<B>,
@@ -555,14 +658,27 @@ where
 {
     let early_cancel = configuration.supergraph.early_cancel;
     let experimental_log_on_broken_pipe = configuration.supergraph.experimental_log_on_broken_pipe;
+    let context_max_bytes = configuration.limits.context_max_bytes;
+    let websocket = configuration.supergraph.experimental_websocket.then(|| {
+        Arc::new(websocket::Config {
+            allowed_origins: configuration
+                .supergraph
+                .experimental_websocket_allowed_origins
+                .clone(),
+            max_operations: configuration.supergraph.experimental_websocket_max_operations,
+        })
+    });
     let mut router = Router::new().route(
         &configuration.supergraph.sanitized_path(),
         get({
+            let websocket = websocket.clone();
             move |Extension(service): Extension<RF>, request: Request<DecompressionBody<Body>>| {
-                handle_graphql(
-                    service.create().boxed(),
+                handle_graphql_get(
+                    service,
+                    websocket.clone(),
                     early_cancel,
                     experimental_log_on_broken_pipe,
+                    context_max_bytes,
                     request,
                 )
             }
@@ -573,6 +689,7 @@ where
                     service.create().boxed(),
                     early_cancel,
                     experimental_log_on_broken_pipe,
+                    context_max_bytes,
                     request,
                 )
             }
@@ -585,10 +702,12 @@ where
             get({
                 move |Extension(service): Extension<RF>,
                       request: Request<DecompressionBody<Body>>| {
-                    handle_graphql(
-                        service.create().boxed(),
+                    handle_graphql_get(
+                        service,
+                        websocket.clone(),
                         early_cancel,
                         experimental_log_on_broken_pipe,
+                        context_max_bytes,
                         request,
                     )
                 }
@@ -600,6 +719,7 @@ where
                         service.create().boxed(),
                         early_cancel,
                         experimental_log_on_broken_pipe,
+                        context_max_bytes,
                         request,
                     )
                 }
@@ -607,22 +727,112 @@ where
         );
     }
 
+    // the requests of the REST endpoints are translated to GraphQL requests by the router
+    // service. A wildcard supergraph path already routes them to it
+    if configuration.supergraph.path != "/*" {
+        let rest_endpoints = &configuration.persisted_queries.experimental_rest_endpoints;
+        let paths: BTreeSet<String> = rest_endpoints
+            .routes
+            .iter()
+            .map(|route| route.server_path())
+            .chain(rest_endpoints.openapi_path.clone())
+            .collect();
+        for path in paths {
+            router = router.route(
+                &path,
+                any({
+                    move |Extension(service): Extension<RF>,
+                          request: Request<DecompressionBody<Body>>| {
+                        handle_graphql(
+                            service.create().boxed(),
+                            early_cancel,
+                            experimental_log_on_broken_pipe,
+                            context_max_bytes,
+                            request,
+                        )
+                    }
+                }),
+            );
+        }
+    }
+
     router
 }
 
+async fn handle_graphql_get<RF>(
+    service: RF,
+    websocket: Option<Arc<websocket::Config>>,
+    early_cancel: bool,
+    experimental_log_on_broken_pipe: bool,
+    context_max_bytes: Option<usize>,
+    http_request: Request<DecompressionBody<Body>>,
+) -> Response
+where
+    RF: RouterFactory,
+{
+    if let Some(config) = websocket.filter(|_| websocket::is_upgrade_request(&http_request)) {
+        return websocket::upgrade(
+            service,
+            &config,
+            early_cancel,
+            experimental_log_on_broken_pipe,
+            context_max_bytes,
+            http_request,
+        )
+        .await;
+    }
+
+    handle_graphql(
+        service.create().boxed(),
+        early_cancel,
+        experimental_log_on_broken_pipe,
+        context_max_bytes,
+        http_request,
+    )
+    .await
+}
+
 async fn handle_graphql(
     service: router::BoxService,
     early_cancel: bool,
     experimental_log_on_broken_pipe: bool,
+    context_max_bytes: Option<usize>,
     http_request: Request<DecompressionBody<Body>>,
-) -> impl IntoResponse {
-    let _guard = SessionCountGuard::start();
-
+) -> Response {
     let (parts, body) = http_request.into_parts();
 
     let http_request = http::Request::from_parts(parts, Body::wrap_stream(BodyStream::new(body)));
 
+    execute_graphql(
+        service,
+        early_cancel,
+        experimental_log_on_broken_pipe,
+        context_max_bytes,
+        http_request,
+    )
+    .await
+}
+
+/// Executes a GraphQL request through the router service.
+pub(super) async fn execute_graphql(
+    service: router::BoxService,
+    early_cancel: bool,
+    experimental_log_on_broken_pipe: bool,
+    context_max_bytes: Option<usize>,
+    http_request: http::Request<Body>,
+) -> Response {
+    let _guard = SessionCountGuard::start();
+
     let request: router::Request = http_request.into();
+    request.context.set_max_size(context_max_bytes);
+    if let Some(client_certificate) = request
+        .router_request
+        .extensions()
+        .get::<ConnectionInfo>()
+        .and_then(|connection_info| connection_info.client_certificate.as_deref())
+    {
+        client_certificate.insert_into(&request.context);
+    }
     let context = request.context.clone();
     let accept_encoding = request
         .router_request
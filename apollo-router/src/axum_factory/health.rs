@@ -0,0 +1,209 @@
+//! Startup, liveness and readiness probes of the health check.
+//!
+//! Each probe is up when the router is in the matching state and the dependencies configured for
+//! the probe are available, so that Kubernetes probes can be tuned without custom sidecars.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::StatusCode;
+use hyper::Body;
+use serde::Serialize;
+use tower::service_fn;
+use tower::BoxError;
+use tower::ServiceExt;
+use url::Url;
+
+use crate::configuration::HealthDependency;
+use crate::configuration::HealthProbe;
+use crate::router_factory::Endpoint;
+use crate::services::router;
+use crate::Configuration;
+
+const COPROCESSOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub(super) enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct Health {
+    pub(super) status: HealthStatus,
+    /// Dependencies that are not available
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(super) unavailable: Vec<&'static str>,
+}
+
+impl Health {
+    pub(super) fn new(status: HealthStatus) -> Self {
+        Self {
+            status,
+            unavailable: Vec::new(),
+        }
+    }
+}
+
+/// The state of the router matching a probe, and the dependencies checked by the probe
+struct Probe {
+    state: Arc<AtomicBool>,
+    ready: Arc<AtomicBool>,
+    checks: Vec<HealthDependency>,
+    coprocessor: Option<Url>,
+}
+
+impl Probe {
+    async fn health(&self) -> Health {
+        let mut unavailable = Vec::new();
+        if !self.state.load(Ordering::SeqCst) {
+            unavailable.push("router");
+        }
+        for check in &self.checks {
+            let (name, available) = match check {
+                // the schema is loaded when the router accepts requests
+                HealthDependency::Schema => ("schema", self.ready.load(Ordering::SeqCst)),
+                HealthDependency::Uplink { max_age } => (
+                    "uplink",
+                    crate::uplink::since_last_successful_fetch()
+                        .map(|elapsed| elapsed <= *max_age)
+                        .unwrap_or(false),
+                ),
+                HealthDependency::Redis => ("redis", crate::cache::redis::all_connected()),
+                HealthDependency::Coprocessor => (
+                    "coprocessor",
+                    coprocessor_reachable(&self.coprocessor).await,
+                ),
+            };
+            if !available {
+                unavailable.push(name);
+            }
+        }
+        Health {
+            status: if unavailable.is_empty() {
+                HealthStatus::Up
+            } else {
+                HealthStatus::Down
+            },
+            unavailable,
+        }
+    }
+}
+
+async fn coprocessor_reachable(url: &Option<Url>) -> bool {
+    let Some((host, port)) = url
+        .as_ref()
+        .and_then(|url| Some((url.host_str()?.to_string(), url.port_or_known_default()?)))
+    else {
+        return false;
+    };
+    matches!(
+        tokio::time::timeout(
+            COPROCESSOR_CONNECT_TIMEOUT,
+            tokio::net::TcpStream::connect((host.as_str(), port)),
+        )
+        .await,
+        Ok(Ok(_))
+    )
+}
+
+/// The endpoints of the configured probes
+pub(super) fn probe_endpoints(
+    configuration: &Configuration,
+    live: Arc<AtomicBool>,
+    ready: Arc<AtomicBool>,
+) -> Vec<Endpoint> {
+    let coprocessor = configuration
+        .apollo_plugins
+        .plugins
+        .get("coprocessor")
+        .and_then(|coprocessor| coprocessor.get("url"))
+        .and_then(|url| url.as_str())
+        .and_then(|url| Url::parse(url).ok());
+
+    let health_check = &configuration.health_check;
+    [
+        (&health_check.startup, ready.clone()),
+        (&health_check.liveness, live),
+        (&health_check.readiness, ready.clone()),
+    ]
+    .into_iter()
+    .filter_map(|(config, state): (&Option<HealthProbe>, _)| {
+        let config = config.as_ref()?;
+        tracing::info!(
+            "Health probe exposed at {}{}",
+            health_check.listen,
+            config.path
+        );
+        let probe = Arc::new(Probe {
+            state,
+            ready: ready.clone(),
+            checks: config.checks.clone(),
+            coprocessor: coprocessor.clone(),
+        });
+        Some(Endpoint::from_router_service(
+            config.path.clone(),
+            service_fn(move |req: router::Request| {
+                let probe = probe.clone();
+                async move {
+                    let health = probe.health().await;
+                    tracing::trace!(?health, request = ?req.router_request, "health probe");
+                    let status_code = match health.status {
+                        HealthStatus::Up => StatusCode::OK,
+                        // k8s interprets this as a probe failure
+                        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+                    };
+                    Ok::<_, BoxError>(router::Response {
+                        response: http::Response::builder().status(status_code).body::<Body>(
+                            serde_json::to_vec(&health).map_err(BoxError::from)?.into(),
+                        )?,
+                        context: req.context,
+                    })
+                }
+            })
+            .boxed(),
+        ))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(state: bool, ready: bool, checks: Vec<HealthDependency>) -> Probe {
+        Probe {
+            state: Arc::new(AtomicBool::new(state)),
+            ready: Arc::new(AtomicBool::new(ready)),
+            checks,
+            coprocessor: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_unavailable_dependencies() {
+        let health = probe(true, true, vec![HealthDependency::Schema])
+            .health()
+            .await;
+        assert!(matches!(health.status, HealthStatus::Up));
+
+        let health = probe(
+            true,
+            false,
+            vec![HealthDependency::Schema, HealthDependency::Coprocessor],
+        )
+        .health()
+        .await;
+        assert!(matches!(health.status, HealthStatus::Down));
+        assert_eq!(health.unavailable, vec!["schema", "coprocessor"]);
+
+        let health = probe(false, true, vec![]).health().await;
+        assert_eq!(
+            serde_json::to_value(&health).unwrap(),
+            serde_json::json!({ "status": "DOWN", "unavailable": ["router"] })
+        );
+    }
+}
@@ -1,10 +1,12 @@
 //! axum factory is useful to create an [`AxumHttpServerFactory`] which implements [`crate::http_server_factory::HttpServerFactory`]
 mod axum_http_server_factory;
 pub(crate) mod compression;
+mod health;
 mod listeners;
 #[cfg(test)]
 pub(crate) mod tests;
 pub(crate) mod utils;
+mod websocket;
 
 use std::sync::Arc;
 use std::sync::OnceLock;
@@ -16,6 +16,7 @@ use futures::future::BoxFuture;
 use futures::stream;
 use futures::stream::poll_fn;
 use futures::Future;
+use futures::SinkExt;
 use futures::StreamExt;
 use http::header::ACCEPT_ENCODING;
 use http::header::CONTENT_ENCODING;
@@ -391,7 +392,57 @@ async fn it_displays_sandbox() {
         "{}",
         response.text().await.unwrap()
     );
-    assert_eq!(response.bytes().await.unwrap(), sandbox_page_content());
+    assert_eq!(
+        response.bytes().await.unwrap(),
+        sandbox_page_content(&Sandbox::fake_builder().enabled(true).build())
+    );
+}
+
+#[tokio::test]
+async fn it_displays_sandbox_with_a_content_security_policy_and_branding() {
+    let sandbox = Sandbox::fake_builder()
+        .enabled(true)
+        .content_security_policy("default-src 'self'")
+        .custom_html("<p>Internal graph</p>")
+        .build();
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .sandbox(sandbox.clone())
+            .homepage(Homepage::fake_builder().enabled(false).build())
+            .supergraph(Supergraph::fake_builder().introspection(true).build())
+            .build()
+            .unwrap(),
+    );
+
+    let router_service = router::service::from_supergraph_mock_callback_and_configuration(
+        move |_| {
+            panic!("this should never be called");
+        },
+        conf.clone(),
+    )
+    .await;
+
+    let (server, client) = init_with_config(router_service, conf, MultiMap::new())
+        .await
+        .unwrap();
+
+    let response = client
+        .get(format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(ACCEPT, "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("content-security-policy").unwrap(),
+        "default-src 'self'"
+    );
+    let page = response.bytes().await.unwrap();
+    assert_eq!(page, sandbox_page_content(&sandbox));
+    assert!(String::from_utf8_lossy(&page).contains("<p>Internal graph</p>"));
 }
 
 #[tokio::test]
@@ -437,7 +488,10 @@ async fn it_displays_sandbox_with_different_supergraph_path() {
         "{}",
         response.text().await.unwrap()
     );
-    assert_eq!(response.bytes().await.unwrap(), sandbox_page_content());
+    assert_eq!(
+        response.bytes().await.unwrap(),
+        sandbox_page_content(&Sandbox::fake_builder().enabled(true).build())
+    );
 }
 
 #[tokio::test]
@@ -1753,6 +1807,292 @@ async fn multipart_response_shape_with_one_chunk() -> Result<(), ApolloRouterErr
     server.shutdown().await
 }
 
+#[test(tokio::test)]
+async fn websocket_operations() -> Result<(), ApolloRouterError> {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        let responses = if req.supergraph_request.body().query.as_deref()
+            == Some("query { me { id ... @defer { name } } }")
+        {
+            vec![
+                graphql::Response::builder()
+                    .data(json!({
+                        "me": "id",
+                    }))
+                    .has_next(true)
+                    .build(),
+                graphql::Response::builder()
+                    .incremental(vec![graphql::IncrementalResponse::builder()
+                        .data(json!({
+                            "name": "Ada"
+                        }))
+                        .path(Path::from("me"))
+                        .build()])
+                    .has_next(false)
+                    .build(),
+            ]
+        } else {
+            vec![graphql::Response::builder()
+                .data(json!({
+                    "me": { "name": "Ada" },
+                }))
+                .build()]
+        };
+        Ok(SupergraphResponse::new_from_response(
+            http::Response::builder()
+                .status(200)
+                .body(stream::iter(responses).boxed())
+                .unwrap(),
+            req.context,
+        ))
+    })
+    .await;
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .supergraph(
+                Supergraph::fake_builder()
+                    .experimental_websocket(true)
+                    .build(),
+            )
+            .build()
+            .unwrap(),
+    );
+    let (server, _client) = init_with_config(router_service, conf, MultiMap::new()).await?;
+    let url =
+        format!("{}/", server.graphql_listen_address().as_ref().unwrap()).replacen("http", "ws", 1);
+
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        HeaderValue::from_static("graphql-transport-ws"),
+    );
+    let (mut socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+    assert_eq!(
+        response.headers().get("sec-websocket-protocol"),
+        Some(&HeaderValue::from_static("graphql-transport-ws"))
+    );
+    for message in [
+        json!({ "type": "connection_init" }),
+        json!({
+            "type": "subscribe",
+            "id": "1",
+            "payload": { "query": "query { me { name } }" }
+        }),
+        json!({
+            "type": "subscribe",
+            "id": "2",
+            "payload": { "query": "query { me { id ... @defer { name } } }" }
+        }),
+    ] {
+        socket
+            .send(Message::Text(message.to_string()))
+            .await
+            .unwrap();
+    }
+
+    let mut messages = Vec::new();
+    while messages.len() < 7 {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => {
+                messages.push(serde_json::from_str::<serde_json::Value>(&text).unwrap())
+            }
+            message => panic!("unexpected message {message:?}"),
+        }
+    }
+    assert_eq!(messages[0], json!({ "type": "connection_ack" }));
+    let operation = |id: &str| {
+        messages
+            .iter()
+            .filter(|message| message["id"] == id)
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        operation("1"),
+        vec![
+            json!({ "type": "next", "id": "1", "payload": { "data": { "me": { "name": "Ada" } } } }),
+            json!({ "type": "complete", "id": "1" }),
+        ]
+    );
+    assert_eq!(
+        operation("2"),
+        vec![
+            json!({ "type": "next", "id": "2", "payload": { "data": { "me": "id" }, "hasNext": true } }),
+            json!({
+                "type": "next",
+                "id": "2",
+                "payload": {
+                    "hasNext": false,
+                    "incremental": [{ "data": { "name": "Ada" }, "path": ["me"] }]
+                }
+            }),
+            json!({ "type": "complete", "id": "2" }),
+        ]
+    );
+
+    server.shutdown().await
+}
+
+#[test(tokio::test)]
+async fn websocket_connection_params_and_operations_limit() -> Result<(), ApolloRouterError> {
+    use futures::Stream;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::Error;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        let connection_params = req
+            .context
+            .get::<_, serde_json::Value>("apollo::websocket::connection_params")
+            .unwrap();
+        // the operation keeps running, like a subscription
+        let responses = stream::iter(vec![graphql::Response::builder()
+            .data(json!({ "connectionParams": connection_params }))
+            .has_next(true)
+            .build()])
+        .chain(stream::pending());
+        Ok(SupergraphResponse::new_from_response(
+            http::Response::builder()
+                .status(200)
+                .body(responses.boxed())
+                .unwrap(),
+            req.context,
+        ))
+    })
+    .await;
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .supergraph(
+                Supergraph::fake_builder()
+                    .experimental_websocket(true)
+                    .experimental_websocket_max_operations(1)
+                    .build(),
+            )
+            .build()
+            .unwrap(),
+    );
+    let (server, _client) = init_with_config(router_service, conf, MultiMap::new()).await?;
+    let url =
+        format!("{}/", server.graphql_listen_address().as_ref().unwrap()).replacen("http", "ws", 1);
+
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "sec-websocket-protocol",
+        HeaderValue::from_static("graphql-transport-ws"),
+    );
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+    let subscribe = |id: &str| {
+        Message::Text(
+            json!({
+                "type": "subscribe",
+                "id": id,
+                "payload": { "query": "query { me { name } }" }
+            })
+            .to_string(),
+        )
+    };
+    async fn next_message(
+        socket: &mut (impl Stream<Item = Result<Message, Error>> + Unpin),
+    ) -> serde_json::Value {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("unexpected message {message:?}"),
+        }
+    }
+    socket
+        .send(Message::Text(
+            json!({ "type": "connection_init", "payload": { "token": "secret" } }).to_string(),
+        ))
+        .await
+        .unwrap();
+    socket.send(subscribe("1")).await.unwrap();
+
+    assert_eq!(
+        next_message(&mut socket).await,
+        json!({ "type": "connection_ack" })
+    );
+    let message = next_message(&mut socket).await;
+    assert_eq!(message["id"], "1");
+    assert_eq!(
+        message["payload"]["data"],
+        json!({ "connectionParams": { "token": "secret" } })
+    );
+
+    // the first operation is still running
+    socket.send(subscribe("2")).await.unwrap();
+    let message = next_message(&mut socket).await;
+    assert_eq!(message["type"], "error");
+    assert_eq!(message["id"], "2");
+    assert_eq!(
+        message["payload"][0]["extensions"]["code"],
+        "WEBSOCKET_TOO_MANY_OPERATIONS"
+    );
+
+    drop(socket);
+    server.shutdown().await
+}
+
+#[test(tokio::test)]
+async fn websocket_rejects_other_origins() -> Result<(), ApolloRouterError> {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::Error;
+
+    let router_service = router::service::from_supergraph_mock_callback(|req| {
+        Ok(SupergraphResponse::new_from_graphql_response(
+            graphql::Response::builder()
+                .data(json!({ "me": { "name": "Ada" } }))
+                .build(),
+            req.context,
+        ))
+    })
+    .await;
+    let conf = Arc::new(
+        Configuration::fake_builder()
+            .supergraph(
+                Supergraph::fake_builder()
+                    .experimental_websocket(true)
+                    .experimental_websocket_allowed_origins(vec![
+                        "https://studio.apollographql.com".to_string(),
+                    ])
+                    .build(),
+            )
+            .build()
+            .unwrap(),
+    );
+    let (server, _client) = init_with_config(router_service, conf, MultiMap::new()).await?;
+    let url =
+        format!("{}/", server.graphql_listen_address().as_ref().unwrap()).replacen("http", "ws", 1);
+    let request = |origin: Option<&'static str>| {
+        let mut request = url.as_str().into_client_request().unwrap();
+        request.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static("graphql-transport-ws"),
+        );
+        if let Some(origin) = origin {
+            request
+                .headers_mut()
+                .insert(ORIGIN, HeaderValue::from_static(origin));
+        }
+        request
+    };
+
+    match tokio_tungstenite::connect_async(request(Some("https://evil.example.com"))).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), StatusCode::FORBIDDEN),
+        Err(error) => panic!("unexpected error: {error}"),
+        Ok(_) => panic!("the connection was not rejected"),
+    }
+    tokio_tungstenite::connect_async(request(Some("https://studio.apollographql.com")))
+        .await
+        .unwrap();
+    tokio_tungstenite::connect_async(request(None))
+        .await
+        .unwrap();
+
+    server.shutdown().await
+}
+
 #[tokio::test]
 async fn it_supports_server_restart() {
     let configuration = Arc::new(
@@ -0,0 +1,579 @@
+//! GraphQL over WebSocket on the GraphQL endpoint.
+//!
+//! Clients using the `graphql-transport-ws` protocol, or the legacy `graphql-ws` one, can send
+//! queries and mutations on the same connection as their subscriptions. Every operation goes
+//! through the router service like an HTTP request made with the headers of the upgrade request,
+//! so the whole plugin pipeline applies, and its responses are sent back on the socket. The
+//! payload of the `connection_init` message is available to the plugins in the context of the
+//! operations.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::HttpBody;
+use axum::extract::ws::CloseFrame;
+use axum::extract::ws::Message;
+use axum::extract::ws::WebSocket;
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::FromRequestParts;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use futures::SinkExt;
+use futures::StreamExt;
+use http::header::ACCEPT;
+use http::header::ACCEPT_ENCODING;
+use http::header::CONNECTION;
+use http::header::CONTENT_LENGTH;
+use http::header::CONTENT_TYPE;
+use http::header::ORIGIN;
+use http::header::UPGRADE;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Method;
+use http::Request;
+use http::StatusCode;
+use http::Uri;
+use hyper::Body;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tower::BoxError;
+use tower::ServiceExt;
+use tower_http::decompression::DecompressionBody;
+use tracing::instrument::WithSubscriber;
+use tracing::Instrument;
+
+use super::axum_http_server_factory::execute_graphql;
+use super::utils::ConnectionInfo;
+use crate::graphql;
+use crate::protocols::websocket::ClientMessage;
+use crate::protocols::websocket::ServerError;
+use crate::protocols::websocket::ServerMessage;
+use crate::protocols::websocket::WebSocketProtocol;
+use crate::router_factory::RouterFactory;
+use crate::services::router;
+use crate::services::MULTIPART_DEFER_ACCEPT;
+use crate::services::MULTIPART_SUBSCRIPTION_ACCEPT;
+
+const CONNECTION_INIT_TIMEOUT: Duration = Duration::from_secs(10);
+const MESSAGE_BUFFER_SIZE: usize = 32;
+const MULTIPART_DELIMITER: &[u8] = b"\r\n--graphql";
+
+/// Context key of the payload of the `connection_init` message of the WebSocket connection an
+/// operation was sent on
+const WEBSOCKET_CONNECTION_PARAMS: &str = "apollo::websocket::connection_params";
+
+/// The WebSocket settings of the GraphQL endpoint.
+pub(super) struct Config {
+    pub(super) allowed_origins: Vec<String>,
+    pub(super) max_operations: usize,
+}
+
+/// Returns `true` if the client asks to switch the connection to the WebSocket protocol.
+pub(super) fn is_upgrade_request<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get(UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Accepts the WebSocket connection and serves the GraphQL operations sent on it.
+pub(super) async fn upgrade<RF>(
+    factory: RF,
+    config: &Config,
+    early_cancel: bool,
+    experimental_log_on_broken_pipe: bool,
+    context_max_bytes: Option<usize>,
+    http_request: Request<DecompressionBody<Body>>,
+) -> Response
+where
+    RF: RouterFactory,
+{
+    let (mut parts, _body) = http_request.into_parts();
+    // browsers let any web page open WebSocket connections with the cookies of the router's
+    // domain, and the operations skip the CSRF checks made on HTTP requests
+    if !is_allowed_origin(&parts.headers, &config.allowed_origins) {
+        return (StatusCode::FORBIDDEN, "origin not allowed").into_response();
+    }
+    let upgrade = match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let settings = Settings {
+        max_operations: config.max_operations,
+        early_cancel,
+        experimental_log_on_broken_pipe,
+        context_max_bytes,
+        uri: parts.uri,
+        headers: parts.headers,
+        connection_info: parts.extensions.get::<ConnectionInfo>().cloned(),
+    };
+    upgrade
+        .protocols([
+            protocol_name(WebSocketProtocol::GraphqlWs),
+            protocol_name(WebSocketProtocol::SubscriptionsTransportWs),
+        ])
+        .on_upgrade(move |socket| serve(socket, factory, Arc::new(settings)))
+}
+
+/// Returns `true` if the upgrade request comes from an allowed origin, or from a client that is
+/// not a browser, since those do not send the `Origin` header.
+fn is_allowed_origin(headers: &HeaderMap, allowed_origins: &[String]) -> bool {
+    match headers.get(ORIGIN) {
+        None => true,
+        Some(origin) => allowed_origins
+            .iter()
+            .any(|allowed| allowed.as_bytes() == origin.as_bytes()),
+    }
+}
+
+fn protocol_name(protocol: WebSocketProtocol) -> &'static str {
+    match protocol {
+        WebSocketProtocol::GraphqlWs => "graphql-transport-ws",
+        WebSocketProtocol::SubscriptionsTransportWs => "graphql-ws",
+    }
+}
+
+/// What the operations of a connection need to go through the router service.
+struct Settings {
+    max_operations: usize,
+    early_cancel: bool,
+    experimental_log_on_broken_pipe: bool,
+    context_max_bytes: Option<usize>,
+    uri: Uri,
+    headers: HeaderMap,
+    connection_info: Option<ConnectionInfo>,
+}
+
+impl Settings {
+    /// Creates the HTTP request executing an operation, with the headers of the upgrade request.
+    fn operation_request(&self, payload: &graphql::Request) -> Result<Request<Body>, BoxError> {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(self.uri.clone())
+            .body(Body::from(serde_json::to_vec(payload)?))?;
+
+        let headers = request.headers_mut();
+        for (name, value) in &self.headers {
+            let is_upgrade_header = [ACCEPT, ACCEPT_ENCODING, CONNECTION, CONTENT_LENGTH, UPGRADE]
+                .contains(name)
+                || name.as_str().starts_with("sec-websocket-");
+            if !is_upgrade_header {
+                headers.append(name, value.clone());
+            }
+        }
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_str(&format!(
+                "application/json, {MULTIPART_DEFER_ACCEPT}, {MULTIPART_SUBSCRIPTION_ACCEPT}"
+            ))?,
+        );
+        if let Some(connection_info) = &self.connection_info {
+            request.extensions_mut().insert(connection_info.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Sends the messages of the server to the client.
+#[derive(Clone)]
+struct Connection {
+    protocol: WebSocketProtocol,
+    sender: mpsc::Sender<Message>,
+}
+
+impl Connection {
+    async fn send(&self, message: ServerMessage) {
+        let mut message = match serde_json::to_value(message) {
+            Ok(message) => message,
+            Err(error) => {
+                tracing::error!("cannot serialize the websocket message: {error}");
+                return;
+            }
+        };
+        // the legacy protocol names the results `data`
+        if self.protocol == WebSocketProtocol::SubscriptionsTransportWs && message["type"] == "next"
+        {
+            message["type"] = "data".into();
+        }
+        // the client is gone if this fails, the connection is closing already
+        let _ = self.sender.send(Message::Text(message.to_string())).await;
+    }
+
+    async fn close(&self, code: u16, reason: impl Into<Cow<'static, str>>) {
+        let _ = self
+            .sender
+            .send(Message::Close(Some(CloseFrame {
+                code,
+                reason: reason.into(),
+            })))
+            .await;
+    }
+}
+
+async fn serve<RF>(socket: WebSocket, factory: RF, settings: Arc<Settings>)
+where
+    RF: RouterFactory,
+{
+    let protocol = if socket
+        .protocol()
+        .and_then(|protocol| protocol.to_str().ok())
+        == Some(protocol_name(WebSocketProtocol::SubscriptionsTransportWs))
+    {
+        WebSocketProtocol::SubscriptionsTransportWs
+    } else {
+        WebSocketProtocol::GraphqlWs
+    };
+    let (mut sink, mut stream) = socket.split();
+    let (sender, mut receiver) = mpsc::channel(MESSAGE_BUFFER_SIZE);
+    let writer = tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            let is_close = matches!(message, Message::Close(_));
+            if sink.send(message).await.is_err() || is_close {
+                break;
+            }
+        }
+    });
+    let connection = Connection { protocol, sender };
+
+    let init_deadline = Instant::now() + CONNECTION_INIT_TIMEOUT;
+    let mut acknowledged = false;
+    let mut connection_params = None;
+    let mut operations: HashMap<String, JoinHandle<()>> = HashMap::new();
+    loop {
+        let message = if acknowledged {
+            stream.next().await
+        } else {
+            match tokio::time::timeout_at(init_deadline, stream.next()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    connection
+                        .close(4408, "Connection initialisation timeout")
+                        .await;
+                    break;
+                }
+            }
+        };
+        let message = match message {
+            Some(Ok(Message::Text(text))) => serde_json::from_str::<ClientMessage>(&text),
+            Some(Ok(Message::Binary(bytes))) => serde_json::from_slice::<ClientMessage>(&bytes),
+            Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+            Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+        };
+
+        match message {
+            Err(_) => {
+                connection.close(4400, "Invalid message received").await;
+                break;
+            }
+            Ok(ClientMessage::ConnectionInit { payload }) => {
+                if acknowledged {
+                    connection
+                        .close(4429, "Too many initialisation requests")
+                        .await;
+                    break;
+                }
+                acknowledged = true;
+                connection_params = payload;
+                connection.send(ServerMessage::ConnectionAck).await;
+            }
+            Ok(
+                ClientMessage::Subscribe { id, payload } | ClientMessage::OldStart { id, payload },
+            ) => {
+                if !acknowledged {
+                    connection.close(4401, "Unauthorized").await;
+                    break;
+                }
+                operations.retain(|_, operation| !operation.is_finished());
+                if operations.contains_key(&id) {
+                    connection
+                        .close(4409, format!("Subscriber for {id} already exists"))
+                        .await;
+                    break;
+                }
+                if operations.len() >= settings.max_operations {
+                    connection
+                        .send(ServerMessage::Error {
+                            id,
+                            payload: ServerError::Errors(vec![graphql::Error::builder()
+                                .message(format!(
+                                    "too many operations running on this connection, the limit is {}",
+                                    settings.max_operations
+                                ))
+                                .extension_code("WEBSOCKET_TOO_MANY_OPERATIONS")
+                                .build()]),
+                        })
+                        .await;
+                    continue;
+                }
+
+                let mut service = factory.create().boxed();
+                if let Some(connection_params) = connection_params.clone() {
+                    service = service
+                        .map_request(move |request: router::Request| {
+                            if let Err(error) = request
+                                .context
+                                .insert(WEBSOCKET_CONNECTION_PARAMS, connection_params.clone())
+                            {
+                                tracing::error!(
+                                    "cannot add the websocket connection parameters to the context: {error}"
+                                );
+                            }
+                            request
+                        })
+                        .boxed();
+                }
+                let operation = execute(
+                    service,
+                    settings.clone(),
+                    connection.clone(),
+                    id.clone(),
+                    payload,
+                );
+                operations.insert(
+                    id,
+                    tokio::spawn(operation.with_current_subscriber().in_current_span()),
+                );
+            }
+            Ok(ClientMessage::Complete { id } | ClientMessage::OldStop { id }) => {
+                if let Some(operation) = operations.remove(&id) {
+                    operation.abort();
+                }
+            }
+            Ok(ClientMessage::Ping { payload }) => {
+                connection
+                    .send(ServerMessage::Pong {
+                        payload: payload.and_then(|payload| serde_json::to_value(payload).ok()),
+                    })
+                    .await;
+            }
+            Ok(ClientMessage::Pong { .. }) => {}
+            Ok(ClientMessage::ConnectionTerminate | ClientMessage::CloseWebsocket) => break,
+        }
+    }
+
+    for operation in operations.into_values() {
+        operation.abort();
+    }
+    drop(connection);
+    let _ = writer.await;
+}
+
+/// Executes an operation and sends its responses on the socket.
+async fn execute(
+    service: router::BoxService,
+    settings: Arc<Settings>,
+    connection: Connection,
+    id: String,
+    payload: graphql::Request,
+) {
+    let http_request = match settings.operation_request(&payload) {
+        Ok(http_request) => http_request,
+        Err(error) => {
+            tracing::error!("cannot create the request of a websocket operation: {error}");
+            connection
+                .send(ServerMessage::Error {
+                    id,
+                    payload: ServerError::Error(internal_error()),
+                })
+                .await;
+            return;
+        }
+    };
+    let response = execute_graphql(
+        service,
+        settings.early_cancel,
+        settings.experimental_log_on_broken_pipe,
+        settings.context_max_bytes,
+        http_request,
+    )
+    .await;
+
+    let is_multipart = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/mixed"));
+    let mut body = response.into_body();
+
+    if !is_multipart {
+        let response = match hyper::body::to_bytes(body)
+            .await
+            .map_err(BoxError::from)
+            .and_then(|bytes| Ok(serde_json::from_slice::<graphql::Response>(&bytes)?))
+        {
+            Ok(response) => response,
+            Err(error) => {
+                tracing::error!("cannot read the response of a websocket operation: {error}");
+                internal_error_response()
+            }
+        };
+        // request errors end the operation without a result
+        if response.data.is_none() && !response.errors.is_empty() {
+            connection
+                .send(ServerMessage::Error {
+                    id,
+                    payload: ServerError::Errors(response.errors),
+                })
+                .await;
+            return;
+        }
+        connection
+            .send(ServerMessage::Next {
+                id: id.clone(),
+                payload: response,
+            })
+            .await;
+    } else {
+        let mut parser = MultipartParser::default();
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    tracing::error!("cannot read the response of a websocket operation: {error}");
+                    connection
+                        .send(ServerMessage::Next {
+                            id: id.clone(),
+                            payload: internal_error_response(),
+                        })
+                        .await;
+                    break;
+                }
+            };
+            for part in parser.push(&chunk) {
+                let response = match serde_json::from_slice::<Part>(&part) {
+                    Ok(Part::Subscription(SubscriptionPart { payload, errors })) => {
+                        match (payload, errors.is_empty()) {
+                            (Some(mut response), _) => {
+                                response.errors.extend(errors);
+                                response
+                            }
+                            (None, false) => graphql::Response::builder().errors(errors).build(),
+                            // heartbeat
+                            (None, true) => continue,
+                        }
+                    }
+                    Ok(Part::Response(response)) => response,
+                    Err(error) => {
+                        tracing::error!(
+                            "cannot read the response of a websocket operation: {error}"
+                        );
+                        internal_error_response()
+                    }
+                };
+                connection
+                    .send(ServerMessage::Next {
+                        id: id.clone(),
+                        payload: response,
+                    })
+                    .await;
+            }
+        }
+    }
+
+    connection.send(ServerMessage::Complete { id }).await;
+}
+
+fn internal_error() -> graphql::Error {
+    graphql::Error::builder()
+        .message("internal server error")
+        .extension_code("INTERNAL_SERVER_ERROR")
+        .build()
+}
+
+fn internal_error_response() -> graphql::Response {
+    graphql::Response::builder().error(internal_error()).build()
+}
+
+/// A part of a multipart response of the router.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Part {
+    Subscription(SubscriptionPart),
+    Response(graphql::Response),
+}
+
+/// A subscription event, or a heartbeat if it is empty.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscriptionPart {
+    payload: Option<graphql::Response>,
+    #[serde(default)]
+    errors: Vec<graphql::Error>,
+}
+
+/// Splits the multipart responses of the router back into their parts.
+#[derive(Default)]
+struct MultipartParser {
+    buffer: Vec<u8>,
+}
+
+impl MultipartParser {
+    /// Adds a chunk of the response, and returns the bodies of the parts it completes.
+    fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        self.buffer.extend_from_slice(chunk);
+        let mut parts = Vec::new();
+        while let Some(position) = find(&self.buffer, MULTIPART_DELIMITER) {
+            let part: Vec<u8> = self
+                .buffer
+                .drain(..position + MULTIPART_DELIMITER.len())
+                .take(position)
+                .collect();
+            // the preamble has no headers
+            if let Some(headers_end) = find(&part, b"\r\n\r\n") {
+                parts.push(part[headers_end + 4..].to_vec());
+            }
+        }
+        parts
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multipart_parts() {
+        let mut parser = MultipartParser::default();
+        assert_eq!(
+            parser.push(b"\r\n--graphql\r\ncontent-type: application/json\r\n\r\n{\"data\":1}"),
+            Vec::<Vec<u8>>::new()
+        );
+        assert_eq!(
+            parser.push(b"\r\n--graphql\r\ncontent-type: application/json\r\n\r\n{}\r\n--gr"),
+            vec![b"{\"data\":1}".to_vec()]
+        );
+        assert_eq!(parser.push(b"aphql--\r\n"), vec![b"{}".to_vec()]);
+    }
+
+    #[test]
+    fn multipart_subscription_parts() {
+        assert!(matches!(
+            serde_json::from_slice::<Part>(b"{}").unwrap(),
+            Part::Subscription(SubscriptionPart { payload: None, .. })
+        ));
+        assert!(matches!(
+            serde_json::from_slice::<Part>(b"{\"payload\":{\"data\":{\"a\":1}}}").unwrap(),
+            Part::Subscription(SubscriptionPart {
+                payload: Some(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            serde_json::from_slice::<Part>(b"{\"data\":{\"a\":1},\"hasNext\":true}").unwrap(),
+            Part::Response(_)
+        ));
+    }
+}
@@ -2,9 +2,15 @@ use std::collections::HashMap;
 use std::fmt;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
 use std::time::Duration;
 
+use bytes::Bytes;
+use fred::clients::SubscriberClient;
 use fred::interfaces::EventInterface;
+use fred::interfaces::HashesInterface;
+use fred::interfaces::PubsubInterface;
 #[cfg(test)]
 use fred::mocks::Mocks;
 use fred::prelude::ClientLike;
@@ -19,18 +25,26 @@ use fred::types::PerformanceConfig;
 use fred::types::ReconnectPolicy;
 use fred::types::RedisConfig;
 use fred::types::ScanResult;
+use fred::types::SetOptions;
 use fred::types::TlsConfig;
 use fred::types::TlsHostMapping;
 use futures::FutureExt;
 use futures::Stream;
+use once_cell::sync::Lazy;
 use tower::BoxError;
 use url::Url;
 
 use super::KeyType;
 use super::ValueType;
+use crate::configuration::spiffe;
 use crate::configuration::RedisCache;
+use crate::configuration::RedisCompression;
 use crate::services::generate_tls_client_config;
 
+/// Magic number starting zstd frames, which JSON values never start with
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const ZSTD_LEVEL: i32 = 3;
+
 const SUPPORTED_REDIS_SCHEMES: [&str; 6] = [
     "redis",
     "rediss",
@@ -50,6 +64,19 @@ pub(crate) struct RedisValue<V>(pub(crate) V)
 where
     V: ValueType;
 
+/// The Redis clients in use, checked by the health probes
+static CLIENTS: Lazy<Mutex<Vec<Weak<RedisClient>>>> = Lazy::new(Default::default);
+
+/// Whether all the Redis clients in use are connected
+pub(crate) fn all_connected() -> bool {
+    let mut clients = CLIENTS.lock().expect("lock poisoned");
+    clients.retain(|client| client.strong_count() > 0);
+    clients
+        .iter()
+        .filter_map(Weak::upgrade)
+        .all(|client| client.is_connected())
+}
+
 #[derive(Clone)]
 pub(crate) struct RedisCacheStorage {
     inner: Arc<RedisClient>,
@@ -57,6 +84,9 @@ pub(crate) struct RedisCacheStorage {
     pub(crate) ttl: Option<Duration>,
     is_cluster: bool,
     reset_ttl: bool,
+    compression: RedisCompression,
+    max_entry_size: Option<usize>,
+    caller: &'static str,
 }
 
 fn get_type_of<T>(_: &T) -> &'static str {
@@ -96,6 +126,20 @@ where
 {
     fn from_value(value: fred::types::RedisValue) -> Result<Self, RedisError> {
         match value {
+            fred::types::RedisValue::Bytes(data) if data.starts_with(&ZSTD_MAGIC) => {
+                let data = zstd::decode_all(&data[..]).map_err(|e| {
+                    RedisError::new(
+                        RedisErrorKind::Parse,
+                        format!("can't decompress value: {e}"),
+                    )
+                })?;
+                serde_json::from_slice(&data).map(RedisValue).map_err(|e| {
+                    RedisError::new(
+                        RedisErrorKind::Parse,
+                        format!("can't deserialize from JSON: {e}"),
+                    )
+                })
+            }
             fred::types::RedisValue::Bytes(data) => {
                 serde_json::from_slice(&data).map(RedisValue).map_err(|e| {
                     RedisError::new(
@@ -142,8 +186,15 @@ where
     }
 }
 
+fn compress(compression: RedisCompression, data: Bytes) -> Result<Bytes, std::io::Error> {
+    match compression {
+        RedisCompression::None => Ok(data),
+        RedisCompression::Zstd => zstd::encode_all(&data[..], ZSTD_LEVEL).map(Bytes::from),
+    }
+}
+
 impl RedisCacheStorage {
-    pub(crate) async fn new(config: RedisCache) -> Result<Self, BoxError> {
+    pub(crate) async fn new(config: RedisCache, caller: &'static str) -> Result<Self, BoxError> {
         let url = Self::preprocess_urls(config.urls)?;
         let mut client_config = RedisConfig::from_url(url.as_str())?;
         let is_cluster = url.scheme() == "redis-cluster" || url.scheme() == "rediss-cluster";
@@ -157,9 +208,13 @@ impl RedisCacheStorage {
         }
 
         if let Some(tls) = config.tls.as_ref() {
-            let tls_cert_store = tls.create_certificate_store().transpose()?;
-            let client_cert_config = tls.client_authentication.as_ref();
-            let tls_client_config = generate_tls_client_config(tls_cert_store, client_cert_config)?;
+            let tls_client_config = if tls.spiffe {
+                spiffe::client_config()?
+            } else {
+                let tls_cert_store = tls.create_certificate_store().transpose()?;
+                let client_cert_config = tls.client_authentication.as_ref();
+                generate_tls_client_config(tls_cert_store, client_cert_config)?
+            };
             let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_client_config));
 
             client_config.tls = Some(TlsConfig {
@@ -202,12 +257,20 @@ impl RedisCacheStorage {
             })??;
 
         tracing::trace!("redis connection established");
+        let client = Arc::new(client);
+        CLIENTS
+            .lock()
+            .expect("lock poisoned")
+            .push(Arc::downgrade(&client));
         Ok(Self {
-            inner: Arc::new(client),
+            inner: client,
             namespace: config.namespace.map(Arc::new),
             ttl: config.ttl,
             is_cluster,
             reset_ttl: config.reset_ttl,
+            compression: config.compression,
+            max_entry_size: config.max_entry_size.map(|size| size.as_u64() as usize),
+            caller,
         })
     }
 
@@ -258,6 +321,9 @@ impl RedisCacheStorage {
             namespace: None,
             is_cluster: false,
             reset_ttl: false,
+            compression: RedisCompression::None,
+            max_entry_size: None,
+            caller: "test",
         })
     }
 
@@ -358,6 +424,40 @@ impl RedisCacheStorage {
         self.ttl = ttl;
     }
 
+    /// Serializes and compresses a value, or returns `None` if it can't be stored in Redis
+    fn encode<V: ValueType>(&self, value: RedisValue<V>) -> Option<fred::types::RedisValue> {
+        let value: fred::types::RedisValue = value.try_into().ok()?;
+        let data = match value {
+            fred::types::RedisValue::Bytes(data) => data,
+            value => return Some(value),
+        };
+        let data = compress(self.compression, data)
+            .map_err(|e| tracing::error!(error = %e, "could not compress value for Redis"))
+            .ok()?;
+
+        if self
+            .max_entry_size
+            .map(|max_entry_size| data.len() > max_entry_size)
+            .unwrap_or(false)
+        {
+            u64_counter!(
+                "apollo.router.cache.redis.entry.rejected",
+                "Number of values not stored in Redis because they are larger than the maximum entry size",
+                1,
+                "kind" = self.caller
+            );
+            return None;
+        }
+
+        u64_histogram!(
+            "apollo.router.cache.redis.entry.size",
+            "Size of the values stored in Redis, after compression",
+            data.len() as u64,
+            "kind" = self.caller
+        );
+        Some(fred::types::RedisValue::Bytes(data))
+    }
+
     fn make_key<K: KeyType>(&self, key: RedisKey<K>) -> String {
         match &self.namespace {
             Some(namespace) => format!("{namespace}:{key}"),
@@ -518,6 +618,9 @@ impl RedisCacheStorage {
     ) {
         let key = self.make_key(key);
         tracing::trace!("inserting into redis: {:?}, {:?}", key, value);
+        let Some(value) = self.encode(value) else {
+            return;
+        };
         let expiration = ttl
             .as_ref()
             .or(self.ttl.as_ref())
@@ -536,22 +639,26 @@ impl RedisCacheStorage {
         ttl: Option<Duration>,
     ) {
         tracing::trace!("inserting into redis: {:#?}", data);
+        let data: Vec<(String, fred::types::RedisValue)> = data
+            .iter()
+            .filter_map(|(key, value)| {
+                self.encode(value.clone())
+                    .map(|value| (self.make_key(key.clone()), value))
+            })
+            .collect();
+        if data.is_empty() {
+            return;
+        }
 
         let r = match ttl.as_ref().or(self.ttl.as_ref()) {
-            None => self.inner.mset(data.to_owned()).await,
+            None => self.inner.mset(data).await,
             Some(ttl) => {
                 let expiration = Some(Expiration::EX(ttl.as_secs() as i64));
                 let pipeline = self.inner.pipeline();
 
                 for (key, value) in data {
                     let _ = pipeline
-                        .set::<(), _, _>(
-                            self.make_key(key.clone()),
-                            value.clone(),
-                            expiration.clone(),
-                            None,
-                            false,
-                        )
+                        .set::<(), _, _>(key, value, expiration.clone(), None, false)
                         .await;
                 }
 
@@ -561,6 +668,110 @@ impl RedisCacheStorage {
         tracing::trace!("insert result {:?}", r);
     }
 
+    /// Stores a value if the key has none, and returns the value of the key
+    pub(crate) async fn get_or_insert<K: KeyType, V: ValueType>(
+        &self,
+        key: RedisKey<K>,
+        value: RedisValue<V>,
+    ) -> Result<RedisValue<V>, RedisError> {
+        let key = self.make_key(key);
+        self.inner
+            .set::<(), _, _>(&key, value, None, Some(SetOptions::NX), false)
+            .await?;
+        self.inner.get(&key).await
+    }
+
+    /// Adds an amount to the integer value of a key, and returns the new value. A new key expires
+    /// after the expiration.
+    pub(crate) async fn increment<K: KeyType>(
+        &self,
+        key: RedisKey<K>,
+        amount: u64,
+        expiration: Duration,
+    ) -> Result<u64, RedisError> {
+        let key = self.make_key(key);
+        let value: u64 = self.inner.incr_by(&key, amount as i64).await?;
+        if value == amount {
+            self.inner
+                .pexpire::<(), _>(&key, expiration.as_millis() as i64)
+                .await?;
+        }
+        Ok(value)
+    }
+
+    /// Sets a field of a hash, resets the expiration of the hash, and returns all its fields
+    pub(crate) async fn hash_insert_and_get_all<K: KeyType>(
+        &self,
+        key: RedisKey<K>,
+        field: String,
+        value: String,
+        expiration: Duration,
+    ) -> Result<HashMap<String, String>, RedisError> {
+        let key = self.make_key(key);
+        let pipeline: fred::clients::Pipeline<RedisClient> = self.inner.pipeline();
+        let _: fred::types::RedisValue = pipeline.hset(&key, (field, value)).await?;
+        let _: fred::types::RedisValue = pipeline
+            .pexpire(&key, expiration.as_millis() as i64)
+            .await?;
+        let _: fred::types::RedisValue = pipeline.hgetall(&key).await?;
+        let (_, _, fields): (u64, bool, HashMap<String, String>) = pipeline.all().await?;
+        Ok(fields)
+    }
+
+    /// Deletes fields of a hash
+    pub(crate) async fn hash_delete<K: KeyType>(
+        &self,
+        key: RedisKey<K>,
+        fields: Vec<String>,
+    ) -> Result<(), RedisError> {
+        self.inner.hdel(self.make_key(key), fields).await
+    }
+
+    /// Publishes a message on a channel, and returns the number of clients that received it.
+    /// Channels are not namespaced.
+    pub(crate) async fn publish(
+        &self,
+        channel: String,
+        message: String,
+    ) -> Result<u64, RedisError> {
+        self.inner.publish(channel, message).await
+    }
+
+    /// Publishes messages on channels in a single round trip, and returns the number of clients
+    /// that received each message. Channels are not namespaced.
+    pub(crate) async fn publish_all(
+        &self,
+        messages: Vec<(String, String)>,
+    ) -> Result<Vec<u64>, RedisError> {
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pipeline: fred::clients::Pipeline<RedisClient> = self.inner.pipeline();
+        for (channel, message) in messages {
+            let _: () = pipeline.publish(channel, message).await?;
+        }
+        pipeline.all().await
+    }
+
+    /// Returns a new client with the same configuration, for the subscriptions to channels. The
+    /// client resubscribes to its channels when it reconnects.
+    pub(crate) async fn subscriber(&self) -> Result<SubscriberClient, RedisError> {
+        let client = SubscriberClient::new(
+            self.inner.client_config(),
+            Some(self.inner.perf_config()),
+            Some(self.inner.connection_config().clone()),
+            self.inner.client_reconnect_policy(),
+        );
+        let _handle = client.connect();
+        let _manage_handle = client.manage_subscriptions();
+        tokio::time::timeout(Duration::from_secs(5), client.wait_for_connect())
+            .await
+            .map_err(|_| {
+                RedisError::new(RedisErrorKind::Timeout, "timeout connecting to Redis")
+            })??;
+        Ok(client)
+    }
+
     pub(crate) async fn delete<K: KeyType>(&self, keys: Vec<RedisKey<K>>) -> Option<u32> {
         self.inner
             .del(keys)
@@ -591,9 +802,11 @@ impl RedisCacheStorage {
 mod test {
     use std::time::SystemTime;
 
+    use fred::types::FromRedis;
     use url::Url;
 
     use crate::cache::storage::ValueType;
+    use crate::configuration::RedisCompression;
 
     #[test]
     fn ensure_invalid_payload_serialization_doesnt_fail() {
@@ -617,6 +830,26 @@ mod test {
         assert!(as_value.is_err());
     }
 
+    #[test]
+    fn compressed_values_are_decompressed() {
+        let query = "query { me { name } }".to_string();
+        let value: fred::types::RedisValue = super::RedisValue(query.clone()).try_into().unwrap();
+        let fred::types::RedisValue::Bytes(data) = value else {
+            panic!("values are serialized to bytes");
+        };
+
+        for compression in [RedisCompression::None, RedisCompression::Zstd] {
+            let data = super::compress(compression, data.clone()).unwrap();
+            assert_eq!(
+                data.starts_with(&super::ZSTD_MAGIC),
+                compression == RedisCompression::Zstd
+            );
+            let decoded: super::RedisValue<String> =
+                FromRedis::from_value(fred::types::RedisValue::Bytes(data)).unwrap();
+            assert_eq!(decoded.0, query);
+        }
+    }
+
     #[test]
     fn it_preprocesses_redis_schemas_correctly() {
         // Base Format
@@ -5,8 +5,10 @@ use std::num::NonZeroUsize;
 use std::sync::atomic::AtomicI64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Weak;
 
 use lru::LruCache;
+use once_cell::sync::Lazy;
 use opentelemetry::metrics::MeterProvider;
 use opentelemetry_api::metrics::Meter;
 use opentelemetry_api::metrics::ObservableGauge;
@@ -47,6 +49,42 @@ where
 
 pub(crate) type InMemoryCache<K, V> = Arc<Mutex<LruCache<K, V>>>;
 
+/// The caches in use, reported in support bundles
+static CACHES: Lazy<std::sync::Mutex<Vec<RegisteredCache>>> = Lazy::new(Default::default);
+
+struct RegisteredCache {
+    kind: &'static str,
+    redis: bool,
+    size: Weak<AtomicI64>,
+    estimated_storage: Weak<AtomicI64>,
+}
+
+/// Statistics of a cache in use
+#[derive(Debug, Serialize)]
+pub(crate) struct CacheStats {
+    kind: &'static str,
+    entries: i64,
+    estimated_storage_bytes: i64,
+    redis: bool,
+}
+
+/// Statistics of the caches in use
+pub(crate) fn stats() -> Vec<CacheStats> {
+    let mut caches = CACHES.lock().expect("lock poisoned");
+    caches.retain(|cache| cache.size.strong_count() > 0);
+    caches
+        .iter()
+        .filter_map(|cache| {
+            Some(CacheStats {
+                kind: cache.kind,
+                entries: cache.size.upgrade()?.load(Ordering::SeqCst),
+                estimated_storage_bytes: cache.estimated_storage.upgrade()?.load(Ordering::SeqCst),
+                redis: cache.redis,
+            })
+        })
+        .collect()
+}
+
 // placeholder storage module
 //
 // this will be replaced by the multi level (in memory + redis/memcached) once we find
@@ -78,7 +116,7 @@ where
         let (cache_estimated_storage, cache_estimated_storage_gauge) =
             Self::create_cache_estimated_storage_size_gauge(&meter, caller);
 
-        Ok(Self {
+        let storage = Self {
             _cache_size_gauge: cache_size_gauge,
             _cache_estimated_storage_gauge: cache_estimated_storage_gauge,
             cache_size,
@@ -87,7 +125,7 @@ where
             inner: Arc::new(Mutex::new(LruCache::new(max_capacity))),
             redis: if let Some(config) = config {
                 let required_to_start = config.required_to_start;
-                match RedisCacheStorage::new(config).await {
+                match RedisCacheStorage::new(config, caller).await {
                     Err(e) => {
                         tracing::error!(
                             cache = caller,
@@ -104,7 +142,14 @@ where
             } else {
                 None
             },
-        })
+        };
+        CACHES.lock().expect("lock poisoned").push(RegisteredCache {
+            kind: caller,
+            redis: storage.redis.is_some(),
+            size: Arc::downgrade(&storage.cache_size),
+            estimated_storage: Arc::downgrade(&storage.cache_estimated_storage),
+        });
+        Ok(storage)
     }
 
     fn create_cache_size_gauge(
@@ -0,0 +1,53 @@
+//! Negotiation of the format of the responses sent to clients
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Response format configuration
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ContentNegotiation {
+    /// Respond with `application/graphql-response+json` to the clients accepting it, with the
+    /// status codes of the GraphQL over HTTP specification: responses without data have a 4xx
+    /// status code, and responses with data a 2xx one. Other clients get `application/json`
+    /// responses
+    pub(crate) graphql_response_json: bool,
+
+    /// Encode the responses in CBOR for the clients accepting `application/cbor`. Deferred
+    /// responses and subscriptions are not encoded in CBOR
+    pub(crate) cbor: bool,
+
+    /// Overrides for specific clients, by client name
+    pub(crate) clients: HashMap<String, ClientContentNegotiation>,
+}
+
+/// Response format overrides for a client
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ClientContentNegotiation {
+    /// Respond with `application/graphql-response+json` and the status codes of the GraphQL over
+    /// HTTP specification, if the client accepts it
+    pub(crate) graphql_response_json: Option<bool>,
+
+    /// Encode the responses in CBOR, if the client accepts it
+    pub(crate) cbor: Option<bool>,
+}
+
+impl ContentNegotiation {
+    /// Returns whether `application/graphql-response+json` and CBOR responses are enabled for
+    /// a client.
+    pub(crate) fn for_client(&self, client_name: Option<&str>) -> (bool, bool) {
+        let overrides = client_name.and_then(|client_name| self.clients.get(client_name));
+        (
+            overrides
+                .and_then(|overrides| overrides.graphql_response_json)
+                .unwrap_or(self.graphql_response_json),
+            overrides
+                .and_then(|overrides| overrides.cbor)
+                .unwrap_or(self.cbor),
+        )
+    }
+}
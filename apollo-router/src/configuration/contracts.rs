@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use http::HeaderMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Context key that plugins, Rhai scripts and coprocessors may set to pick the contract variant
+/// serving a request. It takes precedence over the header and client mappings.
+pub(crate) const CONTRACT_VARIANT_CONTEXT_KEY: &str = "apollo_contracts::variant";
+
+/// Contract variants configuration
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Contracts {
+    /// Contract variants served alongside the main supergraph, keyed by variant name
+    pub(crate) variants: HashMap<String, ContractVariant>,
+
+    /// Request header carrying the name of the contract variant to use
+    pub(crate) header: Option<String>,
+
+    /// Contract variant to use for a client, keyed by client name
+    pub(crate) clients: HashMap<String, String>,
+
+    /// Contract variant used when a request selects none (defaults to the main supergraph)
+    pub(crate) default_variant: Option<String>,
+}
+
+/// Contract variant configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ContractVariant {
+    /// Path to the supergraph schema of this contract variant
+    pub(crate) supergraph_path: PathBuf,
+}
+
+impl Contracts {
+    /// Returns the name of the contract variant requested, or `None` for the main supergraph.
+    ///
+    /// The variant is taken, in order, from the context, the configured header, the client name
+    /// mapping and finally the default variant.
+    pub(crate) fn requested_variant(
+        &self,
+        context_variant: Option<String>,
+        headers: &HeaderMap,
+        client_name: Option<&str>,
+    ) -> Option<String> {
+        if self.variants.is_empty() {
+            return None;
+        }
+
+        context_variant
+            .or_else(|| {
+                self.header
+                    .as_ref()
+                    .and_then(|header| headers.get(header.as_str()))
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            })
+            .or_else(|| {
+                client_name
+                    .and_then(|client_name| self.clients.get(client_name))
+                    .cloned()
+            })
+            .or_else(|| self.default_variant.clone())
+    }
+
+    /// Returns the first variant name referenced by the configuration that is not declared.
+    pub(crate) fn unknown_variant(&self) -> Option<&str> {
+        self.clients
+            .values()
+            .chain(self.default_variant.iter())
+            .find(|name| !self.variants.contains_key(name.as_str()))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn contracts() -> Contracts {
+        serde_yaml::from_str(
+            r#"
+variants:
+  partner:
+    supergraph_path: partner.graphql
+  internal:
+    supergraph_path: internal.graphql
+header: x-contract-variant
+clients:
+  partner-app: partner
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn requested_variant_precedence() {
+        let contracts = contracts();
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(contracts.requested_variant(None, &headers, None), None);
+        assert_eq!(
+            contracts.requested_variant(None, &headers, Some("partner-app")),
+            Some("partner".to_string())
+        );
+
+        headers.insert("x-contract-variant", HeaderValue::from_static("internal"));
+        assert_eq!(
+            contracts.requested_variant(None, &headers, Some("partner-app")),
+            Some("internal".to_string())
+        );
+        assert_eq!(
+            contracts.requested_variant(Some("partner".to_string()), &headers, Some("partner-app")),
+            Some("partner".to_string())
+        );
+    }
+
+    #[test]
+    fn default_variant() {
+        let contracts = Contracts {
+            default_variant: Some("partner".to_string()),
+            ..contracts()
+        };
+        assert_eq!(
+            contracts.requested_variant(None, &HeaderMap::new(), None),
+            Some("partner".to_string())
+        );
+        assert_eq!(contracts.unknown_variant(), None);
+
+        let contracts = Contracts {
+            default_variant: Some("missing".to_string()),
+            ..contracts
+        };
+        assert_eq!(contracts.unknown_variant(), Some("missing"));
+    }
+}
@@ -0,0 +1,333 @@
+//! Differences between the effective settings of two configurations.
+//!
+//! Both configurations are parsed like the router does when loading them, so the differences
+//! take defaults, environment variable expansion and configuration upgrades into account. Each
+//! difference is classified by what the router rebuilds when reloading with the new configuration.
+//!
+//! The values of the settings referencing variables, like `${env.PASSWORD}`, are redacted, since
+//! they are often secrets.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::Configuration;
+use super::ConfigurationError;
+use super::ListenAddr;
+
+/// Settings used to configure the query planners. Changing them creates new query planners, and
+/// the cached query plans are not reused.
+const QUERY_PLANNER_SETTINGS: &[&str] = &[
+    "experimental_query_planner_mode",
+    "supergraph.defer_support",
+    "supergraph.generate_query_fragments",
+    "supergraph.query_planning.experimental_parallelism",
+    "supergraph.query_planning.experimental_paths_limit",
+    "supergraph.query_planning.experimental_plans_limit",
+    "supergraph.query_planning.type_conditioned_fetching",
+    "supergraph.reuse_query_fragments",
+];
+
+/// Settings of the HTTP listeners. Changing them binds the listeners again.
+const LISTENER_SETTINGS: &[&str] = &["tls.supergraph"];
+const LISTEN_KEY: &str = "listen";
+
+const REDACTED: &str = "[REDACTED]";
+const VARIABLE_PREFIX: &str = "${";
+
+/// Configuration difference endpoint
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ConfigDiff {
+    /// Expose an endpoint returning the differences between the running configuration and the
+    /// YAML configuration posted to it
+    pub(crate) enabled: bool,
+
+    /// The socket address and port to listen on
+    pub(crate) listen: ListenAddr,
+
+    /// The path of the endpoint
+    pub(crate) path: String,
+
+    /// Shared key expected in the `Authorization` header of the requests, required when the
+    /// endpoint is enabled
+    pub(crate) shared_key: String,
+}
+
+impl Default for ConfigDiff {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: SocketAddr::from_str("127.0.0.1:8088").unwrap().into(),
+            path: "/config/diff".to_string(),
+            shared_key: String::new(),
+        }
+    }
+}
+
+/// What the router rebuilds when reloading with a changed setting, from the least to the most
+/// disruptive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReloadImpact {
+    /// The request pipeline is recreated, while the query planners, caches and client
+    /// connections are kept
+    InPlace,
+    /// The query planners are recreated, and the cached query plans are not reused
+    QueryPlanner,
+    /// The HTTP listeners are bound again
+    Listeners,
+}
+
+impl ReloadImpact {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReloadImpact::InPlace => "in-place update",
+            ReloadImpact::QueryPlanner => "query planner rebuild",
+            ReloadImpact::Listeners => "listener rebuild",
+        }
+    }
+
+    fn of(path: &str) -> Self {
+        let matches = |setting: &&str| {
+            path == *setting
+                || path
+                    .strip_prefix(*setting)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        };
+        if path.split('.').any(|key| key == LISTEN_KEY) || LISTENER_SETTINGS.iter().any(matches) {
+            ReloadImpact::Listeners
+        } else if QUERY_PLANNER_SETTINGS.iter().any(matches) {
+            ReloadImpact::QueryPlanner
+        } else {
+            ReloadImpact::InPlace
+        }
+    }
+}
+
+/// A setting with a different effective value in two configurations
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct ConfigurationChange {
+    /// Dotted path of the setting, like `supergraph.listen`
+    pub(crate) path: String,
+    /// Value in the old configuration, if it was set
+    pub(crate) old: Option<Value>,
+    /// Value in the new configuration, if it is set
+    pub(crate) new: Option<Value>,
+    pub(crate) impact: ReloadImpact,
+}
+
+/// Adds the leaf values of a configuration, by dotted path. Lists are compared as a whole.
+fn flatten(prefix: &str, value: Value, settings: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                let path = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&path, value, settings);
+            }
+        }
+        value => {
+            settings.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+fn settings(configuration: &Configuration) -> Result<BTreeMap<String, Value>, ConfigurationError> {
+    let value = serde_json::to_value(configuration).map_err(|e| {
+        ConfigurationError::InvalidConfiguration {
+            message: "failed to serialize the configuration",
+            error: e.to_string(),
+        }
+    })?;
+    let mut settings = BTreeMap::new();
+    flatten("", value, &mut settings);
+    Ok(settings)
+}
+
+/// Returns the paths of the settings whose values reference variables, before their expansion.
+fn variable_settings(configuration: &Configuration) -> BTreeSet<String> {
+    let mut settings = BTreeMap::new();
+    if let Some(yaml) = &configuration.unexpanded_yaml {
+        flatten("", yaml.clone(), &mut settings);
+    }
+    settings
+        .into_iter()
+        .filter(|(_, value)| value.to_string().contains(VARIABLE_PREFIX))
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Replaces a value with a placeholder if it overlaps a setting referencing variables.
+fn redact(path: &str, value: Value, variable_settings: &BTreeSet<String>) -> Value {
+    let overlaps = |setting: &String| {
+        let (shorter, longer) = if setting.len() < path.len() {
+            (setting.as_str(), path)
+        } else {
+            (path, setting.as_str())
+        };
+        longer
+            .strip_prefix(shorter)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    };
+    if variable_settings.iter().any(overlaps) {
+        Value::String(REDACTED.to_string())
+    } else {
+        value
+    }
+}
+
+/// Returns the settings with a different effective value in the two configurations, sorted by
+/// path. The values of the settings referencing variables are redacted.
+pub(crate) fn diff(
+    old: &Configuration,
+    new: &Configuration,
+) -> Result<Vec<ConfigurationChange>, ConfigurationError> {
+    let (old_variables, new_variables) = (variable_settings(old), variable_settings(new));
+    let mut old = settings(old)?;
+    let new = settings(new)?;
+
+    let mut changes = Vec::new();
+    for (path, new_value) in new {
+        let old_value = old.remove(&path);
+        if old_value.as_ref() != Some(&new_value) {
+            changes.push(ConfigurationChange {
+                impact: ReloadImpact::of(&path),
+                old: old_value.map(|value| redact(&path, value, &old_variables)),
+                new: Some(redact(&path, new_value, &new_variables)),
+                path,
+            });
+        }
+    }
+    for (path, old_value) in old {
+        changes.push(ConfigurationChange {
+            impact: ReloadImpact::of(&path),
+            old: Some(redact(&path, old_value, &old_variables)),
+            path,
+            new: None,
+        });
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+/// Prints the differences between the effective settings of two YAML configurations.
+pub(crate) fn generate_diff(old: &str, new: &str) -> Result<String, ConfigurationError> {
+    let changes = diff(
+        &Configuration::from_str(old)?,
+        &Configuration::from_str(new)?,
+    )?;
+
+    let mut output = String::new();
+    for change in &changes {
+        let line = match (&change.old, &change.new) {
+            (Some(old), Some(new)) => format!("~ {}: {old} -> {new}", change.path),
+            (None, Some(new)) => format!("+ {}: {new}", change.path),
+            (Some(old), None) => format!("- {}: {old}", change.path),
+            (None, None) => continue,
+        };
+        writeln!(output, "{line} ({})", change.impact.as_str()).expect("write will never fail");
+    }
+    match changes.iter().map(|change| change.impact).max() {
+        Some(impact) => write!(output, "reload: {}", impact.as_str()),
+        None => write!(output, "no changes"),
+    }
+    .expect("write will never fail");
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn classifies_changes() {
+        let old = r#"
+supergraph:
+  listen: 127.0.0.1:4000
+  defer_support: true
+cors:
+  allow_any_origin: false
+"#;
+        let new = r#"
+supergraph:
+  listen: 0.0.0.0:4000
+  defer_support: false
+cors:
+  allow_any_origin: true
+include_subgraph_errors:
+  all: true
+"#;
+        let changes = diff(
+            &Configuration::from_str(old).unwrap(),
+            &Configuration::from_str(new).unwrap(),
+        )
+        .unwrap();
+        let changes = changes
+            .into_iter()
+            .map(|change| (change.path, change.old, change.new, change.impact))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "cors.allow_any_origin".to_string(),
+                    Some(json!(false)),
+                    Some(json!(true)),
+                    ReloadImpact::InPlace
+                ),
+                (
+                    "include_subgraph_errors.all".to_string(),
+                    None,
+                    Some(json!(true)),
+                    ReloadImpact::InPlace
+                ),
+                (
+                    "supergraph.defer_support".to_string(),
+                    Some(json!(true)),
+                    Some(json!(false)),
+                    ReloadImpact::QueryPlanner
+                ),
+                (
+                    "supergraph.listen".to_string(),
+                    Some(json!("127.0.0.1:4000")),
+                    Some(json!("0.0.0.0:4000")),
+                    ReloadImpact::Listeners
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn redacts_expanded_variables() {
+        let old = r#"
+supergraph:
+  path: ${env.CONFIG_DIFF_TEST_UNSET:-/graphql}
+"#;
+        let new = r#"
+supergraph:
+  path: /api
+"#;
+        let output = generate_diff(old, new).unwrap();
+        assert!(!output.contains("/graphql"), "{output}");
+        assert!(output.contains(r#"~ supergraph.path: "[REDACTED]" -> "/api""#));
+    }
+
+    #[test]
+    fn defaults_are_not_changes() {
+        let output = generate_diff("", "supergraph:\n  listen: 127.0.0.1:4000\n").unwrap();
+        assert_eq!(output, "no changes");
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::Value;
+
+use super::ApolloMetricsGenerationMode;
+use super::Configuration;
+use super::QueryPlannerMode;
+use crate::graphql;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
+
+/// Dual execution configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct DualExecution {
+    /// Execute a sample of the queries against a staged supergraph or query planner as well,
+    /// and compare the responses
+    pub(crate) enabled: bool,
+
+    /// Path to the supergraph schema of the staged version (defaults to the active supergraph)
+    pub(crate) supergraph_path: Option<PathBuf>,
+
+    /// Query planner implementation of the staged version (defaults to the active one)
+    pub(crate) query_planner_mode: Option<QueryPlannerMode>,
+
+    /// Fraction of the queries executed against both versions, between 0 and 1
+    pub(crate) sampling: f64,
+}
+
+impl Default for DualExecution {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            supergraph_path: None,
+            query_planner_mode: None,
+            sampling: 0.01,
+        }
+    }
+}
+
+impl DualExecution {
+    /// Returns `true` if a request should be executed against the staged version as well.
+    pub(crate) fn sample(&self) -> bool {
+        self.enabled && rand::thread_rng().gen_bool(self.sampling)
+    }
+
+    /// Returns the configuration of the staged version.
+    pub(crate) fn staged_configuration(&self, configuration: &Configuration) -> Configuration {
+        let mut staged = configuration.clone();
+        staged.dual_execution.enabled = false;
+        if let Some(query_planner_mode) = &self.query_planner_mode {
+            staged.experimental_query_planner_mode = query_planner_mode.clone();
+            // the new query planner cannot generate usage reports the legacy way
+            if *query_planner_mode == QueryPlannerMode::New {
+                staged.experimental_apollo_metrics_generation_mode =
+                    ApolloMetricsGenerationMode::New;
+            }
+        }
+        staged
+    }
+}
+
+/// Returns the paths of the fields whose values differ between two responses.
+///
+/// List indexes are replaced with `@`, so that a mismatch repeated in every item of a list is
+/// reported once. Differing errors are reported under the `errors` path, and the fields they
+/// nulled are not reported again.
+pub(crate) fn mismatched_fields(
+    active: &graphql::Response,
+    staged: &graphql::Response,
+) -> BTreeSet<String> {
+    let mut mismatches = BTreeSet::new();
+    let null = Value::Null;
+    diff(
+        active.data.as_ref().unwrap_or(&null),
+        staged.data.as_ref().unwrap_or(&null),
+        "data",
+        &mut mismatches,
+    );
+
+    let errors = |response: &graphql::Response| -> BTreeSet<(String, Option<String>)> {
+        response
+            .errors
+            .iter()
+            .map(|error| (error.message.clone(), error.path.as_ref().map(normalize)))
+            .collect()
+    };
+    let (active_errors, staged_errors) = (errors(active), errors(staged));
+    let error_paths: Vec<_> = active_errors
+        .symmetric_difference(&staged_errors)
+        .map(|(_, path)| path)
+        .collect();
+    if !error_paths.is_empty() {
+        mismatches.retain(|field| {
+            !error_paths.iter().flatten().any(|path| {
+                field == path
+                    || field
+                        .strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+        });
+        mismatches.insert("errors".to_string());
+    }
+    mismatches
+}
+
+/// Returns the path of an error in the format of the mismatched fields.
+fn normalize(path: &Path) -> String {
+    let mut normalized = "data".to_string();
+    for element in &path.0 {
+        match element {
+            PathElement::Key(key, _) => {
+                normalized.push('.');
+                normalized.push_str(key);
+            }
+            PathElement::Index(_) | PathElement::Flatten(_) => normalized.push_str(".@"),
+            PathElement::Fragment(_) => {}
+        }
+    }
+    normalized
+}
+
+fn diff(active: &Value, staged: &Value, path: &str, mismatches: &mut BTreeSet<String>) {
+    match (active, staged) {
+        (Value::Object(active), Value::Object(staged)) => {
+            for (key, active_value) in active.iter() {
+                let path = format!("{path}.{}", key.as_str());
+                match staged.get(key) {
+                    Some(staged_value) => diff(active_value, staged_value, &path, mismatches),
+                    None => {
+                        mismatches.insert(path);
+                    }
+                }
+            }
+            for key in staged.keys() {
+                if !active.contains_key(key) {
+                    mismatches.insert(format!("{path}.{}", key.as_str()));
+                }
+            }
+        }
+        (Value::Array(active), Value::Array(staged)) if active.len() == staged.len() => {
+            let path = format!("{path}.@");
+            for (active_value, staged_value) in active.iter().zip(staged) {
+                diff(active_value, staged_value, &path, mismatches);
+            }
+        }
+        (active, staged) => {
+            if active != staged {
+                mismatches.insert(path.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    #[test]
+    fn field_level_mismatches() {
+        let active = graphql::Response::builder()
+            .data(json!({
+                "me": {
+                    "name": "Ada",
+                    "reviews": [{ "body": "great" }, { "body": "good" }],
+                    "friends": [{ "id": 1 }]
+                }
+            }))
+            .build();
+        let staged = graphql::Response::builder()
+            .data(json!({
+                "me": {
+                    "name": "Ada",
+                    "reviews": [{ "body": "great!" }, { "body": "good!" }],
+                    "friends": [],
+                    "age": 36
+                }
+            }))
+            .error(
+                graphql::Error::builder()
+                    .message("oops")
+                    .extension_code("ERR")
+                    .build(),
+            )
+            .build();
+
+        assert_eq!(
+            mismatched_fields(&active, &staged),
+            BTreeSet::from([
+                "data.me.age".to_string(),
+                "data.me.friends".to_string(),
+                "data.me.reviews.@.body".to_string(),
+                "errors".to_string(),
+            ])
+        );
+        assert!(mismatched_fields(&active, &active).is_empty());
+    }
+
+    #[test]
+    fn fields_nulled_by_errors_are_reported_once() {
+        let active = graphql::Response::builder()
+            .data(json!({ "me": { "name": "Ada", "reviews": [{ "body": "great" }] } }))
+            .build();
+        let staged = graphql::Response::builder()
+            .data(json!({ "me": { "name": "Ada", "reviews": [{ "body": null }] } }))
+            .error(
+                graphql::Error::builder()
+                    .message("oops")
+                    .path(Path::from("me/reviews/0/body"))
+                    .extension_code("ERR")
+                    .build(),
+            )
+            .build();
+
+        assert_eq!(
+            mismatched_fields(&active, &staged),
+            BTreeSet::from(["errors".to_string()])
+        );
+    }
+
+    #[test]
+    fn staged_configuration() {
+        let configuration = Configuration::fake_builder()
+            .dual_execution(DualExecution {
+                enabled: true,
+                query_planner_mode: Some(QueryPlannerMode::New),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let staged = configuration
+            .dual_execution
+            .staged_configuration(&configuration);
+
+        assert!(!staged.dual_execution.enabled);
+        assert_eq!(
+            staged.experimental_query_planner_mode,
+            QueryPlannerMode::New
+        );
+        assert_eq!(
+            staged.experimental_apollo_metrics_generation_mode,
+            ApolloMetricsGenerationMode::New
+        );
+    }
+}
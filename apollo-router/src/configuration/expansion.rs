@@ -1,5 +1,6 @@
 //! Environment variable expansion in the configuration file
 
+use std::collections::HashMap;
 use std::env;
 use std::env::VarError;
 use std::fs;
@@ -17,6 +18,8 @@ pub(crate) struct Expansion {
     prefix: Option<String>,
     supported_modes: Vec<String>,
     override_configs: Vec<Override>,
+    /// Values of the secrets referenced by the configuration, keyed by variable name.
+    secrets: HashMap<String, String>,
 }
 
 #[derive(buildstructor::Builder, Clone)]
@@ -73,7 +76,7 @@ impl Expansion {
 
         let supported_expansion_modes = match env::var("APOLLO_ROUTER_CONFIG_SUPPORTED_MODES") {
             Ok(v) => v,
-            Err(VarError::NotPresent) => "env,file".to_string(),
+            Err(VarError::NotPresent) => "env,file,secret".to_string(),
             Err(VarError::NotUnicode(_)) => Err(ConfigurationError::InvalidExpansionModeConfig)?,
         };
         let supported_modes = supported_expansion_modes
@@ -125,6 +128,12 @@ impl Expansion {
             .build())
     }
 
+    /// Expand `${secret.*}` variables with the given secret values.
+    pub(crate) fn with_secrets(mut self, secrets: HashMap<String, String>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
     fn prefix_from_env() -> Result<Option<String>, ConfigurationError> {
         // APOLLO_ROUTER_CONFIG_ENV_PREFIX and APOLLO_ROUTER_CONFIG_SUPPORTED_MODES are unsupported and may change in future.
         // If you need this functionality then raise an issue and we can look to promoting this to official support.
@@ -193,17 +202,48 @@ impl Expansion {
                     return Ok(None);
                 }
 
-                return fs::read_to_string(key).map(Some).map_err(|cause| {
+                let fragment = fs::read_to_string(key).map_err(|cause| {
                     ConfigurationError::CannotExpandVariable {
                         key: key.to_string(),
                         cause: format!("{cause}"),
                     }
-                });
+                })?;
+                return self.expand_fragment_secrets(&fragment).map(Some);
+            }
+            if key.starts_with("secret.") {
+                return self.expand_secret(key).map(Some);
             }
             Err(ConfigurationError::InvalidExpansionModeConfig)
         }
     }
 
+    fn expand_secret(&self, key: &str) -> Result<String, ConfigurationError> {
+        match self.secrets.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => Err(ConfigurationError::CannotExpandVariable {
+                key: key.to_string(),
+                cause: "the secret was not fetched".to_string(),
+            }),
+        }
+    }
+
+    /// Expand the `${secret.*}` variables of a file included with a `${file.*}` variable. Other
+    /// variables of the file are left as is.
+    fn expand_fragment_secrets(&self, fragment: &str) -> Result<String, ConfigurationError> {
+        if !self.supported_modes.iter().any(|mode| mode == "secret") {
+            return Ok(fragment.to_string());
+        }
+        shellexpand::env_with_context(fragment, |key: &str| {
+            if key.starts_with("secret.") {
+                self.expand_secret(key).map(Some)
+            } else {
+                Ok(None)
+            }
+        })
+        .map(|expanded| expanded.into_owned())
+        .map_err(|e| e.cause)
+    }
+
     pub(crate) fn expand_env(&self, key: &str) -> Result<Option<String>, ConfigurationError> {
         match self.prefix.as_ref() {
             None => env::var(key),
@@ -0,0 +1,421 @@
+//! Notifications of the lifecycle events of the router.
+//!
+//! Operational events, like the reloads of the schema, the losses of the connection to Uplink,
+//! the changes of the license state and the transitions of the circuit breakers, are sent as
+//! JSON documents to webhooks and Amazon SNS topics, so that on-call tooling does not need to
+//! watch the logs. The body of the webhook requests is signed with an HMAC.
+//!
+//! The events are queued and sent by a background task: sending them never blocks the router,
+//! and the events are dropped when the queue is full.
+
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use arc_swap::ArcSwapOption;
+use aws_config::default_provider::credentials::DefaultCredentialsChain;
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sigv4::http_request::sign;
+use aws_sigv4::http_request::SignableBody;
+use aws_sigv4::http_request::SignableRequest;
+use aws_sigv4::http_request::SigningSettings;
+use hmac::Hmac;
+use hmac::Mac;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::sync::OnceCell;
+use tower::BoxError;
+
+use crate::plugins::traffic_shaping::circuit_breaker::CircuitState;
+
+type HmacSha256 = Hmac<sha2::Sha256>;
+
+/// Header carrying the signature of the body of the webhook requests
+pub(crate) const SIGNATURE_HEADER: &str = "x-router-signature";
+const SNS_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+/// Number of events waiting to be sent, the next ones are dropped
+const QUEUE_CAPACITY: usize = 128;
+
+/// The notifier of the running configuration
+static NOTIFIER: Lazy<ArcSwapOption<Notifier>> = Lazy::new(ArcSwapOption::empty);
+
+/// Lifecycle notifications configuration
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct LifecycleNotifications {
+    /// Webhooks receiving the events as signed JSON `POST` requests
+    pub(crate) webhooks: Vec<Webhook>,
+
+    /// Amazon SNS topics the events are published to. The AWS credentials are read from the
+    /// environment
+    pub(crate) sns_topics: Vec<SnsTopic>,
+
+    /// Timeout of the delivery of an event to a webhook or a topic (default: 5s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(crate) timeout: Duration,
+}
+
+impl Default for LifecycleNotifications {
+    fn default() -> Self {
+        Self {
+            webhooks: Vec::new(),
+            sns_topics: Vec::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A webhook receiving events
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Webhook {
+    /// URL of the webhook
+    #[schemars(with = "String")]
+    pub(crate) url: url::Url,
+
+    /// Key of the HMAC-SHA256 signature of the request body, sent in the `x-router-signature`
+    /// header as `sha256=<hex digest>`. Requests are not signed without a key
+    #[serde(default)]
+    pub(crate) secret: Option<String>,
+
+    /// Events sent to the webhook (default: all)
+    #[serde(default)]
+    pub(crate) events: Vec<LifecycleEventKind>,
+}
+
+/// An Amazon SNS topic events are published to
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SnsTopic {
+    /// ARN of the topic, like `arn:aws:sns:us-east-1:123456789012:router-events`
+    pub(crate) topic_arn: String,
+
+    /// Events published to the topic (default: all)
+    #[serde(default)]
+    pub(crate) events: Vec<LifecycleEventKind>,
+}
+
+/// A kind of lifecycle event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LifecycleEventKind {
+    /// The router reloaded with a new schema
+    SchemaReloaded,
+    /// The router could not apply a new schema, configuration or license
+    ReloadFailed,
+    /// Uplink could not be reached, after it was reachable or since the router started
+    UplinkUnavailable,
+    /// Uplink could be reached again
+    UplinkRestored,
+    /// The state of the license changed, for example when it expires
+    LicenseChanged,
+    /// The circuit breaker of a subgraph opened, closed or became half open
+    CircuitBreaker,
+}
+
+/// A lifecycle event
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum LifecycleEvent {
+    SchemaReloaded {
+        schema_id: String,
+    },
+    ReloadFailed {
+        error: String,
+    },
+    UplinkUnavailable {
+        query: String,
+        error: String,
+    },
+    UplinkRestored {
+        query: String,
+    },
+    LicenseChanged {
+        state: String,
+    },
+    CircuitBreaker {
+        subgraph: String,
+        state: CircuitState,
+    },
+}
+
+impl LifecycleEvent {
+    fn kind(&self) -> LifecycleEventKind {
+        match self {
+            LifecycleEvent::SchemaReloaded { .. } => LifecycleEventKind::SchemaReloaded,
+            LifecycleEvent::ReloadFailed { .. } => LifecycleEventKind::ReloadFailed,
+            LifecycleEvent::UplinkUnavailable { .. } => LifecycleEventKind::UplinkUnavailable,
+            LifecycleEvent::UplinkRestored { .. } => LifecycleEventKind::UplinkRestored,
+            LifecycleEvent::LicenseChanged { .. } => LifecycleEventKind::LicenseChanged,
+            LifecycleEvent::CircuitBreaker { .. } => LifecycleEventKind::CircuitBreaker,
+        }
+    }
+}
+
+/// The JSON document sent for an event
+#[derive(Serialize)]
+struct Envelope<'a> {
+    #[serde(flatten)]
+    event: &'a LifecycleEvent,
+    timestamp: String,
+    router_version: &'static str,
+}
+
+fn subscribed(events: &[LifecycleEventKind], kind: LifecycleEventKind) -> bool {
+    events.is_empty() || events.contains(&kind)
+}
+
+/// Returns the value of the signature header of a webhook request body
+pub(crate) fn signature(secret: &str, body: &[u8]) -> Result<String, BoxError> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())?;
+    mac.update(body);
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+/// Sends the events to the webhooks and topics of a configuration
+struct Notifier {
+    config: LifecycleNotifications,
+    sender: mpsc::Sender<LifecycleEvent>,
+}
+
+/// Delivers the events, in the background task of a notifier
+struct Deliverer {
+    config: LifecycleNotifications,
+    client: reqwest::Client,
+    credentials: OnceCell<DefaultCredentialsChain>,
+}
+
+impl Deliverer {
+    fn new(config: LifecycleNotifications) -> Result<Self, BoxError> {
+        let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self {
+            config,
+            client,
+            credentials: OnceCell::new(),
+        })
+    }
+
+    async fn deliver(&self, event: &LifecycleEvent) {
+        let body = match serde_json::to_vec(&Envelope {
+            event,
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            router_version: std::env!("CARGO_PKG_VERSION"),
+        }) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("could not serialize the lifecycle event: {e}");
+                return;
+            }
+        };
+
+        for webhook in &self.config.webhooks {
+            if subscribed(&webhook.events, event.kind()) {
+                if let Err(e) = self.send_to_webhook(webhook, &body).await {
+                    tracing::warn!(
+                        url = %webhook.url,
+                        "could not send the lifecycle event to the webhook: {e}"
+                    );
+                }
+            }
+        }
+        for topic in &self.config.sns_topics {
+            if subscribed(&topic.events, event.kind()) {
+                if let Err(e) = self.publish_to_topic(topic, &body).await {
+                    tracing::warn!(
+                        topic_arn = %topic.topic_arn,
+                        "could not publish the lifecycle event to the topic: {e}"
+                    );
+                }
+            }
+        }
+    }
+
+    async fn send_to_webhook(&self, webhook: &Webhook, body: &[u8]) -> Result<(), BoxError> {
+        let mut request = self
+            .client
+            .post(webhook.url.clone())
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+        if let Some(secret) = &webhook.secret {
+            request = request.header(SIGNATURE_HEADER, signature(secret, body)?);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn publish_to_topic(&self, topic: &SnsTopic, body: &[u8]) -> Result<(), BoxError> {
+        let region = topic
+            .topic_arn
+            .split(':')
+            .nth(3)
+            .filter(|region| !region.is_empty())
+            .ok_or("invalid topic ARN")?;
+        let url = std::env::var("AWS_ENDPOINT_URL_SNS")
+            .or_else(|_| std::env::var("AWS_ENDPOINT_URL"))
+            .unwrap_or_else(|_| format!("https://sns.{region}.amazonaws.com"));
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("Action", "Publish")
+            .append_pair("Version", "2010-03-31")
+            .append_pair("TopicArn", &topic.topic_arn)
+            .append_pair("Message", std::str::from_utf8(body)?)
+            .finish()
+            .into_bytes();
+
+        let identity = self
+            .credentials
+            .get_or_init(|| DefaultCredentialsChain::builder().build())
+            .await
+            .provide_credentials()
+            .await?
+            .into();
+        let signing_params = aws_sigv4::sign::v4::SigningParams::builder()
+            .identity(&identity)
+            .region(region)
+            .name("sns")
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()?;
+        let headers = [("content-type", SNS_CONTENT_TYPE)];
+        let signable_request = SignableRequest::new(
+            "POST",
+            url.as_str(),
+            headers.into_iter(),
+            SignableBody::Bytes(&body),
+        )?;
+        let (instructions, _signature) =
+            sign(signable_request, &signing_params.into())?.into_parts();
+
+        let mut request = self.client.post(url.as_str()).body(body.clone());
+        for (name, value) in headers.into_iter().chain(instructions.headers()) {
+            request = request.header(name, value);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Applies the notifications configuration of the running router. The notifier is kept if the
+/// configuration did not change
+pub(crate) fn configure(config: &LifecycleNotifications) {
+    if NOTIFIER
+        .load()
+        .as_ref()
+        .is_some_and(|notifier| notifier.config == *config)
+    {
+        return;
+    }
+    if config.webhooks.is_empty() && config.sns_topics.is_empty() {
+        NOTIFIER.store(None);
+        return;
+    }
+
+    let deliverer = match Deliverer::new(config.clone()) {
+        Ok(deliverer) => deliverer,
+        Err(e) => {
+            tracing::error!("could not create the lifecycle notifications client: {e}");
+            NOTIFIER.store(None);
+            return;
+        }
+    };
+    let (sender, mut receiver) = mpsc::channel::<LifecycleEvent>(QUEUE_CAPACITY);
+    // the task stops when the notifier is replaced and the sender dropped
+    tokio::task::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            deliverer.deliver(&event).await;
+        }
+    });
+    NOTIFIER.store(Some(Arc::new(Notifier {
+        config: config.clone(),
+        sender,
+    })));
+}
+
+/// Queues a lifecycle event, if notifications are configured
+pub(crate) fn notify(event: LifecycleEvent) {
+    if let Some(notifier) = NOTIFIER.load().as_ref() {
+        if notifier.sender.try_send(event).is_err() {
+            tracing::warn!("the lifecycle notifications queue is full, dropping an event");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::body_partial_json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+
+    #[test]
+    fn events_serialization() {
+        let event = LifecycleEvent::CircuitBreaker {
+            subgraph: "products".to_string(),
+            state: CircuitState::Open,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({ "type": "circuit_breaker", "subgraph": "products", "state": "open" })
+        );
+        assert_eq!(
+            signature("secret", br#"{"type":"reload_failed"}"#).unwrap(),
+            "sha256=3e87287db94f7acc4522605dc4ee3ac35eb8d86620a9f1fd5ffd12df42d6f166"
+        );
+    }
+
+    #[tokio::test]
+    async fn webhooks_receive_their_events() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("content-type", "application/json"))
+            .and(body_partial_json(serde_json::json!({
+                "type": "uplink_unavailable",
+                "query": "SupergraphSdlQuery",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let deliverer = Deliverer::new(LifecycleNotifications {
+            webhooks: vec![
+                Webhook {
+                    url: server.uri().parse().unwrap(),
+                    secret: Some("secret".to_string()),
+                    events: vec![LifecycleEventKind::UplinkUnavailable],
+                },
+                // not subscribed to the event
+                Webhook {
+                    url: server.uri().parse().unwrap(),
+                    secret: None,
+                    events: vec![LifecycleEventKind::SchemaReloaded],
+                },
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        deliverer
+            .deliver(&LifecycleEvent::UplinkUnavailable {
+                query: "SupergraphSdlQuery".to_string(),
+                error: "connection refused".to_string(),
+            })
+            .await;
+
+        let requests = server.received_requests().await.unwrap();
+        let request = &requests[0];
+        assert_eq!(
+            request.headers.get(SIGNATURE_HEADER).unwrap(),
+            signature("secret", &request.body).unwrap().as_str()
+        );
+    }
+}
@@ -1,4 +1,5 @@
 //! Logic for loading configuration in to an object model
+use std::collections::HashMap;
 use std::fmt;
 use std::io;
 use std::io::BufReader;
@@ -6,17 +7,27 @@ use std::iter;
 use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use bytesize::ByteSize;
 use derivative::Derivative;
 use displaydoc::Display;
+use http::HeaderValue;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 pub(crate) use persisted_queries::PersistedQueries;
 #[cfg(test)]
 pub(crate) use persisted_queries::PersistedQueriesSafelist;
+pub(crate) use persisted_queries::PersistedQueriesSafelistEnforcement;
+pub(crate) use persisted_queries::PersistedQueriesStorage;
+pub(crate) use persisted_queries::PersistedQueriesStorageBackend;
+pub(crate) use persisted_queries::PersistedQueryRoute;
+#[cfg(test)]
+pub(crate) use persisted_queries::PersistedQueryRouteMethod;
+pub(crate) use persisted_queries::RouteSegment;
 use regex::Regex;
 use rustls::Certificate;
 use rustls::PrivateKey;
@@ -36,12 +47,27 @@ use serde_json::Map;
 use serde_json::Value;
 use thiserror::Error;
 
+pub(crate) use self::content_negotiation::ContentNegotiation;
+pub(crate) use self::contracts::Contracts;
 use self::cors::Cors;
+pub(crate) use self::diff::generate_diff;
+pub(crate) use self::diff::ConfigDiff;
+pub(crate) use self::dual_execution::DualExecution;
 use self::expansion::Expansion;
 pub(crate) use self::experimental::Discussed;
+pub(crate) use self::lifecycle_notifications::LifecycleNotifications;
+pub(crate) use self::plugin_execution::PluginExecution;
+pub(crate) use self::problem_details::ProblemDetails;
 pub(crate) use self::schema::generate_config_schema;
 pub(crate) use self::schema::generate_upgrade;
+pub(crate) use self::secrets::Secrets;
+pub(crate) use self::shared_state::SharedState;
+pub(crate) use self::spiffe::Spiffe;
 use self::subgraph::SubgraphConfiguration;
+use self::tls::CertificateResolver;
+use self::tls::TlsClientVerification;
+use self::tls::TlsSniCertificate;
+use self::tls::TlsVersion;
 use crate::cache::DEFAULT_CACHE_CAPACITY;
 use crate::configuration::schema::Mode;
 use crate::graphql;
@@ -52,19 +78,33 @@ use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN;
 use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN_NAME;
 use crate::plugins::telemetry::config::ApolloMetricsReferenceMode;
 use crate::plugins::telemetry::config::ApolloSignatureNormalizationAlgorithm;
+use crate::services::supergraph::variable_transforms::VariableTransforms;
+use crate::spec::custom_scalar::CustomScalarFormat;
 use crate::uplink::UplinkConfig;
 use crate::ApolloRouterError;
 
+pub(crate) mod content_negotiation;
+pub(crate) mod contracts;
 pub(crate) mod cors;
+pub(crate) mod diff;
+pub(crate) mod dual_execution;
 pub(crate) mod expansion;
 mod experimental;
+pub(crate) mod lifecycle_notifications;
 pub(crate) mod metrics;
+pub(crate) mod overlay;
 mod persisted_queries;
+pub(crate) mod plugin_execution;
+pub(crate) mod problem_details;
 mod schema;
+pub(crate) mod secrets;
 pub(crate) mod shared;
+pub(crate) mod shared_state;
+pub(crate) mod spiffe;
 pub(crate) mod subgraph;
 #[cfg(test)]
 mod tests;
+pub(crate) mod tls;
 mod upgrade;
 mod yaml;
 
@@ -121,6 +161,10 @@ pub struct Configuration {
     #[serde(skip)]
     pub(crate) validated_yaml: Option<Value>,
 
+    /// The configuration before the expansion of the variables it references.
+    #[serde(skip)]
+    pub(crate) unexpanded_yaml: Option<Value>,
+
     /// Health check configuration
     #[serde(default)]
     pub(crate) health_check: HealthCheck,
@@ -141,6 +185,14 @@ pub struct Configuration {
     #[serde(default)]
     pub(crate) cors: Cors,
 
+    /// Configures the format of the responses sent to clients
+    #[serde(default)]
+    pub(crate) content_negotiation: ContentNegotiation,
+
+    /// Configures the problem details of the requests rejected before GraphQL processing
+    #[serde(default)]
+    pub(crate) problem_details: ProblemDetails,
+
     #[serde(default)]
     pub(crate) tls: Tls,
 
@@ -152,6 +204,47 @@ pub struct Configuration {
     #[serde(default)]
     pub persisted_queries: PersistedQueries,
 
+    /// Configures contract variants served alongside the main supergraph
+    #[serde(default)]
+    pub(crate) contracts: Contracts,
+
+    /// Configures the execution of a sample of the queries against a staged supergraph or query
+    /// planner, to compare their responses with the active ones
+    #[serde(default)]
+    pub(crate) dual_execution: DualExecution,
+
+    /// Configures the endpoint returning the differences between the running configuration and
+    /// another one
+    #[serde(default)]
+    pub(crate) experimental_config_diff: ConfigDiff,
+
+    /// Configures the endpoint returning a support bundle of the running router
+    #[serde(default)]
+    pub(crate) experimental_support_bundle: SupportBundle,
+
+    /// Configures the refresh of secrets referenced by the configuration
+    #[serde(default)]
+    pub(crate) secrets: Secrets,
+
+    /// Configures the notifications of the lifecycle events of the router, like schema reloads
+    /// and circuit breaker transitions, to webhooks and Amazon SNS topics
+    #[serde(default)]
+    pub(crate) lifecycle_notifications: LifecycleNotifications,
+
+    /// Configures the state shared by the router replicas, to enforce the rate limits and the
+    /// subscription limits across all the replicas
+    #[serde(default)]
+    pub(crate) shared_state: SharedState,
+
+    /// Configures the execution order of the Rhai script, the coprocessor and the user plugins,
+    /// and the requests skipping some plugins
+    #[serde(default)]
+    pub(crate) plugin_execution: PluginExecution,
+
+    /// Configures the containment of the panics happening while executing a request
+    #[serde(default)]
+    pub(crate) experimental_panic_containment: PanicContainment,
+
     /// Configuration for operation limits, parser limits, HTTP limits, etc.
     #[serde(default)]
     pub(crate) limits: Limits,
@@ -188,10 +281,6 @@ pub struct Configuration {
     /// Batching configuration.
     #[serde(default)]
     pub(crate) batching: Batching,
-
-    /// Type conditioned fetching configuration.
-    #[serde(default)]
-    pub(crate) experimental_type_conditioned_fetching: bool,
 }
 
 impl PartialEq for Configuration {
@@ -245,16 +334,26 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             homepage: Homepage,
             supergraph: Supergraph,
             cors: Cors,
+            content_negotiation: ContentNegotiation,
+            problem_details: ProblemDetails,
             plugins: UserPlugins,
             #[serde(flatten)]
             apollo_plugins: ApolloPlugins,
             tls: Tls,
             apq: Apq,
             persisted_queries: PersistedQueries,
+            contracts: Contracts,
+            dual_execution: DualExecution,
+            experimental_config_diff: ConfigDiff,
+            experimental_support_bundle: SupportBundle,
+            secrets: Secrets,
+            lifecycle_notifications: LifecycleNotifications,
+            shared_state: SharedState,
+            plugin_execution: PluginExecution,
+            experimental_panic_containment: PanicContainment,
             limits: Limits,
             experimental_chaos: Chaos,
             batching: Batching,
-            experimental_type_conditioned_fetching: bool,
             experimental_apollo_metrics_generation_mode: ApolloMetricsGenerationMode,
             experimental_query_planner_mode: QueryPlannerMode,
         }
@@ -270,14 +369,24 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             homepage: ad_hoc.homepage,
             supergraph: ad_hoc.supergraph,
             cors: ad_hoc.cors,
+            content_negotiation: ad_hoc.content_negotiation,
+            problem_details: ad_hoc.problem_details,
             tls: ad_hoc.tls,
             apq: ad_hoc.apq,
             persisted_queries: ad_hoc.persisted_queries,
+            contracts: ad_hoc.contracts,
+            dual_execution: ad_hoc.dual_execution,
+            experimental_config_diff: ad_hoc.experimental_config_diff,
+            experimental_support_bundle: ad_hoc.experimental_support_bundle,
+            secrets: ad_hoc.secrets,
+            lifecycle_notifications: ad_hoc.lifecycle_notifications,
+            shared_state: ad_hoc.shared_state,
+            plugin_execution: ad_hoc.plugin_execution,
+            experimental_panic_containment: ad_hoc.experimental_panic_containment,
             limits: ad_hoc.limits,
             experimental_chaos: ad_hoc.experimental_chaos,
             experimental_apollo_metrics_generation_mode: ad_hoc
                 .experimental_apollo_metrics_generation_mode,
-            experimental_type_conditioned_fetching: ad_hoc.experimental_type_conditioned_fetching,
             experimental_query_planner_mode: ad_hoc.experimental_query_planner_mode,
             plugins: ad_hoc.plugins,
             apollo_plugins: ad_hoc.apollo_plugins,
@@ -287,6 +396,7 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             notify,
             uplink: None,
             validated_yaml: None,
+            unexpanded_yaml: None,
         }
         .validate()
         .map_err(|e| serde::de::Error::custom(e.to_string()))
@@ -314,15 +424,25 @@ impl Configuration {
         sandbox: Option<Sandbox>,
         homepage: Option<Homepage>,
         cors: Option<Cors>,
+        content_negotiation: Option<ContentNegotiation>,
+        problem_details: Option<ProblemDetails>,
         plugins: Map<String, Value>,
         apollo_plugins: Map<String, Value>,
         tls: Option<Tls>,
         apq: Option<Apq>,
         persisted_query: Option<PersistedQueries>,
+        contracts: Option<Contracts>,
+        dual_execution: Option<DualExecution>,
+        experimental_config_diff: Option<ConfigDiff>,
+        experimental_support_bundle: Option<SupportBundle>,
+        secrets: Option<Secrets>,
+        lifecycle_notifications: Option<LifecycleNotifications>,
+        shared_state: Option<SharedState>,
+        plugin_execution: Option<PluginExecution>,
+        experimental_panic_containment: Option<PanicContainment>,
         operation_limits: Option<Limits>,
         chaos: Option<Chaos>,
         uplink: Option<UplinkConfig>,
-        experimental_type_conditioned_fetching: Option<bool>,
         batching: Option<Batching>,
         experimental_apollo_metrics_generation_mode: Option<ApolloMetricsGenerationMode>,
         experimental_query_planner_mode: Option<QueryPlannerMode>,
@@ -331,13 +451,25 @@ impl Configuration {
 
         let conf = Self {
             validated_yaml: Default::default(),
+            unexpanded_yaml: Default::default(),
             supergraph: supergraph.unwrap_or_default(),
             health_check: health_check.unwrap_or_default(),
             sandbox: sandbox.unwrap_or_default(),
             homepage: homepage.unwrap_or_default(),
             cors: cors.unwrap_or_default(),
+            content_negotiation: content_negotiation.unwrap_or_default(),
+            problem_details: problem_details.unwrap_or_default(),
             apq: apq.unwrap_or_default(),
             persisted_queries: persisted_query.unwrap_or_default(),
+            contracts: contracts.unwrap_or_default(),
+            dual_execution: dual_execution.unwrap_or_default(),
+            experimental_config_diff: experimental_config_diff.unwrap_or_default(),
+            experimental_support_bundle: experimental_support_bundle.unwrap_or_default(),
+            secrets: secrets.unwrap_or_default(),
+            lifecycle_notifications: lifecycle_notifications.unwrap_or_default(),
+            shared_state: shared_state.unwrap_or_default(),
+            plugin_execution: plugin_execution.unwrap_or_default(),
+            experimental_panic_containment: experimental_panic_containment.unwrap_or_default(),
             limits: operation_limits.unwrap_or_default(),
             experimental_chaos: chaos.unwrap_or_default(),
             experimental_apollo_metrics_generation_mode:
@@ -352,8 +484,6 @@ impl Configuration {
             tls: tls.unwrap_or_default(),
             uplink,
             batching: batching.unwrap_or_default(),
-            experimental_type_conditioned_fetching: experimental_type_conditioned_fetching
-                .unwrap_or_default(),
             notify,
         };
 
@@ -411,9 +541,22 @@ impl Configuration {
                     .or(Some(10000)),
                 paths_limit: self.supergraph.query_planning.experimental_paths_limit,
             }),
-            type_conditioned_fetching: self.experimental_type_conditioned_fetching,
+            type_conditioned_fetching: self
+                .supergraph
+                .query_planning
+                .type_conditioned_fetching
+                .enabled,
         }
     }
+
+    /// Whether operations can opt out of type conditioned fetching. Only the JavaScript query
+    /// planner implements type conditioned fetching.
+    pub(crate) fn type_conditioned_fetching_opt_out(&self) -> bool {
+        let type_conditioned_fetching = &self.supergraph.query_planning.type_conditioned_fetching;
+        type_conditioned_fetching.enabled
+            && type_conditioned_fetching.allow_opt_out
+            && self.experimental_query_planner_mode != QueryPlannerMode::New
+    }
 }
 
 impl Default for Configuration {
@@ -433,27 +576,40 @@ impl Configuration {
         sandbox: Option<Sandbox>,
         homepage: Option<Homepage>,
         cors: Option<Cors>,
+        content_negotiation: Option<ContentNegotiation>,
+        problem_details: Option<ProblemDetails>,
         plugins: Map<String, Value>,
         apollo_plugins: Map<String, Value>,
         tls: Option<Tls>,
         notify: Option<Notify<String, graphql::Response>>,
         apq: Option<Apq>,
         persisted_query: Option<PersistedQueries>,
+        contracts: Option<Contracts>,
+        dual_execution: Option<DualExecution>,
+        experimental_config_diff: Option<ConfigDiff>,
+        experimental_support_bundle: Option<SupportBundle>,
+        secrets: Option<Secrets>,
+        lifecycle_notifications: Option<LifecycleNotifications>,
+        shared_state: Option<SharedState>,
+        plugin_execution: Option<PluginExecution>,
+        experimental_panic_containment: Option<PanicContainment>,
         operation_limits: Option<Limits>,
         chaos: Option<Chaos>,
         uplink: Option<UplinkConfig>,
         batching: Option<Batching>,
-        experimental_type_conditioned_fetching: Option<bool>,
         experimental_apollo_metrics_generation_mode: Option<ApolloMetricsGenerationMode>,
         experimental_query_planner_mode: Option<QueryPlannerMode>,
     ) -> Result<Self, ConfigurationError> {
         let configuration = Self {
             validated_yaml: Default::default(),
+            unexpanded_yaml: Default::default(),
             supergraph: supergraph.unwrap_or_else(|| Supergraph::fake_builder().build()),
             health_check: health_check.unwrap_or_else(|| HealthCheck::fake_builder().build()),
             sandbox: sandbox.unwrap_or_else(|| Sandbox::fake_builder().build()),
             homepage: homepage.unwrap_or_else(|| Homepage::fake_builder().build()),
             cors: cors.unwrap_or_default(),
+            content_negotiation: content_negotiation.unwrap_or_default(),
+            problem_details: problem_details.unwrap_or_default(),
             limits: operation_limits.unwrap_or_default(),
             experimental_chaos: chaos.unwrap_or_default(),
             experimental_apollo_metrics_generation_mode:
@@ -469,9 +625,16 @@ impl Configuration {
             notify: notify.unwrap_or_default(),
             apq: apq.unwrap_or_default(),
             persisted_queries: persisted_query.unwrap_or_default(),
+            contracts: contracts.unwrap_or_default(),
+            dual_execution: dual_execution.unwrap_or_default(),
+            experimental_config_diff: experimental_config_diff.unwrap_or_default(),
+            experimental_support_bundle: experimental_support_bundle.unwrap_or_default(),
+            secrets: secrets.unwrap_or_default(),
+            lifecycle_notifications: lifecycle_notifications.unwrap_or_default(),
+            shared_state: shared_state.unwrap_or_default(),
+            plugin_execution: plugin_execution.unwrap_or_default(),
+            experimental_panic_containment: experimental_panic_containment.unwrap_or_default(),
             uplink,
-            experimental_type_conditioned_fetching: experimental_type_conditioned_fetching
-                .unwrap_or_default(),
             batching: batching.unwrap_or_default(),
         };
 
@@ -495,6 +658,14 @@ impl Configuration {
                 error: "sandbox needs introspection to be enabled".to_string(),
             });
         }
+        if let Some(content_security_policy) = &self.sandbox.content_security_policy {
+            if HeaderValue::from_str(content_security_policy).is_err() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'sandbox.content_security_policy' configuration",
+                    error: "the policy must be a valid header value".to_string(),
+                });
+            }
+        }
         if !self.supergraph.path.starts_with('/') {
             return Err(ConfigurationError::InvalidConfiguration {
             message: "invalid 'server.graphql_path' configuration",
@@ -529,6 +700,24 @@ impl Configuration {
             );
         }
 
+        if self.experimental_support_bundle.enabled
+            && self.experimental_support_bundle.shared_key.is_empty()
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'experimental_support_bundle' configuration",
+                error: "you must set a shared_key for the support bundle endpoint".to_string(),
+            });
+        }
+
+        if self.experimental_config_diff.enabled
+            && self.experimental_config_diff.shared_key.is_empty()
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'experimental_config_diff' configuration",
+                error: "you must set a shared_key for the configuration diff endpoint".to_string(),
+            });
+        }
+
         // PQs.
         if self.persisted_queries.enabled {
             if self.persisted_queries.safelist.enabled && self.apq.enabled {
@@ -543,6 +732,13 @@ impl Configuration {
                     message: "safelist must be enabled to require IDs",
                     error: "either set persisted_queries.safelist.enabled: true or persisted_queries.safelist.require_id: false in your router yaml configuration".into()
                 });
+            } else if self.persisted_queries.experimental_local_manifests.is_some()
+                && self.persisted_queries.experimental_storage.is_some()
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "persisted queries can be read from local manifests or from a storage, not both",
+                    error: "either remove persisted_queries.experimental_local_manifests or persisted_queries.experimental_storage in your router yaml configuration".into()
+                });
             }
         } else {
             // If the feature isn't enabled, sub-features shouldn't be.
@@ -556,6 +752,73 @@ impl Configuration {
                     message: "persisted queries must be enabled to enable logging unknown operations",
                     error: "either set persisted_queries.log_unknown: false or persisted_queries.enabled: true in your router yaml configuration".into()
                 });
+            } else if !self
+                .persisted_queries
+                .experimental_rest_endpoints
+                .routes
+                .is_empty()
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "persisted queries must be enabled to expose REST endpoints",
+                    error: "either remove persisted_queries.experimental_rest_endpoints.routes or set persisted_queries.enabled: true in your router yaml configuration".into()
+                });
+            } else if self
+                .persisted_queries
+                .experimental_fragment_manifests
+                .is_some()
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "persisted queries must be enabled to register fragments",
+                    error: "either remove persisted_queries.experimental_fragment_manifests or set persisted_queries.enabled: true in your router yaml configuration".into()
+                });
+            } else if self.persisted_queries.experimental_storage.is_some() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "persisted queries must be enabled to read them from a storage",
+                    error: "either remove persisted_queries.experimental_storage or set persisted_queries.enabled: true in your router yaml configuration".into()
+                });
+            }
+        }
+
+        if let Err(error) = self
+            .persisted_queries
+            .experimental_rest_endpoints
+            .validate(&self.supergraph.sanitized_path())
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid persisted query REST endpoint",
+                error,
+            });
+        }
+
+        if let Some(variant) = self.contracts.unknown_variant() {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "unknown contract variant",
+                error: format!("'{variant}' is not declared in contracts.variants"),
+            });
+        }
+
+        if !(0.0..=1.0).contains(&self.dual_execution.sampling) {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid dual execution sampling",
+                error: "dual_execution.sampling must be between 0 and 1".into(),
+            });
+        }
+
+        for topic in &self.lifecycle_notifications.sns_topics {
+            if !topic.topic_arn.starts_with("arn:")
+                || topic
+                    .topic_arn
+                    .split(':')
+                    .nth(3)
+                    .map_or(true, str::is_empty)
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid lifecycle notifications SNS topic",
+                    error: format!(
+                        "'{}' is not the ARN of a topic, like 'arn:aws:sns:us-east-1:123456789012:router-events'",
+                        topic.topic_arn
+                    ),
+                });
             }
         }
 
@@ -568,19 +831,27 @@ impl Configuration {
             });
         }
 
-        let apollo_telemetry_config = match self.apollo_plugins.plugins.get("telemetry") {
+        let telemetry_config = match self.apollo_plugins.plugins.get("telemetry") {
             Some(telemetry_config) => {
                 match serde_json::from_value::<crate::plugins::telemetry::config::Conf>(
                     telemetry_config.clone(),
                 ) {
-                    Ok(conf) => Some(conf.apollo),
+                    Ok(conf) => Some(conf),
                     _ => None,
                 }
             }
             _ => None,
         };
 
-        if let Some(config) = apollo_telemetry_config {
+        if let Some(conf) = telemetry_config {
+            if let Err(err) = conf.calculate_field_level_instrumentation_ratio() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid field level instrumentation sampling",
+                    error: err.to_string(),
+                });
+            }
+
+            let config = conf.apollo;
             if matches!(
                 config.experimental_apollo_signature_normalization_algorithm,
                 ApolloSignatureNormalizationAlgorithm::Enhanced
@@ -593,6 +864,18 @@ impl Configuration {
                 });
             }
 
+            if !config
+                .experimental_apollo_signature_customization
+                .is_empty()
+                && self.experimental_apollo_metrics_generation_mode
+                    != ApolloMetricsGenerationMode::New
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "`experimental_apollo_signature_customization` requires `experimental_apollo_metrics_generation_mode: new`",
+                    error: "either remove the signature customization, or change to new metrics generation".into()
+                });
+            }
+
             if matches!(
                 config.experimental_apollo_metrics_reference_mode,
                 ApolloMetricsReferenceMode::Extended
@@ -619,6 +902,21 @@ impl FromStr for Configuration {
     }
 }
 
+impl Configuration {
+    /// Parse the configuration, expanding the secrets it references with the fetched values.
+    pub(crate) fn from_str_with_secrets(
+        s: &str,
+        secrets: HashMap<String, String>,
+    ) -> Result<Self, ConfigurationError> {
+        schema::validate_yaml_configuration(
+            s,
+            Expansion::default()?.with_secrets(secrets),
+            Mode::Upgrade,
+        )?
+        .validate()
+    }
+}
+
 fn gen_schema(plugins: schemars::Map<String, Schema>) -> Schema {
     let plugins_object = SchemaObject {
         object: Some(Box::new(ObjectValidation {
@@ -735,6 +1033,30 @@ pub(crate) struct Supergraph {
     /// Log a message if the client closes the connection before the response is sent.
     /// Default: false.
     pub(crate) experimental_log_on_broken_pipe: bool,
+
+    /// Accept GraphQL operations over WebSocket connections on the GraphQL endpoint,
+    /// with the graphql-transport-ws and graphql-ws protocols.
+    /// Default: false.
+    pub(crate) experimental_websocket: bool,
+
+    /// Origins of the web pages allowed to open WebSocket connections, like
+    /// `https://studio.apollographql.com`. Upgrade requests with an `Origin` header that is not
+    /// in this list are rejected, so that other web pages cannot use the cookies of their visitors.
+    /// Default: []
+    pub(crate) experimental_websocket_allowed_origins: Vec<String>,
+
+    /// Maximum number of operations running at the same time on a WebSocket connection. The
+    /// operations sent over this limit are answered with an error.
+    /// Default: 100
+    pub(crate) experimental_websocket_max_operations: usize,
+
+    /// Formats of the custom scalars, by scalar name. Variables not matching the format of their
+    /// scalar are rejected, and the values in responses are normalized to the format
+    pub(crate) experimental_custom_scalars: HashMap<String, CustomScalarFormat>,
+
+    /// Transforms applied in order to the variables of operations once they are validated, by
+    /// operation name and variable name
+    pub(crate) experimental_variable_transforms: VariableTransforms,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
@@ -760,6 +1082,10 @@ fn default_defer_support() -> bool {
     true
 }
 
+fn default_websocket_max_operations() -> usize {
+    100
+}
+
 #[buildstructor::buildstructor]
 impl Supergraph {
     #[builder]
@@ -773,6 +1099,11 @@ impl Supergraph {
         generate_query_fragments: Option<bool>,
         early_cancel: Option<bool>,
         experimental_log_on_broken_pipe: Option<bool>,
+        experimental_websocket: Option<bool>,
+        experimental_websocket_allowed_origins: Option<Vec<String>>,
+        experimental_websocket_max_operations: Option<usize>,
+        experimental_custom_scalars: Option<HashMap<String, CustomScalarFormat>>,
+        experimental_variable_transforms: Option<VariableTransforms>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(default_graphql_listen),
@@ -792,6 +1123,14 @@ impl Supergraph {
             generate_query_fragments: generate_query_fragments.unwrap_or_default(),
             early_cancel: early_cancel.unwrap_or_default(),
             experimental_log_on_broken_pipe: experimental_log_on_broken_pipe.unwrap_or_default(),
+            experimental_websocket: experimental_websocket.unwrap_or_default(),
+            experimental_websocket_allowed_origins: experimental_websocket_allowed_origins
+                .unwrap_or_default(),
+            experimental_websocket_max_operations: experimental_websocket_max_operations
+                .unwrap_or_else(default_websocket_max_operations),
+            experimental_custom_scalars: experimental_custom_scalars.unwrap_or_default(),
+            experimental_variable_transforms: experimental_variable_transforms
+                .unwrap_or_default(),
         }
     }
 }
@@ -810,6 +1149,11 @@ impl Supergraph {
         generate_query_fragments: Option<bool>,
         early_cancel: Option<bool>,
         experimental_log_on_broken_pipe: Option<bool>,
+        experimental_websocket: Option<bool>,
+        experimental_websocket_allowed_origins: Option<Vec<String>>,
+        experimental_websocket_max_operations: Option<usize>,
+        experimental_custom_scalars: Option<HashMap<String, CustomScalarFormat>>,
+        experimental_variable_transforms: Option<VariableTransforms>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(test_listen),
@@ -829,6 +1173,14 @@ impl Supergraph {
             generate_query_fragments: generate_query_fragments.unwrap_or_default(),
             early_cancel: early_cancel.unwrap_or_default(),
             experimental_log_on_broken_pipe: experimental_log_on_broken_pipe.unwrap_or_default(),
+            experimental_websocket: experimental_websocket.unwrap_or_default(),
+            experimental_websocket_allowed_origins: experimental_websocket_allowed_origins
+                .unwrap_or_default(),
+            experimental_websocket_max_operations: experimental_websocket_max_operations
+                .unwrap_or_else(default_websocket_max_operations),
+            experimental_custom_scalars: experimental_custom_scalars.unwrap_or_default(),
+            experimental_variable_transforms: experimental_variable_transforms
+                .unwrap_or_default(),
         }
     }
 }
@@ -889,20 +1241,17 @@ pub(crate) struct Limits {
     /// are rejected with a HTTP 400 Bad Request response and GraphQL error with
     /// `"extensions": {"code": "MAX_DEPTH_LIMIT"}`
     ///
-    /// Height is based on simple merging of fields using the same name or alias,
-    /// but only within the same selection set.
+    /// Height is based on [merging the fields][merging] using the same name or alias,
+    /// including the fields of fragments.
     /// For example `name` here is only counted once and the query has height 3, not 4:
     ///
     /// ```graphql
     /// query {
     ///     name { first }
-    ///     name { last }
+    ///     ... on Query { name { last } }
     /// }
     /// ```
     ///
-    /// This may change in a future version of Apollo Router to do
-    /// [full field merging across fragments][merging] instead.
-    ///
     /// [merging]: https://spec.graphql.org/October2021/#sec-Field-Selection-Merging]
     pub(crate) max_height: Option<u32>,
 
@@ -919,6 +1268,16 @@ pub(crate) struct Limits {
     /// `"extensions": {"code": "MAX_ALIASES_LIMIT"}`
     pub(crate) max_aliases: Option<u32>,
 
+    /// If set, the limits of the introspection operations, replacing the `max_*` limits.
+    /// Set it to `{}` to exempt introspection operations from the operation limits.
+    pub(crate) introspection: Option<OperationLimitsTier>,
+
+    /// Limits of the operations of some clients, replacing the `max_*` limits. The first tier
+    /// listing the client applies, unless the operation is an introspection operation and the
+    /// `introspection` limits are set. Clients are identified by the name they declare, so any
+    /// client can get the limits of a tier by sending one of its client names.
+    pub(crate) client_tiers: Vec<ClientLimitsTier>,
+
     /// If set to true (which is the default is dev mode),
     /// requests that exceed a `max_*` limit are *not* rejected.
     /// Instead they are executed normally, and a warning is logged.
@@ -934,6 +1293,22 @@ pub(crate) struct Limits {
     /// Limit the size of incoming HTTP requests read from the network,
     /// to protect against running out of memory. Default: 2000000 (2 MB)
     pub(crate) http_max_request_bytes: usize,
+
+    /// If set, limits the size of the request context, approximated from the JSON
+    /// representation of its entries. Plugins, Rhai scripts and coprocessors
+    /// cannot set entries that would grow the context beyond this size.
+    pub(crate) context_max_bytes: Option<usize>,
+
+    /// If set, limits the memory used by each request, approximated from the buffers it holds:
+    /// its body, its variables and the subgraph responses its response is assembled from.
+    /// Requests going over it are rejected with a `MEMORY_LIMIT_EXCEEDED` error, and subgraph
+    /// responses that don't fit are replaced with the same error.
+    pub(crate) request_max_memory_bytes: Option<usize>,
+
+    /// If set, new requests are rejected with a HTTP 503 Service Unavailable response and
+    /// `"extensions": {"code": "MEMORY_WATERMARK_EXCEEDED"}` while the memory used by the
+    /// requests in flight, approximated as for `request_max_memory_bytes`, goes over it.
+    pub(crate) memory_watermark_bytes: Option<usize>,
 }
 
 impl Default for Limits {
@@ -944,9 +1319,14 @@ impl Default for Limits {
             max_height: None,
             max_root_fields: None,
             max_aliases: None,
+            introspection: None,
+            client_tiers: Vec::new(),
             warn_only: false,
             http_max_request_bytes: 2_000_000,
             parser_max_tokens: 15_000,
+            context_max_bytes: None,
+            request_max_memory_bytes: None,
+            memory_watermark_bytes: None,
 
             // This is `apollo-parser`’s default, which protects against stack overflow
             // but is still very high for "reasonable" queries.
@@ -956,6 +1336,44 @@ impl Default for Limits {
     }
 }
 
+/// Operation limits replacing the `max_*` limits for some operations. Unset limits are not
+/// enforced.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct OperationLimitsTier {
+    /// Maximum depth of the operations
+    pub(crate) max_depth: Option<u32>,
+    /// Maximum height of the operations
+    pub(crate) max_height: Option<u32>,
+    /// Maximum number of root fields of the operations
+    pub(crate) max_root_fields: Option<u32>,
+    /// Maximum number of aliases of the operations
+    pub(crate) max_aliases: Option<u32>,
+}
+
+/// Operation limits of some clients, replacing the `max_*` limits. Unset limits are not enforced.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ClientLimitsTier {
+    /// Name of the tier, in logs and in the `limits_tier` value of the `query` telemetry
+    /// selector
+    pub(crate) name: String,
+    /// Names of the clients of the tier
+    pub(crate) client_names: Vec<String>,
+    /// Maximum depth of the operations
+    #[serde(default)]
+    pub(crate) max_depth: Option<u32>,
+    /// Maximum height of the operations
+    #[serde(default)]
+    pub(crate) max_height: Option<u32>,
+    /// Maximum number of root fields of the operations
+    #[serde(default)]
+    pub(crate) max_root_fields: Option<u32>,
+    /// Maximum number of aliases of the operations
+    #[serde(default)]
+    pub(crate) max_aliases: Option<u32>,
+}
+
 /// Router level (APQ) configuration
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, Default)]
 #[serde(deny_unknown_fields)]
@@ -1060,6 +1478,17 @@ pub(crate) struct QueryPlanning {
     /// the cache, this option can be used to deactivate it.
     /// Default: true
     pub(crate) legacy_introspection_caching: bool,
+
+    /// Execution and caching of introspection queries
+    pub(crate) experimental_introspection: IntrospectionExecution,
+
+    /// Saves the query plans to a file when the router shuts down, and restores them at startup
+    /// instead of planning them again. Only the query plans are saved, the schema is still parsed
+    /// and the query planner still initialized at startup
+    pub(crate) experimental_warm_state: Option<WarmState>,
+
+    /// Type conditioned fetching configuration
+    pub(crate) type_conditioned_fetching: TypeConditionedFetching,
 }
 
 impl Default for QueryPlanning {
@@ -1072,14 +1501,70 @@ impl Default for QueryPlanning {
             experimental_paths_limit: Default::default(),
             experimental_reuse_query_plans: Default::default(),
             legacy_introspection_caching: default_legacy_introspection_caching(),
+            experimental_introspection: Default::default(),
+            experimental_warm_state: Default::default(),
+            type_conditioned_fetching: Default::default(),
         }
     }
 }
 
+/// Type conditioned fetching configuration
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct TypeConditionedFetching {
+    /// Fetches the entities of abstract types with a fetch per concrete type, so that the fields
+    /// selected by type conditions are only requested for the entities of those types
+    /// (default: false)
+    pub(crate) enabled: bool,
+
+    /// Lets operations opt out of type conditioned fetching with the
+    /// `apollo-type-conditioned-fetching: false` header or the
+    /// `"typeConditionedFetching": false` request extension. The router keeps a second query
+    /// planner, planning without type conditions (default: false)
+    pub(crate) allow_opt_out: bool,
+}
+
 const fn default_legacy_introspection_caching() -> bool {
     true
 }
 
+/// Introspection execution and caching configuration
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct IntrospectionExecution {
+    /// JSON file of pre-generated introspection responses, served without executing their query.
+    /// It holds a list of objects with a `query` and the `response` to that exact query
+    pub(crate) responses_file: Option<PathBuf>,
+
+    /// Number of introspection responses in the Least Recently Used cache
+    /// Default: 5
+    pub(crate) cache_limit: Option<NonZeroUsize>,
+
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    /// TTL of the cached introspection responses
+    /// Default: no TTL, responses stay cached until the schema changes
+    pub(crate) cache_ttl: Option<Duration>,
+
+    /// Maximum number of introspection queries executed at the same time. Other introspection
+    /// queries wait for their turn, so that they cannot take up the whole query planner pool
+    /// Default: no limit
+    pub(crate) max_concurrency: Option<NonZeroUsize>,
+}
+
+/// Query plans saved to a file and restored at startup
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WarmState {
+    /// File of the saved query plans. It is read at startup, and written when the router shuts down
+    pub(crate) path: PathBuf,
+
+    /// Maximum number of saved query plans, the most recently used first
+    /// Default: all the query plans of the in memory cache
+    #[serde(default)]
+    pub(crate) max_query_plans: Option<NonZeroUsize>,
+}
+
 impl QueryPlanning {
     pub(crate) fn experimental_query_planner_parallelism(&self) -> io::Result<NonZeroUsize> {
         match self.experimental_parallelism {
@@ -1216,6 +1701,26 @@ pub(crate) struct RedisCache {
     #[serde(default = "default_reset_ttl")]
     /// When a TTL is set on a key, reset it when reading the data from that key
     pub(crate) reset_ttl: bool,
+
+    #[serde(default)]
+    /// Compression of the values stored in Redis (default: none)
+    pub(crate) compression: RedisCompression,
+
+    #[serde(default)]
+    #[schemars(with = "Option<String>", default)]
+    /// Maximum size of the values stored in Redis, after compression. Larger values are only kept in memory
+    pub(crate) max_entry_size: Option<ByteSize>,
+}
+
+/// Compression of the values stored in Redis
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RedisCompression {
+    /// Values are stored as JSON
+    #[default]
+    None,
+    /// Values are compressed with zstd. Compressed values can be read whatever the configured compression
+    Zstd,
 }
 
 fn default_required_to_start() -> bool {
@@ -1234,6 +1739,8 @@ impl From<QueryPlanRedisCache> for RedisCache {
             tls: value.tls,
             required_to_start: value.required_to_start,
             reset_ttl: value.reset_ttl,
+            compression: Default::default(),
+            max_entry_size: None,
         }
     }
 }
@@ -1252,6 +1759,9 @@ pub(crate) struct Tls {
     /// this will affect the GraphQL endpoint and any other endpoint targeting the same listen address
     pub(crate) supergraph: Option<TlsSupergraph>,
     pub(crate) subgraph: SubgraphConfiguration<TlsClient>,
+    /// SPIFFE workload identity, fetched from the SPIFFE workload API and used by the subgraph,
+    /// coprocessor and Redis connections enabling `spiffe`
+    pub(crate) spiffe: Option<Spiffe>,
 }
 
 /// Configuration options pertaining to the supergraph server component.
@@ -1270,18 +1780,51 @@ pub(crate) struct TlsSupergraph {
     #[serde(deserialize_with = "deserialize_certificate_chain", skip_serializing)]
     #[schemars(with = "String")]
     pub(crate) certificate_chain: Vec<Certificate>,
+    /// path of a DER encoded OCSP response stapled to handshakes using the server certificate
+    #[serde(default)]
+    pub(crate) ocsp_response_path: Option<PathBuf>,
+    /// certificates selected by the server name requested by the client (SNI), loaded from
+    /// files that are reloaded when they change. The server certificate is used when none matches
+    #[serde(default)]
+    pub(crate) sni_certificates: Vec<TlsSniCertificate>,
+    /// minimum TLS version accepted
+    #[serde(default)]
+    pub(crate) min_version: TlsVersion,
+    /// cipher suites accepted, in order of preference (default: all the supported cipher suites)
+    #[serde(default)]
+    pub(crate) cipher_suites: Vec<String>,
+    /// verification of client certificates
+    #[serde(default)]
+    pub(crate) client_authentication: Option<TlsClientVerification>,
 }
 
 impl TlsSupergraph {
     pub(crate) fn tls_config(&self) -> Result<Arc<rustls::ServerConfig>, ApolloRouterError> {
         let mut certificates = vec![self.certificate.clone()];
         certificates.extend(self.certificate_chain.iter().cloned());
-
-        let mut config = ServerConfig::builder()
-            .with_safe_defaults()
-            .with_no_client_auth()
-            .with_single_cert(certificates, self.key.clone())
+        let ocsp = self
+            .ocsp_response_path
+            .as_ref()
+            .map(std::fs::read)
+            .transpose()
+            .map_err(ApolloRouterError::ServerCreationError)?;
+        let resolver = CertificateResolver::new(
+            tls::certified_key(certificates, &self.key, ocsp)?,
+            &self.sni_certificates,
+        )?;
+
+        let config = ServerConfig::builder()
+            .with_cipher_suites(&tls::cipher_suites(&self.cipher_suites)?)
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(self.min_version.protocol_versions())
             .map_err(ApolloRouterError::Rustls)?;
+        let mut config = match &self.client_authentication {
+            Some(client_authentication) => {
+                config.with_client_cert_verifier(client_authentication.verifier()?)
+            }
+            None => config.with_no_client_auth(),
+        }
+        .with_cert_resolver(Arc::new(resolver));
         config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
 
         Ok(Arc::new(config))
@@ -1377,6 +1920,10 @@ pub(crate) struct TlsClient {
     pub(crate) certificate_authorities: Option<String>,
     /// client certificate authentication
     pub(crate) client_authentication: Option<TlsClientAuth>,
+    /// authenticate with the SPIFFE workload identity of the router (`tls.spiffe`), and only
+    /// accept server certificates that are SVIDs of the accepted trust domains. The certificate
+    /// authorities and client authentication options are ignored
+    pub(crate) spiffe: bool,
 }
 
 #[buildstructor::buildstructor]
@@ -1385,10 +1932,12 @@ impl TlsClient {
     pub(crate) fn new(
         certificate_authorities: Option<String>,
         client_authentication: Option<TlsClientAuth>,
+        spiffe: Option<bool>,
     ) -> Self {
         Self {
             certificate_authorities,
             client_authentication,
+            spiffe: spiffe.unwrap_or_default(),
         }
     }
 }
@@ -1420,6 +1969,15 @@ pub(crate) struct TlsClientAuth {
 pub(crate) struct Sandbox {
     /// Set to true to enable sandbox
     pub(crate) enabled: bool,
+    /// Content-Security-Policy header sent with the sandbox page
+    pub(crate) content_security_policy: Option<String>,
+    /// HTML displayed above the sandbox, for custom branding
+    pub(crate) custom_html: Option<String>,
+    /// Set to true to serve the sandbox page only to the requests authenticated by the
+    /// authentication plugin
+    pub(crate) require_authentication: bool,
+    /// Set to true to reject the mutations sent from the sandbox
+    pub(crate) read_only: bool,
 }
 
 fn default_sandbox() -> bool {
@@ -1429,9 +1987,19 @@ fn default_sandbox() -> bool {
 #[buildstructor::buildstructor]
 impl Sandbox {
     #[builder]
-    pub(crate) fn new(enabled: Option<bool>) -> Self {
+    pub(crate) fn new(
+        enabled: Option<bool>,
+        content_security_policy: Option<String>,
+        custom_html: Option<String>,
+        require_authentication: Option<bool>,
+        read_only: Option<bool>,
+    ) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_sandbox),
+            content_security_policy,
+            custom_html,
+            require_authentication: require_authentication.unwrap_or_default(),
+            read_only: read_only.unwrap_or_default(),
         }
     }
 }
@@ -1440,9 +2008,19 @@ impl Sandbox {
 #[buildstructor::buildstructor]
 impl Sandbox {
     #[builder]
-    pub(crate) fn fake_new(enabled: Option<bool>) -> Self {
+    pub(crate) fn fake_new(
+        enabled: Option<bool>,
+        content_security_policy: Option<String>,
+        custom_html: Option<String>,
+        require_authentication: Option<bool>,
+        read_only: Option<bool>,
+    ) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_sandbox),
+            content_security_policy,
+            custom_html,
+            require_authentication: require_authentication.unwrap_or_default(),
+            read_only: read_only.unwrap_or_default(),
         }
     }
 }
@@ -1513,6 +2091,46 @@ pub(crate) struct HealthCheck {
     /// Optionally set a custom healthcheck path
     /// Defaults to /health
     pub(crate) path: String,
+
+    /// Startup probe, up once the router serves requests
+    pub(crate) startup: Option<HealthProbe>,
+
+    /// Liveness probe, up while the router runs
+    pub(crate) liveness: Option<HealthProbe>,
+
+    /// Readiness probe, up while the router accepts requests
+    pub(crate) readiness: Option<HealthProbe>,
+}
+
+/// Health probe endpoint, served on the health check listen address
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HealthProbe {
+    /// Path of the probe
+    pub(crate) path: String,
+
+    /// Dependencies that must be available for the probe to be up
+    #[serde(default)]
+    pub(crate) checks: Vec<HealthDependency>,
+}
+
+/// Dependency checked by a health probe
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum HealthDependency {
+    /// A supergraph schema is loaded
+    Schema,
+    /// Uplink answered recently
+    Uplink {
+        /// Maximum time since Uplink last answered
+        #[serde(with = "humantime_serde")]
+        #[schemars(with = "String")]
+        max_age: Duration,
+    },
+    /// All the Redis clients are connected
+    Redis,
+    /// The coprocessor accepts connections
+    Coprocessor,
 }
 
 fn default_health_check_listen() -> ListenAddr {
@@ -1534,6 +2152,9 @@ impl HealthCheck {
         listen: Option<ListenAddr>,
         enabled: Option<bool>,
         path: Option<String>,
+        startup: Option<HealthProbe>,
+        liveness: Option<HealthProbe>,
+        readiness: Option<HealthProbe>,
     ) -> Self {
         let mut path = path.unwrap_or_else(default_health_check_path);
         if !path.starts_with('/') {
@@ -1544,6 +2165,9 @@ impl HealthCheck {
             listen: listen.unwrap_or_else(default_health_check_listen),
             enabled: enabled.unwrap_or_else(default_health_check_enabled),
             path,
+            startup,
+            liveness,
+            readiness,
         }
     }
 }
@@ -1556,6 +2180,9 @@ impl HealthCheck {
         listen: Option<ListenAddr>,
         enabled: Option<bool>,
         path: Option<String>,
+        startup: Option<HealthProbe>,
+        liveness: Option<HealthProbe>,
+        readiness: Option<HealthProbe>,
     ) -> Self {
         let mut path = path.unwrap_or_else(default_health_check_path);
         if !path.starts_with('/') {
@@ -1566,6 +2193,9 @@ impl HealthCheck {
             listen: listen.unwrap_or_else(test_listen),
             enabled: enabled.unwrap_or_else(default_health_check_enabled),
             path,
+            startup,
+            liveness,
+            readiness,
         }
     }
 }
@@ -1692,6 +2322,85 @@ pub(crate) struct Batching {
 
     /// Subgraph options for batching
     pub(crate) subgraph: Option<SubgraphConfiguration<CommonBatchingConfig>>,
+
+    /// Maximum number of operations in a client batch (unlimited by default)
+    #[serde(default)]
+    pub(crate) maximum_size: Option<usize>,
+
+    /// Maximum number of operations in a client batch by client name, overriding `maximum_size`
+    #[serde(default)]
+    pub(crate) client_maximum_size: HashMap<String, usize>,
+
+    /// Timeout of each operation of a client batch. An operation that times out gets an error
+    /// response, without failing the other operations of the batch
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) operation_timeout: Option<Duration>,
+}
+
+/// Containment of the panics happening while executing a request
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct PanicContainment {
+    /// Answer the requests that panic with an error instead of terminating the router
+    /// (disabled by default)
+    pub(crate) enabled: bool,
+
+    /// Directory where the diagnostic bundles of the panics are written (defaults to an
+    /// `apollo-router-incidents` directory in the temporary directory)
+    pub(crate) spool_path: Option<PathBuf>,
+
+    /// Maximum number of diagnostic bundles kept in the directory, the oldest ones are removed
+    pub(crate) max_bundles: usize,
+}
+
+impl Default for PanicContainment {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            spool_path: None,
+            max_bundles: 100,
+        }
+    }
+}
+
+impl PanicContainment {
+    /// Directory where the diagnostic bundles are written
+    pub(crate) fn spool_path(&self) -> PathBuf {
+        self.spool_path
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("apollo-router-incidents"))
+    }
+}
+
+/// Support bundle endpoint
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SupportBundle {
+    /// Expose an endpoint returning a support bundle of the running router, with its redacted
+    /// configuration, schema hash, plugins, recent errors, cache statistics and environment
+    pub(crate) enabled: bool,
+
+    /// The socket address and port to listen on
+    pub(crate) listen: ListenAddr,
+
+    /// The path of the endpoint
+    pub(crate) path: String,
+
+    /// Shared key expected in the `Authorization` header of the requests, required when the
+    /// endpoint is enabled
+    pub(crate) shared_key: String,
+}
+
+impl Default for SupportBundle {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: SocketAddr::from_str("127.0.0.1:8088").unwrap().into(),
+            path: "/support-bundle".to_string(),
+            shared_key: String::new(),
+        }
+    }
 }
 
 /// Common options for configuring subgraph batching
@@ -1702,6 +2411,14 @@ pub(crate) struct CommonBatchingConfig {
 }
 
 impl Batching {
+    /// Maximum number of operations in a batch sent by this client, if any
+    pub(crate) fn maximum_size(&self, client_name: Option<&str>) -> Option<usize> {
+        client_name
+            .and_then(|name| self.client_maximum_size.get(name))
+            .copied()
+            .or(self.maximum_size)
+    }
+
     // Check if we should enable batching for a particular subgraph (service_name)
     pub(crate) fn batch_include(&self, service_name: &str) -> bool {
         match &self.subgraph {
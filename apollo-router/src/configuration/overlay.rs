@@ -0,0 +1,120 @@
+//! Configuration files merged in order, like a base configuration and environment overlays.
+//!
+//! Mappings are merged key by key, recursively. Any other value of a later file, including
+//! sequences and `null`, replaces the value of the earlier files. Empty files change nothing.
+//! The files are merged before the expansion of variables, so an overlay can replace a value
+//! referencing an environment variable.
+
+use serde_yaml::Value;
+
+use super::ConfigurationError;
+
+/// Merges the YAML contents of configuration files, in order, into a single YAML configuration
+pub(crate) fn merge_overlays<'a>(
+    contents: impl IntoIterator<Item = &'a str>,
+) -> Result<String, ConfigurationError> {
+    let mut merged = Value::Null;
+    for content in contents {
+        if content.trim().is_empty() {
+            continue;
+        }
+        let overlay: Value = serde_yaml::from_str(content).map_err(|e| {
+            ConfigurationError::InvalidConfiguration {
+                message: "could not parse the configuration file",
+                error: e.to_string(),
+            }
+        })?;
+        if overlay.is_null() {
+            continue;
+        }
+        merge(&mut merged, overlay);
+    }
+    if merged.is_null() {
+        return Ok(String::new());
+    }
+    serde_yaml::to_string(&merged).map_err(|e| ConfigurationError::InvalidConfiguration {
+        message: "could not serialize the merged configuration",
+        error: e.to_string(),
+    })
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(contents: &[&str]) -> Value {
+        serde_yaml::from_str(&merge_overlays(contents.iter().copied()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn merges_mappings_and_replaces_other_values() {
+        let base = r#"
+supergraph:
+  listen: 0.0.0.0:4000
+  introspection: true
+headers:
+  all:
+    request:
+      - propagate:
+          named: x-base
+telemetry:
+  apollo:
+    endpoint: ${env.APOLLO_ENDPOINT}
+"#;
+        let overlay = r#"
+supergraph:
+  introspection: false
+headers:
+  all:
+    request:
+      - propagate:
+          named: x-prod
+telemetry:
+  apollo:
+    endpoint: ~
+"#;
+        let expected: Value = serde_yaml::from_str(
+            r#"
+supergraph:
+  listen: 0.0.0.0:4000
+  introspection: false
+headers:
+  all:
+    request:
+      - propagate:
+          named: x-prod
+telemetry:
+  apollo:
+    endpoint: ~
+"#,
+        )
+        .unwrap();
+        assert_eq!(merged(&[base, "", overlay]), expected);
+    }
+
+    #[test]
+    fn empty_files_give_an_empty_configuration() {
+        assert_eq!(merge_overlays(["", "\n"]).unwrap(), "");
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(merge_overlays(["supergraph:\n  listen: [", "supergraph: {}"]).is_err());
+    }
+}
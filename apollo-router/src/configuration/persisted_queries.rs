@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
@@ -20,6 +23,16 @@ pub struct PersistedQueries {
 
     /// Enables using a local copy of the persisted query manifest to safelist operations
     pub experimental_local_manifests: Option<Vec<String>>,
+
+    /// Exposes persisted queries as REST endpoints, for the clients that can't send GraphQL requests
+    pub experimental_rest_endpoints: PersistedQueriesRestEndpoints,
+
+    /// Local manifests of fragments that operations can spread without defining them
+    pub experimental_fragment_manifests: Option<Vec<String>>,
+
+    /// Reads the persisted queries from a local directory, an S3 bucket or a DynamoDB table
+    /// instead of GraphOS, and keeps them in sync
+    pub experimental_storage: Option<PersistedQueriesStorage>,
 }
 
 #[cfg(test)]
@@ -32,6 +45,9 @@ impl PersistedQueries {
         safelist: Option<PersistedQueriesSafelist>,
         experimental_prewarm_query_plan_cache: Option<bool>,
         experimental_local_manifests: Option<Vec<String>>,
+        experimental_rest_endpoints: Option<PersistedQueriesRestEndpoints>,
+        experimental_fragment_manifests: Option<Vec<String>>,
+        experimental_storage: Option<PersistedQueriesStorage>,
     ) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_pq),
@@ -40,6 +56,9 @@ impl PersistedQueries {
             experimental_prewarm_query_plan_cache: experimental_prewarm_query_plan_cache
                 .unwrap_or_else(default_prewarm_query_plan_cache),
             experimental_local_manifests,
+            experimental_rest_endpoints: experimental_rest_endpoints.unwrap_or_default(),
+            experimental_fragment_manifests,
+            experimental_storage,
         }
     }
 }
@@ -53,18 +72,269 @@ pub struct PersistedQueriesSafelist {
 
     /// Enabling this field configures the router to reject any request that does not include the persisted query ID
     pub require_id: bool,
+
+    /// Enforces the safelist for a part of the traffic only, and logs the operations that would have been rejected for the rest (enforced for all the traffic by default)
+    pub enforcement: Option<PersistedQueriesSafelistEnforcement>,
+}
+
+/// Gradual enforcement of the safelist. Requests matching none of the criteria are not rejected
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub struct PersistedQueriesSafelistEnforcement {
+    /// Percentage of the requests for which the safelist is enforced, between 0 and 100
+    pub percentage: f64,
+
+    /// Names of the clients for which the safelist is always enforced
+    pub client_names: Vec<String>,
 }
 
 #[cfg(test)]
 #[buildstructor::buildstructor]
 impl PersistedQueriesSafelist {
     #[builder]
-    pub(crate) fn new(enabled: Option<bool>, require_id: Option<bool>) -> Self {
+    pub(crate) fn new(
+        enabled: Option<bool>,
+        require_id: Option<bool>,
+        enforcement: Option<PersistedQueriesSafelistEnforcement>,
+    ) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_safelist),
             require_id: require_id.unwrap_or_else(default_require_id),
+            enforcement,
+        }
+    }
+}
+
+/// Storage of the persisted queries, polled for changes
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PersistedQueriesStorage {
+    /// Where the operations are stored
+    pub backend: PersistedQueriesStorageBackend,
+
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Interval between the synchronizations with the backend, which only read the new and
+    /// changed operations. Default: 60s
+    pub poll_interval: Option<Duration>,
+
+    /// Skips the operations whose ID is not the SHA-256 hash of their body (enabled by default)
+    #[serde(default = "default_verify_hashes")]
+    pub verify_hashes: bool,
+}
+
+/// A storage backend of persisted queries. The ID of an operation is the name of its file or
+/// object, without the `.graphql` extension
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum PersistedQueriesStorageBackend {
+    /// A local directory tree, with one `<id>.graphql` file per operation
+    LocalDirectory {
+        /// Path of the directory
+        path: String,
+    },
+    /// An S3 bucket, with one `<prefix><id>.graphql` object per operation. The AWS credentials
+    /// are read from the environment
+    S3 {
+        /// Name of the bucket
+        bucket: String,
+        /// Prefix of the keys of the operations
+        #[serde(default)]
+        prefix: String,
+        /// Region of the bucket (default: the region of the environment)
+        #[serde(default)]
+        region: Option<String>,
+    },
+    /// A DynamoDB table, with one item per operation. The AWS credentials are read from the
+    /// environment
+    #[serde(rename = "dynamodb")]
+    DynamoDb {
+        /// Name of the table
+        table: String,
+        /// String attribute holding the ID of the operation, the partition key of the table
+        #[serde(default = "default_id_attribute")]
+        id_attribute: String,
+        /// String attribute holding the body of the operation
+        #[serde(default = "default_body_attribute")]
+        body_attribute: String,
+        /// String or number attribute changing whenever the body of the operation changes, like
+        /// a version number or an update timestamp. Only the bodies of the new and changed items
+        /// are read
+        #[serde(default = "default_version_attribute")]
+        version_attribute: String,
+        /// Region of the table (default: the region of the environment)
+        #[serde(default)]
+        region: Option<String>,
+    },
+}
+
+/// REST endpoints executing persisted queries
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub struct PersistedQueriesRestEndpoints {
+    /// Routes mapped to persisted queries
+    pub routes: Vec<PersistedQueryRoute>,
+
+    /// Path of the OpenAPI document describing the routes (not served by default)
+    pub openapi_path: Option<String>,
+}
+
+/// Route executing a persisted query. The variables of the operation are taken from the path
+/// parameters, the query string, and the JSON object body of the request
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PersistedQueryRoute {
+    /// HTTP method of the route (GET by default)
+    #[serde(default)]
+    pub method: PersistedQueryRouteMethod,
+
+    /// Path of the route. Segments like `{id}` are passed as the variable of the same name
+    pub path: String,
+
+    /// ID of the persisted query in the manifest
+    pub persisted_query_id: String,
+
+    /// Name of the operation to execute, when the persisted query holds several of them
+    pub operation_name: Option<String>,
+}
+
+/// HTTP method of a route
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PersistedQueryRouteMethod {
+    #[default]
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+/// Segment of the path of a route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RouteSegment<'a> {
+    Static(&'a str),
+    Parameter(&'a str),
+}
+
+impl PersistedQueryRouteMethod {
+    pub(crate) fn as_http(&self) -> http::Method {
+        match self {
+            PersistedQueryRouteMethod::Get => http::Method::GET,
+            PersistedQueryRouteMethod::Post => http::Method::POST,
+            PersistedQueryRouteMethod::Put => http::Method::PUT,
+            PersistedQueryRouteMethod::Patch => http::Method::PATCH,
+            PersistedQueryRouteMethod::Delete => http::Method::DELETE,
         }
     }
+
+    /// Whether the variables can be sent in the body of the request
+    pub(crate) fn has_body(&self) -> bool {
+        matches!(
+            self,
+            PersistedQueryRouteMethod::Post
+                | PersistedQueryRouteMethod::Put
+                | PersistedQueryRouteMethod::Patch
+        )
+    }
+}
+
+impl PersistedQueryRoute {
+    /// The segments of the path, after its leading `/`
+    pub(crate) fn segments(&self) -> impl Iterator<Item = RouteSegment<'_>> {
+        self.path.split('/').skip(1).map(|segment| {
+            match segment
+                .strip_prefix('{')
+                .and_then(|segment| segment.strip_suffix('}'))
+            {
+                Some(name) => RouteSegment::Parameter(name),
+                None => RouteSegment::Static(segment),
+            }
+        })
+    }
+
+    /// The path of the route in the syntax of the HTTP server
+    pub(crate) fn server_path(&self) -> String {
+        self.segments()
+            .map(|segment| match segment {
+                RouteSegment::Static(segment) => format!("/{segment}"),
+                RouteSegment::Parameter(name) => format!("/:{name}"),
+            })
+            .collect()
+    }
+}
+
+impl PersistedQueriesRestEndpoints {
+    /// Returns the first error in the routes, if any
+    pub(crate) fn validate(&self, supergraph_path: &str) -> Result<(), String> {
+        let mut server_paths = HashMap::new();
+        for route in &self.routes {
+            if !route.path.starts_with('/') {
+                return Err(format!("the path '{}' must start with '/'", route.path));
+            }
+            for segment in route.segments() {
+                let valid = match segment {
+                    RouteSegment::Static(segment) => !segment.contains(['{', '}', ':', '*']),
+                    RouteSegment::Parameter(name) => {
+                        !name.is_empty()
+                            && !name.starts_with(|c: char| c.is_ascii_digit())
+                            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    }
+                };
+                if !valid {
+                    return Err(format!(
+                        "the path '{}' has an invalid segment, parameters must be written like '{{name}}' with a GraphQL variable name",
+                        route.path
+                    ));
+                }
+            }
+            let server_path = route.server_path();
+            if server_path == supergraph_path {
+                return Err(format!(
+                    "the path '{}' is the path of the supergraph",
+                    route.path
+                ));
+            }
+            // the HTTP server can't tell apart paths differing only by the names of their
+            // parameters
+            let template = route
+                .segments()
+                .map(|segment| match segment {
+                    RouteSegment::Static(segment) => segment,
+                    RouteSegment::Parameter(_) => "{}",
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let methods = server_paths
+                .entry(template)
+                .or_insert_with(|| (route.path.as_str(), Vec::new()));
+            if methods.0 != route.path {
+                return Err(format!(
+                    "the paths '{}' and '{}' only differ by the names of their parameters",
+                    methods.0, route.path
+                ));
+            }
+            if methods.1.contains(&route.method) {
+                return Err(format!(
+                    "the route {} {} is declared several times",
+                    route.method.as_http(),
+                    route.path
+                ));
+            }
+            methods.1.push(route.method);
+        }
+        if let Some(openapi_path) = &self.openapi_path {
+            if !openapi_path.starts_with('/') {
+                return Err(format!("the path '{openapi_path}' must start with '/'"));
+            }
+            if openapi_path == supergraph_path {
+                return Err(format!(
+                    "the path '{openapi_path}' is the path of the supergraph"
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for PersistedQueries {
@@ -75,6 +345,9 @@ impl Default for PersistedQueries {
             log_unknown: default_log_unknown(),
             experimental_prewarm_query_plan_cache: default_prewarm_query_plan_cache(),
             experimental_local_manifests: None,
+            experimental_rest_endpoints: PersistedQueriesRestEndpoints::default(),
+            experimental_fragment_manifests: None,
+            experimental_storage: None,
         }
     }
 }
@@ -84,6 +357,7 @@ impl Default for PersistedQueriesSafelist {
         Self {
             enabled: default_safelist(),
             require_id: default_require_id(),
+            enforcement: None,
         }
     }
 }
@@ -107,3 +381,19 @@ const fn default_log_unknown() -> bool {
 const fn default_prewarm_query_plan_cache() -> bool {
     false
 }
+
+const fn default_verify_hashes() -> bool {
+    true
+}
+
+fn default_id_attribute() -> String {
+    "id".to_string()
+}
+
+fn default_body_attribute() -> String {
+    "body".to_string()
+}
+
+fn default_version_attribute() -> String {
+    "version".to_string()
+}
@@ -0,0 +1,434 @@
+//! Execution order of the plugins, and plugins skipped by some requests.
+//!
+//! The Rhai script, the coprocessor and the user plugins can be listed in the order they handle
+//! the requests, instead of the default one. Plugins can also be skipped by the requests received
+//! on some paths or listeners, like the authentication on an internal listener. Whether a request
+//! skips a plugin is decided when it enters the router service, and recorded in the extensions of
+//! its context, so that the later stages of the request, including its subgraph requests, skip the
+//! plugin too. Unlike the context entries, the extensions can't be changed by the Rhai scripts and
+//! the coprocessors.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::service_fn;
+use tower::util::BoxService;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use super::ConfigurationError;
+use super::APOLLO_PLUGIN_PREFIX;
+use crate::axum_factory::utils::ConnectionInfo;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::CacheKeyInput;
+use crate::plugin::DynPlugin;
+use crate::router_factory::Endpoint;
+use crate::services::execution;
+use crate::services::http;
+use crate::services::router;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::services::Plugins;
+use crate::Context;
+use crate::ListenAddr;
+
+const RHAI_PLUGIN: &str = "apollo.rhai";
+const COPROCESSOR_PLUGIN: &str = "apollo.coprocessor";
+
+/// Plugin execution configuration
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct PluginExecution {
+    /// Order of the Rhai script (`apollo.rhai`), the coprocessor (`apollo.coprocessor`) and the
+    /// user plugins, from the first one handling the requests to the last one. The plugins left
+    /// out keep their position (default: the Rhai script, the coprocessor, then the user plugins
+    /// in the order of the `plugins` section)
+    pub(crate) order: Vec<String>,
+
+    /// Plugins skipped by the requests received on some paths or listeners
+    pub(crate) skip: Vec<PluginSkip>,
+}
+
+/// Requests skipping a plugin
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PluginSkip {
+    /// Name of the plugin, like `apollo.authentication` or `acme.audit`
+    pub(crate) plugin: String,
+
+    /// Paths of the requests skipping the plugin. A path ending with `*` matches the paths
+    /// starting with it (default: all paths)
+    #[serde(default)]
+    pub(crate) paths: Vec<String>,
+
+    /// Addresses of the listeners whose requests skip the plugin, like `127.0.0.1:4000`
+    /// (default: all listeners)
+    #[serde(default)]
+    #[schemars(with = "Vec<String>")]
+    pub(crate) listeners: Vec<SocketAddr>,
+}
+
+impl PluginSkip {
+    fn matches(&self, path: &str, server_address: Option<SocketAddr>) -> bool {
+        let path_matches = self.paths.is_empty()
+            || self
+                .paths
+                .iter()
+                .any(|skipped| match skipped.strip_suffix('*') {
+                    Some(prefix) => path.starts_with(prefix),
+                    None => path == skipped,
+                });
+        let listener_matches = self.listeners.is_empty()
+            || server_address.is_some_and(|address| {
+                self.listeners.iter().any(|listener| {
+                    listener.port() == address.port()
+                        && (listener.ip().is_unspecified() || listener.ip() == address.ip())
+                })
+            });
+        path_matches && listener_matches
+    }
+}
+
+fn invalid(error: String) -> ConfigurationError {
+    ConfigurationError::InvalidConfiguration {
+        message: "invalid plugin execution configuration",
+        error,
+    }
+}
+
+/// Whether the position of a plugin can be configured
+fn orderable(name: &str) -> bool {
+    name == RHAI_PLUGIN || name == COPROCESSOR_PLUGIN || !name.starts_with(APOLLO_PLUGIN_PREFIX)
+}
+
+impl PluginExecution {
+    /// Orders the plugins and wraps the plugins skipped by some requests
+    pub(crate) fn apply(&self, plugins: Plugins) -> Result<Plugins, Vec<ConfigurationError>> {
+        self.validate(&plugins)?;
+
+        let mut skips: HashMap<&str, Vec<PluginSkip>> = HashMap::new();
+        for skip in &self.skip {
+            skips
+                .entry(skip.plugin.as_str())
+                .or_default()
+                .push(skip.clone());
+        }
+
+        // The listed plugins take the positions of the listed plugins, in the configured order
+        let mut listed = HashMap::new();
+        let mut entries = Vec::with_capacity(plugins.len());
+        for (name, plugin) in plugins {
+            if self.order.contains(&name) {
+                listed.insert(name, plugin);
+                entries.push(None);
+            } else {
+                entries.push(Some((name, plugin)));
+            }
+        }
+        let mut order = self.order.iter();
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                entry.unwrap_or_else(|| {
+                    let name = order.next().expect("each listed plugin has a position");
+                    let plugin = listed.remove(name).expect("listed plugins are enabled");
+                    (name.clone(), plugin)
+                })
+            })
+            .map(|(name, plugin)| match skips.remove(name.as_str()) {
+                Some(skips) => {
+                    let plugin: Box<dyn DynPlugin> = Box::new(SkippablePlugin {
+                        name: name.clone(),
+                        plugin,
+                        skips,
+                    });
+                    (name, plugin)
+                }
+                None => (name, plugin),
+            })
+            .collect())
+    }
+
+    fn validate(&self, plugins: &Plugins) -> Result<(), Vec<ConfigurationError>> {
+        let mut errors = Vec::new();
+
+        let mut listed = HashSet::new();
+        for name in &self.order {
+            if !listed.insert(name) {
+                errors.push(invalid(format!("plugin {name} is listed more than once")));
+            } else if !orderable(name) {
+                errors.push(invalid(format!(
+                    "the position of plugin {name} can't be configured, only the Rhai script, the coprocessor and the user plugins can be ordered"
+                )));
+            } else if !plugins.contains_key(name) {
+                errors.push(invalid(format!("ordered plugin {name} is not enabled")));
+            }
+        }
+
+        for skip in &self.skip {
+            if !plugins.contains_key(&skip.plugin) {
+                errors.push(invalid(format!(
+                    "skipped plugin {} is not enabled",
+                    skip.plugin
+                )));
+            }
+            if skip.paths.is_empty() && skip.listeners.is_empty() {
+                errors.push(invalid(format!(
+                    "the requests skipping plugin {} must be selected by paths or listeners",
+                    skip.plugin
+                )));
+            }
+            if let Some(path) = skip.paths.iter().find(|path| !path.starts_with('/')) {
+                errors.push(invalid(format!(
+                    "path {path} skipping plugin {} must start with '/'",
+                    skip.plugin
+                )));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Names of the plugins skipped by a request, in the extensions of its context
+#[derive(Debug, Default)]
+struct SkippedPlugins(HashSet<String>);
+
+fn is_skipped(context: &Context, name: &str) -> bool {
+    context.extensions().with_lock(|lock| {
+        lock.get::<SkippedPlugins>()
+            .is_some_and(|skipped| skipped.0.contains(name))
+    })
+}
+
+/// A plugin skipped by some requests
+struct SkippablePlugin {
+    name: String,
+    plugin: Box<dyn DynPlugin>,
+    skips: Vec<PluginSkip>,
+}
+
+/// Sends the skipping requests straight to the service, and the other ones through the service
+/// wrapped by the plugin
+fn skippable<Request, Response>(
+    service: BoxService<Request, Response, BoxError>,
+    wrap: impl FnOnce(
+        BoxService<Request, Response, BoxError>,
+    ) -> BoxService<Request, Response, BoxError>,
+    skipped: impl Fn(&Request) -> bool + Send + 'static,
+) -> BoxService<Request, Response, BoxError>
+where
+    Request: Send + 'static,
+    Response: Send + 'static,
+{
+    let bypass = ServiceBuilder::new().buffered().service(service);
+    let applied = ServiceBuilder::new()
+        .buffered()
+        .service(wrap(bypass.clone().boxed()));
+
+    service_fn(move |request: Request| {
+        let skipped = skipped(&request);
+        let bypass = bypass.clone();
+        let applied = applied.clone();
+        async move {
+            if skipped {
+                bypass.oneshot(request).await
+            } else {
+                applied.oneshot(request).await
+            }
+        }
+    })
+    .boxed()
+}
+
+impl DynPlugin for SkippablePlugin {
+    fn router_service(&self, service: router::BoxService) -> router::BoxService {
+        let skips = self.skips.clone();
+        let name = self.name.clone();
+        skippable(
+            service,
+            |service| self.plugin.router_service(service),
+            move |request: &router::Request| {
+                let server_address = request
+                    .router_request
+                    .extensions()
+                    .get::<ConnectionInfo>()
+                    .and_then(|info| info.server_address);
+                let path = request.router_request.uri().path();
+                let skipped = skips.iter().any(|skip| skip.matches(path, server_address));
+                if skipped {
+                    request.context.extensions().with_lock(|mut lock| {
+                        lock.get_or_default_mut::<SkippedPlugins>()
+                            .0
+                            .insert(name.clone());
+                    });
+                }
+                skipped
+            },
+        )
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let skipped_name = self.name.clone();
+        skippable(
+            service,
+            |service| self.plugin.supergraph_service(service),
+            move |request: &supergraph::Request| is_skipped(&request.context, &skipped_name),
+        )
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let skipped_name = self.name.clone();
+        skippable(
+            service,
+            |service| self.plugin.execution_service(service),
+            move |request: &execution::Request| is_skipped(&request.context, &skipped_name),
+        )
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let skipped_name = self.name.clone();
+        skippable(
+            service,
+            |service| self.plugin.subgraph_service(name, service),
+            move |request: &subgraph::Request| is_skipped(&request.context, &skipped_name),
+        )
+    }
+
+    fn http_client_service(&self, name: &str, service: http::BoxService) -> http::BoxService {
+        let skipped_name = self.name.clone();
+        skippable(
+            service,
+            |service| self.plugin.http_client_service(name, service),
+            move |request: &http::HttpRequest| is_skipped(&request.context, &skipped_name),
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        self.plugin.name()
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        self.plugin.web_endpoints()
+    }
+
+    fn cache_key(&self, input: &CacheKeyInput<'_>) -> Option<String> {
+        self.plugin.cache_key(input)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.plugin.as_any()
+    }
+
+    #[cfg(test)]
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self.plugin.as_any_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::Plugin;
+    use crate::plugin::PluginInit;
+
+    struct Noop;
+
+    #[async_trait::async_trait]
+    impl Plugin for Noop {
+        type Config = ();
+
+        async fn new(_init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+            Ok(Noop)
+        }
+    }
+
+    fn plugins(names: &[&str]) -> Plugins {
+        names
+            .iter()
+            .map(|name| (name.to_string(), Box::new(Noop) as Box<dyn DynPlugin>))
+            .collect()
+    }
+
+    #[test]
+    fn listed_plugins_swap_positions() {
+        let execution = PluginExecution {
+            order: vec!["acme.audit".to_string(), RHAI_PLUGIN.to_string()],
+            skip: Vec::new(),
+        };
+        let plugins = execution
+            .apply(plugins(&[
+                "apollo.headers",
+                RHAI_PLUGIN,
+                COPROCESSOR_PLUGIN,
+                "acme.audit",
+            ]))
+            .unwrap();
+        assert_eq!(
+            plugins.keys().collect::<Vec<_>>(),
+            [
+                "apollo.headers",
+                "acme.audit",
+                COPROCESSOR_PLUGIN,
+                RHAI_PLUGIN
+            ]
+        );
+
+        let execution = PluginExecution {
+            order: vec!["apollo.headers".to_string(), "acme.missing".to_string()],
+            skip: vec![PluginSkip {
+                plugin: "apollo.authentication".to_string(),
+                paths: vec!["health".to_string()],
+                listeners: Vec::new(),
+            }],
+        };
+        let errors = execution.apply(plugins(&["apollo.headers"])).err().unwrap();
+        assert_eq!(errors.len(), 4);
+    }
+
+    #[test]
+    fn skips_match_paths_and_listeners() {
+        let skip = PluginSkip {
+            plugin: "apollo.authentication".to_string(),
+            paths: vec!["/internal/*".to_string(), "/health".to_string()],
+            listeners: vec!["0.0.0.0:8088".parse().unwrap()],
+        };
+        let internal = Some("10.0.0.1:8088".parse().unwrap());
+        let public = Some("10.0.0.1:4000".parse().unwrap());
+        assert!(skip.matches("/health", internal));
+        assert!(skip.matches("/internal/graphql", internal));
+        assert!(!skip.matches("/graphql", internal));
+        assert!(!skip.matches("/health", public));
+        assert!(!skip.matches("/health", None));
+    }
+
+    #[test]
+    fn skipped_plugins_are_not_in_the_context_entries() {
+        let context = Context::new();
+        // a context entry set by a script or a coprocessor doesn't skip the plugin
+        let _ = context.insert(
+            "apollo::plugin_execution::skipped::apollo.authentication",
+            true,
+        );
+        assert!(!is_skipped(&context, "apollo.authentication"));
+
+        context.extensions().with_lock(|mut lock| {
+            lock.get_or_default_mut::<SkippedPlugins>()
+                .0
+                .insert("apollo.authentication".to_string())
+        });
+        assert!(is_skipped(&context, "apollo.authentication"));
+        assert!(!is_skipped(&context, "acme.audit"));
+    }
+}
@@ -0,0 +1,54 @@
+//! RFC 9457 problem details for the requests rejected before GraphQL processing
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Problem details configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ProblemDetails {
+    /// Respond with `application/problem+json` bodies to the requests rejected with one of the
+    /// configured status codes, instead of GraphQL error bodies or empty bodies (disabled by
+    /// default)
+    pub(crate) enabled: bool,
+
+    /// Status codes of the rejections answered with problem details (default: 401, 413, 415 and
+    /// 429)
+    pub(crate) status_codes: Vec<u16>,
+
+    /// Type URI of the problems without a specific type (default: `about:blank`)
+    pub(crate) default_type: String,
+
+    /// Type URIs of the problems, by error code like `INVALID_CONTENT_TYPE_HEADER` or by status
+    /// code like `"429"`. The error code takes precedence over the status code
+    pub(crate) types: HashMap<String, String>,
+
+    /// Header carrying the correlation ID of the requests. The correlation ID of a request
+    /// without this header is generated. It is added to the problem details and to this header
+    /// of the response
+    pub(crate) correlation_id_header: String,
+}
+
+impl Default for ProblemDetails {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            status_codes: vec![401, 413, 415, 429],
+            default_type: "about:blank".to_string(),
+            types: HashMap::new(),
+            correlation_id_header: "x-correlation-id".to_string(),
+        }
+    }
+}
+
+impl ProblemDetails {
+    /// Returns the type URI of a problem
+    pub(crate) fn type_uri(&self, code: Option<&str>, status: u16) -> &str {
+        code.and_then(|code| self.types.get(code))
+            .or_else(|| self.types.get(&status.to_string()))
+            .unwrap_or(&self.default_type)
+    }
+}
@@ -305,6 +305,7 @@ pub(crate) fn validate_yaml_configuration(
         });
     }
     config.validated_yaml = Some(expanded_yaml);
+    config.unexpanded_yaml = Some(yaml);
     Ok(config)
 }
 
@@ -0,0 +1,446 @@
+//! Secrets referenced by the configuration.
+//!
+//! Configuration values may reference secrets stored in HashiCorp Vault with
+//! `${secret.vault:<path>#<field>}`, or in AWS Secrets Manager with `${secret.aws:<secret id>}`
+//! (`${secret.aws:<secret id>#<field>}` for a JSON secret). They are fetched before the
+//! configuration is expanded, and fetched again periodically so that rotated secrets are applied.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use aws_config::default_provider::credentials::DefaultCredentialsChain;
+use aws_config::default_provider::region::DefaultRegionChain;
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sigv4::http_request::sign;
+use aws_sigv4::http_request::SignableBody;
+use aws_sigv4::http_request::SignableRequest;
+use aws_sigv4::http_request::SigningSettings;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+use tower::BoxError;
+
+use super::ConfigurationError;
+
+const SECRET_PREFIX: &str = "secret.";
+const FILE_PREFIX: &str = "file.";
+const AWS_TARGET: &str = "secretsmanager.GetSecretValue";
+const AWS_CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Secrets configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Secrets {
+    /// Interval between two fetches of the secrets referenced by the configuration. The
+    /// configuration is reloaded when one of them was rotated (default: 5m)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(crate) refresh_interval: Duration,
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self {
+            refresh_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+/// A store that secrets can be fetched from.
+#[async_trait]
+pub(crate) trait SecretProvider: Send + Sync {
+    /// Fetch a secret. The reference is the part of the variable following
+    /// `secret.<provider>:`.
+    async fn fetch(&self, reference: &str) -> Result<String, BoxError>;
+}
+
+/// Fetches the secrets referenced by a configuration from their providers.
+pub(crate) struct SecretResolver {
+    providers: HashMap<&'static str, Box<dyn SecretProvider>>,
+}
+
+impl Default for SecretResolver {
+    fn default() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("the secrets client configuration is valid");
+        Self::new()
+            .with_provider("vault", VaultProvider::from_env(client.clone()))
+            .with_provider("aws", AwsSecretsManagerProvider::new(client))
+    }
+}
+
+impl SecretResolver {
+    pub(crate) fn new() -> Self {
+        Self {
+            providers: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn with_provider(
+        mut self,
+        name: &'static str,
+        provider: impl SecretProvider + 'static,
+    ) -> Self {
+        self.providers.insert(name, Box::new(provider));
+        self
+    }
+
+    /// Fetch every secret referenced by the raw configuration, keyed by variable name (e.g.
+    /// `secret.vault:kv/data/router#api_key`).
+    pub(crate) async fn resolve(
+        &self,
+        raw_yaml: &str,
+    ) -> Result<HashMap<String, String>, ConfigurationError> {
+        let mut secrets = HashMap::new();
+        for key in references(raw_yaml) {
+            if secrets.contains_key(&key) {
+                continue;
+            }
+            let cannot_expand = |cause: String| ConfigurationError::CannotExpandVariable {
+                key: key.clone(),
+                cause,
+            };
+            let (provider, reference) =
+                key[SECRET_PREFIX.len()..].split_once(':').ok_or_else(|| {
+                    cannot_expand(
+                        "secrets must be referenced as 'secret.<provider>:<reference>'".to_string(),
+                    )
+                })?;
+            let provider = self
+                .providers
+                .get(provider)
+                .ok_or_else(|| cannot_expand(format!("unknown secret provider '{provider}'")))?;
+            let value = provider
+                .fetch(reference)
+                .await
+                .map_err(|err| cannot_expand(err.to_string()))?;
+            secrets.insert(key, value);
+        }
+        Ok(secrets)
+    }
+}
+
+/// Names of the secret variables referenced by the raw configuration, including the ones
+/// referenced by the files it includes with `${file.*}` variables.
+pub(crate) fn references(raw_yaml: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    for variable in variables(raw_yaml) {
+        if variable.starts_with(SECRET_PREFIX) {
+            references.push(variable.to_string());
+        } else if let Some(path) = variable.strip_prefix(FILE_PREFIX) {
+            // missing files are reported when the configuration is expanded
+            if let Ok(fragment) = std::fs::read_to_string(path) {
+                references.extend(
+                    variables(&fragment)
+                        .filter(|variable| variable.starts_with(SECRET_PREFIX))
+                        .map(str::to_string),
+                );
+            }
+        }
+    }
+    references
+}
+
+/// Names of the variables of a text.
+fn variables(text: &str) -> impl Iterator<Item = &str> {
+    text.match_indices("${").filter_map(|(start, _)| {
+        let variable = &text[start + 2..];
+        let end = variable.find('}')?;
+        // A default value is not part of the variable name.
+        variable[..end].split(":-").next()
+    })
+}
+
+fn split_field(reference: &str) -> (&str, Option<&str>) {
+    match reference.rsplit_once('#') {
+        Some((id, field)) => (id, Some(field)),
+        None => (reference, None),
+    }
+}
+
+fn field_value(data: &Value, field: &str) -> Result<String, BoxError> {
+    match data.get(field) {
+        Some(Value::String(value)) => Ok(value.clone()),
+        Some(value) => Ok(value.to_string()),
+        None => Err(format!("the secret has no field '{field}'").into()),
+    }
+}
+
+/// Reads secrets from the KV secrets engine of HashiCorp Vault.
+///
+/// The server and token are taken from `VAULT_ADDR` and `VAULT_TOKEN` (or `~/.vault-token`),
+/// and the namespace from `VAULT_NAMESPACE`.
+pub(crate) struct VaultProvider {
+    client: reqwest::Client,
+    address: Option<String>,
+    token: Option<String>,
+    namespace: Option<String>,
+}
+
+impl VaultProvider {
+    pub(crate) fn from_env(client: reqwest::Client) -> Self {
+        let token = std::env::var("VAULT_TOKEN").ok().or_else(|| {
+            let home = std::env::var("HOME").ok()?;
+            std::fs::read_to_string(std::path::Path::new(&home).join(".vault-token"))
+                .ok()
+                .map(|token| token.trim().to_string())
+        });
+        Self {
+            client,
+            address: std::env::var("VAULT_ADDR").ok(),
+            token,
+            namespace: std::env::var("VAULT_NAMESPACE").ok(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretProvider for VaultProvider {
+    async fn fetch(&self, reference: &str) -> Result<String, BoxError> {
+        let (path, field) = split_field(reference);
+        let field = field.ok_or("vault secrets must be referenced as '<path>#<field>'")?;
+        let address = self.address.as_deref().ok_or("VAULT_ADDR is not set")?;
+        let mut request = self.client.get(format!(
+            "{}/v1/{}",
+            address.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        ));
+        if let Some(token) = &self.token {
+            request = request.header("X-Vault-Token", token);
+        }
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response: Value = request.send().await?.error_for_status()?.json().await?;
+        let data = response
+            .get("data")
+            .ok_or("the vault response has no data")?;
+        // Version 2 of the KV engine nests the secret in a second `data` object.
+        match data.get("data") {
+            Some(nested) if nested.get(field).is_some() => field_value(nested, field),
+            _ => field_value(data, field),
+        }
+    }
+}
+
+/// Reads secrets from AWS Secrets Manager, with credentials from the default AWS chain.
+///
+/// The region is taken from the secret ARN, or from the default AWS chain when the secret is
+/// referenced by name.
+pub(crate) struct AwsSecretsManagerProvider {
+    client: reqwest::Client,
+    credentials: OnceCell<DefaultCredentialsChain>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetSecretValueRequest<'a> {
+    secret_id: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetSecretValueResponse {
+    secret_string: Option<String>,
+}
+
+impl AwsSecretsManagerProvider {
+    pub(crate) fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            credentials: OnceCell::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretProvider for AwsSecretsManagerProvider {
+    async fn fetch(&self, reference: &str) -> Result<String, BoxError> {
+        let (secret_id, field) = split_field(reference);
+        let region = match secret_id.strip_prefix("arn:") {
+            Some(arn) => arn
+                .split(':')
+                .nth(2)
+                .filter(|region| !region.is_empty())
+                .ok_or("invalid secret ARN")?
+                .to_string(),
+            None => DefaultRegionChain::builder()
+                .build()
+                .region()
+                .await
+                .map(|region| region.to_string())
+                .unwrap_or_else(|| "us-east-1".to_string()),
+        };
+        let url = std::env::var("AWS_ENDPOINT_URL_SECRETS_MANAGER")
+            .or_else(|_| std::env::var("AWS_ENDPOINT_URL"))
+            .unwrap_or_else(|_| format!("https://secretsmanager.{region}.amazonaws.com"));
+        let body = serde_json::to_vec(&GetSecretValueRequest { secret_id })?;
+
+        let identity = self
+            .credentials
+            .get_or_init(|| DefaultCredentialsChain::builder().build())
+            .await
+            .provide_credentials()
+            .await?
+            .into();
+        let signing_params = aws_sigv4::sign::v4::SigningParams::builder()
+            .identity(&identity)
+            .region(&region)
+            .name("secretsmanager")
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()?;
+        let headers = [
+            ("content-type", AWS_CONTENT_TYPE),
+            ("x-amz-target", AWS_TARGET),
+        ];
+        let signable_request = SignableRequest::new(
+            "POST",
+            url.as_str(),
+            headers.into_iter(),
+            SignableBody::Bytes(&body),
+        )?;
+        let (instructions, _signature) =
+            sign(signable_request, &signing_params.into())?.into_parts();
+
+        let mut request = self.client.post(url.as_str()).body(body.clone());
+        for (name, value) in headers.into_iter().chain(instructions.headers()) {
+            request = request.header(name, value);
+        }
+        let response: GetSecretValueResponse =
+            request.send().await?.error_for_status()?.json().await?;
+        let secret = response
+            .secret_string
+            .ok_or("binary secrets are not supported")?;
+        match field {
+            Some(field) => field_value(&serde_json::from_str(&secret)?, field),
+            None => Ok(secret),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+
+    struct Static;
+
+    #[async_trait]
+    impl SecretProvider for Static {
+        async fn fetch(&self, reference: &str) -> Result<String, BoxError> {
+            Ok(format!("value of {reference}"))
+        }
+    }
+
+    #[test]
+    fn secret_references() {
+        let raw = r#"
+telemetry:
+  apollo:
+    endpoint: ${env.APOLLO_ENDPOINT}
+apq:
+  router:
+    cache:
+      redis:
+        password: "${secret.vault:kv/data/redis#password}"
+headers:
+  all:
+    request:
+      - insert:
+          name: x-api-key
+          value: ${secret.aws:arn:aws:secretsmanager:eu-west-1:123456789012:secret:api-key}
+"#;
+        assert_eq!(
+            references(raw),
+            vec![
+                "secret.vault:kv/data/redis#password",
+                "secret.aws:arn:aws:secretsmanager:eu-west-1:123456789012:secret:api-key"
+            ]
+        );
+    }
+
+    #[test]
+    fn secret_references_in_files() {
+        let mut fragment = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut fragment,
+            b"-----BEGIN KEY-----\n${secret.vault:kv/data/tls#key}\n-----END KEY-----",
+        )
+        .unwrap();
+        let raw = format!(
+            "tls:\n  key: ${{file.{}}}\nother: ${{file./does/not/exist}}",
+            fragment.path().display()
+        );
+        assert_eq!(references(&raw), vec!["secret.vault:kv/data/tls#key"]);
+    }
+
+    #[tokio::test]
+    async fn resolve_secrets() {
+        let resolver = SecretResolver::new().with_provider("static", Static);
+        let secrets = resolver
+            .resolve("a: ${secret.static:a}\nb: ${secret.static:b#field}\nc: ${secret.static:a}")
+            .await
+            .unwrap();
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(secrets["secret.static:a"], "value of a");
+        assert_eq!(secrets["secret.static:b#field"], "value of b#field");
+
+        assert!(matches!(
+            resolver.resolve("a: ${secret.unknown:a}").await,
+            Err(ConfigurationError::CannotExpandVariable { .. })
+        ));
+        assert!(matches!(
+            resolver.resolve("a: ${secret.static}").await,
+            Err(ConfigurationError::CannotExpandVariable { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn vault_kv_secrets() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/kv/data/redis"))
+            .and(header("X-Vault-Token", "token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "data": { "password": "s3cr3t", "port": 6379 }, "metadata": {} }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/secret/redis"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "password": "v1" }
+            })))
+            .mount(&server)
+            .await;
+        let vault = VaultProvider {
+            client: reqwest::Client::new(),
+            address: Some(server.uri()),
+            token: Some("token".to_string()),
+            namespace: None,
+        };
+
+        assert_eq!(
+            vault.fetch("kv/data/redis#password").await.unwrap(),
+            "s3cr3t"
+        );
+        assert_eq!(vault.fetch("kv/data/redis#port").await.unwrap(), "6379");
+        assert_eq!(vault.fetch("secret/redis#password").await.unwrap(), "v1");
+        assert!(vault.fetch("kv/data/redis#missing").await.is_err());
+        assert!(vault.fetch("kv/data/redis").await.is_err());
+    }
+}
@@ -7,4 +7,17 @@ use crate::plugins::traffic_shaping::Http2Config;
 #[serde(deny_unknown_fields, default)]
 pub(crate) struct Client {
     pub(crate) experimental_http2: Option<Http2Config>,
+    /// Authenticate with the SPIFFE workload identity of the router (`tls.spiffe`), and only
+    /// accept server certificates that are SVIDs of the accepted trust domains
+    pub(crate) spiffe: bool,
+}
+
+/// How information is returned to the clients
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Expose {
+    /// Return it in response headers
+    pub(crate) headers: bool,
+    /// Return it in the response extensions
+    pub(crate) extensions: bool,
 }
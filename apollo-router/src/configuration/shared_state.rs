@@ -0,0 +1,508 @@
+//! State shared by the router replicas, to enforce limits across all the replicas.
+//!
+//! With a shared store, the global rate limit of the router and the rate limits of the subgraphs
+//! count the requests of all the replicas, and the global limit on active subscriptions counts
+//! their subscriptions. Each replica counts locally and synchronizes its counts with the store
+//! periodically, so the store isn't queried for every request. While the store is unavailable,
+//! each replica enforces the limits on its own requests, as without a shared store.
+//!
+//! Cost budgets are out of scope: the demand control only limits the cost of each operation, so
+//! it has no count to share.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use arc_swap::ArcSwapOption;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use uuid::Uuid;
+
+use crate::cache::redis::RedisCacheStorage;
+use crate::cache::redis::RedisKey;
+use crate::configuration::RedisCache;
+
+/// Identifies the values reported by this replica
+static REPLICA_ID: Lazy<String> = Lazy::new(|| Uuid::new_v4().simple().to_string());
+
+/// The store of the running configuration
+static STORE: Lazy<ArcSwapOption<Shared>> = Lazy::new(ArcSwapOption::empty);
+
+/// Number of synchronization intervals after which the value of a replica is ignored
+const STALE_INTERVALS: u32 = 5;
+
+/// State shared by the router replicas
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SharedState {
+    /// Redis instance storing the shared state. Without it, each replica enforces the limits on
+    /// its own requests.
+    pub(crate) redis: Option<RedisCache>,
+
+    /// Interval of the synchronization of the local counts with the store (default: 100ms)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(crate) sync_interval: Duration,
+}
+
+impl Default for SharedState {
+    fn default() -> Self {
+        Self {
+            redis: None,
+            sync_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Store of the counters shared by the router replicas
+#[async_trait::async_trait]
+pub(crate) trait SharedStore: Send + Sync + 'static {
+    /// Adds an amount to a counter expiring after the expiration, and returns the total of the
+    /// counter
+    async fn add(&self, key: &str, amount: u64, expiration: Duration) -> Result<u64, BoxError>;
+
+    /// Reports the value of this replica for a gauge, and returns the sum of the values of the
+    /// other replicas reported within the expiration
+    async fn report(&self, key: &str, value: u64, expiration: Duration) -> Result<u64, BoxError>;
+}
+
+struct RedisStore(RedisCacheStorage);
+
+#[async_trait::async_trait]
+impl SharedStore for RedisStore {
+    async fn add(&self, key: &str, amount: u64, expiration: Duration) -> Result<u64, BoxError> {
+        Ok(self
+            .0
+            .increment(RedisKey(key.to_string()), amount, expiration)
+            .await?)
+    }
+
+    async fn report(&self, key: &str, value: u64, expiration: Duration) -> Result<u64, BoxError> {
+        let now = now_millis();
+        let values = self
+            .0
+            .hash_insert_and_get_all(
+                RedisKey(key.to_string()),
+                REPLICA_ID.clone(),
+                format!("{value}:{now}"),
+                expiration,
+            )
+            .await?;
+
+        let mut others = 0;
+        let mut stale = Vec::new();
+        for (replica, reported) in values {
+            if replica == *REPLICA_ID {
+                continue;
+            }
+            let Some((value, reported_at)) = reported.split_once(':').and_then(|(value, at)| {
+                Some((value.parse::<u64>().ok()?, at.parse::<u64>().ok()?))
+            }) else {
+                stale.push(replica);
+                continue;
+            };
+            if now.saturating_sub(reported_at) > expiration.as_millis() as u64 {
+                stale.push(replica);
+            } else {
+                others += value;
+            }
+        }
+        // the replicas that stopped without removing their values
+        if !stale.is_empty() {
+            self.0.hash_delete(RedisKey(key.to_string()), stale).await?;
+        }
+        Ok(others)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time must be after EPOCH")
+        .as_millis() as u64
+}
+
+struct Shared {
+    /// Serialized configuration, compared on reloads to keep the connection to the store
+    config: serde_json::Value,
+    store: Arc<dyn SharedStore>,
+    sync_interval: Duration,
+}
+
+/// Counter of the requests of all the replicas, in fixed windows
+#[derive(Debug)]
+pub(crate) struct WindowCounter {
+    key: String,
+    capacity: u64,
+    window: Duration,
+    state: Mutex<WindowState>,
+}
+
+#[derive(Debug, Default)]
+struct WindowState {
+    /// Index of the current window since the UNIX epoch
+    index: u64,
+    /// Total of all the replicas at the last synchronization
+    total: u64,
+    /// Requests counted since the last synchronization
+    pending: u64,
+    /// Whether the last synchronization succeeded
+    available: bool,
+}
+
+impl WindowState {
+    fn roll(&mut self, index: u64) {
+        if self.index != index {
+            self.index = index;
+            self.total = 0;
+            self.pending = 0;
+        }
+    }
+}
+
+impl WindowCounter {
+    fn index(&self) -> u64 {
+        now_millis() / (self.window.as_millis() as u64).max(1)
+    }
+
+    /// Counts a request if the capacity of the current window isn't reached by all the replicas.
+    /// Returns `None` while the store is unavailable.
+    pub(crate) fn try_acquire(&self) -> Option<bool> {
+        let index = self.index();
+        let mut state = self.state.lock().expect("lock poisoned");
+        if !state.available {
+            return None;
+        }
+        state.roll(index);
+        if state.total + state.pending >= self.capacity {
+            return Some(false);
+        }
+        state.pending += 1;
+        Some(true)
+    }
+
+    /// Returns the number of requests remaining in the current window of all the replicas, and
+    /// the time until the end of the window. Returns `None` while the store is unavailable.
+    pub(crate) fn remaining(&self) -> Option<(u64, Duration)> {
+        let now = now_millis();
+        let window = (self.window.as_millis() as u64).max(1);
+        let state = self.state.lock().expect("lock poisoned");
+        if !state.available {
+            return None;
+        }
+        let used = if state.index == now / window {
+            state.total + state.pending
+        } else {
+            0
+        };
+        Some((
+            self.capacity.saturating_sub(used),
+            Duration::from_millis(window - now % window),
+        ))
+    }
+
+    async fn synchronize(&self, store: &dyn SharedStore) {
+        let index = self.index();
+        let pending = {
+            let mut state = self.state.lock().expect("lock poisoned");
+            state.roll(index);
+            std::mem::take(&mut state.pending)
+        };
+        let result = store
+            .add(&format!("{}:{index}", self.key), pending, self.window * 2)
+            .await;
+
+        let mut state = self.state.lock().expect("lock poisoned");
+        match result {
+            Ok(total) => {
+                if state.index == index {
+                    state.total = total;
+                }
+                state.available = true;
+            }
+            Err(e) => {
+                // the requests are added at the next synchronization of the window
+                if state.index == index {
+                    state.pending += pending;
+                }
+                if state.available {
+                    tracing::warn!(
+                        key = %self.key,
+                        "the shared state store is unavailable, limits are enforced per replica: {e}"
+                    );
+                }
+                state.available = false;
+            }
+        }
+    }
+}
+
+/// Gauge summing the values of all the replicas, like their active subscriptions
+#[derive(Debug)]
+pub(crate) struct SharedGauge {
+    key: String,
+    state: Mutex<GaugeState>,
+}
+
+#[derive(Debug, Default)]
+struct GaugeState {
+    /// Value of this replica
+    local: u64,
+    /// Sum of the values of the other replicas at the last synchronization, if it succeeded
+    others: Option<u64>,
+}
+
+impl SharedGauge {
+    /// Sets the value of this replica
+    pub(crate) fn set(&self, value: u64) {
+        self.state.lock().expect("lock poisoned").local = value;
+    }
+
+    /// Returns the sum of the values of the other replicas. Returns `None` while the store is
+    /// unavailable.
+    pub(crate) fn others(&self) -> Option<u64> {
+        self.state.lock().expect("lock poisoned").others
+    }
+
+    async fn synchronize(&self, store: &dyn SharedStore, expiration: Duration) {
+        let local = self.state.lock().expect("lock poisoned").local;
+        let result = store.report(&self.key, local, expiration).await;
+
+        let mut state = self.state.lock().expect("lock poisoned");
+        match result {
+            Ok(others) => state.others = Some(others),
+            Err(e) => {
+                if state.others.is_some() {
+                    tracing::warn!(
+                        key = %self.key,
+                        "the shared state store is unavailable, limits are enforced per replica: {e}"
+                    );
+                }
+                state.others = None;
+            }
+        }
+    }
+}
+
+/// Connects to the shared state store on a configuration change
+pub(crate) async fn configure(config: &SharedState) -> Result<(), BoxError> {
+    let Some(redis) = &config.redis else {
+        STORE.store(None);
+        return Ok(());
+    };
+    let serialized_config = serde_json::to_value(config)?;
+    if STORE
+        .load()
+        .as_ref()
+        .is_some_and(|shared| shared.config == serialized_config)
+    {
+        return Ok(());
+    }
+
+    let store = match RedisCacheStorage::new(redis.clone(), "shared_state").await {
+        Ok(storage) => Arc::new(RedisStore(storage)),
+        Err(e) => {
+            tracing::error!(
+                e,
+                "could not open connection to the Redis of the shared state"
+            );
+            if redis.required_to_start {
+                return Err(e);
+            }
+            STORE.store(None);
+            return Ok(());
+        }
+    };
+    STORE.store(Some(Arc::new(Shared {
+        config: serialized_config,
+        store,
+        sync_interval: config.sync_interval,
+    })));
+    Ok(())
+}
+
+/// Returns a counter of the requests of all the replicas in fixed windows, if a shared store is
+/// configured. The counter is synchronized with the store until it is dropped.
+pub(crate) fn window_counter(
+    key: &str,
+    capacity: u64,
+    window: Duration,
+) -> Option<Arc<WindowCounter>> {
+    let shared = STORE.load_full()?;
+    let counter = Arc::new(WindowCounter {
+        key: format!("shared_state:{key}"),
+        capacity,
+        window,
+        state: Default::default(),
+    });
+    let weak = Arc::downgrade(&counter);
+    spawn_synchronization(shared, weak, |counter, shared| async move {
+        counter.synchronize(&*shared.store).await
+    });
+    Some(counter)
+}
+
+/// Returns a gauge summing the values of all the replicas, if a shared store is configured. The
+/// gauge is synchronized with the store until it is dropped.
+pub(crate) fn gauge(key: &str) -> Option<Arc<SharedGauge>> {
+    let shared = STORE.load_full()?;
+    let gauge = Arc::new(SharedGauge {
+        key: format!("shared_state:{key}"),
+        state: Default::default(),
+    });
+    let weak = Arc::downgrade(&gauge);
+    spawn_synchronization(shared, weak, |gauge, shared| async move {
+        let expiration = shared.sync_interval * STALE_INTERVALS;
+        gauge.synchronize(&*shared.store, expiration).await
+    });
+    Some(gauge)
+}
+
+fn spawn_synchronization<T, F, Fut>(shared: Arc<Shared>, weak: Weak<T>, synchronize: F)
+where
+    T: Send + Sync + 'static,
+    F: Fn(Arc<T>, Arc<Shared>) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = ()> + Send,
+{
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(shared.sync_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            // the synchronization stops when the limit is dropped, on a reload
+            let Some(value) = weak.upgrade() else {
+                break;
+            };
+            synchronize(value, shared.clone()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Store of a single replica, counting in memory
+    #[derive(Default)]
+    struct MemoryStore {
+        counters: Mutex<HashMap<String, u64>>,
+        others: u64,
+    }
+
+    #[async_trait::async_trait]
+    impl SharedStore for MemoryStore {
+        async fn add(
+            &self,
+            key: &str,
+            amount: u64,
+            _expiration: Duration,
+        ) -> Result<u64, BoxError> {
+            let mut counters = self.counters.lock().unwrap();
+            let counter = counters.entry(key.to_string()).or_default();
+            // another replica counted 5 requests in the window
+            if *counter == 0 {
+                *counter = 5;
+            }
+            *counter += amount;
+            Ok(*counter)
+        }
+
+        async fn report(
+            &self,
+            _key: &str,
+            _value: u64,
+            _expiration: Duration,
+        ) -> Result<u64, BoxError> {
+            Ok(self.others)
+        }
+    }
+
+    struct UnavailableStore;
+
+    #[async_trait::async_trait]
+    impl SharedStore for UnavailableStore {
+        async fn add(&self, _: &str, _: u64, _: Duration) -> Result<u64, BoxError> {
+            Err("connection refused".into())
+        }
+
+        async fn report(&self, _: &str, _: u64, _: Duration) -> Result<u64, BoxError> {
+            Err("connection refused".into())
+        }
+    }
+
+    fn counter(capacity: u64) -> WindowCounter {
+        WindowCounter {
+            key: "test".to_string(),
+            capacity,
+            window: Duration::from_secs(3600),
+            state: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_the_requests_of_all_replicas() {
+        let store = MemoryStore::default();
+        let counter = counter(8);
+        // unavailable until the first synchronization
+        assert_eq!(counter.try_acquire(), None);
+
+        counter.synchronize(&store).await;
+        assert_eq!(counter.try_acquire(), Some(true));
+        assert_eq!(counter.try_acquire(), Some(true));
+        assert_eq!(counter.try_acquire(), Some(true));
+        assert_eq!(counter.try_acquire(), Some(false));
+
+        counter.synchronize(&store).await;
+        assert_eq!(counter.state.lock().unwrap().total, 8);
+        assert_eq!(counter.try_acquire(), Some(false));
+
+        counter.synchronize(&UnavailableStore).await;
+        assert_eq!(counter.try_acquire(), None);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_requests_counted_while_the_store_is_unavailable() {
+        let store = MemoryStore::default();
+        let counter = counter(10);
+        counter.synchronize(&store).await;
+        assert_eq!(counter.try_acquire(), Some(true));
+        assert_eq!(counter.try_acquire(), Some(true));
+
+        counter.synchronize(&UnavailableStore).await;
+        assert_eq!(counter.state.lock().unwrap().pending, 2);
+
+        counter.synchronize(&store).await;
+        assert_eq!(counter.state.lock().unwrap().total, 7);
+        assert_eq!(counter.state.lock().unwrap().pending, 0);
+    }
+
+    #[tokio::test]
+    async fn gauges_sum_the_other_replicas() {
+        let gauge = SharedGauge {
+            key: "test".to_string(),
+            state: Default::default(),
+        };
+        gauge.set(2);
+        assert_eq!(gauge.others(), None);
+
+        let store = MemoryStore {
+            others: 3,
+            ..Default::default()
+        };
+        gauge.synchronize(&store, Duration::from_secs(1)).await;
+        assert_eq!(gauge.others(), Some(3));
+
+        gauge
+            .synchronize(&UnavailableStore, Duration::from_secs(1))
+            .await;
+        assert_eq!(gauge.others(), None);
+    }
+}
@@ -0,0 +1,505 @@
+//! SPIFFE workload identity of the router.
+//!
+//! The router fetches its X.509 SVID from the SPIFFE workload API, usually served by a SPIRE
+//! agent, and keeps it up to date as the agent rotates it. The subgraph, coprocessor and Redis
+//! connections using the workload identity present the SVID as client certificate, and only
+//! accept server certificates that are SVIDs of the accepted trust domains, verified against the
+//! trust bundles received from the workload API.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use arc_swap::ArcSwap;
+use arc_swap::ArcSwapOption;
+use http::uri::PathAndQuery;
+use http::Uri;
+use once_cell::sync::Lazy;
+use rustls::client::ResolvesClientCert;
+use rustls::client::ServerCertVerified;
+use rustls::client::ServerCertVerifier;
+use rustls::sign::CertifiedKey;
+use rustls::Certificate;
+use rustls::ClientConfig;
+use rustls::PrivateKey;
+use rustls::ServerName;
+use rustls::SignatureScheme;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tonic::codec::Codec;
+use tonic::codec::DecodeBuf;
+use tonic::codec::Decoder;
+use tonic::codec::EncodeBuf;
+use tonic::codec::Encoder;
+use tonic::codec::Streaming;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
+use tonic::transport::Endpoint;
+use tonic::Status;
+use tower::BoxError;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+
+use super::tls::certified_key;
+
+/// Environment variable holding the address of the workload API
+const ENDPOINT_SOCKET_VARIABLE: &str = "SPIFFE_ENDPOINT_SOCKET";
+const FETCH_X509_SVID: &str = "/SpiffeWorkloadAPI/FetchX509SVID";
+/// Maximum time waiting for the first SVID
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time between attempts to reconnect to the workload API
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// The workload identity of the running configuration
+static SOURCE: Lazy<ArcSwapOption<Source>> = Lazy::new(ArcSwapOption::empty);
+
+/// SPIFFE workload identity, used by the subgraph, coprocessor and Redis connections enabling
+/// `spiffe`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Spiffe {
+    /// Address of the SPIFFE workload API, like `unix:///run/spire/agent.sock` or
+    /// `tcp://127.0.0.1:8081` (default: the `SPIFFE_ENDPOINT_SOCKET` environment variable)
+    pub(crate) endpoint_socket: Option<String>,
+    /// Trust domains of the accepted server certificates (default: the trust domain of the
+    /// router)
+    pub(crate) trust_domains: Vec<String>,
+}
+
+/// An X.509 SVID of the router and the trust bundles used to verify the servers
+struct Identity {
+    spiffe_id: String,
+    trust_domain: String,
+    certified_key: Arc<CertifiedKey>,
+    /// DER encoded trust anchors, by trust domain
+    bundles: HashMap<String, Vec<Certificate>>,
+}
+
+impl Identity {
+    fn new(response: X509SvidResponse) -> Result<Self, BoxError> {
+        // The first SVID is the default identity of the workload
+        let svid = response
+            .svids
+            .into_iter()
+            .next()
+            .ok_or("the SPIFFE workload API returned no X.509 SVID")?;
+        let trust_domain = trust_domain(&svid.spiffe_id)
+            .ok_or_else(|| format!("invalid SPIFFE ID: {}", svid.spiffe_id))?
+            .to_string();
+        let certified_key = certified_key(
+            split_der(&svid.x509_svid)?,
+            &PrivateKey(svid.x509_svid_key),
+            None,
+        )?;
+
+        let mut bundles = HashMap::new();
+        for (federated_domain, bundle) in response.federated_bundles {
+            let federated_domain = federated_domain
+                .strip_prefix("spiffe://")
+                .unwrap_or(&federated_domain)
+                .to_string();
+            bundles.insert(federated_domain, split_der(&bundle)?);
+        }
+        bundles.insert(trust_domain.clone(), split_der(&svid.bundle)?);
+
+        Ok(Self {
+            spiffe_id: svid.spiffe_id,
+            trust_domain,
+            certified_key: Arc::new(certified_key),
+            bundles,
+        })
+    }
+}
+
+/// The workload identity, kept up to date with the workload API
+struct Source {
+    config: Spiffe,
+    identity: Arc<ArcSwap<Identity>>,
+    task: JoinHandle<()>,
+}
+
+impl Source {
+    async fn new(config: Spiffe) -> Result<Self, BoxError> {
+        let endpoint = config
+            .endpoint_socket
+            .clone()
+            .or_else(|| std::env::var(ENDPOINT_SOCKET_VARIABLE).ok())
+            .ok_or_else(|| {
+                format!("the address of the SPIFFE workload API must be set in `tls.spiffe.endpoint_socket` or in the {ENDPOINT_SOCKET_VARIABLE} environment variable")
+            })?;
+
+        let (mut svids, identity) = tokio::time::timeout(FETCH_TIMEOUT, async {
+            let mut svids = fetch(&endpoint).await?;
+            let response = svids
+                .message()
+                .await?
+                .ok_or("the SPIFFE workload API closed the stream")?;
+            Ok::<_, BoxError>((svids, Identity::new(response)?))
+        })
+        .await
+        .map_err(|_| "timed out fetching the X.509 SVID from the SPIFFE workload API")??;
+        tracing::info!(spiffe.id = %identity.spiffe_id, "fetched the SPIFFE workload identity");
+
+        let identity = Arc::new(ArcSwap::from_pointee(identity));
+        let task_identity = identity.clone();
+        let task = tokio::task::spawn(async move {
+            loop {
+                match svids.message().await {
+                    Ok(Some(response)) => {
+                        match Identity::new(response) {
+                            Ok(identity) => {
+                                tracing::info!(spiffe.id = %identity.spiffe_id, "rotated the SPIFFE workload identity");
+                                task_identity.store(Arc::new(identity));
+                            }
+                            Err(e) => {
+                                tracing::error!(error = %e, "invalid X.509 SVID received from the SPIFFE workload API")
+                            }
+                        }
+                        continue;
+                    }
+                    Ok(None) => {
+                        tracing::warn!("the SPIFFE workload API closed the stream, reconnecting")
+                    }
+                    Err(e) => {
+                        tracing::warn!(error = %e, "lost the connection to the SPIFFE workload API, reconnecting")
+                    }
+                }
+                // The current identity is kept while reconnecting
+                loop {
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                    match fetch(&endpoint).await {
+                        Ok(stream) => {
+                            svids = stream;
+                            break;
+                        }
+                        Err(e) => {
+                            tracing::warn!(error = %e, "could not reconnect to the SPIFFE workload API")
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            config,
+            identity,
+            task,
+        })
+    }
+
+    fn accepts(&self, trust_domain: &str, identity: &Identity) -> bool {
+        if self.config.trust_domains.is_empty() {
+            trust_domain == identity.trust_domain
+        } else {
+            self.config
+                .trust_domains
+                .iter()
+                .any(|accepted| accepted == trust_domain)
+        }
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Starts, keeps or stops fetching the workload identity on a configuration change
+pub(crate) async fn configure(config: Option<&Spiffe>) -> Result<(), BoxError> {
+    let Some(config) = config else {
+        SOURCE.store(None);
+        return Ok(());
+    };
+    if SOURCE
+        .load()
+        .as_ref()
+        .is_some_and(|source| &source.config == config)
+    {
+        return Ok(());
+    }
+
+    let source = Source::new(config.clone()).await?;
+    SOURCE.store(Some(Arc::new(source)));
+    Ok(())
+}
+
+/// Returns a TLS client configuration presenting the SVID of the router, and accepting the
+/// SVIDs of the accepted trust domains as server certificates
+pub(crate) fn client_config() -> Result<ClientConfig, BoxError> {
+    let source = SOURCE
+        .load_full()
+        .ok_or("the SPIFFE workload identity must be configured in `tls.spiffe`")?;
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(SvidVerifier(source.clone())))
+        .with_client_cert_resolver(Arc::new(SvidResolver(source))))
+}
+
+/// Presents the current SVID of the router
+struct SvidResolver(Arc<Source>);
+
+impl ResolvesClientCert for SvidResolver {
+    fn resolve(
+        &self,
+        _acceptable_issuers: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.identity.load().certified_key.clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// Verifies that the server certificate is an SVID of an accepted trust domain. The server name
+/// isn't verified, as the SPIFFE ID is the identity of the server.
+struct SvidVerifier(Arc<Source>);
+
+impl ServerCertVerifier for SvidVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let identity = self.0.identity.load();
+        let spiffe_id = spiffe_id(&end_entity.0).ok_or_else(|| {
+            rustls::Error::General("the server certificate is not an X.509 SVID".to_string())
+        })?;
+        let trust_domain = trust_domain(&spiffe_id).ok_or_else(|| {
+            rustls::Error::General(format!("invalid SPIFFE ID of the server: {spiffe_id}"))
+        })?;
+        if !self.0.accepts(trust_domain, &identity) {
+            return Err(rustls::Error::General(format!(
+                "the trust domain of the server {spiffe_id} is not accepted"
+            )));
+        }
+        let bundle = identity.bundles.get(trust_domain).ok_or_else(|| {
+            rustls::Error::General(format!(
+                "no trust bundle for the trust domain {trust_domain}"
+            ))
+        })?;
+
+        let anchors = bundle
+            .iter()
+            .map(|certificate| webpki::TrustAnchor::try_from_cert_der(&certificate.0))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| rustls::Error::General(format!("invalid trust bundle: {e:?}")))?;
+        let intermediates: Vec<&[u8]> = intermediates
+            .iter()
+            .map(|certificate| certificate.0.as_slice())
+            .collect();
+        let time =
+            webpki::Time::try_from(now).map_err(|_| rustls::Error::FailedToGetCurrentTime)?;
+        webpki::EndEntityCert::try_from(end_entity.0.as_slice())
+            .and_then(|certificate| {
+                certificate.verify_for_usage(
+                    SUPPORTED_SIG_ALGS,
+                    &anchors,
+                    &intermediates,
+                    time,
+                    webpki::KeyUsage::server_auth(),
+                    &[],
+                )
+            })
+            .map_err(|e| {
+                rustls::Error::General(format!(
+                    "invalid X.509 SVID of the server {spiffe_id}: {e:?}"
+                ))
+            })?;
+
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Returns the SPIFFE ID of an X.509 SVID: its URI subject alternative name
+fn spiffe_id(der: &[u8]) -> Option<String> {
+    let (_, certificate) = X509Certificate::from_der(der).ok()?;
+    let extension = certificate.subject_alternative_name().ok()??;
+    extension
+        .value
+        .general_names
+        .iter()
+        .find_map(|name| match name {
+            GeneralName::URI(uri) if uri.starts_with("spiffe://") => Some(uri.to_string()),
+            _ => None,
+        })
+}
+
+/// Returns the trust domain of a SPIFFE ID
+fn trust_domain(spiffe_id: &str) -> Option<&str> {
+    spiffe_id
+        .strip_prefix("spiffe://")?
+        .split('/')
+        .next()
+        .filter(|trust_domain| !trust_domain.is_empty())
+}
+
+/// Splits concatenated DER encoded certificates, as returned by the workload API
+fn split_der(mut der: &[u8]) -> Result<Vec<Certificate>, BoxError> {
+    let mut certificates = Vec::new();
+    while !der.is_empty() {
+        let (rest, _) = X509Certificate::from_der(der)
+            .map_err(|e| format!("invalid certificate received from the workload API: {e}"))?;
+        certificates.push(Certificate(der[..der.len() - rest.len()].to_vec()));
+        der = rest;
+    }
+    Ok(certificates)
+}
+
+/// Streams the X.509 SVIDs of the workload, and their updates
+async fn fetch(endpoint: &str) -> Result<Streaming<X509SvidResponse>, BoxError> {
+    let mut client = tonic::client::Grpc::new(connect(endpoint).await?);
+    client.ready().await?;
+    let mut request = tonic::Request::new(X509SvidRequest {});
+    // Required by the workload API, to reject requests forwarded by a proxy
+    request
+        .metadata_mut()
+        .insert("workload.spiffe.io", MetadataValue::from_static("true"));
+    Ok(client
+        .server_streaming(
+            request,
+            PathAndQuery::from_static(FETCH_X509_SVID),
+            WorkloadApiCodec,
+        )
+        .await?
+        .into_inner())
+}
+
+async fn connect(endpoint: &str) -> Result<Channel, BoxError> {
+    if let Some(address) = endpoint.strip_prefix("tcp://") {
+        return Ok(Endpoint::from_shared(format!("http://{address}"))?
+            .connect()
+            .await?);
+    }
+    #[cfg(unix)]
+    if let Some(path) = endpoint
+        .strip_prefix("unix://")
+        .or_else(|| endpoint.strip_prefix("unix:"))
+    {
+        let path = path.to_string();
+        // The URI is ignored by the connector
+        return Ok(Endpoint::from_static("http://localhost")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                tokio::net::UnixStream::connect(path.clone())
+            }))
+            .await?);
+    }
+    Err(format!("unsupported address of the SPIFFE workload API: {endpoint}").into())
+}
+
+// Fields of the workload API messages used by the router, from
+// https://github.com/spiffe/go-spiffe/blob/main/proto/spiffe/workload/workload.proto
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct X509SvidRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct X509SvidResponse {
+    #[prost(message, repeated, tag = "1")]
+    svids: Vec<X509Svid>,
+    #[prost(map = "string, bytes", tag = "3")]
+    federated_bundles: HashMap<String, Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct X509Svid {
+    #[prost(string, tag = "1")]
+    spiffe_id: String,
+    #[prost(bytes = "vec", tag = "2")]
+    x509_svid: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    x509_svid_key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "4")]
+    bundle: Vec<u8>,
+}
+
+/// Protobuf codec of the workload API messages
+struct WorkloadApiCodec;
+
+impl Codec for WorkloadApiCodec {
+    type Encode = X509SvidRequest;
+    type Decode = X509SvidResponse;
+    type Encoder = Self;
+    type Decoder = Self;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        Self
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        Self
+    }
+}
+
+impl Encoder for WorkloadApiCodec {
+    type Item = X509SvidRequest;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        prost::Message::encode(&item, dst).map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+impl Decoder for WorkloadApiCodec {
+    type Item = X509SvidResponse;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        <X509SvidResponse as prost::Message>::decode(src)
+            .map(Some)
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::load_certs;
+
+    #[test]
+    fn concatenated_certificates_are_split() {
+        let certificates =
+            load_certs(include_str!("testdata/server.crt")).expect("valid certificate");
+        let mut der = certificates[0].0.clone();
+        der.extend_from_slice(&certificates[0].0);
+        assert_eq!(split_der(&der).unwrap(), vec![certificates[0].clone(); 2]);
+        assert!(split_der(&der[1..]).is_err());
+        assert_eq!(spiffe_id(&der), None);
+    }
+
+    #[test]
+    fn trust_domains() {
+        assert_eq!(
+            trust_domain("spiffe://example.org/ns/default/sa/router"),
+            Some("example.org")
+        );
+        assert_eq!(trust_domain("spiffe://example.org"), Some("example.org"));
+        assert_eq!(trust_domain("spiffe:///router"), None);
+        assert_eq!(trust_domain("https://example.org/router"), None);
+    }
+}
@@ -355,6 +355,28 @@ cors:
     assert_eq!(error, "Invalid CORS configuration: Cannot combine `Access-Control-Allow-Credentials: true` with `Access-Control-Allow-Methods: *`");
 }
 
+#[test]
+fn it_does_not_allow_conflicting_rest_endpoints() {
+    let error = validate_yaml_configuration(
+        r#"
+persisted_queries:
+  enabled: true
+  experimental_rest_endpoints:
+    routes:
+      - path: /api/users/{id}
+        persisted_query_id: user
+      - path: /api/users/{user_id}
+        persisted_query_id: other
+        "#,
+        Expansion::default().unwrap(),
+        Mode::NoUpgrade,
+    )
+    .expect_err("should have resulted in an error");
+    assert!(error
+        .to_string()
+        .contains("the paths '/api/users/{id}' and '/api/users/{user_id}' only differ by the names of their parameters"));
+}
+
 #[test]
 fn it_does_not_allow_invalid_cors_origins() {
     let cfg = validate_yaml_configuration(
@@ -403,6 +425,13 @@ fn validate_project_config_files() {
     std::env::set_var("TEST_CONFIG_ENDPOINT", "http://example.com");
     std::env::set_var("TEST_CONFIG_COLLECTOR_ENDPOINT", "http://example.com");
     std::env::set_var("PARSER_MAX_RECURSION", "500");
+    std::env::set_var("PROMETHEUS_PASSWORD", "pass");
+    std::env::set_var("PROMETHEUS_TOKEN", "token");
+    std::env::set_var("FLAGS_API_KEY", "key");
+    std::env::set_var("PROFILING_KEY", "key");
+    std::env::set_var("CIRCUIT_BREAKER_SHARED_KEY", "key");
+    std::env::set_var("RESPONSE_CACHE_SHARED_KEY", "key");
+    std::env::set_var("FIELD_USAGE_SHARED_KEY", "key");
 
     #[cfg(not(unix))]
     let filename_matcher = Regex::from_str("((.+[.])?router\\.yaml)|(.+\\.mdx)").unwrap();
@@ -615,6 +644,60 @@ supergraph:
     assert!(config.supergraph.introspection);
 }
 
+#[test]
+fn expansion_from_secret() {
+    let config = validate_yaml_configuration(
+        r#"
+supergraph:
+  introspection: ${secret.vault:kv/data/router#introspection}
+        "#,
+        Expansion::builder()
+            .supported_mode("secret")
+            .secret("secret.vault:kv/data/router#introspection", "true")
+            .build(),
+        Mode::NoUpgrade,
+    )
+    .expect("must have expanded successfully");
+    assert!(config.supergraph.introspection);
+
+    let error = validate_yaml_configuration(
+        r#"
+supergraph:
+  introspection: ${secret.vault:kv/data/router#missing}
+        "#,
+        Expansion::builder().supported_mode("secret").build(),
+        Mode::NoUpgrade,
+    )
+    .expect_err("must have failed to expand");
+    assert!(matches!(
+        error,
+        ConfigurationError::CannotExpandVariable { .. }
+    ));
+}
+
+#[test]
+fn expansion_from_secret_in_file() {
+    let mut fragment = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut fragment, b"${secret.vault:kv/data/router#name}").unwrap();
+    let config = validate_yaml_configuration(
+        &format!(
+            r#"
+supergraph:
+  path: /${{file.{}}}
+        "#,
+            fragment.path().display()
+        ),
+        Expansion::builder()
+            .supported_mode("file")
+            .supported_mode("secret")
+            .secret("secret.vault:kv/data/router#name", "graphql")
+            .build(),
+        Mode::NoUpgrade,
+    )
+    .expect("must have expanded successfully");
+    assert_eq!(config.supergraph.path, "/graphql");
+}
+
 #[derive(RustEmbed)]
 #[folder = "src/configuration/testdata/migrations"]
 struct Asset;
@@ -786,6 +869,55 @@ tls:
     cfg.tls.supergraph.unwrap().tls_config().unwrap();
 }
 
+#[test]
+fn load_tls_sni_certificates() {
+    let mut testdata = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    testdata.push("src");
+    testdata.push("configuration");
+    testdata.push("testdata");
+    let cert_path = testdata.join("server.crt");
+    let cert_path = cert_path.to_string_lossy();
+    let key_path = testdata.join("server.key");
+    let key_path = key_path.to_string_lossy();
+
+    let cfg = validate_yaml_configuration(
+        &format!(
+            r#"
+tls:
+  supergraph:
+    certificate: ${{file.{cert_path}}}
+    certificate_chain: ${{file.{cert_path}}}
+    key: ${{file.{key_path}}}
+    min_version: TLSv1.3
+    cipher_suites:
+      - TLS13_AES_256_GCM_SHA384
+    sni_certificates:
+      - server_names: ["local.apollo.dev", "*.apollo.dev"]
+        certificate_path: {cert_path}
+        key_path: {key_path}
+"#,
+        ),
+        Expansion::builder().supported_mode("file").build(),
+        Mode::NoUpgrade,
+    )
+    .expect("should not have resulted in an error");
+    let tls = cfg.tls.supergraph.unwrap();
+    assert_eq!(tls.min_version, TlsVersion::Tls13);
+    tls.tls_config().unwrap();
+
+    let invalid = TlsSupergraph {
+        min_version: TlsVersion::Tls13,
+        cipher_suites: vec!["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256".to_string()],
+        ..tls.clone()
+    };
+    assert!(invalid.tls_config().is_err());
+    let invalid = TlsSupergraph {
+        cipher_suites: vec!["TLS_RSA_WITH_RC4_128_MD5".to_string()],
+        ..tls
+    };
+    assert!(invalid.tls_config().is_err());
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 struct TestSubgraphOverride {
     value: Option<u8>,
@@ -956,7 +1088,14 @@ fn it_defaults_health_check_configuration() {
 #[test]
 fn it_sets_custom_health_check_path() {
     let conf = Configuration::builder()
-        .health_check(HealthCheck::new(None, None, Some("/healthz".to_string())))
+        .health_check(HealthCheck::new(
+            None,
+            None,
+            Some("/healthz".to_string()),
+            None,
+            None,
+            None,
+        ))
         .build()
         .unwrap();
 
@@ -967,7 +1106,14 @@ fn it_sets_custom_health_check_path() {
 fn it_adds_slash_to_custom_health_check_path_if_missing() {
     let conf = Configuration::builder()
         // NB the missing `/`
-        .health_check(HealthCheck::new(None, None, Some("healthz".to_string())))
+        .health_check(HealthCheck::new(
+            None,
+            None,
+            Some("healthz".to_string()),
+            None,
+            None,
+            None,
+        ))
         .build()
         .unwrap();
 
@@ -1077,6 +1223,26 @@ fn it_processes_batching_subgraph_accounts_override_disabled_correctly() {
     assert!(!config.batch_include("accounts"));
 }
 
+#[test]
+fn it_processes_batching_maximum_size_by_client() {
+    let json_config = json!({
+        "enabled": true,
+        "mode": "batch_http_link",
+        "maximum_size": 10,
+        "client_maximum_size": {
+            "dashboard": 50
+        },
+        "operation_timeout": "5s"
+    });
+
+    let config: Batching = serde_json::from_value(json_config).unwrap();
+
+    assert_eq!(config.maximum_size(None), Some(10));
+    assert_eq!(config.maximum_size(Some("web")), Some(10));
+    assert_eq!(config.maximum_size(Some("dashboard")), Some(50));
+    assert_eq!(config.operation_timeout, Some(Duration::from_secs(5)));
+}
+
 #[test]
 fn it_processes_batching_subgraph_accounts_override_enabled_correctly() {
     let json_config = json!({
@@ -1173,6 +1339,32 @@ fn it_requires_rust_apollo_metrics_generation_for_enhanced_signature_normalizati
     );
 }
 
+#[test]
+fn it_requires_rust_apollo_metrics_generation_for_signature_customization() {
+    let mut plugins_config = serde_json::Map::new();
+    plugins_config.insert(
+        "telemetry".to_string(),
+        serde_json::json! {{
+            "apollo": {
+                "experimental_apollo_signature_customization": {
+                    "strip_arguments": ["Query.products(trackingId:)"]
+                }
+            }
+        }},
+    );
+
+    let error = Configuration::builder()
+        .experimental_apollo_metrics_generation_mode(ApolloMetricsGenerationMode::Both)
+        .apollo_plugins(plugins_config)
+        .build()
+        .expect_err("Must have an error because we have conflicting config options");
+
+    assert_eq!(
+        error.to_string(),
+        String::from("`experimental_apollo_signature_customization` requires `experimental_apollo_metrics_generation_mode: new`: either remove the signature customization, or change to new metrics generation")
+    );
+}
+
 #[test]
 fn it_requires_rust_apollo_metrics_generation_for_extended_references() {
     let mut plugins_config = serde_json::Map::new();
@@ -1196,3 +1388,65 @@ fn it_requires_rust_apollo_metrics_generation_for_extended_references() {
         String::from("`experimental_apollo_metrics_reference_mode: extended` requires `experimental_apollo_metrics_generation_mode: new`: either change to the standard reference generation mode, or change to new metrics generation")
     );
 }
+
+#[test]
+fn it_does_not_allow_invalid_field_level_instrumentation_ratios() {
+    for (apollo, expected) in [
+        (
+            serde_json::json!({ "field_level_instrumentation_sampler": 1.5 }),
+            "field level instrumentation sampler ratio must be between 0 and 1",
+        ),
+        (
+            serde_json::json!({ "field_level_instrumentation": { "subgraphs": { "products": -0.5 } } }),
+            "field level instrumentation ratio of subgraph 'products' must be between 0 and 1",
+        ),
+    ] {
+        let mut plugins_config = serde_json::Map::new();
+        plugins_config.insert(
+            "telemetry".to_string(),
+            serde_json::json!({ "apollo": apollo }),
+        );
+
+        let error = Configuration::builder()
+            .apollo_plugins(plugins_config)
+            .build()
+            .expect_err("Must have an error because the ratio is invalid");
+
+        assert_eq!(
+            error.to_string(),
+            format!("invalid field level instrumentation sampling: {expected}")
+        );
+    }
+}
+
+#[test]
+fn it_requires_a_shared_key_for_the_support_bundle_endpoint() {
+    let error = Configuration::builder()
+        .experimental_support_bundle(SupportBundle {
+            enabled: true,
+            ..Default::default()
+        })
+        .build()
+        .expect_err("Must have an error because the shared key is missing");
+
+    assert_eq!(
+        error.to_string(),
+        "invalid 'experimental_support_bundle' configuration: you must set a shared_key for the support bundle endpoint"
+    );
+}
+
+#[test]
+fn it_requires_a_shared_key_for_the_config_diff_endpoint() {
+    let error = Configuration::builder()
+        .experimental_config_diff(ConfigDiff {
+            enabled: true,
+            ..Default::default()
+        })
+        .build()
+        .expect_err("Must have an error because the shared key is missing");
+
+    assert_eq!(
+        error.to_string(),
+        "invalid 'experimental_config_diff' configuration: you must set a shared_key for the configuration diff endpoint"
+    );
+}
@@ -0,0 +1,450 @@
+//! Certificate selection for the TLS server.
+//!
+//! Besides the default certificate, the server may present certificates loaded from files and
+//! selected by the server name requested by the client (SNI). These files are watched, and the
+//! certificates are replaced when they change, without restarting the server.
+//!
+//! Clients may also be asked for a certificate, whose identity is then exposed in the context.
+
+use std::io;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use futures::prelude::*;
+use rustls::server::AllowAnyAnonymousOrAuthenticatedClient;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::server::ClientCertVerifier;
+use rustls::server::ClientHello;
+use rustls::server::ResolvesServerCert;
+use rustls::sign::CertifiedKey;
+use rustls::Certificate;
+use rustls::PrivateKey;
+use rustls::RootCertStore;
+use rustls::SupportedCipherSuite;
+use rustls::SupportedProtocolVersion;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::Value;
+use tokio::sync::oneshot;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+
+use super::deserialize_certificate_chain;
+use super::load_certs;
+use super::load_key;
+use crate::ApolloRouterError;
+use crate::Context;
+
+/// Context key holding the subject of the verified client certificate.
+pub(crate) const CLIENT_CERTIFICATE_SUBJECT: &str = "apollo::tls::client_certificate_subject";
+/// Context key holding the subject alternative names of the verified client certificate.
+pub(crate) const CLIENT_CERTIFICATE_SANS: &str = "apollo::tls::client_certificate_sans";
+
+static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// TLS protocol version
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub(crate) enum TlsVersion {
+    /// TLS 1.2
+    #[default]
+    #[serde(rename = "TLSv1.2")]
+    Tls12,
+    /// TLS 1.3
+    #[serde(rename = "TLSv1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    pub(crate) fn protocol_versions(self) -> &'static [&'static SupportedProtocolVersion] {
+        match self {
+            TlsVersion::Tls12 => rustls::ALL_VERSIONS,
+            TlsVersion::Tls13 => TLS13_ONLY,
+        }
+    }
+}
+
+/// A certificate selected by the server name requested by the client
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TlsSniCertificate {
+    /// Server names this certificate is presented for. `*.example.com` matches any direct
+    /// subdomain of `example.com`, and `*` matches every server name, as well as clients not
+    /// sending one
+    pub(crate) server_names: Vec<String>,
+    /// Path of the certificate in PEM format, followed by its chain
+    pub(crate) certificate_path: PathBuf,
+    /// Path of the key in PEM format
+    pub(crate) key_path: PathBuf,
+    /// Path of a DER encoded OCSP response stapled to handshakes
+    pub(crate) ocsp_response_path: Option<PathBuf>,
+}
+
+impl TlsSniCertificate {
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        [&self.certificate_path, &self.key_path]
+            .into_iter()
+            .chain(self.ocsp_response_path.iter())
+    }
+
+    fn load(&self) -> Result<SniEntry, ApolloRouterError> {
+        let read = |path: &Path| {
+            std::fs::read(path).map_err(|err| {
+                ApolloRouterError::ServerCreationError(io::Error::new(
+                    err.kind(),
+                    format!("could not read {}: {err}", path.display()),
+                ))
+            })
+        };
+        let certificates = load_certs(&String::from_utf8_lossy(&read(&self.certificate_path)?))
+            .map_err(ApolloRouterError::ServerCreationError)?;
+        let key = load_key(&String::from_utf8_lossy(&read(&self.key_path)?))
+            .map_err(ApolloRouterError::ServerCreationError)?;
+        let ocsp = self.ocsp_response_path.as_deref().map(read).transpose()?;
+        Ok(SniEntry {
+            server_names: self
+                .server_names
+                .iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect(),
+            key: Arc::new(certified_key(certificates, &key, ocsp)?),
+        })
+    }
+}
+
+pub(crate) fn certified_key(
+    certificates: Vec<Certificate>,
+    key: &PrivateKey,
+    ocsp: Option<Vec<u8>>,
+) -> Result<CertifiedKey, ApolloRouterError> {
+    if certificates.is_empty() {
+        return Err(ApolloRouterError::Rustls(rustls::Error::General(
+            "expected at least one certificate".to_string(),
+        )));
+    }
+    let key = rustls::sign::any_supported_type(key)
+        .map_err(|err| ApolloRouterError::Rustls(rustls::Error::General(err.to_string())))?;
+    let mut certified_key = CertifiedKey::new(certificates, key);
+    certified_key.ocsp = ocsp;
+    Ok(certified_key)
+}
+
+/// Verification of client certificates
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TlsClientVerification {
+    /// certificate authorities of the client certificates, in PEM format
+    #[serde(deserialize_with = "deserialize_certificate_chain", skip_serializing)]
+    #[schemars(with = "String")]
+    pub(crate) certificate_authorities: Vec<Certificate>,
+    /// reject connections from clients not presenting a certificate
+    #[serde(default)]
+    pub(crate) required: bool,
+    /// request paths that can only be accessed with a client certificate, when certificates are not
+    /// required for every connection. A path ending with `*` matches every path starting with it
+    #[serde(default)]
+    pub(crate) required_paths: Vec<String>,
+}
+
+impl TlsClientVerification {
+    pub(crate) fn verifier(&self) -> Result<Arc<dyn ClientCertVerifier>, ApolloRouterError> {
+        let mut roots = RootCertStore::empty();
+        for certificate in &self.certificate_authorities {
+            roots.add(certificate).map_err(ApolloRouterError::Rustls)?;
+        }
+        Ok(if self.required {
+            AllowAnyAuthenticatedClient::new(roots).boxed()
+        } else {
+            AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()
+        })
+    }
+
+    /// Returns `true` if requests to this path must be made with a client certificate.
+    pub(crate) fn is_required_for(&self, path: &str) -> bool {
+        self.required
+            || self
+                .required_paths
+                .iter()
+                .any(|required| match required.strip_suffix('*') {
+                    Some(prefix) => path.starts_with(prefix),
+                    None => path == required,
+                })
+    }
+}
+
+/// Identity of a client certificate verified during the TLS handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ClientCertificate {
+    pub(crate) subject: String,
+    pub(crate) subject_alternative_names: Vec<String>,
+}
+
+impl ClientCertificate {
+    pub(crate) fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, certificate) = X509Certificate::from_der(der).ok()?;
+        let subject_alternative_names = match certificate.subject_alternative_name() {
+            Ok(Some(extension)) => extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(name.to_string()),
+                    GeneralName::RFC822Name(email) => Some(email.to_string()),
+                    GeneralName::URI(uri) => Some(uri.to_string()),
+                    GeneralName::IPAddress(address) => ip_address(address).map(|ip| ip.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Some(Self {
+            subject: certificate.subject().to_string(),
+            subject_alternative_names,
+        })
+    }
+
+    /// Expose the identity in the context, for authorization and telemetry.
+    pub(crate) fn insert_into(&self, context: &Context) {
+        context.insert_json_value(
+            CLIENT_CERTIFICATE_SUBJECT,
+            Value::String(self.subject.clone().into()),
+        );
+        context.insert_json_value(
+            CLIENT_CERTIFICATE_SANS,
+            Value::Array(
+                self.subject_alternative_names
+                    .iter()
+                    .map(|name| Value::String(name.clone().into()))
+                    .collect(),
+            ),
+        );
+    }
+}
+
+fn ip_address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes)
+            .ok()
+            .map(Ipv4Addr::from)
+            .map(IpAddr::V4),
+        16 => <[u8; 16]>::try_from(bytes)
+            .ok()
+            .map(Ipv6Addr::from)
+            .map(IpAddr::V6),
+        _ => None,
+    }
+}
+
+/// Return the cipher suites with the given names, or all the supported ones if there are none.
+pub(crate) fn cipher_suites(
+    names: &[String],
+) -> Result<Vec<SupportedCipherSuite>, ApolloRouterError> {
+    if names.is_empty() {
+        return Ok(rustls::DEFAULT_CIPHER_SUITES.to_vec());
+    }
+    names
+        .iter()
+        .map(|name| {
+            rustls::ALL_CIPHER_SUITES
+                .iter()
+                .find(|suite| suite.suite().as_str() == Some(name.as_str()))
+                .copied()
+                .ok_or_else(|| {
+                    ApolloRouterError::Rustls(rustls::Error::General(format!(
+                        "unsupported cipher suite {name}"
+                    )))
+                })
+        })
+        .collect()
+}
+
+struct SniEntry {
+    server_names: Vec<String>,
+    key: Arc<CertifiedKey>,
+}
+
+impl SniEntry {
+    fn matches(&self, server_name: Option<&str>) -> bool {
+        self.server_names.iter().any(|pattern| match server_name {
+            _ if pattern == "*" => true,
+            None => false,
+            Some(server_name) => match pattern.strip_prefix("*.") {
+                Some(domain) => server_name
+                    .split_once('.')
+                    .is_some_and(|(_, parent)| parent.eq_ignore_ascii_case(domain)),
+                None => server_name.eq_ignore_ascii_case(pattern),
+            },
+        })
+    }
+}
+
+/// Selects the certificate presented to a client from the server name it requested.
+pub(crate) struct CertificateResolver {
+    default: Arc<CertifiedKey>,
+    entries: Arc<RwLock<Vec<SniEntry>>>,
+    // Stops watching the certificate files when the resolver is dropped.
+    _watch_guard: Option<oneshot::Sender<()>>,
+}
+
+impl CertificateResolver {
+    /// Load the certificates, and watch their files if there is a runtime to do so.
+    pub(crate) fn new(
+        default: CertifiedKey,
+        certificates: &[TlsSniCertificate],
+    ) -> Result<Self, ApolloRouterError> {
+        let entries = Arc::new(RwLock::new(
+            certificates
+                .iter()
+                .map(TlsSniCertificate::load)
+                .collect::<Result<Vec<_>, _>>()?,
+        ));
+        let watch_guard = match tokio::runtime::Handle::try_current() {
+            Ok(handle) if !certificates.is_empty() => {
+                let (sender, receiver) = oneshot::channel();
+                handle.spawn(watch(certificates.to_vec(), entries.clone(), receiver));
+                Some(sender)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            default: Arc::new(default),
+            entries,
+            _watch_guard: watch_guard,
+        })
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let server_name = client_hello.server_name();
+        let entries = self.entries.read().expect("lock poisoned");
+        Some(
+            entries
+                .iter()
+                .find(|entry| entry.matches(server_name))
+                .map(|entry| entry.key.clone())
+                .unwrap_or_else(|| self.default.clone()),
+        )
+    }
+}
+
+/// Reload all the certificates whenever one of their files changes. A certificate that fails to
+/// load is kept as it was.
+async fn watch(
+    certificates: Vec<TlsSniCertificate>,
+    entries: Arc<RwLock<Vec<SniEntry>>>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let mut changes = stream::select_all(
+        certificates
+            .iter()
+            .flat_map(TlsSniCertificate::paths)
+            .map(|path| crate::files::watch(path).skip(1).boxed()),
+    );
+    loop {
+        tokio::select! {
+            _ = &mut stop => return,
+            change = changes.next() => {
+                if change.is_none() {
+                    return;
+                }
+            }
+        }
+        let loaded = certificates
+            .iter()
+            .map(|certificate| {
+                certificate
+                    .load()
+                    .map_err(|err| {
+                        tracing::error!(
+                            certificate = %certificate.certificate_path.display(),
+                            "could not reload the TLS certificate: {err}"
+                        )
+                    })
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        let mut entries = entries.write().expect("lock poisoned");
+        for (entry, loaded) in entries.iter_mut().zip(loaded) {
+            if let Some(loaded) = loaded {
+                *entry = loaded;
+            }
+        }
+        tracing::info!("reloaded the TLS certificates");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(server_names: &[&str]) -> SniEntry {
+        let certificates =
+            load_certs(include_str!("testdata/server.crt")).expect("valid certificate");
+        let key = load_key(include_str!("testdata/server.key")).expect("valid key");
+        SniEntry {
+            server_names: server_names.iter().map(|name| name.to_string()).collect(),
+            key: Arc::new(certified_key(certificates, &key, None).unwrap()),
+        }
+    }
+
+    #[test]
+    fn server_name_matching() {
+        let exact = entry(&["api.example.com"]);
+        assert!(exact.matches(Some("api.example.com")));
+        assert!(exact.matches(Some("API.example.com")));
+        assert!(!exact.matches(Some("www.example.com")));
+        assert!(!exact.matches(None));
+
+        let wildcard = entry(&["*.example.com"]);
+        assert!(wildcard.matches(Some("api.example.com")));
+        assert!(!wildcard.matches(Some("example.com")));
+        assert!(!wildcard.matches(Some("a.api.example.com")));
+
+        let any = entry(&["*"]);
+        assert!(any.matches(Some("api.example.com")));
+        assert!(any.matches(None));
+    }
+
+    #[test]
+    fn client_certificate_identity() {
+        let certificates =
+            load_certs(include_str!("testdata/server.crt")).expect("valid certificate");
+        let identity = ClientCertificate::from_der(&certificates[0].0).unwrap();
+        assert!(identity.subject.contains("CN=local.apollo.dev"));
+        assert_eq!(identity.subject_alternative_names, vec!["local.apollo.dev"]);
+    }
+
+    #[test]
+    fn required_paths() {
+        let mut verification = TlsClientVerification {
+            certificate_authorities: Vec::new(),
+            required: false,
+            required_paths: vec!["/invalidation".to_string(), "/admin/*".to_string()],
+        };
+        assert!(verification.is_required_for("/invalidation"));
+        assert!(verification.is_required_for("/admin/config"));
+        assert!(!verification.is_required_for("/invalidation/all"));
+        assert!(!verification.is_required_for("/"));
+
+        verification.required = true;
+        assert!(verification.is_required_for("/"));
+    }
+
+    #[test]
+    fn cipher_suite_names() {
+        assert_eq!(
+            cipher_suites(&[]).unwrap().len(),
+            rustls::DEFAULT_CIPHER_SUITES.len()
+        );
+        let suites = cipher_suites(&["TLS13_AES_256_GCM_SHA384".to_string()]).unwrap();
+        assert_eq!(suites, vec![rustls::cipher_suite::TLS13_AES_256_GCM_SHA384]);
+        assert!(cipher_suites(&["TLS_RSA_WITH_RC4_128_MD5".to_string()]).is_err());
+    }
+}
@@ -3,6 +3,8 @@
 //! Router plugins accept a mutable [`Context`] when invoked and this contains a DashMap which
 //! allows additional data to be passed back and forth along the request invocation pipeline.
 
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
@@ -17,8 +19,10 @@ use extensions::sync::ExtensionsMutex;
 use parking_lot::Mutex;
 use serde::Deserialize;
 use serde::Serialize;
+use thiserror::Error;
 use tower::BoxError;
 
+use crate::json_ext::Object;
 use crate::json_ext::Value;
 use crate::services::layers::query_analysis::ParsedDocument;
 
@@ -34,6 +38,50 @@ pub(crate) const CONTAINS_GRAPHQL_ERROR: &str = "apollo::telemetry::contains_gra
 /// Holds [`Context`] entries.
 pub(crate) type Entries = Arc<DashMap<String, Value>>;
 
+/// A typed, namespaced [`Context`] entry.
+///
+/// The value is stored under `namespace::name`, which is the key Rhai scripts and coprocessors
+/// use to read or write it.
+///
+/// ```
+/// use apollo_router::ContextKey;
+///
+/// struct TenantId;
+///
+/// impl ContextKey for TenantId {
+///     const NAMESPACE: &'static str = "acme";
+///     const NAME: &'static str = "tenant_id";
+///     type Value = String;
+/// }
+/// ```
+pub trait ContextKey {
+    /// The namespace of the entry, usually the name of the plugin owning it.
+    const NAMESPACE: &'static str;
+
+    /// The name of the entry within its namespace.
+    const NAME: &'static str;
+
+    /// The type of the value stored in the entry.
+    type Value: Serialize + for<'de> Deserialize<'de>;
+
+    /// The key the entry is stored under.
+    fn key() -> String {
+        format!("{}::{}", Self::NAMESPACE, Self::NAME)
+    }
+}
+
+/// Error returned when an entry would grow a [`Context`] beyond its size limit.
+#[derive(Debug, Error)]
+#[error("cannot set context entry '{key}': the context would grow to {size} bytes, over its limit of {max_size} bytes")]
+pub struct ContextSizeExceeded {
+    /// Key of the rejected entry.
+    pub key: String,
+    /// Approximate size the context would have reached, in bytes.
+    pub size: usize,
+    /// Maximum size of the context, in bytes.
+    pub max_size: usize,
+}
+
 /// A map of arbitrary JSON values, for use by plugins.
 ///
 /// Context makes use of [`DashMap`] under the hood which tries to handle concurrency
@@ -45,6 +93,14 @@ pub(crate) type Entries = Arc<DashMap<String, Value>>;
 /// [`crate::services::SubgraphResponse`] processing. At such times,
 /// plugins should restrict themselves to the [`Context::get`] and [`Context::upsert`]
 /// functions to minimise the possibility of mis-sequenced updates.
+///
+/// Entries written while executing a deferred fragment are visible to the rest of the
+/// request, including the following deferred responses. Each subscription event is handled
+/// with a fork of the context of the subscription request: it sees the entries of the request,
+/// but the entries it writes are not visible to other events.
+///
+/// When `limits.context_max_bytes` is configured, insertions that would grow the context beyond
+/// that size, approximated from the JSON representation of its entries, are rejected.
 #[derive(Clone, Deserialize, Serialize, Derivative)]
 #[serde(default)]
 #[derivative(Debug)]
@@ -65,6 +121,10 @@ pub struct Context {
 
     #[serde(skip)]
     pub(crate) id: String,
+
+    /// Maximum size of the entries in bytes, 0 meaning unlimited
+    #[serde(skip)]
+    max_size: Arc<AtomicUsize>,
 }
 
 impl Context {
@@ -80,6 +140,20 @@ impl Context {
             created_at: Instant::now(),
             busy_timer: Arc::new(Mutex::new(BusyTimer::new())),
             id,
+            max_size: Default::default(),
+        }
+    }
+
+    /// Create a context sharing the extensions of this one and starting with a copy of its
+    /// entries, so that entries written to either context are not visible from the other.
+    pub(crate) fn fork(&self) -> Self {
+        Context {
+            entries: Arc::new(self.entries.as_ref().clone()),
+            extensions: self.extensions.clone(),
+            created_at: self.created_at,
+            busy_timer: self.busy_timer.clone(),
+            id: self.id.clone(),
+            max_size: self.max_size.clone(),
         }
     }
 }
@@ -133,24 +207,32 @@ impl Context {
         V: for<'de> serde::Deserialize<'de> + Serialize,
     {
         match serde_json_bytes::to_value(value) {
-            Ok(value) => self
-                .entries
-                .insert(key.into(), value)
-                .map(|v| serde_json_bytes::from_value(v))
-                .transpose()
-                .map_err(|e| e.into()),
+            Ok(value) => {
+                let key = key.into();
+                self.check_size(&key, &value)?;
+                self.entries
+                    .insert(key, value)
+                    .map(|v| serde_json_bytes::from_value(v))
+                    .transpose()
+                    .map_err(|e| e.into())
+            }
             Err(e) => Err(e.into()),
         }
     }
 
     /// Insert a value in the context using the provided key and value.
     ///
-    /// Semantics: the result is the old value as an [`Option`].
+    /// Semantics: the result is the old value as an [`Option`]. If the value would grow the
+    /// context beyond its size limit, it is not inserted and the result is `None`.
     pub fn insert_json_value<K>(&self, key: K, value: Value) -> Option<Value>
     where
         K: Into<String>,
     {
-        self.entries.insert(key.into(), value)
+        let key = key.into();
+        if self.check_size(&key, &value).is_err() {
+            return None;
+        }
+        self.entries.insert(key, value)
     }
 
     /// Get a json value from the context using the provided key.
@@ -182,22 +264,29 @@ impl Context {
         self.entries
             .entry(key.clone())
             .or_try_insert_with(|| serde_json_bytes::to_value::<V>(Default::default()))?;
-        let mut result = Ok(());
+        let size_check = self.size_check(&key);
+        let mut result: Result<(), BoxError> = Ok(());
         self.entries
             .alter(&key, |_, v| match serde_json_bytes::from_value(v.clone()) {
                 Ok(value) => match serde_json_bytes::to_value((upsert)(value)) {
-                    Ok(value) => value,
+                    Ok(value) => match size_check.check(&key, &value) {
+                        Ok(()) => value,
+                        Err(e) => {
+                            result = Err(e.into());
+                            v
+                        }
+                    },
                     Err(e) => {
-                        result = Err(e);
+                        result = Err(e.into());
                         v
                     }
                 },
                 Err(e) => {
-                    result = Err(e);
+                    result = Err(e.into());
                     v
                 }
             });
-        result.map_err(|e| e.into())
+        result
     }
 
     /// Upsert a JSON value in the context using the provided key and resolving
@@ -212,7 +301,88 @@ impl Context {
     {
         let key = key.into();
         self.entries.entry(key.clone()).or_insert(Value::Null);
-        self.entries.alter(&key, |_, v| upsert(v));
+        let size_check = self.size_check(&key);
+        self.entries.alter(&key, |_, v| {
+            if let SizeCheck::Unlimited = size_check {
+                return upsert(v);
+            }
+            let value = upsert(v.clone());
+            match size_check.check(&key, &value) {
+                Ok(()) => value,
+                Err(_) => v,
+            }
+        });
+    }
+
+    /// Get the value of a typed entry from the context.
+    ///
+    /// Semantics:
+    ///  - If the operation fails, that's because we can't deserialize the value.
+    ///  - If the operation succeeds, the value is an [`Option`].
+    pub fn get_typed<K>(&self) -> Result<Option<K::Value>, BoxError>
+    where
+        K: ContextKey,
+    {
+        self.get(K::key())
+    }
+
+    /// Insert the value of a typed entry in the context.
+    ///
+    /// Semantics:
+    ///  - If the operation fails, then the value has not been inserted.
+    ///  - If the operation succeeds, the result is the old value as an [`Option`].
+    pub fn insert_typed<K>(&self, value: K::Value) -> Result<Option<K::Value>, BoxError>
+    where
+        K: ContextKey,
+    {
+        self.insert(K::key(), value)
+    }
+
+    /// Upsert the value of a typed entry in the context using the resolving function.
+    ///
+    /// See [`Context::upsert`].
+    pub fn upsert_typed<K>(&self, upsert: impl FnOnce(K::Value) -> K::Value) -> Result<(), BoxError>
+    where
+        K: ContextKey,
+        K::Value: Default,
+    {
+        self.upsert(K::key(), upsert)
+    }
+
+    /// Approximate size of the context entries in bytes, based on their JSON representation.
+    pub fn size(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry_size(entry.key(), entry.value()))
+            .sum()
+    }
+
+    /// Set the maximum size of the context entries in bytes.
+    pub(crate) fn set_max_size(&self, max_size: Option<usize>) {
+        self.max_size
+            .store(max_size.unwrap_or_default(), Ordering::Relaxed);
+    }
+
+    fn check_size(&self, key: &str, value: &Value) -> Result<(), ContextSizeExceeded> {
+        self.size_check(key).check(key, value)
+    }
+
+    /// Measures the entries other than `key`, so that the check can run while that entry is locked.
+    fn size_check(&self, key: &str) -> SizeCheck {
+        let max_size = self.max_size.load(Ordering::Relaxed);
+        if max_size == 0 {
+            return SizeCheck::Unlimited;
+        }
+        let other_entries = self
+            .entries
+            .iter()
+            .filter(|entry| entry.key() != key)
+            .map(|entry| entry_size(entry.key(), entry.value()))
+            .sum();
+        SizeCheck::Limited {
+            other_entries,
+            max_size,
+        }
     }
 
     /// Convert the context into an iterator.
@@ -275,6 +445,74 @@ impl Context {
     }
 }
 
+enum SizeCheck {
+    Unlimited,
+    Limited {
+        other_entries: usize,
+        max_size: usize,
+    },
+}
+
+impl SizeCheck {
+    fn check(&self, key: &str, value: &Value) -> Result<(), ContextSizeExceeded> {
+        match self {
+            SizeCheck::Unlimited => Ok(()),
+            SizeCheck::Limited {
+                other_entries,
+                max_size,
+            } => {
+                let size = other_entries + entry_size(key, value);
+                if size > *max_size {
+                    let error = ContextSizeExceeded {
+                        key: key.to_string(),
+                        size,
+                        max_size: *max_size,
+                    };
+                    // the results of insertions are often ignored, so rejections are always reported
+                    tracing::warn!("{error}");
+                    u64_counter!(
+                        "apollo.router.context.rejected_entries",
+                        "Number of context entries rejected because of the context size limit",
+                        1
+                    );
+                    Err(error)
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+fn entry_size(key: &str, value: &Value) -> usize {
+    key.len() + 3 + json_size(value)
+}
+
+fn json_size(value: &Value) -> usize {
+    match value {
+        Value::Null => 4,
+        Value::Bool(b) => {
+            if *b {
+                4
+            } else {
+                5
+            }
+        }
+        Value::Number(n) => n.to_string().len(),
+        Value::String(s) => s.as_str().len() + 2,
+        Value::Array(a) => 2 + a.iter().map(|v| json_size(v) + 1).sum::<usize>(),
+        Value::Object(o) => object_size(o),
+    }
+}
+
+/// Approximate size of the JSON representation of an object, like the variables of a request
+pub(crate) fn object_size(object: &Object) -> usize {
+    2 + object
+        .iter()
+        .map(|(k, v)| k.as_str().len() + 4 + json_size(v))
+        .sum::<usize>()
+}
+
 pub struct BusyTimerGuard {
     busy_timer: Arc<Mutex<BusyTimer>>,
 }
@@ -352,6 +590,7 @@ mod test {
     use crate::spec::Schema;
     use crate::Configuration;
     use crate::Context;
+    use crate::ContextKey;
 
     #[test]
     fn test_context_insert() {
@@ -415,6 +654,75 @@ mod test {
         assert_eq!(c.get("two").unwrap(), Some(3));
     }
 
+    #[test]
+    fn test_context_typed() {
+        struct TenantId;
+
+        impl ContextKey for TenantId {
+            const NAMESPACE: &'static str = "acme";
+            const NAME: &'static str = "tenant_id";
+            type Value = String;
+        }
+
+        let c = Context::new();
+        assert!(c.insert_typed::<TenantId>("tenant".to_string()).is_ok());
+        assert_eq!(
+            c.get_typed::<TenantId>().unwrap(),
+            Some("tenant".to_string())
+        );
+        assert_eq!(
+            c.get::<_, String>("acme::tenant_id").unwrap(),
+            Some("tenant".to_string())
+        );
+        assert!(c.upsert_typed::<TenantId>(|v| format!("{v}-2")).is_ok());
+        assert_eq!(
+            c.get_typed::<TenantId>().unwrap(),
+            Some("tenant-2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_context_max_size() {
+        let c = Context::new();
+        c.set_max_size(Some(32));
+        assert!(c.insert("key", "value".to_string()).is_ok());
+        assert_eq!(c.size(), 3 + 3 + 7);
+
+        // Replacing an entry only accounts for the new value
+        assert!(c.insert("key", "other".to_string()).is_ok());
+        assert!(c
+            .insert("key2", "a value that is too long".to_string())
+            .is_err());
+        assert!(c.get::<_, String>("key2").unwrap().is_none());
+        assert!(c
+            .upsert("key", |v: String| format!(
+                "{v} and a value that is too long"
+            ))
+            .is_err());
+        assert_eq!(c.get("key").unwrap(), Some("other".to_string()));
+
+        c.upsert_json_value("key", |_| "a value that is much too long".into());
+        assert_eq!(c.get("key").unwrap(), Some("other".to_string()));
+        assert!(c
+            .insert_json_value("key2", "a value that is much too long".into())
+            .is_none());
+        assert!(!c.contains_key("key2"));
+    }
+
+    #[test]
+    fn test_context_fork() {
+        let c = Context::new();
+        assert!(c.insert("shared", 1).is_ok());
+        let fork = c.fork();
+        assert_eq!(fork.get("shared").unwrap(), Some(1));
+        assert_eq!(fork.id, c.id);
+
+        assert!(fork.insert("forked", 2).is_ok());
+        assert!(c.insert("shared", 3).is_ok());
+        assert!(!c.contains_key("forked"));
+        assert_eq!(fork.get("shared").unwrap(), Some(1));
+    }
+
     #[test]
     fn context_extensions() {
         // This is mostly tested in the extensions module.
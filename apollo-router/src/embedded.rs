@@ -0,0 +1,282 @@
+//! Embedding the router as a library, to execute GraphQL requests in-process.
+
+use std::sync::Arc;
+
+use arc_swap::ArcSwapOption;
+use futures::StreamExt;
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use http::Method;
+use mime::APPLICATION_JSON;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::router::ApolloRouterError;
+use crate::router_factory::RouterFactory;
+use crate::router_factory::RouterSuperServiceFactory;
+use crate::router_factory::YamlRouterFactory;
+use crate::services::new_service::ServiceFactory;
+use crate::services::router;
+use crate::services::router::service::RouterCreator;
+use crate::services::supergraph;
+use crate::spec::Schema;
+use crate::uplink::license_enforcement::LicenseEnforcementReport;
+use crate::Configuration;
+
+/// A router executing GraphQL requests in-process, without an HTTP server.
+///
+/// It runs the same request pipeline as the router's HTTP server, with the plugins, query
+/// planning and subgraph requests, and is suitable for serverless wrappers and custom binaries.
+///
+/// # Examples
+///
+/// ```
+/// use apollo_router::Configuration;
+/// use apollo_router::EmbeddedRouter;
+///
+/// async {
+///     let configuration = serde_yaml::from_str::<Configuration>("Config").unwrap();
+///     let schema = "schema";
+///     let router = EmbeddedRouter::builder()
+///         .schema(schema)
+///         .configuration(configuration)
+///         .build()
+///         .await
+///         .unwrap();
+///     let response = router
+///         .execute(
+///             apollo_router::graphql::Request::builder()
+///                 .query("{ me { name } }")
+///                 .build(),
+///         )
+///         .await;
+///     router.shutdown();
+/// };
+/// ```
+pub struct EmbeddedRouter {
+    running: ArcSwapOption<Running>,
+    /// Serializes the reloads, so that each one starts from the previous router
+    reload: tokio::sync::Mutex<()>,
+}
+
+/// The schema, configuration and services of the router currently executing the requests
+struct Running {
+    schema: Arc<String>,
+    configuration: Arc<Configuration>,
+    router_creator: RouterCreator,
+}
+
+#[buildstructor::buildstructor]
+impl EmbeddedRouter {
+    /// Returns a builder to create a router executing requests in-process.
+    ///
+    /// Builder methods:
+    ///
+    /// * `.schema(impl Into<String>)`
+    ///   Required.
+    ///   The supergraph schema definition.
+    ///
+    /// * `.configuration(`[`Configuration`]`)`
+    ///   Optional.
+    ///   If not provided, the default configuration as with an empty YAML file.
+    ///
+    /// * `.build()`
+    ///   Finishes the builder, and creates the router.
+    ///
+    /// Commercial features need a license and are not available to embedded routers, use
+    /// [`RouterHttpServer`](crate::RouterHttpServer) instead.
+    #[builder(visibility = "pub", entry = "builder", exit = "build")]
+    async fn new(
+        schema: String,
+        configuration: Option<Configuration>,
+    ) -> Result<EmbeddedRouter, ApolloRouterError> {
+        let running = Running::create(
+            Arc::new(schema),
+            Arc::new(configuration.unwrap_or_default()),
+            None,
+        )
+        .await?;
+        Ok(EmbeddedRouter {
+            running: ArcSwapOption::from_pointee(running),
+            reload: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Executes a GraphQL request and returns its first response.
+    ///
+    /// The responses of deferred fragments and subscription events that follow the first
+    /// response are not returned, use [`call`][Self::call] to get all of them.
+    pub async fn execute(&self, request: graphql::Request) -> Result<graphql::Response, BoxError> {
+        let mut supergraph_request = http::Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost/")
+            .body(request)?;
+        supergraph_request.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(APPLICATION_JSON.essence_str()),
+        );
+        let request = router::Request::try_from(supergraph::Request::from(supergraph_request))?;
+        let responses = self
+            .call(request)
+            .await?
+            .into_graphql_response_stream()
+            .await;
+        futures::pin_mut!(responses);
+        Ok(responses
+            .next()
+            .await
+            .ok_or("the router returned no response")??)
+    }
+
+    /// Executes a router request, with its HTTP headers and context, and returns the router
+    /// response.
+    pub async fn call(&self, request: router::Request) -> Result<router::Response, BoxError> {
+        let running = self.running()?;
+        running.router_creator.create().oneshot(request).await
+    }
+
+    /// Replaces the supergraph schema. The requests in flight complete with the previous schema.
+    pub async fn reload_schema(&self, schema: impl Into<String>) -> Result<(), ApolloRouterError> {
+        let _reload = self.reload.lock().await;
+        let previous = self
+            .running()
+            .map_err(ApolloRouterError::ServiceCreationError)?;
+        let running = Running::create(
+            Arc::new(schema.into()),
+            previous.configuration.clone(),
+            Some(&previous.router_creator),
+        )
+        .await?;
+        self.running.store(Some(Arc::new(running)));
+        Ok(())
+    }
+
+    /// Replaces the configuration. The requests in flight complete with the previous
+    /// configuration.
+    pub async fn reload_configuration(
+        &self,
+        configuration: Configuration,
+    ) -> Result<(), ApolloRouterError> {
+        let _reload = self.reload.lock().await;
+        let previous = self
+            .running()
+            .map_err(ApolloRouterError::ServiceCreationError)?;
+        let running = Running::create(
+            previous.schema.clone(),
+            Arc::new(configuration),
+            Some(&previous.router_creator),
+        )
+        .await?;
+        self.running.store(Some(Arc::new(running)));
+        Ok(())
+    }
+
+    /// Saves the query plans to the file configured in
+    /// `supergraph.query_planning.experimental_warm_state`, for the next router to restore them
+    /// at startup. Serverless wrappers call it before their instance is frozen or stopped.
+    pub async fn save_warm_state(&self) -> Result<(), BoxError> {
+        self.running()?.router_creator.save_warm_state().await
+    }
+
+    /// Stops the router. The requests in flight complete, and the following requests fail.
+    pub fn shutdown(&self) {
+        self.running.store(None);
+    }
+
+    fn running(&self) -> Result<Arc<Running>, BoxError> {
+        self.running
+            .load_full()
+            .ok_or_else(|| "the router is shut down".into())
+    }
+}
+
+impl Running {
+    async fn create(
+        schema: Arc<String>,
+        configuration: Arc<Configuration>,
+        previous_router_creator: Option<&RouterCreator>,
+    ) -> Result<Self, ApolloRouterError> {
+        let parsed_schema = Arc::new(
+            Schema::parse_arc(schema.clone(), &configuration)
+                .map_err(|e| ApolloRouterError::ServiceCreationError(e.to_string().into()))?,
+        );
+        let report = LicenseEnforcementReport::build(&configuration, &parsed_schema);
+        if report.uses_restricted_features() {
+            tracing::error!(
+                "Embedded routers can't use the following features, which require a license:\n\n{}",
+                report
+            );
+            return Err(ApolloRouterError::LicenseViolation);
+        }
+        let router_creator = YamlRouterFactory
+            .create(
+                // anonymous usage reporting is not enabled for embedded routers
+                true,
+                configuration.clone(),
+                parsed_schema,
+                previous_router_creator,
+                None,
+            )
+            .await
+            .map_err(ApolloRouterError::ServiceCreationError)?;
+        Ok(Self {
+            schema,
+            configuration,
+            router_creator,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    fn typename_request() -> graphql::Request {
+        graphql::Request::builder().query("{ __typename }").build()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn executes_requests_in_process() {
+        let router = EmbeddedRouter::builder()
+            .schema(include_str!("testdata/supergraph.graphql"))
+            .build()
+            .await
+            .unwrap();
+        let response = router.execute(typename_request()).await.unwrap();
+        assert_eq!(response.data, Some(json!({ "__typename": "Query" })));
+
+        let configuration: Configuration =
+            serde_yaml::from_str("supergraph:\n  introspection: true").unwrap();
+        router.reload_configuration(configuration).await.unwrap();
+        let response = router
+            .execute(
+                graphql::Request::builder()
+                    .query("{ __schema { queryType { name } } }")
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.data,
+            Some(json!({ "__schema": { "queryType": { "name": "Query" } } }))
+        );
+
+        router.shutdown();
+        assert!(router.execute(typename_request()).await.is_err());
+        assert!(router
+            .reload_schema(include_str!("testdata/supergraph.graphql"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_invalid_schemas() {
+        assert!(matches!(
+            EmbeddedRouter::builder().schema("invalid").build().await,
+            Err(ApolloRouterError::ServiceCreationError(_))
+        ));
+    }
+}
@@ -87,6 +87,21 @@ pub(crate) enum FetchError {
         /// The reason the fetch failed.
         reason: String,
     },
+    /// connection to '{service}' timed out
+    SubrequestConnectTimeout {
+        /// The service that could not be connected to.
+        service: String,
+    },
+    /// '{service}' did not send its response headers in time
+    SubrequestFirstByteTimeout {
+        /// The service that did not respond in time.
+        service: String,
+    },
+    /// request memory limit exceeded by the response of '{service}'
+    SubrequestMemoryLimitExceeded {
+        /// The service whose response did not fit in the memory limit.
+        service: String,
+    },
     /// Websocket fetch failed from '{service}': {reason}
     ///
     /// note that this relates to a transport error and not a GraphQL error
@@ -98,6 +113,15 @@ pub(crate) enum FetchError {
         reason: String,
     },
 
+    /// Kafka subscription failed for '{service}': {reason}
+    SubrequestKafkaError {
+        /// The service whose subscription failed.
+        service: String,
+
+        /// The reason the subscription failed.
+        reason: String,
+    },
+
     /// could not find path: {reason}
     ExecutionPathNotFound { reason: String },
 
@@ -137,7 +161,11 @@ impl FetchError {
                 }
                 FetchError::SubrequestMalformedResponse { service, .. }
                 | FetchError::SubrequestUnexpectedPatchResponse { service }
-                | FetchError::SubrequestWsError { service, .. } => {
+                | FetchError::SubrequestConnectTimeout { service }
+                | FetchError::SubrequestFirstByteTimeout { service }
+                | FetchError::SubrequestMemoryLimitExceeded { service }
+                | FetchError::SubrequestWsError { service, .. }
+                | FetchError::SubrequestKafkaError { service, .. } => {
                     extensions
                         .entry("service")
                         .or_insert_with(|| service.clone().into());
@@ -178,7 +206,11 @@ impl ErrorExtension for FetchError {
                 "SUBREQUEST_UNEXPECTED_PATCH_RESPONSE"
             }
             FetchError::SubrequestHttpError { .. } => "SUBREQUEST_HTTP_ERROR",
+            FetchError::SubrequestConnectTimeout { .. } => "SUBREQUEST_CONNECT_TIMEOUT",
+            FetchError::SubrequestFirstByteTimeout { .. } => "SUBREQUEST_FIRST_BYTE_TIMEOUT",
+            FetchError::SubrequestMemoryLimitExceeded { .. } => "MEMORY_LIMIT_EXCEEDED",
             FetchError::SubrequestWsError { .. } => "SUBREQUEST_WEBSOCKET_ERROR",
+            FetchError::SubrequestKafkaError { .. } => "SUBREQUEST_KAFKA_ERROR",
             FetchError::ExecutionPathNotFound { .. } => "EXECUTION_PATH_NOT_FOUND",
             FetchError::MalformedRequest { .. } => "MALFORMED_REQUEST",
             FetchError::MalformedResponse { .. } => "MALFORMED_RESPONSE",
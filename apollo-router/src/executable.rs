@@ -25,27 +25,49 @@ use url::ParseError;
 use url::Url;
 
 use crate::configuration::generate_config_schema;
+use crate::configuration::generate_diff;
 use crate::configuration::generate_upgrade;
+use crate::configuration::overlay::merge_overlays;
 use crate::configuration::Discussed;
 use crate::metrics::meter_provider;
 use crate::plugin::plugins;
 use crate::plugins::telemetry::reload::init_telemetry;
+use crate::router::object_store_url;
 use crate::router::ConfigurationSource;
+use crate::router::KubernetesResource;
 use crate::router::RouterHttpServer;
 use crate::router::SchemaSource;
 use crate::router::ShutdownSource;
 use crate::uplink::Endpoints;
 use crate::uplink::UplinkConfig;
+use crate::uplink::UplinkTlsConfig;
 use crate::LicenseSource;
 
 #[cfg(all(
     feature = "global-allocator",
+    not(feature = "mimalloc-allocator"),
     not(feature = "dhat-heap"),
     target_os = "linux"
 ))]
 #[global_allocator]
 static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+// Samples the allocations for the heap snapshots of the profiling endpoints. The symbol is
+// prefixed like the other jemalloc symbols.
+#[cfg(all(
+    feature = "profiling",
+    feature = "global-allocator",
+    not(feature = "mimalloc-allocator"),
+    not(feature = "dhat-heap"),
+    target_os = "linux"
+))]
+#[export_name = "_rjem_malloc_conf"]
+static MALLOC_CONF: &[u8] = b"prof:true,prof_active:true,lg_prof_sample:19\0";
+
+#[cfg(all(feature = "mimalloc-allocator", not(feature = "dhat-heap")))]
+#[global_allocator]
+static ALLOC: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
 // Note: the dhat-heap and dhat-ad-hoc features should not be both enabled. We name our functions
 // and variables identically to prevent this from happening.
 
@@ -114,6 +136,106 @@ extern "C" fn drop_ad_hoc_profiler() {
 enum Commands {
     /// Configuration subcommands.
     Config(ConfigSubcommandArgs),
+
+    /// Run the tests of Rhai scripts against fake services answering with fixture responses.
+    RhaiTest(RhaiTestArgs),
+
+    /// Run a local coprocessor echoing the payloads it receives, and record them as fixtures.
+    CoprocessorStub(CoprocessorStubArgs),
+
+    /// Print the query plan of an operation against a supergraph schema.
+    Plan(PlanArgs),
+
+    /// Generate the query plan and response snapshots of a corpus of operations, and compare them
+    /// to their golden files.
+    Snapshots(SnapshotsArgs),
+
+    /// Write a support bundle with the redacted configuration, the schema hash, the configured
+    /// plugins and the environment of the router.
+    SupportBundle(SupportBundleArgs),
+
+    /// Print the fields of a supergraph schema that operations haven't used recently, from the
+    /// state file of the field usage report.
+    FieldUsage(FieldUsageArgs),
+}
+
+#[derive(Args, Debug)]
+struct PlanArgs {
+    /// The supergraph schema.
+    #[clap(long, short)]
+    supergraph: PathBuf,
+
+    /// The file of the operation to plan.
+    query: PathBuf,
+
+    /// The name of the operation to plan, if the file contains several operations.
+    #[clap(long)]
+    operation_name: Option<String>,
+
+    /// The output format.
+    #[clap(long, value_enum, default_value_t)]
+    format: crate::query_planner::explain::PlanFormat,
+}
+
+#[derive(Args, Debug)]
+struct SnapshotsArgs {
+    /// The directory of the corpus, with a `supergraph.graphql` schema in each of its suites.
+    dir: PathBuf,
+
+    /// Write the generated snapshots instead of comparing them to the golden files.
+    #[clap(long)]
+    update: bool,
+}
+
+#[derive(Args, Debug)]
+struct SupportBundleArgs {
+    /// The location of the config.
+    #[clap(long, short, env = "APOLLO_ROUTER_CONFIG_PATH")]
+    config: Option<PathBuf>,
+
+    /// The supergraph schema.
+    #[clap(long, short, env = "APOLLO_ROUTER_SUPERGRAPH_PATH")]
+    supergraph: Option<PathBuf>,
+
+    /// The file the bundle is written to.
+    #[clap(long, short, default_value = crate::support_bundle::ARCHIVE_NAME)]
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct FieldUsageArgs {
+    /// The supergraph schema.
+    #[clap(long, short, env = "APOLLO_ROUTER_SUPERGRAPH_PATH")]
+    supergraph: PathBuf,
+
+    /// The state file of the field usage report.
+    #[clap(long)]
+    state: PathBuf,
+
+    /// Fields that operations haven't used during this window are reported as unused.
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "30d")]
+    window: Duration,
+}
+
+#[derive(Args, Debug)]
+struct RhaiTestArgs {
+    /// The directory of the Rhai scripts and of their `rhai_tests.yaml` fixtures file.
+    dir: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct CoprocessorStubArgs {
+    /// The socket address and port to listen on.
+    #[clap(long, default_value = "127.0.0.1:8081")]
+    listen: SocketAddr,
+
+    /// The directory of the recorded payloads.
+    #[clap(long, default_value = "coprocessor_fixtures")]
+    output: PathBuf,
+
+    /// Compare the payloads with the recorded ones instead of recording them.
+    #[clap(long)]
+    check: bool,
 }
 
 #[derive(Args, Debug)]
@@ -138,6 +260,23 @@ enum ConfigSubcommand {
         #[clap(action = ArgAction::SetTrue, long)]
         diff: bool,
     },
+    /// Print the differences between the effective settings of two configurations, and what
+    /// reloading from one to the other would rebuild.
+    Diff {
+        /// The location of the current config.
+        #[clap(value_parser)]
+        old_config_path: PathBuf,
+
+        /// The location of the new config.
+        #[clap(value_parser)]
+        new_config_path: PathBuf,
+    },
+    /// Print the configuration merged from several files, in order.
+    Merge {
+        /// The locations of the configs to merge, from the base to the last overlay.
+        #[clap(value_parser, required = true)]
+        config_paths: Vec<PathBuf>,
+    },
     /// List all the available experimental configurations with related GitHub discussion
     Experimental,
     /// List all the available preview configurations with related GitHub discussion
@@ -169,14 +308,16 @@ pub struct Opt {
     )]
     hot_reload: bool,
 
-    /// Configuration location relative to the project directory.
+    /// Configuration location relative to the project directory. Repeat it, or separate the locations with commas in APOLLO_ROUTER_CONFIG_PATH, to merge several files in order.
     #[clap(
         short,
         long = "config",
         value_parser,
-        env = "APOLLO_ROUTER_CONFIG_PATH"
+        env = "APOLLO_ROUTER_CONFIG_PATH",
+        value_delimiter = ',',
+        action = ArgAction::Append
     )]
-    config_path: Option<PathBuf>,
+    config_paths: Vec<PathBuf>,
 
     /// Enable development mode.
     #[clap(
@@ -199,6 +340,13 @@ pub struct Opt {
     #[clap(env = "APOLLO_ROUTER_SUPERGRAPH_URLS", value_delimiter = ',')]
     supergraph_urls: Option<Vec<Url>>,
 
+    /// Kubernetes resource to watch for the supergraph, either `configmap/[<namespace>/]<name>[#<key>]` or `<plural>.<group>/<version>/[<namespace>/]<name>[#<field>]`.
+    #[clap(
+        long = "supergraph-kubernetes",
+        env = "APOLLO_ROUTER_SUPERGRAPH_KUBERNETES"
+    )]
+    supergraph_kubernetes: Option<KubernetesResource>,
+
     /// Prints the configuration schema.
     #[clap(long, action(ArgAction::SetTrue), hide(true))]
     schema: bool,
@@ -240,6 +388,30 @@ pub struct Opt {
     #[clap(long, default_value = "30s", value_parser = humantime::parse_duration, env)]
     apollo_uplink_timeout: Duration,
 
+    /// PEM encoded client certificate presented to Apollo uplink, for instance when an enterprise proxy requires mTLS.
+    #[clap(long, env)]
+    apollo_uplink_client_cert: Option<PathBuf>,
+
+    /// PEM encoded private key of the Apollo uplink client certificate.
+    #[clap(long, env)]
+    apollo_uplink_client_key: Option<PathBuf>,
+
+    /// PEM encoded certificate authorities trusted when connecting to Apollo uplink, in addition to the system roots.
+    #[clap(long, env)]
+    apollo_uplink_ca_cert: Option<PathBuf>,
+
+    /// Directory where the last supergraph and license fetched from Apollo uplink are persisted. They are used if uplink is unreachable when the router starts.
+    #[clap(long, env)]
+    apollo_uplink_state_dir: Option<PathBuf>,
+
+    /// Supergraph schema used if Apollo uplink is unreachable when the router starts. Takes precedence over the state directory.
+    #[clap(long, env)]
+    apollo_uplink_fallback_supergraph: Option<PathBuf>,
+
+    /// License used if Apollo uplink is unreachable when the router starts. Takes precedence over the state directory.
+    #[clap(long, env)]
+    apollo_uplink_fallback_license: Option<PathBuf>,
+
     /// The listen address for the router. Overrides `supergraph.listen` in router.yaml.
     #[clap(long = "listen", env = "APOLLO_ROUTER_LISTEN_ADDRESS")]
     listen_address: Option<SocketAddr>,
@@ -289,6 +461,14 @@ impl Opt {
                 .transpose()?,
             poll_interval: self.apollo_uplink_poll_interval,
             timeout: self.apollo_uplink_timeout,
+            tls: UplinkTlsConfig {
+                client_certificate: self.apollo_uplink_client_cert.clone(),
+                client_key: self.apollo_uplink_client_key.clone(),
+                certificate_authorities: self.apollo_uplink_ca_cert.clone(),
+            },
+            state_dir: self.apollo_uplink_state_dir.clone(),
+            fallback_supergraph_path: self.apollo_uplink_fallback_supergraph.clone(),
+            fallback_license_path: self.apollo_uplink_fallback_license.clone(),
         })
     }
 
@@ -433,6 +613,30 @@ impl Executable {
                 println!("{output}");
                 Ok(())
             }
+            Some(Commands::Config(ConfigSubcommandArgs {
+                command:
+                    ConfigSubcommand::Diff {
+                        old_config_path,
+                        new_config_path,
+                    },
+            })) => {
+                let old_config_string = std::fs::read_to_string(old_config_path)?;
+                let new_config_string = std::fs::read_to_string(new_config_path)?;
+                let output = generate_diff(&old_config_string, &new_config_string)?;
+                println!("{output}");
+                Ok(())
+            }
+            Some(Commands::Config(ConfigSubcommandArgs {
+                command: ConfigSubcommand::Merge { config_paths },
+            })) => {
+                let contents = config_paths
+                    .iter()
+                    .map(std::fs::read_to_string)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let output = merge_overlays(contents.iter().map(String::as_str))?;
+                print!("{output}");
+                Ok(())
+            }
             Some(Commands::Config(ConfigSubcommandArgs {
                 command: ConfigSubcommand::Experimental,
             })) => {
@@ -445,6 +649,48 @@ impl Executable {
                 Discussed::new().print_preview();
                 Ok(())
             }
+            Some(Commands::RhaiTest(RhaiTestArgs { dir })) => {
+                crate::plugins::rhai::test_runner::run(dir)
+                    .await
+                    .map_err(|e| anyhow!("{e}"))
+            }
+            Some(Commands::CoprocessorStub(CoprocessorStubArgs {
+                listen,
+                output,
+                check,
+            })) => crate::plugins::coprocessor::stub::run(*listen, output.clone(), *check)
+                .await
+                .map_err(|e| anyhow!("{e}")),
+            Some(Commands::Plan(PlanArgs {
+                supergraph,
+                query,
+                operation_name,
+                format,
+            })) => crate::query_planner::explain::run(
+                supergraph,
+                query,
+                operation_name.clone(),
+                *format,
+            )
+            .await
+            .map_err(|e| anyhow!("{e}")),
+            Some(Commands::Snapshots(SnapshotsArgs { dir, update })) => {
+                crate::test_harness::snapshots::run(dir, *update)
+                    .await
+                    .map_err(|e| anyhow!("{e}"))
+            }
+            Some(Commands::SupportBundle(SupportBundleArgs {
+                config,
+                supergraph,
+                output,
+            })) => crate::support_bundle::run(config.as_deref(), supergraph.as_deref(), output)
+                .map_err(|e| anyhow!("{e}")),
+            Some(Commands::FieldUsage(FieldUsageArgs {
+                supergraph,
+                state,
+                window,
+            })) => crate::plugins::field_usage::run(supergraph, state, *window)
+                .map_err(|e| anyhow!("{e}")),
             None => Self::inner_start(shutdown, schema, config, license, opt).await,
         };
 
@@ -473,18 +719,39 @@ impl Executable {
         // Enable hot reload when dev mode is enabled
         opt.hot_reload = opt.hot_reload || opt.dev;
 
-        let configuration = match (config, opt.config_path.as_ref()) {
-            (Some(_), Some(_)) => {
+        let configuration = match (config, opt.config_paths.as_slice()) {
+            (Some(_), [_, ..]) => {
                 return Err(anyhow!(
                     "--config and APOLLO_ROUTER_CONFIG_PATH cannot be used when a custom configuration source is in use"
                 ));
             }
-            (Some(config), None) => config,
+            (Some(config), []) => config,
+            (None, paths) if paths.len() > 1 => {
+                if paths.iter().any(|path| object_store_url(path).is_some()) {
+                    return Err(anyhow!(
+                        "only local configuration files can be merged, use a single --config for object storage URLs"
+                    ));
+                }
+                ConfigurationSource::Files {
+                    paths: paths
+                        .iter()
+                        .map(|path| current_directory.join(path))
+                        .collect(),
+                    watch: opt.hot_reload,
+                }
+            }
             #[allow(clippy::blocks_in_conditions)]
             _ => opt
-                .config_path
-                .as_ref()
+                .config_paths
+                .first()
                 .map(|path| {
+                    if let Some(url) = object_store_url(path) {
+                        return ConfigurationSource::ObjectStore {
+                            url,
+                            watch: opt.hot_reload,
+                            period: opt.apollo_uplink_poll_interval,
+                        };
+                    }
                     let path = if path.is_relative() {
                         current_directory.join(path)
                     } else {
@@ -512,30 +779,39 @@ impl Executable {
         // 1. Cli --supergraph
         // 2. Env APOLLO_ROUTER_SUPERGRAPH_PATH
         // 3. Env APOLLO_ROUTER_SUPERGRAPH_URLS
-        // 4. Env APOLLO_KEY and APOLLO_GRAPH_REF
-        let schema_source = match (schema, &opt.supergraph_path, &opt.supergraph_urls, &opt.apollo_key) {
-            (Some(_), Some(_), _, _) | (Some(_), _, Some(_), _) => {
+        // 4. Cli --supergraph-kubernetes or env APOLLO_ROUTER_SUPERGRAPH_KUBERNETES
+        // 5. Env APOLLO_KEY and APOLLO_GRAPH_REF
+        let schema_source = match (schema, &opt.supergraph_path, &opt.supergraph_urls, &opt.supergraph_kubernetes, &opt.apollo_key) {
+            (Some(_), Some(_), _, _, _) | (Some(_), _, Some(_), _, _) | (Some(_), _, _, Some(_), _) => {
                 return Err(anyhow!(
                     "--supergraph and APOLLO_ROUTER_SUPERGRAPH_PATH cannot be used when a custom schema source is in use"
                 ))
             }
-            (Some(source), None, None,_) => source,
-            (_, Some(supergraph_path), _, _) => {
+            (Some(source), None, None, None, _) => source,
+            (_, Some(supergraph_path), _, _, _) => {
                 tracing::info!("{apollo_router_msg}");
                 tracing::info!("{apollo_telemetry_msg}");
 
-                let supergraph_path = if supergraph_path.is_relative() {
-                    current_directory.join(supergraph_path)
+                if let Some(url) = object_store_url(supergraph_path) {
+                    SchemaSource::ObjectStore {
+                        url,
+                        watch: opt.hot_reload,
+                        period: opt.apollo_uplink_poll_interval,
+                    }
                 } else {
-                    supergraph_path.clone()
-                };
-                SchemaSource::File {
-                    path: supergraph_path,
-                    watch: opt.hot_reload,
-                    delay: None,
+                    let supergraph_path = if supergraph_path.is_relative() {
+                        current_directory.join(supergraph_path)
+                    } else {
+                        supergraph_path.clone()
+                    };
+                    SchemaSource::File {
+                        path: supergraph_path,
+                        watch: opt.hot_reload,
+                        delay: None,
+                    }
                 }
             }
-            (_, _, Some(supergraph_urls), _) => {
+            (_, _, Some(supergraph_urls), _, _) => {
                 tracing::info!("{apollo_router_msg}");
                 tracing::info!("{apollo_telemetry_msg}");
 
@@ -545,7 +821,15 @@ impl Executable {
                     period: opt.apollo_uplink_poll_interval
                 }
             }
-            (_, None, None, Some(_apollo_key)) => {
+            (_, _, _, Some(resource), _) => {
+                tracing::info!("{apollo_router_msg}");
+                tracing::info!("{apollo_telemetry_msg}");
+
+                SchemaSource::Kubernetes {
+                    resource: resource.clone(),
+                }
+            }
+            (_, None, None, None, Some(_apollo_key)) => {
                 tracing::info!("{apollo_router_msg}");
                 tracing::info!("{apollo_telemetry_msg}");
                 SchemaSource::Registry(opt.uplink_config()?)
@@ -678,7 +962,9 @@ fn setup_panic_handler() {
         } else {
             tracing::error!("{}", e)
         }
-        if !USING_CATCH_UNWIND.get() {
+        // The panics of requests are answered with an error when their containment is enabled
+        let contained = crate::panic_containment::record_panic(e);
+        if !USING_CATCH_UNWIND.get() && !contained {
             // Once we've panic'ed the behaviour of the router is non-deterministic
             // We've logged out the panic details. Terminate with an error code
             std::process::exit(1);
@@ -14,6 +14,7 @@ use http::header;
 use http::header::HeaderName;
 use http::HeaderValue;
 use multimap::MultiMap;
+use subtle::ConstantTimeEq;
 
 use crate::graphql;
 use crate::services::APPLICATION_JSON_HEADER_VALUE;
@@ -218,6 +219,19 @@ pub(crate) fn header_map(
     Ok(http)
 }
 
+/// Compares two secrets in a time that does not depend on the position of their first
+/// difference, so that they cannot be guessed from response times.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Returns `true` if the authorization header of a request is the expected shared key.
+pub(crate) fn has_shared_key(headers: &http::HeaderMap, shared_key: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .is_some_and(|value| constant_time_eq(value.as_bytes(), shared_key.as_bytes()))
+}
+
 /// Ignores `http::Extensions`
 pub(crate) fn clone_http_request<B: Clone>(request: &http::Request<B>) -> http::Request<B> {
     let mut new = http::Request::builder()
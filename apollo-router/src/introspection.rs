@@ -1,59 +1,151 @@
-#[cfg(test)]
 use std::collections::HashMap;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
 
 use router_bridge::introspect::IntrospectionError;
 use router_bridge::planner::Planner;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Semaphore;
 use tower::BoxError;
 
 use crate::cache::storage::CacheStorage;
+use crate::cache::storage::ValueType;
+use crate::configuration::IntrospectionExecution;
 use crate::graphql::Response;
 use crate::query_planner::QueryPlanResult;
 
 const DEFAULT_INTROSPECTION_CACHE_CAPACITY: NonZeroUsize =
     unsafe { NonZeroUsize::new_unchecked(5) };
 
+/// A pre-generated introspection response, from the responses file.
+#[derive(Deserialize)]
+struct PregeneratedResponse {
+    query: String,
+    response: Response,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    response: Response,
+    expires_at: Option<SystemTime>,
+}
+
+impl ValueType for CachedResponse {
+    fn estimated_size(&self) -> Option<usize> {
+        None
+    }
+}
+
 /// A cache containing our well known introspection queries.
 pub(crate) struct Introspection {
-    cache: CacheStorage<String, Response>,
+    cache: CacheStorage<String, CachedResponse>,
+    pregenerated: HashMap<String, Response>,
+    cache_ttl: Option<Duration>,
+    executions: Option<Semaphore>,
     planner: Arc<Planner<QueryPlanResult>>,
 }
 
 impl Introspection {
-    pub(crate) async fn with_capacity(
+    pub(crate) async fn new(
         planner: Arc<Planner<QueryPlanResult>>,
-        capacity: NonZeroUsize,
+        config: &IntrospectionExecution,
     ) -> Result<Self, BoxError> {
+        let pregenerated = match &config.responses_file {
+            Some(path) => {
+                let responses: Vec<PregeneratedResponse> =
+                    serde_json::from_slice(&tokio::fs::read(path).await.map_err(|e| {
+                        format!(
+                            "could not read the introspection responses file {}: {e}",
+                            path.display()
+                        )
+                    })?)
+                    .map_err(|e| {
+                        format!(
+                            "could not parse the introspection responses file {}: {e}",
+                            path.display()
+                        )
+                    })?;
+                responses
+                    .into_iter()
+                    .map(|pregenerated| (pregenerated.query, pregenerated.response))
+                    .collect()
+            }
+            None => HashMap::new(),
+        };
+
         Ok(Self {
-            cache: CacheStorage::new(capacity, None, "introspection").await?,
+            cache: CacheStorage::new(
+                config
+                    .cache_limit
+                    .unwrap_or(DEFAULT_INTROSPECTION_CACHE_CAPACITY),
+                None,
+                "introspection",
+            )
+            .await?,
+            pregenerated,
+            executions: config
+                .max_concurrency
+                .map(|max_concurrency| Semaphore::new(max_concurrency.get())),
+            cache_ttl: config.cache_ttl,
             planner,
         })
     }
 
-    pub(crate) async fn new(planner: Arc<Planner<QueryPlanResult>>) -> Result<Self, BoxError> {
-        Self::with_capacity(planner, DEFAULT_INTROSPECTION_CACHE_CAPACITY).await
-    }
-
     #[cfg(test)]
     pub(crate) async fn from_cache(
         planner: Arc<Planner<QueryPlanResult>>,
         cache: HashMap<String, Response>,
     ) -> Result<Self, BoxError> {
-        let this = Self::with_capacity(planner, cache.len().try_into().unwrap()).await?;
+        let this = Self::new(
+            planner,
+            &IntrospectionExecution {
+                cache_limit: Some(cache.len().try_into().unwrap()),
+                ..Default::default()
+            },
+        )
+        .await?;
 
         for (query, response) in cache.into_iter() {
-            this.cache.insert(query, response).await;
+            this.cache
+                .insert(
+                    query,
+                    CachedResponse {
+                        response,
+                        expires_at: None,
+                    },
+                )
+                .await;
         }
         Ok(this)
     }
 
     /// Execute an introspection and cache the response.
     pub(crate) async fn execute(&self, query: String) -> Result<Response, IntrospectionError> {
-        if let Some(response) = self.cache.get(&query, |_| Ok(())).await {
-            return Ok(response);
+        if let Some(response) = self.pregenerated.get(&query) {
+            return Ok(response.clone());
+        }
+        if let Some(cached) = self.cache.get(&query, |_| Ok(())).await {
+            if cached
+                .expires_at
+                .map_or(true, |expires_at| expires_at > SystemTime::now())
+            {
+                return Ok(cached.response);
+            }
         }
 
+        // Wait for a slot if introspection executions are limited
+        let _permit = match &self.executions {
+            Some(executions) => {
+                Some(executions.acquire().await.map_err(|_| IntrospectionError {
+                    message: String::from("introspection executions are closed").into(),
+                })?)
+            }
+            None => None,
+        };
+
         // Do the introspection query and cache it
         let response =
             self.planner
@@ -76,7 +168,15 @@ impl Introspection {
 
         let response = Response::builder().data(introspection_result).build();
 
-        self.cache.insert(query, response.clone()).await;
+        self.cache
+            .insert(
+                query,
+                CachedResponse {
+                    response: response.clone(),
+                    expires_at: self.cache_ttl.map(|ttl| SystemTime::now() + ttl),
+                },
+            )
+            .await;
 
         Ok(response)
     }
@@ -85,25 +185,24 @@ impl Introspection {
 #[cfg(test)]
 mod introspection_tests {
     use std::sync::Arc;
+    use std::time::Duration;
 
     use router_bridge::planner::IncrementalDeliverySupport;
     use router_bridge::planner::QueryPlannerConfig;
 
     use super::*;
 
-    #[tokio::test]
-    async fn test_plan_cache() {
-        let query_to_test = r#"{
+    const QUERY: &str = r#"{
             __schema {
               types {
                 name
               }
             }
           }"#;
-        let schema = include_str!("../tests/fixtures/supergraph.graphql");
-        let expected_data = Response::builder().data(42).build();
 
-        let planner = Arc::new(
+    async fn planner() -> Arc<Planner<QueryPlanResult>> {
+        let schema = include_str!("../tests/fixtures/supergraph.graphql");
+        Arc::new(
             Planner::new(
                 schema.to_string(),
                 QueryPlannerConfig {
@@ -119,20 +218,85 @@ mod introspection_tests {
             )
             .await
             .unwrap(),
-        );
+        )
+    }
+
+    #[tokio::test]
+    async fn test_plan_cache() {
+        let expected_data = Response::builder().data(42).build();
 
-        let cache = [(query_to_test.to_string(), expected_data.clone())]
+        let cache = [(QUERY.to_string(), expected_data.clone())]
             .iter()
             .cloned()
             .collect();
-        let introspection = Introspection::from_cache(planner, cache).await.unwrap();
+        let introspection = Introspection::from_cache(planner().await, cache)
+            .await
+            .unwrap();
 
         assert_eq!(
             expected_data,
-            introspection
-                .execute(query_to_test.to_string())
-                .await
-                .unwrap()
+            introspection.execute(QUERY.to_string()).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pregenerated_responses() {
+        let expected_data = Response::builder().data(42).build();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        serde_json::to_writer(
+            &mut file,
+            &serde_json::json!([{ "query": QUERY, "response": expected_data }]),
+        )
+        .unwrap();
+
+        let introspection = Introspection::new(
+            planner().await,
+            &IntrospectionExecution {
+                responses_file: Some(file.path().to_path_buf()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            expected_data,
+            introspection.execute(QUERY.to_string()).await.unwrap()
         );
     }
+
+    #[tokio::test]
+    async fn test_cache_ttl() {
+        let introspection = Introspection::new(
+            planner().await,
+            &IntrospectionExecution {
+                cache_ttl: Some(Duration::from_secs(60)),
+                max_concurrency: Some(NonZeroUsize::new(1).unwrap()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let expired = Response::builder().data(42).build();
+        introspection
+            .cache
+            .insert(
+                QUERY.to_string(),
+                CachedResponse {
+                    response: expired.clone(),
+                    expires_at: Some(SystemTime::now() - Duration::from_secs(1)),
+                },
+            )
+            .await;
+
+        let response = introspection.execute(QUERY.to_string()).await.unwrap();
+        assert_ne!(expired, response);
+        let cached = introspection
+            .cache
+            .get(&QUERY.to_string(), |_| Ok(()))
+            .await
+            .unwrap();
+        assert_eq!(cached.response, response);
+        assert!(cached.expires_at.unwrap() > SystemTime::now());
+    }
 }
@@ -52,12 +52,14 @@ pub mod plugin;
 #[macro_use]
 pub(crate) mod metrics;
 
+mod allocator;
 mod apollo_studio_interop;
 pub(crate) mod axum_factory;
 mod batching;
 mod cache;
 mod configuration;
 mod context;
+mod embedded;
 mod error;
 mod executable;
 mod files;
@@ -69,6 +71,7 @@ pub mod layers;
 pub(crate) mod logging;
 pub(crate) mod notification;
 mod orbiter;
+mod panic_containment;
 mod plugins;
 pub(crate) mod protocols;
 mod query_planner;
@@ -77,6 +80,7 @@ mod router_factory;
 pub mod services;
 pub(crate) mod spec;
 mod state_machine;
+mod support_bundle;
 pub mod test_harness;
 pub mod tracer;
 mod uplink;
@@ -87,11 +91,15 @@ pub use crate::configuration::ListenAddr;
 pub use crate::context::extensions::sync::ExtensionsMutex;
 pub use crate::context::extensions::Extensions;
 pub use crate::context::Context;
+pub use crate::context::ContextKey;
+pub use crate::context::ContextSizeExceeded;
+pub use crate::embedded::EmbeddedRouter;
 pub use crate::executable::main;
 pub use crate::executable::Executable;
 pub use crate::notification::Notify;
 pub use crate::router::ApolloRouterError;
 pub use crate::router::ConfigurationSource;
+pub use crate::router::KubernetesResource;
 pub use crate::router::LicenseSource;
 pub use crate::router::RouterHttpServer;
 pub use crate::router::SchemaSource;
@@ -120,4 +128,6 @@ pub mod _private {
     pub use crate::query_planner::dual_query_planner::plan_matches;
     // For tests
     pub use crate::router_factory::create_test_service_factory_from_yaml;
+    // For benchmarks
+    pub use crate::spec::query::ResponseFormatter;
 }
@@ -91,6 +91,10 @@ pub(crate) enum Notification<K, V> {
         topics: Vec<K>,
         response_sender: oneshot::Sender<(Vec<K>, Vec<K>)>,
     },
+    Closed {
+        topics: Vec<K>,
+        response_sender: oneshot::Sender<Vec<K>>,
+    },
     UpdateHeartbeat {
         new_ttl: Option<Duration>,
     },
@@ -119,6 +123,7 @@ impl<K, V> Debug for Notification<K, V> {
             Self::ForceDelete { .. } => f.debug_struct("ForceDelete").finish(),
             Self::Exist { .. } => f.debug_struct("Exist").finish(),
             Self::InvalidIds { .. } => f.debug_struct("InvalidIds").finish(),
+            Self::Closed { .. } => f.debug_struct("Closed").finish(),
             Self::UpdateHeartbeat { .. } => f.debug_struct("UpdateHeartbeat").finish(),
             #[cfg(test)]
             Self::TryDelete { .. } => f.debug_struct("TryDelete").finish(),
@@ -174,6 +179,17 @@ where
 }
 
 impl<K, V> Notify<K, V> {
+    /// A notifier sharing the subscriptions of this one, with its own configuration and schema
+    /// broadcasts. The subscriptions of a contract variant use it, so that they are not closed by
+    /// the schema of the main supergraph.
+    pub(crate) fn with_own_broadcasts(&self) -> Self {
+        Notify {
+            sender: self.sender.clone(),
+            queue_size: self.queue_size,
+            router_broadcasts: Arc::new(RouterBroadcasts::new()),
+        }
+    }
+
     /// Broadcast a new configuration
     pub(crate) fn broadcast_configuration(&self, configuration: Weak<Configuration>) {
         self.router_broadcasts.configuration.0.send(configuration).expect("cannot send the configuration update to the static channel. Should not happen because the receiver will always live in this struct; qed");
@@ -319,6 +335,23 @@ where
         Ok(resp)
     }
 
+    /// Given a list of topics, returns the ones that don't exist anymore, without heartbeating
+    /// the others
+    pub(crate) async fn closed(&mut self, topics: Vec<K>) -> Result<Vec<K>, NotifyError<K, V>> {
+        let (response_tx, response_rx) = oneshot::channel();
+
+        self.sender
+            .send(Notification::Closed {
+                topics,
+                response_sender: response_tx,
+            })
+            .await?;
+
+        let resp = response_rx.await?;
+
+        Ok(resp)
+    }
+
     /// Delete the topic even if several subscribers are still listening
     pub(crate) async fn force_delete(&mut self, topic: K) -> Result<(), NotifyError<K, V>> {
         // if disconnected, we don't care (the task was stopped)
@@ -638,6 +671,13 @@ async fn task<K, V>(
                                 let invalid_topics = pubsub.invalid_topics(topics);
                                 let _ = response_sender.send(invalid_topics);
                             }
+                            Notification::Closed {
+                                topics,
+                                response_sender,
+                            } => {
+                                let closed = topics.into_iter().filter(|topic| !pubsub.exist(topic)).collect();
+                                let _ = response_sender.send(closed);
+                            }
                             Notification::UpdateHeartbeat {
                                 mut new_ttl
                             } => {
@@ -1024,6 +1064,25 @@ mod tests {
         assert_eq!(subscriptions_nb, 0);
     }
 
+    #[tokio::test]
+    async fn own_broadcasts_are_separate() {
+        let mut notify = Notify::builder().build();
+        let mut variant = notify.with_own_broadcasts();
+        let mut configurations = Box::pin(notify.subscribe_configuration());
+        let mut variant_configurations = Box::pin(variant.subscribe_configuration());
+
+        let configuration = Arc::new(Configuration::default());
+        notify.broadcast_configuration(Arc::downgrade(&configuration));
+        assert!(configurations.next().await.is_some());
+        assert!(variant_configurations.next().now_or_never().is_none());
+
+        // the subscriptions are shared
+        let topic = Uuid::new_v4();
+        let (_handle, created) = notify.create_or_subscribe(topic, false).await.unwrap();
+        assert!(created);
+        assert!(variant.exist(topic).await.unwrap());
+    }
+
     #[tokio::test]
     async fn it_subscribe_and_delete() {
         let mut notify = Notify::builder().build();
@@ -1053,6 +1112,10 @@ mod tests {
         assert_eq!(subscriptions_nb, 1);
 
         assert!(!notify.exist(topic_1).await.unwrap());
+        assert_eq!(
+            notify.closed(vec![topic_1, topic_2]).await.unwrap(),
+            vec![topic_1]
+        );
 
         notify.force_delete(topic_1).await.unwrap();
 
@@ -0,0 +1,385 @@
+//! Containment of the panics happening while executing a request.
+//!
+//! Without containment, a panic in a plugin or in the request pipeline terminates the router.
+//! With containment, the request panicking is answered with a 500 GraphQL error carrying an
+//! incident ID, a diagnostic bundle with the operation, its query plan, the configuration hash
+//! and the panic stack is written to a spool directory, and the router keeps serving requests.
+//!
+//! Only the panics happening in the task of the request are contained: the panic hook checks
+//! whether it runs in a request task to decide between recording the panic and terminating.
+
+use std::panic::AssertUnwindSafe;
+use std::panic::PanicInfo;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::SystemTime;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::header::CONTENT_TYPE;
+use http::StatusCode;
+use mime::APPLICATION_JSON;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::context::OPERATION_NAME;
+use crate::graphql;
+use crate::services::execution::QueryPlan;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::router;
+use crate::Configuration;
+use crate::Context;
+
+const INCIDENT_ID_EXTENSION: &str = "incidentId";
+
+tokio::task_local! {
+    /// The panic of the request executed by the task, recorded by the panic hook
+    static REQUEST_PANIC: Arc<Mutex<Option<RequestPanic>>>;
+}
+
+/// A panic recorded by the panic hook
+#[derive(Clone, Debug, Serialize)]
+struct RequestPanic {
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backtrace: Option<String>,
+}
+
+impl RequestPanic {
+    /// The panic caught without going through the panic hook, like in an embedded router
+    fn from_payload(payload: &(dyn std::any::Any + Send)) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".to_string());
+        Self {
+            message,
+            location: None,
+            backtrace: None,
+        }
+    }
+}
+
+/// Records the panic of the request executed by the current task.
+///
+/// Called by the panic hook, it returns `false` when the panic happened outside of a request,
+/// and the router must terminate.
+pub(crate) fn record_panic(info: &PanicInfo<'_>) -> bool {
+    REQUEST_PANIC
+        .try_with(|request_panic| {
+            let mut recorded = RequestPanic::from_payload(info.payload());
+            recorded.location = info.location().map(|location| location.to_string());
+            recorded.backtrace = Some(std::backtrace::Backtrace::force_capture().to_string());
+            // the lock can't be held by a panicking thread, only by the panic hook
+            if let Ok(mut request_panic) = request_panic.lock() {
+                *request_panic = Some(recorded);
+            }
+        })
+        .is_ok()
+}
+
+/// What is known about a request that panicked, written to the spool directory
+#[derive(Debug, Serialize)]
+struct DiagnosticBundle {
+    incident_id: String,
+    timestamp: String,
+    router_version: &'static str,
+    configuration_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operation_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operation_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query_plan: Option<serde_json::Value>,
+    panic: RequestPanic,
+}
+
+/// The spool directory of the diagnostic bundles
+struct Containment {
+    spool_path: PathBuf,
+    max_bundles: usize,
+    configuration_hash: String,
+}
+
+impl Containment {
+    /// Answers the request that panicked, and writes its diagnostic bundle
+    fn contain(&self, context: Context, request_panic: RequestPanic) -> router::ServiceResult {
+        let incident_id = uuid::Uuid::new_v4().to_string();
+        tracing::error!(
+            incident_id = %incident_id,
+            "a request panicked, it is answered with an error: {}",
+            request_panic.message
+        );
+        u64_counter!(
+            "apollo.router.request.panics",
+            "Number of requests that panicked and were answered with an error",
+            1
+        );
+
+        let bundle = DiagnosticBundle {
+            incident_id: incident_id.clone(),
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            router_version: env!("CARGO_PKG_VERSION"),
+            configuration_hash: self.configuration_hash.to_string(),
+            operation_name: context.get::<_, String>(OPERATION_NAME).ok().flatten(),
+            operation_hash: context
+                .extensions()
+                .with_lock(|lock| lock.get::<ParsedDocument>().cloned())
+                .map(|document| document.hash.to_string()),
+            query_plan: context
+                .extensions()
+                .with_lock(|lock| lock.get::<Arc<QueryPlan>>().cloned())
+                .and_then(|plan| serde_json::to_value(&plan.root).ok()),
+            panic: request_panic,
+        };
+        if self.max_bundles > 0 {
+            let spool_path = self.spool_path.clone();
+            let max_bundles = self.max_bundles;
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = write_bundle(&spool_path, max_bundles, &bundle) {
+                    tracing::error!(
+                        incident_id = %bundle.incident_id,
+                        "could not write the diagnostic bundle of the panic: {e}"
+                    );
+                }
+            });
+        }
+
+        router::Response::error_builder()
+            .error(
+                graphql::Error::builder()
+                    .message("internal server error")
+                    .extension_code("INTERNAL_SERVER_ERROR")
+                    .extension(INCIDENT_ID_EXTENSION, incident_id)
+                    .build(),
+            )
+            .status_code(StatusCode::INTERNAL_SERVER_ERROR)
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .context(context)
+            .build()
+    }
+}
+
+/// Writes the bundle to the spool directory, and removes the oldest bundles beyond the maximum
+fn write_bundle(
+    spool_path: &Path,
+    max_bundles: usize,
+    bundle: &DiagnosticBundle,
+) -> Result<(), BoxError> {
+    std::fs::create_dir_all(spool_path)?;
+    let path = spool_path.join(format!("{}.json", bundle.incident_id));
+    // renamed once written, so that collectors never read a partial bundle
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(bundle)?)?;
+    std::fs::rename(&tmp_path, &path)?;
+
+    let mut previous_bundles = std::fs::read_dir(spool_path)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let previous_path = entry.path();
+            if previous_path == path || previous_path.extension()? != "json" {
+                return None;
+            }
+            Some((entry.metadata().ok()?.modified().ok()?, previous_path))
+        })
+        .collect::<Vec<_>>();
+    if previous_bundles.len() >= max_bundles {
+        previous_bundles.sort();
+        let removed = previous_bundles.len() + 1 - max_bundles;
+        for (_, previous_path) in &previous_bundles[..removed] {
+            std::fs::remove_file(previous_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// [`Layer`] answering the requests that panic with an error, instead of terminating the router
+#[derive(Clone)]
+pub(crate) struct PanicContainmentLayer {
+    containment: Option<Arc<Containment>>,
+}
+
+impl PanicContainmentLayer {
+    pub(crate) fn new(configuration: &Configuration) -> Self {
+        let config = &configuration.experimental_panic_containment;
+        if !config.enabled {
+            return Self { containment: None };
+        }
+        let configuration_hash = serde_json::to_vec(&configuration.validated_yaml)
+            .map(|yaml| hex::encode(Sha256::digest(yaml)))
+            .unwrap_or_default();
+        Self {
+            containment: Some(Arc::new(Containment {
+                spool_path: config.spool_path(),
+                max_bundles: config.max_bundles,
+                configuration_hash,
+            })),
+        }
+    }
+}
+
+impl<S> Layer<S> for PanicContainmentLayer {
+    type Service = PanicContainmentService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PanicContainmentService {
+            inner,
+            containment: self.containment.clone(),
+        }
+    }
+}
+
+/// [`Service`] answering the requests that panic with an error, see [`PanicContainmentLayer`]
+#[derive(Clone)]
+pub(crate) struct PanicContainmentService<S> {
+    inner: S,
+    containment: Option<Arc<Containment>>,
+}
+
+impl<S> Service<router::Request> for PanicContainmentService<S>
+where
+    S: Service<router::Request, Response = router::Response, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, router::ServiceResult>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: router::Request) -> Self::Future {
+        let Some(containment) = self.containment.clone() else {
+            return Box::pin(self.inner.call(request));
+        };
+        let request_panic: Arc<Mutex<Option<RequestPanic>>> = Default::default();
+        let context = request.context.clone();
+        // plugins run code when creating the future of the request too
+        let inner = &mut self.inner;
+        let called = REQUEST_PANIC.sync_scope(request_panic.clone(), || {
+            std::panic::catch_unwind(AssertUnwindSafe(|| inner.call(request)))
+        });
+
+        Box::pin(async move {
+            let result = match called {
+                Ok(future) => {
+                    AssertUnwindSafe(REQUEST_PANIC.scope(request_panic.clone(), future))
+                        .catch_unwind()
+                        .await
+                }
+                Err(payload) => Err(payload),
+            };
+            match result {
+                Ok(response) => response,
+                Err(payload) => {
+                    let recorded = request_panic
+                        .lock()
+                        .ok()
+                        .and_then(|mut request_panic| request_panic.take());
+                    containment.contain(
+                        context,
+                        recorded.unwrap_or_else(|| RequestPanic::from_payload(&*payload)),
+                    )
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn panics_are_answered_with_an_error() {
+        let spool = tempfile::tempdir().unwrap();
+        let configuration: Configuration = serde_json::from_value(serde_json::json!({
+            "experimental_panic_containment": {
+                "enabled": true,
+                "spool_path": spool.path(),
+                "max_bundles": 1
+            }
+        }))
+        .unwrap();
+        let layer = PanicContainmentLayer::new(&configuration);
+
+        for _ in 0..2 {
+            let service = layer.layer(tower::service_fn(|_request: router::Request| async {
+                panic!("plugin bug");
+                #[allow(unreachable_code)]
+                router::Response::fake_builder().build()
+            }));
+            let mut response = service
+                .oneshot(router::Request::fake_builder().build().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(
+                response.response.status(),
+                StatusCode::INTERNAL_SERVER_ERROR
+            );
+            let body = response.next_response().await.unwrap().unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let incident_id = body["errors"][0]["extensions"][INCIDENT_ID_EXTENSION]
+                .as_str()
+                .unwrap()
+                .to_string();
+
+            // the bundle is written in the background
+            let bundle_path = spool.path().join(format!("{incident_id}.json"));
+            for _ in 0..50 {
+                if bundle_path.exists() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            let bundle: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(&bundle_path).unwrap()).unwrap();
+            assert_eq!(bundle["incident_id"], incident_id.as_str());
+            assert_eq!(bundle["panic"]["message"], "plugin bug");
+        }
+
+        // the oldest bundle was removed
+        assert_eq!(std::fs::read_dir(spool.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn panics_creating_the_future_are_contained() {
+        let configuration: Configuration = serde_json::from_value(serde_json::json!({
+            "experimental_panic_containment": { "enabled": true, "max_bundles": 0 }
+        }))
+        .unwrap();
+        let service = PanicContainmentLayer::new(&configuration).layer(tower::service_fn(
+            |_request: router::Request| -> futures::future::Ready<router::ServiceResult> {
+                panic!("plugin bug")
+            },
+        ));
+        let response = service
+            .oneshot(router::Request::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn containment_is_disabled_by_default() {
+        assert!(PanicContainmentLayer::new(&Configuration::default())
+            .containment
+            .is_none());
+    }
+}
@@ -0,0 +1,175 @@
+//! Plugin contributions to cache keys.
+//!
+//! See [`Plugin::cache_key`](super::Plugin::cache_key).
+
+use std::sync::Arc;
+
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::plugins::telemetry::CLIENT_VERSION;
+use crate::services::Plugins;
+use crate::Context;
+
+/// The cache a key is computed for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheKind<'a> {
+    /// The automatic persisted queries cache.
+    AutomaticPersistedQueries,
+    /// The query plan cache.
+    QueryPlan,
+    /// The entity cache of a subgraph.
+    Entity {
+        /// Name of the subgraph.
+        subgraph_name: &'a str,
+    },
+    /// The response cache.
+    Response,
+}
+
+/// The inputs of a cache key computation.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct CacheKeyInput<'a> {
+    /// The cache the key is computed for.
+    pub cache: CacheKind<'a>,
+    /// Hash of the operation: the persisted query hash for automatic persisted queries,
+    /// and the hash of the query document for the query plan, entity and response caches.
+    pub operation_hash: &'a str,
+    /// Name of the operation, if any.
+    pub operation_name: Option<&'a str>,
+    /// Name of the client, if it was identified.
+    pub client_name: Option<&'a str>,
+    /// Version of the client, if it was identified.
+    pub client_version: Option<&'a str>,
+    /// Context of the request.
+    pub context: &'a Context,
+}
+
+/// Gives the caches of a request access to the cache key hooks of the plugins.
+///
+/// The router service stores it in the context extensions of every request.
+#[derive(Clone)]
+pub(crate) struct CacheKeyHooks {
+    plugins: Arc<Plugins>,
+}
+
+impl CacheKeyHooks {
+    pub(crate) fn new(plugins: Arc<Plugins>) -> Self {
+        Self { plugins }
+    }
+
+    /// Returns the hash of the components contributed by plugins to a cache key, or `None`
+    /// if no plugin contributed to it.
+    ///
+    /// Components are hashed with the name of the plugin returning them, so that they cannot
+    /// alter the structure of the key or collide with the components of another plugin.
+    pub(crate) fn key_component(
+        context: &Context,
+        cache: CacheKind<'_>,
+        operation_hash: &str,
+        operation_name: Option<&str>,
+    ) -> Option<String> {
+        let hooks = context
+            .extensions()
+            .with_lock(|lock| lock.get::<CacheKeyHooks>().cloned())?;
+
+        let client_name = context.get::<_, String>(CLIENT_NAME).ok().flatten();
+        let client_version = context.get::<_, String>(CLIENT_VERSION).ok().flatten();
+        let input = CacheKeyInput {
+            cache,
+            operation_hash,
+            operation_name,
+            client_name: client_name.as_deref(),
+            client_version: client_version.as_deref(),
+            context,
+        };
+
+        let mut digest: Option<Sha256> = None;
+        for (name, plugin) in hooks.plugins.iter() {
+            if let Some(component) = plugin.cache_key(&input) {
+                let digest = digest.get_or_insert_with(Sha256::new);
+                digest.update(name.len().to_le_bytes());
+                digest.update(name.as_bytes());
+                digest.update(component.len().to_le_bytes());
+                digest.update(component.as_bytes());
+            }
+        }
+        digest.map(|digest| hex::encode(digest.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use tower::BoxError;
+
+    use super::*;
+    use crate::plugin::DynPlugin;
+    use crate::plugin::Plugin;
+    use crate::plugin::PluginInit;
+
+    struct Tenant;
+
+    #[async_trait]
+    impl Plugin for Tenant {
+        type Config = ();
+
+        async fn new(_init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+            Ok(Tenant)
+        }
+
+        fn cache_key(&self, input: &CacheKeyInput<'_>) -> Option<String> {
+            if input.cache == CacheKind::AutomaticPersistedQueries {
+                return None;
+            }
+            input.context.get::<_, String>("tenant").ok().flatten()
+        }
+    }
+
+    fn context(tenant: Option<&str>) -> Context {
+        let mut plugins = Plugins::new();
+        plugins.insert(
+            "test.tenant".to_string(),
+            Box::new(Tenant) as Box<dyn DynPlugin>,
+        );
+        let context = Context::new();
+        context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(CacheKeyHooks::new(Arc::new(plugins))));
+        if let Some(tenant) = tenant {
+            context.insert("tenant", tenant.to_string()).unwrap();
+        }
+        context
+    }
+
+    #[test]
+    fn plugin_key_component() {
+        let key = |context: &Context, cache| {
+            CacheKeyHooks::key_component(context, cache, "hash", Some("Query"))
+        };
+
+        assert_eq!(key(&Context::new(), CacheKind::QueryPlan), None);
+        assert_eq!(key(&context(None), CacheKind::QueryPlan), None);
+        assert_eq!(
+            key(&context(Some("a")), CacheKind::AutomaticPersistedQueries),
+            None
+        );
+
+        let a = key(&context(Some("a")), CacheKind::QueryPlan);
+        assert!(a.is_some());
+        assert_eq!(a, key(&context(Some("a")), CacheKind::QueryPlan));
+        assert_ne!(a, key(&context(Some("b")), CacheKind::QueryPlan));
+        assert_eq!(
+            a,
+            key(
+                &context(Some("a")),
+                CacheKind::Entity {
+                    subgraph_name: "products"
+                }
+            )
+        );
+    }
+}
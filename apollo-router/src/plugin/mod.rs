@@ -14,6 +14,7 @@
 //! processing. At each stage a [`Service`] is provided which provides an appropriate
 //! mechanism for interacting with the request and response.
 
+pub mod cache_key;
 pub mod serde;
 #[macro_use]
 pub mod test;
@@ -42,6 +43,8 @@ use tower::BoxError;
 use tower::Service;
 use tower::ServiceBuilder;
 
+pub use self::cache_key::CacheKeyInput;
+pub use self::cache_key::CacheKind;
 use crate::graphql;
 use crate::layers::ServiceBuilderExt;
 use crate::notification::Notify;
@@ -51,6 +54,7 @@ use crate::services::execution;
 use crate::services::router;
 use crate::services::subgraph;
 use crate::services::supergraph;
+use crate::Configuration;
 use crate::ListenAddr;
 
 type InstanceFactory =
@@ -76,6 +80,13 @@ pub struct PluginInit<T> {
     pub(crate) subgraph_schemas: Arc<SubgraphSchemas>,
 
     pub(crate) notify: Notify<String, graphql::Response>,
+
+    /// The configuration of the router, when the plugin is created by the router
+    pub(crate) full_config: Option<Arc<Configuration>>,
+
+    /// The schema of the router, parsed with its configuration, when the plugin is created by the
+    /// router
+    pub(crate) router_schema: Option<Arc<crate::spec::Schema>>,
 }
 
 impl<T> PluginInit<T>
@@ -168,6 +179,8 @@ where
         supergraph_schema: Arc<Valid<Schema>>,
         subgraph_schemas: Option<Arc<SubgraphSchemas>>,
         notify: Notify<String, graphql::Response>,
+        full_config: Option<Arc<Configuration>>,
+        router_schema: Option<Arc<crate::spec::Schema>>,
     ) -> Self {
         PluginInit {
             config,
@@ -175,6 +188,8 @@ where
             supergraph_schema,
             subgraph_schemas: subgraph_schemas.unwrap_or_default(),
             notify,
+            full_config,
+            router_schema,
         }
     }
 
@@ -189,6 +204,8 @@ where
         supergraph_schema: Arc<Valid<Schema>>,
         subgraph_schemas: Option<Arc<SubgraphSchemas>>,
         notify: Notify<String, graphql::Response>,
+        full_config: Option<Arc<Configuration>>,
+        router_schema: Option<Arc<crate::spec::Schema>>,
     ) -> Result<Self, BoxError> {
         let config: T = serde_json::from_value(config)?;
         Ok(PluginInit {
@@ -197,6 +214,8 @@ where
             supergraph_schema,
             subgraph_schemas: subgraph_schemas.unwrap_or_default(),
             notify,
+            full_config,
+            router_schema,
         })
     }
 
@@ -208,6 +227,8 @@ where
         supergraph_schema: Option<Arc<Valid<Schema>>>,
         subgraph_schemas: Option<Arc<SubgraphSchemas>>,
         notify: Option<Notify<String, graphql::Response>>,
+        full_config: Option<Arc<Configuration>>,
+        router_schema: Option<Arc<crate::spec::Schema>>,
     ) -> Self {
         PluginInit {
             config,
@@ -216,6 +237,8 @@ where
                 .unwrap_or_else(|| Arc::new(Valid::assume_valid(Schema::new()))),
             subgraph_schemas: subgraph_schemas.unwrap_or_default(),
             notify: notify.unwrap_or_else(Notify::for_tests),
+            full_config,
+            router_schema,
         }
     }
 }
@@ -232,6 +255,8 @@ impl PluginInit<serde_json::Value> {
             .supergraph_sdl(self.supergraph_sdl)
             .subgraph_schemas(self.subgraph_schemas)
             .notify(self.notify.clone())
+            .and_full_config(self.full_config)
+            .and_router_schema(self.router_schema)
             .build()
     }
 }
@@ -406,6 +431,17 @@ pub trait Plugin: Send + Sync + 'static {
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         MultiMap::new()
     }
+
+    /// Return a component to add to the cache keys computed for a request, or `None` to leave
+    /// them unchanged.
+    ///
+    /// This is called when computing the keys of the automatic persisted queries, query plan and
+    /// entity caches, for example to keep the cache entries of tenants apart. The component is
+    /// hashed with the plugin name into the key, so requests only share cache entries if they got
+    /// the same component.
+    fn cache_key(&self, _input: &CacheKeyInput<'_>) -> Option<String> {
+        None
+    }
 }
 
 /// Plugin trait for unstable features
@@ -481,6 +517,17 @@ pub trait PluginUnstable: Send + Sync + 'static {
         MultiMap::new()
     }
 
+    /// Return a component to add to the cache keys computed for a request, or `None` to leave
+    /// them unchanged.
+    ///
+    /// This is called when computing the keys of the automatic persisted queries, query plan and
+    /// entity caches, for example to keep the cache entries of tenants apart. The component is
+    /// hashed with the plugin name into the key, so requests only share cache entries if they got
+    /// the same component.
+    fn cache_key(&self, _input: &CacheKeyInput<'_>) -> Option<String> {
+        None
+    }
+
     /// test
     fn unstable_method(&self);
 }
@@ -531,6 +578,10 @@ where
         Plugin::web_endpoints(self)
     }
 
+    fn cache_key(&self, input: &CacheKeyInput<'_>) -> Option<String> {
+        Plugin::cache_key(self, input)
+    }
+
     fn unstable_method(&self) {
         todo!()
     }
@@ -618,6 +669,17 @@ pub(crate) trait PluginPrivate: Send + Sync + 'static {
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         MultiMap::new()
     }
+
+    /// Return a component to add to the cache keys computed for a request, or `None` to leave
+    /// them unchanged.
+    ///
+    /// This is called when computing the keys of the automatic persisted queries, query plan and
+    /// entity caches, for example to keep the cache entries of tenants apart. The component is
+    /// hashed with the plugin name into the key, so requests only share cache entries if they got
+    /// the same component.
+    fn cache_key(&self, _input: &CacheKeyInput<'_>) -> Option<String> {
+        None
+    }
 }
 
 #[async_trait]
@@ -665,6 +727,10 @@ where
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         PluginUnstable::web_endpoints(self)
     }
+
+    fn cache_key(&self, input: &CacheKeyInput<'_>) -> Option<String> {
+        PluginUnstable::cache_key(self, input)
+    }
 }
 
 fn get_type_of<T>(_: &T) -> &'static str {
@@ -715,6 +781,9 @@ pub(crate) trait DynPlugin: Send + Sync + 'static {
     /// Return one or several `Endpoint`s and `ListenAddr` and the router will serve your custom web Endpoint(s).
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
 
+    /// Return a component to add to the cache keys computed for a request.
+    fn cache_key(&self, input: &CacheKeyInput<'_>) -> Option<String>;
+
     /// Support downcasting
     fn as_any(&self) -> &dyn std::any::Any;
 
@@ -763,6 +832,10 @@ where
         self.web_endpoints()
     }
 
+    fn cache_key(&self, input: &CacheKeyInput<'_>) -> Option<String> {
+        self.cache_key(input)
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
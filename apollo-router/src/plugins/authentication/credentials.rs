@@ -0,0 +1,264 @@
+//! Credentials sent to the subgraphs: API keys, bearer tokens and HMAC signatures.
+//!
+//! The keys are usually referenced from a secret provider, like
+//! `${secret.vault:kv/data/router#products_key}`, so that their rotation reloads the
+//! configuration. During a rollover, the previous key can be kept next to the new one: requests
+//! rejected with a `401 Unauthorized` status are sent again with the previous key, so the
+//! subgraphs can be switched to the new key before or after the router.
+
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use hmac::Hmac;
+use hmac::Mac;
+use http::header::AUTHORIZATION;
+use http::request::Parts;
+use http::HeaderName;
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::plugin::serde::deserialize_header_name;
+
+type HmacSha256 = Hmac<sha2::Sha256>;
+
+const fn default_api_key_header_str() -> &'static str {
+    "x-api-key"
+}
+
+const fn default_api_key_header() -> HeaderName {
+    HeaderName::from_static(default_api_key_header_str())
+}
+
+const fn default_signature_header_str() -> &'static str {
+    "x-signature"
+}
+
+const fn default_signature_header() -> HeaderName {
+    HeaderName::from_static(default_signature_header_str())
+}
+
+/// API key sent in a header of the subgraph requests
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ApiKeyConfig {
+    /// Name of the header carrying the key (default: `x-api-key`)
+    #[schemars(with = "String", default = "default_api_key_header_str")]
+    #[serde(
+        deserialize_with = "deserialize_header_name",
+        default = "default_api_key_header"
+    )]
+    header: HeaderName,
+    /// The key, usually referenced from a secret provider
+    key: String,
+    /// The key being replaced during a rollover. Requests rejected with a `401 Unauthorized`
+    /// status are sent again with it
+    #[serde(default)]
+    previous_key: Option<String>,
+}
+
+/// Bearer token sent in the `authorization` header of the subgraph requests
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BearerConfig {
+    /// The token, usually referenced from a secret provider
+    token: String,
+    /// The token being replaced during a rollover. Requests rejected with a
+    /// `401 Unauthorized` status are sent again with it
+    #[serde(default)]
+    previous_token: Option<String>,
+}
+
+/// HMAC-SHA256 signature of the subgraph requests
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HmacConfig {
+    /// Name of the header carrying the signature (default: `x-signature`)
+    #[schemars(with = "String", default = "default_signature_header_str")]
+    #[serde(
+        deserialize_with = "deserialize_header_name",
+        default = "default_signature_header"
+    )]
+    header: HeaderName,
+    /// The signing key
+    key: HmacKey,
+    /// The signing key being replaced during a rollover. Requests rejected with a
+    /// `401 Unauthorized` status are sent again signed with it
+    #[serde(default)]
+    previous_key: Option<HmacKey>,
+}
+
+/// A key signing the subgraph requests
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HmacKey {
+    /// Identifier of the key, sent with the signature so that the subgraph can select the key
+    /// verifying it
+    id: String,
+    /// The secret of the key, usually referenced from a secret provider
+    secret: String,
+}
+
+/// Credentials added to the subgraph requests
+#[derive(Clone)]
+pub(crate) enum SubgraphCredentials {
+    Header {
+        name: HeaderName,
+        value: HeaderValue,
+        previous: Option<HeaderValue>,
+    },
+    Hmac {
+        header: HeaderName,
+        key: HmacKey,
+        previous: Option<HmacKey>,
+    },
+}
+
+fn sensitive_header_value(value: String) -> Result<HeaderValue, BoxError> {
+    let mut value = HeaderValue::try_from(value)
+        .map_err(|_| "subgraph credentials must be valid header values")?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+impl SubgraphCredentials {
+    pub(crate) fn api_key(config: &ApiKeyConfig) -> Result<Self, BoxError> {
+        Ok(Self::Header {
+            name: config.header.clone(),
+            value: sensitive_header_value(config.key.clone())?,
+            previous: config
+                .previous_key
+                .clone()
+                .map(sensitive_header_value)
+                .transpose()?,
+        })
+    }
+
+    pub(crate) fn bearer(config: &BearerConfig) -> Result<Self, BoxError> {
+        let bearer = |token: &String| sensitive_header_value(format!("Bearer {token}"));
+        Ok(Self::Header {
+            name: AUTHORIZATION,
+            value: bearer(&config.token)?,
+            previous: config.previous_token.as_ref().map(bearer).transpose()?,
+        })
+    }
+
+    pub(crate) fn hmac(config: &HmacConfig) -> Result<Self, BoxError> {
+        for key in std::iter::once(&config.key).chain(&config.previous_key) {
+            HeaderValue::try_from(&key.id)
+                .map_err(|_| "HMAC key ids must be valid header values")?;
+        }
+        Ok(Self::Hmac {
+            header: config.header.clone(),
+            key: config.key.clone(),
+            previous: config.previous_key.clone(),
+        })
+    }
+
+    /// Whether a previous key is configured
+    pub(crate) fn has_previous(&self) -> bool {
+        match self {
+            Self::Header { previous, .. } => previous.is_some(),
+            Self::Hmac { previous, .. } => previous.is_some(),
+        }
+    }
+
+    /// Adds the credentials to a request, with the current key or the previous one
+    pub(crate) fn apply(
+        &self,
+        parts: &mut Parts,
+        body: &[u8],
+        previous: bool,
+    ) -> Result<(), BoxError> {
+        match self {
+            Self::Header {
+                name,
+                value,
+                previous: previous_value,
+            } => {
+                let value = if previous {
+                    previous_value
+                        .as_ref()
+                        .ok_or("no previous key is configured")?
+                } else {
+                    value
+                };
+                parts.headers.insert(name.clone(), value.clone());
+            }
+            Self::Hmac {
+                header,
+                key,
+                previous: previous_key,
+            } => {
+                let key = if previous {
+                    previous_key
+                        .as_ref()
+                        .ok_or("no previous key is configured")?
+                } else {
+                    key
+                };
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let signature = signature(key, timestamp, parts, body)?;
+                let mut value = HeaderValue::try_from(format!(
+                    r#"keyId="{}",timestamp="{timestamp}",signature="{signature}""#,
+                    key.id
+                ))?;
+                value.set_sensitive(true);
+                parts.headers.insert(header.clone(), value);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the hex encoded HMAC-SHA256 of the timestamp, method, path and body of a request,
+/// separated by new lines
+fn signature(
+    key: &HmacKey,
+    timestamp: u64,
+    parts: &Parts,
+    body: &[u8],
+) -> Result<String, BoxError> {
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    let mut mac = HmacSha256::new_from_slice(key.secret.as_bytes())?;
+    mac.update(format!("{timestamp}\n{}\n{path}\n", parts.method).as_bytes());
+    mac.update(body);
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_are_added_with_the_selected_key() {
+        let credentials = SubgraphCredentials::bearer(&BearerConfig {
+            token: "new".to_string(),
+            previous_token: Some("old".to_string()),
+        })
+        .unwrap();
+        let (mut parts, _) = http::Request::new(()).into_parts();
+        credentials.apply(&mut parts, b"", false).unwrap();
+        assert_eq!(parts.headers[AUTHORIZATION], "Bearer new");
+        credentials.apply(&mut parts, b"", true).unwrap();
+        assert_eq!(parts.headers[AUTHORIZATION], "Bearer old");
+
+        let key = HmacKey {
+            id: "2024-10".to_string(),
+            secret: "secret".to_string(),
+        };
+        let (parts, _) = http::Request::post("http://products/graphql?a=b")
+            .body(())
+            .unwrap()
+            .into_parts();
+        assert_eq!(
+            signature(&key, 1700000000, &parts, br#"{"query":"{me{id}}"}"#).unwrap(),
+            "23e6da25ffac28c19c3fa5ef5482f4ddf99c9a5fd5519c129397d65f182d6362"
+        );
+    }
+}
@@ -55,6 +55,7 @@ use crate::services::router;
 use crate::services::APPLICATION_JSON_HEADER_VALUE;
 use crate::Context;
 
+mod credentials;
 mod jwks;
 pub(crate) mod subgraph;
 
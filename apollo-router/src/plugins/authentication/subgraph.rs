@@ -25,6 +25,11 @@ use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use super::credentials::ApiKeyConfig;
+use super::credentials::BearerConfig;
+use super::credentials::HmacConfig;
+use super::credentials::SubgraphCredentials;
+use crate::plugins::traffic_shaping::timeout::RequestTimeouts;
 use crate::services::router::body::get_body_bytes;
 use crate::services::router::body::RouterBody;
 use crate::services::SubgraphRequest;
@@ -175,6 +180,15 @@ impl AWSSigV4Config {
 pub(crate) enum AuthConfig {
     #[serde(rename = "aws_sig_v4")]
     AWSSigV4(AWSSigV4Config),
+    /// Send an API key in a header
+    #[serde(rename = "api_key")]
+    ApiKey(ApiKeyConfig),
+    /// Send a bearer token in the `authorization` header
+    #[serde(rename = "bearer")]
+    Bearer(BearerConfig),
+    /// Sign the requests with HMAC-SHA256
+    #[serde(rename = "hmac")]
+    Hmac(HmacConfig),
 }
 
 /// Configure subgraph authentication
@@ -198,12 +212,20 @@ pub(crate) struct SigningParams {
 
 #[derive(Clone)]
 pub(crate) struct SigningParamsConfig {
-    credentials_provider: CredentialsProvider,
-    region: Region,
-    service_name: String,
+    method: SigningMethod,
     subgraph_name: String,
 }
 
+#[derive(Clone)]
+enum SigningMethod {
+    AWSSigV4 {
+        credentials_provider: CredentialsProvider,
+        region: Region,
+        service_name: String,
+    },
+    Credentials(SubgraphCredentials),
+}
+
 #[derive(Clone, Debug)]
 struct CredentialsProvider {
     credentials: Arc<RwLock<Credentials>>,
@@ -307,6 +329,15 @@ impl SigningParamsConfig {
         mut req: Request<RouterBody>,
         subgraph_name: &str,
     ) -> Result<Request<RouterBody>, BoxError> {
+        let service_name = match &self.method {
+            SigningMethod::AWSSigV4 { service_name, .. } => service_name,
+            SigningMethod::Credentials(credentials) => {
+                let (mut parts, body) = req.into_parts();
+                let body_bytes = get_body_bytes(body).await?;
+                credentials.apply(&mut parts, &body_bytes, false)?;
+                return Ok(Request::from_parts(parts, body_bytes.into()));
+            }
+        };
         let credentials = self.credentials().await?;
         let builder = self.signing_params_builder(&credentials).await?;
         let (parts, body) = req.into_parts();
@@ -319,7 +350,7 @@ impl SigningParamsConfig {
             parts.method.as_str(),
             parts.uri.to_string(),
             headers.iter().map(|(name, value)| (name.as_str(), *value)),
-            match self.service_name.as_str() {
+            match service_name.as_str() {
                 "vpc-lattice-svcs" => SignableBody::UnsignedPayload,
                 _ => SignableBody::Bytes(body_bytes.as_slice()),
             },
@@ -347,6 +378,14 @@ impl SigningParamsConfig {
         mut req: Request<()>,
         subgraph_name: &str,
     ) -> Result<Request<()>, BoxError> {
+        let service_name = match &self.method {
+            SigningMethod::AWSSigV4 { service_name, .. } => service_name,
+            SigningMethod::Credentials(credentials) => {
+                let (mut parts, _) = req.into_parts();
+                credentials.apply(&mut parts, &[], false)?;
+                return Ok(Request::from_parts(parts, ()));
+            }
+        };
         let credentials = self.credentials().await?;
         let builder = self.signing_params_builder(&credentials).await?;
         let (parts, _) = req.into_parts();
@@ -358,7 +397,7 @@ impl SigningParamsConfig {
             parts.method.as_str(),
             parts.uri.to_string(),
             headers.iter().map(|(name, value)| (name.as_str(), *value)),
-            match self.service_name.as_str() {
+            match service_name.as_str() {
                 "vpc-lattice-svcs" => SignableBody::UnsignedPayload,
                 _ => SignableBody::Bytes(&[]),
             },
@@ -380,22 +419,70 @@ impl SigningParamsConfig {
         Ok(req)
     }
 
+    /// Signs a request, and with a previous key configured, a copy of the request signed with
+    /// it, to send when the subgraph rejects the request signed with the current key
+    pub(crate) async fn sign_with_fallback(
+        &self,
+        req: Request<RouterBody>,
+        subgraph_name: &str,
+    ) -> Result<(Request<RouterBody>, Option<Request<RouterBody>>), BoxError> {
+        let credentials = match &self.method {
+            SigningMethod::Credentials(credentials) if credentials.has_previous() => credentials,
+            _ => return Ok((self.sign(req, subgraph_name).await?, None)),
+        };
+        let (mut parts, body) = req.into_parts();
+        let body_bytes = get_body_bytes(body).await?;
+
+        let mut fallback = Request::builder()
+            .method(parts.method.clone())
+            .uri(parts.uri.clone())
+            .version(parts.version)
+            .body(RouterBody::from(body_bytes.clone()))?;
+        *fallback.headers_mut() = parts.headers.clone();
+        if let Some(timeouts) = parts.extensions.get::<RequestTimeouts>() {
+            fallback.extensions_mut().insert(*timeouts);
+        }
+        let (mut fallback_parts, fallback_body) = fallback.into_parts();
+        credentials.apply(&mut fallback_parts, &body_bytes, true)?;
+
+        credentials.apply(&mut parts, &body_bytes, false)?;
+        Ok((
+            Request::from_parts(parts, body_bytes.into()),
+            Some(Request::from_parts(fallback_parts, fallback_body)),
+        ))
+    }
+
     async fn signing_params_builder<'s>(
         &'s self,
         identity: &'s Identity,
     ) -> Result<aws_sigv4::sign::v4::signing_params::Builder<'s, SigningSettings>, BoxError> {
+        let SigningMethod::AWSSigV4 {
+            region,
+            service_name,
+            ..
+        } = &self.method
+        else {
+            return Err("the subgraph requests are not signed with AWS SigV4".into());
+        };
         let settings = get_signing_settings(self);
         let builder = aws_sigv4::sign::v4::SigningParams::builder()
             .identity(identity)
-            .region(self.region.as_ref())
-            .name(&self.service_name)
+            .region(region.as_ref())
+            .name(service_name)
             .time(SystemTime::now())
             .settings(settings);
         Ok(builder)
     }
 
     async fn credentials(&self) -> Result<Identity, BoxError> {
-        self.credentials_provider
+        let SigningMethod::AWSSigV4 {
+            credentials_provider,
+            ..
+        } = &self.method
+        else {
+            return Err("the subgraph requests are not signed with AWS SigV4".into());
+        };
+        credentials_provider
             .provide_credentials()
             .await
             .map_err(|err| {
@@ -427,10 +514,10 @@ pub(super) async fn make_signing_params(
     config: &AuthConfig,
     subgraph_name: &str,
 ) -> Result<SigningParamsConfig, BoxError> {
-    match config {
+    let method = match config {
         AuthConfig::AWSSigV4(config) => {
             let credentials_provider = config.get_credentials_provider().await;
-            Ok(SigningParamsConfig {
+            SigningMethod::AWSSigV4 {
                 region: config.region(),
                 service_name: config.service_name(),
                 credentials_provider: CredentialsProvider::from_provide_credentials(
@@ -438,18 +525,32 @@ pub(super) async fn make_signing_params(
                 )
                 .await
                 .map_err(BoxError::from)?,
-                subgraph_name: subgraph_name.to_string(),
-            })
+            }
         }
-    }
+        AuthConfig::ApiKey(config) => {
+            SigningMethod::Credentials(SubgraphCredentials::api_key(config)?)
+        }
+        AuthConfig::Bearer(config) => {
+            SigningMethod::Credentials(SubgraphCredentials::bearer(config)?)
+        }
+        AuthConfig::Hmac(config) => SigningMethod::Credentials(SubgraphCredentials::hmac(config)?),
+    };
+    Ok(SigningParamsConfig {
+        method,
+        subgraph_name: subgraph_name.to_string(),
+    })
 }
 
 /// There are three possible cases
 /// https://github.com/awslabs/aws-sdk-rust/blob/9c3168dafa4fd8885ce4e1fd41cec55ce982a33c/sdk/aws-sigv4/src/http_request/sign.rs#L264C1-L271C6
 fn get_signing_settings(signing_params: &SigningParamsConfig) -> SigningSettings {
     let mut settings = SigningSettings::default();
-    settings.payload_checksum_kind = match signing_params.service_name.as_str() {
-        "appsync" | "s3" | "vpc-lattice-svcs" => PayloadChecksumKind::XAmzSha256,
+    settings.payload_checksum_kind = match &signing_params.method {
+        SigningMethod::AWSSigV4 { service_name, .. }
+            if matches!(service_name.as_str(), "appsync" | "s3" | "vpc-lattice-svcs") =>
+        {
+            PayloadChecksumKind::XAmzSha256
+        }
         _ => PayloadChecksumKind::NoHeader,
     };
     settings
@@ -249,6 +249,14 @@ impl AuthorizationPlugin {
     }
 
     pub(crate) fn update_cache_key(context: &Context) {
+        let cache_key = Self::cache_key_metadata(context);
+        context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(cache_key));
+    }
+
+    /// The authentication status, scopes and policies of a request that its cached data depends on
+    pub(crate) fn cache_key_metadata(context: &Context) -> CacheKeyMetadata {
         let is_authenticated = context.contains_key(APOLLO_AUTHENTICATION_JWT_CLAIMS);
 
         let request_scopes = context
@@ -291,13 +299,11 @@ impl AuthorizationPlugin {
             .unwrap_or_default();
         policies.sort();
 
-        context.extensions().with_lock(|mut lock| {
-            lock.insert(CacheKeyMetadata {
-                is_authenticated,
-                scopes,
-                policies,
-            })
-        });
+        CacheKeyMetadata {
+            is_authenticated,
+            scopes,
+            policies,
+        }
     }
 
     pub(crate) fn intersect_cache_keys_subgraph(
@@ -357,11 +357,27 @@ impl CacheControl {
         let elapsed = self.elapsed();
         let expired = self.ttl().map(|ttl| ttl < elapsed).unwrap_or(false);
 
-        // FIXME: we don't honor stale-while-revalidate yet
-        // !expired || self.stale_while_revalidate
+        // expired entries can still be used while they are revalidated, see `can_use_stale`
         !expired && !self.no_store
     }
 
+    /// Whether the entry can be used, even if it expired less than `window` seconds ago, or if
+    /// there's no window, less than its `stale-while-revalidate` directive
+    pub(crate) fn can_use_stale(&self, window: Option<u32>) -> bool {
+        let elapsed = self.elapsed();
+        let window = window.or(self.stale_while_revalidate).unwrap_or(0);
+        let expired = self
+            .ttl()
+            .map(|ttl| ttl.saturating_add(window) < elapsed)
+            .unwrap_or(false);
+
+        !expired && !self.no_store
+    }
+
+    pub(crate) fn stale_while_revalidate(&self) -> Option<u32> {
+        self.stale_while_revalidate
+    }
+
     #[cfg(test)]
     pub(crate) fn remaining_time(&self, now: u64) -> Option<u32> {
         self.ttl().map(|ttl| {
@@ -456,4 +472,33 @@ mod tests {
         assert!(merged.private);
         assert!(merged.can_use());
     }
+
+    #[test]
+    fn stale_while_revalidate() {
+        let now = now_epoch_seconds();
+
+        let expired = CacheControl {
+            created: now - 50,
+            max_age: Some(40),
+            ..Default::default()
+        };
+        assert!(!expired.can_use());
+        assert!(!expired.can_use_stale(None));
+        assert!(!expired.can_use_stale(Some(5)));
+        assert!(expired.can_use_stale(Some(20)));
+
+        let with_directive = CacheControl {
+            stale_while_revalidate: Some(20),
+            ..expired.clone()
+        };
+        assert!(!with_directive.can_use());
+        assert!(with_directive.can_use_stale(None));
+        assert!(!with_directive.can_use_stale(Some(5)));
+
+        let no_store = CacheControl {
+            no_store: true,
+            ..expired
+        };
+        assert!(!no_store.can_use_stale(Some(20)));
+    }
 }
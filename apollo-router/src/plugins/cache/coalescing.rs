@@ -0,0 +1,182 @@
+//! Single flight fetches of entity cache misses.
+//!
+//! When several requests miss the cache for the same entries at the same time, the first one
+//! fetches them from the subgraph while the others wait for its response, instead of sending the
+//! same fetch to the subgraph.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use http::HeaderMap;
+use http::StatusCode;
+use tokio::sync::broadcast;
+use tower::BoxError;
+
+use super::cache_control::CacheControl;
+use crate::graphql;
+use crate::services::subgraph;
+use crate::Context;
+
+type SharedResult = Result<SharedResponse, String>;
+
+/// A subgraph response shared with the requests waiting for it
+#[derive(Clone)]
+pub(crate) struct SharedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: graphql::Response,
+}
+
+impl SharedResponse {
+    /// Only responses that would be stored in the cache are shared, since the other ones might
+    /// be specific to the request that fetched them
+    pub(crate) fn is_cacheable(&self) -> bool {
+        CacheControl::new(&self.headers, None)
+            .map(|control| control.should_store() && !control.private())
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn into_response(
+        self,
+        context: Context,
+        subgraph_name: String,
+    ) -> subgraph::Response {
+        let mut response = http::Response::builder()
+            .status(self.status)
+            .body(self.body)
+            .expect("the status is valid; qed");
+        *response.headers_mut() = self.headers;
+        subgraph::Response::new_from_response(response, context, subgraph_name)
+    }
+}
+
+/// The fetches in flight, by key of the fetched cache entries
+#[derive(Default)]
+pub(crate) struct InFlight {
+    fetches: Mutex<HashMap<String, broadcast::Sender<SharedResult>>>,
+}
+
+pub(crate) enum Flight {
+    /// No identical fetch is in flight, this request fetches and shares the response
+    Leader(LeaderGuard),
+    /// An identical fetch is in flight, this request waits for its response
+    Follower(broadcast::Receiver<SharedResult>),
+}
+
+impl InFlight {
+    pub(crate) fn join(self: &Arc<Self>, key: String) -> Flight {
+        let mut fetches = self.fetches.lock().expect("lock poisoned");
+        if let Some(sender) = fetches.get(&key) {
+            return Flight::Follower(sender.subscribe());
+        }
+        let (sender, _) = broadcast::channel(1);
+        fetches.insert(key.clone(), sender.clone());
+        Flight::Leader(LeaderGuard {
+            in_flight: self.clone(),
+            key,
+            sender,
+            completed: false,
+        })
+    }
+
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.fetches
+            .lock()
+            .expect("lock poisoned")
+            .contains_key(key)
+    }
+}
+
+/// Removes the fetch from the fetches in flight when the leader completes or is dropped. If it
+/// is dropped before completing, the waiting requests fetch by themselves.
+pub(crate) struct LeaderGuard {
+    in_flight: Arc<InFlight>,
+    key: String,
+    sender: broadcast::Sender<SharedResult>,
+    completed: bool,
+}
+
+impl LeaderGuard {
+    pub(crate) fn complete(mut self, result: &Result<subgraph::Response, BoxError>) {
+        self.remove();
+        let shared = match result {
+            Ok(response) => Ok(SharedResponse {
+                status: response.response.status(),
+                headers: response.response.headers().clone(),
+                body: response.response.body().clone(),
+            }),
+            Err(err) => Err(err.to_string()),
+        };
+        // there might not be any waiting request
+        let _ = self.sender.send(shared);
+    }
+
+    fn remove(&mut self) {
+        if !self.completed {
+            self.completed = true;
+            self.in_flight
+                .fetches
+                .lock()
+                .expect("lock poisoned")
+                .remove(&self.key);
+        }
+    }
+}
+
+impl Drop for LeaderGuard {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(value: u64) -> Result<subgraph::Response, BoxError> {
+        Ok(subgraph::Response::fake_builder()
+            .data(serde_json_bytes::json!({ "value": value }))
+            .build())
+    }
+
+    #[tokio::test]
+    async fn followers_get_the_leader_response() {
+        let in_flight = Arc::new(InFlight::default());
+        let Flight::Leader(leader) = in_flight.join("key".to_string()) else {
+            panic!("the first fetch should lead");
+        };
+        let Flight::Follower(mut follower) = in_flight.join("key".to_string()) else {
+            panic!("the second fetch should follow");
+        };
+        assert!(matches!(
+            in_flight.join("other".to_string()),
+            Flight::Leader(_)
+        ));
+
+        leader.complete(&response(1));
+        assert!(!in_flight.contains("key"));
+        let shared = follower.recv().await.unwrap().unwrap();
+        // the response has no `Cache-Control` header
+        assert!(!shared.is_cacheable());
+        assert_eq!(
+            shared
+                .into_response(Context::new(), "products".to_string())
+                .response
+                .into_body(),
+            response(1).unwrap().response.into_body()
+        );
+    }
+
+    #[tokio::test]
+    async fn followers_are_released_when_the_leader_is_dropped() {
+        let in_flight = Arc::new(InFlight::default());
+        let leader = in_flight.join("key".to_string());
+        let Flight::Follower(mut follower) = in_flight.join("key".to_string()) else {
+            panic!("the second fetch should follow");
+        };
+        drop(leader);
+        assert!(!in_flight.contains("key"));
+        assert!(follower.recv().await.is_err());
+    }
+}
@@ -25,6 +25,8 @@ use tracing::Instrument;
 use tracing::Level;
 
 use super::cache_control::CacheControl;
+use super::coalescing::Flight;
+use super::coalescing::InFlight;
 use super::invalidation::Invalidation;
 use super::invalidation::InvalidationOrigin;
 use super::invalidation_endpoint::InvalidationEndpointConfig;
@@ -45,9 +47,12 @@ use crate::graphql::Error;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
 use crate::json_ext::PathElement;
+use crate::plugin::cache_key::CacheKeyHooks;
+use crate::plugin::CacheKind;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::plugins::authorization::CacheKeyMetadata;
+use crate::plugins::data_residency::RegionalCache;
 use crate::query_planner::fetch::QueryHash;
 use crate::query_planner::OperationKind;
 use crate::services::subgraph;
@@ -74,6 +79,7 @@ pub(crate) struct EntityCache {
     enabled: bool,
     metrics: Metrics,
     private_queries: Arc<RwLock<HashSet<String>>>,
+    in_flight: Arc<InFlight>,
     pub(crate) invalidation: Invalidation,
 }
 
@@ -125,6 +131,12 @@ pub(crate) struct Subgraph {
 
     /// Invalidation configuration
     pub(crate) invalidation: Option<SubgraphInvalidationConfig>,
+
+    /// serve expired entries for this long after their expiration while they are refreshed in the background, overrides the `stale-while-revalidate` directive of the `Cache-Control` header in subgraph responses
+    pub(crate) stale_while_revalidate: Option<Ttl>,
+
+    /// send a single fetch to the subgraph for concurrent cache misses of the same entries
+    pub(crate) coalesce_misses: bool,
 }
 
 impl Default for Subgraph {
@@ -135,6 +147,8 @@ impl Default for Subgraph {
             ttl: Default::default(),
             private_id: Default::default(),
             invalidation: Default::default(),
+            stale_while_revalidate: Default::default(),
+            coalesce_misses: false,
         }
     }
 }
@@ -195,7 +209,7 @@ impl Plugin for EntityCache {
             let required_to_start = redis_config.required_to_start;
             // we need to explicitely disable TTL reset because it is managed directly by this plugin
             redis_config.reset_ttl = false;
-            all = match RedisCacheStorage::new(redis_config).await {
+            all = match RedisCacheStorage::new(redis_config, "entity").await {
                 Ok(storage) => Some(storage),
                 Err(e) => {
                     tracing::error!(
@@ -217,7 +231,7 @@ impl Plugin for EntityCache {
                 // we need to explicitely disable TTL reset because it is managed directly by this plugin
                 let mut redis_config = redis.clone();
                 redis_config.reset_ttl = false;
-                let storage = match RedisCacheStorage::new(redis_config).await {
+                let storage = match RedisCacheStorage::new(redis_config, "entity").await {
                     Ok(storage) => Some(storage),
                     Err(e) => {
                         tracing::error!(
@@ -288,6 +302,7 @@ impl Plugin for EntityCache {
             subgraphs: Arc::new(init.config.subgraph),
             metrics: init.config.metrics,
             private_queries: Arc::new(RwLock::new(HashSet::new())),
+            in_flight: Default::default(),
             invalidation,
         })
     }
@@ -343,6 +358,13 @@ impl Plugin for EntityCache {
         let subgraph_enabled =
             self.enabled && (self.subgraphs.all.enabled || self.subgraphs.get(name).enabled);
         let private_id = self.subgraphs.get(name).private_id.clone();
+        let stale_while_revalidate = self
+            .subgraphs
+            .get(name)
+            .stale_while_revalidate
+            .clone()
+            .map(|t| t.0);
+        let coalesce_misses = self.subgraphs.get(name).coalesce_misses;
 
         let name = name.to_string();
 
@@ -376,6 +398,9 @@ impl Plugin for EntityCache {
                     subgraph_ttl,
                     private_queries,
                     private_id,
+                    stale_while_revalidate,
+                    coalesce_misses,
+                    in_flight: self.in_flight.clone(),
                     invalidation: self.invalidation.clone(),
                 })));
             tower::util::BoxService::new(inner)
@@ -460,6 +485,7 @@ impl EntityCache {
             }),
             metrics: Metrics::default(),
             private_queries: Default::default(),
+            in_flight: Default::default(),
             endpoint_config: Some(Arc::new(InvalidationEndpointConfig {
                 path: String::from("/invalidation"),
                 listen: ListenAddr::SocketAddr(SocketAddr::new(
@@ -481,6 +507,9 @@ struct InnerCacheService {
     subgraph_ttl: Option<Duration>,
     private_queries: Arc<RwLock<HashSet<String>>>,
     private_id: Option<String>,
+    stale_while_revalidate: Option<Duration>,
+    coalesce_misses: bool,
+    in_flight: Arc<InFlight>,
     invalidation: Invalidation,
 }
 
@@ -523,6 +552,17 @@ impl InnerCacheService {
         {
             return self.service.call(request).await;
         }
+        // requests pinned to a region use its Redis instead, or bypass the cache without one
+        if let Some(RegionalCache(storage)) = request
+            .context
+            .extensions()
+            .with_lock(|lock| lock.get::<RegionalCache>().cloned())
+        {
+            match storage {
+                Some(storage) => self.storage = storage,
+                None => return self.service.call(request).await,
+            }
+        }
         let query = request
             .subgraph_request
             .body()
@@ -538,6 +578,8 @@ impl InnerCacheService {
             return self.service.call(request).await;
         }
 
+        let stale_window = self.stale_while_revalidate.map(|d| d.as_secs() as u32);
+
         if !request
             .subgraph_request
             .body()
@@ -552,12 +594,13 @@ impl InnerCacheService {
                     self.storage.clone(),
                     is_known_private,
                     private_id.as_deref(),
+                    stale_window,
                     request,
                 )
                 .instrument(tracing::info_span!("cache.entity.lookup"))
                 .await?
                 {
-                    ControlFlow::Break(response) => {
+                    ControlFlow::Break((response, revalidation)) => {
                         cache_hit.insert("Query".to_string(), CacheHitMiss { hit: 1, miss: 0 });
                         let _ = response.context.insert(
                             CacheMetricContextKey::new(
@@ -565,9 +608,12 @@ impl InnerCacheService {
                             ),
                             CacheSubgraph(cache_hit),
                         );
+                        if let Some(revalidation) = revalidation {
+                            self.revalidate(revalidation, query, is_known_private, private_id);
+                        }
                         Ok(response)
                     }
-                    ControlFlow::Continue((request, mut root_cache_key)) => {
+                    ControlFlow::Continue((request, root_cache_key)) => {
                         cache_hit.insert("Query".to_string(), CacheHitMiss { hit: 0, miss: 1 });
                         let _ = request.context.insert(
                             CacheMetricContextKey::new(
@@ -576,58 +622,14 @@ impl InnerCacheService {
                             CacheSubgraph(cache_hit),
                         );
 
-                        let mut response = self.service.call(request).await?;
-
-                        let cache_control =
-                            if response.response.headers().contains_key(CACHE_CONTROL) {
-                                CacheControl::new(response.response.headers(), self.storage.ttl)?
-                            } else {
-                                let mut c = CacheControl::default();
-                                c.no_store = true;
-                                c
-                            };
-
-                        if cache_control.private() {
-                            // we did not know in advance that this was a query with a private scope, so we update the cache key
-                            if !is_known_private {
-                                self.private_queries.write().await.insert(query.to_string());
-
-                                if let Some(s) = private_id.as_ref() {
-                                    root_cache_key = format!("{root_cache_key}:{s}");
-                                }
-                            }
-
-                            if private_id.is_none() {
-                                // the response has a private scope but we don't have a way to differentiate users, so we do not store the response in cache
-                                return Ok(response);
-                            }
-                        }
-
-                        if let Some(invalidation_extensions) = response
-                            .response
-                            .body_mut()
-                            .extensions
-                            .remove("invalidation")
-                        {
-                            self.handle_invalidation(
-                                InvalidationOrigin::Extensions,
-                                invalidation_extensions,
-                            )
-                            .await;
-                        }
-
-                        if cache_control.should_store() {
-                            cache_store_root_from_response(
-                                self.storage,
-                                self.subgraph_ttl,
-                                &response,
-                                cache_control,
-                                root_cache_key,
-                            )
-                            .await?;
-                        }
-
-                        Ok(response)
+                        self.fetch_root(
+                            request,
+                            root_cache_key,
+                            query,
+                            is_known_private,
+                            private_id,
+                        )
+                        .await
                     }
                 }
             } else {
@@ -653,60 +655,243 @@ impl InnerCacheService {
                 self.storage.clone(),
                 is_known_private,
                 private_id.as_deref(),
+                stale_window,
                 request,
             )
             .instrument(tracing::info_span!("cache.entity.lookup"))
             .await?
             {
-                ControlFlow::Break(response) => Ok(response),
+                ControlFlow::Break((response, revalidation)) => {
+                    if let Some(revalidation) = revalidation {
+                        self.revalidate(revalidation, query, is_known_private, private_id);
+                    }
+                    Ok(response)
+                }
                 ControlFlow::Continue((request, cache_result)) => {
-                    let mut response = self.service.call(request).await?;
+                    self.fetch_entities(request, cache_result, query, is_known_private, private_id)
+                        .await
+                }
+            }
+        }
+    }
 
-                    let mut cache_control =
-                        if response.response.headers().contains_key(CACHE_CONTROL) {
-                            CacheControl::new(response.response.headers(), self.storage.ttl)?
-                        } else {
-                            CacheControl::no_store()
-                        };
+    /// Fetches a root field from the subgraph after a cache miss, and stores it in the cache
+    async fn fetch_root(
+        mut self,
+        request: subgraph::Request,
+        mut root_cache_key: String,
+        query: String,
+        is_known_private: bool,
+        private_id: Option<String>,
+    ) -> Result<subgraph::Response, BoxError> {
+        let (mut response, coalesced) = self.fetch(root_cache_key.clone(), request).await?;
 
-                    if let Some(control_from_cached) = cache_result.1 {
-                        cache_control = cache_control.merge(&control_from_cached);
-                    }
+        let cache_control = if response.response.headers().contains_key(CACHE_CONTROL) {
+            CacheControl::new(response.response.headers(), self.storage.ttl)?
+        } else {
+            let mut c = CacheControl::default();
+            c.no_store = true;
+            c
+        };
 
-                    if !is_known_private && cache_control.private() {
-                        self.private_queries.write().await.insert(query.to_string());
-                    }
+        if cache_control.private() {
+            // we did not know in advance that this was a query with a private scope, so we update the cache key
+            if !is_known_private {
+                self.private_queries.write().await.insert(query);
 
-                    if let Some(invalidation_extensions) = response
-                        .response
-                        .body_mut()
-                        .extensions
-                        .remove("invalidation")
-                    {
-                        self.handle_invalidation(
-                            InvalidationOrigin::Extensions,
-                            invalidation_extensions,
-                        )
-                        .await;
-                    }
+                if let Some(s) = private_id.as_ref() {
+                    root_cache_key = format!("{root_cache_key}:{s}");
+                }
+            }
 
-                    cache_store_entities_from_response(
-                        self.storage,
-                        self.subgraph_ttl,
-                        &mut response,
-                        cache_control.clone(),
-                        cache_result.0,
-                        is_known_private,
-                        private_id,
-                    )
-                    .await?;
+            if private_id.is_none() {
+                // the response has a private scope but we don't have a way to differentiate users, so we do not store the response in cache
+                return Ok(response);
+            }
+        }
 
-                    cache_control.to_headers(response.response.headers_mut())?;
+        if let Some(invalidation_extensions) = response
+            .response
+            .body_mut()
+            .extensions
+            .remove("invalidation")
+        {
+            // the request that fetched a coalesced response already handled its invalidations
+            if !coalesced {
+                self.handle_invalidation(InvalidationOrigin::Extensions, invalidation_extensions)
+                    .await;
+            }
+        }
 
-                    Ok(response)
-                }
+        if cache_control.should_store() && !coalesced {
+            cache_store_root_from_response(
+                self.storage,
+                self.subgraph_ttl,
+                self.stale_while_revalidate,
+                &response,
+                cache_control,
+                root_cache_key,
+            )
+            .await?;
+        }
+
+        Ok(response)
+    }
+
+    /// Fetches the entities missing from the cache from the subgraph, stores them in the cache
+    /// and merges them with the cached ones
+    async fn fetch_entities(
+        mut self,
+        request: subgraph::Request,
+        cache_result: EntityCacheResults,
+        query: String,
+        is_known_private: bool,
+        private_id: Option<String>,
+    ) -> Result<subgraph::Response, BoxError> {
+        let (mut response, coalesced) =
+            self.fetch(coalescing_key(&cache_result.0), request).await?;
+
+        let mut cache_control = if response.response.headers().contains_key(CACHE_CONTROL) {
+            CacheControl::new(response.response.headers(), self.storage.ttl)?
+        } else {
+            CacheControl::no_store()
+        };
+
+        if let Some(control_from_cached) = cache_result.1 {
+            cache_control = cache_control.merge(&control_from_cached);
+        }
+
+        if !is_known_private && cache_control.private() {
+            self.private_queries.write().await.insert(query);
+        }
+
+        if let Some(invalidation_extensions) = response
+            .response
+            .body_mut()
+            .extensions
+            .remove("invalidation")
+        {
+            // the request that fetched a coalesced response already handled its invalidations
+            if !coalesced {
+                self.handle_invalidation(InvalidationOrigin::Extensions, invalidation_extensions)
+                    .await;
+            }
+        }
+
+        cache_store_entities_from_response(
+            self.storage,
+            self.subgraph_ttl,
+            self.stale_while_revalidate,
+            &mut response,
+            cache_control.clone(),
+            cache_result.0,
+            is_known_private,
+            private_id,
+            !coalesced,
+        )
+        .await?;
+
+        cache_control.to_headers(response.response.headers_mut())?;
+
+        Ok(response)
+    }
+
+    /// Fetches from the subgraph, or when misses are coalesced and the same entries are already
+    /// being fetched, waits for the response of that fetch. The returned boolean is true if the
+    /// response was fetched by another request.
+    async fn fetch(
+        &mut self,
+        key: String,
+        request: subgraph::Request,
+    ) -> Result<(subgraph::Response, bool), BoxError> {
+        if !self.coalesce_misses {
+            return Ok((self.service.call(request).await?, false));
+        }
+
+        match self.in_flight.join(key) {
+            Flight::Leader(guard) => {
+                let result = self.service.call(request).await;
+                guard.complete(&result);
+                Ok((result?, false))
             }
+            Flight::Follower(mut receiver) => match receiver.recv().await {
+                Ok(Ok(shared)) if shared.is_cacheable() => {
+                    u64_counter!(
+                        "apollo.router.operations.entity.coalesced",
+                        "Entity cache misses served by the subgraph fetch of a concurrent request",
+                        1,
+                        "subgraph.name" = self.name.clone()
+                    );
+                    let response = shared.into_response(request.context, self.name.clone());
+                    Ok((response, true))
+                }
+                Ok(Err(err)) => Err(err.into()),
+                // the fetch in flight was cancelled, or its response would not have been stored
+                // in the cache, so it might be specific to the request that fetched it
+                _ => Ok((self.service.call(request).await?, false)),
+            },
+        }
+    }
+
+    /// Refreshes in the background the stale entries served from the cache
+    fn revalidate(
+        mut self,
+        revalidation: Revalidation,
+        query: String,
+        is_known_private: bool,
+        private_id: Option<String>,
+    ) {
+        let key = match &revalidation {
+            Revalidation::Root(_, key) => key.clone(),
+            Revalidation::Entities(_, cache_result) => coalescing_key(&cache_result.0),
+        };
+        if self.in_flight.contains(&key) {
+            return;
         }
+        // concurrent revalidations of the same entries are always coalesced
+        self.coalesce_misses = true;
+
+        u64_counter!(
+            "apollo.router.operations.entity.revalidation",
+            "Entity cache stale entries refreshed in the background",
+            1,
+            "subgraph.name" = self.name.clone()
+        );
+        let name = self.name.clone();
+        tokio::spawn(
+            async move {
+                let result = match revalidation {
+                    Revalidation::Root(request, root_cache_key) => {
+                        self.fetch_root(
+                            request,
+                            root_cache_key,
+                            query,
+                            is_known_private,
+                            private_id,
+                        )
+                        .await
+                    }
+                    Revalidation::Entities(request, cache_result) => {
+                        self.fetch_entities(
+                            request,
+                            cache_result,
+                            query,
+                            is_known_private,
+                            private_id,
+                        )
+                        .await
+                    }
+                };
+                if let Err(e) = result {
+                    tracing::warn!(
+                        subgraph = %name,
+                        error = %e,
+                        "could not revalidate stale entity cache entries"
+                    );
+                }
+            }
+            .instrument(tracing::info_span!("cache.entity.revalidate")),
+        );
     }
 
     fn get_private_id(&self, context: &Context) -> Option<String> {
@@ -736,14 +921,24 @@ impl InnerCacheService {
     }
 }
 
+/// The subgraph fetch refreshing the stale entries served from the cache
+enum Revalidation {
+    Root(subgraph::Request, String),
+    Entities(subgraph::Request, EntityCacheResults),
+}
+
+/// A response served from the cache, with the fetch refreshing its stale entries if there are any
+type CachedResponse = (subgraph::Response, Option<Revalidation>);
+
 async fn cache_lookup_root(
     name: String,
     entity_type_opt: Option<&str>,
     cache: RedisCacheStorage,
     is_known_private: bool,
     private_id: Option<&str>,
+    stale_window: Option<u32>,
     mut request: subgraph::Request,
-) -> Result<ControlFlow<subgraph::Response, (subgraph::Request, String)>, BoxError> {
+) -> Result<ControlFlow<CachedResponse, (subgraph::Request, String)>, BoxError> {
     let body = request.subgraph_request.body_mut();
 
     let key = extract_cache_key_root(
@@ -761,7 +956,9 @@ async fn cache_lookup_root(
 
     match cache_result {
         Some(value) => {
-            if value.0.control.can_use() {
+            if value.0.control.can_use_stale(stale_window) {
+                let revalidation = (!value.0.control.can_use())
+                    .then(|| Revalidation::Root(request.clone(), key.clone()));
                 let control = value.0.control.clone();
                 request
                     .context
@@ -779,7 +976,7 @@ async fn cache_lookup_root(
                     .0
                     .control
                     .to_headers(response.response.headers_mut())?;
-                Ok(ControlFlow::Break(response))
+                Ok(ControlFlow::Break((response, revalidation)))
             } else {
                 Ok(ControlFlow::Continue((request, key)))
             }
@@ -795,8 +992,9 @@ async fn cache_lookup_entities(
     cache: RedisCacheStorage,
     is_known_private: bool,
     private_id: Option<&str>,
+    stale_window: Option<u32>,
     mut request: subgraph::Request,
-) -> Result<ControlFlow<subgraph::Response, (subgraph::Request, EntityCacheResults)>, BoxError> {
+) -> Result<ControlFlow<CachedResponse, (subgraph::Request, EntityCacheResults)>, BoxError> {
     let body = request.subgraph_request.body_mut();
 
     let keys = extract_cache_keys(
@@ -818,7 +1016,7 @@ async fn cache_lookup_entities(
                 .map(|v| match v {
                     None => None,
                     Some(v) => {
-                        if v.control.can_use() {
+                        if v.control.can_use_stale(stale_window) {
                             Some(v)
                         } else {
                             None
@@ -835,8 +1033,14 @@ async fn cache_lookup_entities(
         .and_then(|value| value.as_array_mut())
         .expect("we already checked that representations exist");
     // remove from representations the entities we already obtained from the cache
-    let (new_representations, cache_result, cache_control) =
-        filter_representations(&name, representations, keys, cache_result, &request.context)?;
+    let (new_representations, stale, cache_result, cache_control) = filter_representations(
+        &name,
+        representations,
+        keys,
+        cache_result,
+        stale_window,
+        &request.context,
+    )?;
 
     if !new_representations.is_empty() {
         body.variables
@@ -847,6 +1051,15 @@ async fn cache_lookup_entities(
             EntityCacheResults(cache_result, cache_control),
         )))
     } else {
+        let revalidation = stale.map(|(representations, results)| {
+            let mut request = request.clone();
+            request
+                .subgraph_request
+                .body_mut()
+                .variables
+                .insert(REPRESENTATIONS, representations.into());
+            Revalidation::Entities(request, EntityCacheResults(results, None))
+        });
         let entities = cache_result
             .into_iter()
             .filter_map(|res| res.cache_entry)
@@ -866,11 +1079,11 @@ async fn cache_lookup_entities(
             .unwrap_or_default()
             .to_headers(response.response.headers_mut())?;
 
-        Ok(ControlFlow::Break(response))
+        Ok(ControlFlow::Break((response, revalidation)))
     }
 }
 
-fn update_cache_control(context: &Context, cache_control: &CacheControl) {
+pub(crate) fn update_cache_control(context: &Context, cache_control: &CacheControl) {
     context.extensions().with_lock(|mut lock| {
         if let Some(c) = lock.get_mut::<CacheControl>() {
             *c = c.merge(cache_control);
@@ -893,18 +1106,35 @@ impl ValueType for CacheEntry {
     }
 }
 
+/// Expiration of an entry in Redis: its TTL, extended by the stale-while-revalidate window
+/// during which it can still be served while it is refreshed
+fn storage_ttl(
+    cache_control: &CacheControl,
+    subgraph_ttl: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
+) -> Option<Duration> {
+    let ttl = cache_control
+        .ttl()
+        .map(|secs| Duration::from_secs(secs as u64))
+        .or(subgraph_ttl)?;
+    let stale_while_revalidate = stale_while_revalidate.or_else(|| {
+        cache_control
+            .stale_while_revalidate()
+            .map(|secs| Duration::from_secs(secs as u64))
+    });
+    Some(ttl + stale_while_revalidate.unwrap_or_default())
+}
+
 async fn cache_store_root_from_response(
     cache: RedisCacheStorage,
     subgraph_ttl: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
     response: &subgraph::Response,
     cache_control: CacheControl,
     cache_key: String,
 ) -> Result<(), BoxError> {
     if let Some(data) = response.response.body().data.as_ref() {
-        let ttl: Option<Duration> = cache_control
-            .ttl()
-            .map(|secs| Duration::from_secs(secs as u64))
-            .or(subgraph_ttl);
+        let ttl = storage_ttl(&cache_control, subgraph_ttl, stale_while_revalidate);
 
         if response.response.body().errors.is_empty() && cache_control.should_store() {
             let span = tracing::info_span!("cache.entity.store");
@@ -928,14 +1158,17 @@ async fn cache_store_root_from_response(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn cache_store_entities_from_response(
     cache: RedisCacheStorage,
     subgraph_ttl: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
     response: &mut subgraph::Response,
     cache_control: CacheControl,
     mut result_from_cache: Vec<IntermediateResult>,
     is_known_private: bool,
     private_id: Option<String>,
+    store: bool,
 ) -> Result<(), BoxError> {
     let mut data = response.response.body_mut().data.take();
 
@@ -945,7 +1178,7 @@ async fn cache_store_entities_from_response(
         .and_then(|o| o.remove(ENTITIES))
     {
         // if the scope is private but we do not have a way to differentiate users, do not store anything in the cache
-        let should_cache_private = !cache_control.private() || private_id.is_some();
+        let should_cache_private = store && (!cache_control.private() || private_id.is_some());
 
         let update_key_private = if !is_known_private && cache_control.private() {
             private_id
@@ -962,6 +1195,7 @@ async fn cache_store_entities_from_response(
             &response.response.body().errors,
             cache,
             subgraph_ttl,
+            stale_while_revalidate,
             cache_control,
             &mut result_from_cache,
             update_key_private,
@@ -1013,6 +1247,8 @@ pub(crate) fn hash_query(query_hash: &QueryHash, body: &graphql::Request) -> Str
 }
 
 pub(crate) fn hash_additional_data(
+    subgraph_name: &str,
+    query_hash: &QueryHash,
     body: &mut graphql::Request,
     context: &Context,
     cache_key: &CacheKeyMetadata,
@@ -1042,6 +1278,15 @@ pub(crate) fn hash_additional_data(
         }
     }
 
+    if let Some(plugin_cache_key) = CacheKeyHooks::key_component(
+        context,
+        CacheKind::Entity { subgraph_name },
+        &query_hash.to_string(),
+        body.operation_name.as_deref(),
+    ) {
+        digest.update(plugin_cache_key);
+    }
+
     hex::encode(digest.finalize().as_slice())
 }
 
@@ -1057,10 +1302,11 @@ fn extract_cache_key_root(
     is_known_private: bool,
     private_id: Option<&str>,
 ) -> String {
+    // hash more data like variables and authorization status
+    let additional_data_hash =
+        hash_additional_data(subgraph_name, query_hash, body, context, cache_key);
     // hash the query and operation name
     let query_hash = hash_query(query_hash, body);
-    // hash more data like variables and authorization status
-    let additional_data_hash = hash_additional_data(body, context, cache_key);
 
     let entity_type = entity_type_opt.unwrap_or("Query");
 
@@ -1094,10 +1340,11 @@ fn extract_cache_keys(
     is_known_private: bool,
     private_id: Option<&str>,
 ) -> Result<Vec<String>, BoxError> {
+    // hash more data like variables and authorization status
+    let additional_data_hash =
+        hash_additional_data(subgraph_name, query_hash, body, context, cache_key);
     // hash the query and operation name
     let query_hash = hash_query(query_hash, body);
-    // hash more data like variables and authorization status
-    let additional_data_hash = hash_additional_data(body, context, cache_key);
 
     let representations = body
         .variables
@@ -1148,6 +1395,16 @@ pub(crate) fn hash_entity_key(representation: &Value) -> String {
     hex::encode(digest.finalize().as_slice())
 }
 
+/// Key of a fetch of the entries missing from the cache, to coalesce concurrent identical fetches
+fn coalescing_key(results: &[IntermediateResult]) -> String {
+    let mut digest = Sha256::new();
+    for result in results.iter().filter(|result| result.cache_entry.is_none()) {
+        digest.update(result.key.as_bytes());
+        digest.update(&[0u8; 1][..]);
+    }
+    hex::encode(digest.finalize().as_slice())
+}
+
 /// represents the result of a cache lookup for an entity type and key
 struct IntermediateResult {
     key: String,
@@ -1162,12 +1419,20 @@ fn filter_representations(
     representations: &mut Vec<Value>,
     keys: Vec<String>,
     mut cache_result: Vec<Option<CacheEntry>>,
+    stale_window: Option<u32>,
     context: &Context,
-) -> Result<(Vec<Value>, Vec<IntermediateResult>, Option<CacheControl>), BoxError> {
-    let mut new_representations: Vec<Value> = Vec::new();
+) -> Result<
+    (
+        Vec<Value>,
+        Option<(Vec<Value>, Vec<IntermediateResult>)>,
+        Vec<IntermediateResult>,
+        Option<CacheControl>,
+    ),
+    BoxError,
+> {
     let mut result = Vec::new();
-    let mut cache_hit: HashMap<String, CacheHitMiss> = HashMap::new();
-    let mut cache_control = None;
+    // representations of the missing and stale entries, with their index in the result
+    let mut to_fetch: Vec<(usize, Value, bool)> = Vec::new();
 
     for ((mut representation, key), mut cache_entry) in representations
         .drain(..)
@@ -1183,18 +1448,62 @@ fn filter_representations(
 
         let typename = opt_type.as_str().unwrap_or("-").to_string();
 
-        // do not use that cache entry if it is stale
-        if let Some(false) = cache_entry.as_ref().map(|c| c.control.can_use()) {
+        // do not use that cache entry if it is stale, beyond its stale-while-revalidate window
+        if let Some(false) = cache_entry
+            .as_ref()
+            .map(|c| c.control.can_use_stale(stale_window))
+        {
             cache_entry = None;
         }
+        let is_stale = cache_entry
+            .as_ref()
+            .map(|c| !c.control.can_use())
+            .unwrap_or(false);
+        if cache_entry.is_none() || is_stale {
+            representation
+                .as_object_mut()
+                .map(|o| o.insert(TYPENAME, opt_type));
+            to_fetch.push((result.len(), representation, is_stale));
+        }
+
+        result.push(IntermediateResult {
+            key,
+            typename,
+            cache_entry,
+        });
+    }
+
+    // if some entities have to be fetched anyway, the stale ones are refreshed in the same fetch,
+    // otherwise they are served from the cache and refreshed in the background
+    let has_misses = to_fetch.iter().any(|(_, _, is_stale)| !is_stale);
+    let mut new_representations: Vec<Value> = Vec::new();
+    let mut stale_representations: Vec<Value> = Vec::new();
+    let mut stale_results = Vec::new();
+    for (index, representation, _) in to_fetch {
+        if has_misses {
+            result[index].cache_entry = None;
+            new_representations.push(representation);
+        } else {
+            stale_representations.push(representation);
+            stale_results.push(IntermediateResult {
+                key: result[index].key.clone(),
+                typename: result[index].typename.clone(),
+                cache_entry: None,
+            });
+        }
+    }
+
+    let mut cache_hit: HashMap<String, CacheHitMiss> = HashMap::new();
+    let mut cache_control = None;
+    for IntermediateResult {
+        typename,
+        cache_entry,
+        ..
+    } in &result
+    {
         match cache_entry.as_ref() {
             None => {
                 cache_hit.entry(typename.clone()).or_default().miss += 1;
-
-                representation
-                    .as_object_mut()
-                    .map(|o| o.insert(TYPENAME, opt_type));
-                new_representations.push(representation);
             }
             Some(entry) => {
                 cache_hit.entry(typename.clone()).or_default().hit += 1;
@@ -1204,12 +1513,6 @@ fn filter_representations(
                 }
             }
         }
-
-        result.push(IntermediateResult {
-            key,
-            typename,
-            cache_entry,
-        });
     }
 
     let _ = context.insert(
@@ -1217,7 +1520,9 @@ fn filter_representations(
         CacheSubgraph(cache_hit),
     );
 
-    Ok((new_representations, result, cache_control))
+    let stale =
+        (!stale_representations.is_empty()).then_some((stale_representations, stale_results));
+    Ok((new_representations, stale, result, cache_control))
 }
 
 // fill in the entities for the response
@@ -1227,15 +1532,13 @@ async fn insert_entities_in_result(
     errors: &[Error],
     cache: RedisCacheStorage,
     subgraph_ttl: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
     cache_control: CacheControl,
     result: &mut Vec<IntermediateResult>,
     update_key_private: Option<String>,
     should_cache_private: bool,
 ) -> Result<(Vec<Value>, Vec<Error>), BoxError> {
-    let ttl: Option<Duration> = cache_control
-        .ttl()
-        .map(|secs| Duration::from_secs(secs as u64))
-        .or(subgraph_ttl);
+    let ttl = storage_ttl(&cache_control, subgraph_ttl, stale_while_revalidate);
 
     let mut new_entities = Vec::new();
     let mut new_errors = Vec::new();
@@ -238,6 +238,7 @@ mod tests {
                     enabled: true,
                     shared_key: String::from("test"),
                 }),
+                ..Default::default()
             },
             subgraphs: HashMap::new(),
         });
@@ -329,6 +330,7 @@ mod tests {
                     enabled: true,
                     shared_key: String::from("test"),
                 }),
+                ..Default::default()
             },
             subgraphs: [(
                 String::from("test"),
@@ -341,6 +343,7 @@ mod tests {
                         enabled: true,
                         shared_key: String::from("test_test"),
                     }),
+                    ..Default::default()
                 },
             )]
             .into_iter()
@@ -431,6 +434,7 @@ mod tests {
                     enabled: true,
                     shared_key: String::from("test"),
                 }),
+                ..Default::default()
             },
             subgraphs: [(
                 String::from("test"),
@@ -443,6 +447,7 @@ mod tests {
                         enabled: true,
                         shared_key: String::from("test_test"),
                     }),
+                    ..Default::default()
                 },
             )]
             .into_iter()
@@ -529,6 +534,7 @@ mod tests {
                     enabled: true,
                     shared_key: String::from("test"),
                 }),
+                ..Default::default()
             },
             subgraphs: HashMap::new(),
         });
@@ -1,7 +1,9 @@
 pub(crate) mod cache_control;
+pub(crate) mod coalescing;
 pub(crate) mod entity;
 pub(crate) mod invalidation;
 pub(crate) mod invalidation_endpoint;
 pub(crate) mod metrics;
+pub(crate) mod response;
 #[cfg(test)]
 pub(crate) mod tests;
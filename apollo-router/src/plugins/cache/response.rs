@@ -0,0 +1,608 @@
+//! Caching of whole responses.
+//!
+//! Unlike the entity cache, which caches the data of each subgraph, this caches the responses of
+//! listed query operations, whose responses are the same for all users, like catalog browsing
+//! queries. The operations are listed by the hash the router computes when parsing them, so that
+//! a client cannot get another operation cached by reusing the name of a listed one. The
+//! responses are keyed by the operation hash and the variables, and stored in Redis for
+//! the TTL configured for the operation, or else for the TTL of the `Cache-Control` headers of
+//! the subgraph responses.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use apollo_compiler::ast::OperationType;
+use bytes::Buf;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::json;
+use serde_json_bytes::Value;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+use tower_service::Service;
+use tracing::Instrument;
+
+use super::cache_control::CacheControl;
+use super::entity::update_cache_control;
+use super::entity::Ttl;
+use crate::batching::BatchQuery;
+use crate::cache::redis::RedisCacheStorage;
+use crate::cache::redis::RedisKey;
+use crate::cache::redis::RedisValue;
+use crate::cache::storage::ValueType;
+use crate::configuration::RedisCache;
+use crate::http_ext;
+use crate::json_ext::Object;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::cache_key::CacheKeyHooks;
+use crate::plugin::CacheKind;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::authorization::AuthorizationPlugin;
+use crate::plugins::data_residency::RegionalCache;
+use crate::query_planner::fetch::QueryHash;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::router;
+use crate::services::router::body::RouterBody;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+/// Change this key if you introduce a breaking change in response caching to make sure it won't take the previous entries
+pub(crate) const RESPONSE_CACHE_VERSION: &str = "1.0";
+
+register_plugin!("apollo", "preview_response_cache", ResponseCache);
+
+#[derive(Clone)]
+pub(crate) struct ResponseCache {
+    storage: Option<RedisCacheStorage>,
+    operations: Arc<HashMap<QueryHash, CachedOperation>>,
+    schema_id: Arc<String>,
+    endpoint_config: Option<Arc<ResponseInvalidationEndpointConfig>>,
+    enabled: bool,
+}
+
+/// Configuration for response caching
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) struct ResponseCacheConfig {
+    /// Enable or disable the response caching feature
+    #[serde(default)]
+    enabled: bool,
+
+    /// Redis configuration
+    redis: RedisCache,
+
+    /// Query operations whose responses are cached, by the hash of the operation computed by the
+    /// router, in hexadecimal. Their responses must be the same for all users
+    #[serde(default)]
+    operations: HashMap<String, CachedOperation>,
+
+    /// Invalidation endpoint configuration
+    invalidation: Option<ResponseInvalidationEndpointConfig>,
+}
+
+/// Configuration of a cached operation
+#[derive(Clone, Debug, Default, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields, default)]
+pub(crate) struct CachedOperation {
+    /// expiration of the responses of this operation. When it is not set, the responses expire
+    /// with the `Cache-Control` headers of the subgraph responses, and are not cached without them
+    pub(crate) ttl: Option<Ttl>,
+}
+
+/// Endpoint invalidating the cached responses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) struct ResponseInvalidationEndpointConfig {
+    /// Specify on which path you want to listen for invalidation endpoint.
+    pub(crate) path: String,
+    /// Listen address on which the invalidation endpoint must listen.
+    pub(crate) listen: ListenAddr,
+    /// Shared key expected in the `Authorization` header of the invalidation requests
+    pub(crate) shared_key: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    data: Value,
+}
+
+impl ValueType for CacheEntry {
+    fn estimated_size(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Cached responses to invalidate
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum ResponseInvalidationRequest {
+    /// All the cached responses
+    All,
+    /// The cached responses of an operation, whatever their variables
+    Operation { operation_hash: QueryHash },
+}
+
+impl ResponseInvalidationRequest {
+    fn key_prefix(&self) -> String {
+        match self {
+            ResponseInvalidationRequest::All => {
+                format!("response:version:{RESPONSE_CACHE_VERSION}:*")
+            }
+            ResponseInvalidationRequest::Operation { operation_hash } => {
+                format!("response:version:{RESPONSE_CACHE_VERSION}:operation:{operation_hash}:*")
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for ResponseCache {
+    type Config = ResponseCacheConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError>
+    where
+        Self: Sized,
+    {
+        let storage = if init.config.enabled {
+            let mut redis_config = init.config.redis.clone();
+            let required_to_start = redis_config.required_to_start;
+            // we need to explicitely disable TTL reset because it is managed directly by this plugin
+            redis_config.reset_ttl = false;
+            match RedisCacheStorage::new(redis_config, "response").await {
+                Ok(storage) => Some(storage),
+                Err(e) => {
+                    tracing::error!(
+                        cache = "response",
+                        e,
+                        "could not open connection to Redis for caching",
+                    );
+                    if required_to_start {
+                        return Err(e);
+                    }
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if init
+            .config
+            .invalidation
+            .as_ref()
+            .map(|i| i.shared_key.is_empty())
+            .unwrap_or_default()
+        {
+            return Err(
+                "you must set a shared_key for the response cache invalidation endpoint"
+                    .to_string()
+                    .into(),
+            );
+        }
+
+        Ok(Self {
+            storage,
+            operations: Arc::new(operations(init.config.operations)?),
+            schema_id: Arc::new(hex::encode(Sha256::digest(init.supergraph_sdl.as_bytes()))),
+            endpoint_config: init.config.invalidation.map(Arc::new),
+            enabled: init.config.enabled,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        match &self.storage {
+            Some(storage) if self.enabled && !self.operations.is_empty() => {
+                CacheService(Some(InnerCacheService {
+                    service,
+                    storage: storage.clone(),
+                    operations: self.operations.clone(),
+                    schema_id: self.schema_id.clone(),
+                }))
+                .boxed()
+            }
+            _ => service,
+        }
+    }
+
+    fn subgraph_service(&self, _name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.enabled || self.storage.is_none() {
+            return service;
+        }
+        let operations = self.operations.clone();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                move |request: &subgraph::Request| {
+                    operation_hash(&request.context)
+                        .map_or(false, |hash| operations.contains_key(&*hash))
+                },
+                |cached: bool, future| async move {
+                    let response: subgraph::Response = future.await?;
+                    // the TTL of the operations without a configured TTL comes from the subgraphs
+                    if cached {
+                        update_cache_control(
+                            &response.context,
+                            &CacheControl::new(response.response.headers(), None)
+                                .ok()
+                                .unwrap_or_else(CacheControl::no_store),
+                        );
+                    }
+                    Ok(response)
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if let (true, Some(storage), Some(endpoint_config)) =
+            (self.enabled, &self.storage, &self.endpoint_config)
+        {
+            let endpoint = Endpoint::from_router_service(
+                endpoint_config.path.clone(),
+                ResponseInvalidationService {
+                    storage: storage.clone(),
+                    shared_key: endpoint_config.shared_key.clone(),
+                }
+                .boxed(),
+            );
+            tracing::info!(
+                "Response caching invalidation endpoint listening on: {}{}",
+                endpoint_config.listen,
+                endpoint_config.path
+            );
+            map.insert(endpoint_config.listen.clone(), endpoint);
+        }
+        map
+    }
+}
+
+impl ResponseCache {
+    #[cfg(test)]
+    pub(crate) async fn with_mocks(
+        storage: RedisCacheStorage,
+        operations: HashMap<String, CachedOperation>,
+    ) -> Result<Self, BoxError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            storage: Some(storage),
+            operations: Arc::new(self::operations(operations)?),
+            schema_id: Arc::new(String::from("schema")),
+            endpoint_config: None,
+            enabled: true,
+        })
+    }
+}
+
+struct CacheService(Option<InnerCacheService>);
+struct InnerCacheService {
+    service: supergraph::BoxService,
+    storage: RedisCacheStorage,
+    operations: Arc<HashMap<QueryHash, CachedOperation>>,
+    schema_id: Arc<String>,
+}
+
+/// Decodes the hashes of the cached operations
+fn operations(
+    operations: HashMap<String, CachedOperation>,
+) -> Result<HashMap<QueryHash, CachedOperation>, BoxError> {
+    operations
+        .into_iter()
+        .map(|(hash, operation)| {
+            let hash = hex::decode(&hash)
+                .map_err(|err| format!("invalid hash of cached operation {hash}: {err}"))?;
+            Ok((QueryHash(hash), operation))
+        })
+        .collect()
+}
+
+/// The hash of the operation computed when it was parsed
+fn operation_hash(context: &crate::Context) -> Option<Arc<QueryHash>> {
+    context
+        .extensions()
+        .with_lock(|lock| lock.get::<ParsedDocument>().map(|doc| doc.hash.clone()))
+}
+
+impl Service<supergraph::Request> for CacheService {
+    type Response = supergraph::Response;
+    type Error = BoxError;
+    type Future = <supergraph::BoxService as Service<supergraph::Request>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.0 {
+            Some(s) => s.service.poll_ready(cx),
+            None => panic!("service should have been called only once"),
+        }
+    }
+
+    fn call(&mut self, request: supergraph::Request) -> Self::Future {
+        match self.0.take() {
+            None => panic!("service should have been called only once"),
+            Some(s) => Box::pin(s.call_inner(request)),
+        }
+    }
+}
+
+impl InnerCacheService {
+    async fn call_inner(
+        mut self,
+        request: supergraph::Request,
+    ) -> Result<supergraph::Response, BoxError> {
+        let body = request.supergraph_request.body();
+        let operation = operation_hash(&request.context)
+            .and_then(|hash| Some((hash.clone(), self.operations.get(&*hash)?.clone())));
+        // Serving a request of a batch from the cache would leave the other requests of the
+        // batch waiting for its subgraph fetches
+        let in_batch = request
+            .context
+            .extensions()
+            .with_lock(|lock| lock.contains_key::<BatchQuery>());
+        let (Some((operation_hash, operation)), false) = (operation, in_batch) else {
+            return self.service.call(request).await;
+        };
+        // requests pinned to a region use its Redis instead, or bypass the cache without one
+        if let Some(RegionalCache(storage)) = request
+            .context
+            .extensions()
+            .with_lock(|lock| lock.get::<RegionalCache>().cloned())
+        {
+            match storage {
+                Some(storage) => self.storage = storage,
+                None => return self.service.call(request).await,
+            }
+        }
+
+        let operation_name = body.operation_name.clone();
+        let key = cache_key(
+            &self.schema_id,
+            &operation_hash,
+            body.operation_name.as_deref(),
+            &body.variables,
+            &request.context,
+        );
+        let cached: Option<RedisValue<CacheEntry>> = self
+            .storage
+            .get(RedisKey(key.clone()))
+            .instrument(tracing::info_span!("cache.response.lookup"))
+            .await;
+        u64_counter!(
+            "apollo.router.operations.response_cache",
+            "Number of cacheable operations served from the response cache or executed",
+            1,
+            "graphql.operation.name" = operation_name.clone().unwrap_or_default(),
+            "cache.hit" = cached.is_some()
+        );
+        if let Some(RedisValue(entry)) = cached {
+            return supergraph::Response::builder()
+                .data(entry.data)
+                .context(request.context)
+                .build();
+        }
+
+        let mut response = self.service.call(request).await?;
+        let Some(first) = response.next_response().await else {
+            return Ok(response);
+        };
+        let ttl = operation.ttl.map(|ttl| ttl.0);
+        if let Some(ttl) = storage_ttl(&response, ttl) {
+            let is_query = response
+                .context
+                .extensions()
+                .with_lock(|lock| lock.get::<ParsedDocument>().cloned())
+                .and_then(|doc| {
+                    doc.executable
+                        .operations
+                        .get(operation_name.as_deref())
+                        .ok()
+                        .map(|op| op.operation_type == OperationType::Query)
+                })
+                .unwrap_or(false);
+            let complete = first.has_next != Some(true) && first.errors.is_empty();
+            if let (true, true, Some(data)) = (is_query, complete, first.data.clone()) {
+                let storage = self.storage.clone();
+                let span = tracing::info_span!("cache.response.store");
+                tokio::spawn(async move {
+                    storage
+                        .insert(RedisKey(key), RedisValue(CacheEntry { data }), Some(ttl))
+                        .instrument(span)
+                        .await;
+                });
+            }
+        }
+
+        Ok(response
+            .map(move |stream| Box::pin(futures::stream::once(async move { first }).chain(stream))))
+    }
+}
+
+/// The key of a response: the operation hash, to invalidate all the responses of an operation,
+/// and a hash of the schema, the variables, the authorization status and the components of the
+/// plugins, like the keys of the entities
+fn cache_key(
+    schema_id: &str,
+    operation_hash: &QueryHash,
+    operation_name: Option<&str>,
+    variables: &Object,
+    context: &Context,
+) -> String {
+    let mut digest = Sha256::new();
+    digest.update(schema_id.as_bytes());
+    digest.update([0u8; 1]);
+    // the variables are sorted so that their order does not change the key
+    let variables: BTreeMap<&str, &Value> = variables
+        .iter()
+        .map(|(name, value)| (name.as_str(), value))
+        .collect();
+    digest.update(serde_json::to_vec(&variables).unwrap_or_default());
+    digest.update([0u8; 1]);
+    digest.update(
+        serde_json::to_vec(&AuthorizationPlugin::cache_key_metadata(context)).unwrap_or_default(),
+    );
+    if let Some(plugin_cache_key) = CacheKeyHooks::key_component(
+        context,
+        CacheKind::Response,
+        &operation_hash.to_string(),
+        operation_name,
+    ) {
+        digest.update([0u8; 1]);
+        digest.update(plugin_cache_key);
+    }
+    let hash = hex::encode(digest.finalize());
+
+    format!("response:version:{RESPONSE_CACHE_VERSION}:operation:{operation_hash}:hash:{hash}")
+}
+
+/// How long a response is stored: the TTL of its operation, or else the TTL of the
+/// `Cache-Control` headers of the subgraph responses. Private responses are never stored
+fn storage_ttl(
+    response: &supergraph::Response,
+    operation_ttl: Option<Duration>,
+) -> Option<Duration> {
+    let cache_control = response
+        .context
+        .extensions()
+        .with_lock(|lock| lock.get::<CacheControl>().cloned());
+    if cache_control.as_ref().map_or(false, CacheControl::private) {
+        return None;
+    }
+    operation_ttl.or_else(|| {
+        let cache_control = cache_control.filter(CacheControl::should_store)?;
+        let ttl = cache_control.ttl()?.saturating_sub(cache_control.elapsed());
+        (ttl > 0).then(|| Duration::from_secs(ttl as u64))
+    })
+}
+
+/// Invalidates the cached responses with `POST`
+#[derive(Clone)]
+struct ResponseInvalidationService {
+    storage: RedisCacheStorage,
+    shared_key: String,
+}
+
+impl ResponseInvalidationService {
+    async fn invalidate(
+        &self,
+        requests: Vec<ResponseInvalidationRequest>,
+    ) -> Result<u64, BoxError> {
+        let mut count = 0u64;
+        for request in requests {
+            let key_prefix = request.key_prefix();
+            // FIXME: configurable batch size
+            let mut stream = self.storage.scan(key_prefix.clone(), Some(10));
+            while let Some(res) = stream.next().await {
+                let scan_res = res.map_err(|e| {
+                    tracing::error!(
+                        pattern = key_prefix,
+                        error = %e,
+                        message = "error scanning for key",
+                    );
+                    e
+                })?;
+                if let Some(keys) = scan_res.results() {
+                    let keys = keys
+                        .iter()
+                        .filter_map(|k| k.as_str())
+                        .map(|k| RedisKey(k.to_string()))
+                        .collect::<Vec<_>>();
+                    if !keys.is_empty() {
+                        count += keys.len() as u64;
+                        self.storage.delete(keys).await;
+                    }
+                }
+            }
+        }
+        u64_counter!(
+            "apollo.router.operations.response_cache.invalidation.entry",
+            "Number of responses removed from the response cache by invalidation requests",
+            count
+        );
+        Ok(count)
+    }
+}
+
+fn response(
+    status: StatusCode,
+    body: String,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .body(body.into())
+            .map_err(BoxError::from)?,
+        context,
+    })
+}
+
+impl Service<router::Request> for ResponseInvalidationService {
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, req: router::Request) -> Self::Future {
+        let endpoint = self.clone();
+        Box::pin(
+            async move {
+                let (parts, body) = req.router_request.into_parts();
+                if !http_ext::has_shared_key(&parts.headers, &endpoint.shared_key) {
+                    return response(
+                        StatusCode::UNAUTHORIZED,
+                        "Invalid authorization header".to_string(),
+                        req.context,
+                    );
+                }
+                if parts.method != Method::POST {
+                    return response(StatusCode::METHOD_NOT_ALLOWED, String::new(), req.context);
+                }
+
+                let requests = Into::<RouterBody>::into(body)
+                    .to_bytes()
+                    .await
+                    .map_err(|e| format!("failed to get the request body: {e}"))
+                    .and_then(|bytes| {
+                        serde_json::from_reader::<_, Vec<ResponseInvalidationRequest>>(
+                            bytes.reader(),
+                        )
+                        .map_err(|err| {
+                            format!("failed to deserialize the request body into JSON: {err}")
+                        })
+                    });
+                match requests {
+                    Ok(requests) => match endpoint.invalidate(requests).await {
+                        Ok(count) => response(
+                            StatusCode::ACCEPTED,
+                            serde_json::to_string(&json!({ "count": count }))?,
+                            req.context,
+                        ),
+                        Err(err) => response(StatusCode::BAD_REQUEST, err.to_string(), req.context),
+                    },
+                    Err(err) => response(StatusCode::BAD_REQUEST, err, req.context),
+                }
+            }
+            .instrument(tracing::info_span!("response_cache_invalidation_endpoint")),
+        )
+    }
+}
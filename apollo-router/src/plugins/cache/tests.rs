@@ -1,5 +1,8 @@
 use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::Bytes;
 use fred::error::RedisErrorKind;
@@ -13,10 +16,17 @@ use parking_lot::Mutex;
 use tower::ServiceExt;
 
 use super::entity::EntityCache;
+use super::entity::Ttl;
+use super::response::CachedOperation;
+use super::response::ResponseCache;
 use crate::cache::redis::RedisCacheStorage;
 use crate::plugin::test::MockSubgraph;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::plugins::cache::entity::Subgraph;
+use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::supergraph;
+use crate::spec::Query;
+use crate::spec::Schema;
 use crate::Context;
 use crate::MockedSubgraphs;
 use crate::TestHarness;
@@ -620,3 +630,266 @@ async fn invalidate() {
     insta::assert_json_snapshot!(response);
     panic!()
 }*/
+
+#[tokio::test]
+async fn coalesce_misses() {
+    let query = "query { currentUser { activeOrganization { id creatorUser { __typename id } } } }";
+
+    let subgraphs = MockedSubgraphs([
+        ("user", MockSubgraph::builder().with_json(
+                serde_json::json!{{"query":"{currentUser{activeOrganization{__typename id}}}"}},
+                serde_json::json!{{"data": {"currentUser": { "activeOrganization": {
+                    "__typename": "Organization",
+                    "id": "1"
+                } }}}}
+        ).with_header(CACHE_CONTROL, HeaderValue::from_static("public")).build()),
+        ("orga", MockSubgraph::builder().with_json(
+            serde_json::json!{{
+                "query": "query($representations:[_Any!]!){_entities(representations:$representations){...on Organization{creatorUser{__typename id}}}}",
+            "variables": {
+                "representations": [
+                    {
+                        "id": "1",
+                        "__typename": "Organization",
+                    }
+                ]
+            }}},
+            serde_json::json!{{"data": {
+                "_entities": [{
+                    "creatorUser": {
+                        "__typename": "User",
+                        "id": 2
+                    }
+                }]
+            }}}
+        ).with_header(CACHE_CONTROL, HeaderValue::from_static("public")).build())
+    ].into_iter().collect());
+
+    let redis_cache = RedisCacheStorage::from_mocks(Arc::new(MockStore::new()))
+        .await
+        .unwrap();
+    let map = ["user", "orga"]
+        .into_iter()
+        .map(|name| {
+            (
+                name.to_string(),
+                Subgraph {
+                    coalesce_misses: true,
+                    ..Default::default()
+                },
+            )
+        })
+        .collect();
+    let entity_cache = EntityCache::with_mocks(redis_cache.clone(), map)
+        .await
+        .unwrap();
+
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let counter = fetches.clone();
+    let service = TestHarness::builder()
+        .configuration_json(serde_json::json!({"include_subgraph_errors": { "all": true } }))
+        .unwrap()
+        .schema(SCHEMA)
+        .extra_plugin(entity_cache)
+        // slow down the subgraphs so that both requests miss the cache while the fetches are in flight
+        .subgraph_hook(move |_name, service| {
+            let counter = counter.clone();
+            service
+                .map_request(move |request| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    request
+                })
+                .map_future(|response| async move {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    response.await
+                })
+                .boxed()
+        })
+        .extra_plugin(subgraphs)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let request = || {
+        supergraph::Request::fake_builder()
+            .query(query)
+            .context(Context::new())
+            .build()
+            .unwrap()
+    };
+    let (first, second) = tokio::join!(
+        service.clone().oneshot(request()),
+        service.oneshot(request())
+    );
+    let first = first.unwrap().next_response().await.unwrap();
+    let second = second.unwrap().next_response().await.unwrap();
+
+    // one fetch to each subgraph served both requests
+    assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    assert_eq!(first, second);
+    assert_eq!(
+        first.data,
+        Some(serde_json_bytes::json!({
+            "currentUser": {
+                "activeOrganization": {
+                    "id": "1",
+                    "creatorUser": {
+                        "__typename": "User",
+                        "id": 2
+                    }
+                }
+            }
+        }))
+    );
+}
+
+#[tokio::test]
+async fn response_cache() {
+    let query = "query Current { currentUser { activeOrganization { id } } }";
+
+    let subgraphs = MockedSubgraphs([
+        ("user", MockSubgraph::builder().with_json(
+                serde_json::json!{{"query":"query Current__user__0{currentUser{activeOrganization{id}}}", "operationName": "Current__user__0"}},
+                serde_json::json!{{"data": {"currentUser": { "activeOrganization": {
+                    "id": "1"
+                } }}}}
+        ).build()),
+    ].into_iter().collect());
+
+    let store = MockStore::new();
+    let map = store.map.clone();
+    let redis_cache = RedisCacheStorage::from_mocks(Arc::new(store))
+        .await
+        .unwrap();
+    // the operations are listed by the hash computed when parsing them
+    let schema = Schema::parse(SCHEMA, &Default::default()).unwrap();
+    let document =
+        Query::parse_document(query, Some("Current"), &schema, &Default::default()).unwrap();
+    let context = || {
+        let context = Context::new();
+        context
+            .extensions()
+            .with_lock(|mut lock| lock.insert::<ParsedDocument>(document.clone()));
+        context
+    };
+    let operations: HashMap<String, CachedOperation> = [(
+        document.hash.to_string(),
+        CachedOperation {
+            ttl: Some(Ttl(Duration::from_secs(60))),
+        },
+    )]
+    .into_iter()
+    .collect();
+    let response_cache = ResponseCache::with_mocks(redis_cache.clone(), operations.clone())
+        .await
+        .unwrap();
+
+    let service = TestHarness::builder()
+        .schema(SCHEMA)
+        .extra_plugin(response_cache)
+        .extra_plugin(subgraphs)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let request = supergraph::Request::fake_builder()
+        .query(query)
+        .operation_name("Current")
+        .context(context())
+        .build()
+        .unwrap();
+    let response = service
+        .oneshot(request)
+        .await
+        .unwrap()
+        .next_response()
+        .await
+        .unwrap();
+    let expected = serde_json_bytes::json!({"currentUser": {"activeOrganization": {"id": "1"}}});
+    assert_eq!(response.data, Some(expected.clone()));
+
+    // the response is stored in the background
+    while map.lock().is_empty() {
+        tokio::task::yield_now().await;
+    }
+
+    // Now testing without any mock subgraphs, the response should come from the cache
+    let response_cache = ResponseCache::with_mocks(redis_cache.clone(), operations.clone())
+        .await
+        .unwrap();
+
+    let service = TestHarness::builder()
+        .schema(SCHEMA)
+        .extra_plugin(response_cache)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let request = supergraph::Request::fake_builder()
+        .query(query)
+        .operation_name("Current")
+        .context(context())
+        .build()
+        .unwrap();
+    let response = service
+        .oneshot(request)
+        .await
+        .unwrap()
+        .next_response()
+        .await
+        .unwrap();
+    assert_eq!(response.data, Some(expected.clone()));
+
+    // the responses of authenticated requests are not served to unauthenticated ones
+    let subgraphs = MockedSubgraphs([
+        ("user", MockSubgraph::builder().with_json(
+                serde_json::json!{{"query":"query Current__user__0{currentUser{activeOrganization{id}}}", "operationName": "Current__user__0"}},
+                serde_json::json!{{"data": {"currentUser": { "activeOrganization": {
+                    "id": "2"
+                } }}}}
+        ).build()),
+    ].into_iter().collect());
+    let response_cache = ResponseCache::with_mocks(redis_cache.clone(), operations.clone())
+        .await
+        .unwrap();
+
+    let service = TestHarness::builder()
+        .schema(SCHEMA)
+        .extra_plugin(response_cache)
+        .extra_plugin(subgraphs)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let context = context();
+    context
+        .insert(
+            APOLLO_AUTHENTICATION_JWT_CLAIMS,
+            serde_json::json!({"sub": "2"}),
+        )
+        .unwrap();
+    let request = supergraph::Request::fake_builder()
+        .query(query)
+        .operation_name("Current")
+        .context(context)
+        .build()
+        .unwrap();
+    let response = service
+        .oneshot(request)
+        .await
+        .unwrap()
+        .next_response()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.data,
+        Some(serde_json_bytes::json!({"currentUser": {"activeOrganization": {"id": "2"}}}))
+    );
+
+    let operations = [("Current".to_string(), CachedOperation::default())]
+        .into_iter()
+        .collect();
+    assert!(ResponseCache::with_mocks(redis_cache, operations)
+        .await
+        .is_err());
+}
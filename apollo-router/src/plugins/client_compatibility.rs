@@ -0,0 +1,663 @@
+//! Compatibility of the responses with clients that can't be updated.
+//!
+//! Shipped clients, like mobile applications, can stay in use for years after the graph evolved.
+//! Rules matching them by client name and version adjust their operations and responses: the
+//! `__typename` field is always selected on some types, for clients whose cache requires it, the
+//! renamed error codes are returned with their former name, and the error paths can be formatted
+//! as strings, like older servers did. Other clients get the regular responses.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use apollo_compiler::ast;
+use apollo_compiler::name;
+use apollo_compiler::schema::ExtendedType;
+use http::header::CONTENT_TYPE;
+use lru::LruCache;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::cache::DEFAULT_CACHE_CAPACITY;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::plugins::telemetry::CLIENT_VERSION;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::router;
+use crate::services::router::body::get_body_bytes;
+use crate::services::router::body::RouterBody;
+use crate::services::supergraph;
+use crate::spec::query::transform;
+use crate::spec::Query;
+use crate::spec::Schema;
+use crate::spec::TYPENAME;
+use crate::Configuration;
+use crate::Context;
+
+register_plugin!("apollo", "client_compatibility", ClientCompatibility);
+
+/// Per-client adjustments of the operations and responses, for clients that can't be updated
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct ClientCompatibilityConfig {
+    /// Rules checked in order, the first rule matching the client applies
+    rules: Vec<CompatibilityRule>,
+}
+
+/// Adjustments applied to the operations of some clients
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct CompatibilityRule {
+    /// Name of the rule, used in logs
+    name: String,
+    /// Clients matched by the rule. Every client matches when no criteria is set
+    #[serde(default, rename = "match")]
+    conditions: ClientMatch,
+    /// Types whose `__typename` field is always returned, even when the operation doesn't select
+    /// it. Abstract types are matched by their implementations
+    #[serde(default)]
+    include_typename: Vec<String>,
+    /// Former names of the renamed error codes, by current name. The `code` extension of the
+    /// errors is returned with its former name
+    #[serde(default)]
+    error_codes: BTreeMap<String, String>,
+    /// Return the path of the errors as a string, like `products.0.name`, instead of a list
+    #[serde(default)]
+    legacy_error_path: bool,
+}
+
+/// Criteria of the matched clients. A client must match all the set criteria
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct ClientMatch {
+    /// Names of the matched clients
+    client_names: Vec<String>,
+    /// Semantic version requirement of the matched client versions, like `<2.4.0`. Clients
+    /// without a valid semantic version don't match
+    client_versions: Option<String>,
+}
+
+/// A rule ready to be matched
+struct Rule {
+    name: String,
+    client_names: Vec<String>,
+    client_versions: Option<semver::VersionReq>,
+    include_typename: HashSet<String>,
+    error_codes: BTreeMap<String, String>,
+    legacy_error_path: bool,
+}
+
+impl Rule {
+    fn new(rule: CompatibilityRule) -> Result<Self, BoxError> {
+        let name = rule.name;
+        let client_versions = rule
+            .conditions
+            .client_versions
+            .as_deref()
+            .map(semver::VersionReq::parse)
+            .transpose()
+            .map_err(|err| {
+                format!(
+                    "invalid client version requirement of the {name} compatibility rule: {err}"
+                )
+            })?;
+        Ok(Self {
+            name,
+            client_names: rule.conditions.client_names,
+            client_versions,
+            include_typename: rule.include_typename.into_iter().collect(),
+            error_codes: rule.error_codes,
+            legacy_error_path: rule.legacy_error_path,
+        })
+    }
+
+    fn matches(&self, context: &Context) -> bool {
+        if !self.client_names.is_empty() {
+            let client_name = context.get::<_, String>(CLIENT_NAME).ok().flatten();
+            if !client_name.is_some_and(|name| self.client_names.contains(&name)) {
+                return false;
+            }
+        }
+        if let Some(client_versions) = &self.client_versions {
+            let client_version = context
+                .get::<_, String>(CLIENT_VERSION)
+                .ok()
+                .flatten()
+                .and_then(|version| semver::Version::parse(version.trim_start_matches('v')).ok());
+            if !client_version.is_some_and(|version| client_versions.matches(&version)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn adjusts_errors(&self) -> bool {
+        !self.error_codes.is_empty() || self.legacy_error_path
+    }
+
+    /// Adjusts the errors of a serialized GraphQL response, or of a batch of responses
+    fn adjust_errors(&self, response: &mut serde_json::Value) {
+        if let Some(responses) = response.as_array_mut() {
+            responses
+                .iter_mut()
+                .for_each(|response| self.adjust_errors(response));
+            return;
+        }
+        let Some(errors) = response
+            .get_mut("errors")
+            .and_then(|errors| errors.as_array_mut())
+        else {
+            return;
+        };
+        for error in errors {
+            if let Some(code) = error
+                .get_mut("extensions")
+                .and_then(|extensions| extensions.get_mut("code"))
+            {
+                if let Some(former) = code.as_str().and_then(|code| self.error_codes.get(code)) {
+                    *code = former.clone().into();
+                }
+            }
+            if self.legacy_error_path {
+                if let Some(path) = error.get_mut("path") {
+                    if let Some(segments) = path.as_array() {
+                        *path = legacy_path(segments).into();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Formats an error path as a string, like `products.0.name`
+fn legacy_path(segments: &[serde_json::Value]) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            serde_json::Value::String(key) => key.clone(),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Adds the `__typename` field to the selections of the configured types
+struct TypenameVisitor<'a> {
+    schema: &'a apollo_compiler::Schema,
+    types: &'a HashSet<String>,
+    changed: bool,
+}
+
+impl TypenameVisitor<'_> {
+    fn includes_typename(&self, type_name: &str) -> bool {
+        if self.types.contains(type_name) {
+            return true;
+        }
+        match self.schema.types.get(type_name) {
+            Some(ExtendedType::Interface(_) | ExtendedType::Union(_)) => self
+                .types
+                .iter()
+                .any(|included| self.schema.is_subtype(type_name, included)),
+            _ => false,
+        }
+    }
+}
+
+impl transform::Visitor for TypenameVisitor<'_> {
+    fn schema(&self) -> &apollo_compiler::Schema {
+        self.schema
+    }
+
+    fn field(
+        &mut self,
+        _parent_type: &str,
+        field_def: &ast::FieldDefinition,
+        def: &ast::Field,
+    ) -> Result<Option<ast::Field>, BoxError> {
+        let mut field = transform::field(self, field_def, def)?;
+        if let Some(field) = &mut field {
+            let has_typename = field.selection_set.iter().any(|selection| {
+                matches!(selection, ast::Selection::Field(field)
+                    if field.name.as_str() == TYPENAME && field.alias.is_none())
+            });
+            if !field.selection_set.is_empty()
+                && !has_typename
+                && self.includes_typename(field_def.ty.inner_named_type())
+            {
+                field.selection_set.push(ast::Selection::Field(
+                    ast::Field {
+                        alias: None,
+                        name: name!("__typename"),
+                        arguments: Vec::new(),
+                        directives: Default::default(),
+                        selection_set: Vec::new(),
+                    }
+                    .into(),
+                ));
+                self.changed = true;
+            }
+        }
+        Ok(field)
+    }
+}
+
+type RewrittenQuery = Option<(String, ParsedDocument)>;
+
+/// Operations rewritten to include the `__typename` field
+struct TypenameRewriter {
+    schema: Arc<Schema>,
+    configuration: Arc<Configuration>,
+    /// Rewritten operations by rule, query and operation name, `None` when unchanged
+    cache: Mutex<LruCache<(String, String, Option<String>), RewrittenQuery>>,
+}
+
+impl TypenameRewriter {
+    fn rewrite(&self, rule: &Rule, request: &mut supergraph::Request) {
+        let Some(query) = request.supergraph_request.body().query.clone() else {
+            return;
+        };
+        let operation_name = request.supergraph_request.body().operation_name.clone();
+        let key = (rule.name.clone(), query, operation_name);
+        let cached = self.cache.lock().expect("lock poisoned").get(&key).cloned();
+        let rewritten = match cached {
+            Some(rewritten) => rewritten,
+            None => {
+                let doc = request
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<ParsedDocument>().cloned());
+                let Some(doc) = doc else {
+                    return;
+                };
+                let rewritten = match self.rewrite_document(rule, &doc, key.2.as_deref()) {
+                    Ok(rewritten) => rewritten,
+                    Err(error) => {
+                        tracing::debug!(
+                            "could not add __typename for the {} compatibility rule: {error}",
+                            rule.name
+                        );
+                        None
+                    }
+                };
+                self.cache
+                    .lock()
+                    .expect("lock poisoned")
+                    .put(key, rewritten.clone());
+                rewritten
+            }
+        };
+
+        if let Some((query, doc)) = rewritten {
+            request.supergraph_request.body_mut().query = Some(query);
+            request
+                .context
+                .extensions()
+                .with_lock(|mut lock| lock.insert::<ParsedDocument>(doc));
+        }
+    }
+
+    fn rewrite_document(
+        &self,
+        rule: &Rule,
+        doc: &ParsedDocument,
+        operation_name: Option<&str>,
+    ) -> Result<RewrittenQuery, BoxError> {
+        let mut visitor = TypenameVisitor {
+            schema: self.schema.supergraph_schema(),
+            types: &rule.include_typename,
+            changed: false,
+        };
+        let ast = transform::document(&mut visitor, &doc.ast)?;
+        if !visitor.changed {
+            return Ok(None);
+        }
+        let query = ast.to_string();
+        let doc = Query::parse_document(&query, operation_name, &self.schema, &self.configuration)?;
+        Ok(Some((query, doc)))
+    }
+}
+
+struct ClientCompatibility {
+    rules: Arc<Vec<Arc<Rule>>>,
+    typename_rewriter: Option<Arc<TypenameRewriter>>,
+}
+
+impl ClientCompatibility {
+    fn rule(rules: &[Arc<Rule>], context: &Context) -> Option<Arc<Rule>> {
+        rules.iter().find(|rule| rule.matches(context)).cloned()
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for ClientCompatibility {
+    type Config = ClientCompatibilityConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let rules = init
+            .config
+            .rules
+            .into_iter()
+            .map(|rule| Rule::new(rule).map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()?;
+        for rule in &rules {
+            if let Some(unknown) = rule.include_typename.iter().find(|type_name| {
+                !init
+                    .supergraph_schema
+                    .types
+                    .contains_key(type_name.as_str())
+            }) {
+                return Err(format!(
+                    "unknown type {unknown} in the {} compatibility rule",
+                    rule.name
+                )
+                .into());
+            }
+        }
+
+        let typename_rewriter = if rules.iter().any(|rule| !rule.include_typename.is_empty()) {
+            // the rewritten operations are parsed like the operations of the clients
+            let configuration = init.full_config.clone().unwrap_or_default();
+            let schema = match init.router_schema.clone() {
+                Some(schema) => schema,
+                None => Arc::new(Schema::parse(&init.supergraph_sdl, &configuration)?),
+            };
+            Some(Arc::new(TypenameRewriter {
+                schema,
+                configuration,
+                cache: Mutex::new(LruCache::new(DEFAULT_CACHE_CAPACITY)),
+            }))
+        } else {
+            None
+        };
+        Ok(Self {
+            rules: Arc::new(rules),
+            typename_rewriter,
+        })
+    }
+
+    fn router_service(&self, service: router::BoxService) -> router::BoxService {
+        if !self.rules.iter().any(|rule| rule.adjusts_errors()) {
+            return service;
+        }
+
+        let rules = self.rules.clone();
+        service
+            .and_then(move |response: router::Response| async move {
+                let Some(rule) =
+                    Self::rule(&rules, &response.context).filter(|rule| rule.adjusts_errors())
+                else {
+                    return Ok(response);
+                };
+                // multipart responses are streamed, and CBOR responses are left as is
+                let is_json = response
+                    .response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|content_type| content_type.contains("json"));
+                if !is_json {
+                    return Ok(response);
+                }
+
+                let (parts, body) = response.response.into_parts();
+                let bytes = get_body_bytes(body).await?;
+                let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                    Ok(mut json) => {
+                        rule.adjust_errors(&mut json);
+                        serde_json::to_vec(&json)?.into()
+                    }
+                    Err(_) => bytes,
+                };
+                Ok::<_, BoxError>(router::Response {
+                    response: http::Response::from_parts(
+                        parts,
+                        RouterBody::from(body).into_inner(),
+                    ),
+                    context: response.context,
+                })
+            })
+            .boxed()
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let Some(typename_rewriter) = self.typename_rewriter.clone() else {
+            return service;
+        };
+
+        let rules = self.rules.clone();
+        ServiceBuilder::new()
+            .map_request(move |mut request: supergraph::Request| {
+                if let Some(rule) = Self::rule(&rules, &request.context)
+                    .filter(|rule| !rule.include_typename.is_empty())
+                {
+                    typename_rewriter.rewrite(&rule, &mut request);
+                }
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::graphql;
+    use crate::json_ext::Path;
+    use crate::plugin::test::MockRouterService;
+    use crate::plugin::test::MockSupergraphService;
+
+    const SCHEMA: &str = include_str!("../testdata/supergraph.graphql");
+
+    async fn plugin(config: serde_json::Value) -> Result<ClientCompatibility, BoxError> {
+        ClientCompatibility::new(PluginInit::fake_new(
+            serde_json::from_value(config).unwrap(),
+            Arc::new(SCHEMA.to_string()),
+        ))
+        .await
+    }
+
+    fn context(client_name: &str, client_version: &str) -> Context {
+        let context = Context::new();
+        context
+            .insert(CLIENT_NAME, client_name.to_string())
+            .unwrap();
+        context
+            .insert(CLIENT_VERSION, client_version.to_string())
+            .unwrap();
+        context
+    }
+
+    #[tokio::test]
+    async fn rules_match_client_names_and_versions() {
+        let plugin = plugin(serde_json::json!({
+            "rules": [{
+                "name": "legacy_ios",
+                "match": { "client_names": ["ios"], "client_versions": "<2.0.0" },
+                "legacy_error_path": true
+            }]
+        }))
+        .await
+        .unwrap();
+        let rules = &plugin.rules;
+        assert!(ClientCompatibility::rule(rules, &context("ios", "1.9.3")).is_some());
+        assert!(ClientCompatibility::rule(rules, &context("ios", "v1.2.0")).is_some());
+        assert!(ClientCompatibility::rule(rules, &context("ios", "2.0.0")).is_none());
+        assert!(ClientCompatibility::rule(rules, &context("ios", "latest")).is_none());
+        assert!(ClientCompatibility::rule(rules, &context("android", "1.0.0")).is_none());
+
+        assert!(plugin(serde_json::json!({
+            "rules": [{ "name": "version", "match": { "client_versions": "not a version" } }]
+        }))
+        .await
+        .is_err());
+        assert!(plugin(serde_json::json!({
+            "rules": [{ "name": "type", "include_typename": ["Unknown"] }]
+        }))
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn errors_are_returned_in_the_legacy_format() {
+        let plugin = plugin(serde_json::json!({
+            "rules": [{
+                "name": "legacy",
+                "match": { "client_names": ["ios"] },
+                "error_codes": { "SUBREQUEST_HTTP_ERROR": "DOWNSTREAM_SERVICE_ERROR" },
+                "legacy_error_path": true
+            }]
+        }))
+        .await
+        .unwrap();
+
+        for (client_name, path, code) in [
+            (
+                "ios",
+                serde_json::json!("me.reviews.0"),
+                "DOWNSTREAM_SERVICE_ERROR",
+            ),
+            (
+                "web",
+                serde_json::json!(["me", "reviews", 0]),
+                "SUBREQUEST_HTTP_ERROR",
+            ),
+        ] {
+            let mut mock = MockRouterService::new();
+            mock.expect_call().times(1).returning(|request| {
+                router::Response::fake_builder()
+                    .data(json!({ "me": null }))
+                    .error(
+                        graphql::Error::builder()
+                            .message("subgraph unavailable")
+                            .path(Path::from("me/reviews/0"))
+                            .extension_code("SUBREQUEST_HTTP_ERROR")
+                            .build(),
+                    )
+                    .header("content-type", "application/json")
+                    .context(request.context)
+                    .build()
+            });
+            let request = router::Request::fake_builder()
+                .context(context(client_name, "1.0.0"))
+                .build()
+                .unwrap();
+            let response = plugin
+                .router_service(mock.boxed())
+                .oneshot(request)
+                .await
+                .unwrap();
+            let body = get_body_bytes(response.response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["data"], serde_json::json!({ "me": null }));
+            assert_eq!(body["errors"][0]["path"], path);
+            assert_eq!(body["errors"][0]["extensions"]["code"], code);
+        }
+    }
+
+    #[tokio::test]
+    async fn typename_is_added_to_the_selected_types() {
+        let plugin = plugin(serde_json::json!({
+            "rules": [{
+                "name": "cache",
+                "match": { "client_names": ["ios"] },
+                "include_typename": ["User"]
+            }]
+        }))
+        .await
+        .unwrap();
+        let rewriter = plugin.typename_rewriter.clone().unwrap();
+        let query = "{ me { name } topProducts { upc } }";
+        let request = |client_name: &str| {
+            let request = supergraph::Request::fake_builder()
+                .query(query)
+                .context(context(client_name, "1.0.0"))
+                .build()
+                .unwrap();
+            let doc = Query::parse_document(query, None, &rewriter.schema, &rewriter.configuration)
+                .unwrap();
+            request
+                .context
+                .extensions()
+                .with_lock(|mut lock| lock.insert::<ParsedDocument>(doc));
+            request
+        };
+
+        // only the `User` selection gets the `__typename` field
+        for (client_name, typenames) in [("ios", 1), ("web", 0)] {
+            let mut mock = MockSupergraphService::new();
+            mock.expect_call().times(1).returning(|request| {
+                let query = request.supergraph_request.body().query.clone().unwrap();
+                let doc = request
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<ParsedDocument>().cloned())
+                    .unwrap();
+                supergraph::Response::fake_builder()
+                    .data(json!({ "query": query, "parsed": doc.ast.to_string() }))
+                    .context(request.context)
+                    .build()
+            });
+            let mut response = plugin
+                .supergraph_service(mock.boxed())
+                .oneshot(request(client_name))
+                .await
+                .unwrap();
+            let data = response.next_response().await.unwrap().data.unwrap();
+            let query = data["query"].as_str().unwrap();
+            assert_eq!(query.matches("__typename").count(), typenames);
+            assert_eq!(
+                data["parsed"]
+                    .as_str()
+                    .unwrap()
+                    .matches("__typename")
+                    .count(),
+                typenames
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn operations_are_parsed_with_the_router_configuration() {
+        let configuration: Configuration = serde_json::from_value(serde_json::json!({
+            "limits": { "parser_max_tokens": 6 }
+        }))
+        .unwrap();
+        let schema = Arc::new(Schema::parse(SCHEMA, &configuration).unwrap());
+        let plugin = ClientCompatibility::new(
+            PluginInit::fake_builder()
+                .config(
+                    serde_json::from_value(serde_json::json!({
+                        "rules": [{ "name": "cache", "include_typename": ["User"] }]
+                    }))
+                    .unwrap(),
+                )
+                .supergraph_sdl(Arc::new(SCHEMA.to_string()))
+                .supergraph_schema(Arc::new(schema.supergraph_schema().clone()))
+                .full_config(Arc::new(configuration))
+                .router_schema(schema.clone())
+                .build(),
+        )
+        .await
+        .unwrap();
+        let rewriter = plugin.typename_rewriter.clone().unwrap();
+        assert!(Arc::ptr_eq(&rewriter.schema, &schema));
+
+        // the operation with `__typename` exceeds the token limit of the router
+        let doc =
+            Query::parse_document("{ me { name } }", None, &schema, &Configuration::default())
+                .unwrap();
+        assert!(rewriter
+            .rewrite_document(&plugin.rules[0], &doc, None)
+            .is_err());
+    }
+}
@@ -0,0 +1,452 @@
+//! Resolution of the arguments set with `@fromContext` at execution.
+//!
+//! The values of context arguments are selected in the data already fetched for the parents of
+//! an entity. When a value can't be found, because the parent selection is missing or null, the
+//! subgraph receives no value and the whole entity is usually nulled with an opaque error. This
+//! plugin chooses what happens to those entities, reports structured errors identifying the
+//! argument and the types setting the context, and records metrics per context argument.
+//!
+//! The query planner passes context arguments to subgraphs in generated variables, like
+//! `contextualArgument_1_0`. Errors and metrics name the `@context` and the argument instead,
+//! from the `contextArguments` of the `@join__field` directives of the supergraph.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use apollo_compiler::ast;
+use apollo_compiler::executable::Selection;
+use apollo_compiler::executable::SelectionSet;
+use apollo_compiler::schema::ExtendedType;
+use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Name;
+use apollo_compiler::Schema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::json;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::error::Error;
+use crate::json_ext::Path;
+use crate::json_ext::Value;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::progressive_override::JOIN_FIELD_DIRECTIVE_NAME;
+use crate::plugins::progressive_override::JOIN_SPEC_BASE_URL;
+use crate::query_planner::subgraph_context::MissingContextValue;
+use crate::register_plugin;
+use crate::services::supergraph;
+use crate::spec;
+
+register_plugin!("apollo", "context_arguments", ContextArguments);
+
+const CONTEXT_VALUE_NOT_FOUND: &str = "CONTEXT_VALUE_NOT_FOUND";
+// context arguments were added to the join spec in v0.5
+const JOIN_SPEC_VERSION_RANGE: &str = ">=0.5";
+const CONTEXT_ARGUMENTS_ARG_NAME: &str = "contextArguments";
+
+/// Resolution of the arguments set with `@fromContext`
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct ContextArgumentsConfig {
+    /// Apply the policy and report errors and metrics for context arguments
+    enabled: bool,
+    /// What happens to an entity when the value of one of its context arguments can't be found
+    on_missing_value: MissingValuePolicy,
+    /// Record the `apollo.router.operations.context_arguments` counter, by context argument
+    metrics: bool,
+}
+
+impl Default for ContextArgumentsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_missing_value: MissingValuePolicy::default(),
+            metrics: true,
+        }
+    }
+}
+
+/// What happens to an entity when the value of a context argument can't be found. Fetching the
+/// missing values again with an augmented parent fetch is not supported
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MissingValuePolicy {
+    /// The entity is not fetched, and an error is returned at its path
+    #[default]
+    Error,
+    /// The entity is fetched with a null value for the argument, so that only the fields using
+    /// it are affected, and an error is returned at its path. Subgraphs reject null values for
+    /// non nullable arguments
+    NullField,
+}
+
+/// The contexts of the arguments set with `@fromContext` in the supergraph, by type, field and
+/// argument. The contexts are prefixed with the name of their subgraph, like
+/// `Subgraph1__context`, because a field can be resolved by several subgraphs
+type ContextArgumentDefinitions = HashMap<(Name, Name, Name), Vec<String>>;
+
+fn collect_context_arguments(schema: &Schema) -> ContextArgumentDefinitions {
+    let mut definitions = ContextArgumentDefinitions::new();
+    let Some(join_field_directive_name) = spec::Schema::directive_name(
+        schema,
+        JOIN_SPEC_BASE_URL,
+        JOIN_SPEC_VERSION_RANGE,
+        JOIN_FIELD_DIRECTIVE_NAME,
+    ) else {
+        return definitions;
+    };
+
+    for (type_name, extended_type) in &schema.types {
+        let fields = match extended_type {
+            ExtendedType::Object(object_type) => &object_type.fields,
+            ExtendedType::Interface(interface_type) => &interface_type.fields,
+            _ => continue,
+        };
+        for (field_name, field) in fields {
+            let context_arguments = field
+                .directives
+                .iter()
+                .filter(|d| d.name.as_str() == join_field_directive_name)
+                .filter_map(
+                    |d| match d.argument_by_name(CONTEXT_ARGUMENTS_ARG_NAME)?.as_ref() {
+                        ast::Value::List(arguments) => Some(arguments),
+                        _ => None,
+                    },
+                )
+                .flatten()
+                .filter_map(|argument| match argument.as_ref() {
+                    ast::Value::Object(fields) => Some(fields),
+                    _ => None,
+                });
+            for argument in context_arguments {
+                let value = |name: &str| {
+                    argument
+                        .iter()
+                        .find(|(key, _)| key.as_str() == name)
+                        .and_then(|(_, value)| value.as_str())
+                };
+                let (Some(context), Some(Ok(name))) =
+                    (value("context"), value("name").map(Name::new))
+                else {
+                    continue;
+                };
+                definitions
+                    .entry((type_name.clone(), field_name.clone(), name))
+                    .or_default()
+                    .push(context.to_string());
+            }
+        }
+    }
+    definitions
+}
+
+/// A context argument passed to a subgraph, as defined in the schema
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ContextArgument {
+    /// The name given to the context with `@context`
+    context: String,
+    /// The coordinate of the argument, like `U.field(a:)`
+    coordinate: String,
+}
+
+/// Set in the context extensions when the resolution of context arguments is configured
+#[derive(Clone, Debug)]
+pub(crate) struct ContextArgumentResolution {
+    pub(crate) on_missing_value: MissingValuePolicy,
+    metrics: bool,
+    definitions: Arc<ContextArgumentDefinitions>,
+    // the context arguments of the current fetch, by the name of their variable
+    arguments: HashMap<String, ContextArgument>,
+}
+
+impl ContextArgumentResolution {
+    /// The resolution for a fetch to `service_name`, naming the context arguments passed in the
+    /// variables of its operation
+    pub(crate) fn for_fetch(&self, service_name: &str, document: &ExecutableDocument) -> Self {
+        let mut arguments = HashMap::new();
+        for operation in document.operations.iter() {
+            self.collect_fetch_arguments(
+                service_name,
+                document,
+                &operation.selection_set,
+                &mut arguments,
+            );
+        }
+        Self {
+            arguments,
+            ..self.clone()
+        }
+    }
+
+    fn collect_fetch_arguments(
+        &self,
+        service_name: &str,
+        document: &ExecutableDocument,
+        selection_set: &SelectionSet,
+        arguments: &mut HashMap<String, ContextArgument>,
+    ) {
+        for selection in &selection_set.selections {
+            match selection {
+                Selection::Field(field) => {
+                    for argument in &field.arguments {
+                        let ast::Value::Variable(variable) = argument.value.as_ref() else {
+                            continue;
+                        };
+                        let key = (
+                            selection_set.ty.clone(),
+                            field.name.clone(),
+                            argument.name.clone(),
+                        );
+                        let context = self.definitions.get(&key).and_then(|contexts| {
+                            contexts.iter().find_map(|context| {
+                                context.strip_prefix(service_name)?.strip_prefix("__")
+                            })
+                        });
+                        if let Some(context) = context {
+                            arguments.insert(
+                                variable.to_string(),
+                                ContextArgument {
+                                    context: context.to_string(),
+                                    coordinate: format!(
+                                        "{}.{}({}:)",
+                                        selection_set.ty, field.name, argument.name
+                                    ),
+                                },
+                            );
+                        }
+                    }
+                    self.collect_fetch_arguments(
+                        service_name,
+                        document,
+                        &field.selection_set,
+                        arguments,
+                    );
+                }
+                Selection::InlineFragment(fragment) => self.collect_fetch_arguments(
+                    service_name,
+                    document,
+                    &fragment.selection_set,
+                    arguments,
+                ),
+                Selection::FragmentSpread(spread) => {
+                    if let Some(fragment) = document.fragments.get(&spread.fragment_name) {
+                        self.collect_fetch_arguments(
+                            service_name,
+                            document,
+                            &fragment.selection_set,
+                            arguments,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // the context name and the coordinate of the argument passed in `variable`. Falls back to
+    // the variable for operations the argument was not found in
+    fn argument(&self, variable: &str) -> (Option<&str>, String) {
+        match self.arguments.get(variable) {
+            Some(argument) => (Some(&argument.context), argument.coordinate.clone()),
+            None => (None, variable.to_string()),
+        }
+    }
+
+    /// The errors reported for the entity at `path` when some of its context arguments could not
+    /// be resolved
+    pub(crate) fn errors(
+        &self,
+        path: &Path,
+        entity: &Value,
+        missing: &[MissingContextValue],
+    ) -> Vec<Error> {
+        let typename = entity.get("__typename").cloned().unwrap_or_default();
+        missing
+            .iter()
+            .map(|m| {
+                let (context, argument) = self.argument(&m.argument);
+                Error::builder()
+                    .message(format!(
+                        "could not find the value of context argument {argument} for this entity"
+                    ))
+                    .path(path.clone())
+                    .extension_code(CONTEXT_VALUE_NOT_FOUND)
+                    .extension("context", json!(context))
+                    .extension("contextArgument", argument)
+                    .extension("sourceTypes", json!(m.source_types))
+                    .extension("contextPaths", json!(m.context_paths))
+                    .extension("typename", typename.clone())
+                    .extension("policy", json!(self.on_missing_value))
+                    .build()
+            })
+            .collect()
+    }
+
+    /// Records, for one fetch, the number of entities each context argument was resolved and
+    /// not resolved for
+    pub(crate) fn record<'a>(
+        &self,
+        resolved: impl IntoIterator<Item = (&'a String, &'a u64)>,
+        missing: impl IntoIterator<Item = (&'a String, &'a u64)>,
+    ) {
+        if !self.metrics {
+            return;
+        }
+        for (outcome, counts) in [
+            ("resolved", resolved.into_iter().collect::<Vec<_>>()),
+            ("missing", missing.into_iter().collect::<Vec<_>>()),
+        ] {
+            for (variable, count) in counts {
+                let (context, argument) = self.argument(variable);
+                u64_counter!(
+                    "apollo.router.operations.context_arguments",
+                    "Number of entities the value of a context argument was resolved or not resolved for",
+                    *count,
+                    "context.name" = context.unwrap_or_default().to_string(),
+                    "context.argument" = argument,
+                    "context.outcome" = outcome
+                );
+            }
+        }
+    }
+}
+
+struct ContextArguments {
+    config: ContextArgumentsConfig,
+    definitions: Arc<ContextArgumentDefinitions>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ContextArguments {
+    type Config = ContextArgumentsConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let definitions = if init.config.enabled {
+            collect_context_arguments(&init.supergraph_schema)
+        } else {
+            ContextArgumentDefinitions::new()
+        };
+        Ok(Self {
+            config: init.config,
+            definitions: Arc::new(definitions),
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let resolution = ContextArgumentResolution {
+            on_missing_value: self.config.on_missing_value,
+            metrics: self.config.metrics,
+            definitions: self.definitions.clone(),
+            arguments: HashMap::new(),
+        };
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                request
+                    .context
+                    .extensions()
+                    .with_lock(|mut lock| lock.insert(resolution.clone()));
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUBGRAPH1_SCHEMA: &str = r#"
+        scalar _Any
+        union _Entity = T | U
+        type Query {
+            t: T!
+            _entities(representations: [_Any!]!): [_Entity]!
+        }
+        type T {
+            id: ID!
+            u: U!
+            prop: String!
+        }
+        type U {
+            id: ID!
+            field(a: String): Int!
+        }
+    "#;
+
+    fn resolution() -> ContextArgumentResolution {
+        let supergraph = Schema::parse_and_validate(
+            include_str!("../testdata/supergraph_with_context.graphql"),
+            "supergraph.graphql",
+        )
+        .unwrap();
+        let subgraph = Schema::parse_and_validate(SUBGRAPH1_SCHEMA, "schema.graphql").unwrap();
+        let operation = ExecutableDocument::parse_and_validate(
+            &subgraph,
+            "query($representations: [_Any!]!, $contextualArgument_1_0: String) { \
+                _entities(representations: $representations) { \
+                    ... on U { id field(a: $contextualArgument_1_0) } \
+                } \
+            }",
+            "operation.graphql",
+        )
+        .unwrap();
+        ContextArgumentResolution {
+            on_missing_value: MissingValuePolicy::NullField,
+            metrics: false,
+            definitions: Arc::new(collect_context_arguments(&supergraph)),
+            arguments: HashMap::new(),
+        }
+        .for_fetch("Subgraph1", &operation)
+    }
+
+    #[test]
+    fn variables_are_named_after_the_context_and_argument() {
+        let resolution = resolution();
+        assert_eq!(
+            resolution.argument("contextualArgument_1_0"),
+            (Some("context"), "U.field(a:)".to_string())
+        );
+        assert_eq!(
+            resolution.argument("representations"),
+            (None, "representations".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_identify_the_argument_and_source_types() {
+        let resolution = resolution();
+        let missing = vec![MissingContextValue {
+            argument: "contextualArgument_1_0".to_string(),
+            source_types: vec!["T".to_string()],
+            context_paths: vec!["/../... on T/prop".to_string()],
+        }];
+        let errors = resolution.errors(
+            &Path::from("t/u"),
+            &json!({ "__typename": "U", "id": "1" }),
+            &missing,
+        );
+
+        assert_eq!(errors.len(), 1);
+        let error = &errors[0];
+        assert_eq!(error.path, Some(Path::from("t/u")));
+        assert_eq!(
+            serde_json_bytes::Value::Object(error.extensions.clone()),
+            json!({
+                "code": "CONTEXT_VALUE_NOT_FOUND",
+                "context": "context",
+                "contextArgument": "U.field(a:)",
+                "sourceTypes": ["T"],
+                "contextPaths": ["/../... on T/prop"],
+                "typename": "U",
+                "policy": "null_field"
+            })
+        );
+    }
+}
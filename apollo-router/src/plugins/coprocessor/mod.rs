@@ -30,6 +30,7 @@ use tower::ServiceBuilder;
 use tower::ServiceExt;
 
 use crate::configuration::shared::Client;
+use crate::configuration::spiffe;
 use crate::error::Error;
 use crate::graphql;
 use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
@@ -60,6 +61,7 @@ use crate::services::trust_dns_connector::AsyncHyperResolver;
 mod test;
 
 mod execution;
+pub(crate) mod stub;
 mod supergraph;
 
 pub(crate) const EXTERNAL_SPAN_NAME: &str = "external_plugin";
@@ -83,10 +85,19 @@ impl Plugin for CoprocessorPlugin<HTTPClientService> {
         http_connector.set_keepalive(Some(std::time::Duration::from_secs(60)));
         http_connector.enforce_http(false);
 
-        let tls_config = rustls::ClientConfig::builder()
-            .with_safe_defaults()
-            .with_native_roots()
-            .with_no_client_auth();
+        let tls_config = if init
+            .config
+            .client
+            .as_ref()
+            .is_some_and(|client| client.spiffe)
+        {
+            spiffe::client_config()?
+        } else {
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_native_roots()
+                .with_no_client_auth()
+        };
 
         let builder = hyper_rustls::HttpsConnectorBuilder::new()
             .with_tls_config(tls_config)
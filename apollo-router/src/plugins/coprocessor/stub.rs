@@ -0,0 +1,227 @@
+//! A local echo coprocessor, run with `router coprocessor-stub`.
+//!
+//! The stub answers every coprocessor request with the payload it received, so the router
+//! continues as if no coprocessor was configured, and records each payload as a JSON fixture
+//! named after its stage. Coprocessor authors can develop against those fixtures, and compare the
+//! payloads of a new router version against the recorded ones to detect format changes.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::Json;
+use axum::Router;
+use serde_json::Value;
+use tower::BoxError;
+
+/// Value replacing the request identifier in fixtures, as it changes with every request
+const REDACTED_ID: &str = "<id>";
+
+struct Stub {
+    output: PathBuf,
+    /// Compare the payloads with the recorded fixtures instead of recording them
+    check: bool,
+}
+
+/// Name of the fixture file of a payload: its stage, and the subgraph name for subgraph stages.
+fn fixture_name(payload: &Value) -> String {
+    let stage = payload
+        .get("stage")
+        .and_then(Value::as_str)
+        .unwrap_or("Unknown");
+    match payload.get("serviceName").and_then(Value::as_str) {
+        Some(service_name) => format!("{stage}-{service_name}.json"),
+        None => format!("{stage}.json"),
+    }
+}
+
+/// Removes the parts of a payload that change with every request.
+fn normalize(mut payload: Value) -> Value {
+    if let Some(id) = payload.get_mut("id") {
+        *id = REDACTED_ID.into();
+    }
+    payload
+}
+
+/// The coprocessor response leaving the request or response unchanged.
+fn echo(mut payload: Value) -> Value {
+    if let Some(object) = payload.as_object_mut() {
+        object.entry("control").or_insert_with(|| "continue".into());
+    }
+    payload
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Adds the JSON type of each field of a payload, by dotted path. Array elements share the
+/// `[]` path.
+fn shape(path: &str, value: &Value, fields: &mut BTreeMap<String, &'static str>) {
+    fields.insert(path.to_string(), type_name(value));
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                shape(&path, value, fields);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                shape(&format!("{path}[]"), value, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the fields added, removed or with a different type in the new payload.
+fn drift(recorded: &Value, received: &Value) -> Vec<String> {
+    let mut old = BTreeMap::new();
+    shape("", recorded, &mut old);
+    let mut new = BTreeMap::new();
+    shape("", received, &mut new);
+
+    let mut drift = BTreeMap::new();
+    for (path, new_type) in &new {
+        match old.remove(path) {
+            None => {
+                drift.insert(path.clone(), format!("+ {path}: {new_type}"));
+            }
+            Some(old_type) if old_type != *new_type => {
+                drift.insert(path.clone(), format!("~ {path}: {old_type} -> {new_type}"));
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, old_type) in old {
+        drift.insert(path.clone(), format!("- {path}: {old_type}"));
+    }
+    drift.into_values().collect()
+}
+
+/// Records a payload as a fixture, or compares it with the recorded fixture in check mode.
+fn record(output: &Path, check: bool, payload: &Value) -> Result<(), BoxError> {
+    let name = fixture_name(payload);
+    let path = output.join(&name);
+    let payload = normalize(payload.clone());
+
+    if check {
+        let recorded: Value = match std::fs::read(&path) {
+            Ok(recorded) => serde_json::from_slice(&recorded)?,
+            Err(_) => {
+                println!("{name}: no recorded fixture");
+                return Ok(());
+            }
+        };
+        let changes = drift(&recorded, &payload);
+        if changes.is_empty() {
+            println!("{name}: ok");
+        } else {
+            println!("{name}: payload format changed");
+            for line in changes {
+                println!("  {line}");
+            }
+        }
+    } else {
+        std::fs::create_dir_all(output)?;
+        std::fs::write(&path, serde_json::to_vec_pretty(&payload)?)?;
+        println!("{name}: recorded");
+    }
+    Ok(())
+}
+
+async fn handle(State(stub): State<Arc<Stub>>, Json(payload): Json<Value>) -> Json<Value> {
+    if let Err(e) = record(&stub.output, stub.check, &payload) {
+        eprintln!(
+            "{}: could not record the payload: {e}",
+            fixture_name(&payload)
+        );
+    }
+    Json(echo(payload))
+}
+
+/// Serves the echo coprocessor until the process is stopped.
+pub(crate) async fn run(listen: SocketAddr, output: PathBuf, check: bool) -> Result<(), BoxError> {
+    let app = Router::new()
+        .fallback(handle)
+        .with_state(Arc::new(Stub { output, check }));
+
+    println!("coprocessor stub listening on http://{listen}");
+    axum::Server::bind(&listen)
+        .serve(app.into_make_service())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn echoes_and_records_payloads() {
+        let payload = json!({
+            "version": 1,
+            "stage": "SubgraphRequest",
+            "id": "1b19c05fdafc521016df33148ad63c1b",
+            "serviceName": "accounts",
+            "headers": { "content-type": ["application/json"] },
+            "body": { "query": "{ me { name } }" }
+        });
+        let dir = tempfile::tempdir().unwrap();
+        record(dir.path(), false, &payload).unwrap();
+
+        let recorded: Value = serde_json::from_slice(
+            &std::fs::read(dir.path().join("SubgraphRequest-accounts.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(recorded["id"], REDACTED_ID);
+        assert_eq!(recorded["body"], payload["body"]);
+        assert_eq!(echo(payload.clone())["control"], "continue");
+        assert_eq!(echo(payload.clone())["body"], payload["body"]);
+    }
+
+    #[test]
+    fn detects_format_drift() {
+        let recorded = json!({
+            "version": 1,
+            "stage": "RouterRequest",
+            "headers": { "accept": ["*/*"] },
+            "body": "{}",
+            "sdl": "type Query { me: String }"
+        });
+        let received = json!({
+            "version": 2,
+            "stage": "RouterRequest",
+            "headers": { "accept": ["*/*"] },
+            "body": { "query": "{ me }" },
+            "method": "POST"
+        });
+        assert_eq!(
+            drift(&recorded, &received),
+            vec![
+                "~ body: string -> object",
+                "+ body.query: string",
+                "+ method: string",
+                "- sdl: string",
+            ]
+        );
+        assert!(drift(&recorded, &recorded).is_empty());
+    }
+}
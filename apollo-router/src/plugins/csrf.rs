@@ -3,6 +3,7 @@ use std::ops::ControlFlow;
 
 use http::header;
 use http::HeaderMap;
+use http::Method;
 use http::StatusCode;
 use schemars::JsonSchema;
 use serde::Deserialize;
@@ -145,10 +146,18 @@ impl Plugin for Csrf {
 // Given the first step is covered in our web browser, we'll take care of the two other steps below:
 fn is_preflighted(req: &supergraph::Request, required_headers: &[String]) -> bool {
     let headers = req.supergraph_request.headers();
-    content_type_requires_preflight(headers)
+    method_requires_preflight(req.supergraph_request.method())
+        || content_type_requires_preflight(headers)
         || recommended_header_is_provided(headers, required_headers)
 }
 
+// Part one of the algorithm above:
+// The GraphQL endpoint only accepts simple methods, but the REST endpoints of persisted queries
+// keep the method of their route, like `PUT` or `DELETE`, which browsers always preflight.
+fn method_requires_preflight(method: &Method) -> bool {
+    ![Method::GET, Method::HEAD, Method::POST].contains(method)
+}
+
 // Part two of the algorithm above:
 // If content-type is set, it must be with a mime type that is application/x-www-form-urlencoded OR multipart/form-data OR text/plain
 // The details of the algorithm are covered in the fetch specification https://fetch.spec.whatwg.org/#cors-safelisted-request-header
@@ -264,6 +273,20 @@ mod csrf_tests {
         assert_rejected(config, non_preflighted_request).await
     }
 
+    #[tokio::test]
+    async fn it_lets_preflighted_methods_pass_through() {
+        let config = CSRFConfig::default();
+        let mut with_preflight_method = supergraph::Request::fake_builder()
+            .method(Method::DELETE)
+            .build()
+            .unwrap();
+        with_preflight_method
+            .supergraph_request
+            .headers_mut()
+            .remove("content-type");
+        assert_accepted(config, with_preflight_method).await;
+    }
+
     #[tokio::test]
     async fn it_rejects_non_preflighted_content_type_request() {
         let config = CSRFConfig::default();
@@ -0,0 +1,456 @@
+//! Data residency: requests are pinned to the subgraph endpoints of a region.
+//!
+//! The region of a request comes from a JWT claim or a request header. Each region declares the
+//! URLs of all the subgraphs, and optionally the Redis used by the entity and response caches, so
+//! that the data of a request never leaves its region. The requests of a region without its own
+//! Redis bypass these caches, which would otherwise share their data with the other regions.
+
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use apollo_compiler::validation::Valid;
+use apollo_compiler::Schema;
+use http::StatusCode;
+use http::Uri;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::cache::redis::RedisCacheStorage;
+use crate::configuration::RedisCache;
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!("apollo", "data_residency", DataResidency);
+
+/// Context key of the region of a request
+pub(crate) const REGION_CONTEXT_KEY: &str = "apollo_data_residency::region";
+
+/// Pinning of requests to the subgraph endpoints of a region
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct DataResidencyConfig {
+    /// Route the requests to the subgraph endpoints of their region
+    enabled: bool,
+    /// Where the region of a request comes from
+    region: RegionSource,
+    /// Region of the requests without one. Requests without a region are rejected if it isn't set
+    default_region: Option<String>,
+    /// Endpoints of each region, by region name
+    regions: HashMap<String, RegionConfig>,
+}
+
+/// Where the region of a request comes from. The JWT claim takes precedence over the header
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct RegionSource {
+    /// Claim of the JWT authenticating the request
+    claim: Option<String>,
+    /// Request header
+    header: Option<String>,
+}
+
+/// Endpoints of a region
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct RegionConfig {
+    /// URLs of all the subgraphs in this region, by subgraph name
+    subgraphs: HashMap<String, String>,
+    /// Redis used by the entity and response caches for the requests of this region, instead of
+    /// their own. Without it, the requests of this region bypass these caches
+    #[serde(default)]
+    redis: Option<RedisCache>,
+}
+
+/// The Redis the caches use for a request, set for the requests pinned to a region. Caches are
+/// bypassed if the region has no Redis, or if the connection to it could not be opened.
+#[derive(Clone)]
+pub(crate) struct RegionalCache(pub(crate) Option<RedisCacheStorage>);
+
+struct Region {
+    subgraphs: HashMap<String, Uri>,
+    cache: RegionalCache,
+}
+
+struct DataResidency {
+    enabled: bool,
+    source: Arc<RegionSource>,
+    default_region: Option<String>,
+    regions: Arc<HashMap<String, Region>>,
+}
+
+/// Names of the subgraphs of the supergraph schema
+fn subgraph_names(schema: &Schema) -> Vec<String> {
+    schema
+        .get_enum("join__Graph")
+        .map(|join_enum| {
+            join_enum
+                .values
+                .values()
+                .filter_map(|value| {
+                    let join_directive = value.directives.get("join__graph")?;
+                    Some(
+                        join_directive
+                            .argument_by_name("name")?
+                            .as_str()?
+                            .to_string(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks that every subgraph has a URL in every region, and that the regions don't declare
+/// unknown subgraphs
+fn validate(config: &DataResidencyConfig, schema: &Valid<Schema>) -> Result<(), BoxError> {
+    if config.regions.is_empty() {
+        return Err("data residency requires at least one region".into());
+    }
+    if config.region.claim.is_none() && config.region.header.is_none() {
+        return Err("data residency requires a claim or a header for the region".into());
+    }
+    if let Some(default_region) = &config.default_region {
+        if !config.regions.contains_key(default_region) {
+            return Err(format!("the default region '{default_region}' is not declared").into());
+        }
+    }
+
+    let subgraphs = subgraph_names(schema);
+    for (region_name, region) in &config.regions {
+        for subgraph_name in &subgraphs {
+            if !region.subgraphs.contains_key(subgraph_name) {
+                return Err(format!(
+                    "the subgraph '{subgraph_name}' has no URL in the region '{region_name}'"
+                )
+                .into());
+            }
+        }
+        for subgraph_name in region.subgraphs.keys() {
+            if !subgraphs.contains(subgraph_name) {
+                return Err(format!(
+                    "the region '{region_name}' declares an unknown subgraph '{subgraph_name}'"
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Region {
+    async fn new(name: &str, config: &RegionConfig) -> Result<Self, BoxError> {
+        let subgraphs = config
+            .subgraphs
+            .iter()
+            .map(|(subgraph_name, url)| Ok((subgraph_name.clone(), Uri::from_str(url)?)))
+            .collect::<Result<_, BoxError>>()?;
+
+        let cache = match &config.redis {
+            None => RegionalCache(None),
+            Some(redis) => {
+                let mut redis_config = redis.clone();
+                let required_to_start = redis_config.required_to_start;
+                // the caches manage the TTL of their entries directly
+                redis_config.reset_ttl = false;
+                match RedisCacheStorage::new(redis_config, "regional").await {
+                    Ok(storage) => RegionalCache(Some(storage)),
+                    Err(e) => {
+                        tracing::error!(
+                            region = name,
+                            e,
+                            "could not open connection to the Redis of the region",
+                        );
+                        if required_to_start {
+                            return Err(e);
+                        }
+                        RegionalCache(None)
+                    }
+                }
+            }
+        };
+
+        Ok(Self { subgraphs, cache })
+    }
+}
+
+/// Returns the region of a request, from the JWT claim or the header
+fn request_region(source: &RegionSource, request: &supergraph::Request) -> Option<String> {
+    let from_claim = source.claim.as_ref().and_then(|claim| {
+        request
+            .context
+            .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS)?
+            .as_object()?
+            .get(claim.as_str())?
+            .as_str()
+            .map(str::to_string)
+    });
+    from_claim.or_else(|| {
+        let header = source.header.as_ref()?;
+        request
+            .supergraph_request
+            .headers()
+            .get(header.as_str())?
+            .to_str()
+            .ok()
+            .map(str::to_string)
+    })
+}
+
+fn reject(context: Context, message: String, code: &str) -> Result<supergraph::Response, BoxError> {
+    supergraph::Response::error_builder()
+        .error(
+            graphql::Error::builder()
+                .message(message)
+                .extension_code(code)
+                .build(),
+        )
+        .status_code(StatusCode::BAD_REQUEST)
+        .context(context)
+        .build()
+}
+
+#[async_trait::async_trait]
+impl Plugin for DataResidency {
+    type Config = DataResidencyConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let config = init.config;
+        let mut regions = HashMap::new();
+        if config.enabled {
+            validate(&config, &init.supergraph_schema)?;
+            for (name, region) in &config.regions {
+                regions.insert(name.clone(), Region::new(name, region).await?);
+            }
+        }
+
+        Ok(Self {
+            enabled: config.enabled,
+            source: Arc::new(config.region),
+            default_region: config.default_region,
+            regions: Arc::new(regions),
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.enabled {
+            return service;
+        }
+
+        let source = self.source.clone();
+        let default_region = self.default_region.clone();
+        let regions = self.regions.clone();
+        ServiceBuilder::new()
+            .checkpoint(move |request: supergraph::Request| {
+                let Some(region_name) =
+                    request_region(&source, &request).or_else(|| default_region.clone())
+                else {
+                    return Ok(ControlFlow::Break(reject(
+                        request.context,
+                        "the region of the request is missing".to_string(),
+                        "REGION_MISSING",
+                    )?));
+                };
+                let Some(region) = regions.get(&region_name) else {
+                    return Ok(ControlFlow::Break(reject(
+                        request.context,
+                        format!("unknown region '{region_name}'"),
+                        "UNKNOWN_REGION",
+                    )?));
+                };
+
+                let cache = region.cache.clone();
+                request
+                    .context
+                    .extensions()
+                    .with_lock(|mut lock| lock.insert(cache));
+                request.context.insert(REGION_CONTEXT_KEY, region_name)?;
+                Ok(ControlFlow::Continue(request))
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.enabled {
+            return service;
+        }
+
+        let urls = self
+            .regions
+            .iter()
+            .filter_map(|(region_name, region)| {
+                Some((region_name.clone(), region.subgraphs.get(name)?.clone()))
+            })
+            .collect::<HashMap<_, _>>();
+        service
+            .map_request(move |mut request: subgraph::Request| {
+                let url = request
+                    .context
+                    .get::<_, String>(REGION_CONTEXT_KEY)
+                    .ok()
+                    .flatten()
+                    .and_then(|region_name| urls.get(&region_name));
+                if let Some(url) = url {
+                    *request.subgraph_request.uri_mut() = url.clone();
+                }
+                request
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+    use tower::Service;
+
+    use super::*;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::test::MockSupergraphService;
+
+    fn schema() -> Arc<Valid<Schema>> {
+        Arc::new(
+            Schema::parse_and_validate(
+                include_str!("../testdata/minimal_supergraph.graphql"),
+                "supergraph.graphql",
+            )
+            .unwrap(),
+        )
+    }
+
+    async fn plugin(config: serde_json::Value) -> Result<DataResidency, BoxError> {
+        DataResidency::new(
+            PluginInit::fake_builder()
+                .config(serde_json::from_value(config)?)
+                .supergraph_schema(schema())
+                .build(),
+        )
+        .await
+    }
+
+    fn config() -> serde_json::Value {
+        serde_json::json!({
+            "enabled": true,
+            "region": { "claim": "region", "header": "x-region" },
+            "regions": {
+                "eu": { "subgraphs": { "accounts": "http://accounts.eu:4001/graphql" } },
+                "us": { "subgraphs": { "accounts": "http://accounts.us:4001/graphql" } }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn every_subgraph_needs_a_url_in_every_region() {
+        assert!(plugin(config()).await.is_ok());
+
+        let mut missing_url = config();
+        missing_url["regions"]["us"]["subgraphs"] = serde_json::json!({});
+        assert!(plugin(missing_url).await.is_err());
+
+        let mut unknown_subgraph = config();
+        unknown_subgraph["regions"]["eu"]["subgraphs"]["products"] =
+            serde_json::json!("http://products.eu:4001/graphql");
+        assert!(plugin(unknown_subgraph).await.is_err());
+
+        let mut unknown_default = config();
+        unknown_default["default_region"] = serde_json::json!("ap");
+        assert!(plugin(unknown_default).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn region_comes_from_the_claim_then_the_header() {
+        let plugin = plugin(config()).await.unwrap();
+        let mut mock = MockSupergraphService::new();
+        mock.expect_call().times(2).returning(|request| {
+            // the regions without their own Redis bypass the caches
+            assert!(request.context.extensions().with_lock(|lock| matches!(
+                lock.get::<RegionalCache>(),
+                Some(RegionalCache(None))
+            )));
+            let region = request
+                .context
+                .get::<_, String>(REGION_CONTEXT_KEY)
+                .unwrap()
+                .unwrap();
+            supergraph::Response::fake_builder()
+                .data(json!({ "region": region }))
+                .context(request.context)
+                .build()
+        });
+        let mut service = plugin.supergraph_service(mock.boxed());
+
+        let context = Context::new();
+        context
+            .insert(APOLLO_AUTHENTICATION_JWT_CLAIMS, json!({ "region": "us" }))
+            .unwrap();
+        let request = supergraph::Request::fake_builder()
+            .header("x-region", "eu")
+            .context(context)
+            .build()
+            .unwrap();
+        let mut response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(
+            response.next_response().await.unwrap().data,
+            Some(json!({ "region": "us" }))
+        );
+
+        let request = supergraph::Request::fake_builder()
+            .header("x-region", "eu")
+            .build()
+            .unwrap();
+        let mut response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(
+            response.next_response().await.unwrap().data,
+            Some(json!({ "region": "eu" }))
+        );
+
+        // without a region or a default one, requests are rejected
+        let request = supergraph::Request::fake_builder().build().unwrap();
+        let mut response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.next_response().await.unwrap().errors[0]
+                .extensions
+                .get("code"),
+            Some(&json!("REGION_MISSING"))
+        );
+    }
+
+    #[tokio::test]
+    async fn subgraph_requests_go_to_the_region_url() {
+        let plugin = plugin(config()).await.unwrap();
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call()
+            .withf(|request| {
+                request.subgraph_request.uri()
+                    == &Uri::from_static("http://accounts.eu:4001/graphql")
+            })
+            .times(1)
+            .returning(|request| {
+                Ok(subgraph::Response::fake_builder()
+                    .context(request.context)
+                    .build())
+            });
+        let mut service = plugin.subgraph_service("accounts", mock.boxed());
+
+        let context = Context::new();
+        context
+            .insert(REGION_CONTEXT_KEY, "eu".to_string())
+            .unwrap();
+        let request = subgraph::Request::fake_builder().context(context).build();
+        service.ready().await.unwrap().call(request).await.unwrap();
+    }
+}
@@ -321,7 +321,7 @@ impl StaticCostCalculator {
         }
     }
 
-    fn estimated_cost_of_operation(
+    pub(crate) fn estimated_cost_of_operation(
         &self,
         subgraph: &str,
         operation: &SubgraphOperation,
@@ -12,6 +12,8 @@ use displaydoc::Display;
 use futures::future::Either;
 use futures::stream;
 use futures::StreamExt;
+use http::HeaderName;
+use http::HeaderValue;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
@@ -20,6 +22,7 @@ use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use crate::configuration::shared::Expose;
 use crate::error::Error;
 use crate::graphql;
 use crate::graphql::IntoGraphQLErrors;
@@ -38,6 +41,9 @@ use crate::Context;
 pub(crate) mod cost_calculator;
 pub(crate) mod strategy;
 
+static X_GRAPHQL_COST: HeaderName = HeaderName::from_static("x-graphql-cost");
+static X_GRAPHQL_COST_REMAINING: HeaderName = HeaderName::from_static("x-graphql-cost-remaining");
+
 /// The cost calculation information stored in context for use in telemetry and other plugins that need to know what cost was calculated.
 #[derive(Debug, Clone)]
 pub(crate) struct CostContext {
@@ -100,6 +106,17 @@ pub(crate) enum StrategyConfig {
     },
 }
 
+impl StrategyConfig {
+    /// The maximum cost of an operation, if the strategy has one
+    fn max(&self) -> Option<f64> {
+        match self {
+            StrategyConfig::StaticEstimated { max, .. } => Some(*max),
+            #[cfg(test)]
+            StrategyConfig::Test { .. } => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, JsonSchema, Eq, PartialEq)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub(crate) enum Mode {
@@ -119,6 +136,12 @@ pub(crate) struct DemandControlConfig {
     mode: Mode,
     /// The strategy used to reject requests.
     strategy: StrategyConfig,
+    /// Return the cost of the operations to the clients, so they can budget their operations:
+    /// the estimated cost and the remaining budget (the maximum cost minus the estimated cost) in
+    /// the `x-graphql-cost` and `x-graphql-cost-remaining` headers, and the estimated and actual
+    /// costs, the maximum cost and the remaining budget in the `cost` entry of the extensions
+    #[serde(default)]
+    expose: Expose,
 }
 
 #[derive(Debug, Display, Error)]
@@ -205,6 +228,48 @@ pub(crate) struct DemandControl {
 }
 
 impl DemandControl {
+    /// Returns the estimated cost and the remaining budget in the response headers
+    fn expose_cost_headers(response: &mut execution::Response, max: Option<f64>) {
+        let Some(estimated) = response
+            .context
+            .extensions()
+            .with_lock(|lock| lock.get::<CostContext>().map(|cost| cost.estimated))
+        else {
+            return;
+        };
+        let headers = response.response.headers_mut();
+        if let Ok(value) = HeaderValue::try_from(estimated.to_string()) {
+            headers.insert(X_GRAPHQL_COST.clone(), value);
+        }
+        if let Some(max) = max {
+            if let Ok(value) = HeaderValue::try_from((max - estimated).max(0.0).to_string()) {
+                headers.insert(X_GRAPHQL_COST_REMAINING.clone(), value);
+            }
+        }
+    }
+
+    /// Returns the costs calculated so far and the remaining budget in the response extensions
+    fn expose_cost_extensions(
+        context: &Context,
+        max: Option<f64>,
+        response: &mut graphql::Response,
+    ) {
+        let Some(cost) = context
+            .extensions()
+            .with_lock(|lock| lock.get::<CostContext>().cloned())
+        else {
+            return;
+        };
+        let mut extension = Object::new();
+        extension.insert("estimated", cost.estimated.into());
+        extension.insert("actual", cost.actual.into());
+        if let Some(max) = max {
+            extension.insert("max", max.into());
+            extension.insert("remaining", (max - cost.estimated).max(0.0).into());
+        }
+        response.extensions.insert("cost", extension.into());
+    }
+
     fn report_operation_metric(context: Context) {
         let result = context
             .extensions()
@@ -238,6 +303,8 @@ impl Plugin for DemandControl {
             service
         } else {
             let strategy = self.strategy_factory.create();
+            let expose = self.config.expose.clone();
+            let max = self.config.strategy.max();
             ServiceBuilder::new()
                 .checkpoint(move |req: execution::Request| {
                     req.context
@@ -258,7 +325,11 @@ impl Plugin for DemandControl {
                         ),
                     })
                 })
-                .map_response(|mut resp: execution::Response| {
+                .map_response(move |mut resp: execution::Response| {
+                    if expose.headers {
+                        Self::expose_cost_headers(&mut resp, max);
+                    }
+                    let expose_extensions = expose.extensions;
                     let req = resp
                         .context
                         .unsupported_executable_document()
@@ -280,9 +351,14 @@ impl Plugin for DemandControl {
                         // Here we are going to abort the stream if the cost is too high
                         // First we map based on cost, then we use take while to abort the stream if an error is emitted.
                         // When we terminate the stream we still want to emit a graphql error, so the error response is emitted first before a termination error.
-                        resp.flat_map(move |resp| {
+                        resp.flat_map(move |mut resp| {
                             match strategy.on_execution_response(&context, req.as_ref(), &resp) {
-                                Ok(_) => Either::Left(stream::once(future::ready(Ok(resp)))),
+                                Ok(_) => {
+                                    if expose_extensions {
+                                        Self::expose_cost_extensions(&context, max, &mut resp);
+                                    }
+                                    Either::Left(stream::once(future::ready(Ok(resp))))
+                                }
                                 Err(err) => {
                                     Either::Right(stream::iter(vec![
                                         // This is the error we are returning to the user
@@ -521,6 +597,46 @@ mod test {
         .await
     }
 
+    #[tokio::test]
+    async fn test_expose_cost() {
+        let plugin = PluginTestHarness::<DemandControl>::builder()
+            .config(include_str!("fixtures/expose_cost.router.yaml"))
+            .build()
+            .await;
+
+        let resp = plugin
+            .call_execution(
+                execution::Request::fake_builder()
+                    .context(context())
+                    .build(),
+                |req| {
+                    execution::Response::fake_builder()
+                        .context(req.context)
+                        .build()
+                        .unwrap()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.response.headers()["x-graphql-cost"], "0");
+        assert_eq!(resp.response.headers()["x-graphql-cost-remaining"], "100");
+
+        let responses = resp
+            .response
+            .into_body()
+            .collect::<Vec<graphql::Response>>()
+            .await;
+        assert_eq!(
+            responses[0].extensions.get("cost"),
+            Some(&serde_json_bytes::json!({
+                "estimated": 0.0,
+                "actual": 0.0,
+                "max": 100.0,
+                "remaining": 100.0
+            }))
+        );
+    }
+
     async fn test_on_execution(config: &'static str) -> Vec<Response> {
         let plugin = PluginTestHarness::<DemandControl>::builder()
             .config(config)
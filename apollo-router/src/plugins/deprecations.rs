@@ -0,0 +1,265 @@
+//! Warnings about the deprecated fields and arguments used by operations.
+//!
+//! Operations are checked against the `@deprecated` directives of the schema. The deprecated
+//! elements they use are counted per client, and can be listed in the `deprecations` extension of
+//! the response, so that clients see what they have to migrate away from.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use apollo_compiler::ast;
+use apollo_compiler::executable;
+use apollo_compiler::validation::Valid;
+use apollo_compiler::Schema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::supergraph;
+use crate::spec::query::traverse;
+
+register_plugin!("apollo", "deprecations", Deprecations);
+
+pub(crate) const DEPRECATIONS_KEY: &str = "apollo_deprecations::deprecations";
+
+const DEPRECATED_DIRECTIVE_NAME: &str = "deprecated";
+const DEFAULT_DEPRECATION_REASON: &str = "No longer supported";
+
+/// Warnings about the deprecated fields and arguments used by operations
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct DeprecationsConfig {
+    /// Look for the deprecated fields and arguments used by operations, and count them per client
+    /// in the `apollo.router.operations.deprecated` metric
+    enabled: bool,
+    /// List the deprecated fields and arguments used by the operation in the `deprecations`
+    /// extension of the response
+    response_extension: bool,
+}
+
+/// A deprecated schema element used by an operation.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub(crate) struct Deprecation {
+    /// Schema coordinate of the field or argument, like `Product.price` or `Query.products(first:)`
+    pub(crate) coordinate: String,
+    /// Reason from the `@deprecated` directive
+    pub(crate) reason: String,
+}
+
+struct DeprecationVisitor<'a> {
+    schema: &'a Schema,
+    deprecations: BTreeMap<String, String>,
+}
+
+impl<'a> DeprecationVisitor<'a> {
+    fn new(schema: &'a Schema) -> Self {
+        Self {
+            schema,
+            deprecations: BTreeMap::new(),
+        }
+    }
+
+    fn into_deprecations(self) -> Vec<Deprecation> {
+        self.deprecations
+            .into_iter()
+            .map(|(coordinate, reason)| Deprecation { coordinate, reason })
+            .collect()
+    }
+}
+
+fn deprecation_reason(directives: &ast::DirectiveList) -> Option<String> {
+    let directive = directives.get(DEPRECATED_DIRECTIVE_NAME)?;
+    Some(
+        directive
+            .argument_by_name("reason")
+            .and_then(|reason| reason.as_str())
+            .unwrap_or(DEFAULT_DEPRECATION_REASON)
+            .to_string(),
+    )
+}
+
+impl<'a> traverse::Visitor for DeprecationVisitor<'a> {
+    fn schema(&self) -> &apollo_compiler::Schema {
+        self.schema
+    }
+
+    fn field(
+        &mut self,
+        parent_type: &str,
+        field_def: &ast::FieldDefinition,
+        node: &executable::Field,
+    ) -> Result<(), BoxError> {
+        if let Some(reason) = deprecation_reason(&field_def.directives) {
+            self.deprecations
+                .insert(format!("{parent_type}.{}", field_def.name), reason);
+        }
+        for argument in &node.arguments {
+            let Some(argument_def) = field_def
+                .arguments
+                .iter()
+                .find(|argument_def| argument_def.name == argument.name)
+            else {
+                continue;
+            };
+            if let Some(reason) = deprecation_reason(&argument_def.directives) {
+                self.deprecations.insert(
+                    format!("{parent_type}.{}({}:)", field_def.name, argument_def.name),
+                    reason,
+                );
+            }
+        }
+        traverse::field(self, field_def, node)
+    }
+}
+
+/// Returns the deprecated fields and arguments used by an operation, sorted by coordinate.
+fn deprecations(
+    schema: &Schema,
+    document: &executable::ExecutableDocument,
+    operation_name: Option<&str>,
+) -> Result<Vec<Deprecation>, BoxError> {
+    let mut visitor = DeprecationVisitor::new(schema);
+    traverse::document(&mut visitor, document, operation_name)?;
+    Ok(visitor.into_deprecations())
+}
+
+struct Deprecations {
+    config: DeprecationsConfig,
+    schema: Arc<Valid<Schema>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for Deprecations {
+    type Config = DeprecationsConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            config: init.config,
+            schema: init.supergraph_schema.clone(),
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let schema = self.schema.clone();
+        let response_extension = self.config.response_extension;
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                let parsed_doc = request
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<ParsedDocument>().cloned());
+                let Some(parsed_doc) = parsed_doc else {
+                    return request;
+                };
+                let operation_name = request.supergraph_request.body().operation_name.as_deref();
+                let deprecations =
+                    match deprecations(&schema, &parsed_doc.executable, operation_name) {
+                        Ok(deprecations) => deprecations,
+                        Err(error) => {
+                            tracing::debug!("could not look for deprecated fields: {error}");
+                            return request;
+                        }
+                    };
+                if deprecations.is_empty() {
+                    return request;
+                }
+
+                let client_name = request
+                    .context
+                    .get::<_, String>(CLIENT_NAME)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default();
+                for deprecation in &deprecations {
+                    u64_counter!(
+                        "apollo.router.operations.deprecated",
+                        "Number of operations using a deprecated field or argument",
+                        1,
+                        "client.name" = client_name.clone(),
+                        "graphql.schema.coordinate" = deprecation.coordinate.clone()
+                    );
+                }
+                let _ = request.context.insert(DEPRECATIONS_KEY, deprecations);
+                request
+            })
+            .map_first_graphql_response(move |context, parts, mut response| {
+                if response_extension {
+                    if let Ok(Some(deprecations)) =
+                        context.get::<_, serde_json_bytes::Value>(DEPRECATIONS_KEY)
+                    {
+                        response.extensions.insert("deprecations", deprecations);
+                    }
+                }
+                (parts, response)
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            products(first: Int, limit: Int @deprecated(reason: "Use `first`")): [Product]
+        }
+
+        type Product {
+            id: ID!
+            price: Float @deprecated(reason: "Use `priceInCents`")
+            priceInCents: Int
+            weight: Float @deprecated
+        }
+    "#;
+
+    fn find(query: &str) -> Vec<Deprecation> {
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let document =
+            executable::ExecutableDocument::parse_and_validate(&schema, query, "query.graphql")
+                .unwrap();
+        deprecations(&schema, &document, None).unwrap()
+    }
+
+    #[test]
+    fn deprecated_fields_and_arguments() {
+        assert_eq!(
+            find(
+                "{ products(limit: 3) { id price ...Weight } } fragment Weight on Product { weight }"
+            ),
+            vec![
+                Deprecation {
+                    coordinate: "Product.price".to_string(),
+                    reason: "Use `priceInCents`".to_string(),
+                },
+                Deprecation {
+                    coordinate: "Product.weight".to_string(),
+                    reason: "No longer supported".to_string(),
+                },
+                Deprecation {
+                    coordinate: "Query.products(limit:)".to_string(),
+                    reason: "Use `first`".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_deprecations() {
+        assert!(find("{ products(first: 3) { id priceInCents } }").is_empty());
+    }
+}
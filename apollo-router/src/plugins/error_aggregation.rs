@@ -0,0 +1,195 @@
+//! Aggregation of identical subgraph errors.
+//!
+//! When an `_entities` fetch fails, the subgraph usually returns the same error for every
+//! representation, and the client receives hundreds of errors that only differ by their path.
+//! This plugin combines the identical errors of a fetch into one error, with the number of errors
+//! it replaces and a few representative paths in its extensions.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::json;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::configuration::subgraph::SubgraphConfiguration;
+use crate::error::Error;
+use crate::json_ext::Path;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "error_aggregation", ErrorAggregation);
+
+const COUNT_EXTENSION: &str = "count";
+const PATHS_EXTENSION: &str = "paths";
+
+/// Aggregation of the identical errors of a subgraph fetch
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Aggregation {
+    /// Combine the errors of a fetch that only differ by their path
+    enabled: bool,
+    /// Maximum number of paths of the combined errors listed in the `paths` extension
+    max_paths: usize,
+}
+
+impl Default for Aggregation {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_paths: 5,
+        }
+    }
+}
+
+/// Set in the context extensions when the aggregation is enabled for some subgraphs
+#[derive(Clone, Debug)]
+pub(crate) struct ErrorAggregator {
+    config: Arc<SubgraphConfiguration<Aggregation>>,
+}
+
+impl ErrorAggregator {
+    /// Combines the errors returned by a fetch to `service_name` that have the same message,
+    /// locations and extensions. The combined error keeps the path of the first error, and gets
+    /// the `count` and `paths` extensions. Errors that are not repeated are left unchanged
+    pub(crate) fn aggregate(&self, service_name: &str, errors: Vec<Error>) -> Vec<Error> {
+        let config = self.config.get(service_name);
+        if !config.enabled || errors.len() < 2 {
+            return errors;
+        }
+
+        let mut aggregated: Vec<(Error, u64, Vec<Path>)> = Vec::with_capacity(errors.len());
+        let mut indexes: HashMap<String, usize> = HashMap::new();
+        for error in errors {
+            let key = serde_json::to_string(&(&error.message, &error.locations, &error.extensions))
+                .unwrap_or_default();
+            match indexes.get(&key) {
+                Some(index) => {
+                    let (_, count, paths) = &mut aggregated[*index];
+                    *count += 1;
+                    if let Some(path) = error.path {
+                        if paths.len() < config.max_paths {
+                            paths.push(path);
+                        }
+                    }
+                }
+                None => {
+                    indexes.insert(key, aggregated.len());
+                    let paths = error.path.iter().take(config.max_paths).cloned().collect();
+                    aggregated.push((error, 1, paths));
+                }
+            }
+        }
+
+        aggregated
+            .into_iter()
+            .map(|(mut error, count, paths)| {
+                if count > 1 {
+                    error.extensions.insert(COUNT_EXTENSION, json!(count));
+                    error.extensions.insert(PATHS_EXTENSION, json!(paths));
+                }
+                error
+            })
+            .collect()
+    }
+}
+
+struct ErrorAggregation {
+    config: Arc<SubgraphConfiguration<Aggregation>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ErrorAggregation {
+    type Config = SubgraphConfiguration<Aggregation>;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            config: Arc::new(init.config),
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.all.enabled && !self.config.subgraphs.values().any(|s| s.enabled) {
+            return service;
+        }
+
+        let aggregator = ErrorAggregator {
+            config: self.config.clone(),
+        };
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                request
+                    .context
+                    .extensions()
+                    .with_lock(|mut lock| lock.insert(aggregator.clone()));
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(message: &str, path: &str) -> Error {
+        Error::builder()
+            .message(message)
+            .path(Path::from(path))
+            .extension_code("FETCH_ERROR")
+            .build()
+    }
+
+    fn aggregator(config: serde_json::Value) -> ErrorAggregator {
+        ErrorAggregator {
+            config: Arc::new(serde_json::from_value(config).unwrap()),
+        }
+    }
+
+    #[test]
+    fn aggregates_identical_errors() {
+        let aggregator = aggregator(serde_json::json!({
+            "all": { "enabled": true, "max_paths": 2 }
+        }));
+        let errors = aggregator.aggregate(
+            "products",
+            vec![
+                error("subgraph failed", "topProducts/0/reviews"),
+                error("not found", "topProducts/1"),
+                error("subgraph failed", "topProducts/1/reviews"),
+                error("subgraph failed", "topProducts/2/reviews"),
+            ],
+        );
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].path, Some(Path::from("topProducts/0/reviews")));
+        assert_eq!(
+            serde_json_bytes::Value::Object(errors[0].extensions.clone()),
+            json!({
+                "code": "FETCH_ERROR",
+                "count": 3,
+                "paths": [["topProducts", 0, "reviews"], ["topProducts", 1, "reviews"]]
+            })
+        );
+        assert_eq!(errors[1], error("not found", "topProducts/1"));
+    }
+
+    #[test]
+    fn per_subgraph_configuration() {
+        let aggregator = aggregator(serde_json::json!({
+            "all": { "enabled": true },
+            "subgraphs": { "reviews": { "enabled": false } }
+        }));
+        let errors = vec![error("failed", "a/0"), error("failed", "a/1")];
+
+        assert_eq!(aggregator.aggregate("products", errors.clone()).len(), 1);
+        assert_eq!(aggregator.aggregate("reviews", errors).len(), 2);
+    }
+}
@@ -0,0 +1,361 @@
+//! Localized messages of the errors returned to clients.
+//!
+//! The messages of the errors generated by the router, like exceeded limits, failed
+//! authentication or invalid operations, are written for developers, in English. Consumer facing
+//! apps that display them need them in the language of their users. Catalogs map the error codes
+//! to messages per locale, and each request gets the messages of the locale best matching its
+//! `Accept-Language` header. The `code` extension of the errors is left unchanged, so that clients
+//! can keep handling the errors by code.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use http::header::ACCEPT_LANGUAGE;
+use http::header::CONTENT_LANGUAGE;
+use http::header::CONTENT_TYPE;
+use http::header::VARY;
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::router;
+use crate::services::router::body::get_body_bytes;
+use crate::services::router::body::RouterBody;
+
+register_plugin!("apollo", "error_messages", ErrorMessages);
+
+/// Placeholder replaced with the original message of the error
+const MESSAGE_PLACEHOLDER: &str = "{message}";
+static ACCEPT_LANGUAGE_HEADER_VALUE: HeaderValue = HeaderValue::from_static("accept-language");
+
+/// Localized messages of the errors, chosen by the `Accept-Language` header of the requests
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct ErrorMessagesConfig {
+    /// Messages by locale, like `fr` or `pt-BR`, then by error code. `{message}` in a message is
+    /// replaced with the original message of the error
+    catalogs: HashMap<String, HashMap<String, String>>,
+    /// Locale of the messages of the requests that accept none of the locales of the catalogs.
+    /// Without it, those requests get the original messages
+    default_locale: Option<String>,
+}
+
+/// The messages of a locale
+#[derive(Debug)]
+struct Catalog {
+    locale: String,
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Replaces the messages of the errors of a serialized GraphQL response, or of a batch of
+    /// responses, whose code is in the catalog. Returns whether a message was replaced
+    fn localize(&self, response: &mut serde_json::Value) -> bool {
+        if let Some(responses) = response.as_array_mut() {
+            return responses.iter_mut().fold(false, |localized, response| {
+                self.localize(response) || localized
+            });
+        }
+        let Some(errors) = response
+            .get_mut("errors")
+            .and_then(|errors| errors.as_array_mut())
+        else {
+            return false;
+        };
+        let mut localized = false;
+        for error in errors {
+            let Some(message) = error
+                .get("extensions")
+                .and_then(|extensions| extensions.get("code"))
+                .and_then(|code| code.as_str())
+                .and_then(|code| self.messages.get(code))
+            else {
+                continue;
+            };
+            if let Some(original) = error.get_mut("message") {
+                let replaced =
+                    message.replace(MESSAGE_PLACEHOLDER, original.as_str().unwrap_or_default());
+                *original = replaced.into();
+                localized = true;
+            }
+        }
+        localized
+    }
+}
+
+/// The catalogs, by lowercase locale
+#[derive(Debug, Default)]
+struct Catalogs {
+    catalogs: HashMap<String, Arc<Catalog>>,
+    default: Option<Arc<Catalog>>,
+}
+
+impl Catalogs {
+    fn new(config: ErrorMessagesConfig) -> Result<Self, BoxError> {
+        let catalogs: HashMap<String, Arc<Catalog>> = config
+            .catalogs
+            .into_iter()
+            .map(|(locale, messages)| {
+                (
+                    locale.to_ascii_lowercase(),
+                    Arc::new(Catalog { locale, messages }),
+                )
+            })
+            .collect();
+        let default = config
+            .default_locale
+            .map(|locale| {
+                catalogs
+                    .get(&locale.to_ascii_lowercase())
+                    .cloned()
+                    .ok_or_else(|| {
+                        format!("no error messages catalog for the default locale {locale}")
+                    })
+            })
+            .transpose()?;
+        Ok(Self { catalogs, default })
+    }
+
+    /// The catalog best matching an `Accept-Language` header, like `fr-CH, fr;q=0.9, en;q=0.8`.
+    /// The locales are tried by decreasing quality, and a locale with a region matches the
+    /// catalog of its language when there is none for the region
+    fn negotiate(&self, accept_language: Option<&str>) -> Option<Arc<Catalog>> {
+        let mut ranges: Vec<(&str, f32)> = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let locale = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                    .map(|quality| quality.trim().parse().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                (!locale.is_empty() && quality > 0.0).then_some((locale, quality))
+            })
+            .collect();
+        // the sort is stable, so that ranges of equal quality keep their order
+        ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        ranges
+            .into_iter()
+            .find_map(|(locale, _)| {
+                if locale == "*" {
+                    return self.default.clone();
+                }
+                let locale = locale.to_ascii_lowercase();
+                self.catalogs.get(&locale).cloned().or_else(|| {
+                    let (language, _) = locale.split_once('-')?;
+                    self.catalogs.get(language).cloned()
+                })
+            })
+            .or_else(|| self.default.clone())
+    }
+}
+
+/// Set in the context extensions with the catalog chosen for the request
+#[derive(Clone, Debug)]
+struct ErrorMessagesLocale(Arc<Catalog>);
+
+struct ErrorMessages {
+    catalogs: Arc<Catalogs>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ErrorMessages {
+    type Config = ErrorMessagesConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            catalogs: Arc::new(Catalogs::new(init.config)?),
+        })
+    }
+
+    fn router_service(&self, service: router::BoxService) -> router::BoxService {
+        if self.catalogs.catalogs.is_empty() {
+            return service;
+        }
+
+        let catalogs = self.catalogs.clone();
+        ServiceBuilder::new()
+            .map_request(move |request: router::Request| {
+                let accept_language = request
+                    .router_request
+                    .headers()
+                    .get(ACCEPT_LANGUAGE)
+                    .and_then(|value| value.to_str().ok());
+                if let Some(catalog) = catalogs.negotiate(accept_language) {
+                    request
+                        .context
+                        .extensions()
+                        .with_lock(|mut lock| lock.insert(ErrorMessagesLocale(catalog)));
+                }
+                request
+            })
+            .service(service)
+            .and_then(|mut response: router::Response| async move {
+                // requests with other Accept-Language headers can get other messages, including
+                // the ones that matched no catalog, so shared caches must key responses on it
+                response
+                    .response
+                    .headers_mut()
+                    .append(VARY, ACCEPT_LANGUAGE_HEADER_VALUE.clone());
+                let Some(ErrorMessagesLocale(catalog)) = response
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<ErrorMessagesLocale>().cloned())
+                else {
+                    return Ok(response);
+                };
+                // multipart responses are streamed, and CBOR responses are left as is
+                let is_json = response
+                    .response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|content_type| content_type.contains("json"));
+                if !is_json {
+                    return Ok(response);
+                }
+
+                let (mut parts, body) = response.response.into_parts();
+                let bytes = get_body_bytes(body).await?;
+                let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                    Ok(mut json) if catalog.localize(&mut json) => {
+                        if let Ok(locale) = HeaderValue::from_str(&catalog.locale) {
+                            parts.headers.insert(CONTENT_LANGUAGE, locale);
+                        }
+                        serde_json::to_vec(&json)?.into()
+                    }
+                    _ => bytes,
+                };
+                Ok::<_, BoxError>(router::Response {
+                    response: http::Response::from_parts(
+                        parts,
+                        RouterBody::from(body).into_inner(),
+                    ),
+                    context: response.context,
+                })
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::graphql;
+    use crate::plugin::test::MockRouterService;
+
+    fn catalogs(default_locale: Option<&str>) -> Catalogs {
+        Catalogs::new(ErrorMessagesConfig {
+            catalogs: serde_json::from_value(serde_json::json!({
+                "fr": { "MAX_DEPTH_LIMIT": "L'opération est trop profonde" },
+                "pt-BR": { "MAX_DEPTH_LIMIT": "A operação é muito profunda" },
+                "en": { "MAX_DEPTH_LIMIT": "{message}" }
+            }))
+            .unwrap(),
+            default_locale: default_locale.map(str::to_string),
+        })
+        .unwrap()
+    }
+
+    fn locale(catalogs: &Catalogs, accept_language: &str) -> Option<String> {
+        catalogs
+            .negotiate(Some(accept_language))
+            .map(|catalog| catalog.locale.clone())
+    }
+
+    #[test]
+    fn negotiates_the_best_locale() {
+        let catalogs = catalogs(None);
+        assert_eq!(
+            locale(&catalogs, "fr-CH, fr;q=0.9, en;q=0.8").as_deref(),
+            Some("fr")
+        );
+        assert_eq!(
+            locale(&catalogs, "de, pt-br;q=0.5").as_deref(),
+            Some("pt-BR")
+        );
+        assert_eq!(
+            locale(&catalogs, "en;q=0.5, fr;q=0.7").as_deref(),
+            Some("fr")
+        );
+        assert_eq!(locale(&catalogs, "pt-PT"), None);
+        assert_eq!(locale(&catalogs, "fr;q=0"), None);
+        assert_eq!(locale(&catalogs, "*"), None);
+
+        let catalogs = self::catalogs(Some("en"));
+        assert_eq!(locale(&catalogs, "de").as_deref(), Some("en"));
+        assert_eq!(catalogs.negotiate(None).unwrap().locale, "en");
+
+        assert!(Catalogs::new(ErrorMessagesConfig {
+            catalogs: HashMap::new(),
+            default_locale: Some("en".to_string()),
+        })
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn localizes_error_messages() {
+        let plugin = ErrorMessages {
+            catalogs: Arc::new(catalogs(None)),
+        };
+
+        for (accept_language, message, content_language) in [
+            ("fr", "L'opération est trop profonde", Some("fr")),
+            ("en", "Maximum depth limit exceeded", Some("en")),
+            ("de", "Maximum depth limit exceeded", None),
+        ] {
+            let mut mock = MockRouterService::new();
+            mock.expect_call().times(1).returning(|request| {
+                router::Response::fake_builder()
+                    .data(json!({ "me": null }))
+                    .error(
+                        graphql::Error::builder()
+                            .message("Maximum depth limit exceeded")
+                            .extension_code("MAX_DEPTH_LIMIT")
+                            .build(),
+                    )
+                    .header("content-type", "application/json")
+                    .context(request.context)
+                    .build()
+            });
+            let request = router::Request::fake_builder()
+                .header("accept-language", accept_language)
+                .build()
+                .unwrap();
+            let response = plugin
+                .router_service(mock.boxed())
+                .oneshot(request)
+                .await
+                .unwrap();
+            assert_eq!(
+                response
+                    .response
+                    .headers()
+                    .get(CONTENT_LANGUAGE)
+                    .and_then(|value| value.to_str().ok()),
+                content_language
+            );
+            assert!(response
+                .response
+                .headers()
+                .get_all(VARY)
+                .iter()
+                .any(|value| value == "accept-language"));
+            let body = get_body_bytes(response.response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["errors"][0]["message"], message);
+            assert_eq!(body["errors"][0]["extensions"]["code"], "MAX_DEPTH_LIMIT");
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use futures::future::ready;
 use futures::stream::once;
 use futures::StreamExt;
@@ -12,6 +14,9 @@ use tower::ServiceExt as TowerServiceExt;
 use crate::layers::ServiceExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::demand_control::cost_calculator::static_cost::StaticCostCalculator;
+use crate::query_planner::explain::explain;
+use crate::query_planner::explain::EXPLAIN_LIST_SIZE;
 use crate::register_plugin;
 use crate::services::execution;
 use crate::services::supergraph;
@@ -20,11 +25,14 @@ const EXPOSE_QUERY_PLAN_HEADER_NAME: &str = "Apollo-Expose-Query-Plan";
 const ENABLE_EXPOSE_QUERY_PLAN_ENV: &str = "APOLLO_EXPOSE_QUERY_PLAN";
 const QUERY_PLAN_CONTEXT_KEY: &str = "experimental::expose_query_plan.plan";
 const FORMATTED_QUERY_PLAN_CONTEXT_KEY: &str = "experimental::expose_query_plan.formatted_plan";
+const EXPLAINED_QUERY_PLAN_CONTEXT_KEY: &str = "experimental::expose_query_plan.explained_plan";
 const ENABLED_CONTEXT_KEY: &str = "experimental::expose_query_plan.enabled";
+const EXPLAIN_CONTEXT_KEY: &str = "experimental::expose_query_plan.explain";
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 struct ExposeQueryPlan {
     enabled: bool,
+    costs: Arc<StaticCostCalculator>,
 }
 
 /// Expose query plan
@@ -43,10 +51,15 @@ impl Plugin for ExposeQueryPlan {
         Ok(ExposeQueryPlan {
             enabled: init.config.0
                 || std::env::var(ENABLE_EXPOSE_QUERY_PLAN_ENV).as_deref() == Ok("true"),
+            costs: Arc::new(StaticCostCalculator::new(
+                init.subgraph_schemas,
+                EXPLAIN_LIST_SIZE,
+            )),
         })
     }
 
     fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let costs = self.costs.clone();
         service
             .map_request(move |req: execution::Request| {
                 if req
@@ -65,6 +78,14 @@ impl Plugin for ExposeQueryPlan {
                             req.query_plan.formatted_query_plan.clone(),
                         )
                         .unwrap();
+                    if req.context.contains_key(EXPLAIN_CONTEXT_KEY) {
+                        req.context
+                            .insert(
+                                EXPLAINED_QUERY_PLAN_CONTEXT_KEY,
+                                explain(&req.query_plan, Some(&costs)),
+                            )
+                            .unwrap();
+                    }
                 }
 
                 req
@@ -76,9 +97,14 @@ impl Plugin for ExposeQueryPlan {
         let conf_enabled = self.enabled;
         service
             .map_future_with_request_data(move |req: &supergraph::Request| {
-                let is_enabled = conf_enabled && req.supergraph_request.headers().get(EXPOSE_QUERY_PLAN_HEADER_NAME) == Some(&HeaderValue::from_static("true"));
+                let header = req.supergraph_request.headers().get(EXPOSE_QUERY_PLAN_HEADER_NAME);
+                let is_explain = header == Some(&HeaderValue::from_static("explain"));
+                let is_enabled = conf_enabled && (header == Some(&HeaderValue::from_static("true")) || is_explain);
                 if is_enabled {
                     req.context.insert(ENABLED_CONTEXT_KEY, true).unwrap();
+                    if is_explain {
+                        req.context.insert(EXPLAIN_CONTEXT_KEY, true).unwrap();
+                    }
                 }
 
                 is_enabled
@@ -95,9 +121,15 @@ impl Plugin for ExposeQueryPlan {
                                 if let Some(plan) =
                                     res.context.get_json_value(QUERY_PLAN_CONTEXT_KEY)
                                 {
+                                    let mut query_plan = json!({ "object": { "kind": "QueryPlan", "node": plan }, "text": res.context.get_json_value(FORMATTED_QUERY_PLAN_CONTEXT_KEY) });
+                                    if let Some(explained) = res.context.get_json_value(EXPLAINED_QUERY_PLAN_CONTEXT_KEY) {
+                                        if let Some(query_plan) = query_plan.as_object_mut() {
+                                            query_plan.insert("explain", explained);
+                                        }
+                                    }
                                     first
                                         .extensions
-                                        .insert("apolloQueryPlan", json!({ "object": { "kind": "QueryPlan", "node": plan }, "text": res.context.get_json_value(FORMATTED_QUERY_PLAN_CONTEXT_KEY) }));
+                                        .insert("apolloQueryPlan", query_plan);
                                 }
                             }
                             res.response = http::Response::from_parts(
@@ -189,13 +221,21 @@ mod tests {
     }
 
     async fn execute_supergraph_test(
+        query: &str,
+        supergraph_service: supergraph::BoxCloneService,
+    ) -> Response {
+        execute_supergraph_test_with_header(query, supergraph_service, "true").await
+    }
+
+    async fn execute_supergraph_test_with_header(
         query: &str,
         mut supergraph_service: supergraph::BoxCloneService,
+        expose: &str,
     ) -> Response {
         let request = supergraph::Request::fake_builder()
             .query(query.to_string())
             .variable("first", 2usize)
-            .header(EXPOSE_QUERY_PLAN_HEADER_NAME, "true")
+            .header(EXPOSE_QUERY_PLAN_HEADER_NAME, expose)
             .build()
             .expect("expecting valid request");
 
@@ -240,6 +280,29 @@ mod tests {
         insta::assert_json_snapshot!(serde_json::to_value(response).unwrap());
     }
 
+    #[tokio::test]
+    async fn it_explains_query_plan() {
+        let response = execute_supergraph_test_with_header(
+            VALID_QUERY,
+            build_mock_supergraph(serde_json::json! {{
+                "plugins": {
+                    "experimental.expose_query_plan": true
+                }
+            }})
+            .await,
+            "explain",
+        )
+        .await;
+
+        let response = serde_json::to_value(response).unwrap();
+        let query_plan = &response["extensions"]["apolloQueryPlan"];
+        assert!(query_plan["object"].is_object());
+        let explained = query_plan["explain"].as_str().unwrap();
+        assert!(explained.starts_with("QueryPlan (estimated cost: "));
+        assert!(explained.contains("[1] Fetch(service: \"products\")"));
+        assert!(explained.contains("after: [1]"));
+    }
+
     #[tokio::test]
     async fn it_doesnt_expose_query_plan() {
         let supergraph = build_mock_supergraph(serde_json::json! {{
@@ -0,0 +1,351 @@
+//! Feature flags evaluated by an OpenFeature compatible provider.
+//!
+//! Flag values are configured with defaults, and updated by polling a provider implementing the
+//! OpenFeature Remote Evaluation Protocol (OFREP), like flagd. They're inserted in the context
+//! of each request, where telemetry selectors, Rhai scripts, coprocessors and the static
+//! responses rules read them, so behavior toggles don't need a configuration rollout.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use http::header::CONTENT_TYPE;
+use http::HeaderName;
+use http::HeaderValue;
+use mime::APPLICATION_JSON;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+use url::Url;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::router;
+use crate::Context;
+
+register_plugin!("apollo", "feature_flags", FeatureFlags);
+
+/// Prefix of the context keys of the flags, followed by the flag name
+pub(crate) const FEATURE_FLAG_CONTEXT_PREFIX: &str = "apollo_feature_flags::";
+
+const OFREP_BULK_EVALUATION_PATH: &str = "ofrep/v1/evaluate/flags";
+
+/// Whether a flag is enabled for the request, with the `true` value
+pub(crate) fn is_enabled(context: &Context, flag: &str) -> bool {
+    context
+        .get::<_, serde_json::Value>(format!("{FEATURE_FLAG_CONTEXT_PREFIX}{flag}"))
+        .ok()
+        .flatten()
+        .is_some_and(|value| value == serde_json::Value::Bool(true))
+}
+
+/// Feature flags available in the context of the requests
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct FeatureFlagsConfig {
+    /// Default values of the flags, used for the flags the provider doesn't evaluate
+    flags: HashMap<String, serde_json::Value>,
+    /// Provider evaluating the flags
+    provider: Option<FeatureFlagsProvider>,
+}
+
+/// Provider evaluating the flags
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+enum FeatureFlagsProvider {
+    /// Service implementing the OpenFeature Remote Evaluation Protocol, polled with bulk
+    /// evaluations
+    Ofrep(OfrepProvider),
+}
+
+/// OpenFeature Remote Evaluation Protocol provider
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct OfrepProvider {
+    /// Base URL of the OFREP service
+    url: String,
+    /// Evaluation context sent to the service, like the `targetingKey` of the router
+    #[serde(default)]
+    context: BTreeMap<String, serde_json::Value>,
+    /// Headers sent to the service, like an API key
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Interval between two evaluations of the flags, in human-readable format; defaults to 30s
+    #[serde(
+        deserialize_with = "humantime_serde::deserialize",
+        default = "default_poll_interval"
+    )]
+    #[schemars(with = "String", default = "default_poll_interval")]
+    poll_interval: Duration,
+}
+
+fn default_poll_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// Bulk evaluation response of an OFREP service
+#[derive(Deserialize)]
+struct BulkEvaluation {
+    #[serde(default)]
+    flags: Vec<FlagEvaluation>,
+}
+
+/// Evaluation of a flag. Flags with an evaluation error have no value
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FlagEvaluation {
+    key: String,
+    value: Option<serde_json::Value>,
+    error_code: Option<String>,
+}
+
+/// The OFREP service and the request evaluating the flags
+struct OfrepClient {
+    client: reqwest::Client,
+    url: Url,
+    headers: http::HeaderMap,
+    body: serde_json::Value,
+}
+
+impl OfrepClient {
+    fn new(provider: &OfrepProvider) -> Result<Self, BoxError> {
+        let url = Url::from_str(&provider.url)
+            .and_then(|url| url.join(OFREP_BULK_EVALUATION_PATH))
+            .map_err(|e| format!("invalid feature flags provider URL: {e}"))?;
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(APPLICATION_JSON.essence_str()),
+        );
+        for (name, value) in &provider.headers {
+            headers.insert(
+                HeaderName::try_from(name.as_str())
+                    .map_err(|e| format!("invalid feature flags provider header: {e}"))?,
+                HeaderValue::try_from(value.as_str())
+                    .map_err(|e| format!("invalid feature flags provider header: {e}"))?,
+            );
+        }
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(provider.poll_interval)
+                .build()?,
+            url,
+            headers,
+            body: serde_json::json!({ "context": provider.context }),
+        })
+    }
+
+    async fn evaluate(&self) -> Result<HashMap<String, serde_json::Value>, BoxError> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .headers(self.headers.clone())
+            .json(&self.body)
+            .send()
+            .await?
+            .error_for_status()?;
+        let evaluation: BulkEvaluation = response.json().await?;
+        Ok(evaluation
+            .flags
+            .into_iter()
+            .filter_map(|flag| {
+                if let Some(code) = flag.error_code {
+                    tracing::debug!("the {} feature flag was not evaluated: {code}", flag.key);
+                }
+                Some((flag.key, flag.value?))
+            })
+            .collect())
+    }
+}
+
+struct FeatureFlags {
+    /// Context keys and values of the flags
+    flags: Arc<ArcSwap<Vec<(String, serde_json_bytes::Value)>>>,
+    poller: Option<JoinHandle<()>>,
+}
+
+/// The context keys and values of the flags, with the evaluated values replacing the defaults
+fn context_entries(
+    defaults: &HashMap<String, serde_json::Value>,
+    evaluated: HashMap<String, serde_json::Value>,
+) -> Vec<(String, serde_json_bytes::Value)> {
+    let mut flags = defaults.clone();
+    flags.extend(evaluated);
+    flags
+        .into_iter()
+        .map(|(name, value)| (format!("{FEATURE_FLAG_CONTEXT_PREFIX}{name}"), value.into()))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl Plugin for FeatureFlags {
+    type Config = FeatureFlagsConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let defaults = init.config.flags;
+        let flags = Arc::new(ArcSwap::from_pointee(context_entries(
+            &defaults,
+            HashMap::new(),
+        )));
+
+        let poller = match init.config.provider {
+            None => None,
+            Some(FeatureFlagsProvider::Ofrep(provider)) => {
+                let client = OfrepClient::new(&provider)?;
+                // the first evaluation is done before serving requests
+                match client.evaluate().await {
+                    Ok(evaluated) => flags.store(Arc::new(context_entries(&defaults, evaluated))),
+                    Err(e) => tracing::error!(
+                        "could not evaluate the feature flags, using the default values: {e}"
+                    ),
+                }
+                let flags = flags.clone();
+                Some(tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(provider.poll_interval);
+                    interval.tick().await;
+                    loop {
+                        interval.tick().await;
+                        match client.evaluate().await {
+                            Ok(evaluated) => {
+                                flags.store(Arc::new(context_entries(&defaults, evaluated)))
+                            }
+                            Err(e) => tracing::error!(
+                                "could not evaluate the feature flags, keeping the previous values: {e}"
+                            ),
+                        }
+                    }
+                }))
+            }
+        };
+
+        Ok(Self { flags, poller })
+    }
+
+    fn router_service(&self, service: router::BoxService) -> router::BoxService {
+        let flags = self.flags.clone();
+        ServiceBuilder::new()
+            .map_request(move |request: router::Request| {
+                for (key, value) in flags.load().iter() {
+                    request
+                        .context
+                        .insert_json_value(key.clone(), value.clone());
+                }
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+impl Drop for FeatureFlags {
+    fn drop(&mut self) {
+        if let Some(poller) = self.poller.take() {
+            poller.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::body_json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+    use crate::plugin::test::MockRouterService;
+
+    async fn context(plugin: &FeatureFlags) -> Context {
+        let mut mock = MockRouterService::new();
+        mock.expect_call().times(1).returning(|request| {
+            Ok(router::Response::fake_builder()
+                .context(request.context)
+                .build()
+                .unwrap())
+        });
+        plugin
+            .router_service(mock.boxed())
+            .oneshot(router::Request::fake_builder().build().unwrap())
+            .await
+            .unwrap()
+            .context
+    }
+
+    #[tokio::test]
+    async fn default_values_are_in_the_context() {
+        let plugin = FeatureFlags::new(PluginInit::fake_new(
+            serde_json::from_value(serde_json::json!({
+                "flags": { "maintenance": true, "variant": "blue" }
+            }))
+            .unwrap(),
+            Default::default(),
+        ))
+        .await
+        .unwrap();
+        let context = context(&plugin).await;
+        assert!(is_enabled(&context, "maintenance"));
+        assert!(!is_enabled(&context, "variant"));
+        assert!(!is_enabled(&context, "unknown"));
+        assert_eq!(
+            context
+                .get::<_, String>("apollo_feature_flags::variant")
+                .unwrap(),
+            Some("blue".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn provider_evaluations_replace_the_default_values() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/ofrep/v1/evaluate/flags"))
+            .and(header("authorization", "Bearer key"))
+            .and(body_json(
+                serde_json::json!({ "context": { "targetingKey": "router" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "flags": [
+                    { "key": "maintenance", "value": true, "reason": "STATIC", "variant": "on" },
+                    { "key": "variant", "errorCode": "FLAG_NOT_FOUND" }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let plugin = FeatureFlags::new(PluginInit::fake_new(
+            serde_json::from_value(serde_json::json!({
+                "flags": { "maintenance": false, "variant": "blue" },
+                "provider": {
+                    "ofrep": {
+                        "url": server.uri(),
+                        "context": { "targetingKey": "router" },
+                        "headers": { "authorization": "Bearer key" }
+                    }
+                }
+            }))
+            .unwrap(),
+            Default::default(),
+        ))
+        .await
+        .unwrap();
+        let context = context(&plugin).await;
+        assert!(is_enabled(&context, "maintenance"));
+        assert_eq!(
+            context
+                .get::<_, String>("apollo_feature_flags::variant")
+                .unwrap(),
+            Some("blue".to_string())
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! Details of the subgraph fetches of a request, returned in the response extensions.
+//!
+//! When a client sends the debug header, the `fetchDetails` extension of the first response lists
+//! the fetches to subgraphs, with their start time and duration, their retries, whether they were
+//! deduplicated with an identical fetch in flight, and their entity cache hits and misses.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use http::HeaderName;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::cache::entity::CacheSubgraph;
+use crate::plugins::cache::metrics::CacheMetricContextKey;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!("apollo", "fetch_details", FetchDetails);
+
+const FETCH_DETAILS_EXTENSION: &str = "fetchDetails";
+
+/// Subgraph fetch details returned in the response extensions, for debugging
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct FetchDetailsConfig {
+    /// Return the fetch details to the requests sending the header
+    enabled: bool,
+    /// Header requesting the fetch details, with the `true` value
+    header: String,
+}
+
+impl Default for FetchDetailsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            header: "apollo-fetch-details".to_string(),
+        }
+    }
+}
+
+/// Retries and deduplication of a subgraph fetch, in the extensions of the subgraph HTTP request
+/// for the traffic shaping layers to update them
+#[derive(Debug, Default)]
+pub(crate) struct FetchStats {
+    retries: AtomicUsize,
+    deduplicated: AtomicBool,
+}
+
+impl FetchStats {
+    pub(crate) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_deduplicated(&self) {
+        self.deduplicated.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A fetch to a subgraph during the request
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FetchDetail {
+    subgraph: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operation_name: Option<String>,
+    /// Start of the fetch, from the start of the request
+    start_ms: f64,
+    duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    retries: usize,
+    deduplicated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_hits: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_misses: Option<usize>,
+}
+
+/// The fetches of a request that asked for their details
+#[derive(Clone)]
+struct RequestFetches {
+    start: Instant,
+    fetches: Arc<Mutex<Vec<FetchDetail>>>,
+}
+
+struct FetchDetails {
+    config: FetchDetailsConfig,
+}
+
+/// Entity cache hits and misses of a subgraph fetch
+fn cache_hits(context: &Context, subgraph_name: &str) -> Option<(usize, usize)> {
+    let cache: CacheSubgraph = context
+        .get(CacheMetricContextKey::new(subgraph_name.to_string()))
+        .ok()
+        .flatten()?;
+    Some(cache.0.values().fold((0, 0), |(hits, misses), hit_miss| {
+        (hits + hit_miss.hit, misses + hit_miss.miss)
+    }))
+}
+
+#[async_trait::async_trait]
+impl Plugin for FetchDetails {
+    type Config = FetchDetailsConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        HeaderName::try_from(init.config.header.as_str())
+            .map_err(|e| format!("invalid fetch details header: {e}"))?;
+        Ok(Self {
+            config: init.config,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let header = self.config.header.clone();
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                let requested = request
+                    .supergraph_request
+                    .headers()
+                    .get(&header)
+                    .is_some_and(|value| value == "true");
+                if requested {
+                    request.context.extensions().with_lock(|mut lock| {
+                        lock.insert(RequestFetches {
+                            start: Instant::now(),
+                            fetches: Default::default(),
+                        })
+                    });
+                }
+                request
+            })
+            .map_first_graphql_response(|context, parts, mut response| {
+                let Some(request_fetches) = context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<RequestFetches>().cloned())
+                else {
+                    return (parts, response);
+                };
+                // the fetches of deferred fragments complete after the first response
+                let fetches = request_fetches
+                    .fetches
+                    .lock()
+                    .expect("lock poisoned")
+                    .clone();
+                match serde_json_bytes::to_value(fetches) {
+                    Ok(fetches) => {
+                        response.extensions.insert(FETCH_DETAILS_EXTENSION, fetches);
+                    }
+                    Err(e) => tracing::error!("could not serialize the fetch details: {e}"),
+                }
+                (parts, response)
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let name = name.to_string();
+        ServiceBuilder::new()
+            .map_request(|mut request: subgraph::Request| {
+                let requested = request
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.contains_key::<RequestFetches>());
+                if requested {
+                    request
+                        .subgraph_request
+                        .extensions_mut()
+                        .insert(Arc::new(FetchStats::default()));
+                }
+                request
+            })
+            .map_future_with_request_data(
+                |request: &subgraph::Request| {
+                    (
+                        request.context.clone(),
+                        request
+                            .context
+                            .extensions()
+                            .with_lock(|lock| lock.get::<RequestFetches>().cloned()),
+                        request
+                            .subgraph_request
+                            .extensions()
+                            .get::<Arc<FetchStats>>()
+                            .cloned(),
+                        request.subgraph_request.body().operation_name.clone(),
+                    )
+                },
+                move |(context, request_fetches, stats, operation_name): (
+                    Context,
+                    Option<RequestFetches>,
+                    Option<Arc<FetchStats>>,
+                    Option<String>,
+                ),
+                      future| {
+                    let name = name.clone();
+                    let start = Instant::now();
+                    async move {
+                        let result: Result<subgraph::Response, BoxError> = future.await;
+                        if let (Some(request_fetches), Some(stats)) = (request_fetches, stats) {
+                            // the entity cache sets the hits and misses of the last fetch to
+                            // the subgraph
+                            let (cache_hits, cache_misses) = cache_hits(&context, &name).unzip();
+                            let detail = FetchDetail {
+                                subgraph: name,
+                                operation_name,
+                                start_ms: start
+                                    .saturating_duration_since(request_fetches.start)
+                                    .as_secs_f64()
+                                    * 1000.0,
+                                duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+                                status: result
+                                    .as_ref()
+                                    .ok()
+                                    .map(|response| response.response.status().as_u16()),
+                                error: result.as_ref().err().map(|e| e.to_string()),
+                                retries: stats.retries.load(Ordering::Relaxed),
+                                deduplicated: stats.deduplicated.load(Ordering::Relaxed),
+                                cache_hits,
+                                cache_misses,
+                            };
+                            request_fetches
+                                .fetches
+                                .lock()
+                                .expect("lock poisoned")
+                                .push(detail);
+                        }
+                        result
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::test::MockSupergraphService;
+
+    async fn plugin() -> FetchDetails {
+        FetchDetails::new(PluginInit::fake_new(
+            serde_json::from_value(serde_json::json!({ "enabled": true })).unwrap(),
+            Default::default(),
+        ))
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn subgraph_fetches_are_recorded() {
+        let plugin = plugin().await;
+        let context = Context::new();
+        let request_fetches = RequestFetches {
+            start: Instant::now(),
+            fetches: Default::default(),
+        };
+        context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(request_fetches.clone()));
+
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call().times(1).returning(|request| {
+            // as the retry layer does
+            request
+                .subgraph_request
+                .extensions()
+                .get::<Arc<FetchStats>>()
+                .unwrap()
+                .record_retry();
+            Ok(subgraph::Response::fake_builder()
+                .context(request.context)
+                .build())
+        });
+        plugin
+            .subgraph_service("products", mock.boxed())
+            .oneshot(subgraph::Request::fake_builder().context(context).build())
+            .await
+            .unwrap();
+
+        let fetches = request_fetches.fetches.lock().unwrap();
+        assert_eq!(fetches.len(), 1);
+        assert_eq!(fetches[0].subgraph, "products");
+        assert_eq!(fetches[0].status, Some(200));
+        assert_eq!(fetches[0].retries, 1);
+        assert!(!fetches[0].deduplicated);
+        assert_eq!(fetches[0].cache_hits, None);
+    }
+
+    #[tokio::test]
+    async fn fetch_details_are_returned_with_the_header() {
+        let plugin = plugin().await;
+        for (header, expected) in [(Some("true"), true), (Some("false"), false), (None, false)] {
+            let mut mock = MockSupergraphService::new();
+            mock.expect_call().times(1).returning(|request| {
+                request.context.extensions().with_lock(|lock| {
+                    if let Some(request_fetches) = lock.get::<RequestFetches>() {
+                        request_fetches.fetches.lock().unwrap().push(FetchDetail {
+                            subgraph: "products".to_string(),
+                            operation_name: None,
+                            start_ms: 1.0,
+                            duration_ms: 2.5,
+                            status: Some(200),
+                            error: None,
+                            retries: 0,
+                            deduplicated: true,
+                            cache_hits: None,
+                            cache_misses: None,
+                        });
+                    }
+                });
+                supergraph::Response::fake_builder()
+                    .context(request.context)
+                    .data(json!({ "topProducts": [] }))
+                    .build()
+            });
+
+            let mut request = supergraph::Request::fake_builder().query("{ topProducts { upc } }");
+            if let Some(header) = header {
+                request = request.header("apollo-fetch-details", header);
+            }
+            let response = plugin
+                .supergraph_service(mock.boxed())
+                .oneshot(request.build().unwrap())
+                .await
+                .unwrap()
+                .next_response()
+                .await
+                .unwrap();
+            if expected {
+                assert_eq!(
+                    response.extensions.get(FETCH_DETAILS_EXTENSION),
+                    Some(&json!([{
+                        "subgraph": "products",
+                        "startMs": 1.0,
+                        "durationMs": 2.5,
+                        "status": 200,
+                        "retries": 0,
+                        "deduplicated": true
+                    }]))
+                );
+            } else {
+                assert!(response.extensions.get(FETCH_DETAILS_EXTENSION).is_none());
+            }
+        }
+    }
+}
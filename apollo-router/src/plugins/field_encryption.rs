@@ -0,0 +1,694 @@
+//! Encryption of sensitive fields in the responses sent to clients.
+//!
+//! The values of the configured fields are replaced with ciphertext strings, so that the
+//! intermediaries between the router and the client, like proxies or logging gateways, never see
+//! them. Each value is encrypted for the X25519 public key of the client: the key configured for
+//! its client name, or the key sent in a request header.
+//!
+//! A response (or each chunk of a deferred response or subscription) is encrypted with a new
+//! ephemeral key: the AES-256-GCM key is derived with HKDF-SHA256 from the X25519 shared secret,
+//! salted with the ephemeral and client public keys. The ciphertext of a value is
+//! `v1.<ephemeral public key>.<nonce>.<encrypted JSON value>`, each part encoded in unpadded
+//! base64url.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use apollo_compiler::executable;
+use apollo_compiler::executable::Selection;
+use apollo_compiler::executable::SelectionSet;
+use apollo_compiler::validation::Valid;
+use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Schema;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use ring::aead;
+use ring::agreement;
+use ring::hkdf;
+use ring::rand::SecureRandom;
+use ring::rand::SystemRandom;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::json_ext::Object;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "field_encryption", FieldEncryption);
+
+const CIPHERTEXT_VERSION: &str = "v1";
+const KEY_DERIVATION_INFO: &[u8] = b"apollo-router field encryption v1";
+const X25519_PUBLIC_KEY_LEN: usize = 32;
+const MISSING_KEY_ERROR_CODE: &str = "FIELD_ENCRYPTION_KEY_MISSING";
+
+/// Encryption of sensitive fields in the responses sent to clients
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct FieldEncryptionConfig {
+    /// Encrypt the values of the configured fields in responses
+    enabled: bool,
+    /// Schema coordinates of the fields to encrypt, like `User.email`. A field selected on an
+    /// interface or union is encrypted if it is configured for one of its possible types
+    fields: Vec<String>,
+    /// X25519 public keys of the clients, encoded in base64url, by client name
+    client_keys: HashMap<String, String>,
+    /// Request header where clients without a configured key send their X25519 public key,
+    /// encoded in base64url. Keys sent by clients are not accepted if it isn't set
+    client_key_header: Option<String>,
+}
+
+/// The public key the fields of a response are encrypted for
+#[derive(Clone, Debug)]
+enum ClientKey {
+    Valid(Arc<Vec<u8>>),
+    Missing,
+    Invalid,
+}
+
+fn decode_public_key(encoded: &str) -> Option<Vec<u8>> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(encoded.trim())
+        .ok()
+        .filter(|key| key.len() == X25519_PUBLIC_KEY_LEN)
+}
+
+/// Encrypts the values of a response for a client public key, with a new ephemeral key
+struct Encryptor {
+    key: aead::LessSafeKey,
+    ephemeral_public_key: String,
+    rng: SystemRandom,
+}
+
+/// Derives the AES-256-GCM key from the X25519 shared secret
+fn derive_key(
+    shared_secret: &[u8],
+    ephemeral_public_key: &[u8],
+    client_public_key: &[u8],
+) -> Result<aead::LessSafeKey, ring::error::Unspecified> {
+    let salt = hkdf::Salt::new(
+        hkdf::HKDF_SHA256,
+        &[ephemeral_public_key, client_public_key].concat(),
+    );
+    let okm = salt
+        .extract(shared_secret)
+        .expand(&[KEY_DERIVATION_INFO], &aead::AES_256_GCM)?;
+    Ok(aead::LessSafeKey::new(aead::UnboundKey::from(okm)))
+}
+
+impl Encryptor {
+    fn new(client_public_key: &[u8]) -> Result<Self, ring::error::Unspecified> {
+        let rng = SystemRandom::new();
+        let ephemeral_private_key =
+            agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)?;
+        let ephemeral_public_key = ephemeral_private_key.compute_public_key()?;
+        let key = agreement::agree_ephemeral(
+            ephemeral_private_key,
+            &agreement::UnparsedPublicKey::new(&agreement::X25519, client_public_key),
+            |shared_secret| {
+                derive_key(
+                    shared_secret,
+                    ephemeral_public_key.as_ref(),
+                    client_public_key,
+                )
+            },
+        )??;
+        Ok(Self {
+            key,
+            ephemeral_public_key: BASE64_URL_SAFE_NO_PAD.encode(ephemeral_public_key.as_ref()),
+            rng,
+        })
+    }
+
+    fn encrypt(&self, value: &Value) -> Result<String, BoxError> {
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        self.rng.fill(&mut nonce)?;
+        let mut in_out = serde_json::to_vec(value)?;
+        self.key.seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::empty(),
+            &mut in_out,
+        )?;
+        Ok(format!(
+            "{CIPHERTEXT_VERSION}.{}.{}.{}",
+            self.ephemeral_public_key,
+            BASE64_URL_SAFE_NO_PAD.encode(nonce),
+            BASE64_URL_SAFE_NO_PAD.encode(in_out)
+        ))
+    }
+}
+
+/// What to do with the values of the sensitive fields of a response
+enum Protection {
+    Encrypt(Encryptor),
+    /// Without a valid key, the values are removed
+    Remove,
+}
+
+impl Protection {
+    fn protect(&self, value: &mut Value) {
+        if value.is_null() {
+            return;
+        }
+        *value = match self {
+            Protection::Encrypt(encryptor) => match encryptor.encrypt(value) {
+                Ok(ciphertext) => Value::String(ciphertext.into()),
+                Err(error) => {
+                    tracing::error!("could not encrypt a field value: {error}");
+                    Value::Null
+                }
+            },
+            Protection::Remove => Value::Null,
+        };
+    }
+}
+
+/// The sensitive fields of the schema, and how to find them in the responses of an operation
+struct SensitiveFields {
+    schema: Arc<Valid<Schema>>,
+    /// Types with a sensitive field, by field name
+    fields: HashMap<String, HashSet<String>>,
+}
+
+impl SensitiveFields {
+    fn new(schema: Arc<Valid<Schema>>, coordinates: &[String]) -> Result<Self, BoxError> {
+        let mut fields: HashMap<String, HashSet<String>> = HashMap::new();
+        for coordinate in coordinates {
+            let Some((type_name, field_name)) = coordinate.split_once('.') else {
+                return Err(format!(
+                    "invalid field encryption coordinate {coordinate}, expected `Type.field`"
+                )
+                .into());
+            };
+            if schema.type_field(type_name, field_name).is_err() {
+                return Err(format!(
+                    "the {coordinate} field to encrypt is not defined in the schema"
+                )
+                .into());
+            }
+            fields
+                .entry(field_name.to_string())
+                .or_default()
+                .insert(type_name.to_string());
+        }
+        Ok(Self { schema, fields })
+    }
+
+    /// Whether the field is sensitive when selected on the parent type. Types related to the
+    /// parent type are checked too, as the concrete type of the object is not always known.
+    fn is_sensitive(&self, parent_type: &str, field_name: &str) -> bool {
+        self.fields.get(field_name).is_some_and(|types| {
+            types.iter().any(|ty| {
+                ty == parent_type
+                    || self.schema.is_subtype(parent_type, ty)
+                    || self.schema.is_subtype(ty, parent_type)
+            })
+        })
+    }
+
+    /// Protects the sensitive fields of a response object, found with the selection sets it
+    /// was returned for. Returns whether there were sensitive fields.
+    fn protect_object(
+        &self,
+        document: &ExecutableDocument,
+        selection_sets: &[&SelectionSet],
+        object: &mut Object,
+        protection: &Protection,
+    ) -> bool {
+        let mut found = false;
+        for (key, value) in object.iter_mut() {
+            let fields = fields_for_key(document, selection_sets, key.as_str());
+            if fields
+                .iter()
+                .any(|(parent_type, field)| self.is_sensitive(parent_type, &field.name))
+            {
+                found |= !value.is_null();
+                protection.protect(value);
+            } else {
+                let selection_sets = fields
+                    .iter()
+                    .map(|(_, field)| &field.selection_set)
+                    .collect::<Vec<_>>();
+                found |= self.protect_value(document, &selection_sets, value, protection);
+            }
+        }
+        found
+    }
+
+    fn protect_value(
+        &self,
+        document: &ExecutableDocument,
+        selection_sets: &[&SelectionSet],
+        value: &mut Value,
+        protection: &Protection,
+    ) -> bool {
+        match value {
+            Value::Object(object) => {
+                self.protect_object(document, selection_sets, object, protection)
+            }
+            Value::Array(items) => items.iter_mut().fold(false, |found, item| {
+                self.protect_value(document, selection_sets, item, protection) | found
+            }),
+            _ => false,
+        }
+    }
+
+    /// Protects the sensitive fields of the data of a response, or of an incremental response
+    /// at the given path
+    fn protect_data(
+        &self,
+        document: &ExecutableDocument,
+        operation_name: Option<&str>,
+        path: Option<&Path>,
+        data: &mut Value,
+        protection: &Protection,
+    ) -> bool {
+        let Ok(operation) = document.operations.get(operation_name) else {
+            return false;
+        };
+        let mut selection_sets = vec![&operation.selection_set];
+        for element in path.map(|path| path.iter()).into_iter().flatten() {
+            if let PathElement::Key(key, _) = element {
+                selection_sets = fields_for_key(document, &selection_sets, key)
+                    .into_iter()
+                    .map(|(_, field)| &field.selection_set)
+                    .collect();
+            }
+        }
+        self.protect_value(document, &selection_sets, data, protection)
+    }
+}
+
+/// The fields selected with this response key in the selection sets and their fragments, with
+/// the type they are selected on
+fn fields_for_key<'a>(
+    document: &'a ExecutableDocument,
+    selection_sets: &[&'a SelectionSet],
+    key: &str,
+) -> Vec<(&'a str, &'a executable::Field)> {
+    let mut fields = Vec::new();
+    let mut pending = selection_sets.to_vec();
+    while let Some(selection_set) = pending.pop() {
+        for selection in &selection_set.selections {
+            match selection {
+                Selection::Field(field) => {
+                    if field.response_key().as_str() == key {
+                        fields.push((selection_set.ty.as_str(), &**field));
+                    }
+                }
+                Selection::InlineFragment(fragment) => pending.push(&fragment.selection_set),
+                Selection::FragmentSpread(spread) => {
+                    if let Some(fragment) = document.fragments.get(&spread.fragment_name) {
+                        pending.push(&fragment.selection_set);
+                    }
+                }
+            }
+        }
+    }
+    fields
+}
+
+struct FieldEncryption {
+    enabled: bool,
+    sensitive_fields: Arc<SensitiveFields>,
+    client_keys: Arc<HashMap<String, Arc<Vec<u8>>>>,
+    client_key_header: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for FieldEncryption {
+    type Config = FieldEncryptionConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let config = init.config;
+        let client_keys = config
+            .client_keys
+            .iter()
+            .map(|(client_name, key)| {
+                let key = decode_public_key(key).ok_or_else(|| {
+                    format!(
+                        "the encryption key of the {client_name} client must be a base64url encoded X25519 public key"
+                    )
+                })?;
+                Ok((client_name.clone(), Arc::new(key)))
+            })
+            .collect::<Result<HashMap<_, _>, BoxError>>()?;
+        Ok(Self {
+            enabled: config.enabled,
+            sensitive_fields: Arc::new(SensitiveFields::new(
+                init.supergraph_schema.clone(),
+                &config.fields,
+            )?),
+            client_keys: Arc::new(client_keys),
+            client_key_header: config.client_key_header,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.enabled || self.sensitive_fields.fields.is_empty() {
+            return service;
+        }
+
+        let client_keys = self.client_keys.clone();
+        let client_key_header = self.client_key_header.clone();
+        let sensitive_fields = self.sensitive_fields.clone();
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                let client_name = request
+                    .context
+                    .get::<_, String>(CLIENT_NAME)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default();
+                let client_key = match client_keys.get(&client_name) {
+                    Some(key) => ClientKey::Valid(key.clone()),
+                    None => match client_key_header
+                        .as_deref()
+                        .and_then(|header| request.supergraph_request.headers().get(header))
+                    {
+                        None => ClientKey::Missing,
+                        Some(header) => match header.to_str().ok().and_then(decode_public_key) {
+                            Some(key) => ClientKey::Valid(Arc::new(key)),
+                            None => ClientKey::Invalid,
+                        },
+                    },
+                };
+                request
+                    .context
+                    .extensions()
+                    .with_lock(|mut lock| lock.insert(client_key));
+                request
+            })
+            .map_response(move |response: supergraph::Response| {
+                let (document, client_key) = response.context.extensions().with_lock(|lock| {
+                    (
+                        lock.get::<ParsedDocument>().cloned(),
+                        lock.get::<ClientKey>().cloned(),
+                    )
+                });
+                let (Some(document), Some(client_key)) = (document, client_key) else {
+                    return response;
+                };
+                let sensitive_fields = sensitive_fields.clone();
+                let operation_name = response
+                    .context
+                    .get::<_, String>(crate::context::OPERATION_NAME)
+                    .ok()
+                    .flatten();
+                response.map_stream(move |mut response: graphql::Response| {
+                    protect_response(
+                        &sensitive_fields,
+                        &document.executable,
+                        operation_name.as_deref(),
+                        &client_key,
+                        &mut response,
+                    );
+                    response
+                })
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+/// Protects the sensitive fields of a response, or of a chunk of a deferred response or
+/// subscription
+fn protect_response(
+    sensitive_fields: &SensitiveFields,
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    client_key: &ClientKey,
+    response: &mut graphql::Response,
+) {
+    let protection = match client_key {
+        ClientKey::Valid(key) => match Encryptor::new(key) {
+            Ok(encryptor) => Protection::Encrypt(encryptor),
+            Err(error) => {
+                tracing::error!("could not derive the field encryption key: {error}");
+                Protection::Remove
+            }
+        },
+        ClientKey::Missing | ClientKey::Invalid => Protection::Remove,
+    };
+
+    let mut found = false;
+    if let Some(data) = response.data.as_mut() {
+        found |= sensitive_fields.protect_data(
+            document,
+            operation_name,
+            response.path.as_ref(),
+            data,
+            &protection,
+        );
+    }
+    for incremental in &mut response.incremental {
+        if let Some(data) = incremental.data.as_mut() {
+            found |= sensitive_fields.protect_data(
+                document,
+                operation_name,
+                incremental.path.as_ref(),
+                data,
+                &protection,
+            );
+        }
+    }
+
+    if found && matches!(protection, Protection::Remove) {
+        let message = match client_key {
+            ClientKey::Invalid => "sensitive fields were removed: the encryption key of the client must be a base64url encoded X25519 public key",
+            _ => "sensitive fields were removed: the client did not provide an encryption key",
+        };
+        response.errors.push(
+            graphql::Error::builder()
+                .message(message)
+                .extension_code(MISSING_KEY_ERROR_CODE)
+                .build(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            me: User
+            people: [Person]
+        }
+
+        interface Person {
+            name: String
+            email: String
+        }
+
+        type User implements Person {
+            name: String
+            email: String
+            address: Address
+        }
+
+        type Employee implements Person {
+            name: String
+            email: String
+        }
+
+        type Address {
+            street: String
+            city: String
+        }
+    "#;
+
+    fn sensitive_fields(coordinates: &[&str]) -> SensitiveFields {
+        let schema = Arc::new(Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap());
+        let coordinates = coordinates
+            .iter()
+            .map(|coordinate| coordinate.to_string())
+            .collect::<Vec<_>>();
+        SensitiveFields::new(schema, &coordinates).unwrap()
+    }
+
+    fn remove(sensitive_fields: &SensitiveFields, query: &str, mut data: Value) -> Value {
+        let document = ExecutableDocument::parse_and_validate(
+            &sensitive_fields.schema,
+            query,
+            "query.graphql",
+        )
+        .unwrap();
+        sensitive_fields.protect_data(&document, None, None, &mut data, &Protection::Remove);
+        data
+    }
+
+    /// Decrypts the values encrypted for a client private key
+    fn decrypt(client_key: agreement::EphemeralPrivateKey, ciphertexts: &[&str]) -> Vec<Value> {
+        let client_public_key = client_key.compute_public_key().unwrap();
+        let parts = ciphertexts[0].split('.').collect::<Vec<_>>();
+        let ephemeral_public_key = BASE64_URL_SAFE_NO_PAD.decode(parts[1]).unwrap();
+        let key = agreement::agree_ephemeral(
+            client_key,
+            &agreement::UnparsedPublicKey::new(&agreement::X25519, &ephemeral_public_key),
+            |shared_secret| {
+                derive_key(
+                    shared_secret,
+                    &ephemeral_public_key,
+                    client_public_key.as_ref(),
+                )
+            },
+        )
+        .unwrap()
+        .unwrap();
+        ciphertexts
+            .iter()
+            .map(|ciphertext| {
+                let parts = ciphertext.split('.').collect::<Vec<_>>();
+                assert_eq!(parts[0], CIPHERTEXT_VERSION);
+                let nonce: [u8; aead::NONCE_LEN] = BASE64_URL_SAFE_NO_PAD
+                    .decode(parts[2])
+                    .unwrap()
+                    .try_into()
+                    .unwrap();
+                let mut in_out = BASE64_URL_SAFE_NO_PAD.decode(parts[3]).unwrap();
+                let plaintext = key
+                    .open_in_place(
+                        aead::Nonce::assume_unique_for_key(nonce),
+                        aead::Aad::empty(),
+                        &mut in_out,
+                    )
+                    .unwrap();
+                serde_json::from_slice(plaintext).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sensitive_fields_are_found_through_fragments_and_aliases() {
+        let sensitive_fields = sensitive_fields(&["User.email", "User.address"]);
+        assert_eq!(
+            remove(
+                &sensitive_fields,
+                "{ me { name mail: email ...Address } } fragment Address on User { address { city } }",
+                json!({ "me": { "name": "Ada", "mail": "ada@example.com", "address": { "city": "London" } } }),
+            ),
+            json!({ "me": { "name": "Ada", "mail": null, "address": null } })
+        );
+        // selected on an interface, the field may be the sensitive one of a type
+        assert_eq!(
+            remove(
+                &sensitive_fields,
+                "{ people { name email } }",
+                json!({ "people": [{ "name": "Ada", "email": "ada@example.com" }, { "name": "Bob", "email": null }] }),
+            ),
+            json!({ "people": [{ "name": "Ada", "email": null }, { "name": "Bob", "email": null }] })
+        );
+    }
+
+    #[test]
+    fn deferred_chunks_are_protected() {
+        let sensitive_fields = sensitive_fields(&["Address.street"]);
+        let document = ExecutableDocument::parse_and_validate(
+            &sensitive_fields.schema,
+            "{ me { name ... @defer { address { street city } } } }",
+            "query.graphql",
+        )
+        .unwrap();
+        let mut data = json!({ "address": { "street": "1 Main St", "city": "London" } });
+        let path = Path::from("me");
+        assert!(sensitive_fields.protect_data(
+            &document,
+            None,
+            Some(&path),
+            &mut data,
+            &Protection::Remove
+        ));
+        assert_eq!(
+            data,
+            json!({ "address": { "street": null, "city": "London" } })
+        );
+    }
+
+    #[test]
+    fn values_are_encrypted_for_the_client_key() {
+        let rng = SystemRandom::new();
+        let client_key =
+            agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng).unwrap();
+        let client_public_key = client_key.compute_public_key().unwrap();
+
+        let sensitive_fields = sensitive_fields(&["User.email", "User.address"]);
+        let document = ExecutableDocument::parse_and_validate(
+            &sensitive_fields.schema,
+            "{ me { name email address { city } } }",
+            "query.graphql",
+        )
+        .unwrap();
+        let mut response = graphql::Response::builder()
+            .data(json!({ "me": { "name": "Ada", "email": "ada@example.com", "address": { "city": "London" } } }))
+            .build();
+        protect_response(
+            &sensitive_fields,
+            &document,
+            None,
+            &ClientKey::Valid(Arc::new(client_public_key.as_ref().to_vec())),
+            &mut response,
+        );
+
+        let me = &response.data.as_ref().unwrap()["me"];
+        assert_eq!(me["name"], json!("Ada"));
+        assert!(response.errors.is_empty());
+        let email = me["email"].as_str().unwrap();
+        let address = me["address"].as_str().unwrap();
+        assert_eq!(
+            decrypt(client_key, &[email, address]),
+            vec![json!("ada@example.com"), json!({ "city": "London" })]
+        );
+    }
+
+    #[test]
+    fn sensitive_fields_are_removed_without_a_key() {
+        let sensitive_fields = sensitive_fields(&["User.email"]);
+        let document = ExecutableDocument::parse_and_validate(
+            &sensitive_fields.schema,
+            "{ me { name email } }",
+            "query.graphql",
+        )
+        .unwrap();
+        let mut response = graphql::Response::builder()
+            .data(json!({ "me": { "name": "Ada", "email": "ada@example.com" } }))
+            .build();
+        protect_response(
+            &sensitive_fields,
+            &document,
+            None,
+            &ClientKey::Missing,
+            &mut response,
+        );
+        assert_eq!(
+            response.data,
+            Some(json!({ "me": { "name": "Ada", "email": null } }))
+        );
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&json!(MISSING_KEY_ERROR_CODE))
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let schema = Arc::new(Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap());
+        assert!(SensitiveFields::new(schema.clone(), &["User.phone".to_string()]).is_err());
+        assert!(SensitiveFields::new(schema, &["email".to_string()]).is_err());
+    }
+}
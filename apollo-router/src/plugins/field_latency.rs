@@ -0,0 +1,304 @@
+//! Field latency attribution derived from the query plan and the subgraph fetch timings.
+//!
+//! Subgraphs that don't return federated traces (ftv1) give no field level timings. Instead, the
+//! duration of each fetch of the query plan is attributed to the response paths of the fields it
+//! resolves, and exported as a histogram and as an attribute of the fetch spans.
+
+use std::time::Duration;
+
+use apollo_compiler::executable::Selection;
+use apollo_compiler::executable::SelectionSet;
+use apollo_compiler::ExecutableDocument;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::context::OPERATION_NAME;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::dynamic_attribute::SpanDynAttribute;
+use crate::query_planner::fetch::FetchNode;
+use crate::register_plugin;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!("apollo", "field_latency", FieldLatency);
+
+const FIELD_PATHS_ATTRIBUTE: &str = "graphql.field.paths";
+const ENTITIES_FIELD: &str = "_entities";
+const TYPENAME_FIELD: &str = "__typename";
+
+/// Attribution of the subgraph fetch durations to the fields they resolve
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct FieldLatencyConfig {
+    /// Attribute the subgraph fetch durations to fields
+    enabled: bool,
+    /// Record the `apollo.router.operations.field.duration` histogram, by response path
+    metrics: bool,
+    /// Add the response paths of the fields resolved by a fetch to its span
+    spans: bool,
+    /// Maximum number of elements of the response paths, at least 1. Longer paths are truncated,
+    /// to limit the cardinality of the metrics
+    max_path_depth: Option<usize>,
+}
+
+impl Default for FieldLatencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            metrics: true,
+            spans: true,
+            max_path_depth: None,
+        }
+    }
+}
+
+/// Set in the context extensions when the fetch durations of the request are attributed to
+/// fields
+#[derive(Clone, Debug)]
+pub(crate) struct FieldLatencyAttribution {
+    metrics: bool,
+    spans: bool,
+    max_path_depth: Option<usize>,
+}
+
+impl FieldLatencyAttribution {
+    /// Attributes the duration of a fetch to the fields it resolved under `current_dir`. Called
+    /// in the span of the fetch.
+    pub(crate) fn record(
+        &self,
+        fetch: &FetchNode,
+        current_dir: &Path,
+        duration: Duration,
+        context: &Context,
+    ) {
+        let Ok(document) = fetch.operation.as_parsed() else {
+            return;
+        };
+        let paths = field_paths(
+            document,
+            fetch.operation_name.as_deref(),
+            current_dir,
+            self.max_path_depth,
+        );
+        if paths.is_empty() {
+            return;
+        }
+
+        if self.spans {
+            tracing::Span::current().set_span_dyn_attribute(
+                FIELD_PATHS_ATTRIBUTE.into(),
+                opentelemetry::Value::Array(opentelemetry::Array::String(
+                    paths.iter().map(|path| path.clone().into()).collect(),
+                )),
+            );
+        }
+        if self.metrics {
+            let operation_name = context
+                .get::<_, String>(OPERATION_NAME)
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+            for path in paths {
+                f64_histogram!(
+                    "apollo.router.operations.field.duration",
+                    "Duration of the subgraph fetches resolving the fields, by response path",
+                    duration.as_secs_f64(),
+                    "graphql.field.path" = path,
+                    "graphql.operation.name" = operation_name.clone(),
+                    "subgraph.name" = fetch.service_name.to_string()
+                );
+            }
+        }
+    }
+}
+
+/// The response paths of the fields selected by a fetch. Entity fetches resolve the fields
+/// selected on `_entities`, at the path of the fetch.
+fn field_paths(
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    current_dir: &Path,
+    max_path_depth: Option<usize>,
+) -> Vec<String> {
+    let Ok(operation) = document.operations.get(operation_name) else {
+        return Vec::new();
+    };
+    let mut root_fields = Vec::new();
+    response_keys(document, &operation.selection_set, &mut root_fields);
+    let mut fields = Vec::new();
+    if root_fields.iter().any(|key| key == ENTITIES_FIELD) {
+        for selection in &operation.selection_set.selections {
+            if let Selection::Field(field) = selection {
+                if field.name == ENTITIES_FIELD {
+                    response_keys(document, &field.selection_set, &mut fields);
+                }
+            }
+        }
+    } else {
+        fields = root_fields;
+    }
+
+    let base: Vec<String> = current_dir
+        .iter()
+        .filter_map(|element| match element {
+            PathElement::Key(key, _) => Some(key.clone()),
+            PathElement::Index(_) | PathElement::Flatten(_) => Some("@".to_string()),
+            PathElement::Fragment(_) => None,
+        })
+        .collect();
+    let mut paths = Vec::new();
+    for field in fields {
+        let mut elements = base.clone();
+        elements.push(field);
+        if let Some(max) = max_path_depth {
+            elements.truncate(max);
+        }
+        let path = format!("/{}", elements.join("/"));
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// The response keys of the fields of a selection set, through fragments
+fn response_keys(
+    document: &ExecutableDocument,
+    selection_set: &SelectionSet,
+    keys: &mut Vec<String>,
+) {
+    for selection in &selection_set.selections {
+        match selection {
+            Selection::Field(field) => {
+                let key = field.response_key().to_string();
+                if key != TYPENAME_FIELD && !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            Selection::InlineFragment(fragment) => {
+                response_keys(document, &fragment.selection_set, keys);
+            }
+            Selection::FragmentSpread(spread) => {
+                if let Some(fragment) = document.fragments.get(&spread.fragment_name) {
+                    response_keys(document, &fragment.selection_set, keys);
+                }
+            }
+        }
+    }
+}
+
+struct FieldLatency {
+    config: FieldLatencyConfig,
+}
+
+#[async_trait::async_trait]
+impl Plugin for FieldLatency {
+    type Config = FieldLatencyConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        if init.config.max_path_depth == Some(0) {
+            return Err("the maximum field latency path depth must be at least 1".into());
+        }
+        Ok(Self {
+            config: init.config,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled || !(self.config.metrics || self.config.spans) {
+            return service;
+        }
+
+        let attribution = FieldLatencyAttribution {
+            metrics: self.config.metrics,
+            spans: self.config.spans,
+            max_path_depth: self.config.max_path_depth,
+        };
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                request
+                    .context
+                    .extensions()
+                    .with_lock(|mut lock| lock.insert(attribution.clone()));
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apollo_compiler::Schema;
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        scalar _Any
+        union _Entity = Product
+        type Query {
+            topProducts: [Product]
+            _entities(representations: [_Any!]!): [_Entity]!
+        }
+        type Product {
+            upc: String
+            name: String
+            reviews: [Review]
+        }
+        type Review {
+            body: String
+        }
+    "#;
+
+    fn paths(operation: &str, current_dir: Path, max_path_depth: Option<usize>) -> Vec<String> {
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let document =
+            ExecutableDocument::parse_and_validate(&schema, operation, "operation.graphql")
+                .unwrap();
+        field_paths(&document, None, &current_dir, max_path_depth)
+    }
+
+    #[test]
+    fn root_fetches_resolve_the_root_fields() {
+        assert_eq!(
+            paths("{ topProducts { upc name } }", Path::empty(), None),
+            vec!["/topProducts"]
+        );
+    }
+
+    #[test]
+    fn entity_fetches_resolve_the_fields_at_their_path() {
+        let operation = "query($representations: [_Any!]!) { \
+            _entities(representations: $representations) { \
+                __typename ...ProductFields ... on Product { name } \
+            } \
+        } \
+        fragment ProductFields on Product { reviews { body } name }";
+        assert_eq!(
+            paths(operation, Path::from("topProducts/@"), None),
+            vec!["/topProducts/@/reviews", "/topProducts/@/name"]
+        );
+        assert_eq!(
+            paths(operation, Path::from("topProducts/@"), Some(2)),
+            vec!["/topProducts/@"]
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_paths_are_rejected() {
+        let config = serde_json::json!({ "enabled": true, "max_path_depth": 0 });
+        assert!(FieldLatency::new(PluginInit::fake_new(
+            serde_json::from_value(config).unwrap(),
+            Default::default(),
+        ))
+        .await
+        .is_err());
+    }
+}
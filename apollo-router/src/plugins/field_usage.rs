@@ -0,0 +1,583 @@
+//! Report of the schema fields that operations haven't used recently.
+//!
+//! The fields selected by each operation are recorded with the time of their last use. The
+//! report lists, per type, the fields of the schema that weren't used during the configured
+//! window, so that schema owners can find the fields that are safe to remove, without sending
+//! usage reports to GraphOS. The last uses are kept across reloads, and saved to a file so that
+//! they are kept across restarts.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use apollo_compiler::ast;
+use apollo_compiler::executable;
+use apollo_compiler::schema::ExtendedType;
+use apollo_compiler::validation::Valid;
+use apollo_compiler::Schema;
+use futures::future::BoxFuture;
+use http::header::CONTENT_TYPE;
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tower::BoxError;
+use tower::Service;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::http_ext::has_shared_key;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::router;
+use crate::services::supergraph;
+use crate::spec::query::traverse;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+register_plugin!("apollo", "experimental_field_usage", FieldUsage);
+
+const INACCESSIBLE_DIRECTIVE_NAME: &str = "inaccessible";
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The last uses of the fields, kept across reloads. It is loaded from the state file when the
+/// plugin is first created.
+static USAGE: Lazy<Mutex<Option<Usage>>> = Lazy::new(Default::default);
+
+/// Report of the schema fields that operations haven't used recently
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct FieldUsageConfig {
+    /// Record the time of the last use of each field of the schema
+    enabled: bool,
+    /// Fields that operations haven't used during this window are reported as unused
+    /// (default: 30d)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    window: Duration,
+    /// File where the last uses of the fields are saved every minute, and loaded from when the
+    /// router starts
+    state_path: Option<PathBuf>,
+    /// Endpoint returning the report of the unused fields
+    endpoint: Option<FieldUsageEndpointConfig>,
+}
+
+impl Default for FieldUsageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: Duration::from_secs(30 * 24 * 60 * 60),
+            state_path: None,
+            endpoint: None,
+        }
+    }
+}
+
+/// Endpoint returning the report of the unused fields
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct FieldUsageEndpointConfig {
+    /// Listen address of the endpoint
+    listen: ListenAddr,
+    /// Path of the endpoint
+    path: String,
+    /// Shared key expected in the `Authorization` header of the requests
+    shared_key: String,
+}
+
+/// When each field was last used, in seconds since the Unix epoch
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub(crate) struct Usage {
+    /// When the recording started
+    since: u64,
+    /// Last use of each field, by schema coordinate like `Product.price`
+    last_used: HashMap<String, u64>,
+}
+
+impl Usage {
+    fn new(now: u64) -> Self {
+        Self {
+            since: now,
+            last_used: HashMap::new(),
+        }
+    }
+
+    /// Reads the usage saved in a state file
+    pub(crate) fn load(path: &Path) -> Result<Self, BoxError> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), BoxError> {
+        // renamed once written, so that the file is never read partially written
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    fn record(&mut self, coordinates: BTreeSet<String>, now: u64) {
+        for coordinate in coordinates {
+            self.last_used.insert(coordinate, now);
+        }
+    }
+}
+
+/// The fields that operations haven't used during the window, by type
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Report {
+    window_start: String,
+    recording_since: String,
+    /// Whether the recording started before the window, otherwise fields used before the
+    /// recording started may be reported as unused
+    complete: bool,
+    types: BTreeMap<String, Vec<UnusedField>>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct UnusedField {
+    field: String,
+    /// Last use before the window, if any since the recording started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_used: Option<String>,
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn format_seconds(seconds: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(seconds)).to_string()
+}
+
+/// Lists the fields of the object and interface types of the schema that weren't used since the
+/// start of the window. The fields of an object or interface type are also used when they are
+/// selected on an interface it implements.
+pub(crate) fn report(schema: &Schema, usage: &Usage, window: Duration, now: SystemTime) -> Report {
+    let window_start = unix_seconds(now).saturating_sub(window.as_secs());
+    let mut types = BTreeMap::new();
+    for (type_name, ty) in &schema.types {
+        let (fields, interfaces) = match ty {
+            ExtendedType::Object(object) if !object.directives.has(INACCESSIBLE_DIRECTIVE_NAME) => {
+                (&object.fields, &object.implements_interfaces)
+            }
+            ExtendedType::Interface(interface)
+                if !interface.directives.has(INACCESSIBLE_DIRECTIVE_NAME) =>
+            {
+                (&interface.fields, &interface.implements_interfaces)
+            }
+            _ => continue,
+        };
+        if ty.is_built_in() {
+            continue;
+        }
+        let unused = fields
+            .values()
+            .filter(|field| !field.directives.has(INACCESSIBLE_DIRECTIVE_NAME))
+            .filter_map(|field| {
+                let last_used = std::iter::once(type_name.as_str())
+                    .chain(interfaces.iter().map(|interface| interface.as_str()))
+                    .filter_map(|ty| usage.last_used.get(&format!("{ty}.{}", field.name)))
+                    .max()
+                    .copied();
+                if last_used.is_some_and(|last_used| last_used >= window_start) {
+                    return None;
+                }
+                Some(UnusedField {
+                    field: field.name.to_string(),
+                    last_used: last_used.map(format_seconds),
+                })
+            })
+            .collect::<Vec<_>>();
+        if !unused.is_empty() {
+            types.insert(type_name.to_string(), unused);
+        }
+    }
+    Report {
+        window_start: format_seconds(window_start),
+        recording_since: format_seconds(usage.since),
+        complete: usage.since <= window_start,
+        types,
+    }
+}
+
+struct UsedFields<'a> {
+    schema: &'a Schema,
+    coordinates: BTreeSet<String>,
+}
+
+impl<'a> traverse::Visitor for UsedFields<'a> {
+    fn schema(&self) -> &apollo_compiler::Schema {
+        self.schema
+    }
+
+    fn field(
+        &mut self,
+        parent_type: &str,
+        field_def: &ast::FieldDefinition,
+        node: &executable::Field,
+    ) -> Result<(), BoxError> {
+        if !field_def.name.starts_with("__") {
+            self.coordinates
+                .insert(format!("{parent_type}.{}", field_def.name));
+        }
+        traverse::field(self, field_def, node)
+    }
+}
+
+/// Returns the schema coordinates of the fields selected by an operation
+fn used_fields(
+    schema: &Schema,
+    document: &executable::ExecutableDocument,
+    operation_name: Option<&str>,
+) -> Result<BTreeSet<String>, BoxError> {
+    let mut visitor = UsedFields {
+        schema,
+        coordinates: BTreeSet::new(),
+    };
+    traverse::document(&mut visitor, document, operation_name)?;
+    Ok(visitor.coordinates)
+}
+
+struct FieldUsage {
+    config: FieldUsageConfig,
+    schema: Arc<Valid<Schema>>,
+    _drop_signal: Option<oneshot::Sender<()>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for FieldUsage {
+    type Config = FieldUsageConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let config = init.config;
+        let mut drop_signal = None;
+        if config.enabled {
+            {
+                let mut usage = USAGE.lock().expect("lock poisoned");
+                if usage.is_none() {
+                    let loaded = config
+                        .state_path
+                        .as_deref()
+                        .filter(|path| path.exists())
+                        .map(Usage::load)
+                        .transpose()
+                        .map_err(|e| format!("could not load the field usage state file: {e}"))?;
+                    *usage =
+                        Some(loaded.unwrap_or_else(|| Usage::new(unix_seconds(SystemTime::now()))));
+                }
+            }
+            if let Some(state_path) = config.state_path.clone() {
+                let (sender, receiver) = oneshot::channel();
+                tokio::task::spawn(save_periodically(state_path, receiver));
+                drop_signal = Some(sender);
+            }
+        }
+        Ok(Self {
+            config,
+            schema: init.supergraph_schema.clone(),
+            _drop_signal: drop_signal,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let schema = self.schema.clone();
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                let parsed_doc = request
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<ParsedDocument>().cloned());
+                let Some(parsed_doc) = parsed_doc else {
+                    return request;
+                };
+                let operation_name = request.supergraph_request.body().operation_name.as_deref();
+                match used_fields(&schema, &parsed_doc.executable, operation_name) {
+                    Ok(coordinates) => {
+                        if let Some(usage) = USAGE.lock().expect("lock poisoned").as_mut() {
+                            usage.record(coordinates, unix_seconds(SystemTime::now()));
+                        }
+                    }
+                    Err(error) => {
+                        tracing::debug!(
+                            "could not record the fields used by the operation: {error}"
+                        );
+                    }
+                }
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if let (true, Some(endpoint_config)) = (self.config.enabled, &self.config.endpoint) {
+            let endpoint = Endpoint::from_router_service(
+                endpoint_config.path.clone(),
+                FieldUsageService {
+                    schema: self.schema.clone(),
+                    window: self.config.window,
+                    shared_key: endpoint_config.shared_key.clone(),
+                }
+                .boxed(),
+            );
+            tracing::info!(
+                "Field usage report endpoint listening on: {}{}",
+                endpoint_config.listen,
+                endpoint_config.path
+            );
+            map.insert(endpoint_config.listen.clone(), endpoint);
+        }
+        map
+    }
+}
+
+/// Saves the usage to the state file every minute, until the plugin is dropped
+async fn save_periodically(state_path: PathBuf, mut drop_receiver: oneshot::Receiver<()>) {
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+    // the first tick completes immediately, and the usage was just loaded
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = &mut drop_receiver => return,
+            _ = interval.tick() => {}
+        }
+        let usage = USAGE.lock().expect("lock poisoned").clone();
+        if let Some(usage) = usage {
+            let state_path = state_path.clone();
+            let saved = tokio::task::spawn_blocking(move || usage.save(&state_path)).await;
+            if let Ok(Err(error)) = saved {
+                tracing::error!("could not save the field usage state file: {error}");
+            }
+        }
+    }
+}
+
+/// Serves the report of the unused fields with `GET`
+#[derive(Clone)]
+struct FieldUsageService {
+    schema: Arc<Valid<Schema>>,
+    window: Duration,
+    shared_key: String,
+}
+
+fn response(
+    status: StatusCode,
+    body: String,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .map_err(BoxError::from)?,
+        context,
+    })
+}
+
+impl Service<router::Request> for FieldUsageService {
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, req: router::Request) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            let parts = req.router_request.into_parts().0;
+            if !has_shared_key(&parts.headers, &service.shared_key) {
+                return response(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid authorization header".to_string(),
+                    req.context,
+                );
+            }
+            if parts.method != Method::GET {
+                return response(StatusCode::METHOD_NOT_ALLOWED, String::new(), req.context);
+            }
+
+            let usage = USAGE
+                .lock()
+                .expect("lock poisoned")
+                .clone()
+                .unwrap_or_default();
+            let report = report(&service.schema, &usage, service.window, SystemTime::now());
+            response(StatusCode::OK, serde_json::to_string(&report)?, req.context)
+        })
+    }
+}
+
+/// Prints the report of the unused fields of a schema, from a field usage state file
+pub(crate) fn run(
+    supergraph_path: &Path,
+    state_path: &Path,
+    window: Duration,
+) -> Result<(), BoxError> {
+    let sdl = std::fs::read_to_string(supergraph_path)?;
+    let schema = Schema::parse_and_validate(sdl, supergraph_path)
+        .map_err(|e| format!("could not parse the supergraph schema: {}", e.errors))?;
+    let usage = Usage::load(state_path)
+        .map_err(|e| format!("could not load the field usage state file: {e}"))?;
+    let report = report(&schema, &usage, window, SystemTime::now());
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            products(first: Int): [Product]
+            me: User
+        }
+
+        interface Node {
+            id: ID!
+        }
+
+        type Product implements Node {
+            id: ID!
+            name: String
+            price: Float
+            weight: Float
+            secret: String @inaccessible
+        }
+
+        type User {
+            name: String
+        }
+
+        directive @inaccessible on FIELD_DEFINITION | OBJECT | INTERFACE
+    "#;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn schema() -> Valid<Schema> {
+        Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap()
+    }
+
+    fn record(schema: &Schema, usage: &mut Usage, query: &str, now: u64) {
+        let document =
+            executable::ExecutableDocument::parse_and_validate(schema, query, "query.graphql")
+                .unwrap();
+        usage.record(used_fields(schema, &document, None).unwrap(), now);
+    }
+
+    #[test]
+    fn fields_used_by_operations() {
+        let schema = schema();
+        let document = executable::ExecutableDocument::parse_and_validate(
+            &schema,
+            "{ products { __typename ... on Node { id } ...Name } } fragment Name on Product { name }",
+            "query.graphql",
+        )
+        .unwrap();
+        assert_eq!(
+            used_fields(&schema, &document, None).unwrap(),
+            BTreeSet::from([
+                "Node.id".to_string(),
+                "Product.name".to_string(),
+                "Query.products".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn unused_fields_are_reported() {
+        let schema = schema();
+        let now = 100 * DAY;
+        let mut usage = Usage::new(10 * DAY);
+        record(&schema, &mut usage, "{ products { weight } }", 20 * DAY);
+        record(
+            &schema,
+            &mut usage,
+            "{ products { ... on Node { id } name } }",
+            90 * DAY,
+        );
+
+        let report = report(
+            &schema,
+            &usage,
+            Duration::from_secs(30 * DAY),
+            UNIX_EPOCH + Duration::from_secs(now),
+        );
+        assert!(report.complete);
+        assert_eq!(report.window_start, format_seconds(70 * DAY));
+        assert_eq!(
+            report.types,
+            BTreeMap::from([
+                (
+                    "Product".to_string(),
+                    vec![
+                        UnusedField {
+                            field: "price".to_string(),
+                            last_used: None,
+                        },
+                        UnusedField {
+                            field: "weight".to_string(),
+                            last_used: Some(format_seconds(20 * DAY)),
+                        },
+                    ]
+                ),
+                (
+                    "Query".to_string(),
+                    vec![UnusedField {
+                        field: "me".to_string(),
+                        last_used: None,
+                    }]
+                ),
+                (
+                    "User".to_string(),
+                    vec![UnusedField {
+                        field: "name".to_string(),
+                        last_used: None,
+                    }]
+                ),
+            ])
+        );
+
+        let report = report(
+            &schema,
+            &usage,
+            Duration::from_secs(95 * DAY),
+            UNIX_EPOCH + Duration::from_secs(now),
+        );
+        assert!(!report.complete);
+    }
+
+    #[test]
+    fn usage_is_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("field_usage.json");
+        let mut usage = Usage::new(10);
+        usage.record(BTreeSet::from(["Query.me".to_string()]), 20);
+        usage.save(&path).unwrap();
+        assert_eq!(Usage::load(&path).unwrap(), usage);
+    }
+}
@@ -23,20 +23,46 @@ macro_rules! schemar_fn {
 pub(crate) mod authentication;
 pub(crate) mod authorization;
 pub(crate) mod cache;
+mod client_compatibility;
+pub(crate) mod context_arguments;
 mod coprocessor;
 pub(crate) mod csrf;
+pub(crate) mod data_residency;
 mod demand_control;
+mod deprecations;
+pub(crate) mod error_aggregation;
+pub(crate) mod error_messages;
 mod expose_query_plan;
+pub(crate) mod feature_flags;
+pub(crate) mod fetch_details;
+mod field_encryption;
+pub(crate) mod field_latency;
+pub(crate) mod field_usage;
 pub(crate) mod file_uploads;
 mod forbid_mutations;
 mod headers;
 mod include_subgraph_errors;
+pub(crate) mod null_provenance;
 pub(crate) mod override_url;
+mod preflight;
+mod profiling;
 pub(crate) mod progressive_override;
+mod query_plan_summary;
 mod record_replay;
+mod request_id;
+mod response_validation;
 pub(crate) mod rhai;
+mod slow_operations;
+mod static_responses;
+mod subgraph_capabilities;
+mod subgraph_failover;
+mod subgraph_transforms;
 pub(crate) mod subscription;
+pub(crate) mod subscription_coordination;
+mod subscription_quotas;
 pub(crate) mod telemetry;
 #[cfg(test)]
 pub(crate) mod test;
 pub(crate) mod traffic_shaping;
+mod unknown_typenames;
+mod wide_events;
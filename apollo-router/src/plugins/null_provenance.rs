@@ -0,0 +1,75 @@
+//! Debugging of null propagation.
+//!
+//! A null or error on a non-null field nulls its nearest nullable parent, which can be far above
+//! it in a deep federated query. For requests asking for it with a header, this plugin makes the
+//! formatting of the response record which position each null cascade originated from, with the
+//! subgraph errors found there, in the `apolloNullProvenance` response extension.
+
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "null_provenance", NullProvenance);
+
+const NULL_PROVENANCE_HEADER_NAME: &str = "apollo-null-provenance";
+
+/// Tracing of null propagation in responses
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct NullProvenanceConfig {
+    /// Add the `apolloNullProvenance` extension to responses to requests with the
+    /// `apollo-null-provenance: true` header
+    enabled: bool,
+}
+
+/// Set in the context extensions when the null provenance of the response is recorded
+#[derive(Clone, Debug)]
+pub(crate) struct RecordNullProvenance;
+
+struct NullProvenance {
+    config: NullProvenanceConfig,
+}
+
+#[async_trait::async_trait]
+impl Plugin for NullProvenance {
+    type Config = NullProvenanceConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            config: init.config,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .map_request(|request: supergraph::Request| {
+                if request
+                    .supergraph_request
+                    .headers()
+                    .get(NULL_PROVENANCE_HEADER_NAME)
+                    == Some(&HeaderValue::from_static("true"))
+                {
+                    request
+                        .context
+                        .extensions()
+                        .with_lock(|mut lock| lock.insert(RecordNullProvenance));
+                }
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
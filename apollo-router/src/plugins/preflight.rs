@@ -0,0 +1,162 @@
+//! Pre-flight checks of operations.
+//!
+//! A request with the `Apollo-Preflight: true` header is planned but not executed. Its response
+//! only has the estimated demand control cost, the operation limits metrics and a summary of the
+//! query plan, so that clients can check new operations against the configuration of the router.
+
+use std::collections::BTreeSet;
+use std::ops::ControlFlow;
+
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::json;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::demand_control::CostContext;
+use crate::register_plugin;
+use crate::services::execution;
+use crate::spec::operation_limits::OperationLimits;
+
+const PREFLIGHT_HEADER_NAME: &str = "Apollo-Preflight";
+const PREFLIGHT_EXTENSION: &str = "apolloPreflight";
+
+/// Pre-flight checks of operations, requested with the `Apollo-Preflight: true` header
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct PreflightConfig {
+    /// Answer the requests with the `Apollo-Preflight: true` header with the estimated cost,
+    /// limits metrics and query plan summary of their operation, without executing it
+    enabled: bool,
+}
+
+struct Preflight {
+    enabled: bool,
+}
+
+#[async_trait::async_trait]
+impl Plugin for Preflight {
+    type Config = PreflightConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            enabled: init.config.enabled,
+        })
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        if !self.enabled {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .checkpoint(|request: execution::Request| {
+                if request
+                    .supergraph_request
+                    .headers()
+                    .get(PREFLIGHT_HEADER_NAME)
+                    != Some(&HeaderValue::from_static("true"))
+                {
+                    return Ok(ControlFlow::Continue(request));
+                }
+
+                let (cost, limits) = request.context.extensions().with_lock(|lock| {
+                    (
+                        lock.get::<CostContext>().cloned(),
+                        lock.get::<OperationLimits<u32>>().cloned(),
+                    )
+                });
+                let root = &request.query_plan.root;
+                let subgraphs = root.service_usage().collect::<BTreeSet<_>>();
+                let preflight = json!({
+                    "cost": cost.map(|cost| json!({
+                        "estimated": cost.estimated,
+                        "result": cost.result,
+                        "strategy": cost.strategy,
+                    })),
+                    "limits": limits.map(|limits| json!({
+                        "depth": limits.depth,
+                        "height": limits.height,
+                        "rootFields": limits.root_fields,
+                        "aliases": limits.aliases,
+                    })),
+                    "queryPlan": {
+                        "subgraphFetches": root.subgraph_fetches(),
+                        "subgraphs": subgraphs.into_iter().collect::<Vec<_>>(),
+                    },
+                });
+
+                Ok(ControlFlow::Break(
+                    execution::Response::builder()
+                        .extension(PREFLIGHT_EXTENSION, preflight)
+                        .context(request.context)
+                        .build()?,
+                ))
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+register_plugin!("apollo", "preflight", Preflight);
+
+#[cfg(test)]
+mod tests {
+    use tower::Service;
+
+    use super::*;
+    use crate::services::supergraph;
+
+    #[tokio::test]
+    async fn preflight_does_not_execute() {
+        let mut service = crate::TestHarness::builder()
+            .schema(include_str!(
+                "../../../apollo-router-benchmarks/benches/fixtures/supergraph.graphql"
+            ))
+            .configuration_json(serde_json::json!({
+                "preflight": { "enabled": true },
+                "preview_demand_control": {
+                    "enabled": true,
+                    "mode": "measure",
+                    "strategy": { "static_estimated": { "list_size": 10, "max": 50.0 } }
+                }
+            }))
+            .unwrap()
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .query("{ topProducts { upc reviews { id author { name } } } }")
+            .header(PREFLIGHT_HEADER_NAME, "true")
+            .build()
+            .unwrap();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+
+        assert!(response.data.is_none());
+        assert!(response.errors.is_empty());
+        let preflight = response.extensions.get(PREFLIGHT_EXTENSION).unwrap();
+        assert_eq!(preflight["limits"]["depth"], 4);
+        assert_eq!(preflight["cost"]["result"], "COST_ESTIMATED_TOO_EXPENSIVE");
+        assert_eq!(preflight["queryPlan"]["subgraphFetches"], 3);
+        assert_eq!(
+            preflight["queryPlan"]["subgraphs"],
+            json!(["accounts", "products", "reviews"])
+        );
+    }
+}
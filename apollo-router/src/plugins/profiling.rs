@@ -0,0 +1,325 @@
+//! On-demand CPU and heap profiles, in the pprof format.
+//!
+//! With the `profiling` Cargo feature, on Linux, the router serves CPU profiles sampled for the
+//! requested duration, and snapshots of the jemalloc heap profile, so a production instance can be
+//! profiled without deploying a special build. The endpoints require a shared key, and are only
+//! served on their own listen address.
+
+use std::net::SocketAddr;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use http::header::CONTENT_DISPOSITION;
+use http::header::CONTENT_TYPE;
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::Service;
+use tower::ServiceExt;
+
+use crate::http_ext::has_shared_key;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::router_factory::Endpoint;
+use crate::services::router;
+use crate::ListenAddr;
+
+register_plugin!("apollo", "profiling", Profiling);
+
+/// Sampling frequency of the CPU profiles, off the round numbers of periodic tasks
+#[cfg(all(feature = "profiling", target_os = "linux"))]
+const CPU_SAMPLING_FREQUENCY: i32 = 99;
+
+/// On-demand CPU and heap profiles, in the pprof format
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct ProfilingConfig {
+    /// Serve the profiling endpoints. Requires a router built with the `profiling` feature, on
+    /// Linux
+    enabled: bool,
+    /// The socket address and port to listen on
+    listen: ListenAddr,
+    /// Path prefix of the endpoints: `<path>/profile` serves CPU profiles, and `<path>/heap`
+    /// heap snapshots
+    path: String,
+    /// Shared key expected in the `Authorization` header of the requests
+    shared_key: String,
+    /// Longest CPU profile, in human-readable format; defaults to 60s
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    max_duration: Duration,
+}
+
+impl Default for ProfilingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: SocketAddr::from(([127, 0, 0, 1], 8089)).into(),
+            path: "/debug/pprof".to_string(),
+            shared_key: String::new(),
+            max_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Query parameters of the CPU profile endpoint
+#[derive(Deserialize)]
+struct ProfileParameters {
+    /// Duration of the profile, in seconds; defaults to 30
+    seconds: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ProfileKind {
+    Cpu,
+    Heap,
+}
+
+#[cfg(all(feature = "profiling", target_os = "linux"))]
+async fn cpu_profile(duration: Duration) -> Result<Vec<u8>, BoxError> {
+    use pprof::protos::Message;
+
+    // the profiler samples the threads of the process until the guard is dropped, so it's kept
+    // on a blocking thread rather than held across await points
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>, BoxError> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(CPU_SAMPLING_FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()?;
+        std::thread::sleep(duration);
+        let profile = guard.report().build()?.pprof()?;
+        Ok(profile.encode_to_vec())
+    })
+    .await?
+}
+
+#[cfg(not(all(feature = "profiling", target_os = "linux")))]
+async fn cpu_profile(_duration: Duration) -> Result<Vec<u8>, BoxError> {
+    Err("CPU profiles require a router built with the `profiling` feature, on Linux".into())
+}
+
+#[cfg(all(feature = "profiling", target_os = "linux"))]
+async fn heap_profile() -> Result<Vec<u8>, BoxError> {
+    let Some(prof_ctl) = jemalloc_pprof::PROF_CTL.as_ref() else {
+        return Err("heap snapshots require the jemalloc allocator with profiling enabled".into());
+    };
+    let mut prof_ctl = prof_ctl.lock().await;
+    if !prof_ctl.activated() {
+        return Err("the jemalloc heap profiling is not active".into());
+    }
+    Ok(prof_ctl.dump_pprof()?)
+}
+
+#[cfg(not(all(feature = "profiling", target_os = "linux")))]
+async fn heap_profile() -> Result<Vec<u8>, BoxError> {
+    Err("heap snapshots require a router built with the `profiling` feature, on Linux".into())
+}
+
+/// Serves a profile with `GET`
+#[derive(Clone)]
+struct ProfilingService {
+    kind: ProfileKind,
+    shared_key: String,
+    max_duration: Duration,
+}
+
+fn response(
+    status: StatusCode,
+    body: impl Into<router::Body>,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .body(body.into())
+            .map_err(BoxError::from)?,
+        context,
+    })
+}
+
+impl Service<router::Request> for ProfilingService {
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, req: router::Request) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            if !has_shared_key(req.router_request.headers(), &service.shared_key) {
+                return response(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid authorization header",
+                    req.context,
+                );
+            }
+            if req.router_request.method() != Method::GET {
+                return response(StatusCode::METHOD_NOT_ALLOWED, "", req.context);
+            }
+
+            let profile = match service.kind {
+                ProfileKind::Cpu => {
+                    let parameters: ProfileParameters = match serde_urlencoded::from_str(
+                        req.router_request.uri().query().unwrap_or_default(),
+                    ) {
+                        Ok(parameters) => parameters,
+                        Err(e) => {
+                            return response(
+                                StatusCode::BAD_REQUEST,
+                                format!("invalid query parameters: {e}"),
+                                req.context,
+                            )
+                        }
+                    };
+                    let duration = Duration::from_secs(parameters.seconds.unwrap_or(30));
+                    if duration.is_zero() || duration > service.max_duration {
+                        return response(
+                            StatusCode::BAD_REQUEST,
+                            format!(
+                                "the profile duration must be between 1s and {}",
+                                humantime::format_duration(service.max_duration)
+                            ),
+                            req.context,
+                        );
+                    }
+                    tracing::info!("collecting a CPU profile for {}s", duration.as_secs());
+                    cpu_profile(duration).await
+                }
+                ProfileKind::Heap => heap_profile().await,
+            };
+
+            match profile {
+                Ok(profile) => Ok(router::Response {
+                    response: http::Response::builder()
+                        .status(StatusCode::OK)
+                        .header(CONTENT_TYPE, "application/octet-stream")
+                        .header(
+                            CONTENT_DISPOSITION,
+                            match service.kind {
+                                ProfileKind::Cpu => "attachment; filename=\"profile.pb\"",
+                                ProfileKind::Heap => "attachment; filename=\"heap.pb.gz\"",
+                            },
+                        )
+                        .body(profile.into())
+                        .map_err(BoxError::from)?,
+                    context: req.context,
+                }),
+                Err(e) => response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    e.to_string(),
+                    req.context,
+                ),
+            }
+        })
+    }
+}
+
+struct Profiling {
+    config: ProfilingConfig,
+}
+
+#[async_trait::async_trait]
+impl Plugin for Profiling {
+    type Config = ProfilingConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        if init.config.enabled {
+            if !cfg!(all(feature = "profiling", target_os = "linux")) {
+                return Err(
+                    "the profiling endpoints require a router built with the `profiling` feature, on Linux"
+                        .into(),
+                );
+            }
+            if init.config.shared_key.is_empty() {
+                return Err("the profiling endpoints require a shared key".into());
+            }
+        }
+        Ok(Self {
+            config: init.config,
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if !self.config.enabled {
+            return map;
+        }
+
+        let path = self.config.path.trim_end_matches('/');
+        for (kind, suffix) in [(ProfileKind::Cpu, "profile"), (ProfileKind::Heap, "heap")] {
+            let endpoint = Endpoint::from_router_service(
+                format!("{path}/{suffix}"),
+                ProfilingService {
+                    kind,
+                    shared_key: self.config.shared_key.clone(),
+                    max_duration: self.config.max_duration,
+                }
+                .boxed(),
+            );
+            map.insert(self.config.listen.clone(), endpoint);
+        }
+        tracing::info!(
+            "Profiling endpoints listening on: {}{}",
+            self.config.listen,
+            path
+        );
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::AUTHORIZATION;
+
+    use super::*;
+
+    fn service(kind: ProfileKind) -> ProfilingService {
+        ProfilingService {
+            kind,
+            shared_key: "key".to_string(),
+            max_duration: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn profiles_require_the_shared_key() {
+        for authorization in [None, Some("other")] {
+            let mut request = router::Request::fake_builder().method(Method::GET);
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            let response = service(ProfileKind::Heap)
+                .oneshot(request.build().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn profile_durations_are_bounded() {
+        for query in ["seconds=0", "seconds=61", "seconds=ten"] {
+            let request = router::Request::fake_builder()
+                .method(Method::GET)
+                .uri(
+                    format!("http://127.0.0.1:8089/debug/pprof/profile?{query}")
+                        .parse::<http::Uri>()
+                        .unwrap(),
+                )
+                .header(AUTHORIZATION, "key")
+                .build()
+                .unwrap();
+            let response = service(ProfileKind::Cpu).oneshot(request).await.unwrap();
+            assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -6,6 +6,7 @@ use apollo_compiler::schema::ExtendedType;
 use apollo_compiler::validation::Valid;
 use apollo_compiler::Schema;
 use dashmap::DashMap;
+use multimap::MultiMap;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use sha2::Digest;
@@ -15,6 +16,9 @@ use tower::ServiceBuilder;
 use tower::ServiceExt;
 
 use self::layers::query_analysis::ParsedDocument;
+use self::rollout::Rollout;
+use self::rollout::RolloutEndpointConfig;
+use self::rollout::RolloutService;
 use self::visitor::OverrideLabelVisitor;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
@@ -22,8 +26,12 @@ use crate::register_plugin;
 use crate::services::*;
 use crate::spec;
 use crate::spec::query::traverse;
+use crate::Endpoint;
+use crate::ListenAddr;
 
+pub(crate) mod rollout;
 pub(crate) mod visitor;
+pub(crate) const APOLLO_PROGRESSIVE_OVERRIDE: &str = "apollo.progressive_override";
 pub(crate) const UNRESOLVED_LABELS_KEY: &str = "apollo_override::unresolved_labels";
 pub(crate) const LABELS_TO_OVERRIDE_KEY: &str = "apollo_override::labels_to_override";
 
@@ -34,12 +42,21 @@ pub(crate) const OVERRIDE_LABEL_ARG_NAME: &str = "overrideLabel";
 
 /// Configuration for the progressive override plugin
 #[derive(Debug, Default, Deserialize, JsonSchema)]
-pub(crate) struct Config {}
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Config {
+    /// Rollout percentages of override labels, between 0 and 100, taking precedence over the
+    /// percentages of `percent(x)` labels in the schema
+    rollout: HashMap<String, f64>,
+    /// Endpoint reading and updating the rollout percentages while the router runs
+    rollout_endpoint: Option<RolloutEndpointConfig>,
+}
 
 pub(crate) struct ProgressiveOverridePlugin {
     enabled: bool,
     schema: Arc<Valid<Schema>>,
     labels_from_schema: LabelsFromSchema,
+    rollout: Arc<Rollout>,
+    rollout_endpoint: Option<RolloutEndpointConfig>,
     // We have to visit each operation to find out which labels from the schema
     // are relevant for any given operation. This allows us to minimize the
     // number of labels we ultimately send to the query planner. Since these
@@ -129,10 +146,17 @@ impl Plugin for ProgressiveOverridePlugin {
         let schema = init.supergraph_schema.clone();
         let labels_from_schema = collect_labels_from_schema(&schema);
         let enabled = !labels_from_schema.0.is_empty() || !labels_from_schema.1.is_empty();
+        let rollout = Rollout::new(
+            &labels_from_schema.0,
+            &labels_from_schema.1,
+            &init.config.rollout,
+        )?;
         Ok(ProgressiveOverridePlugin {
             enabled,
             schema,
             labels_from_schema,
+            rollout: Arc::new(rollout),
+            rollout_endpoint: init.config.rollout_endpoint,
             // we have to visit each operation to find out which labels from the schema are relevant.
             labels_per_operation_cache: Arc::new(DashMap::new()),
         })
@@ -158,8 +182,8 @@ impl Plugin for ProgressiveOverridePlugin {
     }
 
     // Here we'll do a few things:
-    // 1. "Roll the dice" for all of our percentage-based labels and collect the
-    //    subset that will be enabled for this request
+    // 1. "Roll the dice" for all of our labels with a rollout percentage and
+    //    collect the subset that will be enabled for this request
     // 2. Collect any externally-resolved labels from the context
     // 3. Filter the set of labels to only those that are relevant to the
     //    operation
@@ -169,21 +193,15 @@ impl Plugin for ProgressiveOverridePlugin {
         if !self.enabled {
             service
         } else {
-            let (percentage_labels, _) = self.labels_from_schema.clone();
+            let rollout = self.rollout.clone();
             let labels_per_operation_cache = self.labels_per_operation_cache.clone();
 
             let schema = self.schema.clone();
             ServiceBuilder::new()
             .map_request(move |request: supergraph::Request| {
-                // evaluate each percentage-based label in the schema
-                let percentage_override_labels =
-                    percentage_labels.iter().filter_map(|(label, percentage)| {
-                        if rand::random::<f64>() * 100.0 >= **percentage {
-                            None
-                        } else {
-                            Some(label.clone())
-                        }
-                    });
+                // evaluate each label with a rollout percentage, from the
+                // schema, the configuration or the rollout endpoint
+                let percentage_override_labels = rollout.enabled_labels();
 
                 // collect any externally-resolved labels from the context
                 let externally_overridden_labels = request
@@ -233,6 +251,7 @@ impl Plugin for ProgressiveOverridePlugin {
                     // external) and the labels relevant to this operation is
                     // the set of labels we'll send to the query planner
                     let mut overridden_labels_for_operation = percentage_override_labels
+                        .into_iter()
                         .chain(externally_overridden_labels)
                         .filter(|l| relevant_labels.contains(l))
                         .collect::<Vec<_>>();
@@ -257,6 +276,24 @@ impl Plugin for ProgressiveOverridePlugin {
             .boxed()
         }
     }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if let (true, Some(endpoint_config)) = (self.enabled, &self.rollout_endpoint) {
+            let endpoint = Endpoint::from_router_service(
+                endpoint_config.path.clone(),
+                RolloutService::new(self.rollout.clone(), endpoint_config.shared_key.clone())
+                    .boxed(),
+            );
+            tracing::info!(
+                "Progressive override rollout endpoint listening on: {}{}",
+                endpoint_config.listen,
+                endpoint_config.path
+            );
+            map.insert(endpoint_config.listen.clone(), endpoint);
+        }
+        map
+    }
 }
 
 fn hash_operation(operation: &Option<String>, operation_name: &Option<String>) -> String {
@@ -272,5 +309,18 @@ fn hash_operation(operation: &Option<String>, operation_name: &Option<String>) -
 
 register_plugin!("apollo", "progressive_override", ProgressiveOverridePlugin);
 
+/// Hands the rollout percentages set with the endpoint over to the plugin of a new router.
+pub(crate) fn inherit_rollout(previous: &Plugins, plugins: &Plugins) {
+    let plugin = |plugins: &Plugins| {
+        plugins
+            .get(APOLLO_PROGRESSIVE_OVERRIDE)
+            .and_then(|plugin| plugin.as_any().downcast_ref::<ProgressiveOverridePlugin>())
+            .map(|plugin| plugin.rollout.clone())
+    };
+    if let (Some(previous), Some(rollout)) = (plugin(previous), plugin(plugins)) {
+        rollout.inherit(&previous);
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,286 @@
+//! Rollout percentages of override labels, controlled while the router runs.
+//!
+//! The percentage of a `percent(x)` label comes from the supergraph schema, so ramping a field
+//! migration used to require a new composition for each step. The configuration can set the
+//! percentage of any label of the schema, and the rollout endpoint can change it at runtime.
+//! Percentages set with the endpoint are handed over to the plugin created by a schema or
+//! configuration reload.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+
+use arc_swap::ArcSwap;
+use bytes::Buf;
+use futures::future::BoxFuture;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::Service;
+
+use crate::http_ext::has_shared_key;
+use crate::services::router;
+use crate::services::router::body::RouterBody;
+use crate::ListenAddr;
+
+/// Endpoint reading and updating the rollout percentages of override labels
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RolloutEndpointConfig {
+    /// Listen address of the endpoint
+    pub(crate) listen: ListenAddr,
+    /// Path of the endpoint
+    pub(crate) path: String,
+    /// Shared key expected in the `Authorization` header of the requests
+    pub(crate) shared_key: String,
+}
+
+/// Where the percentage of a label comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Source {
+    Schema,
+    Configuration,
+    Endpoint,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+struct Percentage {
+    percentage: f64,
+    source: Source,
+}
+
+/// The rollout percentages of the override labels of a schema
+pub(crate) struct Rollout {
+    /// Labels with a percentage in the schema
+    schema: HashMap<Arc<String>, f64>,
+    /// Other labels of the schema, which only have a percentage if one is set
+    other_labels: HashSet<Arc<String>>,
+    configured: HashMap<String, f64>,
+    /// Percentages set with the rollout endpoint, by label
+    endpoint: Mutex<HashMap<String, f64>>,
+    percentages: ArcSwap<HashMap<Arc<String>, Percentage>>,
+}
+
+fn check_percentage(label: &str, percentage: f64) -> Result<(), String> {
+    if (0.0..=100.0).contains(&percentage) {
+        Ok(())
+    } else {
+        Err(format!(
+            "the rollout percentage of the {label} label must be between 0 and 100"
+        ))
+    }
+}
+
+impl Rollout {
+    pub(crate) fn new(
+        schema: &HashMap<Arc<String>, Arc<f64>>,
+        other_labels: &HashSet<Arc<String>>,
+        configured: &HashMap<String, f64>,
+    ) -> Result<Self, BoxError> {
+        for (label, percentage) in configured {
+            check_percentage(label, *percentage)?;
+        }
+        let rollout = Self {
+            schema: schema
+                .iter()
+                .map(|(label, percentage)| (label.clone(), **percentage))
+                .collect(),
+            other_labels: other_labels.clone(),
+            configured: configured.clone(),
+            endpoint: Default::default(),
+            percentages: Default::default(),
+        };
+        for label in configured.keys() {
+            if rollout.label(label).is_none() {
+                tracing::warn!(
+                    "the {label} override label has a rollout percentage but is not used in the schema"
+                );
+            }
+        }
+        rollout.refresh();
+        Ok(rollout)
+    }
+
+    /// The label of the schema with this name
+    fn label(&self, name: &str) -> Option<Arc<String>> {
+        self.schema
+            .get_key_value(&name.to_string())
+            .map(|(label, _)| label)
+            .or_else(|| self.other_labels.get(&name.to_string()))
+            .cloned()
+    }
+
+    fn refresh(&self) {
+        let mut percentages: HashMap<Arc<String>, Percentage> = self
+            .schema
+            .iter()
+            .map(|(label, percentage)| {
+                (
+                    label.clone(),
+                    Percentage {
+                        percentage: *percentage,
+                        source: Source::Schema,
+                    },
+                )
+            })
+            .collect();
+        let endpoint = self.endpoint.lock().expect("lock poisoned").clone();
+        for (overrides, source) in [
+            (&self.configured, Source::Configuration),
+            (&endpoint, Source::Endpoint),
+        ] {
+            for (name, percentage) in overrides {
+                if let Some(label) = self.label(name) {
+                    percentages.insert(
+                        label,
+                        Percentage {
+                            percentage: *percentage,
+                            source,
+                        },
+                    );
+                }
+            }
+        }
+        self.percentages.store(Arc::new(percentages));
+    }
+
+    /// Rolls the dice for each label with a percentage, and returns the enabled labels.
+    pub(crate) fn enabled_labels(&self) -> Vec<Arc<String>> {
+        self.percentages
+            .load()
+            .iter()
+            .filter(|(_, percentage)| rand::random::<f64>() * 100.0 < percentage.percentage)
+            .map(|(label, _)| label.clone())
+            .collect()
+    }
+
+    /// Sets or removes, with a `null` percentage, the percentages of labels.
+    fn update(&self, changes: HashMap<String, Option<f64>>) -> Result<(), String> {
+        for (name, percentage) in &changes {
+            if self.label(name).is_none() {
+                return Err(format!(
+                    "the {name} override label is not used in the schema"
+                ));
+            }
+            if let Some(percentage) = percentage {
+                check_percentage(name, *percentage)?;
+            }
+        }
+        {
+            let mut endpoint = self.endpoint.lock().expect("lock poisoned");
+            for (name, percentage) in changes {
+                tracing::info!(
+                    "rollout percentage of the {name} override label set to {percentage:?}"
+                );
+                match percentage {
+                    Some(percentage) => endpoint.insert(name, percentage),
+                    None => endpoint.remove(&name),
+                };
+            }
+        }
+        self.refresh();
+        Ok(())
+    }
+
+    /// Takes over the percentages set with the endpoint of the rollout of the previous router.
+    pub(crate) fn inherit(&self, previous: &Rollout) {
+        let endpoint = previous.endpoint.lock().expect("lock poisoned").clone();
+        *self.endpoint.lock().expect("lock poisoned") = endpoint;
+        self.refresh();
+    }
+
+    fn to_json(&self) -> Result<String, serde_json::Error> {
+        let percentages = self.percentages.load();
+        let sorted: BTreeMap<&str, &Percentage> = percentages
+            .iter()
+            .map(|(label, percentage)| (label.as_str(), percentage))
+            .collect();
+        serde_json::to_string(&sorted)
+    }
+}
+
+/// Serves the rollout percentages with `GET`, and updates them with `PATCH`
+#[derive(Clone)]
+pub(crate) struct RolloutService {
+    rollout: Arc<Rollout>,
+    shared_key: String,
+}
+
+impl RolloutService {
+    pub(crate) fn new(rollout: Arc<Rollout>, shared_key: String) -> Self {
+        Self {
+            rollout,
+            shared_key,
+        }
+    }
+}
+
+fn response(
+    status: StatusCode,
+    body: String,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .body(body.into())
+            .map_err(BoxError::from)?,
+        context,
+    })
+}
+
+impl Service<router::Request> for RolloutService {
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, req: router::Request) -> Self::Future {
+        let rollout = self.rollout.clone();
+        let shared_key = self.shared_key.clone();
+        Box::pin(async move {
+            let (parts, body) = req.router_request.into_parts();
+            if !has_shared_key(&parts.headers, &shared_key) {
+                return response(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid authorization header".to_string(),
+                    req.context,
+                );
+            }
+
+            match parts.method {
+                Method::GET => response(StatusCode::OK, rollout.to_json()?, req.context),
+                Method::PATCH => {
+                    let changes = Into::<RouterBody>::into(body)
+                        .to_bytes()
+                        .await
+                        .map_err(|e| format!("failed to get the request body: {e}"))
+                        .and_then(|bytes| {
+                            serde_json::from_reader::<_, HashMap<String, Option<f64>>>(
+                                bytes.reader(),
+                            )
+                            .map_err(|err| {
+                                format!("failed to deserialize the request body into JSON: {err}")
+                            })
+                        })
+                        .and_then(|changes| rollout.update(changes));
+                    match changes {
+                        Ok(()) => response(StatusCode::OK, rollout.to_json()?, req.context),
+                        Err(err) => response(StatusCode::BAD_REQUEST, err, req.context),
+                    }
+                }
+                _ => response(StatusCode::METHOD_NOT_ALLOWED, String::new(), req.context),
+            }
+        })
+    }
+}
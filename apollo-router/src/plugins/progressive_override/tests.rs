@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use apollo_compiler::Schema;
+use tower::BoxError;
 use tower::ServiceExt;
 
 use crate::metrics::FutureMetricsExt;
@@ -8,6 +11,8 @@ use crate::plugin::test::MockRouterService;
 use crate::plugin::test::MockSupergraphService;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::progressive_override::rollout::Rollout;
+use crate::plugins::progressive_override::rollout::RolloutService;
 use crate::plugins::progressive_override::Config;
 use crate::plugins::progressive_override::ProgressiveOverridePlugin;
 use crate::plugins::progressive_override::JOIN_FIELD_DIRECTIVE_NAME;
@@ -72,7 +77,7 @@ fn test_progressive_overrides_are_recognised_vor_join_v0_4_and_above() {
 #[tokio::test]
 async fn plugin_disables_itself_with_no_progressive_override_usages() {
     let plugin = ProgressiveOverridePlugin::new(PluginInit::fake_new(
-        Config {},
+        Config::default(),
         Arc::new(SCHEMA_NO_USAGES.to_string()),
     ))
     .await
@@ -84,7 +89,7 @@ async fn plugin_disables_itself_with_no_progressive_override_usages() {
 #[tokio::test]
 async fn plugin_enables_itself_with_progressive_override_usages() {
     let plugin = ProgressiveOverridePlugin::new(PluginInit::fake_new(
-        Config {},
+        Config::default(),
         Arc::new(SCHEMA.to_string()),
     ))
     .await
@@ -118,7 +123,7 @@ async fn plugin_router_service_adds_all_arbitrary_labels_to_context() {
     });
 
     let service_stack = ProgressiveOverridePlugin::new(PluginInit::fake_new(
-        Config {},
+        Config::default(),
         Arc::new(SCHEMA.to_string()),
     ))
     .await
@@ -173,7 +178,7 @@ async fn assert_expected_and_absent_labels_for_supergraph_service(
     });
 
     let service_stack = ProgressiveOverridePlugin::new(PluginInit::fake_new(
-        Config {},
+        Config::default(),
         Arc::new(SCHEMA.to_string()),
     ))
     .await
@@ -319,7 +324,7 @@ async fn query_with_labels(query: &str, labels_from_coprocessors: Vec<&str>) {
         .returning(|_| SupergraphResponse::fake_builder().build());
 
     let service_stack = ProgressiveOverridePlugin::new(PluginInit::fake_new(
-        Config {},
+        Config::default(),
         Arc::new(SCHEMA.to_string()),
     ))
     .await
@@ -387,3 +392,102 @@ async fn query_with_externally_resolved_labels_metrics() {
     .with_metrics()
     .await;
 }
+
+fn rollout(configured: &[(&str, f64)]) -> Result<Rollout, BoxError> {
+    let schema_percentages = [(Arc::new("percent(0)".to_string()), Arc::new(0.0))]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let other_labels = [Arc::new("foo".to_string()), Arc::new("ramp".to_string())]
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let configured = configured
+        .iter()
+        .map(|(label, percentage)| (label.to_string(), *percentage))
+        .collect();
+    Rollout::new(&schema_percentages, &other_labels, &configured)
+}
+
+#[test]
+fn configured_rollout_overrides_schema_percentages() {
+    let rollout = rollout(&[("percent(0)", 100.0), ("foo", 100.0), ("unknown", 100.0)]).unwrap();
+    let mut enabled = rollout.enabled_labels();
+    enabled.sort();
+    assert_eq!(
+        enabled,
+        vec![
+            Arc::new("foo".to_string()),
+            Arc::new("percent(0)".to_string())
+        ]
+    );
+
+    assert!(self::rollout(&[("foo", 101.0)]).is_err());
+}
+
+#[tokio::test]
+async fn rollout_endpoint_updates_percentages() {
+    let rollout = Arc::new(rollout(&[]).unwrap());
+    let service = RolloutService::new(rollout.clone(), "secret".to_string());
+    let patch = |authorization: &str, body: &str| {
+        router::Request::fake_builder()
+            .method(http::Method::PATCH)
+            .header(http::header::AUTHORIZATION, authorization)
+            .body(body.to_string())
+            .build()
+            .unwrap()
+    };
+
+    let response = service
+        .clone()
+        .oneshot(patch("wrong", r#"{"ramp": 100}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.response.status(), http::StatusCode::UNAUTHORIZED);
+
+    for body in [r#"{"ramp": 150}"#, r#"{"unknown": 100}"#] {
+        let response = service
+            .clone()
+            .oneshot(patch("secret", body))
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), http::StatusCode::BAD_REQUEST);
+    }
+    assert!(rollout.enabled_labels().is_empty());
+
+    let response = service
+        .clone()
+        .oneshot(patch("secret", r#"{"ramp": 100}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.response.status(), http::StatusCode::OK);
+    assert_eq!(rollout.enabled_labels(), vec![Arc::new("ramp".to_string())]);
+
+    let response = service
+        .clone()
+        .oneshot(patch("secret", r#"{"ramp": null}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.response.status(), http::StatusCode::OK);
+    assert!(rollout.enabled_labels().is_empty());
+}
+
+#[tokio::test]
+async fn rollout_endpoint_percentages_are_inherited() {
+    let previous = Arc::new(rollout(&[]).unwrap());
+    let response = RolloutService::new(previous.clone(), "secret".to_string())
+        .oneshot(
+            router::Request::fake_builder()
+                .method(http::Method::PATCH)
+                .header(http::header::AUTHORIZATION, "secret")
+                .body(r#"{"ramp": 100}"#.to_string())
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.response.status(), http::StatusCode::OK);
+
+    let rollout = rollout(&[]).unwrap();
+    assert!(rollout.enabled_labels().is_empty());
+    rollout.inherit(&previous);
+    assert_eq!(rollout.enabled_labels(), vec![Arc::new("ramp".to_string())]);
+}
@@ -0,0 +1,253 @@
+//! Summary of the query plan of the requests, available before their execution.
+//!
+//! Policies like "reject the operations of this client touching the payments subgraph" need to
+//! know what the plan of an operation will fetch, before any fetch is made. Once the operation is
+//! planned, this plugin summarizes the plan in the `apollo_query_plan::summary` context entry,
+//! which the execution stage of Rust plugins, Rhai scripts and coprocessors can read.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+
+use apollo_compiler::executable;
+use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Name;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::fetch::SubgraphOperation;
+use crate::query_planner::PlanNode;
+use crate::query_planner::QueryPlan;
+use crate::register_plugin;
+use crate::services::execution;
+
+register_plugin!("apollo", "query_plan_summary", QueryPlanSummaryPlugin);
+
+pub(crate) const QUERY_PLAN_SUMMARY_KEY: &str = "apollo_query_plan::summary";
+
+/// Summary of the query plan of the operations, in the context of the requests
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct QueryPlanSummaryConfig {
+    /// Add the summary of the query plan to the `apollo_query_plan::summary` context entry, before
+    /// the execution of the operation
+    enabled: bool,
+}
+
+/// What the query plan of an operation fetches
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct QueryPlanSummary {
+    /// Names of the subgraphs fetched by the plan
+    pub(crate) subgraphs: BTreeSet<String>,
+    /// Root fields of the operation fetched from each subgraph. Subgraphs only fetched for
+    /// entities have none
+    pub(crate) root_fields: BTreeMap<String, BTreeSet<String>>,
+    /// Whether the plan fetches mutations
+    pub(crate) contains_mutations: bool,
+    /// Number of fetches of the plan. For `@include` and `@skip` conditions, only the fetches of
+    /// the branch with the most fetches are counted
+    pub(crate) estimated_fetches: usize,
+}
+
+impl QueryPlanSummary {
+    pub(crate) fn new(plan: &QueryPlan) -> Self {
+        let mut summary = Self {
+            subgraphs: plan.root.service_usage().map(str::to_string).collect(),
+            root_fields: BTreeMap::new(),
+            contains_mutations: plan.root.contains_mutations(),
+            estimated_fetches: plan.root.subgraph_fetches(),
+        };
+        summary.add_root_fields(&plan.root);
+        summary
+    }
+
+    /// Adds the root fields of the fetches of a node, which are the fetches not nested in a
+    /// `Flatten` node
+    fn add_root_fields(&mut self, node: &PlanNode) {
+        match node {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => {
+                nodes.iter().for_each(|node| self.add_root_fields(node))
+            }
+            PlanNode::Fetch(fetch) => {
+                self.add_operation_root_fields(&fetch.service_name, &fetch.operation)
+            }
+            PlanNode::Subscription { primary, rest } => {
+                self.add_operation_root_fields(&primary.service_name, &primary.operation);
+                if let Some(rest) = rest {
+                    self.add_root_fields(rest);
+                }
+            }
+            PlanNode::Flatten(_) => {}
+            PlanNode::Defer { primary, deferred } => {
+                if let Some(node) = &primary.node {
+                    self.add_root_fields(node);
+                }
+                for deferred in deferred {
+                    if let Some(node) = &deferred.node {
+                        self.add_root_fields(node);
+                    }
+                }
+            }
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => {
+                for clause in [if_clause, else_clause].into_iter().flatten() {
+                    self.add_root_fields(clause);
+                }
+            }
+        }
+    }
+
+    fn add_operation_root_fields(&mut self, service_name: &str, operation: &SubgraphOperation) {
+        // the operations of the plans are parsed when the plans are created
+        let Ok(document) = operation.as_parsed() else {
+            return;
+        };
+        let root_fields = self
+            .root_fields
+            .entry(service_name.to_string())
+            .or_default();
+        for operation in document.operations.iter() {
+            collect_fields(
+                document,
+                &operation.selection_set,
+                root_fields,
+                &mut HashSet::new(),
+            );
+        }
+    }
+}
+
+fn collect_fields<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a executable::SelectionSet,
+    fields: &mut BTreeSet<String>,
+    spread_fragments: &mut HashSet<&'a Name>,
+) {
+    for selection in &selection_set.selections {
+        match selection {
+            executable::Selection::Field(field) => {
+                if field.name.as_str() != "__typename" {
+                    fields.insert(field.name.to_string());
+                }
+            }
+            executable::Selection::InlineFragment(fragment) => {
+                collect_fields(document, &fragment.selection_set, fields, spread_fragments)
+            }
+            executable::Selection::FragmentSpread(spread) => {
+                if !spread_fragments.insert(&spread.fragment_name) {
+                    continue;
+                }
+                if let Some(definition) = document.fragments.get(&spread.fragment_name) {
+                    collect_fields(
+                        document,
+                        &definition.selection_set,
+                        fields,
+                        spread_fragments,
+                    )
+                }
+            }
+        }
+    }
+}
+
+struct QueryPlanSummaryPlugin {
+    config: QueryPlanSummaryConfig,
+}
+
+#[async_trait::async_trait]
+impl Plugin for QueryPlanSummaryPlugin {
+    type Config = QueryPlanSummaryConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            config: init.config,
+        })
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .map_request(|request: execution::Request| {
+                let summary = QueryPlanSummary::new(&request.query_plan);
+                let _ = request.context.insert(QUERY_PLAN_SUMMARY_KEY, summary);
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::services::supergraph;
+    use crate::TestHarness;
+
+    #[tokio::test]
+    async fn summarizes_the_plan_before_execution() {
+        let summary = Arc::new(Mutex::new(None));
+        let captured = summary.clone();
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "query_plan_summary": { "enabled": true }
+            }))
+            .unwrap()
+            .execution_hook(move |service| {
+                let captured = captured.clone();
+                service
+                    .map_request(move |request: execution::Request| {
+                        *captured.lock().unwrap() = request
+                            .context
+                            .get::<_, QueryPlanSummary>(QUERY_PLAN_SUMMARY_KEY)
+                            .unwrap();
+                        request
+                    })
+                    .boxed()
+            })
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .query("{ topProducts { name reviews { author { name } } } }")
+            .build()
+            .unwrap();
+        let _ = service
+            .oneshot(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+
+        let summary = summary.lock().unwrap().take().unwrap();
+        assert_eq!(
+            summary.subgraphs,
+            BTreeSet::from(["accounts", "products", "reviews"].map(str::to_string))
+        );
+        assert_eq!(
+            summary.root_fields,
+            BTreeMap::from([(
+                "products".to_string(),
+                BTreeSet::from(["topProducts".to_string()])
+            )])
+        );
+        assert!(!summary.contains_mutations);
+        assert_eq!(summary.estimated_fetches, 3);
+    }
+}
@@ -0,0 +1,317 @@
+//! Generation, validation and propagation of request IDs.
+//!
+//! The request ID is read from a header of the client request, and generated when it is absent
+//! or malformed. It is sent to the subgraphs, stored in the context for coprocessors and Rhai
+//! scripts, added to the router and subgraph spans, and returned on the response.
+
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use http::HeaderName;
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::dynamic_attribute::SpanDynAttribute;
+use crate::register_plugin;
+use crate::services::router;
+use crate::services::subgraph;
+
+register_plugin!("apollo", "request_id", RequestId);
+
+/// Context key of the request ID
+pub(crate) const REQUEST_ID: &str = "apollo_request_id::id";
+
+const REQUEST_ID_ATTRIBUTE: &str = "request.id";
+
+/// Crockford's base 32 alphabet used by ULIDs
+const ULID_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ULID_LENGTH: usize = 26;
+
+/// Generation, validation and propagation of request IDs
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct RequestIdConfig {
+    /// Handle request IDs
+    enabled: bool,
+    /// Header of the request ID, on the client request and response and on the subgraph requests
+    header: String,
+    /// Format of the request IDs. Request IDs sent by clients in another format are replaced
+    format: RequestIdFormat,
+    /// Keep valid request IDs sent by clients. When disabled, a new request ID is always generated
+    accept_inbound: bool,
+    /// Send the request ID to subgraphs
+    propagate_to_subgraphs: bool,
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            header: "x-request-id".to_string(),
+            format: RequestIdFormat::default(),
+            accept_inbound: true,
+            propagate_to_subgraphs: true,
+        }
+    }
+}
+
+/// Format of the request IDs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum RequestIdFormat {
+    /// Time ordered UUID (version 7)
+    #[default]
+    UuidV7,
+    /// Universally Unique Lexicographically Sortable Identifier
+    Ulid,
+}
+
+impl RequestIdFormat {
+    fn generate(&self) -> String {
+        match self {
+            RequestIdFormat::UuidV7 => uuid::Uuid::now_v7().to_string(),
+            RequestIdFormat::Ulid => generate_ulid(),
+        }
+    }
+
+    fn is_valid(&self, id: &str) -> bool {
+        match self {
+            RequestIdFormat::UuidV7 => uuid::Uuid::try_parse(id)
+                .map(|uuid| uuid.get_version_num() == 7)
+                .unwrap_or(false),
+            RequestIdFormat::Ulid => {
+                id.len() == ULID_LENGTH
+                    // the first character encodes the 3 high bits of the 48 bits timestamp
+                    && id.as_bytes()[0] <= b'7'
+                    && id
+                        .bytes()
+                        .all(|c| ULID_ALPHABET.contains(&c.to_ascii_uppercase()))
+            }
+        }
+    }
+}
+
+/// A ULID: 48 bits of milliseconds timestamp followed by 80 random bits, in base 32
+fn generate_ulid() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u128)
+        .unwrap_or_default();
+    let value = ((millis & 0xFFFF_FFFF_FFFF) << 80) | (rand::random::<u128>() >> 48);
+    (0..ULID_LENGTH)
+        .rev()
+        .map(|index| ULID_ALPHABET[((value >> (index * 5)) & 0x1F) as usize] as char)
+        .collect()
+}
+
+struct RequestId {
+    config: RequestIdConfig,
+    header: HeaderName,
+}
+
+#[async_trait::async_trait]
+impl Plugin for RequestId {
+    type Config = RequestIdConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let header = HeaderName::try_from(init.config.header.as_str())
+            .map_err(|err| format!("invalid request ID header: {err}"))?;
+        Ok(Self {
+            config: init.config,
+            header,
+        })
+    }
+
+    fn router_service(&self, service: router::BoxService) -> router::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let header = self.header.clone();
+        let response_header = self.header.clone();
+        let format = self.config.format;
+        let accept_inbound = self.config.accept_inbound;
+        ServiceBuilder::new()
+            .map_request(move |mut request: router::Request| {
+                let inbound = request
+                    .router_request
+                    .headers()
+                    .get(&header)
+                    .and_then(|value| value.to_str().ok())
+                    .filter(|id| accept_inbound && format.is_valid(id))
+                    .map(str::to_string);
+                let id = match inbound {
+                    Some(id) => id,
+                    None => {
+                        let id = format.generate();
+                        // coprocessors and Rhai scripts see the request ID in the headers too
+                        request.router_request.headers_mut().insert(
+                            header.clone(),
+                            HeaderValue::from_str(&id).expect("request IDs are valid headers; qed"),
+                        );
+                        id
+                    }
+                };
+                tracing::Span::current()
+                    .set_span_dyn_attribute(REQUEST_ID_ATTRIBUTE.into(), id.clone().into());
+                let _ = request.context.insert(REQUEST_ID, id);
+                request
+            })
+            .map_response(move |mut response: router::Response| {
+                if let Some(id) = response
+                    .context
+                    .get::<_, String>(REQUEST_ID)
+                    .ok()
+                    .flatten()
+                    .and_then(|id| HeaderValue::from_str(&id).ok())
+                {
+                    response
+                        .response
+                        .headers_mut()
+                        .insert(response_header.clone(), id);
+                }
+                response
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, _name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let header = self.header.clone();
+        let propagate = self.config.propagate_to_subgraphs;
+        ServiceBuilder::new()
+            .map_request(move |mut request: subgraph::Request| {
+                if let Some(id) = request.context.get::<_, String>(REQUEST_ID).ok().flatten() {
+                    tracing::Span::current()
+                        .set_span_dyn_attribute(REQUEST_ID_ATTRIBUTE.into(), id.clone().into());
+                    if propagate {
+                        if let Ok(value) = HeaderValue::from_str(&id) {
+                            request
+                                .subgraph_request
+                                .headers_mut()
+                                .insert(header.clone(), value);
+                        }
+                    }
+                }
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::plugin::test::MockRouterService;
+    use crate::plugin::test::MockSubgraphService;
+
+    async fn plugin(config: serde_json::Value) -> RequestId {
+        RequestId::new(PluginInit::fake_new(
+            serde_json::from_value(config).unwrap(),
+            Default::default(),
+        ))
+        .await
+        .unwrap()
+    }
+
+    async fn response_id(plugin: &RequestId, inbound: Option<&str>) -> String {
+        let mut mock = MockRouterService::new();
+        mock.expect_call().times(1).returning(|request| {
+            Ok(router::Response::fake_builder()
+                .context(request.context)
+                .build()
+                .unwrap())
+        });
+        let mut request = router::Request::fake_builder().build().unwrap();
+        if let Some(inbound) = inbound {
+            request
+                .router_request
+                .headers_mut()
+                .insert("x-request-id", HeaderValue::from_str(inbound).unwrap());
+        }
+        let response = plugin
+            .router_service(mock.boxed())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let id = response.response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            response.context.get::<_, String>(REQUEST_ID).unwrap(),
+            Some(id.clone())
+        );
+        id
+    }
+
+    #[test]
+    fn generates_valid_ids() {
+        for format in [RequestIdFormat::UuidV7, RequestIdFormat::Ulid] {
+            let id = format.generate();
+            assert!(format.is_valid(&id), "{id}");
+        }
+        assert!(!RequestIdFormat::UuidV7.is_valid(&uuid::Uuid::new_v4().to_string()));
+        assert!(!RequestIdFormat::Ulid.is_valid("not-a-ulid"));
+        assert!(RequestIdFormat::Ulid.is_valid("01ARZ3NDEKTSV4RRFFQ69G5FAV"));
+    }
+
+    #[tokio::test]
+    async fn keeps_valid_inbound_ids() {
+        let plugin = plugin(serde_json::json!({ "enabled": true })).await;
+        let inbound = uuid::Uuid::now_v7().to_string();
+        assert_eq!(response_id(&plugin, Some(&inbound)).await, inbound);
+
+        let generated = response_id(&plugin, Some("malformed")).await;
+        assert_ne!(generated, "malformed");
+        assert!(RequestIdFormat::UuidV7.is_valid(&generated));
+
+        let plugin = plugin(serde_json::json!({ "enabled": true, "accept_inbound": false })).await;
+        assert_ne!(response_id(&plugin, Some(&inbound)).await, inbound);
+    }
+
+    #[tokio::test]
+    async fn propagates_ids_to_subgraphs() {
+        let plugin = plugin(serde_json::json!({ "enabled": true })).await;
+        let sent = Arc::new(Mutex::new(None));
+        let sent_in_mock = sent.clone();
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call().times(1).returning(move |request| {
+            *sent_in_mock.lock().unwrap() = request
+                .subgraph_request
+                .headers()
+                .get("x-request-id")
+                .cloned();
+            Ok(subgraph::Response::fake_builder().build())
+        });
+        let request = subgraph::Request::fake_builder().build();
+        request
+            .context
+            .insert(REQUEST_ID, "id".to_string())
+            .unwrap();
+        plugin
+            .subgraph_service("products", mock.boxed())
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(
+            sent.lock().unwrap().clone(),
+            Some(HeaderValue::from_static("id"))
+        );
+    }
+}
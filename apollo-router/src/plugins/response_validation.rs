@@ -0,0 +1,450 @@
+//! Validation of subgraph responses against the subgraph schemas.
+//!
+//! This is a debugging aid: the data of each subgraph response is checked against the selection
+//! set of the subgraph operation and the types of the subgraph schema. Mismatches (null values
+//! for non-null fields, unknown enum values, lists where objects are expected...) are logged and
+//! counted per subgraph, but the responses are not modified.
+
+use std::fmt;
+use std::sync::Arc;
+
+use apollo_compiler::ast;
+use apollo_compiler::executable;
+use apollo_compiler::schema::ExtendedType;
+use apollo_compiler::validation::Valid;
+use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Schema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::fetch::SubgraphSchemas;
+use crate::register_plugin;
+use crate::services::subgraph;
+
+register_plugin!("apollo", "response_validation", ResponseValidation);
+
+const TYPENAME: &str = "__typename";
+
+/// Validation of subgraph responses against the subgraph schemas, for debugging
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct ResponseValidationConfig {
+    /// Check the data of subgraph responses against the subgraph operations and schemas, and log
+    /// the mismatches. This is expensive, enable it to debug subgraphs returning invalid data
+    enabled: bool,
+}
+
+/// The ways a response value can fail to match its schema type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MismatchKind {
+    /// A null value for a non-null field
+    NonNull,
+    /// A value that is not a list for a list field
+    List,
+    /// A value that is not an object for a composite field
+    Object,
+    /// A value that does not match the built-in scalar type of the field
+    Scalar,
+    /// A value that is not one of the values of the enum type of the field
+    Enum,
+    /// A `__typename` that is not a possible type of the field
+    Typename,
+    /// A selected field missing from the response
+    MissingField,
+}
+
+impl MismatchKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MismatchKind::NonNull => "non_null",
+            MismatchKind::List => "list",
+            MismatchKind::Object => "object",
+            MismatchKind::Scalar => "scalar",
+            MismatchKind::Enum => "enum",
+            MismatchKind::Typename => "typename",
+            MismatchKind::MissingField => "missing_field",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Mismatch {
+    kind: MismatchKind,
+    path: Path,
+    /// The type expected at this path
+    expected: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} mismatch at {}, expected {}",
+            self.kind.as_str(),
+            self.path,
+            self.expected
+        )
+    }
+}
+
+struct Validator<'a> {
+    schema: &'a Schema,
+    document: &'a ExecutableDocument,
+    path: Vec<PathElement>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl<'a> Validator<'a> {
+    fn mismatch(&mut self, kind: MismatchKind, expected: impl ToString) {
+        self.mismatches.push(Mismatch {
+            kind,
+            path: Path(self.path.clone()),
+            expected: expected.to_string(),
+        });
+    }
+
+    fn value(&mut self, ty: &ast::Type, selection_set: &executable::SelectionSet, value: &Value) {
+        match (ty, value) {
+            (ty, Value::Null) => {
+                if ty.is_non_null() {
+                    self.mismatch(MismatchKind::NonNull, ty);
+                }
+            }
+            (ast::Type::List(inner) | ast::Type::NonNullList(inner), Value::Array(items)) => {
+                for (index, item) in items.iter().enumerate() {
+                    self.path.push(PathElement::Index(index));
+                    self.value(inner, selection_set, item);
+                    self.path.pop();
+                }
+            }
+            (ast::Type::List(_) | ast::Type::NonNullList(_), _) => {
+                self.mismatch(MismatchKind::List, ty);
+            }
+            (ast::Type::Named(name) | ast::Type::NonNullNamed(name), value) => {
+                self.named(name, selection_set, value)
+            }
+        }
+    }
+
+    fn named(&mut self, name: &str, selection_set: &executable::SelectionSet, value: &Value) {
+        match self.schema.types.get(name) {
+            Some(ExtendedType::Scalar(_)) => {
+                let valid = match name {
+                    "Int" => value.as_i64().is_some_and(|int| i32::try_from(int).is_ok()),
+                    "Float" => value.is_number(),
+                    "String" => value.is_string(),
+                    "Boolean" => value.is_boolean(),
+                    "ID" => value.is_string() || value.is_i64() || value.is_u64(),
+                    // custom scalars can have any value
+                    _ => true,
+                };
+                if !valid {
+                    self.mismatch(MismatchKind::Scalar, name);
+                }
+            }
+            Some(ExtendedType::Enum(enum_type)) => {
+                if !value
+                    .as_str()
+                    .is_some_and(|value| enum_type.values.contains_key(value))
+                {
+                    self.mismatch(MismatchKind::Enum, name);
+                }
+            }
+            Some(ExtendedType::Object(_) | ExtendedType::Interface(_) | ExtendedType::Union(_)) => {
+                match value {
+                    Value::Object(object) => self.object(name, selection_set, object),
+                    _ => self.mismatch(MismatchKind::Object, name),
+                }
+            }
+            Some(ExtendedType::InputObject(_)) | None => {}
+        }
+    }
+
+    fn object(
+        &mut self,
+        ty: &str,
+        selection_set: &executable::SelectionSet,
+        object: &Map<ByteString, Value>,
+    ) {
+        // the concrete type of the object, if it is known
+        let concrete_type = match object.get(TYPENAME).and_then(|typename| typename.as_str()) {
+            Some(typename) => {
+                if typename != ty && !self.schema.is_subtype(ty, typename) {
+                    self.path.push(PathElement::Key(TYPENAME.to_string(), None));
+                    self.mismatch(MismatchKind::Typename, ty);
+                    self.path.pop();
+                    return;
+                }
+                Some(typename.to_string())
+            }
+            None => matches!(self.schema.types.get(ty), Some(ExtendedType::Object(_)))
+                .then(|| ty.to_string()),
+        };
+        self.selection_set(concrete_type.as_deref(), selection_set, object);
+    }
+
+    fn selection_set(
+        &mut self,
+        concrete_type: Option<&str>,
+        selection_set: &executable::SelectionSet,
+        object: &Map<ByteString, Value>,
+    ) {
+        for selection in &selection_set.selections {
+            match selection {
+                executable::Selection::Field(field) => {
+                    if field.name.as_str() == TYPENAME {
+                        continue;
+                    }
+                    let key = field.response_key().as_str();
+                    self.path.push(PathElement::Key(key.to_string(), None));
+                    match object.get(key) {
+                        Some(value) => self.value(field.ty(), &field.selection_set, value),
+                        None => self.mismatch(MismatchKind::MissingField, field.ty()),
+                    }
+                    self.path.pop();
+                }
+                executable::Selection::InlineFragment(fragment) => {
+                    let applies = match &fragment.type_condition {
+                        None => true,
+                        Some(condition) => self.applies(condition, concrete_type),
+                    };
+                    if applies {
+                        self.selection_set(concrete_type, &fragment.selection_set, object);
+                    }
+                }
+                executable::Selection::FragmentSpread(spread) => {
+                    let Some(fragment) = spread.fragment_def(self.document) else {
+                        continue;
+                    };
+                    if self.applies(fragment.type_condition(), concrete_type) {
+                        self.selection_set(concrete_type, &fragment.selection_set, object);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a fragment applies to the object. Without a known concrete type, the fragment is
+    /// skipped as its fields may legitimately be missing.
+    fn applies(&self, type_condition: &str, concrete_type: Option<&str>) -> bool {
+        concrete_type.is_some_and(|concrete_type| {
+            concrete_type == type_condition || self.schema.is_subtype(type_condition, concrete_type)
+        })
+    }
+}
+
+/// Returns the mismatches between the data of a subgraph response and its operation.
+fn validate(
+    schema: &Schema,
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    data: &Value,
+) -> Vec<Mismatch> {
+    let Ok(operation) = document.operations.get(operation_name) else {
+        return Vec::new();
+    };
+    let Value::Object(data) = data else {
+        return Vec::new();
+    };
+    let mut validator = Validator {
+        schema,
+        document,
+        path: Vec::new(),
+        mismatches: Vec::new(),
+    };
+    validator.selection_set(
+        Some(operation.object_type().as_str()),
+        &operation.selection_set,
+        data,
+    );
+    validator.mismatches
+}
+
+struct ResponseValidation {
+    enabled: bool,
+    subgraph_schemas: Arc<SubgraphSchemas>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ResponseValidation {
+    type Config = ResponseValidationConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            enabled: init.config.enabled,
+            subgraph_schemas: init.subgraph_schemas.clone(),
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let Some(schema) = self
+            .enabled
+            .then(|| self.subgraph_schemas.get(name).cloned())
+            .flatten()
+        else {
+            return service;
+        };
+
+        let subgraph_name = name.to_string();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |request: &subgraph::Request| {
+                    (
+                        request.executable_document.clone(),
+                        request.subgraph_request.body().operation_name.clone(),
+                    )
+                },
+                move |(document, operation_name): (
+                    Option<Arc<Valid<ExecutableDocument>>>,
+                    Option<String>,
+                ),
+                      future| {
+                    let schema = schema.clone();
+                    let subgraph_name = subgraph_name.clone();
+                    async move {
+                        let response: subgraph::Response = future.await?;
+                        if let (Some(document), Some(data)) =
+                            (document, &response.response.body().data)
+                        {
+                            let mismatches =
+                                validate(&schema, &document, operation_name.as_deref(), data);
+                            for mismatch in &mismatches {
+                                tracing::warn!(
+                                    subgraph = subgraph_name.as_str(),
+                                    "invalid subgraph response: {mismatch}"
+                                );
+                                u64_counter!(
+                                    "apollo.router.operations.subgraph.response.validation",
+                                    "Number of values of subgraph responses not matching the subgraph schema",
+                                    1,
+                                    "subgraph.name" = subgraph_name.clone(),
+                                    "validation.error" = mismatch.kind.as_str()
+                                );
+                            }
+                        }
+                        Ok(response)
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            products: [Product!]!
+            node: Node
+        }
+
+        interface Node {
+            id: ID!
+        }
+
+        type Product implements Node {
+            id: ID!
+            name: String
+            status: Status!
+            reviews: [Review]
+        }
+
+        type Review implements Node {
+            id: ID!
+            rating: Int!
+        }
+
+        enum Status {
+            AVAILABLE
+            DISCONTINUED
+        }
+    "#;
+
+    fn mismatches(query: &str, data: Value) -> Vec<(MismatchKind, String)> {
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let document =
+            ExecutableDocument::parse_and_validate(&schema, query, "query.graphql").unwrap();
+        validate(&schema, &document, None, &data)
+            .into_iter()
+            .map(|mismatch| (mismatch.kind, mismatch.path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_response() {
+        assert!(mismatches(
+            "{ products { id name status reviews { rating } } }",
+            json!({ "products": [
+                { "id": "1", "name": null, "status": "AVAILABLE", "reviews": [{ "rating": 4 }, null] },
+                { "id": 2, "name": "Couch", "status": "DISCONTINUED", "reviews": null }
+            ]}),
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn invalid_response() {
+        assert_eq!(
+            mismatches(
+                "{ products { id status reviews { rating } } }",
+                json!({ "products": [
+                    { "id": null, "status": "SOLD_OUT", "reviews": { "rating": 4 } },
+                    { "status": "AVAILABLE", "reviews": [{ "rating": "4" }] },
+                    null
+                ]}),
+            ),
+            vec![
+                (MismatchKind::NonNull, "/products/0/id".to_string()),
+                (MismatchKind::Enum, "/products/0/status".to_string()),
+                (MismatchKind::List, "/products/0/reviews".to_string()),
+                (MismatchKind::MissingField, "/products/1/id".to_string()),
+                (
+                    MismatchKind::Scalar,
+                    "/products/1/reviews/0/rating".to_string()
+                ),
+                (MismatchKind::NonNull, "/products/2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn abstract_types() {
+        let query = "{ node { id ... on Review { rating } } }";
+        assert!(mismatches(
+            query,
+            json!({ "node": { "__typename": "Product", "id": "1" } })
+        )
+        .is_empty());
+        assert_eq!(
+            mismatches(
+                query,
+                json!({ "node": { "__typename": "Review", "id": "1" } })
+            ),
+            vec![(MismatchKind::MissingField, "/node/rating".to_string())]
+        );
+        assert_eq!(
+            mismatches(
+                query,
+                json!({ "node": { "__typename": "Status", "id": "1" } })
+            ),
+            vec![(MismatchKind::Typename, "/node/__typename".to_string())]
+        );
+    }
+}
@@ -55,6 +55,7 @@ mod execution;
 mod router;
 mod subgraph;
 mod supergraph;
+pub(crate) mod test_runner;
 
 struct EngineBlock {
     ast: AST,
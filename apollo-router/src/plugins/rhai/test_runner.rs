@@ -0,0 +1,443 @@
+//! Unit tests of Rhai scripts, run with `router rhai-test <dir>`.
+//!
+//! The scripts are loaded like the Rhai plugin does, and their service callbacks wrap fake
+//! services answering with fixture responses. Each test sends a fixture request through one of
+//! the services, then calls an assertion function of the scripts with the request received by
+//! the fake service and the response returned by the scripts. Assertion functions fail a test by
+//! throwing.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use apollo_compiler::validation::Valid;
+use apollo_compiler::Schema;
+use http::HeaderMap;
+use http::Method;
+use http::StatusCode;
+use rhai::serde::to_dynamic;
+use rhai::EvalAltResult;
+use serde::Deserialize;
+use serde_json_bytes::json;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value;
+use tower::service_fn;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use super::Conf;
+use super::Rhai;
+use crate::graphql;
+use crate::notification::Notify;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::services::execution;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+/// Name of the fixtures file in the tested directory
+const FIXTURES_FILE: &str = "rhai_tests.yaml";
+
+/// Tests of the Rhai scripts of a directory
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fixtures {
+    /// The main entry point of the scripts, relative to the directory
+    #[serde(default = "default_main")]
+    main: String,
+    /// Supergraph schema available to the scripts as `apollo_sdl`, relative to the directory
+    schema: Option<PathBuf>,
+    tests: Vec<Fixture>,
+}
+
+fn default_main() -> String {
+    "main.rhai".to_string()
+}
+
+/// The service going through the Rhai callbacks of a test
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Stage {
+    Supergraph,
+    Execution,
+    Subgraph,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fixture {
+    name: String,
+    service: Stage,
+    /// Name of the subgraph, for subgraph service tests
+    subgraph: Option<String>,
+    #[serde(default)]
+    request: FixtureRequest,
+    #[serde(default)]
+    response: FixtureResponse,
+    /// Rhai function called with the request received by the fake service and the response
+    /// returned by the scripts
+    assert: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct FixtureRequest {
+    query: Option<String>,
+    operation_name: Option<String>,
+    variables: Map<ByteString, Value>,
+    extensions: Map<ByteString, Value>,
+    headers: BTreeMap<String, String>,
+    context: BTreeMap<String, Value>,
+}
+
+/// Response of the fake service
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct FixtureResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    data: Option<Value>,
+    errors: Vec<graphql::Error>,
+    extensions: Map<ByteString, Value>,
+}
+
+impl Default for FixtureResponse {
+    fn default() -> Self {
+        Self {
+            status: 200,
+            headers: Default::default(),
+            data: None,
+            errors: Vec::new(),
+            extensions: Default::default(),
+        }
+    }
+}
+
+impl FixtureRequest {
+    fn http_request(&self) -> Result<http::Request<graphql::Request>, BoxError> {
+        let mut builder = http::Request::builder()
+            .method(Method::POST)
+            .uri("http://default");
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        Ok(builder.body(
+            graphql::Request::builder()
+                .and_query(self.query.clone())
+                .and_operation_name(self.operation_name.clone())
+                .variables(self.variables.clone())
+                .extensions(self.extensions.clone())
+                .build(),
+        )?)
+    }
+
+    fn context(&self) -> Result<Context, BoxError> {
+        let context = Context::new();
+        for (key, value) in &self.context {
+            context.insert(key.clone(), value.clone())?;
+        }
+        Ok(context)
+    }
+}
+
+fn headers_value(headers: &HeaderMap) -> Value {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                ByteString::from(name.as_str()),
+                Value::from(String::from_utf8_lossy(value.as_bytes()).into_owned()),
+            )
+        })
+        .collect::<Map<ByteString, Value>>()
+        .into()
+}
+
+fn context_value(context: &Context) -> Value {
+    context
+        .iter()
+        .map(|entry| {
+            (
+                ByteString::from(entry.key().as_str()),
+                entry.value().clone(),
+            )
+        })
+        .collect::<Map<ByteString, Value>>()
+        .into()
+}
+
+/// The request as received by the fake service, or the response as returned by the scripts
+fn observed(
+    status: Option<StatusCode>,
+    headers: &HeaderMap,
+    body: Value,
+    context: &Context,
+) -> Value {
+    let mut observed = json!({
+        "headers": headers_value(headers),
+        "body": body,
+        "context": context_value(context),
+    });
+    if let (Some(status), Some(object)) = (status, observed.as_object_mut()) {
+        object.insert("status", status.as_u16().into());
+    }
+    observed
+}
+
+fn to_value(value: impl serde::Serialize) -> Result<Value, BoxError> {
+    Ok(serde_json_bytes::to_value(value)?)
+}
+
+/// Sends the fixture request through the scripts, and returns the request received by the fake
+/// service (`null` if the scripts did not call it) and the response returned by the scripts.
+async fn run_fixture(rhai: &Rhai, fixture: &Fixture) -> Result<Value, BoxError> {
+    let received: Arc<Mutex<Option<Value>>> = Default::default();
+    let recorded = received.clone();
+    let response = fixture.response.clone();
+    let status = StatusCode::from_u16(response.status)?;
+
+    let returned = match fixture.service {
+        Stage::Supergraph | Stage::Execution => {
+            let fake = service_fn(move |request: supergraph::Request| {
+                let observed_request = to_value(request.supergraph_request.body()).map(|body| {
+                    observed(
+                        None,
+                        request.supergraph_request.headers(),
+                        body,
+                        &request.context,
+                    )
+                });
+                let response = response.clone();
+                let recorded = recorded.clone();
+                async move {
+                    *recorded.lock().expect("lock poisoned") = Some(observed_request?);
+                    let mut builder = supergraph::Response::fake_builder()
+                        .and_data(response.data)
+                        .errors(response.errors)
+                        .extensions(response.extensions)
+                        .status_code(status)
+                        .context(request.context);
+                    for (name, value) in response.headers {
+                        builder = builder.header(name, value);
+                    }
+                    builder.build()
+                }
+            });
+            let http_request = fixture.request.http_request()?;
+            let context = fixture.request.context()?;
+            let mut response = match fixture.service {
+                Stage::Supergraph => {
+                    rhai.supergraph_service(fake.boxed())
+                        .oneshot(supergraph::Request {
+                            supergraph_request: http_request,
+                            context,
+                        })
+                        .await?
+                }
+                _ => {
+                    // the execution service has its own request type
+                    let fake =
+                        fake.map_request(|request: execution::Request| supergraph::Request {
+                            supergraph_request: request.supergraph_request,
+                            context: request.context,
+                        });
+                    rhai.execution_service(fake.boxed())
+                        .oneshot(
+                            execution::Request::fake_builder()
+                                .supergraph_request(http_request)
+                                .context(context)
+                                .build(),
+                        )
+                        .await?
+                }
+            };
+            let body = response.next_response().await.unwrap_or_default();
+            observed(
+                Some(response.response.status()),
+                response.response.headers(),
+                to_value(body)?,
+                &response.context,
+            )
+        }
+        Stage::Subgraph => {
+            let subgraph_name = fixture.subgraph.clone().unwrap_or_default();
+            let response_subgraph_name = subgraph_name.clone();
+            let fake = service_fn(move |request: subgraph::Request| {
+                let observed_request = to_value(request.subgraph_request.body()).map(|body| {
+                    observed(
+                        None,
+                        request.subgraph_request.headers(),
+                        body,
+                        &request.context,
+                    )
+                });
+                let response = response.clone();
+                let recorded = recorded.clone();
+                let subgraph_name = response_subgraph_name.clone();
+                async move {
+                    *recorded.lock().expect("lock poisoned") = Some(observed_request?);
+                    let mut headers = HeaderMap::new();
+                    for (name, value) in response.headers {
+                        headers.insert(
+                            http::HeaderName::try_from(name)?,
+                            http::HeaderValue::try_from(value)?,
+                        );
+                    }
+                    Ok::<_, BoxError>(
+                        subgraph::Response::fake_builder()
+                            .and_data(response.data)
+                            .errors(response.errors)
+                            .extensions(response.extensions)
+                            .status_code(status)
+                            .headers(headers)
+                            .context(request.context)
+                            .subgraph_name(subgraph_name)
+                            .build(),
+                    )
+                }
+            });
+            let http_request = fixture.request.http_request()?;
+            let response = rhai
+                .subgraph_service(&subgraph_name, fake.boxed())
+                .oneshot(
+                    subgraph::Request::fake_builder()
+                        .supergraph_request(Arc::new(fixture.request.http_request()?))
+                        .subgraph_request(http_request)
+                        .subgraph_name(subgraph_name.clone())
+                        .context(fixture.request.context()?)
+                        .build(),
+                )
+                .await?;
+            observed(
+                Some(response.response.status()),
+                response.response.headers(),
+                to_value(response.response.body())?,
+                &response.context,
+            )
+        }
+    };
+
+    let received = received
+        .lock()
+        .expect("lock poisoned")
+        .take()
+        .unwrap_or_default();
+    Ok(json!({
+        "request": received,
+        "response": returned,
+    }))
+}
+
+/// Runs the test of a fixture, returning the reason of its failure.
+async fn run_test(rhai: &Rhai, fixture: &Fixture) -> Result<(), String> {
+    let outcome = run_fixture(rhai, fixture)
+        .await
+        .map_err(|e| format!("could not run the services: {e}"))?;
+    let outcome = to_dynamic(outcome).map_err(|e| e.to_string())?;
+
+    let block = rhai.block.load();
+    let mut scope = block.scope.lock().expect("lock poisoned");
+    block
+        .engine
+        .call_fn::<rhai::Dynamic>(&mut scope, &block.ast, &fixture.assert, (outcome,))
+        .map(|_| ())
+        .map_err(|error| match error.unwrap_inner() {
+            // thrown by the assertion
+            EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+            error => error.to_string(),
+        })
+}
+
+/// Loads the scripts like the Rhai plugin does. Without a schema, `apollo_sdl` is empty.
+async fn load(dir: &Path, main: &str, sdl: String) -> Result<Rhai, BoxError> {
+    let supergraph_schema = if sdl.is_empty() {
+        Valid::assume_valid(Schema::new())
+    } else {
+        Schema::parse_and_validate(sdl.as_str(), "schema.graphql")
+            .map_err(|e| format!("invalid schema: {}", e.errors))?
+    };
+    Rhai::new(
+        PluginInit::builder()
+            .config(Conf {
+                scripts: Some(dir.to_path_buf()),
+                main: Some(main.to_string()),
+            })
+            .supergraph_sdl(Arc::new(sdl))
+            .supergraph_schema(Arc::new(supergraph_schema))
+            .notify(Notify::builder().build())
+            .build(),
+    )
+    .await
+}
+
+/// Runs the Rhai tests of a directory and prints their results. Fails if a test failed.
+pub(crate) async fn run(dir: &Path) -> Result<(), BoxError> {
+    let fixtures_path = dir.join(FIXTURES_FILE);
+    let fixtures: Fixtures = serde_yaml::from_str(
+        &std::fs::read_to_string(&fixtures_path)
+            .map_err(|e| format!("could not read {}: {e}", fixtures_path.display()))?,
+    )
+    .map_err(|e| format!("invalid {}: {e}", fixtures_path.display()))?;
+    let sdl = match &fixtures.schema {
+        Some(schema) => std::fs::read_to_string(dir.join(schema))?,
+        None => String::new(),
+    };
+
+    let rhai = load(dir, &fixtures.main, sdl).await?;
+
+    let mut failed = 0;
+    for fixture in &fixtures.tests {
+        match run_test(&rhai, fixture).await {
+            Ok(()) => println!("test {} ... ok", fixture.name),
+            Err(reason) => {
+                failed += 1;
+                println!("test {} ... FAILED: {reason}", fixture.name);
+            }
+        }
+    }
+    println!(
+        "\n{} tests, {} passed, {failed} failed",
+        fixtures.tests.len(),
+        fixtures.tests.len() - failed
+    );
+
+    if failed > 0 {
+        return Err(format!("{failed} Rhai tests failed").into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_fixtures() {
+        let dir = PathBuf::from("tests/fixtures/rhai_test");
+        let fixtures: Fixtures =
+            serde_yaml::from_str(&std::fs::read_to_string(dir.join(FIXTURES_FILE)).unwrap())
+                .unwrap();
+        let rhai = load(&dir, &fixtures.main, String::new()).await.unwrap();
+
+        let results = futures::future::join_all(
+            fixtures
+                .tests
+                .iter()
+                .map(|fixture| run_test(&rhai, fixture)),
+        )
+        .await;
+        assert_eq!(
+            results,
+            vec![
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Err("expected the x-client header to be propagated".to_string()),
+            ]
+        );
+    }
+}
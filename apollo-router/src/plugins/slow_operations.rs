@@ -0,0 +1,428 @@
+//! Slow operation log.
+//!
+//! The requests taking longer than a latency threshold, or costing more than a cost threshold,
+//! emit a structured record with what is needed to investigate them: the operation, the client,
+//! the timings of the subgraph fetches, a summary of the query plan and the cache hits. The
+//! records are rate limited, independently of the sampling of the traces, so that a slow
+//! subgraph does not flood the logs.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::context::OPERATION_KIND;
+use crate::context::OPERATION_NAME;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::cache::entity::CacheSubgraph;
+use crate::plugins::cache::metrics::CacheMetricContextKey;
+use crate::plugins::demand_control::CostContext;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::plugins::telemetry::CLIENT_VERSION;
+use crate::query_planner::APOLLO_OPERATION_ID;
+use crate::query_planner::QUERY_PLAN_CACHE_HIT;
+use crate::register_plugin;
+use crate::services::execution;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!("apollo", "slow_operations", SlowOperations);
+
+/// Target of the slow operation records, to route them with the logging configuration
+const SLOW_OPERATION_TARGET: &str = "apollo_router::slow_operation";
+
+/// Slow operation log configuration
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SlowOperationsConfig {
+    /// Emit a record for the operations over one of the thresholds
+    enabled: bool,
+    /// Latency over which an operation is slow, measured until the first response is sent to
+    /// the client (default: 1s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    threshold: Duration,
+    /// Cost over which an operation is logged, whatever its latency. The actual cost is used
+    /// when demand control computed it, the estimated cost otherwise
+    cost_threshold: Option<f64>,
+    /// Maximum number of records emitted per interval
+    rate_limit: SlowOperationsRateLimit,
+}
+
+impl Default for SlowOperationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: Duration::from_secs(1),
+            cost_threshold: None,
+            rate_limit: SlowOperationsRateLimit::default(),
+        }
+    }
+}
+
+/// Maximum number of records emitted per interval
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SlowOperationsRateLimit {
+    /// Number of records emitted per interval (default: 10)
+    capacity: u64,
+    /// Interval (default: 1s)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    interval: Duration,
+}
+
+impl Default for SlowOperationsRateLimit {
+    fn default() -> Self {
+        Self {
+            capacity: 10,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Fixed window limiter of the records. The records over the capacity are counted, and the
+/// count is reported by the next emitted record
+struct RecordLimiter {
+    capacity: u64,
+    interval: Duration,
+    window: Mutex<Window>,
+}
+
+struct Window {
+    start: Instant,
+    emitted: u64,
+    suppressed: u64,
+}
+
+impl RecordLimiter {
+    fn new(config: &SlowOperationsRateLimit) -> Self {
+        Self {
+            capacity: config.capacity,
+            interval: config.interval,
+            window: Mutex::new(Window {
+                start: Instant::now(),
+                emitted: 0,
+                suppressed: 0,
+            }),
+        }
+    }
+
+    /// Returns the number of records suppressed since the last emitted one if a record can be
+    /// emitted now
+    fn acquire(&self, now: Instant) -> Option<u64> {
+        let mut window = self.window.lock().expect("lock poisoned");
+        if now.duration_since(window.start) >= self.interval {
+            window.start = now;
+            window.emitted = 0;
+        }
+        if window.emitted >= self.capacity {
+            window.suppressed += 1;
+            return None;
+        }
+        window.emitted += 1;
+        Some(std::mem::take(&mut window.suppressed))
+    }
+}
+
+/// A fetch to a subgraph during the request
+#[derive(Clone, Debug, Serialize)]
+struct SubgraphFetch {
+    name: String,
+    duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_hits: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_misses: Option<usize>,
+}
+
+/// Summary of the query plan of the request
+#[derive(Clone, Debug, Default, Serialize)]
+struct PlanSummary {
+    fetches: usize,
+    subgraphs: Vec<String>,
+    deferred: bool,
+}
+
+/// What the record of a request gathers before the request is known to be slow
+#[derive(Clone)]
+struct RequestRecord {
+    start: Instant,
+    fetches: Arc<Mutex<Vec<SubgraphFetch>>>,
+    plan: Arc<Mutex<Option<PlanSummary>>>,
+}
+
+struct SlowOperations {
+    config: SlowOperationsConfig,
+    limiter: Arc<RecordLimiter>,
+}
+
+fn context_string(context: &Context, key: &str) -> Option<String> {
+    context.get::<_, String>(key).ok().flatten()
+}
+
+fn context_bool(context: &Context, key: &str) -> Option<bool> {
+    context.get::<_, bool>(key).ok().flatten()
+}
+
+/// Entity cache hits and misses of a subgraph fetch
+fn cache_hits(context: &Context, subgraph_name: &str) -> Option<(usize, usize)> {
+    let cache: CacheSubgraph = context
+        .get(CacheMetricContextKey::new(subgraph_name.to_string()))
+        .ok()
+        .flatten()?;
+    Some(cache.0.values().fold((0, 0), |(hits, misses), hit_miss| {
+        (hits + hit_miss.hit, misses + hit_miss.miss)
+    }))
+}
+
+fn plan_summary(request: &execution::Request) -> PlanSummary {
+    let root = &request.query_plan.root;
+    let mut subgraphs: Vec<String> = root.service_usage().map(str::to_string).collect();
+    subgraphs.sort();
+    subgraphs.dedup();
+    let body = request.supergraph_request.body();
+    PlanSummary {
+        fetches: root.subgraph_fetches(),
+        subgraphs,
+        deferred: request
+            .query_plan
+            .is_deferred(body.operation_name.as_deref(), &body.variables),
+    }
+}
+
+impl SlowOperationsConfig {
+    /// Returns whether an operation is over the latency or the cost threshold
+    fn is_slow(&self, duration: Duration, cost: Option<&CostContext>) -> bool {
+        let cost = cost.map(|cost| {
+            if cost.actual > 0.0 {
+                cost.actual
+            } else {
+                cost.estimated
+            }
+        });
+        duration >= self.threshold
+            || self
+                .cost_threshold
+                .zip(cost)
+                .is_some_and(|(threshold, cost)| cost >= threshold)
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for SlowOperations {
+    type Config = SlowOperationsConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        if init.config.enabled && init.config.rate_limit.interval.is_zero() {
+            return Err("the slow operations rate limit interval must not be zero".into());
+        }
+        Ok(Self {
+            limiter: Arc::new(RecordLimiter::new(&init.config.rate_limit)),
+            config: init.config,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let config = self.config.clone();
+        let limiter = self.limiter.clone();
+        ServiceBuilder::new()
+            .map_request(|request: supergraph::Request| {
+                request.context.extensions().with_lock(|mut lock| {
+                    lock.insert(RequestRecord {
+                        start: Instant::now(),
+                        fetches: Default::default(),
+                        plan: Default::default(),
+                    })
+                });
+                request
+            })
+            .map_first_graphql_response(move |context, parts, response| {
+                let Some(record) = context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<RequestRecord>().cloned())
+                else {
+                    return (parts, response);
+                };
+                let duration = record.start.elapsed();
+                let cost = context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<CostContext>().cloned());
+                if !config.is_slow(duration, cost.as_ref()) {
+                    return (parts, response);
+                }
+                let Some(suppressed) = limiter.acquire(Instant::now()) else {
+                    return (parts, response);
+                };
+
+                let fetches =
+                    serde_json::to_string(&*record.fetches.lock().expect("lock poisoned"))
+                        .unwrap_or_default();
+                let plan = record
+                    .plan
+                    .lock()
+                    .expect("lock poisoned")
+                    .as_ref()
+                    .and_then(|plan| serde_json::to_string(plan).ok());
+                tracing::warn!(
+                    target: SLOW_OPERATION_TARGET,
+                    duration_ms = duration.as_secs_f64() * 1000.0,
+                    threshold_ms = config.threshold.as_secs_f64() * 1000.0,
+                    http.response.status_code = parts.status.as_u16(),
+                    graphql.operation.name = context_string(&context, OPERATION_NAME),
+                    "graphql.operation.type" = context_string(&context, OPERATION_KIND),
+                    graphql.operation.id = context_string(&context, APOLLO_OPERATION_ID),
+                    client.name = context_string(&context, CLIENT_NAME),
+                    client.version = context_string(&context, CLIENT_VERSION),
+                    subgraphs = fetches,
+                    plan,
+                    cache.query_plan_hit = context_bool(&context, QUERY_PLAN_CACHE_HIT),
+                    cache.persisted_query_hit = context_bool(&context, "persisted_query_hit"),
+                    cost.estimated = cost.as_ref().map(|cost| cost.estimated),
+                    cost.actual = cost.as_ref().map(|cost| cost.actual),
+                    suppressed,
+                    "slow operation"
+                );
+                (parts, response)
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .map_request(|request: execution::Request| {
+                if let Some(record) = request
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<RequestRecord>().cloned())
+                {
+                    *record.plan.lock().expect("lock poisoned") = Some(plan_summary(&request));
+                }
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let name = name.to_string();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |request: &subgraph::Request| {
+                    (
+                        request.context.clone(),
+                        request
+                            .context
+                            .extensions()
+                            .with_lock(|lock| lock.get::<RequestRecord>().cloned()),
+                    )
+                },
+                move |(context, record): (Context, Option<RequestRecord>), future| {
+                    let name = name.clone();
+                    let start = Instant::now();
+                    async move {
+                        let result: Result<subgraph::Response, BoxError> = future.await;
+                        if let Some(record) = record {
+                            let (cache_hits, cache_misses) = cache_hits(&context, &name).unzip();
+                            record
+                                .fetches
+                                .lock()
+                                .expect("lock poisoned")
+                                .push(SubgraphFetch {
+                                    name,
+                                    duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+                                    status: result
+                                        .as_ref()
+                                        .ok()
+                                        .map(|response| response.response.status().as_u16()),
+                                    cache_hits,
+                                    cache_misses,
+                                });
+                        }
+                        result
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(cost_threshold: Option<f64>) -> SlowOperationsConfig {
+        SlowOperationsConfig {
+            enabled: true,
+            threshold: Duration::from_millis(500),
+            cost_threshold,
+            rate_limit: SlowOperationsRateLimit::default(),
+        }
+    }
+
+    #[test]
+    fn operations_over_a_threshold_are_slow() {
+        let cost = CostContext {
+            estimated: 100.0,
+            actual: 0.0,
+            ..Default::default()
+        };
+        assert!(!config(None).is_slow(Duration::from_millis(100), Some(&cost)));
+        assert!(config(None).is_slow(Duration::from_millis(600), None));
+        assert!(config(Some(50.0)).is_slow(Duration::from_millis(100), Some(&cost)));
+        assert!(!config(Some(150.0)).is_slow(Duration::from_millis(100), Some(&cost)));
+
+        // the actual cost takes precedence over the estimated one
+        let cost = CostContext {
+            estimated: 100.0,
+            actual: 200.0,
+            ..Default::default()
+        };
+        assert!(config(Some(150.0)).is_slow(Duration::from_millis(100), Some(&cost)));
+    }
+
+    #[test]
+    fn records_are_rate_limited() {
+        let limiter = RecordLimiter::new(&SlowOperationsRateLimit {
+            capacity: 2,
+            interval: Duration::from_secs(1),
+        });
+        let now = Instant::now();
+        assert_eq!(limiter.acquire(now), Some(0));
+        assert_eq!(limiter.acquire(now), Some(0));
+        assert_eq!(limiter.acquire(now), None);
+        assert_eq!(limiter.acquire(now), None);
+
+        // the next window reports the suppressed records
+        let later = now + Duration::from_secs(2);
+        assert_eq!(limiter.acquire(later), Some(2));
+        assert_eq!(limiter.acquire(later), Some(0));
+        assert_eq!(limiter.acquire(later), None);
+    }
+}
@@ -0,0 +1,477 @@
+//! Configured responses returned instead of executing matching operations.
+//!
+//! During an incident or a brownout, operations can be matched by name, document hash or client,
+//! and answered with a configured GraphQL response, like a maintenance message or a
+//! feature-disabled error, without reaching the subgraphs. Rules are changed with a configuration
+//! reload.
+
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use http::HeaderName;
+use http::HeaderValue;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::ByteString;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::context::OPERATION_NAME;
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::feature_flags;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::query_planner::fetch::QueryHash;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "static_responses", StaticResponses);
+
+const DEFAULT_ERROR_CODE: &str = "STATIC_RESPONSE";
+
+/// Configured responses returned instead of executing matching operations
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct StaticResponsesConfig {
+    /// Rules checked in order, the first matching rule gives the response
+    rules: Vec<StaticResponseRule>,
+}
+
+/// Operations answered with a static response
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct StaticResponseRule {
+    /// Name of the rule, used in logs and metrics
+    name: String,
+    /// Set to false to disable the rule without removing it
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    /// Operations matched by the rule. Every operation matches when no criteria is set
+    #[serde(default, rename = "match")]
+    conditions: OperationMatch,
+    /// Response returned to the matching operations
+    response: StaticResponse,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Criteria of the matched operations. An operation must match all the set criteria
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct OperationMatch {
+    /// Names of the matched operations
+    operation_names: Vec<String>,
+    /// Hashes of the matched operations computed by the router, in hexadecimal
+    operation_hashes: Vec<String>,
+    /// Names of the clients of the matched operations
+    client_names: Vec<String>,
+    /// Feature flags that must be enabled, with the `true` value, for the rule to match
+    feature_flags: Vec<String>,
+}
+
+/// A GraphQL response
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct StaticResponse {
+    /// HTTP status code of the response
+    #[serde(default = "default_status")]
+    status: u16,
+    /// Headers of the response
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Data of the response
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    /// Errors of the response
+    #[serde(default)]
+    errors: Vec<StaticError>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+/// A GraphQL error
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct StaticError {
+    /// Message of the error
+    message: String,
+    /// Extensions of the error. The `code` extension defaults to `STATIC_RESPONSE`
+    #[serde(default)]
+    extensions: BTreeMap<String, serde_json::Value>,
+}
+
+/// A rule with its response ready to be returned
+struct Rule {
+    name: String,
+    conditions: OperationMatch,
+    operation_hashes: Vec<QueryHash>,
+    status: StatusCode,
+    headers: MultiMap<HeaderName, HeaderValue>,
+    data: Option<serde_json_bytes::Value>,
+    errors: Vec<graphql::Error>,
+}
+
+impl Rule {
+    fn new(rule: StaticResponseRule) -> Result<Self, BoxError> {
+        let name = rule.name;
+        let response = rule.response;
+        let status = StatusCode::from_u16(response.status)
+            .map_err(|err| format!("invalid status of the {name} static response: {err}"))?;
+        let mut headers = MultiMap::new();
+        for (header, value) in response.headers {
+            headers.insert(
+                HeaderName::try_from(header.as_str()).map_err(|err| {
+                    format!("invalid header name in the {name} static response: {err}")
+                })?,
+                HeaderValue::try_from(value.as_str()).map_err(|err| {
+                    format!("invalid header value in the {name} static response: {err}")
+                })?,
+            );
+        }
+        let operation_hashes = rule
+            .conditions
+            .operation_hashes
+            .iter()
+            .map(|hash| {
+                hex::decode(hash).map(QueryHash).map_err(|err| {
+                    format!("invalid operation hash in the {name} static response: {err}")
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let errors = response
+            .errors
+            .into_iter()
+            .map(|error| {
+                let code = error
+                    .extensions
+                    .get("code")
+                    .and_then(|code| code.as_str())
+                    .unwrap_or(DEFAULT_ERROR_CODE)
+                    .to_string();
+                graphql::Error::builder()
+                    .message(error.message)
+                    .extension_code(code)
+                    .extensions(
+                        error
+                            .extensions
+                            .into_iter()
+                            .map(|(key, value)| (ByteString::from(key), value.into()))
+                            .collect::<serde_json_bytes::Map<_, _>>(),
+                    )
+                    .build()
+            })
+            .collect();
+        Ok(Self {
+            name,
+            conditions: rule.conditions,
+            operation_hashes,
+            status,
+            headers,
+            data: response.data.map(Into::into),
+            errors,
+        })
+    }
+
+    fn matches(&self, request: &supergraph::Request) -> bool {
+        let conditions = &self.conditions;
+        if !conditions
+            .feature_flags
+            .iter()
+            .all(|flag| feature_flags::is_enabled(&request.context, flag))
+        {
+            return false;
+        }
+        if !conditions.operation_names.is_empty() {
+            let operation_name = request
+                .context
+                .get::<_, String>(OPERATION_NAME)
+                .ok()
+                .flatten();
+            if !operation_name.is_some_and(|name| conditions.operation_names.contains(&name)) {
+                return false;
+            }
+        }
+        if !conditions.client_names.is_empty() {
+            let client_name = request.context.get::<_, String>(CLIENT_NAME).ok().flatten();
+            if !client_name.is_some_and(|name| conditions.client_names.contains(&name)) {
+                return false;
+            }
+        }
+        if !self.operation_hashes.is_empty() {
+            // the hash computed when the operation was parsed
+            let hash = request
+                .context
+                .extensions()
+                .with_lock(|lock| lock.get::<ParsedDocument>().map(|doc| doc.hash.clone()));
+            if !hash.is_some_and(|hash| self.operation_hashes.contains(&*hash)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn response(&self, request: supergraph::Request) -> supergraph::Response {
+        supergraph::Response::infallible_builder()
+            .and_data(self.data.clone())
+            .errors(self.errors.clone())
+            .status_code(self.status)
+            .headers(self.headers.clone())
+            .context(request.context)
+            .build()
+    }
+}
+
+struct StaticResponses {
+    rules: Arc<Vec<Rule>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for StaticResponses {
+    type Config = StaticResponsesConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let rules = init
+            .config
+            .rules
+            .into_iter()
+            .filter(|rule| rule.enabled)
+            .map(Rule::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        for rule in &rules {
+            tracing::warn!(
+                "the {} static response rule is enabled, matching operations are not executed",
+                rule.name
+            );
+        }
+        Ok(Self {
+            rules: Arc::new(rules),
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if self.rules.is_empty() {
+            return service;
+        }
+
+        let rules = self.rules.clone();
+        ServiceBuilder::new()
+            .checkpoint(move |request: supergraph::Request| {
+                match rules.iter().find(|rule| rule.matches(&request)) {
+                    Some(rule) => {
+                        tracing::debug!("operation answered by the {} static response", rule.name);
+                        u64_counter!(
+                            "apollo.router.operations.static_response",
+                            "Number of operations answered with a static response",
+                            1,
+                            "rule" = rule.name.clone()
+                        );
+                        Ok(ControlFlow::Break(rule.response(request)))
+                    }
+                    None => Ok(ControlFlow::Continue(request)),
+                }
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apollo_compiler::ast;
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::services::layers::query_analysis::ParsedDocumentInner;
+
+    const OPERATION_HASH: [u8; 32] = [7; 32];
+
+    async fn plugin(config: serde_json::Value) -> Result<StaticResponses, BoxError> {
+        StaticResponses::new(PluginInit::fake_new(
+            serde_json::from_value(config).unwrap(),
+            Default::default(),
+        ))
+        .await
+    }
+
+    fn request(operation_name: &str, client_name: &str) -> supergraph::Request {
+        let request = supergraph::Request::fake_builder()
+            .query("query Me { me { name } }")
+            .build()
+            .unwrap();
+        request
+            .context
+            .insert(OPERATION_NAME, operation_name.to_string())
+            .unwrap();
+        request
+            .context
+            .insert(CLIENT_NAME, client_name.to_string())
+            .unwrap();
+        let ast = ast::Document::parse(
+            "type Query { me: User } type User { name: String } query Me { me { name } }",
+            "",
+        )
+        .unwrap();
+        let (_schema, executable) = ast.to_mixed_validate().unwrap();
+        request.context.extensions().with_lock(|mut lock| {
+            lock.insert::<ParsedDocument>(Arc::new(ParsedDocumentInner {
+                ast,
+                executable: Arc::new(executable),
+                hash: Arc::new(QueryHash(OPERATION_HASH.to_vec())),
+            }))
+        });
+        request
+    }
+
+    fn config() -> serde_json::Value {
+        serde_json::json!({
+            "rules": [{
+                "name": "disabled",
+                "enabled": false,
+                "response": { "data": { "disabled": true } }
+            }, {
+                "name": "maintenance",
+                "match": {
+                    "operation_names": ["Me"],
+                    "client_names": ["web"]
+                },
+                "response": {
+                    "status": 503,
+                    "headers": { "retry-after": "60" },
+                    "errors": [{ "message": "down for maintenance" }]
+                }
+            }, {
+                "name": "by_hash",
+                "match": {
+                    "operation_hashes": [hex::encode(OPERATION_HASH)]
+                },
+                "response": {
+                    "data": { "me": null },
+                    "errors": [{
+                        "message": "feature disabled",
+                        "extensions": { "code": "FEATURE_DISABLED" }
+                    }]
+                }
+            }]
+        })
+    }
+
+    #[tokio::test]
+    async fn matching_operations_get_the_static_response() {
+        let plugin = plugin(config()).await.unwrap();
+        let service = plugin.supergraph_service(MockSupergraphService::new().boxed());
+        let mut response = service.oneshot(request("Me", "web")).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.response.headers()["retry-after"], "60");
+        let body = response.next_response().await.unwrap();
+        assert_eq!(body.data, None);
+        assert_eq!(body.errors[0].message, "down for maintenance");
+        assert_eq!(
+            body.errors[0].extensions.get("code"),
+            Some(&json!(DEFAULT_ERROR_CODE))
+        );
+
+        let service = plugin.supergraph_service(MockSupergraphService::new().boxed());
+        let mut response = service.oneshot(request("Me", "mobile")).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+        let body = response.next_response().await.unwrap();
+        assert_eq!(body.data, Some(json!({ "me": null })));
+        assert_eq!(
+            body.errors[0].extensions.get("code"),
+            Some(&json!("FEATURE_DISABLED"))
+        );
+    }
+
+    #[tokio::test]
+    async fn other_operations_are_executed() {
+        let mut config = config();
+        config["rules"].as_array_mut().unwrap().pop();
+        let plugin = plugin(config).await.unwrap();
+        for request in [request("Me", "mobile"), request("Other", "web")] {
+            let mut mock = MockSupergraphService::new();
+            mock.expect_call().times(1).returning(|request| {
+                Ok(supergraph::Response::fake_builder()
+                    .data(json!({ "me": { "name": "Ada" } }))
+                    .context(request.context)
+                    .build()
+                    .unwrap())
+            });
+            let service = plugin.supergraph_service(mock.boxed());
+            let mut response = service.oneshot(request).await.unwrap();
+            assert_eq!(
+                response.next_response().await.unwrap().data,
+                Some(json!({ "me": { "name": "Ada" } }))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rules_can_require_feature_flags() {
+        let plugin = plugin(serde_json::json!({
+            "rules": [{
+                "name": "maintenance",
+                "match": { "feature_flags": ["maintenance"] },
+                "response": { "status": 503 }
+            }]
+        }))
+        .await
+        .unwrap();
+        let request = request("Me", "web");
+        request
+            .context
+            .insert("apollo_feature_flags::maintenance", true)
+            .unwrap();
+        let service = plugin.supergraph_service(MockSupergraphService::new().boxed());
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let mut mock = MockSupergraphService::new();
+        mock.expect_call().times(1).returning(|request| {
+            Ok(supergraph::Response::fake_builder()
+                .context(request.context)
+                .build()
+                .unwrap())
+        });
+        let response = plugin
+            .supergraph_service(mock.boxed())
+            .oneshot(request("Me", "web"))
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn invalid_responses_are_rejected() {
+        assert!(plugin(serde_json::json!({
+            "rules": [{ "name": "status", "response": { "status": 42 } }]
+        }))
+        .await
+        .is_err());
+        assert!(plugin(serde_json::json!({
+            "rules": [{ "name": "header", "response": { "headers": { "invalid header": "value" } } }]
+        }))
+        .await
+        .is_err());
+        assert!(plugin(serde_json::json!({
+            "rules": [{
+                "name": "hash",
+                "match": { "operation_hashes": ["not hexadecimal"] },
+                "response": {}
+            }]
+        }))
+        .await
+        .is_err());
+    }
+}
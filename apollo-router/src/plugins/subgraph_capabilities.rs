@@ -0,0 +1,307 @@
+//! Per subgraph allow and deny of GraphQL features.
+//!
+//! Some subgraphs are known not to handle subscriptions, introspection or `@defer`. Instead of
+//! sending them operations they will fail on, the query plan is checked before execution, and
+//! operations that would send those features to such subgraphs are rejected with an error naming
+//! the subgraph and the feature.
+
+use std::ops::ControlFlow;
+
+use apollo_compiler::executable;
+use apollo_compiler::ExecutableDocument;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::configuration::subgraph::SubgraphConfiguration;
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::fetch::SubgraphOperation;
+use crate::query_planner::PlanNode;
+use crate::register_plugin;
+use crate::services::execution;
+
+register_plugin!("apollo", "subgraph_capabilities", SubgraphCapabilities);
+
+const UNSUPPORTED_FEATURE_ERROR_CODE: &str = "SUBGRAPH_FEATURE_NOT_SUPPORTED";
+const DEFER_DIRECTIVE_NAME: &str = "defer";
+
+/// GraphQL features supported by a subgraph. Client operations whose query plan would send the
+/// subgraph a feature it does not support are rejected before any subgraph request is made
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Capabilities {
+    /// The subgraph supports the introspection fields `__schema` and `__type`
+    introspection: bool,
+    /// The subgraph supports subscriptions
+    subscriptions: bool,
+    /// The subgraph supports the `@defer` directive
+    defer: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            introspection: true,
+            subscriptions: true,
+            defer: true,
+        }
+    }
+}
+
+/// A GraphQL feature that can be denied to subgraphs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Feature {
+    Introspection,
+    Subscriptions,
+    Defer,
+}
+
+impl Feature {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Introspection => "introspection",
+            Feature::Subscriptions => "subscriptions",
+            Feature::Defer => "defer",
+        }
+    }
+}
+
+/// Features used by subgraph operations of a query plan that their subgraph does not support
+struct Checker<'a> {
+    config: &'a SubgraphConfiguration<Capabilities>,
+    unsupported: Vec<(String, Feature)>,
+}
+
+impl<'a> Checker<'a> {
+    fn deny(&mut self, service_name: &str, feature: Feature) {
+        let denied = (service_name.to_string(), feature);
+        if !self.unsupported.contains(&denied) {
+            self.unsupported.push(denied);
+        }
+    }
+
+    fn node(&mut self, node: &PlanNode) {
+        match node {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => {
+                nodes.iter().for_each(|node| self.node(node))
+            }
+            PlanNode::Fetch(fetch) => self.operation(fetch.service_name(), &fetch.operation),
+            PlanNode::Flatten(flatten) => self.node(&flatten.node),
+            PlanNode::Defer { primary, deferred } => {
+                if let Some(node) = &primary.node {
+                    self.node(node);
+                }
+                for deferred in deferred {
+                    if let Some(node) = &deferred.node {
+                        self.node(node);
+                    }
+                }
+            }
+            PlanNode::Subscription { primary, rest } => {
+                if !self.config.get(&primary.service_name).subscriptions {
+                    self.deny(&primary.service_name, Feature::Subscriptions);
+                }
+                self.operation(&primary.service_name, &primary.operation);
+                if let Some(node) = rest {
+                    self.node(node);
+                }
+            }
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => {
+                if let Some(node) = if_clause {
+                    self.node(node);
+                }
+                if let Some(node) = else_clause {
+                    self.node(node);
+                }
+            }
+        }
+    }
+
+    fn operation(&mut self, service_name: &str, operation: &SubgraphOperation) {
+        let capabilities = self.config.get(service_name);
+        if capabilities.introspection && capabilities.defer {
+            return;
+        }
+        let Ok(document) = operation.as_parsed() else {
+            return;
+        };
+        let mut features = Vec::new();
+        for operation in document.operations.iter() {
+            used_features(document, &operation.selection_set, &mut features);
+        }
+        for feature in features {
+            let supported = match feature {
+                Feature::Introspection => capabilities.introspection,
+                Feature::Defer => capabilities.defer,
+                Feature::Subscriptions => capabilities.subscriptions,
+            };
+            if !supported {
+                self.deny(service_name, feature);
+            }
+        }
+    }
+}
+
+/// Adds the introspection and `@defer` features used by a selection set.
+fn used_features(
+    document: &ExecutableDocument,
+    selection_set: &executable::SelectionSet,
+    features: &mut Vec<Feature>,
+) {
+    for selection in &selection_set.selections {
+        match selection {
+            executable::Selection::Field(field) => {
+                if matches!(field.name.as_str(), "__schema" | "__type") {
+                    features.push(Feature::Introspection);
+                }
+                used_features(document, &field.selection_set, features);
+            }
+            executable::Selection::InlineFragment(fragment) => {
+                if fragment.directives.has(DEFER_DIRECTIVE_NAME) {
+                    features.push(Feature::Defer);
+                }
+                used_features(document, &fragment.selection_set, features);
+            }
+            executable::Selection::FragmentSpread(spread) => {
+                if spread.directives.has(DEFER_DIRECTIVE_NAME) {
+                    features.push(Feature::Defer);
+                }
+                if let Some(fragment) = spread.fragment_def(document) {
+                    used_features(document, &fragment.selection_set, features);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the subgraphs of the query plan that would be sent a feature they do not support.
+fn unsupported_features(
+    config: &SubgraphConfiguration<Capabilities>,
+    root: &PlanNode,
+) -> Vec<(String, Feature)> {
+    let mut checker = Checker {
+        config,
+        unsupported: Vec::new(),
+    };
+    checker.node(root);
+    checker.unsupported
+}
+
+struct SubgraphCapabilities {
+    config: SubgraphConfiguration<Capabilities>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for SubgraphCapabilities {
+    type Config = SubgraphConfiguration<Capabilities>;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            config: init.config,
+        })
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let config = self.config.clone();
+        ServiceBuilder::new()
+            .checkpoint(move |request: execution::Request| {
+                let unsupported = unsupported_features(&config, &request.query_plan.root);
+                if unsupported.is_empty() {
+                    return Ok(ControlFlow::Continue(request));
+                }
+
+                let errors = unsupported
+                    .into_iter()
+                    .map(|(service_name, feature)| {
+                        graphql::Error::builder()
+                            .message(format!(
+                                "subgraph '{service_name}' does not support {}",
+                                feature.as_str()
+                            ))
+                            .extension_code(UNSUPPORTED_FEATURE_ERROR_CODE)
+                            .extension("service", service_name)
+                            .extension("feature", feature.as_str())
+                            .build()
+                    })
+                    .collect();
+                Ok(ControlFlow::Break(
+                    execution::Response::builder()
+                        .errors(errors)
+                        .context(request.context)
+                        .build()?,
+                ))
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apollo_compiler::Schema;
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            product: Product
+        }
+
+        type Product {
+            id: ID!
+            name: String
+        }
+    "#;
+
+    fn features(query: &str) -> Vec<Feature> {
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let document = ExecutableDocument::parse(&schema, query, "query.graphql").unwrap();
+        let mut features = Vec::new();
+        for operation in document.operations.iter() {
+            used_features(&document, &operation.selection_set, &mut features);
+        }
+        features
+    }
+
+    #[test]
+    fn finds_introspection_and_defer() {
+        assert!(features("{ product { id name } }").is_empty());
+        assert_eq!(
+            features("{ __schema { queryType { name } } product { id } }"),
+            vec![Feature::Introspection]
+        );
+        assert_eq!(
+            features("{ product { id ... @defer { name } } }"),
+            vec![Feature::Defer]
+        );
+        assert_eq!(
+            features("{ product { id ...Name @defer } } fragment Name on Product { name }"),
+            vec![Feature::Defer]
+        );
+    }
+
+    #[test]
+    fn subgraph_overrides() {
+        let config: SubgraphConfiguration<Capabilities> =
+            serde_json::from_value(serde_json::json!({
+                "all": { "introspection": false },
+                "subgraphs": { "products": { "subscriptions": false } }
+            }))
+            .unwrap();
+        assert!(!config.get("accounts").introspection);
+        assert!(config.get("accounts").subscriptions);
+        assert!(!config.get("products").introspection);
+        assert!(!config.get("products").subscriptions);
+        assert!(config.get("products").defer);
+    }
+}
@@ -0,0 +1,367 @@
+//! Failover between the endpoints of a subgraph.
+//!
+//! Each subgraph can list several URLs, in order of preference. Requests go to the first healthy
+//! endpoint. An endpoint becomes unhealthy after a number of consecutive failures, and once in a
+//! while a request is sent to it again to probe whether it recovered.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use http::StatusCode;
+use http::Uri;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::metrics::ObservableGauge;
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::layers::ServiceBuilderExt;
+use crate::metrics;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::config_new::instruments::METER_NAME;
+use crate::register_plugin;
+use crate::services::subgraph;
+
+register_plugin!("apollo", "subgraph_failover", SubgraphFailover);
+
+/// Failover between the endpoints of subgraphs
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct FailoverConf {
+    /// Endpoints of specific subgraphs
+    subgraphs: HashMap<String, Failover>,
+}
+
+/// Endpoints of a subgraph
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Failover {
+    /// URLs of the subgraph, in order of preference. They replace the URL from the supergraph schema
+    urls: Vec<String>,
+    /// Number of consecutive failures after which an endpoint is considered unhealthy
+    #[serde(default = "default_failure_threshold")]
+    failure_threshold: u32,
+    /// Delay between the requests probing whether an unhealthy endpoint recovered
+    #[serde(
+        deserialize_with = "humantime_serde::deserialize",
+        serialize_with = "humantime_serde::serialize",
+        default = "default_recovery_interval"
+    )]
+    #[schemars(with = "String", default = "default_recovery_interval")]
+    recovery_interval: Duration,
+    /// Count the responses with GraphQL errors and no data as failures, in addition to the
+    /// connection errors and the 5xx status codes
+    #[serde(default)]
+    graphql_errors: bool,
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_recovery_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    uri: Uri,
+    url: String,
+    consecutive_failures: u32,
+    /// Set while the endpoint is unhealthy
+    next_probe: Option<Instant>,
+}
+
+/// The endpoints of a subgraph, with their health.
+#[derive(Debug)]
+struct Endpoints {
+    subgraph_name: String,
+    failure_threshold: u32,
+    recovery_interval: Duration,
+    graphql_errors: bool,
+    endpoints: Mutex<Vec<Endpoint>>,
+}
+
+impl Endpoints {
+    fn new(subgraph_name: &str, failover: &Failover) -> Result<Self, BoxError> {
+        if failover.urls.is_empty() {
+            return Err(format!("no URL configured for the subgraph '{subgraph_name}'").into());
+        }
+        if failover.failure_threshold == 0 {
+            return Err("the failure threshold must be at least 1".into());
+        }
+        let endpoints = failover
+            .urls
+            .iter()
+            .map(|url| {
+                Ok(Endpoint {
+                    uri: Uri::from_str(url)?,
+                    url: url.clone(),
+                    consecutive_failures: 0,
+                    next_probe: None,
+                })
+            })
+            .collect::<Result<_, BoxError>>()?;
+
+        Ok(Self {
+            subgraph_name: subgraph_name.to_string(),
+            failure_threshold: failover.failure_threshold,
+            recovery_interval: failover.recovery_interval,
+            graphql_errors: failover.graphql_errors,
+            endpoints: Mutex::new(endpoints),
+        })
+    }
+
+    /// Returns the endpoint the next request goes to: the first healthy one, or an unhealthy one
+    /// preferred over it that is due for a probe.
+    fn select(&self, now: Instant) -> Uri {
+        let mut endpoints = self.endpoints.lock();
+        for endpoint in endpoints.iter_mut() {
+            match endpoint.next_probe {
+                None => return endpoint.uri.clone(),
+                Some(next_probe) if next_probe <= now => {
+                    endpoint.next_probe = Some(now + self.recovery_interval);
+                    return endpoint.uri.clone();
+                }
+                Some(_) => {}
+            }
+        }
+        // every endpoint is unhealthy, so fall back to the preferred one
+        endpoints[0].uri.clone()
+    }
+
+    /// Records the outcome of a request sent to an endpoint.
+    fn record(&self, uri: &Uri, succeeded: bool, now: Instant) {
+        let mut endpoints = self.endpoints.lock();
+        let Some(endpoint) = endpoints.iter_mut().find(|endpoint| &endpoint.uri == uri) else {
+            return;
+        };
+        if succeeded {
+            endpoint.consecutive_failures = 0;
+            if endpoint.next_probe.take().is_some() {
+                tracing::info!(
+                    "subgraph '{}' endpoint {} recovered",
+                    self.subgraph_name,
+                    endpoint.url
+                );
+                self.count_transition(endpoint, true);
+            }
+        } else {
+            endpoint.consecutive_failures += 1;
+            if endpoint.next_probe.is_none()
+                && endpoint.consecutive_failures >= self.failure_threshold
+            {
+                tracing::warn!(
+                    "subgraph '{}' endpoint {} is unhealthy after {} consecutive failures",
+                    self.subgraph_name,
+                    endpoint.url,
+                    endpoint.consecutive_failures
+                );
+                endpoint.next_probe = Some(now + self.recovery_interval);
+                self.count_transition(endpoint, false);
+            }
+        }
+    }
+
+    fn count_transition(&self, endpoint: &Endpoint, healthy: bool) {
+        u64_counter!(
+            "apollo.router.operations.subgraph.failover",
+            "Number of times a subgraph endpoint became unhealthy or recovered",
+            1,
+            "subgraph.name" = self.subgraph_name.clone(),
+            "endpoint.url" = endpoint.url.clone(),
+            "endpoint.healthy" = healthy
+        );
+    }
+
+    /// Returns the endpoint requests currently go to, without probes.
+    fn active(&self) -> String {
+        let endpoints = self.endpoints.lock();
+        endpoints
+            .iter()
+            .find(|endpoint| endpoint.next_probe.is_none())
+            .unwrap_or(&endpoints[0])
+            .url
+            .clone()
+    }
+
+    fn urls(&self) -> Vec<String> {
+        self.endpoints
+            .lock()
+            .iter()
+            .map(|endpoint| endpoint.url.clone())
+            .collect()
+    }
+
+    fn is_failure(&self, result: &Result<subgraph::Response, BoxError>) -> bool {
+        match result {
+            Err(_) => true,
+            Ok(response) => {
+                let body = response.response.body();
+                response.response.status() >= StatusCode::INTERNAL_SERVER_ERROR
+                    || (self.graphql_errors
+                        && !body.errors.is_empty()
+                        && matches!(body.data, None | Some(Value::Null)))
+            }
+        }
+    }
+}
+
+struct SubgraphFailover {
+    subgraphs: HashMap<String, Arc<Endpoints>>,
+    _active_endpoint_gauge: Option<ObservableGauge<u64>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for SubgraphFailover {
+    type Config = FailoverConf;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let subgraphs: HashMap<String, Arc<Endpoints>> = init
+            .config
+            .subgraphs
+            .iter()
+            .map(|(name, failover)| Ok((name.clone(), Arc::new(Endpoints::new(name, failover)?))))
+            .collect::<Result<_, BoxError>>()?;
+
+        let active_endpoint_gauge = (!subgraphs.is_empty()).then(|| {
+            let subgraphs = subgraphs.values().cloned().collect::<Vec<_>>();
+            metrics::meter_provider()
+                .meter(METER_NAME)
+                .u64_observable_gauge("apollo.router.subgraph.endpoint.active")
+                .with_description("Whether requests currently go to a subgraph endpoint")
+                .with_callback(move |gauge| {
+                    for endpoints in &subgraphs {
+                        let active = endpoints.active();
+                        for url in endpoints.urls() {
+                            gauge.observe(
+                                (url == active) as u64,
+                                &[
+                                    KeyValue::new("subgraph.name", endpoints.subgraph_name.clone()),
+                                    KeyValue::new("endpoint.url", url),
+                                ],
+                            );
+                        }
+                    }
+                })
+                .init()
+        });
+
+        Ok(Self {
+            subgraphs,
+            _active_endpoint_gauge: active_endpoint_gauge,
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let Some(endpoints) = self.subgraphs.get(name).cloned() else {
+            return service;
+        };
+
+        let select_endpoints = endpoints.clone();
+        ServiceBuilder::new()
+            .map_request(move |mut request: subgraph::Request| {
+                *request.subgraph_request.uri_mut() = select_endpoints.select(Instant::now());
+                request
+            })
+            .map_future_with_request_data(
+                |request: &subgraph::Request| request.subgraph_request.uri().clone(),
+                move |uri: Uri, future: BoxFuture<'static, Result<subgraph::Response, BoxError>>| {
+                    let endpoints = endpoints.clone();
+                    async move {
+                        let result = future.await;
+                        endpoints.record(&uri, !endpoints.is_failure(&result), Instant::now());
+                        result
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints() -> Endpoints {
+        Endpoints::new(
+            "products",
+            &Failover {
+                urls: vec![
+                    "http://products.eu:4001/graphql".to_string(),
+                    "http://products.us:4001/graphql".to_string(),
+                ],
+                failure_threshold: 2,
+                recovery_interval: Duration::from_secs(10),
+                graphql_errors: false,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn failover_and_recovery() {
+        let endpoints = endpoints();
+        let primary = Uri::from_static("http://products.eu:4001/graphql");
+        let fallback = Uri::from_static("http://products.us:4001/graphql");
+        let now = Instant::now();
+
+        assert_eq!(endpoints.select(now), primary);
+        endpoints.record(&primary, false, now);
+        assert_eq!(endpoints.select(now), primary);
+        endpoints.record(&primary, false, now);
+        assert_eq!(endpoints.select(now), fallback);
+        assert_eq!(endpoints.active(), "http://products.us:4001/graphql");
+
+        // a failed probe keeps the primary endpoint unhealthy
+        let probe = now + Duration::from_secs(10);
+        assert_eq!(endpoints.select(probe), primary);
+        assert_eq!(endpoints.select(probe), fallback);
+        endpoints.record(&primary, false, probe);
+        assert_eq!(endpoints.select(probe + Duration::from_secs(5)), fallback);
+
+        // a successful probe brings it back
+        let probe = probe + Duration::from_secs(10);
+        assert_eq!(endpoints.select(probe), primary);
+        endpoints.record(&primary, true, probe);
+        assert_eq!(endpoints.select(probe), primary);
+        assert_eq!(endpoints.active(), "http://products.eu:4001/graphql");
+    }
+
+    #[test]
+    fn failures_are_consecutive() {
+        let endpoints = endpoints();
+        let primary = Uri::from_static("http://products.eu:4001/graphql");
+        let now = Instant::now();
+
+        endpoints.record(&primary, false, now);
+        endpoints.record(&primary, true, now);
+        endpoints.record(&primary, false, now);
+        assert_eq!(endpoints.select(now), primary);
+    }
+
+    #[tokio::test]
+    async fn invalid_configuration() {
+        let config = serde_json::json!({
+            "subgraphs": { "products": { "urls": [] } }
+        });
+        assert!(crate::plugin::plugins()
+            .find(|factory| factory.name == "apollo.subgraph_failover")
+            .unwrap()
+            .create_instance_without_schema(&config)
+            .await
+            .is_err());
+    }
+}
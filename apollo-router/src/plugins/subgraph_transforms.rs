@@ -0,0 +1,362 @@
+//! Declarative transformations of subgraph requests and responses.
+//!
+//! Covers the common tweaks that would otherwise need a Rhai script: rewriting the path of the
+//! subgraph URL, adding query parameters, renaming response extensions and dropping errors.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use http::uri::PathAndQuery;
+use http::Uri;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::configuration::subgraph::SubgraphConfiguration;
+use crate::graphql;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::Context;
+
+register_plugin!("apollo", "subgraph_transforms", SubgraphTransforms);
+
+/// Transformations of the requests and responses of a subgraph
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Transforms {
+    /// Path of the subgraph URL, replacing the one from the supergraph schema
+    path: Option<String>,
+    /// Query parameters added to the subgraph URL
+    query_parameters: Vec<QueryParameter>,
+    /// Extensions of the subgraph responses to rename, from their current name to their new name
+    rename_extensions: HashMap<String, String>,
+    /// Errors removed from the subgraph responses
+    drop_errors: Vec<DropError>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+/// Query parameter added to the subgraph URL
+enum QueryParameter {
+    /// Query parameter with a static value
+    Static(QueryParameterStatic),
+    /// Query parameter with a value coming from a context key (works only for a string in the context)
+    FromContext(QueryParameterFromContext),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// Query parameter with a static value
+struct QueryParameterStatic {
+    /// Query parameter name
+    name: String,
+    /// Query parameter value
+    value: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// Query parameter with a value coming from a context key
+struct QueryParameterFromContext {
+    /// Query parameter name
+    name: String,
+    /// Context key holding the query parameter value
+    from_context: String,
+}
+
+/// Errors removed from the subgraph responses. An error is removed if it matches every condition
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct DropError {
+    /// Error code, from the `code` extension of the error
+    code: Option<String>,
+    /// Regex matching the error message
+    message: Option<String>,
+}
+
+/// Transformations of a subgraph, ready to be applied.
+#[derive(Debug, Default)]
+struct SubgraphTransform {
+    path: Option<String>,
+    query_parameters: Vec<QueryParameter>,
+    rename_extensions: HashMap<String, String>,
+    drop_errors: Vec<(Option<String>, Option<Regex>)>,
+}
+
+impl SubgraphTransform {
+    fn new(transforms: &Transforms) -> Result<Self, BoxError> {
+        if let Some(path) = &transforms.path {
+            if !path.starts_with('/') {
+                return Err(format!("the subgraph path '{path}' must start with '/'").into());
+            }
+        }
+        let drop_errors = transforms
+            .drop_errors
+            .iter()
+            .map(|drop_error| {
+                if drop_error.code.is_none() && drop_error.message.is_none() {
+                    return Err(BoxError::from(
+                        "errors to drop must be matched on their code or message",
+                    ));
+                }
+                let message = drop_error.message.as_deref().map(Regex::new).transpose()?;
+                Ok((drop_error.code.clone(), message))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            path: transforms.path.clone(),
+            query_parameters: transforms.query_parameters.clone(),
+            rename_extensions: transforms.rename_extensions.clone(),
+            drop_errors,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.path.is_none()
+            && self.query_parameters.is_empty()
+            && self.rename_extensions.is_empty()
+            && self.drop_errors.is_empty()
+    }
+
+    fn transform_uri(&self, uri: &Uri, context: &Context) -> Result<Uri, BoxError> {
+        let path = self.path.as_deref().unwrap_or_else(|| uri.path());
+        let mut query =
+            url::form_urlencoded::Serializer::new(uri.query().unwrap_or_default().to_string());
+        for parameter in &self.query_parameters {
+            match parameter {
+                QueryParameter::Static(QueryParameterStatic { name, value }) => {
+                    query.append_pair(name, value);
+                }
+                QueryParameter::FromContext(QueryParameterFromContext { name, from_context }) => {
+                    if let Some(value) = context.get::<_, String>(from_context)? {
+                        query.append_pair(name, &value);
+                    }
+                }
+            }
+        }
+        let query = query.finish();
+        let path_and_query = if query.is_empty() {
+            path.parse::<PathAndQuery>()?
+        } else {
+            format!("{path}?{query}").parse::<PathAndQuery>()?
+        };
+
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = Some(path_and_query);
+        Ok(Uri::from_parts(parts)?)
+    }
+
+    fn transform_response(&self, response: &mut graphql::Response) {
+        for (from, to) in &self.rename_extensions {
+            if let Some(value) = response.extensions.remove(from.as_str()) {
+                response.extensions.insert(to.as_str().into(), value);
+            }
+        }
+        if !self.drop_errors.is_empty() {
+            response.errors.retain(|error| {
+                !self.drop_errors.iter().any(|(code, message)| {
+                    code.as_ref().map_or(true, |code| {
+                        error.extensions.get("code").and_then(|c| c.as_str()) == Some(code.as_str())
+                    }) && message
+                        .as_ref()
+                        .map_or(true, |message| message.is_match(&error.message))
+                })
+            });
+        }
+    }
+}
+
+struct SubgraphTransforms {
+    all: Arc<SubgraphTransform>,
+    subgraphs: HashMap<String, Arc<SubgraphTransform>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for SubgraphTransforms {
+    type Config = SubgraphConfiguration<Transforms>;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            all: Arc::new(SubgraphTransform::new(&init.config.all)?),
+            subgraphs: init
+                .config
+                .subgraphs
+                .iter()
+                .map(|(name, transforms)| {
+                    Ok((name.clone(), Arc::new(SubgraphTransform::new(transforms)?)))
+                })
+                .collect::<Result<_, BoxError>>()?,
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let transform = self.subgraphs.get(name).unwrap_or(&self.all).clone();
+        if transform.is_empty() {
+            return service;
+        }
+
+        let response_transform = transform.clone();
+        ServiceBuilder::new()
+            .map_request(move |mut request: subgraph::Request| {
+                let uri = transform.transform_uri(request.subgraph_request.uri(), &request.context);
+                match uri {
+                    Ok(uri) => *request.subgraph_request.uri_mut() = uri,
+                    Err(error) => {
+                        tracing::error!("could not transform the subgraph URL: {error}");
+                    }
+                }
+                request
+            })
+            .map_response(move |mut response: subgraph::Response| {
+                response_transform.transform_response(response.response.body_mut());
+                response
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json_bytes::json;
+    use tower::Service;
+
+    use super::*;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::DynPlugin;
+    use crate::services::SubgraphRequest;
+    use crate::services::SubgraphResponse;
+
+    async fn plugin(config: serde_json::Value) -> Box<dyn DynPlugin> {
+        crate::plugin::plugins()
+            .find(|factory| factory.name == "apollo.subgraph_transforms")
+            .expect("Plugin not found")
+            .create_instance_without_schema(&config)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn transform_request_url() {
+        let mut mock_service = MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .withf(|request| {
+                request.subgraph_request.uri()
+                    == &Uri::from_str(
+                        "http://products:4001/v2/graphql?version=1&tenant=acme&region=eu+west",
+                    )
+                    .unwrap()
+            })
+            .times(1)
+            .returning(|request: SubgraphRequest| {
+                Ok(SubgraphResponse::fake_builder()
+                    .context(request.context)
+                    .build())
+            });
+
+        let plugin = plugin(serde_json::json!({
+            "subgraphs": {
+                "products": {
+                    "path": "/v2/graphql",
+                    "query_parameters": [
+                        { "name": "tenant", "from_context": "tenant_id" },
+                        { "name": "region", "value": "eu west" }
+                    ]
+                }
+            }
+        }))
+        .await;
+        let mut service =
+            plugin.subgraph_service("products", subgraph::BoxService::new(mock_service));
+
+        let context = Context::new();
+        context.insert("tenant_id", "acme".to_string()).unwrap();
+        let request = SubgraphRequest::fake_builder()
+            .subgraph_request(
+                http::Request::builder()
+                    .uri("http://products:4001/graphql?version=1")
+                    .body(graphql::Request::default())
+                    .unwrap(),
+            )
+            .context(context)
+            .build();
+        service.ready().await.unwrap().call(request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn transform_response() {
+        let mut mock_service = MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(|request: SubgraphRequest| {
+                Ok(SubgraphResponse::fake_builder()
+                    .context(request.context)
+                    .extension("cost", json!(3))
+                    .errors(vec![
+                        graphql::Error::builder()
+                            .message("deprecated field")
+                            .extension_code("DEPRECATED")
+                            .build(),
+                        graphql::Error::builder()
+                            .message("could not fetch the price")
+                            .extension_code("UPSTREAM")
+                            .build(),
+                        graphql::Error::builder()
+                            .message("could not fetch the stock")
+                            .extension_code("UPSTREAM")
+                            .build(),
+                    ])
+                    .build())
+            });
+
+        let plugin = plugin(serde_json::json!({
+            "all": {
+                "rename_extensions": { "cost": "subgraph_cost" },
+                "drop_errors": [
+                    { "code": "DEPRECATED" },
+                    { "code": "UPSTREAM", "message": "price$" }
+                ]
+            }
+        }))
+        .await;
+        let mut service =
+            plugin.subgraph_service("products", subgraph::BoxService::new(mock_service));
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(SubgraphRequest::fake_builder().build())
+            .await
+            .unwrap();
+        let body = response.response.body();
+        assert_eq!(body.extensions.get("subgraph_cost"), Some(&json!(3)));
+        assert!(!body.extensions.contains_key("cost"));
+        assert_eq!(body.errors.len(), 1);
+        assert_eq!(body.errors[0].message, "could not fetch the stock");
+    }
+
+    #[tokio::test]
+    async fn invalid_transforms() {
+        let config = serde_json::json!({
+            "all": { "drop_errors": [{}] }
+        });
+        assert!(crate::plugin::plugins()
+            .find(|factory| factory.name == "apollo.subgraph_transforms")
+            .unwrap()
+            .create_instance_without_schema(&config)
+            .await
+            .is_err());
+    }
+}
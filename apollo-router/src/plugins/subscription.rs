@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::ops::ControlFlow;
+use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
 
@@ -27,6 +28,8 @@ use tracing_futures::Instrument;
 use uuid::Uuid;
 
 use crate::context::Context;
+use crate::context::OPERATION_KIND;
+use crate::context::OPERATION_NAME;
 use crate::graphql;
 use crate::graphql::Response;
 use crate::json_ext::Object;
@@ -35,12 +38,22 @@ use crate::notification::Notify;
 use crate::notification::NotifyError;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::subscription_coordination::CallbackCoordination;
+use crate::plugins::subscription_coordination::Coordinator;
+use crate::plugins::subscription_quotas;
+use crate::plugins::subscription_quotas::QuotaPermit;
+use crate::plugins::subscription_quotas::QuotasConfig;
+use crate::plugins::subscription_quotas::SubscriptionQuotas;
+use crate::protocols::kafka::KafkaMode;
+use crate::protocols::kafka::KafkaSubscription;
 use crate::protocols::websocket::WebSocketProtocol;
 use crate::query_planner::OperationKind;
 use crate::register_plugin;
 use crate::services::router;
 use crate::services::router::body::RouterBody;
 use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::services::supergraph::event_buffer::EventBufferConfig;
 use crate::Endpoint;
 use crate::ListenAddr;
 
@@ -57,6 +70,8 @@ const CALLBACK_SUBSCRIPTION_HEADER_VALUE: &str = "callback/1.0";
 pub(crate) struct Subscription {
     notify: Notify<String, graphql::Response>,
     callback_hmac_key: Option<String>,
+    quotas: Option<Arc<SubscriptionQuotas>>,
+    pub(crate) coordinator: Option<Arc<Coordinator>>,
     pub(crate) config: SubscriptionConfig,
 }
 
@@ -71,10 +86,21 @@ pub(crate) struct SubscriptionConfig {
     /// Enable the deduplication of subscription (for example if we detect the exact same request to subgraph we won't open a new websocket to the subgraph in passthrough mode)
     /// (default: true)
     pub(crate) enable_deduplication: bool,
+    /// What makes subscriptions identical for the deduplication
+    pub(crate) deduplication_key: DeduplicationKeyConfig,
     /// This is a limit to only have maximum X opened subscriptions at the same time. By default if it's not set there is no limit.
     pub(crate) max_opened_subscriptions: Option<usize>,
     /// It represent the capacity of the in memory queue to know how many events we can keep in a buffer
     pub(crate) queue_capacity: Option<usize>,
+    /// Buffer the events waiting to be sent to each subscription's client, reading the events from
+    /// subgraphs even when a client can't keep up. By default events are not buffered per
+    /// subscription.
+    pub(crate) event_buffer: Option<EventBufferConfig>,
+    /// Limits on the number of active subscriptions of each client
+    pub(crate) quotas: QuotasConfig,
+    /// Relay the callbacks of the callback mode subscriptions between router replicas, so the
+    /// callbacks reach the replica the client is connected to without sticky sessions
+    pub(crate) callback_coordination: Option<CallbackCoordination>,
 }
 
 impl Default for SubscriptionConfig {
@@ -83,8 +109,33 @@ impl Default for SubscriptionConfig {
             enabled: true,
             mode: Default::default(),
             enable_deduplication: true,
+            deduplication_key: Default::default(),
             max_opened_subscriptions: None,
             queue_capacity: None,
+            event_buffer: None,
+            quotas: Default::default(),
+            callback_coordination: None,
+        }
+    }
+}
+
+/// Headers that grant access to data, which can't be ignored by the deduplication
+const AUTHORIZATION_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
+/// What makes subscriptions identical for the deduplication
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct DeduplicationKeyConfig {
+    /// Subgraph request headers ignored when comparing subscriptions, because they change with
+    /// every request without changing the events, like tracing headers. Authorization headers
+    /// can't be ignored.
+    pub(crate) ignored_headers: Vec<String>,
+}
+
+impl Default for DeduplicationKeyConfig {
+    fn default() -> Self {
+        Self {
+            ignored_headers: vec!["traceparent".to_string(), "tracestate".to_string()],
         }
     }
 }
@@ -96,10 +147,23 @@ pub(crate) struct SubscriptionModeConfig {
     pub(crate) callback: Option<CallbackMode>,
     /// Enable passthrough mode for subgraph(s)
     pub(crate) passthrough: Option<SubgraphPassthroughMode>,
+    /// Consume the events of the subscriptions of subgraph(s) from Kafka topics, instead of
+    /// sending the subscriptions to the subgraphs
+    pub(crate) kafka: Option<KafkaMode>,
 }
 
 impl SubscriptionModeConfig {
     pub(crate) fn get_subgraph_config(&self, service_name: &str) -> Option<SubscriptionMode> {
+        if let Some(kafka_cfg) = &self.kafka {
+            if let Some(subgraph_cfg) = kafka_cfg.subgraphs.get(service_name) {
+                return SubscriptionMode::Kafka(KafkaSubscription {
+                    brokers: kafka_cfg.brokers.clone(),
+                    subgraph: subgraph_cfg.clone(),
+                })
+                .into();
+            }
+        }
+
         if let Some(passthrough_cfg) = &self.passthrough {
             if let Some(subgraph_cfg) = passthrough_cfg.subgraphs.get(service_name) {
                 return SubscriptionMode::Passthrough(subgraph_cfg.clone()).into();
@@ -141,6 +205,8 @@ pub(crate) enum SubscriptionMode {
     Callback(CallbackMode),
     /// Using websocket to directly connect to subgraph
     Passthrough(WebSocketConfiguration),
+    /// Consuming the events from Kafka
+    Kafka(KafkaSubscription),
 }
 
 /// Using a callback url
@@ -238,13 +304,60 @@ impl Plugin for Subscription {
     type Config = SubscriptionConfig;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        if let Some(header) = init
+            .config
+            .deduplication_key
+            .ignored_headers
+            .iter()
+            .find(|header| {
+                AUTHORIZATION_HEADERS
+                    .iter()
+                    .any(|authorization| header.eq_ignore_ascii_case(authorization))
+            })
+        {
+            return Err(format!(
+                "the {header} header can't be ignored by the subscription deduplication, subscriptions of different users would be shared"
+            )
+            .into());
+        }
+
+        if let Some(kafka) = &init.config.mode.kafka {
+            kafka.validate()?;
+        }
+
+        if init.config.callback_coordination.is_some() && init.config.mode.callback.is_none() {
+            return Err("the callback coordination requires the callback mode".into());
+        }
+        let coordinator = match &init.config.callback_coordination {
+            Some(config) => Some(Arc::new(
+                Coordinator::new(config, init.notify.clone()).await?,
+            )),
+            None => None,
+        };
+
         let mut callback_hmac_key = None;
         if init.config.mode.callback.is_some() {
-            callback_hmac_key = Some(
-                SUBSCRIPTION_CALLBACK_HMAC_KEY
+            let hmac_key = match &coordinator {
+                // the replicas must verify the callbacks of each other's subscriptions
+                Some(coordinator) => {
+                    let candidate = SUBSCRIPTION_CALLBACK_HMAC_KEY
+                        .get()
+                        .cloned()
+                        .unwrap_or_else(|| Uuid::new_v4().to_string());
+                    let shared_hmac_key = coordinator.shared_hmac_key(candidate).await?;
+                    let hmac_key = SUBSCRIPTION_CALLBACK_HMAC_KEY
+                        .get_or_init(|| shared_hmac_key.clone())
+                        .clone();
+                    if hmac_key != shared_hmac_key {
+                        return Err("the callback verifiers of this router differ from the other replicas, restart it to enable the callback coordination".into());
+                    }
+                    hmac_key
+                }
+                None => SUBSCRIPTION_CALLBACK_HMAC_KEY
                     .get_or_init(|| Uuid::new_v4().to_string())
                     .clone(),
-            );
+            };
+            callback_hmac_key = Some(hmac_key);
             #[cfg(not(test))]
             init.notify
                 .set_ttl(
@@ -262,22 +375,86 @@ impl Plugin for Subscription {
         Ok(Subscription {
             notify: init.notify,
             callback_hmac_key,
+            quotas: SubscriptionQuotas::new(
+                &init.config.quotas,
+                init.config.max_opened_subscriptions,
+            ),
+            coordinator,
             config: init.config,
         })
     }
 
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let Some(quotas) = self.quotas.clone().filter(|_| self.config.enabled) else {
+            return service;
+        };
+
+        ServiceBuilder::new()
+            .map_response(|response: supergraph::Response| {
+                let permit = response
+                    .context
+                    .extensions()
+                    .with_lock(|mut lock| lock.remove::<QuotaPermit>());
+                match permit {
+                    Some(permit) => subscription_quotas::hold(permit, response),
+                    None => response,
+                }
+            })
+            .oneshot_checkpoint_async(move |request: supergraph::Request| {
+                let quotas = quotas.clone();
+                async move {
+                    let is_subscription = request
+                        .context
+                        .get::<_, OperationKind>(OPERATION_KIND)
+                        .ok()
+                        .flatten()
+                        == Some(OperationKind::Subscription);
+                    if !is_subscription {
+                        return Ok(ControlFlow::Continue(request));
+                    }
+                    let operation_name = request
+                        .context
+                        .get::<_, String>(OPERATION_NAME)
+                        .ok()
+                        .flatten()
+                        .unwrap_or_default();
+                    match quotas.acquire(&request, &operation_name).await {
+                        Ok(permit) => {
+                            request
+                                .context
+                                .extensions()
+                                .with_lock(|mut lock| lock.insert(permit));
+                            Ok(ControlFlow::Continue(request))
+                        }
+                        Err(exceeded) => Ok(ControlFlow::Break(
+                            subscription_quotas::rejected_response(exceeded, request.context),
+                        )),
+                    }
+                }
+            })
+            .service(service)
+            .boxed()
+    }
+
     fn subgraph_service(
         &self,
         _subgraph_name: &str,
         service: subgraph::BoxService,
     ) -> subgraph::BoxService {
         let enabled = self.config.enabled
-            && (self.config.mode.callback.is_some() || self.config.mode.passthrough.is_some());
+            && (self.config.mode.callback.is_some()
+                || self.config.mode.passthrough.is_some()
+                || self.config.mode.kafka.is_some());
+        let coordinator = self.coordinator.clone();
         ServiceBuilder::new()
             .checkpoint(move |req: subgraph::Request| {
                 if req.operation_kind == OperationKind::Subscription && !enabled {
                     Ok(ControlFlow::Break(subgraph::Response::builder().context(req.context).error(graphql::Error::builder().message("cannot execute a subscription if it's not enabled in the configuration").extension_code("SUBSCRIPTION_DISABLED").build()).extensions(Object::default()).build()))
                 } else {
+                    if let Some(coordinator) = coordinator.as_ref().filter(|_| req.operation_kind == OperationKind::Subscription) {
+                        // The subgraph service serves the callback subscriptions with it
+                        req.context.extensions().with_lock(|mut lock| lock.insert(coordinator.clone()));
+                    }
                     Ok(ControlFlow::Continue(req))
                 }
             }).service(service)
@@ -296,8 +473,13 @@ impl Plugin for Subscription {
                 .expect("cannot run subscription in callback mode without a hmac key");
             let endpoint = Endpoint::from_router_service(
                 format!("{path}/:callback"),
-                CallbackService::new(self.notify.clone(), path.to_string(), callback_hmac_key)
-                    .boxed(),
+                CallbackService::new(
+                    self.notify.clone(),
+                    path.to_string(),
+                    callback_hmac_key,
+                    self.coordinator.clone(),
+                )
+                .boxed(),
             );
             map.insert(listen.clone().unwrap_or_else(default_listen_addr), endpoint);
         }
@@ -314,7 +496,7 @@ pub(crate) enum CallbackPayload {
 }
 
 impl CallbackPayload {
-    fn id(&self) -> &String {
+    pub(crate) fn id(&self) -> &String {
         match self {
             CallbackPayload::Subscription(subscription_payload) => subscription_payload.id(),
         }
@@ -390,6 +572,7 @@ pub(crate) struct CallbackService {
     notify: Notify<String, graphql::Response>,
     path: String,
     callback_hmac_key: String,
+    coordinator: Option<Arc<Coordinator>>,
 }
 
 impl CallbackService {
@@ -397,11 +580,13 @@ impl CallbackService {
         notify: Notify<String, graphql::Response>,
         path: String,
         callback_hmac_key: String,
+        coordinator: Option<Arc<Coordinator>>,
     ) -> Self {
         Self {
             notify,
             path,
             callback_hmac_key,
+            coordinator,
         }
     }
 }
@@ -419,6 +604,7 @@ impl Service<router::Request> for CallbackService {
         let mut notify = self.notify.clone();
         let path = self.path.clone();
         let callback_hmac_key = self.callback_hmac_key.clone();
+        let coordinator = self.coordinator.clone();
         Box::pin(
             async move {
                 let (parts, body) = req.router_request.into_parts();
@@ -484,6 +670,29 @@ impl Service<router::Request> for CallbackService {
                             return Ok(res);
                         }
 
+                        // The subscription may be served by another router replica
+                        if let Some(coordinator) = &coordinator {
+                            if !matches!(cb_body, CallbackPayload::Subscription(SubscriptionPayload::Heartbeat { .. }))
+                                && !notify.exist(id.clone()).await?
+                            {
+                                let is_check = matches!(cb_body, CallbackPayload::Subscription(SubscriptionPayload::Check { .. }));
+                                let (status, body) = match (&cb_body, coordinator.relay(&cb_body).await) {
+                                    (_, false) => (StatusCode::NOT_FOUND, "suscription doesn't exist"),
+                                    (CallbackPayload::Subscription(SubscriptionPayload::Check { .. }), true) => (StatusCode::NO_CONTENT, ""),
+                                    (CallbackPayload::Subscription(SubscriptionPayload::Complete { .. }), true) => (StatusCode::ACCEPTED, ""),
+                                    (_, true) => (StatusCode::OK, ""),
+                                };
+                                let mut response = http::Response::builder().status(status);
+                                if is_check {
+                                    response = response.header(HeaderName::from_static(CALLBACK_SUBSCRIPTION_HEADER_NAME), HeaderValue::from_static(CALLBACK_SUBSCRIPTION_HEADER_VALUE));
+                                }
+                                return Ok(router::Response {
+                                    response: response.body(body.into()).map_err(BoxError::from)?,
+                                    context: req.context,
+                                });
+                            }
+                        }
+
                         match cb_body {
                             CallbackPayload::Subscription(SubscriptionPayload::Next {
                                 mut payload,
@@ -555,7 +764,13 @@ impl Service<router::Request> for CallbackService {
                                     });
                                 }
 
-                                let (mut valid_ids, invalid_ids) = notify.invalid_ids(ids).await?;
+                                let (mut valid_ids, mut invalid_ids) = notify.invalid_ids(ids).await?;
+                                if let Some(coordinator) = &coordinator {
+                                    // The subscriptions served by other router replicas are valid too
+                                    let served_elsewhere = coordinator.served_elsewhere(&invalid_ids).await;
+                                    invalid_ids.retain(|id| !served_elsewhere.contains(id));
+                                    valid_ids.extend(served_elsewhere);
+                                }
                                 if invalid_ids.is_empty() {
                                     Ok(router::Response {
                                         response: http::Response::builder()
@@ -721,6 +936,7 @@ mod tests {
     use crate::http_ext;
     use crate::plugin::test::MockSubgraphService;
     use crate::plugin::DynPlugin;
+    use crate::plugins::authorization::CacheKeyMetadata;
     use crate::services::SubgraphRequest;
     use crate::services::SubgraphResponse;
     use crate::Notify;
@@ -1177,6 +1393,69 @@ mod tests {
         assert_eq!(subgraph_response.response.body(), &graphql::Response::builder().data(serde_json_bytes::Value::Null).error(graphql::Error::builder().message("cannot execute a subscription if it's not enabled in the configuration").extension_code("SUBSCRIPTION_DISABLED").build()).extensions(Object::default()).build());
     }
 
+    #[tokio::test]
+    async fn it_test_deduplication_key_keeps_authorization_headers() {
+        let result = crate::plugin::plugins()
+            .find(|factory| factory.name == APOLLO_SUBSCRIPTION_PLUGIN)
+            .expect("Plugin not found")
+            .create_instance_without_schema(&serde_json::json!({
+                "deduplication_key": { "ignored_headers": ["traceparent", "Authorization"] }
+            }))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_test_deduplication_key() {
+        let ignored_headers = DeduplicationKeyConfig::default().ignored_headers;
+        let subgraph_request = |headers: &[(&str, &str)]| {
+            let mut builder = http::Request::builder().uri("http://localhost:4001/graphql");
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            SubgraphRequest::fake_builder()
+                .operation_kind(OperationKind::Subscription)
+                .subgraph_request(
+                    builder
+                        .body(
+                            Request::builder()
+                                .query("subscription { userWasCreated { name } }")
+                                .build(),
+                        )
+                        .unwrap(),
+                )
+                .build()
+        };
+
+        let key = subgraph_request(&[("authorization", "a"), ("x-tenant", "1")])
+            .to_sha256(&ignored_headers);
+        assert_eq!(
+            key,
+            subgraph_request(&[
+                ("x-tenant", "1"),
+                (
+                    "traceparent",
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                ),
+                ("authorization", "a"),
+            ])
+            .to_sha256(&ignored_headers)
+        );
+        assert_ne!(
+            key,
+            subgraph_request(&[("authorization", "b"), ("x-tenant", "1")])
+                .to_sha256(&ignored_headers)
+        );
+
+        let mut scoped = subgraph_request(&[("authorization", "a"), ("x-tenant", "1")]);
+        scoped.authorization = Arc::new(CacheKeyMetadata {
+            is_authenticated: true,
+            scopes: vec!["read:users".to_string()],
+            policies: vec![],
+        });
+        assert_ne!(key, scoped.to_sha256(&ignored_headers));
+    }
+
     #[test]
     fn it_test_subscription_config() {
         let config_with_callback: SubscriptionConfig = serde_json::from_value(serde_json::json!({
@@ -0,0 +1,520 @@
+//! Coordination of the callback mode subscriptions across router replicas.
+//!
+//! Subgraphs send the events of callback mode subscriptions to the public URL of the router, so
+//! behind a load balancer they can reach a replica other than the one the client is connected to.
+//! With the coordination, each replica subscribes to a Redis channel for every callback
+//! subscription it serves, and a replica receiving a callback for a subscription it doesn't serve
+//! relays it on the channel of the subscription. The number of replicas receiving the relayed
+//! callback tells whether the subscription still exists. A replica unsubscribes from the channels
+//! of the subscriptions it closed. Each subscription plugin owns its coordinator, and on a
+//! reconfiguration the coordinator of the new plugin takes over the channels of the subscriptions
+//! still opened with the previous one.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use fred::clients::SubscriberClient;
+use fred::interfaces::ClientLike;
+use fred::interfaces::EventInterface;
+use fred::interfaces::PubsubInterface;
+use fred::prelude::RedisError;
+use fred::types::Message;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+use uuid::Uuid;
+
+use crate::cache::redis::RedisCacheStorage;
+use crate::cache::redis::RedisKey;
+use crate::cache::redis::RedisValue;
+use crate::configuration::RedisCache;
+use crate::graphql;
+use crate::notification::Notify;
+use crate::plugins::subscription::CallbackPayload;
+use crate::plugins::subscription::Subscription;
+use crate::plugins::subscription::SubscriptionPayload;
+use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN;
+use crate::services::supergraph::Plugins;
+
+/// Key of the HMAC key of the callback verifiers, shared by the replicas
+const HMAC_KEY: &str = "subscription:callback:hmac_key";
+const CHANNEL_PREFIX: &str = "subscription:callback:";
+/// Interval between the checks for the closed subscriptions whose channels can be unsubscribed
+const CLOSED_SUBSCRIPTIONS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The subscriptions of a replica whose channels are subscribed, with the notifier they were
+/// opened with
+type Served = Mutex<HashMap<String, Notify<String, graphql::Response>>>;
+
+/// Coordination of the callback mode subscriptions across router replicas
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CallbackCoordination {
+    /// Redis instance relaying the callbacks between the router replicas
+    pub(crate) redis: RedisCache,
+}
+
+/// Relays the callbacks to the replicas serving the subscriptions
+pub(crate) struct Coordinator {
+    storage: RedisCacheStorage,
+    subscriber: SubscriberClient,
+    channel_prefix: String,
+    /// Suffix of the callback subscription IDs of this replica. Each replica deduplicates its own
+    /// subscriptions, so identical subscriptions of different replicas must not share an ID.
+    replica_id: String,
+    notify: Notify<String, graphql::Response>,
+    /// The subscriptions taken over from a previous coordinator keep the notifier of their
+    /// configuration
+    served: Arc<Served>,
+    task: JoinHandle<()>,
+}
+
+impl std::fmt::Debug for Coordinator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Coordinator")
+            .field("channel_prefix", &self.channel_prefix)
+            .field("replica_id", &self.replica_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Coordinator {
+    pub(crate) async fn new(
+        config: &CallbackCoordination,
+        notify: Notify<String, graphql::Response>,
+    ) -> Result<Self, BoxError> {
+        let storage = RedisCacheStorage::new(config.redis.clone(), "subscription").await?;
+        let subscriber = storage.subscriber().await?;
+        let channel_prefix = match &config.redis.namespace {
+            Some(namespace) => format!("{namespace}:{CHANNEL_PREFIX}"),
+            None => CHANNEL_PREFIX.to_string(),
+        };
+
+        let served: Arc<Served> = Default::default();
+        let mut messages = subscriber.message_rx();
+        let task_subscriber = subscriber.clone();
+        let task_channel_prefix = channel_prefix.clone();
+        let task_served = served.clone();
+        let task = tokio::task::spawn(async move {
+            let mut closed_subscriptions = tokio::time::interval_at(
+                tokio::time::Instant::now() + CLOSED_SUBSCRIPTIONS_INTERVAL,
+                CLOSED_SUBSCRIPTIONS_INTERVAL,
+            );
+            loop {
+                tokio::select! {
+                    message = messages.recv() => match message {
+                        Ok(message) => {
+                            apply(
+                                message,
+                                &task_channel_prefix,
+                                &task_subscriber,
+                                &task_served,
+                            )
+                            .await
+                        }
+                        Err(RecvError::Lagged(count)) => {
+                            tracing::warn!("{count} relayed subscription callbacks were dropped")
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = closed_subscriptions.tick() => {
+                        unsubscribe_closed(
+                            &task_channel_prefix,
+                            &task_subscriber,
+                            &task_served,
+                        )
+                        .await
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            storage,
+            subscriber,
+            channel_prefix,
+            replica_id: Uuid::new_v4().simple().to_string(),
+            notify,
+            served,
+            task,
+        })
+    }
+
+    fn channel(&self, id: &str) -> String {
+        format!("{}{id}", self.channel_prefix)
+    }
+
+    /// Returns the ID of a callback subscription of this replica
+    pub(crate) fn subscription_id(&self, hashed_request: &str) -> String {
+        format!("{hashed_request}-{}", self.replica_id)
+    }
+
+    /// Receives the callbacks of a subscription of this replica relayed by the other replicas
+    pub(crate) async fn serve(&self, id: &str) -> Result<(), RedisError> {
+        self.serve_with(id, self.notify.clone()).await
+    }
+
+    async fn serve_with(
+        &self,
+        id: &str,
+        notify: Notify<String, graphql::Response>,
+    ) -> Result<(), RedisError> {
+        self.subscriber.subscribe(self.channel(id)).await?;
+        self.served.lock().insert(id.to_string(), notify);
+        Ok(())
+    }
+
+    /// Receives the callbacks of the subscriptions served by the coordinator of the previous
+    /// configuration that are still opened, and stops the previous coordinator
+    pub(crate) async fn take_over(&self, previous: &Coordinator) {
+        previous.task.abort();
+        let served: Vec<_> = previous.served.lock().drain().collect();
+        for (id, mut notify) in served {
+            if notify
+                .closed(vec![id.clone()])
+                .await
+                .is_ok_and(|closed| !closed.is_empty())
+            {
+                continue;
+            }
+            if let Err(e) = self.serve_with(&id, notify).await {
+                tracing::error!(
+                    subscription.id = %id,
+                    error = %e,
+                    "could not resubscribe to a subscription callback channel"
+                );
+            }
+        }
+    }
+
+    /// Relays a callback to the replica serving its subscription, and returns whether a replica
+    /// received it
+    pub(crate) async fn relay(&self, payload: &CallbackPayload) -> bool {
+        let message = match serde_json::to_string(payload) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::error!(error = %e, "could not serialize the subscription callback");
+                return false;
+            }
+        };
+        match self
+            .storage
+            .publish(self.channel(payload.id()), message)
+            .await
+        {
+            Ok(receivers) => receivers > 0,
+            Err(e) => {
+                tracing::error!(error = %e, "could not relay the subscription callback");
+                false
+            }
+        }
+    }
+
+    /// Returns the IDs of the subscriptions served by other replicas. The checks are relayed in a
+    /// single round trip.
+    pub(crate) async fn served_elsewhere(&self, ids: &[String]) -> Vec<String> {
+        let mut checks = Vec::with_capacity(ids.len());
+        for id in ids {
+            let check = CallbackPayload::Subscription(SubscriptionPayload::Check {
+                id: id.clone(),
+                verifier: String::new(),
+            });
+            match serde_json::to_string(&check) {
+                Ok(message) => checks.push((self.channel(id), message)),
+                Err(e) => {
+                    tracing::error!(error = %e, "could not serialize the subscription callback");
+                    return Vec::new();
+                }
+            }
+        }
+        match self.storage.publish_all(checks).await {
+            Ok(receivers) => ids
+                .iter()
+                .zip(receivers)
+                .filter(|(_, receivers)| *receivers > 0)
+                .map(|(id, _)| id.clone())
+                .collect(),
+            Err(e) => {
+                tracing::error!(error = %e, "could not relay the subscription callbacks");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Returns the HMAC key of the callback verifiers shared by the replicas, storing the
+    /// candidate key if no replica stored one before
+    pub(crate) async fn shared_hmac_key(&self, candidate: String) -> Result<String, RedisError> {
+        self.storage
+            .get_or_insert(RedisKey(HMAC_KEY.to_string()), RedisValue(candidate))
+            .await
+            .map(|key| key.0)
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        self.task.abort();
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let subscriber = self.subscriber.clone();
+            runtime.spawn(async move {
+                let _ = subscriber.quit().await;
+            });
+        }
+    }
+}
+
+/// Applies a callback relayed by another replica to a subscription of this replica. The replica
+/// stops receiving the callbacks of the subscriptions it no longer serves.
+async fn apply(
+    message: Message,
+    channel_prefix: &str,
+    subscriber: &SubscriberClient,
+    served: &Served,
+) {
+    let Some(id) = message.channel.strip_prefix(channel_prefix) else {
+        return;
+    };
+    let id = id.to_string();
+    let Some(mut notify) = served.lock().get(&id).cloned() else {
+        return;
+    };
+    let payload = match message
+        .value
+        .as_string()
+        .map(|value| serde_json::from_str::<CallbackPayload>(&value))
+    {
+        Some(Ok(payload)) => payload,
+        _ => {
+            tracing::warn!(
+                subscription.id = %id,
+                "could not deserialize a relayed subscription callback"
+            );
+            return;
+        }
+    };
+
+    let is_served = match payload {
+        CallbackPayload::Subscription(SubscriptionPayload::Next { mut payload, .. }) => {
+            match notify.subscribe_if_exist(id.clone()).await {
+                Ok(Some(handle)) => {
+                    // Keep the subscription to the client opened
+                    payload.subscribed = Some(true);
+                    tracing::info!(
+                        monotonic_counter
+                            .apollo
+                            .router
+                            .operations
+                            .subscriptions
+                            .events = 1u64,
+                        subscriptions.mode = "callback"
+                    );
+                    handle.into_sink().send_sync(payload).is_ok()
+                }
+                _ => false,
+            }
+        }
+        CallbackPayload::Subscription(
+            SubscriptionPayload::Check { .. } | SubscriptionPayload::Heartbeat { .. },
+        ) => notify.exist(id.clone()).await.unwrap_or_default(),
+        CallbackPayload::Subscription(SubscriptionPayload::Complete { errors, .. }) => {
+            if let Some(errors) = errors {
+                if let Ok(handle) = notify.subscribe(id.clone()).await {
+                    tracing::info!(
+                        monotonic_counter
+                            .apollo
+                            .router
+                            .operations
+                            .subscriptions
+                            .events = 1u64,
+                        subscriptions.mode = "callback",
+                        subscriptions.complete = true
+                    );
+                    let _ = handle
+                        .into_sink()
+                        .send_sync(graphql::Response::builder().errors(errors).build());
+                }
+            }
+            let _ = notify.force_delete(id.clone()).await;
+            false
+        }
+    };
+
+    if !is_served {
+        unsubscribe(channel_prefix, subscriber, served, id).await;
+    }
+}
+
+/// Stops receiving the callbacks of the subscriptions closed by this replica
+async fn unsubscribe_closed(channel_prefix: &str, subscriber: &SubscriberClient, served: &Served) {
+    let served_ids: Vec<_> = served
+        .lock()
+        .iter()
+        .map(|(id, notify)| (id.clone(), notify.clone()))
+        .collect();
+    for (id, mut notify) in served_ids {
+        if notify
+            .closed(vec![id.clone()])
+            .await
+            .is_ok_and(|closed| !closed.is_empty())
+        {
+            unsubscribe(channel_prefix, subscriber, served, id).await;
+        }
+    }
+}
+
+async fn unsubscribe(
+    channel_prefix: &str,
+    subscriber: &SubscriberClient,
+    served: &Served,
+    id: String,
+) {
+    if let Err(e) = subscriber
+        .unsubscribe(format!("{channel_prefix}{id}"))
+        .await
+    {
+        tracing::warn!(error = %e, "could not unsubscribe from a subscription callback channel");
+    }
+    served.lock().remove(&id);
+}
+
+/// Moves the subscriptions served by the coordinator of the previous subscription plugin to the
+/// coordinator of the new one
+pub(crate) async fn inherit_coordination(previous: &Plugins, plugins: &Plugins) {
+    let coordinator = |plugins: &Plugins| {
+        plugins
+            .get(APOLLO_SUBSCRIPTION_PLUGIN)
+            .and_then(|plugin| plugin.as_any().downcast_ref::<Subscription>())
+            .and_then(|subscription| subscription.coordinator.clone())
+    };
+    if let (Some(previous), Some(coordinator)) = (coordinator(previous), coordinator(plugins)) {
+        coordinator.take_over(&previous).await;
+    }
+}
+
+// Like the Redis integration tests, these tests need a Redis instance on localhost
+#[cfg(all(
+    test,
+    any(not(feature = "ci"), all(target_arch = "x86_64", target_os = "linux"))
+))]
+mod tests {
+    use futures::StreamExt;
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    async fn coordinator(
+        namespace: &str,
+        notify: Notify<String, graphql::Response>,
+    ) -> Coordinator {
+        let config: CallbackCoordination = serde_json::from_value(serde_json::json!({
+            "redis": {
+                "urls": ["redis://127.0.0.1:6379"],
+                "namespace": namespace,
+            }
+        }))
+        .unwrap();
+        Coordinator::new(&config, notify).await.unwrap()
+    }
+
+    fn next(id: &str) -> CallbackPayload {
+        CallbackPayload::Subscription(SubscriptionPayload::Next {
+            id: id.to_string(),
+            payload: graphql::Response::builder()
+                .data(json!({"userWasCreated": {"name": "Ada"}}))
+                .build(),
+            verifier: String::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn relays_callbacks_to_the_serving_replica() {
+        let namespace = Uuid::new_v4().simple().to_string();
+        let mut notify = Notify::builder().build();
+        let serving = coordinator(&namespace, notify.clone()).await;
+        let relaying = coordinator(&namespace, Notify::builder().build()).await;
+
+        let id = serving.subscription_id("hashed");
+        assert_ne!(id, relaying.subscription_id("hashed"));
+        let (handle, _) = notify.create_or_subscribe(id.clone(), true).await.unwrap();
+        let mut stream = handle.into_stream();
+        serving.serve(&id).await.unwrap();
+
+        assert_eq!(
+            relaying
+                .served_elsewhere(&[id.clone(), "unknown".to_string()])
+                .await,
+            vec![id.clone()]
+        );
+        assert!(relaying.relay(&next(&id)).await);
+        let response = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            response.data,
+            Some(json!({"userWasCreated": {"name": "Ada"}}))
+        );
+        assert_eq!(response.subscribed, Some(true));
+
+        // the channel of a closed subscription is unsubscribed
+        drop(stream);
+        unsubscribe_closed(
+            &serving.channel_prefix,
+            &serving.subscriber,
+            &serving.served,
+        )
+        .await;
+        assert!(serving.served.lock().is_empty());
+        assert!(relaying.served_elsewhere(&[id.clone()]).await.is_empty());
+        assert!(!relaying.relay(&next(&id)).await);
+    }
+
+    #[tokio::test]
+    async fn takes_over_the_opened_subscriptions_on_reconfiguration() {
+        let namespace = Uuid::new_v4().simple().to_string();
+        let mut notify = Notify::builder().build();
+        let previous = coordinator(&namespace, notify.clone()).await;
+        let relaying = coordinator(&namespace, Notify::builder().build()).await;
+
+        let id = previous.subscription_id("opened");
+        let (handle, _) = notify.create_or_subscribe(id.clone(), true).await.unwrap();
+        let mut stream = handle.into_stream();
+        previous.serve(&id).await.unwrap();
+        let closed_id = previous.subscription_id("closed");
+        let (closed_handle, _) = notify
+            .create_or_subscribe(closed_id.clone(), true)
+            .await
+            .unwrap();
+        previous.serve(&closed_id).await.unwrap();
+        drop(closed_handle);
+
+        // the coordinator of the new configuration has the notifier of the new configuration
+        let current = coordinator(&namespace, Notify::builder().build()).await;
+        current.take_over(&previous).await;
+        drop(previous);
+        assert!(current.served.lock().contains_key(&id));
+        assert!(!current.served.lock().contains_key(&closed_id));
+
+        assert_eq!(
+            relaying
+                .served_elsewhere(&[id.clone(), closed_id.clone()])
+                .await,
+            vec![id.clone()]
+        );
+        // the subscription opened before the reconfiguration still receives its callbacks
+        assert!(relaying.relay(&next(&id)).await);
+        let response = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            response.data,
+            Some(json!({"userWasCreated": {"name": "Ada"}}))
+        );
+    }
+}
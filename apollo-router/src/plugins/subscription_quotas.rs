@@ -0,0 +1,511 @@
+//! Limits on the number of active subscriptions, per client and globally.
+//!
+//! The global limit is `max_opened_subscriptions` of the subscription configuration. Each
+//! subscription holds a permit for as long as its response stream is open. When a new
+//! subscription would exceed a limit, it is either rejected, admitted after evicting the oldest
+//! subscription of the exceeded scope, or queued until a permit is released.
+//!
+//! Clients that cannot be identified share a single per client limit, so that leaving out the
+//! identity does not escape it.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::ready;
+use futures::stream::once;
+use futures::StreamExt;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tokio::sync::Notify;
+
+use crate::axum_factory::utils::ConnectionInfo;
+use crate::configuration::shared_state;
+use crate::configuration::shared_state::SharedGauge;
+use crate::graphql;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::services::supergraph;
+
+const QUOTA_EXCEEDED_ERROR_CODE: &str = "SUBSCRIPTION_QUOTA_EXCEEDED";
+const EVICTED_ERROR_CODE: &str = "SUBSCRIPTION_EVICTED";
+
+/// Limits on the number of active subscriptions of each client
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct QuotasConfig {
+    /// Maximum number of active subscriptions of each client. The clients that cannot be
+    /// identified share one limit. By default there is no limit.
+    pub(crate) max_active_per_client: Option<usize>,
+    /// How clients are identified for the per client limit
+    pub(crate) client_identity: ClientIdentity,
+    /// What happens to a new subscription exceeding the per client limit or
+    /// `max_opened_subscriptions`
+    pub(crate) on_limit: OverflowPolicy,
+    /// How long a queued subscription waits for an active subscription to end before being
+    /// rejected
+    #[serde(
+        deserialize_with = "humantime_serde::deserialize",
+        serialize_with = "humantime_serde::serialize"
+    )]
+    #[schemars(with = "String", default = "default_queue_timeout")]
+    pub(crate) queue_timeout: Duration,
+}
+
+fn default_queue_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl Default for QuotasConfig {
+    fn default() -> Self {
+        Self {
+            max_active_per_client: None,
+            client_identity: ClientIdentity::default(),
+            on_limit: OverflowPolicy::default(),
+            queue_timeout: default_queue_timeout(),
+        }
+    }
+}
+
+/// How clients are identified for the per client limit
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum ClientIdentity {
+    /// The IP address of the client
+    #[default]
+    Ip,
+    /// The value of a request header
+    Header(String),
+    /// The value of a claim of the JWT validated by the authentication plugin
+    Claim(String),
+    /// The client name, from the client name header or the client identification rules of
+    /// `telemetry.apollo.experimental_client_identification`
+    ClientName,
+}
+
+/// What happens to a new subscription exceeding a limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OverflowPolicy {
+    /// Reject the new subscription
+    #[default]
+    Reject,
+    /// Close the oldest active subscription of the client, or of all clients for the global
+    /// limit, to make room for the new one
+    EvictOldest,
+    /// Wait for an active subscription to end, up to the queue timeout
+    Queue,
+}
+
+/// The limit exceeded by a new subscription
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum QuotaExceeded {
+    Global,
+    Client,
+}
+
+impl QuotaExceeded {
+    fn as_str(&self) -> &'static str {
+        match self {
+            QuotaExceeded::Global => "global",
+            QuotaExceeded::Client => "client",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ActiveSubscription {
+    id: u64,
+    client: Option<String>,
+    evict: Option<oneshot::Sender<()>>,
+}
+
+#[derive(Debug, Default)]
+struct Active {
+    next_id: u64,
+    /// Active subscriptions, from the oldest to the newest
+    subscriptions: Vec<ActiveSubscription>,
+}
+
+/// The active subscriptions of the router, and their limits
+#[derive(Debug)]
+pub(crate) struct SubscriptionQuotas {
+    config: QuotasConfig,
+    /// The global limit, from `max_opened_subscriptions`
+    max_active: Option<usize>,
+    active: Mutex<Active>,
+    released: Notify,
+    /// Active subscriptions of all the router replicas, for the global limit
+    shared: Option<Arc<SharedGauge>>,
+}
+
+/// Held by an active subscription, and released when dropped
+pub(crate) struct QuotaPermit {
+    quotas: Arc<SubscriptionQuotas>,
+    id: u64,
+    operation_name: String,
+    evicted: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        {
+            let mut active = self.quotas.active.lock().expect("lock poisoned");
+            active
+                .subscriptions
+                .retain(|subscription| subscription.id != self.id);
+            self.quotas.report(&active);
+        }
+        self.quotas.released.notify_waiters();
+        i64_up_down_counter!(
+            "apollo.router.subscriptions.active",
+            "Number of active subscriptions",
+            -1,
+            "graphql.operation.name" = self.operation_name.clone()
+        );
+    }
+}
+
+impl SubscriptionQuotas {
+    /// Returns the quotas to enforce, if they are configured.
+    ///
+    /// Without a per client limit or an overflow policy, `max_opened_subscriptions` is left to
+    /// the subscription execution, which rejects the subscriptions over the limit.
+    pub(crate) fn new(
+        config: &QuotasConfig,
+        max_opened_subscriptions: Option<usize>,
+    ) -> Option<Arc<Self>> {
+        let global_policy =
+            max_opened_subscriptions.is_some() && config.on_limit != OverflowPolicy::Reject;
+        if config.max_active_per_client.is_none() && !global_policy {
+            return None;
+        }
+        Some(Arc::new(Self {
+            config: config.clone(),
+            max_active: max_opened_subscriptions,
+            active: Default::default(),
+            released: Notify::new(),
+            shared: max_opened_subscriptions
+                .and_then(|_| shared_state::gauge("subscriptions:active")),
+        }))
+    }
+
+    /// Reports the active subscriptions of this replica to the other replicas
+    fn report(&self, active: &Active) {
+        if let Some(shared) = &self.shared {
+            shared.set(active.subscriptions.len() as u64);
+        }
+    }
+
+    /// Identifies the client of a request for the per client limit.
+    fn client(&self, request: &supergraph::Request) -> Option<String> {
+        match &self.config.client_identity {
+            ClientIdentity::Ip => request
+                .supergraph_request
+                .extensions()
+                .get::<ConnectionInfo>()
+                .and_then(|info| info.peer_address)
+                .map(|address| address.ip().to_string()),
+            ClientIdentity::Header(name) => request
+                .supergraph_request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            ClientIdentity::Claim(claim) => request
+                .context
+                .get::<_, serde_json::Value>(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+                .ok()
+                .flatten()
+                .and_then(|claims| claims.get(claim).cloned())
+                .map(|value| match value {
+                    serde_json::Value::String(value) => value,
+                    value => value.to_string(),
+                }),
+            ClientIdentity::ClientName => {
+                request.context.get::<_, String>(CLIENT_NAME).ok().flatten()
+            }
+        }
+    }
+
+    fn exceeded(&self, active: &Active, client: Option<&str>) -> Option<QuotaExceeded> {
+        if let Some(limit) = self.config.max_active_per_client {
+            // the unidentified clients, with no client, share a limit
+            let count = active
+                .subscriptions
+                .iter()
+                .filter(|subscription| subscription.client.as_deref() == client)
+                .count();
+            if count >= limit {
+                return Some(QuotaExceeded::Client);
+            }
+        }
+        // the active subscriptions of the other replicas, while the shared state is available
+        let others = self
+            .shared
+            .as_ref()
+            .and_then(|shared| shared.others())
+            .unwrap_or_default() as usize;
+        match self.max_active {
+            Some(limit) if active.subscriptions.len() + others >= limit => {
+                Some(QuotaExceeded::Global)
+            }
+            _ => None,
+        }
+    }
+
+    fn try_acquire(
+        self: &Arc<Self>,
+        client: Option<&str>,
+        operation_name: &str,
+        evict: bool,
+    ) -> Result<QuotaPermit, QuotaExceeded> {
+        let mut active = self.active.lock().expect("lock poisoned");
+        while let Some(exceeded) = self.exceeded(&active, client) {
+            let oldest = evict
+                .then(|| {
+                    active.subscriptions.iter().position(|subscription| {
+                        exceeded == QuotaExceeded::Global
+                            || subscription.client.as_deref() == client
+                    })
+                })
+                .flatten();
+            let Some(oldest) = oldest else {
+                self.report(&active);
+                return Err(exceeded);
+            };
+            let mut evicted = active.subscriptions.remove(oldest);
+            if let Some(evict) = evicted.evict.take() {
+                let _ = evict.send(());
+            }
+            u64_counter!(
+                "apollo.router.subscriptions.evicted",
+                "Number of subscriptions closed to make room for newer ones",
+                1,
+                "limit" = exceeded.as_str()
+            );
+        }
+
+        let id = active.next_id;
+        active.next_id += 1;
+        let (evict, evicted) = oneshot::channel();
+        active.subscriptions.push(ActiveSubscription {
+            id,
+            client: client.map(str::to_string),
+            evict: Some(evict),
+        });
+        self.report(&active);
+        i64_up_down_counter!(
+            "apollo.router.subscriptions.active",
+            "Number of active subscriptions",
+            1,
+            "graphql.operation.name" = operation_name.to_string()
+        );
+        Ok(QuotaPermit {
+            quotas: self.clone(),
+            id,
+            operation_name: operation_name.to_string(),
+            evicted: Some(evicted),
+        })
+    }
+
+    /// Acquires a permit for a new subscription, following the overflow policy if a limit is
+    /// exceeded.
+    pub(crate) async fn acquire(
+        self: &Arc<Self>,
+        request: &supergraph::Request,
+        operation_name: &str,
+    ) -> Result<QuotaPermit, QuotaExceeded> {
+        let client = self.client(request);
+        let client = client.as_deref();
+        let result = match self.config.on_limit {
+            OverflowPolicy::Reject => self.try_acquire(client, operation_name, false),
+            OverflowPolicy::EvictOldest => self.try_acquire(client, operation_name, true),
+            OverflowPolicy::Queue => {
+                let deadline = tokio::time::Instant::now() + self.config.queue_timeout;
+                loop {
+                    // Registered before trying, so that a release in between is not missed
+                    let released = self.released.notified();
+                    tokio::pin!(released);
+                    released.as_mut().enable();
+                    match self.try_acquire(client, operation_name, false) {
+                        Ok(permit) => break Ok(permit),
+                        Err(exceeded) => {
+                            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                                break Err(exceeded);
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        if let Err(exceeded) = result {
+            u64_counter!(
+                "apollo.router.subscriptions.rejected",
+                "Number of subscriptions rejected because of a quota",
+                1,
+                "limit" = exceeded.as_str()
+            );
+        }
+        result
+    }
+}
+
+/// The response to a subscription rejected because of a quota
+pub(crate) fn rejected_response(
+    exceeded: QuotaExceeded,
+    context: crate::Context,
+) -> supergraph::Response {
+    let message = match exceeded {
+        QuotaExceeded::Global => "the maximum number of active subscriptions is reached",
+        QuotaExceeded::Client => {
+            "the maximum number of active subscriptions of this client is reached"
+        }
+    };
+    supergraph::Response::infallible_builder()
+        .error(
+            graphql::Error::builder()
+                .message(message)
+                .extension_code(QUOTA_EXCEEDED_ERROR_CODE)
+                .extension("limit", exceeded.as_str())
+                .build(),
+        )
+        .status_code(StatusCode::TOO_MANY_REQUESTS)
+        .context(context)
+        .build()
+}
+
+/// Keeps the permit for as long as the response stream is open, and ends the stream with an
+/// error if the subscription is evicted.
+pub(crate) fn hold(
+    mut permit: QuotaPermit,
+    response: supergraph::Response,
+) -> supergraph::Response {
+    let was_evicted = Arc::new(AtomicBool::new(false));
+    let evicted = permit.evicted.take();
+    let eviction = {
+        let was_evicted = was_evicted.clone();
+        async move {
+            match evicted {
+                Some(evicted) if evicted.await.is_ok() => was_evicted.store(true, Ordering::SeqCst),
+                // the permit is released with the stream, it can't be evicted anymore
+                _ => futures::future::pending::<()>().await,
+            }
+        }
+    };
+    let eviction_error =
+        once(async move { was_evicted.load(Ordering::SeqCst) }).filter_map(|evicted| {
+            ready(evicted.then(|| {
+                graphql::Response::builder()
+                    .subscribed(false)
+                    .error(
+                        graphql::Error::builder()
+                            .message("subscription closed to make room for a newer subscription")
+                            .extension_code(EVICTED_ERROR_CODE)
+                            .build(),
+                    )
+                    .build()
+            }))
+        });
+
+    response.map_stream(move |stream| {
+        stream
+            .take_until(eviction)
+            .chain(eviction_error)
+            .map(move |response| {
+                let _ = &permit;
+                response
+            })
+            .boxed()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quotas(config: serde_json::Value, max_active: Option<usize>) -> Arc<SubscriptionQuotas> {
+        SubscriptionQuotas::new(&serde_json::from_value(config).unwrap(), max_active).unwrap()
+    }
+
+    #[test]
+    fn rejects_over_limits() {
+        let quotas = quotas(serde_json::json!({ "max_active_per_client": 2 }), Some(3));
+        let first = quotas.try_acquire(Some("a"), "OnEvent", false).unwrap();
+        let _second = quotas.try_acquire(Some("a"), "OnEvent", false).unwrap();
+        assert_eq!(
+            quotas.try_acquire(Some("a"), "OnEvent", false).err(),
+            Some(QuotaExceeded::Client)
+        );
+        let _third = quotas.try_acquire(Some("b"), "OnEvent", false).unwrap();
+        assert_eq!(
+            quotas.try_acquire(None, "OnEvent", false).err(),
+            Some(QuotaExceeded::Global)
+        );
+
+        drop(first);
+        assert!(quotas.try_acquire(Some("a"), "OnEvent", false).is_ok());
+    }
+
+    #[test]
+    fn unidentified_clients_share_a_limit() {
+        let quotas = quotas(serde_json::json!({ "max_active_per_client": 1 }), None);
+        let _first = quotas.try_acquire(None, "OnEvent", false).unwrap();
+        assert_eq!(
+            quotas.try_acquire(None, "OnEvent", false).err(),
+            Some(QuotaExceeded::Client)
+        );
+        assert!(quotas.try_acquire(Some("a"), "OnEvent", false).is_ok());
+    }
+
+    #[test]
+    fn leaves_the_global_limit_to_the_execution_without_quotas() {
+        assert!(SubscriptionQuotas::new(&QuotasConfig::default(), Some(10)).is_none());
+    }
+
+    #[test]
+    fn evicts_oldest() {
+        let quotas = quotas(serde_json::json!({ "max_active_per_client": 2 }), None);
+        let mut first = quotas.try_acquire(Some("a"), "OnEvent", true).unwrap();
+        let mut second = quotas.try_acquire(Some("a"), "OnEvent", true).unwrap();
+        let mut other = quotas.try_acquire(Some("b"), "OnEvent", true).unwrap();
+        let _third = quotas.try_acquire(Some("a"), "OnEvent", true).unwrap();
+
+        assert!(first.evicted.take().unwrap().try_recv().is_ok());
+        assert!(second.evicted.take().unwrap().try_recv().is_err());
+        assert!(other.evicted.take().unwrap().try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn queues_until_released() {
+        let quotas = quotas(
+            serde_json::json!({
+                "on_limit": "queue",
+                "queue_timeout": "50ms"
+            }),
+            Some(1),
+        );
+        let request = supergraph::Request::fake_builder().build().unwrap();
+        let first = quotas.acquire(&request, "OnEvent").await.unwrap();
+        assert_eq!(
+            quotas.acquire(&request, "OnEvent").await.err(),
+            Some(QuotaExceeded::Global)
+        );
+
+        let queued = {
+            let quotas = quotas.clone();
+            tokio::spawn(async move {
+                let request = supergraph::Request::fake_builder().build().unwrap();
+                quotas.acquire(&request, "OnEvent").await.is_ok()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(first);
+        assert!(queued.await.unwrap());
+    }
+}
@@ -4,6 +4,7 @@ use std::fmt::Display;
 use std::num::NonZeroUsize;
 use std::ops::AddAssign;
 use std::sync::OnceLock;
+use std::time::Duration;
 use std::time::SystemTime;
 
 use http::header::HeaderName;
@@ -15,7 +16,9 @@ use serde::Serialize;
 use url::Url;
 use uuid::Uuid;
 
+use super::client_identification::ClientIdentificationConfig;
 use super::config::ApolloMetricsReferenceMode;
+use super::config::ApolloSignatureCustomization;
 use super::config::ApolloSignatureNormalizationAlgorithm;
 use super::config::Sampler;
 use super::metrics::apollo::studio::ContextualizedStats;
@@ -77,12 +80,18 @@ pub(crate) struct Config {
     #[serde(deserialize_with = "deserialize_header_name")]
     pub(crate) client_version_header: HeaderName,
 
+    /// Identification of the clients of the requests without the client name header, from other headers or from rules over their user agent and IP address.
+    pub(crate) experimental_client_identification: ClientIdentificationConfig,
+
     /// The buffer size for sending traces to Apollo. Increase this if you are experiencing lost traces.
     pub(crate) buffer_size: NonZeroUsize,
 
     /// Field level instrumentation for subgraphs via ftv1. ftv1 tracing can cause performance issues as it is transmitted in band with subgraph responses.
     pub(crate) field_level_instrumentation_sampler: SamplerOption,
 
+    /// Field level instrumentation (ftv1) requests by subgraph and by operation latency, for the requests sampled by `field_level_instrumentation_sampler`.
+    pub(crate) field_level_instrumentation: FieldLevelInstrumentation,
+
     /// Percentage of traces to send via the OTel protocol when sending to Apollo Studio.
     pub(crate) experimental_otlp_tracing_sampler: SamplerOption,
 
@@ -110,6 +119,9 @@ pub(crate) struct Config {
     pub(crate) experimental_apollo_signature_normalization_algorithm:
         ApolloSignatureNormalizationAlgorithm,
 
+    /// Customize the operation signatures of Apollo usage reports.
+    pub(crate) experimental_apollo_signature_customization: ApolloSignatureCustomization,
+
     /// Set the Apollo usage report reference reporting mode to use.
     pub(crate) experimental_apollo_metrics_reference_mode: ApolloMetricsReferenceMode,
 
@@ -117,6 +129,38 @@ pub(crate) struct Config {
     pub(crate) experimental_local_field_metrics: bool,
 }
 
+#[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct FieldLevelInstrumentation {
+    /// Ratio of the sampled requests that request ftv1 from each subgraph, between 0 and 1. Subgraphs that are not listed request ftv1 for all the sampled requests.
+    pub(crate) subgraphs: HashMap<String, f64>,
+    /// Only request ftv1 for operations whose previous execution exceeded a latency threshold.
+    pub(crate) adaptive: Option<AdaptiveFieldLevelInstrumentation>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AdaptiveFieldLevelInstrumentation {
+    /// Latency of the previous execution of an operation above which ftv1 is requested for it.
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    pub(crate) latency_threshold: Duration,
+    /// Maximum number of slow operations that are tracked.
+    #[serde(default = "default_adaptive_max_operations")]
+    pub(crate) max_operations: NonZeroUsize,
+}
+
+const fn default_adaptive_max_operations() -> NonZeroUsize {
+    unsafe { NonZeroUsize::new_unchecked(10000) }
+}
+
+impl FieldLevelInstrumentation {
+    /// Ratio of the sampled requests that request ftv1 from a subgraph
+    pub(crate) fn subgraph_ratio(&self, subgraph_name: &str) -> f64 {
+        self.subgraphs.get(subgraph_name).copied().unwrap_or(1.0)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
 #[serde(deny_unknown_fields, default)]
 pub(crate) struct ErrorsConfiguration {
@@ -207,9 +251,11 @@ impl Default for Config {
             apollo_graph_ref: apollo_graph_reference(),
             client_name_header: client_name_header_default(),
             client_version_header: client_version_header_default(),
+            experimental_client_identification: ClientIdentificationConfig::default(),
             schema_id: "<no_schema_id>".to_string(),
             buffer_size: default_buffer_size(),
             field_level_instrumentation_sampler: default_field_level_instrumentation_sampler(),
+            field_level_instrumentation: FieldLevelInstrumentation::default(),
             experimental_otlp_tracing_sampler: default_experimental_otlp_tracing_sampler(),
             send_headers: ForwardHeaders::None,
             send_variable_values: ForwardValues::None,
@@ -217,6 +263,7 @@ impl Default for Config {
             errors: ErrorsConfiguration::default(),
             experimental_apollo_signature_normalization_algorithm:
                 ApolloSignatureNormalizationAlgorithm::default(),
+            experimental_apollo_signature_customization: ApolloSignatureCustomization::default(),
             experimental_local_field_metrics: false,
             experimental_apollo_metrics_reference_mode: ApolloMetricsReferenceMode::default(),
         }
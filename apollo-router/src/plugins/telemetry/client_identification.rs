@@ -0,0 +1,432 @@
+//! Identification of the clients of the requests without the client name header.
+//!
+//! Browsers, partners and legacy apps often don't send `apollographql-client-name`, so their
+//! traffic is not attributed to any client in telemetry, and can't be targeted by the policies
+//! configured per client. Their name and version can be read from other headers, or given by the
+//! first rule matching their user agent or IP address.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use http::header::HeaderName;
+use http::header::USER_AGENT;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::axum_factory::utils::ConnectionInfo;
+use crate::plugin::serde::deserialize_option_header_name;
+use crate::plugin::serde::deserialize_vec_header_name;
+use crate::services::router;
+
+/// Identification of the clients of the requests without the client name header
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ClientIdentificationConfig {
+    /// Headers read in order for the client name, when the client name header is absent
+    #[schemars(with = "Vec<String>")]
+    #[serde(deserialize_with = "deserialize_vec_header_name")]
+    pub(crate) name_headers: Vec<HeaderName>,
+
+    /// Headers read in order for the client version, when the client version header is absent
+    #[schemars(with = "Vec<String>")]
+    #[serde(deserialize_with = "deserialize_vec_header_name")]
+    pub(crate) version_headers: Vec<HeaderName>,
+
+    /// Header listing the IP addresses of the client and of the proxies, like
+    /// `x-forwarded-for`, whose client address is matched by the rules instead of the peer
+    /// address of the connection
+    #[schemars(with = "Option<String>")]
+    #[serde(deserialize_with = "deserialize_option_header_name")]
+    pub(crate) ip_header: Option<HeaderName>,
+
+    /// Number of trusted proxies in front of the router appending to `ip_header`, at least 1.
+    /// The client address is the one appended by the farthest trusted proxy, counted from the
+    /// right of the header, as the addresses on its left can be set by the client. Defaults to 1
+    pub(crate) trusted_proxies: Option<usize>,
+
+    /// Rules checked in order for the requests still without a client name. The first rule
+    /// matching the request gives the client name and version
+    pub(crate) rules: Vec<ClientIdentificationRule>,
+}
+
+/// Client of the requests matching every set criteria
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ClientIdentificationRule {
+    /// Name of the matched clients
+    pub(crate) name: String,
+
+    /// Version of the matched clients. It can reference the capture groups of `user_agent`,
+    /// like `$1` or `$version`
+    #[serde(default)]
+    pub(crate) version: Option<String>,
+
+    /// Regex matching the `user-agent` header
+    #[serde(default)]
+    pub(crate) user_agent: Option<String>,
+
+    /// IP ranges containing the address of the client, like `10.0.0.0/8` or `2001:db8::/32`
+    #[serde(default)]
+    pub(crate) ip_ranges: Vec<String>,
+}
+
+/// How the client of a request was identified
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum IdentifiedBy {
+    /// The client name header
+    Header,
+    /// One of the fallback headers
+    FallbackHeader,
+    /// One of the rules
+    Rule,
+}
+
+impl IdentifiedBy {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            IdentifiedBy::Header => "header",
+            IdentifiedBy::FallbackHeader => "fallback_header",
+            IdentifiedBy::Rule => "rule",
+        }
+    }
+}
+
+/// The name and version of the client of a request
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ClientIdentity {
+    pub(crate) name: Option<String>,
+    pub(crate) version: Option<String>,
+    pub(crate) identified_by: Option<IdentifiedBy>,
+}
+
+/// An IP address range in CIDR notation
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct IpRange {
+    address: IpAddr,
+    prefix: u32,
+}
+
+impl FromStr for IpRange {
+    type Err = BoxError;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = range.split_once('/').unwrap_or((range, ""));
+        let address: IpAddr = address
+            .parse()
+            .map_err(|e| format!("invalid IP range '{range}': {e}"))?;
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = if prefix.is_empty() {
+            max_prefix
+        } else {
+            prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| format!("invalid prefix length in IP range '{range}'"))?
+        };
+        Ok(Self { address, prefix })
+    }
+}
+
+impl IpRange {
+    fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or_default();
+                u32::from(range) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or_default();
+                u128::from(range) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A rule, ready to be matched
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    version: Option<String>,
+    user_agent: Option<Regex>,
+    ip_ranges: Vec<IpRange>,
+}
+
+impl Rule {
+    /// The identity of the client, if the request matches the rule
+    fn identify(
+        &self,
+        user_agent: Option<&str>,
+        address: Option<IpAddr>,
+    ) -> Option<(String, Option<String>)> {
+        if !self.ip_ranges.is_empty()
+            && !address.is_some_and(|address| self.ip_ranges.iter().any(|r| r.contains(address)))
+        {
+            return None;
+        }
+        let version = match &self.user_agent {
+            Some(regex) => {
+                let captures = regex.captures(user_agent?)?;
+                self.version.as_ref().map(|version| {
+                    let mut expanded = String::new();
+                    captures.expand(version, &mut expanded);
+                    expanded
+                })
+            }
+            None => self.version.clone(),
+        };
+        Some((self.name.clone(), version))
+    }
+}
+
+/// Identifies the clients of the requests
+#[derive(Debug)]
+pub(crate) struct ClientIdentifier {
+    name_header: HeaderName,
+    version_header: HeaderName,
+    name_headers: Vec<HeaderName>,
+    version_headers: Vec<HeaderName>,
+    ip_header: Option<HeaderName>,
+    trusted_proxies: usize,
+    rules: Vec<Rule>,
+}
+
+impl ClientIdentifier {
+    pub(crate) fn new(
+        name_header: HeaderName,
+        version_header: HeaderName,
+        config: &ClientIdentificationConfig,
+    ) -> Result<Self, BoxError> {
+        let trusted_proxies = config.trusted_proxies.unwrap_or(1);
+        if trusted_proxies == 0 {
+            return Err("the number of trusted proxies must be at least 1".into());
+        }
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                Ok(Rule {
+                    name: rule.name.clone(),
+                    version: rule.version.clone(),
+                    user_agent: rule.user_agent.as_deref().map(Regex::new).transpose()?,
+                    ip_ranges: rule
+                        .ip_ranges
+                        .iter()
+                        .map(|range| range.parse())
+                        .collect::<Result<_, BoxError>>()?,
+                })
+            })
+            .collect::<Result<_, BoxError>>()?;
+
+        Ok(Self {
+            name_header,
+            version_header,
+            name_headers: config.name_headers.clone(),
+            version_headers: config.version_headers.clone(),
+            ip_header: config.ip_header.clone(),
+            trusted_proxies,
+            rules,
+        })
+    }
+
+    /// Identifies the client from the client name and version headers, then from the fallback
+    /// headers, then from the first matching rule
+    pub(crate) fn identify(&self, request: &router::Request) -> ClientIdentity {
+        let headers = request.router_request.headers();
+        let header = |name: &HeaderName| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let version =
+            header(&self.version_header).or_else(|| self.version_headers.iter().find_map(header));
+
+        if let Some(name) = header(&self.name_header) {
+            return ClientIdentity {
+                name: Some(name),
+                version,
+                identified_by: Some(IdentifiedBy::Header),
+            };
+        }
+        if let Some(name) = self.name_headers.iter().find_map(header) {
+            return ClientIdentity {
+                name: Some(name),
+                version,
+                identified_by: Some(IdentifiedBy::FallbackHeader),
+            };
+        }
+
+        if !self.rules.is_empty() {
+            let user_agent = headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok());
+            let address = match &self.ip_header {
+                Some(ip_header) => headers
+                    .get(ip_header)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| self.client_address(value)),
+                None => request
+                    .router_request
+                    .extensions()
+                    .get::<ConnectionInfo>()
+                    .and_then(|info| info.peer_address)
+                    .map(|address| address.ip()),
+            };
+            if let Some((name, rule_version)) = self
+                .rules
+                .iter()
+                .find_map(|rule| rule.identify(user_agent, address))
+            {
+                return ClientIdentity {
+                    name: Some(name),
+                    version: version.or(rule_version),
+                    identified_by: Some(IdentifiedBy::Rule),
+                };
+            }
+        }
+
+        ClientIdentity {
+            name: None,
+            version,
+            identified_by: None,
+        }
+    }
+
+    /// The address appended by the farthest trusted proxy. When the header lists fewer
+    /// addresses, they were all appended by trusted proxies, and the first one is used
+    fn client_address(&self, header: &str) -> Option<IpAddr> {
+        let addresses: Vec<&str> = header.split(',').map(str::trim).collect();
+        let index = addresses.len().saturating_sub(self.trusted_proxies);
+        addresses[index].parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifier(config: serde_json::Value) -> ClientIdentifier {
+        ClientIdentifier::new(
+            HeaderName::from_static("apollographql-client-name"),
+            HeaderName::from_static("apollographql-client-version"),
+            &serde_json::from_value(config).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn request(headers: &[(&str, &str)]) -> router::Request {
+        let mut builder = http::Request::builder().uri("http://localhost/graphql");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        router::Request::from(builder.body(hyper::Body::empty()).unwrap())
+    }
+
+    #[test]
+    fn headers_take_precedence_over_rules() {
+        let identifier = identifier(serde_json::json!({
+            "name_headers": ["x-client-name"],
+            "rules": [{ "name": "web", "user_agent": "Mozilla" }]
+        }));
+
+        let identity = identifier.identify(&request(&[
+            ("apollographql-client-name", "ios"),
+            ("apollographql-client-version", "1.2.0"),
+            ("x-client-name", "partner"),
+            ("user-agent", "Mozilla/5.0"),
+        ]));
+        assert_eq!(identity.name.as_deref(), Some("ios"));
+        assert_eq!(identity.version.as_deref(), Some("1.2.0"));
+        assert_eq!(identity.identified_by, Some(IdentifiedBy::Header));
+
+        let identity = identifier.identify(&request(&[
+            ("x-client-name", "partner"),
+            ("user-agent", "Mozilla/5.0"),
+        ]));
+        assert_eq!(identity.name.as_deref(), Some("partner"));
+        assert_eq!(identity.identified_by, Some(IdentifiedBy::FallbackHeader));
+    }
+
+    #[test]
+    fn first_matching_rule_identifies_the_client() {
+        let identifier = identifier(serde_json::json!({
+            "ip_header": "x-forwarded-for",
+            "rules": [
+                {
+                    "name": "android",
+                    "version": "$version",
+                    "user_agent": "^AcmeApp/(?<version>[0-9.]+) Android"
+                },
+                { "name": "internal", "ip_ranges": ["10.0.0.0/8", "fd00::/8"] }
+            ]
+        }));
+
+        let identity = identifier.identify(&request(&[("user-agent", "AcmeApp/4.2.1 Android 14")]));
+        assert_eq!(identity.name.as_deref(), Some("android"));
+        assert_eq!(identity.version.as_deref(), Some("4.2.1"));
+        assert_eq!(identity.identified_by, Some(IdentifiedBy::Rule));
+
+        let identity =
+            identifier.identify(&request(&[("x-forwarded-for", "192.168.0.1, 10.1.2.3")]));
+        assert_eq!(identity.name.as_deref(), Some("internal"));
+        assert_eq!(identity.version, None);
+
+        let identity = identifier.identify(&request(&[("x-forwarded-for", "192.168.0.1")]));
+        assert_eq!(identity, ClientIdentity::default());
+    }
+
+    #[test]
+    fn client_addresses_are_counted_from_the_right() {
+        let identifier = identifier(serde_json::json!({
+            "ip_header": "x-forwarded-for",
+            "trusted_proxies": 2,
+            "rules": [{ "name": "internal", "ip_ranges": ["10.0.0.0/8"] }]
+        }));
+
+        // the addresses on the left of the ones appended by the trusted proxies are spoofed
+        let identity = identifier.identify(&request(&[(
+            "x-forwarded-for",
+            "10.1.2.3, 192.168.0.1, 172.16.0.1",
+        )]));
+        assert_eq!(identity, ClientIdentity::default());
+
+        let identity = identifier.identify(&request(&[(
+            "x-forwarded-for",
+            "192.168.0.1, 10.1.2.3, 172.16.0.1",
+        )]));
+        assert_eq!(identity.name.as_deref(), Some("internal"));
+
+        let identity = identifier.identify(&request(&[("x-forwarded-for", "10.1.2.3")]));
+        assert_eq!(identity.name.as_deref(), Some("internal"));
+
+        assert!(ClientIdentifier::new(
+            HeaderName::from_static("apollographql-client-name"),
+            HeaderName::from_static("apollographql-client-version"),
+            &serde_json::from_value(serde_json::json!({ "trusted_proxies": 0 })).unwrap(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn ip_ranges() {
+        let range: IpRange = "192.168.0.0/16".parse().unwrap();
+        assert!(range.contains("192.168.10.1".parse().unwrap()));
+        assert!(range.contains("::ffff:192.168.10.1".parse().unwrap()));
+        assert!(!range.contains("192.169.0.1".parse().unwrap()));
+
+        let range: IpRange = "2001:db8::/32".parse().unwrap();
+        assert!(range.contains("2001:db8::1".parse().unwrap()));
+        assert!(!range.contains("2001:db9::1".parse().unwrap()));
+
+        let range: IpRange = "0.0.0.0/0".parse().unwrap();
+        assert!(range.contains("8.8.8.8".parse().unwrap()));
+
+        assert!("10.0.0.1".parse::<IpRange>().is_ok());
+        assert!("10.0.0.0/33".parse::<IpRange>().is_err());
+        assert!("10.0.0/8".parse::<IpRange>().is_err());
+    }
+}
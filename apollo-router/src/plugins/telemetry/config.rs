@@ -29,6 +29,10 @@ use crate::Configuration;
 pub(crate) enum Error {
     #[error("field level instrumentation sampler must sample less frequently than tracing level sampler")]
     InvalidFieldLevelInstrumentationSampler,
+    #[error("field level instrumentation sampler ratio must be between 0 and 1")]
+    InvalidFieldLevelInstrumentationRatio,
+    #[error("field level instrumentation ratio of subgraph '{0}' must be between 0 and 1")]
+    InvalidSubgraphFieldLevelInstrumentationRatio(String),
 }
 
 pub(in crate::plugins::telemetry) trait GenericWith<T>
@@ -283,6 +287,27 @@ pub(crate) enum ApolloSignatureNormalizationAlgorithm {
     Enhanced,
 }
 
+/// Customization of the operation signatures of Apollo usage reports, applied on top of the
+/// normalization algorithm. Operations whose signatures differ are reported separately.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ApolloSignatureCustomization {
+    /// Arguments removed from the signatures, by coordinate like `Query.products(trackingId:)`
+    /// for a field argument, or `@tag(name:)` for a directive argument. The type of a field
+    /// coordinate is the type the field is selected on
+    pub(crate) strip_arguments: Vec<String>,
+    /// Arguments whose literal values are kept in the signatures instead of being redacted,
+    /// by coordinate like `Query.products(category:)` for a field argument, or
+    /// `@feature(name:)` for a directive argument, like the conditions of inline fragments
+    pub(crate) keep_literals: Vec<String>,
+}
+
+impl ApolloSignatureCustomization {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.strip_arguments.is_empty() && self.keep_literals.is_empty()
+    }
+}
+
 /// Apollo usage report reference generation modes.
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema, Copy)]
 #[serde(deny_unknown_fields, rename_all = "lowercase")]
@@ -658,6 +683,25 @@ fn parent_based(sampler: opentelemetry::sdk::trace::Sampler) -> opentelemetry::s
 
 impl Conf {
     pub(crate) fn calculate_field_level_instrumentation_ratio(&self) -> Result<f64, Error> {
+        if let SamplerOption::TraceIdRatioBased(ratio) =
+            &self.apollo.field_level_instrumentation_sampler
+        {
+            if !(0.0..=1.0).contains(ratio) {
+                return Err(Error::InvalidFieldLevelInstrumentationRatio);
+            }
+        }
+        // subgraph ratios apply to the requests already sampled for field level instrumentation
+        if let Some((subgraph, _)) = self
+            .apollo
+            .field_level_instrumentation
+            .subgraphs
+            .iter()
+            .find(|(_, ratio)| !(0.0..=1.0).contains(*ratio))
+        {
+            return Err(Error::InvalidSubgraphFieldLevelInstrumentationRatio(
+                subgraph.clone(),
+            ));
+        }
         Ok(
             match (
                 &self.exporters.tracing.common.sampler,
@@ -723,6 +767,20 @@ impl Conf {
         }
     }
 
+    pub(crate) fn signature_customization(
+        configuration: &Configuration,
+    ) -> ApolloSignatureCustomization {
+        match configuration.apollo_plugins.plugins.get("telemetry") {
+            Some(telemetry_config) => {
+                match serde_json::from_value::<Conf>(telemetry_config.clone()) {
+                    Ok(conf) => conf.apollo.experimental_apollo_signature_customization,
+                    _ => ApolloSignatureCustomization::default(),
+                }
+            }
+            _ => ApolloSignatureCustomization::default(),
+        }
+    }
+
     pub(crate) fn signature_normalization_algorithm(
         configuration: &Configuration,
     ) -> ApolloSignatureNormalizationAlgorithm {
@@ -793,4 +851,39 @@ mod tests {
         AttributeValue::try_from(json!([1.1, true])).expect_err("mixed conversion must fail");
         AttributeValue::try_from(json!([true, "bar"])).expect_err("mixed conversion must fail");
     }
+
+    #[test]
+    fn test_subgraph_field_level_instrumentation_ratio() {
+        let mut conf: Conf = serde_json::from_value(json!({
+            "apollo": {
+                "field_level_instrumentation": {
+                    "subgraphs": { "products": 0.5 },
+                    "adaptive": { "latency_threshold": "200ms" }
+                }
+            }
+        }))
+        .unwrap();
+        assert!(conf.calculate_field_level_instrumentation_ratio().is_ok());
+        assert_eq!(
+            conf.apollo
+                .field_level_instrumentation
+                .subgraph_ratio("products"),
+            0.5
+        );
+        assert_eq!(
+            conf.apollo
+                .field_level_instrumentation
+                .subgraph_ratio("users"),
+            1.0
+        );
+
+        conf.apollo
+            .field_level_instrumentation
+            .subgraphs
+            .insert("users".to_string(), 1.5);
+        assert!(matches!(
+            conf.calculate_field_level_instrumentation_ratio(),
+            Err(Error::InvalidSubgraphFieldLevelInstrumentationRatio(subgraph)) if subgraph == "users"
+        ));
+    }
 }
@@ -1572,6 +1572,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = common.on_request(&req);
         assert_eq!(
@@ -1594,6 +1595,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1611,6 +1613,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1633,6 +1636,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1650,6 +1654,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1675,6 +1680,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1700,6 +1706,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1725,6 +1732,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1750,6 +1758,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1775,6 +1784,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1797,6 +1807,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1814,6 +1825,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1836,6 +1848,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -1853,6 +1866,7 @@ mod test {
         req.router_request.extensions_mut().insert(ConnectionInfo {
             peer_address: Some(SocketAddr::from_str("192.168.0.8:6060").unwrap()),
             server_address: Some(SocketAddr::from_str("192.168.0.1:8080").unwrap()),
+            client_certificate: None,
         });
         let attributes = server.on_request(&req);
         assert_eq!(
@@ -15,6 +15,7 @@ use crate::plugin::serde::deserialize_jsonpath;
 use crate::plugins::cache::entity::CacheSubgraph;
 use crate::plugins::cache::metrics::CacheMetricContextKey;
 use crate::plugins::demand_control::CostContext;
+use crate::plugins::telemetry::client_identification::IdentifiedBy;
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config::TraceIdFormat;
 use crate::plugins::telemetry::config_new::cost::CostValue;
@@ -25,11 +26,14 @@ use crate::plugins::telemetry::config_new::instruments::Standard;
 use crate::plugins::telemetry::config_new::trace_id;
 use crate::plugins::telemetry::config_new::Selector;
 use crate::plugins::telemetry::config_new::ToOtelValue;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::plugins::telemetry::CLIENT_VERSION;
 use crate::query_planner::APOLLO_OPERATION_ID;
 use crate::services::router;
 use crate::services::subgraph;
 use crate::services::supergraph;
 use crate::services::FIRST_EVENT_CONTEXT_KEY;
+use crate::spec::operation_limits::LimitsTier;
 use crate::spec::operation_limits::OperationLimits;
 use crate::Context;
 
@@ -65,6 +69,8 @@ pub(crate) enum Query {
     Height,
     /// The query root fields.
     RootFields,
+    /// The tier of operation limits applied to the query.
+    LimitsTier,
 }
 
 #[derive(Deserialize, JsonSchema, Clone, Debug, PartialEq)]
@@ -90,6 +96,32 @@ pub(crate) enum OperationKind {
     String,
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum ClientAttribute {
+    /// The client name.
+    Name,
+    /// The client version.
+    Version,
+    /// How the client was identified: `header`, `fallback_header` or `rule`.
+    IdentifiedBy,
+}
+
+impl ClientAttribute {
+    fn value(&self, context: &Context, default: &Option<String>) -> Option<opentelemetry::Value> {
+        match self {
+            ClientAttribute::Name => context.get::<_, String>(CLIENT_NAME).ok().flatten(),
+            ClientAttribute::Version => context.get::<_, String>(CLIENT_VERSION).ok().flatten(),
+            ClientAttribute::IdentifiedBy => context
+                .extensions()
+                .with_lock(|lock| lock.get::<IdentifiedBy>().copied())
+                .map(|identified_by| identified_by.as_str().to_string()),
+        }
+        .or_else(|| default.clone())
+        .map(opentelemetry::Value::from)
+    }
+}
+
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case", untagged)]
 pub(crate) enum RouterValue {
@@ -210,6 +242,13 @@ pub(crate) enum RouterSelector {
         /// Critical error if it happens
         error: ErrorRepr,
     },
+    /// The client of the request, from the client name and version headers or the client identification rules.
+    Client {
+        /// The client attribute.
+        client: ClientAttribute,
+        /// Optional default value.
+        default: Option<String>,
+    },
 }
 
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
@@ -386,6 +425,13 @@ pub(crate) enum SupergraphSelector {
         /// Boolean returning true if it's the primary response and not events like subscription events or deferred responses
         is_primary_response: bool,
     },
+    /// The client of the request, from the client name and version headers or the client identification rules.
+    Client {
+        /// The client attribute.
+        client: ClientAttribute,
+        /// Optional default value.
+        default: Option<String>,
+    },
 }
 
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
@@ -617,6 +663,13 @@ pub(crate) enum SubgraphSelector {
         /// Specify the entity type on which you want the cache data. (default: all)
         entity_type: Option<EntityType>,
     },
+    /// The client of the request, from the client name and version headers or the client identification rules.
+    Client {
+        /// The client attribute.
+        client: ClientAttribute,
+        /// Optional default value.
+        default: Option<String>,
+    },
 }
 
 #[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
@@ -678,6 +731,7 @@ impl Selector for RouterSelector {
             } => get_baggage(baggage).or_else(|| default.maybe_to_otel_value()),
             RouterSelector::Static(val) => Some(val.clone().into()),
             RouterSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            RouterSelector::Client { client, default } => client.value(&request.context, default),
             // Related to Response
             _ => None,
         }
@@ -754,6 +808,7 @@ impl Selector for RouterSelector {
                 .ok()
                 .flatten()
                 .map(opentelemetry::Value::from),
+            RouterSelector::Client { client, default } => client.value(&response.context, default),
             _ => None,
         }
     }
@@ -884,6 +939,9 @@ impl Selector for SupergraphSelector {
                 .map(opentelemetry::Value::from),
             SupergraphSelector::Static(val) => Some(val.clone().into()),
             SupergraphSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            SupergraphSelector::Client { client, default } => {
+                client.value(&request.context, default)
+            }
             // For response
             _ => None,
         }
@@ -908,6 +966,11 @@ impl Selector for SupergraphSelector {
                     }
                     Query::RootFields => limits_opt
                         .map(|limits| opentelemetry::Value::I64(limits.root_fields as i64)),
+                    Query::LimitsTier => response
+                        .context
+                        .extensions()
+                        .with_lock(|lock| lock.get::<LimitsTier>().cloned())
+                        .map(|tier| opentelemetry::Value::from(tier.0)),
                     Query::String => None,
                 }
             }
@@ -979,6 +1042,9 @@ impl Selector for SupergraphSelector {
             } if *is_primary => Some(true.into()),
             SupergraphSelector::Static(val) => Some(val.clone().into()),
             SupergraphSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            SupergraphSelector::Client { client, default } => {
+                client.value(&response.context, default)
+            }
             // For request
             _ => None,
         }
@@ -1113,6 +1179,10 @@ impl Selector for SupergraphSelector {
                     }
                     Query::RootFields => limits_opt
                         .map(|limits| opentelemetry::Value::I64(limits.root_fields as i64)),
+                    Query::LimitsTier => ctx
+                        .extensions()
+                        .with_lock(|lock| lock.get::<LimitsTier>().cloned())
+                        .map(|tier| opentelemetry::Value::from(tier.0)),
                     Query::String => None,
                 }
             }
@@ -1226,6 +1296,11 @@ impl Selector for SubgraphSelector {
                     }
                     Query::RootFields => limits_opt
                         .map(|limits| opentelemetry::Value::I64(limits.root_fields as i64)),
+                    Query::LimitsTier => request
+                        .context
+                        .extensions()
+                        .with_lock(|lock| lock.get::<LimitsTier>().cloned())
+                        .map(|tier| opentelemetry::Value::from(tier.0)),
                     Query::String => request
                         .supergraph_request
                         .body()
@@ -1311,6 +1386,7 @@ impl Selector for SubgraphSelector {
                 .map(opentelemetry::Value::from),
             SubgraphSelector::Static(val) => Some(val.clone().into()),
             SubgraphSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            SubgraphSelector::Client { client, default } => client.value(&request.context, default),
 
             // For response
             _ => None,
@@ -1319,6 +1395,9 @@ impl Selector for SubgraphSelector {
 
     fn on_response(&self, response: &subgraph::Response) -> Option<opentelemetry::Value> {
         match self {
+            SubgraphSelector::Client { client, default } => {
+                client.value(&response.context, default)
+            }
             SubgraphSelector::SubgraphResponseHeader {
                 subgraph_response_header,
                 default,
@@ -1551,9 +1630,11 @@ mod test {
     use crate::plugins::cache::entity::CacheHitMiss;
     use crate::plugins::cache::entity::CacheSubgraph;
     use crate::plugins::cache::metrics::CacheMetricContextKey;
+    use crate::plugins::telemetry::client_identification::IdentifiedBy;
     use crate::plugins::telemetry::config::AttributeValue;
     use crate::plugins::telemetry::config_new::selectors::All;
     use crate::plugins::telemetry::config_new::selectors::CacheKind;
+    use crate::plugins::telemetry::config_new::selectors::ClientAttribute;
     use crate::plugins::telemetry::config_new::selectors::EntityType;
     use crate::plugins::telemetry::config_new::selectors::OperationKind;
     use crate::plugins::telemetry::config_new::selectors::OperationName;
@@ -1566,8 +1647,10 @@ mod test {
     use crate::plugins::telemetry::config_new::selectors::TraceIdFormat;
     use crate::plugins::telemetry::config_new::Selector;
     use crate::plugins::telemetry::otel;
+    use crate::plugins::telemetry::CLIENT_NAME;
     use crate::query_planner::APOLLO_OPERATION_ID;
     use crate::services::FIRST_EVENT_CONTEXT_KEY;
+    use crate::spec::operation_limits::LimitsTier;
     use crate::spec::operation_limits::OperationLimits;
 
     #[test]
@@ -2089,6 +2172,35 @@ mod test {
         );
     }
 
+    #[test]
+    fn supergraph_client() {
+        let context = crate::context::Context::new();
+        let _ = context.insert(CLIENT_NAME, "web".to_string());
+        context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(IdentifiedBy::Rule));
+        let request = crate::services::SupergraphRequest::fake_builder()
+            .context(context)
+            .build()
+            .unwrap();
+
+        let selector = SupergraphSelector::Client {
+            client: ClientAttribute::Name,
+            default: None,
+        };
+        assert_eq!(selector.on_request(&request).unwrap(), "web".into());
+        let selector = SupergraphSelector::Client {
+            client: ClientAttribute::IdentifiedBy,
+            default: None,
+        };
+        assert_eq!(selector.on_request(&request).unwrap(), "rule".into());
+        let selector = SupergraphSelector::Client {
+            client: ClientAttribute::Version,
+            default: Some("unknown".to_string()),
+        };
+        assert_eq!(selector.on_request(&request).unwrap(), "unknown".into());
+    }
+
     #[test]
     fn supergraph_response_context() {
         let selector = SupergraphSelector::ResponseContext {
@@ -3019,6 +3131,42 @@ mod test {
         );
     }
 
+    #[test]
+    fn supergraph_query_limits_tier() {
+        let (selector, context) = create_select_and_context(Query::LimitsTier);
+        context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(LimitsTier("internal".to_string())));
+        let subgraph_selector = SubgraphSelector::SupergraphQuery {
+            supergraph_query: Query::LimitsTier,
+            redact: None,
+            default: None,
+        };
+        assert_eq!(
+            subgraph_selector.on_request(
+                &crate::services::SubgraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build()
+            ),
+            Some("internal".into())
+        );
+        assert_eq!(
+            selector
+                .on_response(
+                    &crate::services::SupergraphResponse::fake_builder()
+                        .context(context)
+                        .build()
+                        .unwrap()
+                )
+                .unwrap(),
+            "internal".into()
+        );
+        assert_eq!(
+            subgraph_selector.on_request(&crate::services::SubgraphRequest::fake_builder().build()),
+            None
+        );
+    }
+
     #[test]
     fn subgraph_supergraph_query() {
         let selector = SubgraphSelector::SupergraphQuery {
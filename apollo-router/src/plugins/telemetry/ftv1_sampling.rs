@@ -0,0 +1,94 @@
+//! Adaptive field level instrumentation: ftv1 is only requested for operations whose previous
+//! execution was slower than a latency threshold, to reduce the overhead on subgraphs.
+
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lru::LruCache;
+use sha2::Digest;
+use sha2::Sha256;
+
+use super::apollo::AdaptiveFieldLevelInstrumentation;
+use crate::graphql;
+
+/// Key of the operation of a request, stored in the context extensions to record its latency
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct OperationLatencyKey(String);
+
+impl OperationLatencyKey {
+    pub(crate) fn new(request: &graphql::Request) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(request.operation_name.as_deref().unwrap_or_default());
+        hasher.update([0]);
+        hasher.update(request.query.as_deref().unwrap_or_default());
+        Self(hex::encode(hasher.finalize()))
+    }
+}
+
+/// The operations whose last execution exceeded the latency threshold
+pub(crate) struct SlowOperations {
+    latency_threshold: Duration,
+    operations: Mutex<LruCache<OperationLatencyKey, ()>>,
+}
+
+impl SlowOperations {
+    pub(crate) fn new(config: &AdaptiveFieldLevelInstrumentation) -> Self {
+        Self::with_capacity(config.latency_threshold, config.max_operations)
+    }
+
+    fn with_capacity(latency_threshold: Duration, capacity: NonZeroUsize) -> Self {
+        Self {
+            latency_threshold,
+            operations: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub(crate) fn is_slow(&self, key: &OperationLatencyKey) -> bool {
+        self.operations
+            .lock()
+            .expect("lock poisoned")
+            .get(key)
+            .is_some()
+    }
+
+    pub(crate) fn record(&self, key: OperationLatencyKey, latency: Duration) {
+        let mut operations = self.operations.lock().expect("lock poisoned");
+        if latency > self.latency_threshold {
+            operations.put(key, ());
+        } else {
+            operations.pop(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(query: &str) -> OperationLatencyKey {
+        OperationLatencyKey::new(&graphql::Request::builder().query(query).build())
+    }
+
+    #[test]
+    fn tracks_operations_slower_than_the_threshold() {
+        let slow = SlowOperations::with_capacity(
+            Duration::from_millis(100),
+            NonZeroUsize::new(1).unwrap(),
+        );
+        assert!(!slow.is_slow(&key("{ a }")));
+
+        slow.record(key("{ a }"), Duration::from_millis(150));
+        assert!(slow.is_slow(&key("{ a }")));
+        assert!(!slow.is_slow(&key("{ b }")));
+
+        slow.record(key("{ a }"), Duration::from_millis(50));
+        assert!(!slow.is_slow(&key("{ a }")));
+
+        // the least recently slow operations are evicted
+        slow.record(key("{ a }"), Duration::from_millis(150));
+        slow.record(key("{ b }"), Duration::from_millis(150));
+        assert!(!slow.is_slow(&key("{ a }")));
+        assert!(slow.is_slow(&key("{ b }")));
+    }
+}
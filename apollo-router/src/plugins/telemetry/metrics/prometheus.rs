@@ -1,13 +1,25 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Mutex;
 use std::task::Context;
 use std::task::Poll;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine as _;
 use futures::future::BoxFuture;
+use http::header::ACCEPT;
+use http::header::AUTHORIZATION;
+use http::header::WWW_AUTHENTICATE;
+use http::HeaderMap;
 use http::StatusCode;
 use once_cell::sync::Lazy;
 use opentelemetry::sdk::metrics::MeterProvider;
 use opentelemetry::sdk::metrics::View;
 use opentelemetry::sdk::Resource;
+use opentelemetry::KeyValue;
 use prometheus::Encoder;
 use prometheus::Registry;
 use prometheus::TextEncoder;
@@ -17,6 +29,7 @@ use tower::BoxError;
 use tower::ServiceExt;
 use tower_service::Service;
 
+use crate::http_ext::constant_time_eq;
 use crate::plugins::telemetry::config::MetricView;
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::CustomAggregationSelector;
@@ -25,8 +38,14 @@ use crate::plugins::telemetry::metrics::MetricsConfigurator;
 use crate::router_factory::Endpoint;
 use crate::services::router;
 use crate::services::router::Body;
+use crate::tracer::TraceId;
 use crate::ListenAddr;
 
+const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+const OPEN_METRICS_MEDIA_TYPE: &str = "application/openmetrics-text";
+const OPEN_METRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 /// Prometheus configuration
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, default)]
@@ -37,6 +56,11 @@ pub(crate) struct Config {
     pub(crate) listen: ListenAddr,
     /// The path where prometheus will be exposed
     pub(crate) path: String,
+    /// Credentials required to read the metrics
+    pub(crate) authentication: Option<MetricsAuthentication>,
+    /// Expose the metrics in the OpenMetrics format to clients accepting it, with exemplars
+    /// linking the HTTP request duration buckets to traces
+    pub(crate) open_metrics: bool,
 }
 
 impl Default for Config {
@@ -45,8 +69,205 @@ impl Default for Config {
             enabled: false,
             listen: ListenAddr::SocketAddr("127.0.0.1:9090".parse().expect("valid listenAddr")),
             path: "/metrics".to_string(),
+            authentication: None,
+            open_metrics: false,
+        }
+    }
+}
+
+/// Credentials required to read the metrics
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum MetricsAuthentication {
+    /// A bearer token in the authorization header
+    Bearer {
+        /// The expected token
+        token: String,
+    },
+    /// Basic authentication
+    Basic {
+        /// The expected user name
+        username: String,
+        /// The expected password
+        password: String,
+    },
+}
+
+impl MetricsAuthentication {
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(authorization) = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+        match self {
+            MetricsAuthentication::Bearer { token } => authorization
+                .strip_prefix("Bearer ")
+                .is_some_and(|received| constant_time_eq(received.as_bytes(), token.as_bytes())),
+            MetricsAuthentication::Basic { username, password } => authorization
+                .strip_prefix("Basic ")
+                .and_then(|credentials| BASE64_STANDARD.decode(credentials).ok())
+                .is_some_and(|credentials| {
+                    constant_time_eq(&credentials, format!("{username}:{password}").as_bytes())
+                }),
         }
     }
+
+    fn challenge(&self) -> &'static str {
+        match self {
+            MetricsAuthentication::Bearer { .. } => "Bearer",
+            MetricsAuthentication::Basic { .. } => "Basic realm=\"metrics\"",
+        }
+    }
+}
+
+/// The latest sampled observation of a histogram series, linking it to its trace
+#[derive(Clone, Debug, PartialEq)]
+struct Exemplar {
+    labels: Vec<(String, String)>,
+    trace_id: String,
+    value: f64,
+    timestamp: f64,
+}
+
+static EXEMPLARS_ENABLED: AtomicBool = AtomicBool::new(false);
+// Exemplars by histogram name, with one exemplar per label set
+static EXEMPLARS: Lazy<Mutex<HashMap<String, Vec<Exemplar>>>> = Lazy::new(Default::default);
+
+/// Prometheus label name of an attribute
+fn label_name(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Records an observation of a histogram as the exemplar of its series, if it is part of a
+/// sampled trace and OpenMetrics exposition is enabled.
+pub(crate) fn record_exemplar(histogram: &str, attributes: &[KeyValue], value: f64) {
+    if !EXEMPLARS_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(trace_id) = TraceId::maybe_new() else {
+        return;
+    };
+    let exemplar = Exemplar {
+        labels: attributes
+            .iter()
+            .map(|kv| (label_name(kv.key.as_str()), kv.value.to_string()))
+            .collect(),
+        trace_id: trace_id.to_string(),
+        value,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64(),
+    };
+    let mut exemplars = EXEMPLARS.lock().expect("lock poisoned");
+    let series = exemplars.entry(histogram.to_string()).or_default();
+    match series.iter_mut().find(|e| e.labels == exemplar.labels) {
+        Some(existing) => *existing = exemplar,
+        None => series.push(exemplar),
+    }
+}
+
+/// Splits a sample line into its metric name and its labels.
+fn parse_sample(line: &str) -> Option<(&str, Vec<(String, String)>)> {
+    let start = line.find('{')?;
+    let name = &line[..start];
+    let mut labels = Vec::new();
+    let mut chars = line[start + 1..].chars();
+    loop {
+        let mut key = String::new();
+        for c in chars.by_ref() {
+            match c {
+                '=' => break,
+                '}' => return Some((name, labels)),
+                ',' => {}
+                c => key.push(c),
+            }
+        }
+        chars.next().filter(|c| *c == '"')?;
+        let mut value = String::new();
+        let mut escaped = false;
+        for c in chars.by_ref() {
+            match (escaped, c) {
+                (false, '\\') => escaped = true,
+                (false, '"') => break,
+                (true, 'n') => {
+                    value.push('\n');
+                    escaped = false;
+                }
+                (_, c) => {
+                    value.push(c);
+                    escaped = false;
+                }
+            }
+        }
+        labels.push((key, value));
+    }
+}
+
+/// Converts the Prometheus text exposition to OpenMetrics, adding the exemplars to the first
+/// bucket of their series that contains them.
+fn to_open_metrics(text: &str, exemplars: &HashMap<String, Vec<Exemplar>>) -> String {
+    let mut output = String::with_capacity(text.len());
+    // OpenMetrics counter families are named without the `_total` suffix of their samples
+    let counters: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("# TYPE ")?.strip_suffix(" counter"))
+        .collect();
+    let mut exemplified: Option<(String, Vec<(String, String)>)> = None;
+    for line in text.lines() {
+        let mut line = line.to_string();
+        if line.starts_with("# TYPE ") || line.starts_with("# HELP ") {
+            let (name, rest) = line[7..].split_once(' ').unwrap_or((&line[7..], ""));
+            if counters.contains(&name) {
+                if let Some(family) = name.strip_suffix("_total") {
+                    line = format!("{}{family} {rest}", &line[..7]);
+                }
+            }
+        } else if let Some((name, labels)) = parse_sample(&line) {
+            let family = name.strip_suffix("_bucket").unwrap_or(name);
+            let series = exemplars.get(family).and_then(|series| {
+                let le = labels
+                    .iter()
+                    .find(|(key, _)| key == "le")?
+                    .1
+                    .parse::<f64>()
+                    .ok()?;
+                let other_labels: Vec<_> = labels
+                    .iter()
+                    .filter(|(key, _)| key != "le")
+                    .cloned()
+                    .collect();
+                series
+                    .iter()
+                    .find(|exemplar| {
+                        exemplar.value <= le
+                            && exemplar
+                                .labels
+                                .iter()
+                                .all(|label| other_labels.contains(label))
+                    })
+                    .map(|exemplar| (exemplar, other_labels))
+            });
+            if let Some((exemplar, other_labels)) = series {
+                let key = (family.to_string(), other_labels);
+                if exemplified.as_ref() != Some(&key) {
+                    line.push_str(&format!(
+                        " # {{trace_id=\"{}\"}} {} {:.3}",
+                        exemplar.trace_id, exemplar.value, exemplar.timestamp
+                    ));
+                    exemplified = Some(key);
+                }
+            }
+        }
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output.push_str("# EOF\n");
+    output
 }
 
 // Prometheus metrics are special. We want them to persist between restarts if possible.
@@ -87,6 +308,8 @@ impl MetricsConfigurator for Config {
         // Prometheus metrics are special, they must persist between reloads. This means that we only want to create something new if the resources have changed.
         // The prometheus exporter, and the associated registry are linked, so replacing one means replacing the other.
 
+        EXEMPLARS_ENABLED.store(self.open_metrics, Ordering::Relaxed);
+
         let prometheus_config = PrometheusConfig {
             resource: builder.resource.clone(),
             buckets: metrics_config.buckets.clone(),
@@ -109,6 +332,8 @@ impl MetricsConfigurator for Config {
                         self.path.clone(),
                         PrometheusService {
                             registry: last_registry.clone(),
+                            authentication: self.authentication.clone(),
+                            open_metrics: self.open_metrics,
                         }
                         .boxed(),
                     ),
@@ -150,6 +375,8 @@ impl MetricsConfigurator for Config {
                 self.path.clone(),
                 PrometheusService {
                     registry: registry.clone(),
+                    authentication: self.authentication.clone(),
+                    open_metrics: self.open_metrics,
                 }
                 .boxed(),
             ),
@@ -174,6 +401,8 @@ impl MetricsConfigurator for Config {
 #[derive(Clone)]
 pub(crate) struct PrometheusService {
     registry: Registry,
+    authentication: Option<MetricsAuthentication>,
+    open_metrics: bool,
 }
 
 impl Service<router::Request> for PrometheusService {
@@ -186,6 +415,30 @@ impl Service<router::Request> for PrometheusService {
     }
 
     fn call(&mut self, req: router::Request) -> Self::Future {
+        if let Some(authentication) = &self.authentication {
+            if !authentication.is_authorized(req.router_request.headers()) {
+                let challenge = authentication.challenge();
+                return Box::pin(async move {
+                    Ok(router::Response {
+                        response: http::Response::builder()
+                            .status(StatusCode::UNAUTHORIZED)
+                            .header(WWW_AUTHENTICATE, challenge)
+                            .body::<Body>(Body::empty())
+                            .map_err(BoxError::from)?,
+                        context: req.context,
+                    })
+                });
+            }
+        }
+
+        let open_metrics = self.open_metrics
+            && req
+                .router_request
+                .headers()
+                .get_all(ACCEPT)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .any(|value| value.contains(OPEN_METRICS_MEDIA_TYPE));
         let metric_families = self.registry.gather();
         Box::pin(async move {
             let encoder = TextEncoder::new();
@@ -194,11 +447,18 @@ impl Service<router::Request> for PrometheusService {
             // otel 0.19.0 started adding "_total" onto various statistics.
             // Let's remove any problems they may have created for us.
             let stats = String::from_utf8_lossy(&result);
-            let modified_stats = stats.replace("_total_total", "_total");
+            let mut modified_stats = stats.replace("_total_total", "_total");
+            let content_type = if open_metrics {
+                modified_stats =
+                    to_open_metrics(&modified_stats, &EXEMPLARS.lock().expect("lock poisoned"));
+                OPEN_METRICS_CONTENT_TYPE
+            } else {
+                TEXT_CONTENT_TYPE
+            };
             Ok(router::Response {
                 response: http::Response::builder()
                     .status(StatusCode::OK)
-                    .header(http::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .header(http::header::CONTENT_TYPE, content_type)
                     .body::<Body>(modified_stats.into())
                     .map_err(BoxError::from)?,
                 context: req.context,
@@ -206,3 +466,78 @@ impl Service<router::Request> for PrometheusService {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn authentication() {
+        let bearer = MetricsAuthentication::Bearer {
+            token: "secret".to_string(),
+        };
+        let basic = MetricsAuthentication::Basic {
+            username: "prometheus".to_string(),
+            password: "secret".to_string(),
+        };
+        let headers = |authorization: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+            headers
+        };
+
+        assert!(bearer.is_authorized(&headers("Bearer secret")));
+        assert!(!bearer.is_authorized(&headers("Bearer other")));
+        assert!(!bearer.is_authorized(&HeaderMap::new()));
+        assert!(basic.is_authorized(&headers(&format!(
+            "Basic {}",
+            BASE64_STANDARD.encode("prometheus:secret")
+        ))));
+        assert!(!basic.is_authorized(&headers(&format!(
+            "Basic {}",
+            BASE64_STANDARD.encode("prometheus:other")
+        ))));
+        assert!(!basic.is_authorized(&headers("Bearer secret")));
+    }
+
+    #[test]
+    fn open_metrics_with_exemplars() {
+        let text = r#"# HELP apollo_router_http_requests_total Total number of HTTP requests made.
+# TYPE apollo_router_http_requests_total counter
+apollo_router_http_requests_total{status="200",otel_scope_name="apollo/router"} 2
+# HELP apollo_router_http_request_duration_seconds Duration of HTTP requests.
+# TYPE apollo_router_http_request_duration_seconds histogram
+apollo_router_http_request_duration_seconds_bucket{status="200",otel_scope_name="apollo/router",le="0.1"} 1
+apollo_router_http_request_duration_seconds_bucket{status="200",otel_scope_name="apollo/router",le="0.5"} 2
+apollo_router_http_request_duration_seconds_bucket{status="200",otel_scope_name="apollo/router",le="+Inf"} 2
+apollo_router_http_request_duration_seconds_sum{status="200",otel_scope_name="apollo/router"} 0.35
+apollo_router_http_request_duration_seconds_count{status="200",otel_scope_name="apollo/router"} 2
+"#;
+        let exemplars = HashMap::from([(
+            "apollo_router_http_request_duration_seconds".to_string(),
+            vec![Exemplar {
+                labels: vec![("status".to_string(), "200".to_string())],
+                trace_id: "0af7651916cd43dd8448eb211c80319c".to_string(),
+                value: 0.3,
+                timestamp: 1700000000.0,
+            }],
+        )]);
+        assert_eq!(
+            to_open_metrics(text, &exemplars),
+            r#"# HELP apollo_router_http_requests Total number of HTTP requests made.
+# TYPE apollo_router_http_requests counter
+apollo_router_http_requests_total{status="200",otel_scope_name="apollo/router"} 2
+# HELP apollo_router_http_request_duration_seconds Duration of HTTP requests.
+# TYPE apollo_router_http_request_duration_seconds histogram
+apollo_router_http_request_duration_seconds_bucket{status="200",otel_scope_name="apollo/router",le="0.1"} 1
+apollo_router_http_request_duration_seconds_bucket{status="200",otel_scope_name="apollo/router",le="0.5"} 2 # {trace_id="0af7651916cd43dd8448eb211c80319c"} 0.3 1700000000.000
+apollo_router_http_request_duration_seconds_bucket{status="200",otel_scope_name="apollo/router",le="+Inf"} 2
+apollo_router_http_request_duration_seconds_sum{status="200",otel_scope_name="apollo/router"} 0.35
+apollo_router_http_request_duration_seconds_count{status="200",otel_scope_name="apollo/router"} 2
+# EOF
+"#
+        );
+    }
+}
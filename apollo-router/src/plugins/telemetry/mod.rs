@@ -64,6 +64,7 @@ use self::apollo::OperationSubType;
 use self::apollo::SingleReport;
 use self::apollo_exporter::proto;
 use self::apollo_exporter::Sender;
+use self::client_identification::ClientIdentifier;
 use self::config::Conf;
 use self::config::Sampler;
 use self::config::SamplerOption;
@@ -75,6 +76,8 @@ use self::config_new::instruments::Instrumented;
 use self::config_new::instruments::RouterInstruments;
 use self::config_new::instruments::SubgraphInstruments;
 use self::config_new::spans::Spans;
+use self::ftv1_sampling::OperationLatencyKey;
+use self::ftv1_sampling::SlowOperations;
 use self::metrics::apollo::studio::SingleTypeStat;
 use self::metrics::AttributesForwardConf;
 use self::reload::reload_fmt;
@@ -124,6 +127,7 @@ use crate::plugins::telemetry::metrics::apollo::studio::SingleQueryLatencyStats;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleStats;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleStatsReport;
 use crate::plugins::telemetry::metrics::prometheus::commit_prometheus;
+use crate::plugins::telemetry::metrics::prometheus::record_exemplar;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
 use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
@@ -154,6 +158,7 @@ use crate::ListenAddr;
 pub(crate) mod apollo;
 pub(crate) mod apollo_exporter;
 pub(crate) mod apollo_otlp_exporter;
+pub(crate) mod client_identification;
 pub(crate) mod config;
 pub(crate) mod config_new;
 pub(crate) mod consts;
@@ -161,6 +166,7 @@ pub(crate) mod dynamic_attribute;
 mod endpoint;
 mod fmt_layer;
 pub(crate) mod formatters;
+mod ftv1_sampling;
 mod logging;
 pub(crate) mod metrics;
 /// Opentelemetry utils
@@ -173,8 +179,8 @@ pub(crate) mod tracing;
 pub(crate) mod utils;
 
 // Tracing consts
-const CLIENT_NAME: &str = "apollo_telemetry::client_name";
-const CLIENT_VERSION: &str = "apollo_telemetry::client_version";
+pub(crate) const CLIENT_NAME: &str = "apollo_telemetry::client_name";
+pub(crate) const CLIENT_VERSION: &str = "apollo_telemetry::client_version";
 const SUBGRAPH_FTV1: &str = "apollo_telemetry::subgraph_ftv1";
 pub(crate) const STUDIO_EXCLUDE: &str = "apollo_telemetry::studio::exclude";
 pub(crate) const LOGGING_DISPLAY_HEADERS: &str = "apollo_telemetry::logging::display_headers";
@@ -201,6 +207,8 @@ pub(crate) struct Telemetry {
     custom_endpoints: MultiMap<ListenAddr, Endpoint>,
     apollo_metrics_sender: apollo_exporter::Sender,
     field_level_instrumentation_ratio: f64,
+    slow_operations: Option<Arc<SlowOperations>>,
+    client_identifier: Arc<ClientIdentifier>,
     sampling_filter_ratio: SamplerOption,
     pub(crate) graphql_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
     router_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
@@ -309,10 +317,23 @@ impl Plugin for Telemetry {
             cache_custom_instruments,
         } = create_builtin_instruments(&config.instrumentation.instruments);
 
+        let client_identifier = ClientIdentifier::new(
+            config.apollo.client_name_header.clone(),
+            config.apollo.client_version_header.clone(),
+            &config.apollo.experimental_client_identification,
+        )?;
+
         Ok(Telemetry {
             custom_endpoints: metrics_builder.custom_endpoints,
             apollo_metrics_sender: metrics_builder.apollo_metrics_sender,
             field_level_instrumentation_ratio,
+            slow_operations: config
+                .apollo
+                .field_level_instrumentation
+                .adaptive
+                .as_ref()
+                .map(|adaptive| Arc::new(SlowOperations::new(adaptive))),
+            client_identifier: Arc::new(client_identifier),
             activation: Mutex::new(TelemetryActivation {
                 tracer_provider: Some(tracer_provider),
                 public_meter_provider: Some(FilterMeterProvider::public(
@@ -346,6 +367,7 @@ impl Plugin for Telemetry {
         let field_level_instrumentation_ratio = self.field_level_instrumentation_ratio;
         let metrics_sender = self.apollo_metrics_sender.clone();
         let static_router_instruments = self.router_custom_instruments.read().clone();
+        let client_identifier = self.client_identifier.clone();
 
         ServiceBuilder::new()
             .map_response(move |response: router::Response| {
@@ -399,16 +421,9 @@ impl Plugin for Telemetry {
                         );
                     }
 
-                    let client_name = request
-                        .router_request
-                        .headers()
-                        .get(&config_request.apollo.client_name_header)
-                        .and_then(|h| h.to_str().ok());
-                    let client_version = request
-                        .router_request
-                        .headers()
-                        .get(&config_request.apollo.client_version_header)
-                        .and_then(|h| h.to_str().ok());
+                    let client = client_identifier.identify(request);
+                    let client_name = client.name.as_deref();
+                    let client_version = client.version.as_deref();
 
                     if let Some(name) = client_name {
                         let _ = request.context.insert(CLIENT_NAME, name.to_owned());
@@ -418,6 +433,13 @@ impl Plugin for Telemetry {
                         let _ = request.context.insert(CLIENT_VERSION, version.to_owned());
                     }
 
+                    if let Some(identified_by) = client.identified_by {
+                        request
+                            .context
+                            .extensions()
+                            .with_lock(|mut lock| lock.insert(identified_by));
+                    }
+
                     let mut custom_attributes = config_request
                         .instrumentation
                         .spans
@@ -567,6 +589,8 @@ impl Plugin for Telemetry {
         let config_map_res_first = config.clone();
         let config_map_res = config.clone();
         let field_level_instrumentation_ratio = self.field_level_instrumentation_ratio;
+        let slow_operations = self.slow_operations.clone();
+        let slow_operations_response = self.slow_operations.clone();
         let static_supergraph_instruments = self.supergraph_custom_instruments.read().clone();
         let static_graphql_instruments = self.graphql_custom_instruments.read().clone();
         ServiceBuilder::new()
@@ -630,7 +654,7 @@ impl Plugin for Telemetry {
             .map_future_with_request_data(
                 move |req: &SupergraphRequest| {
                     let custom_attributes = config.instrumentation.spans.supergraph.attributes.on_request(req);
-                    Self::populate_context(config.clone(), field_level_instrumentation_ratio, req);
+                    Self::populate_context(config.clone(), field_level_instrumentation_ratio, slow_operations.as_deref(), req);
                     let custom_instruments = config
                         .instrumentation
                         .instruments
@@ -649,11 +673,17 @@ impl Plugin for Telemetry {
                 move |(ctx, custom_instruments, mut custom_attributes, supergraph_events, custom_graphql_instruments): (Context, SupergraphInstruments, Vec<KeyValue>, SupergraphEvents, GraphQLInstruments), fut| {
                     let config = config_map_res.clone();
                     let sender = metrics_sender.clone();
+                    let slow_operations = slow_operations_response.clone();
                     let start = Instant::now();
 
                     async move {
                         let span = Span::current();
                         let mut result: Result<SupergraphResponse, BoxError> = fut.await;
+                        if let Some(slow_operations) = &slow_operations {
+                            if let Some(key) = ctx.extensions().with_lock(|lock| lock.get::<OperationLatencyKey>().cloned()) {
+                                slow_operations.record(key, start.elapsed());
+                            }
+                        }
                         add_query_attributes(&ctx, &mut custom_attributes);
                         add_cost_attributes(&ctx, &mut custom_attributes);
                         span.set_span_dyn_attributes(custom_attributes);
@@ -732,12 +762,17 @@ impl Plugin for Telemetry {
         let subgraph_metrics_conf_req = self.create_subgraph_metrics_conf(name);
         let subgraph_metrics_conf_resp = subgraph_metrics_conf_req.clone();
         let subgraph_name = ByteString::from(name);
+        let subgraph_ftv1_ratio = self
+            .config
+            .apollo
+            .field_level_instrumentation
+            .subgraph_ratio(name);
         let name = name.to_owned();
         let static_subgraph_instruments = self.subgraph_custom_instruments.read().clone();
         let static_cache_instruments = self.cache_custom_instruments.read().clone();
         ServiceBuilder::new()
             .instrument(move |req: &SubgraphRequest| span_mode.create_subgraph(name.as_str(), req))
-            .map_request(move |req: SubgraphRequest| request_ftv1(req))
+            .map_request(move |req: SubgraphRequest| request_ftv1(req, subgraph_ftv1_ratio))
             .map_response(move |resp| store_ftv1(&subgraph_name, resp))
             .map_future_with_request_data(
                 move |sub_request: &SubgraphRequest| {
@@ -1117,6 +1152,11 @@ impl Telemetry {
             metric_attrs
         );
 
+        record_exemplar(
+            "apollo_router_http_request_duration_seconds",
+            &metric_attrs,
+            request_duration.as_secs_f64(),
+        );
         f64_histogram!(
             "apollo_router_http_request_duration_seconds",
             "Duration of HTTP requests.",
@@ -1129,6 +1169,7 @@ impl Telemetry {
     fn populate_context(
         config: Arc<Conf>,
         field_level_instrumentation_ratio: f64,
+        slow_operations: Option<&SlowOperations>,
         req: &SupergraphRequest,
     ) {
         let context = &req.context;
@@ -1172,7 +1213,18 @@ impl Telemetry {
         let _ = context
             .extensions()
             .with_lock(|mut lock| lock.insert(MetricsAttributes(attributes)));
-        if rand::thread_rng().gen_bool(field_level_instrumentation_ratio) {
+        // with adaptive sampling, only operations that were slow on their previous execution
+        // request ftv1
+        let slow = match slow_operations {
+            Some(slow_operations) => {
+                let key = OperationLatencyKey::new(req.supergraph_request.body());
+                let slow = slow_operations.is_slow(&key);
+                context.extensions().with_lock(|mut lock| lock.insert(key));
+                slow
+            }
+            None => true,
+        };
+        if slow && rand::thread_rng().gen_bool(field_level_instrumentation_ratio) {
             context
                 .extensions()
                 .with_lock(|mut lock| lock.insert(EnableSubgraphFtv1));
@@ -1308,10 +1360,16 @@ impl Telemetry {
                 );
             }
         }
+        let request_duration = now.elapsed().as_secs_f64();
+        record_exemplar(
+            "apollo_router_http_request_duration_seconds",
+            &metric_attrs,
+            request_duration,
+        );
         f64_histogram!(
             "apollo_router_http_request_duration_seconds",
             "Duration of HTTP requests.",
-            now.elapsed().as_secs_f64(),
+            request_duration,
             metric_attrs
         );
     }
@@ -1972,12 +2030,13 @@ fn handle_error_internal<T: Into<opentelemetry::global::Error>>(
 
 register_plugin!("apollo", "telemetry", Telemetry);
 
-fn request_ftv1(mut req: SubgraphRequest) -> SubgraphRequest {
+fn request_ftv1(mut req: SubgraphRequest, subgraph_ratio: f64) -> SubgraphRequest {
     if req
         .context
         .extensions()
         .with_lock(|lock| lock.contains_key::<EnableSubgraphFtv1>())
         && Span::current().context().span().span_context().is_sampled()
+        && (subgraph_ratio >= 1.0 || rand::thread_rng().gen_bool(subgraph_ratio))
     {
         req.subgraph_request
             .headers_mut()
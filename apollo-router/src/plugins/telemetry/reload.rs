@@ -36,6 +36,7 @@ use crate::plugins::telemetry::otel;
 use crate::plugins::telemetry::otel::OpenTelemetryLayer;
 use crate::plugins::telemetry::otel::PreSampledTracer;
 use crate::plugins::telemetry::tracing::reload::ReloadTracer;
+use crate::support_bundle::RecentErrorsLayer;
 use crate::tracer::TraceId;
 
 pub(crate) type LayeredRegistry = Layered<SpanMetricsLayer, Layered<DynAttributeLayer, Registry>>;
@@ -104,6 +105,7 @@ pub(crate) fn init_telemetry(log_level: &str) -> Result<()> {
                 .with(opentelemetry_layer)
                 .with(fmt_layer)
                 .with(metrics_layer.clone())
+                .with(RecentErrorsLayer)
                 .with(EnvFilter::try_new(log_level)?)
                 .try_init()?;
 
@@ -0,0 +1,809 @@
+//! Circuit breaking of the subgraph requests.
+//!
+//! When the share of failed requests of a subgraph over a sliding window goes over a threshold,
+//! the circuit opens and the requests to the subgraph fail fast instead of piling up on an
+//! unhealthy service. After a delay, the circuit is half open: a few probe requests are sent, and
+//! the circuit closes if they all succeed, or opens again at the first failure. While the circuit
+//! is open, queries can be answered with the last successful response to the same subgraph
+//! request.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use bytes::Buf;
+use futures::future::BoxFuture;
+use http::Method;
+use http::StatusCode;
+use lru::LruCache;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use crate::configuration::lifecycle_notifications;
+use crate::configuration::lifecycle_notifications::LifecycleEvent;
+use crate::graphql;
+use crate::http_ext;
+use crate::plugins::authorization::CacheKeyMetadata;
+use crate::plugins::traffic_shaping::rate::RateLimited;
+use crate::query_planner::fetch::OperationKind;
+use crate::services::router;
+use crate::services::router::body::RouterBody;
+use crate::services::subgraph;
+use crate::ListenAddr;
+
+/// Duration of the buckets counting the requests of the sliding window
+const BUCKET_DURATION: Duration = Duration::from_secs(1);
+
+/// The circuit breakers of the subgraphs, by subgraph name
+pub(crate) type CircuitBreakers = Arc<Mutex<HashMap<String, CircuitBreakerLayer>>>;
+
+/// Subgraph requests and the authorization metadata of their response, like the keys of the
+/// query deduplication
+type StaleKey = (http_ext::Request<graphql::Request>, Arc<CacheKeyMetadata>);
+
+/// The error returned for the requests to a subgraph while its circuit is open
+#[derive(Debug, Default)]
+pub(crate) struct CircuitOpen;
+
+impl CircuitOpen {
+    pub(crate) fn new() -> Self {
+        CircuitOpen {}
+    }
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("the circuit breaker of the subgraph is open")
+    }
+}
+
+impl From<CircuitOpen> for graphql::Error {
+    fn from(_: CircuitOpen) -> Self {
+        graphql::Error::builder()
+            .message(String::from(
+                "The subgraph is unavailable because its circuit breaker is open",
+            ))
+            .extension_code("SUBGRAPH_CIRCUIT_OPEN")
+            .build()
+    }
+}
+
+impl error::Error for CircuitOpen {}
+
+/// Endpoint reading, tripping and resetting the circuit breakers
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CircuitBreakerEndpointConfig {
+    /// Listen address of the endpoint
+    pub(crate) listen: ListenAddr,
+    /// Path of the endpoint
+    pub(crate) path: String,
+    /// Shared key expected in the `Authorization` header of the requests
+    pub(crate) shared_key: String,
+}
+
+/// State of a circuit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub(crate) const ALL: [CircuitState; 3] = [
+        CircuitState::Closed,
+        CircuitState::Open,
+        CircuitState::HalfOpen,
+    ];
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Manual change of a circuit, sent to the endpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    /// Opens the circuit until it is reset
+    Trip,
+    /// Closes the circuit
+    Reset,
+}
+
+/// Requests and failures of a slice of the sliding window
+struct Bucket {
+    start: Instant,
+    requests: u32,
+    failures: u32,
+}
+
+enum Circuit {
+    Closed,
+    /// Open until the instant after which probes are sent, or until a reset if tripped manually
+    Open {
+        until: Option<Instant>,
+    },
+    HalfOpen {
+        sent: u32,
+        succeeded: u32,
+    },
+}
+
+struct Breaker {
+    circuit: Circuit,
+    buckets: VecDeque<Bucket>,
+}
+
+/// Whether a request can be sent to the subgraph
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Admission {
+    Allowed,
+    Probe,
+    Rejected,
+}
+
+pub(crate) struct CircuitBreakerState {
+    subgraph_name: String,
+    error_percent: f64,
+    slow_request: Option<Duration>,
+    min_requests: u32,
+    window: Duration,
+    open_duration: Duration,
+    half_open_requests: u32,
+    bypass_operations: HashSet<String>,
+    breaker: Mutex<Breaker>,
+    /// Last successful responses of the queries, served while the circuit is open
+    stale_responses: Option<Mutex<LruCache<StaleKey, http::Response<graphql::Response>>>>,
+}
+
+impl CircuitBreakerState {
+    pub(crate) fn state(&self) -> CircuitState {
+        match self.breaker.lock().expect("lock poisoned").circuit {
+            Circuit::Closed => CircuitState::Closed,
+            Circuit::Open { .. } => CircuitState::Open,
+            Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    fn admit(&self, now: Instant) -> Admission {
+        let mut breaker = self.breaker.lock().expect("lock poisoned");
+        if let Circuit::Open { until: Some(until) } = breaker.circuit {
+            if until <= now {
+                tracing::info!(
+                    "the circuit breaker of the {} subgraph is half open",
+                    self.subgraph_name
+                );
+                breaker.circuit = Circuit::HalfOpen {
+                    sent: 1,
+                    succeeded: 0,
+                };
+                self.notify(CircuitState::HalfOpen);
+                return Admission::Probe;
+            }
+        }
+        match &mut breaker.circuit {
+            Circuit::Closed => Admission::Allowed,
+            Circuit::HalfOpen { sent, .. } if *sent < self.half_open_requests => {
+                *sent += 1;
+                Admission::Probe
+            }
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => Admission::Rejected,
+        }
+    }
+
+    /// Records the outcome of a request. The outcomes of the requests sent before the circuit
+    /// changed are ignored
+    fn record(&self, admission: Admission, failed: bool, now: Instant) {
+        let mut guard = self.breaker.lock().expect("lock poisoned");
+        let breaker = &mut *guard;
+        match (admission, &mut breaker.circuit) {
+            (Admission::Probe, Circuit::HalfOpen { .. }) if failed => {
+                self.open(breaker, now);
+            }
+            (Admission::Probe, Circuit::HalfOpen { succeeded, .. }) => {
+                *succeeded += 1;
+                if *succeeded >= self.half_open_requests {
+                    tracing::info!(
+                        "the circuit breaker of the {} subgraph is closed",
+                        self.subgraph_name
+                    );
+                    breaker.circuit = Circuit::Closed;
+                    breaker.buckets.clear();
+                    self.notify(CircuitState::Closed);
+                }
+            }
+            (Admission::Allowed, Circuit::Closed) => {
+                while breaker
+                    .buckets
+                    .front()
+                    .map_or(false, |bucket| bucket.start + self.window <= now)
+                {
+                    breaker.buckets.pop_front();
+                }
+                match breaker.buckets.back_mut() {
+                    Some(bucket) if bucket.start + BUCKET_DURATION > now => {
+                        bucket.requests += 1;
+                        bucket.failures += failed as u32;
+                    }
+                    _ => breaker.buckets.push_back(Bucket {
+                        start: now,
+                        requests: 1,
+                        failures: failed as u32,
+                    }),
+                }
+
+                let (requests, failures) =
+                    breaker
+                        .buckets
+                        .iter()
+                        .fold((0, 0), |(requests, failures), bucket| {
+                            (requests + bucket.requests, failures + bucket.failures)
+                        });
+                if failed
+                    && requests >= self.min_requests
+                    && failures as f64 * 100.0 >= self.error_percent * requests as f64
+                {
+                    self.open(breaker, now);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Gives back the slot of a probe whose outcome was not recorded
+    fn release(&self) {
+        let mut breaker = self.breaker.lock().expect("lock poisoned");
+        if let Circuit::HalfOpen { sent, succeeded } = &mut breaker.circuit {
+            if *sent > *succeeded {
+                *sent -= 1;
+            }
+        }
+    }
+
+    fn open(&self, breaker: &mut Breaker, now: Instant) {
+        tracing::warn!(
+            "the circuit breaker of the {} subgraph is open",
+            self.subgraph_name
+        );
+        breaker.circuit = Circuit::Open {
+            until: Some(now + self.open_duration),
+        };
+        breaker.buckets.clear();
+        self.notify(CircuitState::Open);
+    }
+
+    fn apply(&self, action: Action) {
+        let mut breaker = self.breaker.lock().expect("lock poisoned");
+        tracing::info!(
+            "the circuit breaker of the {} subgraph is {}",
+            self.subgraph_name,
+            match action {
+                Action::Trip => "tripped",
+                Action::Reset => "reset",
+            }
+        );
+        breaker.circuit = match action {
+            Action::Trip => Circuit::Open { until: None },
+            Action::Reset => Circuit::Closed,
+        };
+        breaker.buckets.clear();
+        self.notify(match action {
+            Action::Trip => CircuitState::Open,
+            Action::Reset => CircuitState::Closed,
+        });
+    }
+
+    fn notify(&self, state: CircuitState) {
+        lifecycle_notifications::notify(LifecycleEvent::CircuitBreaker {
+            subgraph: self.subgraph_name.clone(),
+            state,
+        });
+    }
+
+    fn stale_response(&self, key: &StaleKey) -> Option<http::Response<graphql::Response>> {
+        let mut stale_responses = self
+            .stale_responses
+            .as_ref()?
+            .lock()
+            .expect("lock poisoned");
+        stale_responses.get(key).map(http_ext::clone_http_response)
+    }
+
+    fn store_response(&self, key: StaleKey, response: &http::Response<graphql::Response>) {
+        if let Some(stale_responses) = &self.stale_responses {
+            stale_responses
+                .lock()
+                .expect("lock poisoned")
+                .put(key, http_ext::clone_http_response(response));
+        }
+    }
+}
+
+/// Admission of a request, giving back its probe slot if the request is cancelled or rate
+/// limited before its outcome is recorded
+struct AdmissionGuard {
+    state: Arc<CircuitBreakerState>,
+    admission: Admission,
+    recorded: bool,
+}
+
+impl AdmissionGuard {
+    fn record(mut self, failed: bool, now: Instant) {
+        self.recorded = true;
+        self.state.record(self.admission, failed, now);
+    }
+}
+
+impl Drop for AdmissionGuard {
+    fn drop(&mut self) {
+        if !self.recorded && self.admission == Admission::Probe {
+            self.state.release();
+        }
+    }
+}
+
+/// [`Layer`] failing fast the requests to a subgraph while its circuit is open
+#[derive(Clone)]
+pub(crate) struct CircuitBreakerLayer {
+    pub(crate) state: Arc<CircuitBreakerState>,
+}
+
+impl CircuitBreakerLayer {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        error_percent: Option<f64>,
+        slow_request: Option<Duration>,
+        min_requests: Option<u32>,
+        window: Option<Duration>,
+        open_duration: Option<Duration>,
+        half_open_requests: Option<u32>,
+        bypass_operations: Option<Vec<String>>,
+        stale_responses: Option<NonZeroUsize>,
+        subgraph_name: String,
+    ) -> Self {
+        Self {
+            state: Arc::new(CircuitBreakerState {
+                subgraph_name,
+                error_percent: error_percent.unwrap_or(50.0),
+                slow_request,
+                min_requests: min_requests.unwrap_or(20),
+                window: window.unwrap_or_else(|| Duration::from_secs(10)),
+                open_duration: open_duration.unwrap_or_else(|| Duration::from_secs(30)),
+                half_open_requests: half_open_requests.unwrap_or(5),
+                bypass_operations: bypass_operations.unwrap_or_default().into_iter().collect(),
+                breaker: Mutex::new(Breaker {
+                    circuit: Circuit::Closed,
+                    buckets: VecDeque::new(),
+                }),
+                stale_responses: stale_responses.map(|size| Mutex::new(LruCache::new(size))),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CircuitBreaker<S> {
+    inner: S,
+    state: Arc<CircuitBreakerState>,
+}
+
+impl<S> Service<subgraph::Request> for CircuitBreaker<S>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    type Response = subgraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // the rejected requests must not wait for the readiness of the inner service
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: subgraph::Request) -> Self::Future {
+        let service = self.inner.clone();
+        let state = self.state.clone();
+
+        Box::pin(async move {
+            let bypass = req
+                .supergraph_request
+                .body()
+                .operation_name
+                .as_ref()
+                .map_or(false, |name| state.bypass_operations.contains(name));
+            if bypass {
+                return service.oneshot(req).await.map_err(Into::into);
+            }
+
+            let stale_key = (state.stale_responses.is_some()
+                && req.operation_kind == OperationKind::Query)
+                .then(|| ((&req.subgraph_request).into(), req.authorization.clone()));
+
+            let admission = state.admit(Instant::now());
+            if admission == Admission::Rejected {
+                u64_counter!(
+                    "apollo.router.operations.subgraph.circuit_breaker.rejected",
+                    "Number of subgraph requests rejected because the circuit breaker is open",
+                    1,
+                    "subgraph.name" = state.subgraph_name.clone()
+                );
+                if let Some(response) = stale_key.and_then(|key| state.stale_response(&key)) {
+                    u64_counter!(
+                        "apollo.router.operations.subgraph.circuit_breaker.stale",
+                        "Number of subgraph requests answered with a stale response because the circuit breaker is open",
+                        1,
+                        "subgraph.name" = state.subgraph_name.clone()
+                    );
+                    return Ok(subgraph::Response::new_from_response(
+                        response,
+                        req.context,
+                        state.subgraph_name.clone(),
+                    ));
+                }
+                return Err(CircuitOpen::new().into());
+            }
+            let guard = AdmissionGuard {
+                state: state.clone(),
+                admission,
+                recorded: false,
+            };
+
+            let start = Instant::now();
+            let response = service.oneshot(req).await.map_err(Into::into);
+            if matches!(&response, Err(error) if error.is::<RateLimited>()) {
+                // the rate limited requests did not reach the subgraph
+                return response;
+            }
+            let failed = match &response {
+                Ok(response) => {
+                    response.response.status().is_server_error()
+                        || state
+                            .slow_request
+                            .map_or(false, |slow_request| start.elapsed() >= slow_request)
+                }
+                Err(_) => true,
+            };
+            guard.record(failed, Instant::now());
+
+            if let (Some(key), Ok(response)) = (stale_key, &response) {
+                if !failed
+                    && response.response.status().is_success()
+                    && response.response.body().errors.is_empty()
+                {
+                    state.store_response(key, &response.response);
+                }
+            }
+            response
+        })
+    }
+}
+
+/// Serves the states of the circuits with `GET`, and trips or resets them with `PATCH`
+#[derive(Clone)]
+pub(crate) struct CircuitBreakerService {
+    circuit_breakers: CircuitBreakers,
+    shared_key: String,
+}
+
+impl CircuitBreakerService {
+    pub(crate) fn new(circuit_breakers: CircuitBreakers, shared_key: String) -> Self {
+        Self {
+            circuit_breakers,
+            shared_key,
+        }
+    }
+
+    fn to_json(&self) -> Result<String, serde_json::Error> {
+        let states: BTreeMap<String, CircuitState> = self
+            .circuit_breakers
+            .lock()
+            .expect("lock poisoned")
+            .iter()
+            .map(|(name, layer)| (name.clone(), layer.state.state()))
+            .collect();
+        serde_json::to_string(&states)
+    }
+
+    fn apply(&self, actions: HashMap<String, Action>) -> Result<(), String> {
+        let circuit_breakers = self.circuit_breakers.lock().expect("lock poisoned");
+        if let Some(name) = actions
+            .keys()
+            .find(|name| !circuit_breakers.contains_key(*name))
+        {
+            return Err(format!("the {name} subgraph has no circuit breaker"));
+        }
+        for (name, action) in actions {
+            circuit_breakers[&name].state.apply(action);
+        }
+        Ok(())
+    }
+}
+
+fn response(
+    status: StatusCode,
+    body: String,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .body(body.into())
+            .map_err(BoxError::from)?,
+        context,
+    })
+}
+
+impl Service<router::Request> for CircuitBreakerService {
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, req: router::Request) -> Self::Future {
+        let endpoint = self.clone();
+        Box::pin(async move {
+            let (parts, body) = req.router_request.into_parts();
+            if !http_ext::has_shared_key(&parts.headers, &endpoint.shared_key) {
+                return response(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid authorization header".to_string(),
+                    req.context,
+                );
+            }
+
+            match parts.method {
+                Method::GET => response(StatusCode::OK, endpoint.to_json()?, req.context),
+                Method::PATCH => {
+                    let actions = Into::<RouterBody>::into(body)
+                        .to_bytes()
+                        .await
+                        .map_err(|e| format!("failed to get the request body: {e}"))
+                        .and_then(|bytes| {
+                            serde_json::from_reader::<_, HashMap<String, Action>>(bytes.reader())
+                                .map_err(|err| {
+                                    format!(
+                                        "failed to deserialize the request body into JSON: {err}"
+                                    )
+                                })
+                        })
+                        .and_then(|actions| endpoint.apply(actions));
+                    match actions {
+                        Ok(()) => response(StatusCode::OK, endpoint.to_json()?, req.context),
+                        Err(err) => response(StatusCode::BAD_REQUEST, err, req.context),
+                    }
+                }
+                _ => response(StatusCode::METHOD_NOT_ALLOWED, String::new(), req.context),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    fn layer() -> CircuitBreakerLayer {
+        CircuitBreakerLayer::new(
+            Some(50.0),
+            None,
+            Some(4),
+            None,
+            Some(Duration::from_secs(30)),
+            Some(2),
+            Some(vec!["Health".to_string()]),
+            NonZeroUsize::new(10),
+            "test".to_string(),
+        )
+    }
+
+    #[test]
+    fn the_circuit_opens_then_closes_after_successful_probes() {
+        let state = layer().state;
+        let now = Instant::now();
+
+        for failed in [true, false, true] {
+            assert_eq!(state.admit(now), Admission::Allowed);
+            state.record(Admission::Allowed, failed, now);
+        }
+        assert_eq!(state.state(), CircuitState::Closed);
+        state.record(Admission::Allowed, true, now);
+        assert_eq!(state.state(), CircuitState::Open);
+        assert_eq!(state.admit(now), Admission::Rejected);
+
+        // probes are sent once the circuit has been open for a while, up to the limit
+        let later = now + Duration::from_secs(31);
+        assert_eq!(state.admit(later), Admission::Probe);
+        assert_eq!(state.state(), CircuitState::HalfOpen);
+        assert_eq!(state.admit(later), Admission::Probe);
+        assert_eq!(state.admit(later), Admission::Rejected);
+        state.record(Admission::Probe, false, later);
+        assert_eq!(state.state(), CircuitState::HalfOpen);
+        state.record(Admission::Probe, false, later);
+        assert_eq!(state.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn a_failed_probe_opens_the_circuit_again() {
+        let state = layer().state;
+        let now = Instant::now();
+        state.apply(Action::Trip);
+        assert_eq!(
+            state.admit(now + Duration::from_secs(3600)),
+            Admission::Rejected
+        );
+
+        state.apply(Action::Reset);
+        for _ in 0..4 {
+            state.record(Admission::Allowed, true, now);
+        }
+        let later = now + Duration::from_secs(31);
+        assert_eq!(state.admit(later), Admission::Probe);
+        state.record(Admission::Probe, true, later);
+        assert_eq!(state.state(), CircuitState::Open);
+        assert_eq!(state.admit(later), Admission::Rejected);
+    }
+
+    #[test]
+    fn failures_out_of_the_window_are_forgotten() {
+        let state = layer().state;
+        let now = Instant::now();
+        for _ in 0..3 {
+            state.record(Admission::Allowed, true, now);
+        }
+        state.record(Admission::Allowed, true, now + Duration::from_secs(11));
+        assert_eq!(state.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn open_circuits_fail_fast_except_for_bypassed_operations() {
+        let layer = layer();
+        layer.state.apply(Action::Trip);
+        let service = tower::service_fn(|req: subgraph::Request| async move {
+            Ok::<_, BoxError>(
+                subgraph::Response::fake_builder()
+                    .data(json!("ok"))
+                    .context(req.context)
+                    .build(),
+            )
+        });
+
+        let error = layer
+            .layer(service)
+            .oneshot(subgraph::Request::fake_builder().build())
+            .await
+            .unwrap_err();
+        assert!(error.is::<CircuitOpen>());
+
+        let response = layer
+            .layer(service)
+            .oneshot(
+                subgraph::Request::fake_builder()
+                    .supergraph_request(Arc::new(http::Request::new(
+                        graphql::Request::builder().operation_name("Health").build(),
+                    )))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.response.body().data, Some(json!("ok")));
+    }
+
+    #[test]
+    fn a_cancelled_probe_gives_back_its_slot() {
+        let state = layer().state;
+        let now = Instant::now();
+        for _ in 0..4 {
+            state.record(Admission::Allowed, true, now);
+        }
+
+        let later = now + Duration::from_secs(31);
+        assert_eq!(state.admit(later), Admission::Probe);
+        drop(AdmissionGuard {
+            state: state.clone(),
+            admission: Admission::Probe,
+            recorded: false,
+        });
+        assert_eq!(state.admit(later), Admission::Probe);
+        assert_eq!(state.admit(later), Admission::Probe);
+        assert_eq!(state.admit(later), Admission::Rejected);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_not_failures() {
+        let layer = layer();
+        let service = tower::service_fn(|_: subgraph::Request| async move {
+            Err::<subgraph::Response, BoxError>(RateLimited::new().into())
+        });
+
+        for _ in 0..4 {
+            let error = layer
+                .layer(service)
+                .oneshot(subgraph::Request::fake_builder().build())
+                .await
+                .unwrap_err();
+            assert!(error.is::<RateLimited>());
+        }
+        assert_eq!(layer.state.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn open_circuits_serve_the_stale_responses_of_queries() {
+        let layer = layer();
+        let service = tower::service_fn(|req: subgraph::Request| async move {
+            Ok::<_, BoxError>(
+                subgraph::Response::fake_builder()
+                    .data(json!(req.subgraph_request.body().query))
+                    .context(req.context)
+                    .build(),
+            )
+        });
+        let request = |query: &str| {
+            subgraph::Request::fake_builder()
+                .subgraph_request(http::Request::new(
+                    graphql::Request::builder().query(query).build(),
+                ))
+                .build()
+        };
+
+        let response = layer
+            .layer(service)
+            .oneshot(request("{ me { id } }"))
+            .await
+            .unwrap();
+        assert_eq!(response.response.body().data, Some(json!("{ me { id } }")));
+
+        layer.state.apply(Action::Trip);
+        let response = layer
+            .layer(service)
+            .oneshot(request("{ me { id } }"))
+            .await
+            .unwrap();
+        assert_eq!(response.response.body().data, Some(json!("{ me { id } }")));
+
+        let error = layer
+            .layer(service)
+            .oneshot(request("{ me { name } }"))
+            .await
+            .unwrap_err();
+        assert!(error.is::<CircuitOpen>());
+    }
+}
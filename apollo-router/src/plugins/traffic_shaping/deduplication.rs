@@ -19,6 +19,7 @@ use crate::batching::BatchQuery;
 use crate::graphql::Request;
 use crate::http_ext;
 use crate::plugins::authorization::CacheKeyMetadata;
+use crate::plugins::fetch_details::FetchStats;
 use crate::query_planner::fetch::OperationKind;
 use crate::services::SubgraphRequest;
 use crate::services::SubgraphResponse;
@@ -96,6 +97,13 @@ where
                     // Register interest in key
                     let mut receiver = waiter.subscribe();
                     drop(locked_wait_map);
+                    if let Some(stats) = request
+                        .subgraph_request
+                        .extensions()
+                        .get::<Arc<FetchStats>>()
+                    {
+                        stats.record_deduplicated();
+                    }
 
                     match receiver.recv().await {
                         Ok(value) => {
@@ -0,0 +1,314 @@
+//! Hedging of the subgraph queries.
+//!
+//! When a query takes longer than a percentile of the recent latencies of its subgraph, a second
+//! attempt is sent, and the first successful response is kept. Only queries are hedged, since
+//! they are idempotent, and the extra attempts are capped by a budget, like the retries.
+
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::future::Either;
+use futures::TryFutureExt;
+use tower::retry::budget::Budget;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use crate::plugins::traffic_shaping::retry::clone_request;
+use crate::query_planner::OperationKind;
+use crate::services::subgraph;
+
+/// Latencies kept to compute the hedging delay
+const WINDOW_SIZE: usize = 1000;
+
+/// Latencies recorded between two updates of the hedging delay
+const UPDATE_INTERVAL: usize = 50;
+
+/// Recent latencies of a subgraph
+#[derive(Default)]
+struct Latencies {
+    samples: VecDeque<Duration>,
+    since_update: usize,
+}
+
+struct HedgingState {
+    subgraph_name: String,
+    percentile: f64,
+    min_data_points: usize,
+    budget: Budget,
+    latencies: Mutex<Latencies>,
+    /// Delay before a query is hedged, in microseconds. Zero until enough latencies are recorded
+    delay_micros: AtomicU64,
+}
+
+impl HedgingState {
+    fn delay(&self) -> Option<Duration> {
+        match self.delay_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    /// Records the latency of a successful response, and updates the hedging delay every
+    /// `UPDATE_INTERVAL` latencies
+    fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.samples.len() == WINDOW_SIZE {
+            latencies.samples.pop_front();
+        }
+        latencies.samples.push_back(latency);
+        latencies.since_update += 1;
+
+        let first_update = self.delay().is_none();
+        if latencies.samples.len() < self.min_data_points
+            || (!first_update && latencies.since_update < UPDATE_INTERVAL)
+        {
+            return;
+        }
+        latencies.since_update = 0;
+        let mut sorted: Vec<Duration> = latencies.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let index = ((sorted.len() - 1) as f64 * self.percentile / 100.0).round() as usize;
+        let delay = sorted[index.min(sorted.len() - 1)];
+        self.delay_micros
+            .store((delay.as_micros() as u64).max(1), Ordering::Relaxed);
+    }
+}
+
+/// [`Layer`] sending a second attempt for the queries slower than a percentile of the latencies
+/// of their subgraph
+#[derive(Clone)]
+pub(crate) struct HedgingLayer {
+    state: Arc<HedgingState>,
+}
+
+impl HedgingLayer {
+    pub(crate) fn new(
+        percentile: Option<f64>,
+        min_data_points: Option<usize>,
+        duration: Option<Duration>,
+        min_per_sec: Option<u32>,
+        hedge_percent: Option<f32>,
+        subgraph_name: String,
+    ) -> Self {
+        Self {
+            state: Arc::new(HedgingState {
+                subgraph_name,
+                percentile: percentile.unwrap_or(95.0),
+                min_data_points: min_data_points.unwrap_or(100),
+                budget: Budget::new(
+                    duration.unwrap_or_else(|| Duration::from_secs(10)),
+                    min_per_sec.unwrap_or(10),
+                    hedge_percent.unwrap_or(0.1),
+                ),
+                latencies: Mutex::new(Latencies::default()),
+                delay_micros: AtomicU64::new(0),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for HedgingLayer {
+    type Service = Hedging<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Hedging {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Hedging<S> {
+    inner: S,
+    state: Arc<HedgingState>,
+}
+
+impl<S> Service<subgraph::Request> for Hedging<S>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    type Response = subgraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // each attempt waits for the readiness of its own clone of the inner service
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: subgraph::Request) -> Self::Future {
+        let service = self.inner.clone();
+        let state = self.state.clone();
+
+        Box::pin(async move {
+            state.budget.deposit();
+            let hedge = match state.delay() {
+                Some(delay) if req.operation_kind == OperationKind::Query => {
+                    Some((delay, clone_request(&req)))
+                }
+                _ => None,
+            };
+
+            let start = Instant::now();
+            let original = service.clone().oneshot(req).map_err(Into::into);
+            let Some((delay, hedge_request)) = hedge else {
+                let response = original.await;
+                if response.is_ok() {
+                    state.record(start.elapsed());
+                }
+                return response;
+            };
+
+            tokio::pin!(original);
+            let response = tokio::select! {
+                response = &mut original => Some(response),
+                _ = tokio::time::sleep(delay) => None,
+            };
+            // the latency recorded is the one of the attempt which produced the response
+            let (response, latency) = match response {
+                Some(response) => (response, start.elapsed()),
+                None if state.budget.withdraw().is_err() => {
+                    u64_counter!(
+                        "apollo.router.operations.subgraph.hedge",
+                        "Number of subgraph queries sent again after the hedging delay",
+                        1,
+                        "subgraph.name" = state.subgraph_name.clone(),
+                        "status" = "aborted"
+                    );
+                    let response = original.await;
+                    (response, start.elapsed())
+                }
+                None => {
+                    u64_counter!(
+                        "apollo.router.operations.subgraph.hedge",
+                        "Number of subgraph queries sent again after the hedging delay",
+                        1,
+                        "subgraph.name" = state.subgraph_name.clone(),
+                        "status" = "sent"
+                    );
+                    let hedge_start = Instant::now();
+                    let hedge = service.oneshot(hedge_request).map_err(Into::into);
+                    tokio::pin!(hedge);
+                    // the first successful response is kept, the other attempt is cancelled
+                    match futures::future::select(original, hedge).await {
+                        Either::Left((Ok(response), _)) => (Ok(response), start.elapsed()),
+                        Either::Right((Ok(response), _)) => (Ok(response), hedge_start.elapsed()),
+                        Either::Left((Err(_), hedge)) => {
+                            let response = hedge.await;
+                            (response, hedge_start.elapsed())
+                        }
+                        Either::Right((Err(_), original)) => {
+                            let response = original.await;
+                            (response, start.elapsed())
+                        }
+                    }
+                }
+            };
+            if response.is_ok() {
+                state.record(latency);
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    fn service(
+        calls: Arc<AtomicUsize>,
+    ) -> impl Service<
+        subgraph::Request,
+        Response = subgraph::Response,
+        Error = BoxError,
+        Future = BoxFuture<'static, Result<subgraph::Response, BoxError>>,
+    > + Clone {
+        // the first attempt is slow, the next ones are fast
+        tower::service_fn(move |req: subgraph::Request| {
+            let first = calls.fetch_add(1, Ordering::SeqCst) == 0;
+            Box::pin(async move {
+                let data = if first {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    "slow"
+                } else {
+                    "fast"
+                };
+                Ok(subgraph::Response::fake_builder()
+                    .data(json!(data))
+                    .context(req.context)
+                    .build())
+            }) as BoxFuture<'static, Result<subgraph::Response, BoxError>>
+        })
+    }
+
+    fn layer() -> HedgingLayer {
+        let layer = HedgingLayer::new(Some(50.0), Some(2), None, None, None, "test".to_string());
+        layer.state.record(Duration::from_millis(5));
+        assert_eq!(layer.state.delay(), None);
+        layer.state.record(Duration::from_millis(20));
+        layer
+    }
+
+    #[tokio::test]
+    async fn slow_queries_are_hedged() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let response = layer()
+            .layer(service(calls.clone()))
+            .oneshot(subgraph::Request::fake_builder().build())
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.body().data, Some(json!("fast")));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn the_latency_of_the_winning_hedge_is_recorded() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let layer = layer();
+        layer
+            .layer(service(calls.clone()))
+            .oneshot(subgraph::Request::fake_builder().build())
+            .await
+            .unwrap();
+
+        // the hedge answers right away, the hedging delay is not part of its latency
+        let latencies = layer.state.latencies.lock().unwrap();
+        assert_eq!(latencies.samples.len(), 3);
+        assert!(latencies.samples[2] < Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn mutations_are_not_hedged() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let response = layer()
+            .layer(service(calls.clone()))
+            .oneshot(
+                subgraph::Request::fake_builder()
+                    .operation_kind(OperationKind::Mutation)
+                    .build(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.body().data, Some(json!("slow")));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,422 @@
+//! Mirroring of a sample of the queries to a shadow endpoint.
+//!
+//! Mirrored requests are sent in the background, so they do not affect the latency of the
+//! original requests. Their responses are discarded once they have been compared with the
+//! responses of the router.
+
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::header::ACCEPT;
+use http::header::CONTENT_LENGTH;
+use http::header::CONTENT_TYPE;
+use http::header::HOST;
+use http::HeaderMap;
+use http::HeaderName;
+use http::HeaderValue;
+use http::StatusCode;
+use mime::APPLICATION_JSON;
+use rand::Rng;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::sync::oneshot;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::context::OPERATION_KIND;
+use crate::graphql;
+use crate::plugin::serde::deserialize_header_name;
+use crate::plugin::serde::deserialize_regex;
+use crate::query_planner::OperationKind;
+use crate::services::supergraph;
+
+const DEFAULT_MIRROR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Mirroring of a sample of the queries to a shadow endpoint, like a router running a candidate
+/// schema or configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MirrorConf {
+    /// URL of the shadow endpoint
+    pub(crate) url: String,
+    /// Fraction of the queries that are mirrored, between 0 and 1
+    pub(crate) sampling: f64,
+    /// Headers removed from the mirrored requests
+    #[serde(default)]
+    pub(crate) scrub_headers: Vec<ScrubHeader>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Timeout of the mirrored requests (default: 30s)
+    pub(crate) timeout: Option<Duration>,
+}
+
+schemar_fn!(scrub_named, String, "Remove a header given a header name");
+schemar_fn!(
+    scrub_matching,
+    String,
+    "Remove a header given a regex matching against the header name"
+);
+
+/// Header removed from the mirrored requests
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ScrubHeader {
+    #[schemars(schema_with = "scrub_named")]
+    #[serde(deserialize_with = "deserialize_header_name")]
+    /// Remove a header given a header name
+    Named(HeaderName),
+
+    #[schemars(schema_with = "scrub_matching")]
+    #[serde(deserialize_with = "deserialize_regex")]
+    /// Remove a header given a regex matching header name
+    Matching(Regex),
+}
+
+impl ScrubHeader {
+    fn matches(&self, name: &HeaderName) -> bool {
+        match self {
+            ScrubHeader::Named(named) => named == name,
+            ScrubHeader::Matching(regex) => regex.is_match(name.as_str()),
+        }
+    }
+}
+
+/// Mirrors a sample of the queries to a shadow endpoint.
+#[derive(Clone)]
+pub(crate) struct MirrorLayer {
+    client: reqwest::Client,
+    url: reqwest::Url,
+    conf: Arc<MirrorConf>,
+}
+
+impl MirrorLayer {
+    pub(crate) fn new(conf: MirrorConf) -> Result<Self, BoxError> {
+        if !(0.0..=1.0).contains(&conf.sampling) {
+            return Err("the mirror sampling must be between 0 and 1".into());
+        }
+        let url = reqwest::Url::parse(&conf.url)?;
+        let client = reqwest::Client::builder()
+            .timeout(conf.timeout.unwrap_or(DEFAULT_MIRROR_TIMEOUT))
+            .build()?;
+        Ok(Self {
+            client,
+            url,
+            conf: Arc::new(conf),
+        })
+    }
+
+    fn sample(&self, request: &supergraph::Request) -> bool {
+        // mutations and subscriptions would have side effects, or never complete
+        let is_query = request
+            .context
+            .get::<_, OperationKind>(OPERATION_KIND)
+            .ok()
+            .flatten()
+            .map(|kind| kind == OperationKind::Query)
+            .unwrap_or_default();
+        is_query && rand::thread_rng().gen_bool(self.conf.sampling)
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> HeaderMap {
+        let mut mirrored = HeaderMap::with_capacity(headers.len());
+        for (name, value) in headers {
+            if name == HOST
+                || name == CONTENT_LENGTH
+                || self
+                    .conf
+                    .scrub_headers
+                    .iter()
+                    .any(|rule| rule.matches(name))
+            {
+                continue;
+            }
+            mirrored.append(name.clone(), value.clone());
+        }
+        let json = HeaderValue::from_static(APPLICATION_JSON.essence_str());
+        mirrored.insert(CONTENT_TYPE, json.clone());
+        mirrored.insert(ACCEPT, json);
+        mirrored
+    }
+}
+
+impl<S> Layer<S> for MirrorLayer {
+    type Service = Mirror<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        Mirror {
+            inner: service,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Mirror<S> {
+    inner: S,
+    layer: MirrorLayer,
+}
+
+impl<S> Service<supergraph::Request> for Mirror<S>
+where
+    S: Service<supergraph::Request, Response = supergraph::Response, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = supergraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: supergraph::Request) -> Self::Future {
+        if !self.layer.sample(&request) {
+            return self.inner.call(request).boxed();
+        }
+
+        let (primary_sender, primary_receiver) = oneshot::channel();
+        match serde_json::to_vec(request.supergraph_request.body()) {
+            Ok(body) => {
+                let mirrored = self
+                    .layer
+                    .client
+                    .post(self.layer.url.clone())
+                    .headers(self.layer.headers(request.supergraph_request.headers()))
+                    .body(body);
+                tokio::spawn(mirror(mirrored, primary_receiver));
+            }
+            Err(error) => {
+                tracing::debug!("could not serialize the mirrored request: {error}");
+            }
+        }
+
+        let start = Instant::now();
+        self.inner
+            .call(request)
+            .map(move |result| {
+                result.map(|response| {
+                    let status = response.response.status();
+                    let mut primary_sender = Some(primary_sender);
+                    response.map_stream(move |response| {
+                        if let Some(sender) = primary_sender.take() {
+                            let _ = sender.send(Outcome {
+                                status,
+                                hash: response_hash(&response),
+                                duration: start.elapsed(),
+                            });
+                        }
+                        response
+                    })
+                })
+            })
+            .boxed()
+    }
+}
+
+/// Status, response hash and latency of a request, to compare the router and the shadow endpoint.
+struct Outcome {
+    status: StatusCode,
+    hash: Option<Vec<u8>>,
+    duration: Duration,
+}
+
+async fn mirror(request: reqwest::RequestBuilder, primary: oneshot::Receiver<Outcome>) {
+    let start = Instant::now();
+    let shadow = match request.send().await {
+        Ok(response) => {
+            let status = response.status();
+            let hash = response
+                .bytes()
+                .await
+                .ok()
+                .and_then(|body| serde_json::from_slice::<graphql::Response>(&body).ok())
+                .and_then(|response| response_hash(&response));
+            Ok(Outcome {
+                status,
+                hash,
+                duration: start.elapsed(),
+            })
+        }
+        Err(error) => Err(error),
+    };
+
+    // the primary response was dropped before its first chunk, there is nothing to compare
+    let Ok(primary) = primary.await else {
+        return;
+    };
+
+    match shadow {
+        Ok(shadow) => {
+            for (target, duration) in [("primary", primary.duration), ("shadow", shadow.duration)] {
+                f64_histogram!(
+                    "apollo.router.operations.traffic_shaping.mirror.duration",
+                    "Duration of the mirrored operations, on the router and on the shadow endpoint.",
+                    duration.as_secs_f64(),
+                    "mirror.target" = target
+                );
+            }
+            u64_counter!(
+                "apollo.router.operations.traffic_shaping.mirror",
+                "Total operations mirrored to the shadow endpoint",
+                1,
+                "mirror.status_code" = shadow.status.as_u16() as i64,
+                "mirror.status_match" = shadow.status == primary.status,
+                "mirror.response_match" = shadow.hash.is_some() && shadow.hash == primary.hash
+            );
+        }
+        Err(error) => {
+            tracing::debug!("could not mirror the request: {error}");
+            u64_counter!(
+                "apollo.router.operations.traffic_shaping.mirror",
+                "Total operations mirrored to the shadow endpoint",
+                1,
+                "mirror.error" = true
+            );
+        }
+    }
+}
+
+/// Hashes the data and errors of a response, ignoring the extensions.
+fn response_hash(response: &graphql::Response) -> Option<Vec<u8>> {
+    #[derive(Serialize)]
+    struct Compared<'a> {
+        data: &'a Option<serde_json_bytes::Value>,
+        errors: &'a [graphql::Error],
+    }
+
+    let serialized = serde_json::to_vec(&Compared {
+        data: &response.data,
+        errors: &response.errors,
+    })
+    .ok()?;
+    Some(Sha256::digest(serialized).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+    use tokio::sync::mpsc;
+    use tower::ServiceExt;
+    use wiremock::matchers::body_json;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::Respond;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+
+    fn mirror_layer(url: String, sampling: f64) -> MirrorLayer {
+        MirrorLayer::new(
+            serde_json::from_value(serde_json::json!({
+                "url": url,
+                "sampling": sampling,
+                "scrub_headers": [{ "named": "authorization" }, { "matching": "^x-secret-.*" }]
+            }))
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn query() -> supergraph::Request {
+        let request = supergraph::Request::fake_builder()
+            .query("{ me { name } }")
+            .header("authorization", "Bearer token")
+            .header("x-secret-key", "secret")
+            .header("x-client", "web")
+            .build()
+            .unwrap();
+        request
+            .context
+            .insert(OPERATION_KIND, OperationKind::Query)
+            .unwrap();
+        request
+    }
+
+    fn primary() -> MockSupergraphService {
+        let mut primary = MockSupergraphService::new();
+        primary.expect_call().returning(|request| {
+            supergraph::Response::fake_builder()
+                .data(json!({ "me": { "name": "Ada" } }))
+                .context(request.context)
+                .build()
+        });
+        primary
+    }
+
+    /// Answers the mirrored requests like the router, and sends them to the test
+    struct Shadow(mpsc::UnboundedSender<wiremock::Request>);
+
+    impl Respond for Shadow {
+        fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+            let _ = self.0.send(request.clone());
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({ "data": { "me": { "name": "Ada" } } }))
+        }
+    }
+
+    #[tokio::test]
+    async fn mirrors_queries_without_scrubbed_headers() {
+        let shadow = MockServer::start().await;
+        let (sender, mut mirrored) = mpsc::unbounded_channel();
+        Mock::given(method("POST"))
+            .and(header("x-client", "web"))
+            .and(body_json(serde_json::json!({ "query": "{ me { name } }" })))
+            .respond_with(Shadow(sender))
+            .expect(1)
+            .mount(&shadow)
+            .await;
+
+        let service = mirror_layer(shadow.uri(), 1.0).layer(primary());
+        let mut response = service.oneshot(query()).await.unwrap();
+        response.next_response().await.unwrap();
+
+        let request = tokio::time::timeout(Duration::from_secs(5), mirrored.recv())
+            .await
+            .expect("the query was not mirrored")
+            .unwrap();
+        assert!(!request.headers.contains_key("authorization"));
+        assert!(!request.headers.contains_key("x-secret-key"));
+    }
+
+    #[test]
+    fn only_samples_queries() {
+        assert!(mirror_layer("http://shadow".to_string(), 1.0).sample(&query()));
+        assert!(!mirror_layer("http://shadow".to_string(), 0.0).sample(&query()));
+
+        let mutation = query();
+        mutation
+            .context
+            .insert(OPERATION_KIND, OperationKind::Mutation)
+            .unwrap();
+        assert!(!mirror_layer("http://shadow".to_string(), 1.0).sample(&mutation));
+    }
+
+    #[test]
+    fn response_hashes_ignore_extensions() {
+        let response = graphql::Response::builder()
+            .data(json!({ "me": { "name": "Ada" } }))
+            .build();
+        let with_extensions = graphql::Response::builder()
+            .data(json!({ "me": { "name": "Ada" } }))
+            .extension("cost", json!(1))
+            .build();
+        let different = graphql::Response::builder()
+            .data(json!({ "me": { "name": "Grace" } }))
+            .build();
+
+        assert_eq!(response_hash(&response), response_hash(&with_extensions));
+        assert_ne!(response_hash(&response), response_hash(&different));
+    }
+}
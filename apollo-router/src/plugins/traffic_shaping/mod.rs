@@ -5,22 +5,33 @@
 //! * Timeout
 //! * Compression
 //! * Rate limiting
+//! * Mirroring
+//! * Circuit breaking
 //!
+pub(crate) mod circuit_breaker;
 mod deduplication;
+mod hedging;
+mod mirror;
 pub(crate) mod rate;
 mod retry;
 pub(crate) mod timeout;
 
 use std::collections::HashMap;
 use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
 use std::sync::Mutex;
 use std::time::Duration;
 
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use http::header::CONTENT_ENCODING;
+use http::HeaderName;
 use http::HeaderValue;
 use http::StatusCode;
+use multimap::MultiMap;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry::metrics::ObservableGauge;
+use opentelemetry::KeyValue;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use tower::util::Either;
@@ -29,24 +40,44 @@ use tower::Service;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use self::circuit_breaker::CircuitBreakerEndpointConfig;
+use self::circuit_breaker::CircuitBreakerLayer;
+use self::circuit_breaker::CircuitBreakerService;
+use self::circuit_breaker::CircuitBreakers;
+use self::circuit_breaker::CircuitOpen;
+use self::circuit_breaker::CircuitState;
 use self::deduplication::QueryDeduplicationLayer;
+use self::hedging::HedgingLayer;
+use self::mirror::MirrorConf;
+use self::mirror::MirrorLayer;
 use self::rate::RateLimitLayer;
+use self::rate::RateLimitState;
 use self::rate::RateLimited;
 pub(crate) use self::retry::RetryPolicy;
 use self::timeout::Elapsed;
+use self::timeout::RequestTimeouts;
 use self::timeout::TimeoutLayer;
+use crate::configuration::shared::Expose;
 use crate::error::ConfigurationError;
 use crate::graphql;
 use crate::layers::ServiceBuilderExt;
+use crate::metrics;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::telemetry::config_new::instruments::METER_NAME;
+use crate::query_planner::fetch::OperationKind;
 use crate::register_plugin;
 use crate::services::http::service::Compression;
 use crate::services::subgraph;
 use crate::services::supergraph;
 use crate::services::SubgraphRequest;
+use crate::Endpoint;
+use crate::ListenAddr;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+static X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+static X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+static X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
 pub(crate) const APOLLO_TRAFFIC_SHAPING: &str = "apollo.traffic_shaping";
 
 trait Merge {
@@ -59,19 +90,25 @@ trait Merge {
 struct Shaping {
     /// Enable query deduplication
     deduplicate_query: Option<bool>,
-    /// Enable compression for subgraphs (available compressions are deflate, br, gzip)
+    /// Enable compression for subgraphs (available compressions are deflate, br, gzip, zstd)
     compression: Option<Compression>,
     /// Enable global rate limiting
     global_rate_limit: Option<RateLimitConf>,
-    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
-    #[schemars(with = "String", default)]
-    /// Enable timeout for incoming requests
-    timeout: Option<Duration>,
+    /// Timeouts of the subgraph requests
+    timeout: Option<SubgraphTimeoutConf>,
     /// Retry configuration
     //  *experimental feature*: Enables request retry
     experimental_retry: Option<RetryConfig>,
+    /// Hedging configuration
+    //  *experimental feature*: Sends a second attempt for the slow queries
+    experimental_hedging: Option<HedgingConfig>,
+    /// Circuit breaker configuration
+    //  *experimental feature*: Fails fast the requests to the subgraphs returning too many errors
+    experimental_circuit_breaker: Option<CircuitBreakerConfig>,
     /// Enable HTTP2 for subgraphs
     experimental_http2: Option<Http2Config>,
+    /// HTTP2 keepalive for the connections to subgraphs
+    experimental_http2_keep_alive: Option<Http2KeepAlive>,
 }
 
 #[derive(PartialEq, Default, Debug, Clone, Deserialize, JsonSchema)]
@@ -86,6 +123,162 @@ pub(crate) enum Http2Config {
     Http2Only,
 }
 
+/// HTTP2 keepalive configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Http2KeepAlive {
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// Interval between the PING frames sent on the connections
+    pub(crate) interval: Duration,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Time to wait for the acknowledgement of a PING frame before closing the connection.
+    /// Default: 20s
+    pub(crate) timeout: Option<Duration>,
+    /// Send PING frames on idle connections too, so that dead connections are evicted from
+    /// the pool before a request is sent on them. Default: true
+    pub(crate) while_idle: Option<bool>,
+}
+
+/// Timeouts of the subgraph requests
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum SubgraphTimeoutConf {
+    /// Total timeout of the requests, e.g. '100ms' or '10s'
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    Total(Duration),
+    /// Timeouts of the phases of the requests, by operation kind
+    Phases(SubgraphTimeouts),
+}
+
+impl SubgraphTimeoutConf {
+    fn timeouts(&self) -> SubgraphTimeouts {
+        match self {
+            SubgraphTimeoutConf::Total(total) => SubgraphTimeouts {
+                total: Some(*total),
+                ..Default::default()
+            },
+            SubgraphTimeoutConf::Phases(timeouts) => timeouts.clone(),
+        }
+    }
+}
+
+impl Merge for SubgraphTimeoutConf {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => {
+                SubgraphTimeoutConf::Phases(self.timeouts().merge(Some(&fallback.timeouts())))
+            }
+        }
+    }
+}
+
+/// Connect, first byte and total timeouts of the subgraph requests. The first byte and total
+/// timeouts can be set by operation kind
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct SubgraphTimeouts {
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Time to establish a connection to the subgraph. Connections are shared by all the
+    /// operation kinds, so this timeout can't be set by operation kind
+    connect: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Time to receive the response headers of the subgraph
+    first_byte: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Time of the whole request, retries included. Default: 30s
+    total: Option<Duration>,
+    /// Timeouts of the queries
+    query: Option<OperationTimeouts>,
+    /// Timeouts of the mutations
+    mutation: Option<OperationTimeouts>,
+    /// Timeouts of the subscriptions
+    subscription: Option<OperationTimeouts>,
+}
+
+/// First byte and total timeouts of the requests of an operation kind
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct OperationTimeouts {
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Time to receive the response headers of the subgraph
+    first_byte: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Time of the whole request, retries included
+    total: Option<Duration>,
+}
+
+impl Merge for OperationTimeouts {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => OperationTimeouts {
+                first_byte: self.first_byte.or(fallback.first_byte),
+                total: self.total.or(fallback.total),
+            },
+        }
+    }
+}
+
+impl Merge for SubgraphTimeouts {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => {
+                // the timeouts of a subgraph take precedence over the timeouts of all the
+                // subgraphs, including the ones set by operation kind
+                let operation =
+                    |timeouts: &Option<OperationTimeouts>, fallback: &Option<OperationTimeouts>| {
+                        let fallback = fallback.as_ref().map(|fallback| OperationTimeouts {
+                            first_byte: fallback.first_byte.filter(|_| self.first_byte.is_none()),
+                            total: fallback.total.filter(|_| self.total.is_none()),
+                        });
+                        match timeouts {
+                            Some(timeouts) => Some(timeouts.merge(fallback.as_ref())),
+                            None => fallback,
+                        }
+                    };
+                SubgraphTimeouts {
+                    connect: self.connect.or(fallback.connect),
+                    first_byte: self.first_byte.or(fallback.first_byte),
+                    total: self.total.or(fallback.total),
+                    query: operation(&self.query, &fallback.query),
+                    mutation: operation(&self.mutation, &fallback.mutation),
+                    subscription: operation(&self.subscription, &fallback.subscription),
+                }
+            }
+        }
+    }
+}
+
+impl SubgraphTimeouts {
+    /// The timeouts of a request, the ones of its operation kind taking precedence
+    fn for_operation(&self, kind: OperationKind) -> RequestTimeouts {
+        let operation = match kind {
+            OperationKind::Query => self.query.as_ref(),
+            OperationKind::Mutation => self.mutation.as_ref(),
+            OperationKind::Subscription => self.subscription.as_ref(),
+        };
+        RequestTimeouts {
+            first_byte: operation
+                .and_then(|timeouts| timeouts.first_byte)
+                .or(self.first_byte),
+            total: operation
+                .and_then(|timeouts| timeouts.total)
+                .or(self.total)
+                .unwrap_or(DEFAULT_TIMEOUT),
+        }
+    }
+}
+
 impl Merge for Shaping {
     fn merge(&self, fallback: Option<&Self>) -> Self {
         match fallback {
@@ -93,7 +286,11 @@ impl Merge for Shaping {
             Some(fallback) => Shaping {
                 deduplicate_query: self.deduplicate_query.or(fallback.deduplicate_query),
                 compression: self.compression.or(fallback.compression),
-                timeout: self.timeout.or(fallback.timeout),
+                timeout: self
+                    .timeout
+                    .as_ref()
+                    .map(|timeout| timeout.merge(fallback.timeout.as_ref()))
+                    .or_else(|| fallback.timeout.clone()),
                 global_rate_limit: self
                     .global_rate_limit
                     .as_ref()
@@ -104,11 +301,28 @@ impl Merge for Shaping {
                     .as_ref()
                     .or(fallback.experimental_retry.as_ref())
                     .cloned(),
+                experimental_hedging: self
+                    .experimental_hedging
+                    .as_ref()
+                    .map(|hedging| hedging.merge(fallback.experimental_hedging.as_ref()))
+                    .or_else(|| fallback.experimental_hedging.clone()),
+                experimental_circuit_breaker: self
+                    .experimental_circuit_breaker
+                    .as_ref()
+                    .map(|circuit_breaker| {
+                        circuit_breaker.merge(fallback.experimental_circuit_breaker.as_ref())
+                    })
+                    .or_else(|| fallback.experimental_circuit_breaker.clone()),
                 experimental_http2: self
                     .experimental_http2
                     .as_ref()
                     .or(fallback.experimental_http2.as_ref())
                     .cloned(),
+                experimental_http2_keep_alive: self
+                    .experimental_http2_keep_alive
+                    .as_ref()
+                    .or(fallback.experimental_http2_keep_alive.as_ref())
+                    .cloned(),
             },
         }
     }
@@ -150,6 +364,106 @@ impl Merge for RetryConfig {
     }
 }
 
+/// Hedging configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct HedgingConfig {
+    /// percentile of the recent latencies of the subgraph that a query must exceed before a
+    /// second attempt is sent. Must be between 0 and 100, default value is 95
+    percentile: Option<f64>,
+    /// number of successful requests to the subgraph whose latency is recorded before queries
+    /// are hedged. Must be between 1 and 1000, default value is 100
+    min_data_points: Option<usize>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// how long each request to the subgraph adds to the budget of hedged queries. Must be
+    /// between 1 and 60 seconds, default value is 10 seconds
+    ttl: Option<Duration>,
+    /// number of queries per second that can be hedged whatever the budget, so that subgraphs
+    /// receiving few requests can be hedged. The default value is 10
+    min_per_sec: Option<u32>,
+    /// ratio of the requests to the subgraph over `ttl` that can be hedged, on top of
+    /// `min_per_sec`. Must be between 0 and 1000, default value is 0.1, one hedged query for
+    /// ten requests
+    hedge_percent: Option<f32>,
+}
+
+impl Merge for HedgingConfig {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => HedgingConfig {
+                percentile: self.percentile.or(fallback.percentile),
+                min_data_points: self.min_data_points.or(fallback.min_data_points),
+                ttl: self.ttl.or(fallback.ttl),
+                min_per_sec: self.min_per_sec.or(fallback.min_per_sec),
+                hedge_percent: self.hedge_percent.or(fallback.hedge_percent),
+            },
+        }
+    }
+}
+
+/// Circuit breaker configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct CircuitBreakerConfig {
+    /// percentage of failed requests over the window after which the circuit opens. A
+    /// request fails if it returns an error or a 5xx status code, or if it is slower than
+    /// `slow_request`. Must be between 0 and 100, default value is 50
+    error_percent: Option<f64>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// requests slower than this duration are counted as failed. Not set by default
+    slow_request: Option<Duration>,
+    /// number of requests in the window before the circuit can open. The default value
+    /// is 20
+    min_requests: Option<u32>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// duration of the sliding window over which the failed requests are counted. Must
+    /// be at least 1 second, default value is 10 seconds
+    window: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// how long the circuit stays open before probe requests are sent, default value is
+    /// 30 seconds
+    open_duration: Option<Duration>,
+    /// number of probe requests sent while the circuit is half open. The circuit closes
+    /// if they all succeed, and opens again at the first failure. Must be at least 1,
+    /// default value is 5
+    half_open_requests: Option<u32>,
+    /// names of the operations sent to the subgraph even when the circuit is open, and
+    /// not counted by the circuit breaker
+    bypass_operations: Option<Vec<String>>,
+    /// number of successful query responses kept to answer the same subgraph requests while
+    /// the circuit is open. The requests are matched with their headers, so that responses
+    /// are not shared between users. Not set by default, the requests fail while the circuit
+    /// is open
+    stale_responses: Option<NonZeroUsize>,
+}
+
+impl Merge for CircuitBreakerConfig {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => CircuitBreakerConfig {
+                error_percent: self.error_percent.or(fallback.error_percent),
+                slow_request: self.slow_request.or(fallback.slow_request),
+                min_requests: self.min_requests.or(fallback.min_requests),
+                window: self.window.or(fallback.window),
+                open_duration: self.open_duration.or(fallback.open_duration),
+                half_open_requests: self.half_open_requests.or(fallback.half_open_requests),
+                bypass_operations: self
+                    .bypass_operations
+                    .as_ref()
+                    .or(fallback.bypass_operations.as_ref())
+                    .cloned(),
+                stale_responses: self.stale_responses.or(fallback.stale_responses),
+            },
+        }
+    }
+}
+
 // this is a wrapper struct to add subgraph specific options over Shaping
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
@@ -169,7 +483,7 @@ impl Merge for SubgraphShaping {
     }
 }
 
-#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct RouterShaping {
     /// Enable global rate limiting
@@ -178,6 +492,13 @@ struct RouterShaping {
     #[schemars(with = "String", default)]
     /// Enable timeout for incoming requests
     timeout: Option<Duration>,
+    /// Mirror a sample of the queries to a shadow endpoint
+    mirror: Option<MirrorConf>,
+    /// Return the state of the global rate limit to the clients, so they can slow down before
+    /// being rejected: in the `x-ratelimit-limit`, `x-ratelimit-remaining` and `x-ratelimit-reset`
+    /// (in seconds) headers, and in the `rateLimit` entry of the extensions of the first response
+    #[serde(default)]
+    expose_rate_limit: Expose,
 }
 
 #[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
@@ -192,6 +513,8 @@ pub(crate) struct Config {
     all: Option<SubgraphShaping>,
     /// Applied on specific subgraphs
     subgraphs: HashMap<String, SubgraphShaping>,
+    /// Endpoint reading, tripping and resetting the circuit breakers of the subgraphs
+    experimental_circuit_breaker_endpoint: Option<CircuitBreakerEndpointConfig>,
     /// DEPRECATED, now always enabled: Enable variable deduplication optimization when sending requests to subgraphs (https://github.com/apollographql/router/issues/87)
     deduplicate_variables: Option<bool>,
 }
@@ -224,7 +547,10 @@ impl Merge for RateLimitConf {
 pub(crate) struct TrafficShaping {
     config: Config,
     rate_limit_router: Option<RateLimitLayer>,
+    mirror: Option<MirrorLayer>,
     rate_limit_subgraphs: Mutex<HashMap<String, RateLimitLayer>>,
+    circuit_breakers: CircuitBreakers,
+    _circuit_breaker_gauge: Option<ObservableGauge<u64>>,
 }
 
 #[async_trait::async_trait]
@@ -250,19 +576,171 @@ impl Plugin for TrafficShaping {
                     Ok(RateLimitLayer::new(
                         router_rate_limit_conf.capacity,
                         router_rate_limit_conf.interval,
-                    ))
+                    )
+                    .shared("rate_limit:router"))
                 }
             })
             .transpose()?;
 
+        for hedging in init
+            .config
+            .all
+            .iter()
+            .chain(init.config.subgraphs.values())
+            .filter_map(|shaping| shaping.shaping.experimental_hedging.as_ref())
+        {
+            if hedging
+                .percentile
+                .map_or(false, |percentile| !(0.0..=100.0).contains(&percentile))
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the hedging percentile must be between 0 and 100".to_string(),
+                }
+                .into());
+            }
+            if hedging.min_data_points.map_or(false, |min_data_points| {
+                !(1..=1000).contains(&min_data_points)
+            }) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the hedging min_data_points must be between 1 and 1000".to_string(),
+                }
+                .into());
+            }
+            if hedging.ttl.map_or(false, |ttl| {
+                !(Duration::from_secs(1)..=Duration::from_secs(60)).contains(&ttl)
+            }) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the hedging ttl must be between 1 and 60 seconds".to_string(),
+                }
+                .into());
+            }
+            if hedging.hedge_percent.map_or(false, |hedge_percent| {
+                !(0.0..=1000.0).contains(&hedge_percent)
+            }) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the hedging hedge_percent must be between 0 and 1000".to_string(),
+                }
+                .into());
+            }
+        }
+
+        let mut circuit_breaker_configured = false;
+        for circuit_breaker in init
+            .config
+            .all
+            .iter()
+            .chain(init.config.subgraphs.values())
+            .filter_map(|shaping| shaping.shaping.experimental_circuit_breaker.as_ref())
+        {
+            circuit_breaker_configured = true;
+            if circuit_breaker
+                .error_percent
+                .map_or(false, |error_percent| {
+                    !(0.0..=100.0).contains(&error_percent)
+                })
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the circuit breaker error_percent must be between 0 and 100"
+                        .to_string(),
+                }
+                .into());
+            }
+            if circuit_breaker
+                .window
+                .map_or(false, |window| window < Duration::from_secs(1))
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the circuit breaker window must be at least 1 second".to_string(),
+                }
+                .into());
+            }
+            if circuit_breaker.half_open_requests == Some(0) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the circuit breaker half_open_requests must be at least 1".to_string(),
+                }
+                .into());
+            }
+        }
+
+        let circuit_breakers = CircuitBreakers::default();
+        let circuit_breaker_gauge = circuit_breaker_configured.then(|| {
+            let circuit_breakers = circuit_breakers.clone();
+            metrics::meter_provider()
+                .meter(METER_NAME)
+                .u64_observable_gauge("apollo.router.operations.subgraph.circuit_breaker.state")
+                .with_description("Whether the circuit breaker of a subgraph is in a state")
+                .with_callback(move |gauge| {
+                    let circuit_breakers = circuit_breakers.lock().unwrap();
+                    for (name, circuit_breaker) in circuit_breakers.iter() {
+                        let current = circuit_breaker.state.state();
+                        for state in CircuitState::ALL {
+                            gauge.observe(
+                                (state == current) as u64,
+                                &[
+                                    KeyValue::new("subgraph.name", name.clone()),
+                                    KeyValue::new("state", state.as_str()),
+                                ],
+                            );
+                        }
+                    }
+                })
+                .init()
+        });
+
+        let mirror = init
+            .config
+            .router
+            .as_ref()
+            .and_then(|r| r.mirror.clone())
+            .map(|mirror_conf| {
+                MirrorLayer::new(mirror_conf).map_err(|error| {
+                    ConfigurationError::InvalidConfiguration {
+                        message: "bad configuration for traffic_shaping plugin",
+                        error: error.to_string(),
+                    }
+                })
+            })
+            .transpose()?;
+
         {
             Ok(Self {
                 config: init.config,
                 rate_limit_router,
+                mirror,
                 rate_limit_subgraphs: Mutex::new(HashMap::new()),
+                circuit_breakers,
+                _circuit_breaker_gauge: circuit_breaker_gauge,
             })
         }
     }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if let Some(endpoint_config) = &self.config.experimental_circuit_breaker_endpoint {
+            let endpoint = Endpoint::from_router_service(
+                endpoint_config.path.clone(),
+                CircuitBreakerService::new(
+                    self.circuit_breakers.clone(),
+                    endpoint_config.shared_key.clone(),
+                )
+                .boxed(),
+            );
+            tracing::info!(
+                "Circuit breaker endpoint listening on: {}{}",
+                endpoint_config.listen,
+                endpoint_config.path
+            );
+            map.insert(endpoint_config.listen.clone(), endpoint);
+        }
+        map
+    }
 }
 
 pub(crate) type TrafficShapingSubgraphFuture<S> = Either<
@@ -302,13 +780,20 @@ impl TrafficShaping {
             + 'static,
         <S as Service<supergraph::Request>>::Future: std::marker::Send,
     {
+        let exposed_rate_limit = self.rate_limit_router.clone().zip(
+            self.config
+                .router
+                .as_ref()
+                .map(|router| router.expose_rate_limit.clone()),
+        );
         ServiceBuilder::new()
             .map_future_with_request_data(
                 |req: &supergraph::Request| req.context.clone(),
                 move |ctx, future| {
-                    async {
+                    let exposed_rate_limit = exposed_rate_limit.clone();
+                    async move {
                         let response: Result<supergraph::Response, BoxError> = future.await;
-                        match response {
+                        let response = match response {
                             Err(error) if error.is::<Elapsed>() => {
                                 supergraph::Response::error_builder()
                                     .status_code(StatusCode::GATEWAY_TIMEOUT)
@@ -324,6 +809,12 @@ impl TrafficShaping {
                                     .build()
                             }
                             _ => response,
+                        };
+                        match (response, exposed_rate_limit) {
+                            (Ok(response), Some((rate_limit, expose))) => {
+                                Ok(expose_rate_limit(response, rate_limit.state(), &expose))
+                            }
+                            (response, _) => response,
                         }
                     }
                     .boxed()
@@ -337,6 +828,7 @@ impl TrafficShaping {
                     .unwrap_or(DEFAULT_TIMEOUT),
             ))
             .option_layer(self.rate_limit_router.clone())
+            .option_layer(self.mirror.clone())
             .service(service)
     }
 
@@ -378,10 +870,18 @@ impl TrafficShaping {
                         .entry(name.to_string())
                         .or_insert_with(|| {
                             RateLimitLayer::new(rate_limit_conf.capacity, rate_limit_conf.interval)
+                                .shared(&format!("rate_limit:subgraph:{name}"))
                         })
                         .clone()
                 });
 
+            let timeouts = config
+                .shaping
+                .timeout
+                .as_ref()
+                .map(SubgraphTimeoutConf::timeouts)
+                .unwrap_or_default();
+
             let retry = config.shaping.experimental_retry.as_ref().map(|config| {
                 let retry_policy = RetryPolicy::new(
                     config.ttl,
@@ -393,6 +893,43 @@ impl TrafficShaping {
                 tower::retry::RetryLayer::new(retry_policy)
             });
 
+            let hedging = config.shaping.experimental_hedging.as_ref().map(|config| {
+                HedgingLayer::new(
+                    config.percentile,
+                    config.min_data_points,
+                    config.ttl,
+                    config.min_per_sec,
+                    config.hedge_percent,
+                    name.to_string(),
+                )
+            });
+
+            let circuit_breaker =
+                config
+                    .shaping
+                    .experimental_circuit_breaker
+                    .as_ref()
+                    .map(|config| {
+                        self.circuit_breakers
+                            .lock()
+                            .unwrap()
+                            .entry(name.to_string())
+                            .or_insert_with(|| {
+                                CircuitBreakerLayer::new(
+                                    config.error_percent,
+                                    config.slow_request,
+                                    config.min_requests,
+                                    config.window,
+                                    config.open_duration,
+                                    config.half_open_requests,
+                                    config.bypass_operations.clone(),
+                                    config.stale_responses,
+                                    name.to_string(),
+                                )
+                            })
+                            .clone()
+                    });
+
             Either::A(ServiceBuilder::new()
 
                 .option_layer(config.shaping.deduplicate_query.unwrap_or_default().then(
@@ -418,20 +955,29 @@ impl TrafficShaping {
                                             .context(ctx)
                                             .build()
                                     }
+                                    Err(error) if error.is::<CircuitOpen>() => {
+                                        subgraph::Response::error_builder()
+                                            .status_code(StatusCode::SERVICE_UNAVAILABLE)
+                                            .error::<graphql::Error>(CircuitOpen::new().into())
+                                            .context(ctx)
+                                            .build()
+                                    }
                                     _ => response,
                                 }
                             }.boxed()
                         },
                     )
-                    .layer(TimeoutLayer::new(
-                        config.shaping
-                        .timeout
-                        .unwrap_or(DEFAULT_TIMEOUT),
-                    ))
+                    // the timeouts are counted as failures by the circuit breaker
+                    .option_layer(circuit_breaker)
+                    // the total timeout of each request is selected by its operation kind
+                    .layer(TimeoutLayer::new(DEFAULT_TIMEOUT))
                     .option_layer(retry)
+                    .option_layer(hedging)
                     .option_layer(rate_limit)
                 .service(service)
                 .map_request(move |mut req: SubgraphRequest| {
+                    let timeouts = timeouts.for_operation(req.operation_kind);
+                    req.subgraph_request.extensions_mut().insert(timeouts);
                     if let Some(compression) = config.shaping.compression {
                         let compression_header_val = HeaderValue::from_str(&compression.to_string()).expect("compression is manually implemented and already have the right values; qed");
                         req.subgraph_request.headers_mut().insert(CONTENT_ENCODING, compression_header_val);
@@ -452,6 +998,58 @@ impl TrafficShaping {
         .and_then(|config| config.shaping.experimental_http2)
         .unwrap_or(Http2Config::Enable)
     }
+
+    pub(crate) fn subgraph_connect_timeout(&self, service_name: &str) -> Option<Duration> {
+        Self::merge_config(
+            self.config.all.as_ref(),
+            self.config.subgraphs.get(service_name),
+        )
+        .and_then(|config| config.shaping.timeout)
+        .and_then(|timeout| timeout.timeouts().connect)
+    }
+
+    pub(crate) fn subgraph_http2_keep_alive(&self, service_name: &str) -> Option<Http2KeepAlive> {
+        Self::merge_config(
+            self.config.all.as_ref(),
+            self.config.subgraphs.get(service_name),
+        )
+        .and_then(|config| config.shaping.experimental_http2_keep_alive)
+    }
+}
+
+/// Returns the state of the global rate limit in the headers and extensions of the response
+fn expose_rate_limit(
+    mut response: supergraph::Response,
+    state: RateLimitState,
+    expose: &Expose,
+) -> supergraph::Response {
+    let reset = state.reset.as_millis().div_ceil(1000) as u64;
+    if expose.headers {
+        let headers = response.response.headers_mut();
+        headers.insert(X_RATELIMIT_LIMIT.clone(), HeaderValue::from(state.limit));
+        headers.insert(
+            X_RATELIMIT_REMAINING.clone(),
+            HeaderValue::from(state.remaining),
+        );
+        headers.insert(X_RATELIMIT_RESET.clone(), HeaderValue::from(reset));
+    }
+    if expose.extensions {
+        let mut first = true;
+        response = response.map_stream(move |mut response| {
+            if std::mem::take(&mut first) {
+                response.extensions.insert(
+                    "rateLimit",
+                    serde_json_bytes::json!({
+                        "limit": state.limit,
+                        "remaining": state.remaining,
+                        "reset": reset,
+                    }),
+                );
+            }
+            response
+        });
+    }
+    response
 }
 
 register_plugin!("apollo", "traffic_shaping", TrafficShaping);
@@ -773,6 +1371,110 @@ mod test {
         assert!(shaping_config.enable_subgraph_http2("this_doesnt_exist") == Http2Config::Disable);
     }
 
+    #[tokio::test]
+    async fn test_subgraph_http2_keep_alive() {
+        let config = serde_yaml::from_str::<Config>(
+            r#"
+        all:
+          experimental_http2_keep_alive:
+            interval: 10s
+        subgraphs:
+          products:
+            experimental_http2_keep_alive:
+              interval: 5s
+              timeout: 2s
+              while_idle: false
+        "#,
+        )
+        .unwrap();
+
+        let shaping_config = TrafficShaping::new(PluginInit::fake_builder().config(config).build())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            shaping_config.subgraph_http2_keep_alive("products"),
+            Some(Http2KeepAlive {
+                interval: Duration::from_secs(5),
+                timeout: Some(Duration::from_secs(2)),
+                while_idle: Some(false),
+            })
+        );
+        assert_eq!(
+            shaping_config.subgraph_http2_keep_alive("reviews"),
+            Some(Http2KeepAlive {
+                interval: Duration::from_secs(10),
+                timeout: None,
+                while_idle: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_subgraph_timeouts() {
+        let config = serde_yaml::from_str::<Config>(
+            r#"
+        all:
+          timeout:
+            connect: 1s
+            first_byte: 5s
+            mutation:
+              total: 60s
+        subgraphs:
+          products:
+            timeout: 10s
+          reviews:
+            timeout:
+              first_byte: 2s
+              mutation:
+                first_byte: 20s
+        "#,
+        )
+        .unwrap();
+        let timeouts = |name: &str| {
+            TrafficShaping::merge_config(config.all.as_ref(), config.subgraphs.get(name))
+                .unwrap()
+                .shaping
+                .timeout
+                .unwrap()
+                .timeouts()
+        };
+        let request_timeouts = |first_byte: u64, total: u64| RequestTimeouts {
+            first_byte: Some(Duration::from_secs(first_byte)),
+            total: Duration::from_secs(total),
+        };
+
+        let accounts = timeouts("accounts");
+        assert_eq!(accounts.connect, Some(Duration::from_secs(1)));
+        assert_eq!(
+            accounts.for_operation(OperationKind::Query),
+            request_timeouts(5, 30)
+        );
+        assert_eq!(
+            accounts.for_operation(OperationKind::Mutation),
+            request_timeouts(5, 60)
+        );
+
+        // the timeouts of a subgraph take precedence over the ones of all the subgraphs, even
+        // when they are set by operation kind
+        let products = timeouts("products");
+        assert_eq!(products.connect, Some(Duration::from_secs(1)));
+        assert_eq!(
+            products.for_operation(OperationKind::Mutation),
+            request_timeouts(5, 10)
+        );
+
+        let reviews = timeouts("reviews");
+        assert_eq!(
+            reviews.for_operation(OperationKind::Subscription),
+            request_timeouts(2, 30)
+        );
+        assert_eq!(
+            reviews.for_operation(OperationKind::Mutation),
+            request_timeouts(20, 60)
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn it_rate_limit_subgraph_requests() {
         let config = serde_yaml::from_str::<serde_json::Value>(
@@ -927,4 +1629,84 @@ mod test {
             .errors
             .is_empty());
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_exposes_the_router_rate_limit() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        router:
+            global_rate_limit:
+                capacity: 2
+                interval: 10s
+            expose_rate_limit:
+                headers: true
+                extensions: true
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_clone().returning(|| {
+            let mut mock_service = MockSupergraphService::new();
+            mock_service.expect_call().times(0..2).returning(move |_| {
+                Ok(SupergraphResponse::fake_builder()
+                    .data(json!({ "test": 1234_u32 }))
+                    .build()
+                    .unwrap())
+            });
+            mock_service
+        });
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        let mut response = shaping
+            .supergraph_service_internal(mock_service.clone())
+            .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        let headers = response.response.headers();
+        assert_eq!(headers["x-ratelimit-limit"], "2");
+        assert_eq!(headers["x-ratelimit-remaining"], "1");
+        assert_eq!(headers["x-ratelimit-reset"], "10");
+        assert_eq!(
+            response
+                .next_response()
+                .await
+                .unwrap()
+                .extensions
+                .get("rateLimit"),
+            Some(&json!({ "limit": 2, "remaining": 1, "reset": 10 }))
+        );
+
+        shaping
+            .supergraph_service_internal(mock_service.clone())
+            .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        let response = shaping
+            .supergraph_service_internal(mock_service.clone())
+            .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.response.headers()["x-ratelimit-remaining"], "0");
+    }
+
+    #[tokio::test]
+    async fn it_rejects_out_of_range_hedging_settings() {
+        for hedging in [
+            serde_json::json!({ "percentile": 101 }),
+            serde_json::json!({ "min_data_points": 0 }),
+            serde_json::json!({ "ttl": "2m" }),
+            serde_json::json!({ "hedge_percent": -1 }),
+        ] {
+            let config = serde_json::json!({ "all": { "experimental_hedging": hedging } });
+            assert!(crate::plugin::plugins()
+                .find(|factory| factory.name == APOLLO_TRAFFIC_SHAPING)
+                .expect("Plugin not found")
+                .create_instance_without_schema(&config)
+                .await
+                .is_err());
+        }
+    }
 }
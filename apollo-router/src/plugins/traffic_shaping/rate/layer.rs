@@ -1,6 +1,7 @@
 use std::num::NonZeroU64;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
@@ -10,6 +11,9 @@ use tower::Layer;
 
 use super::Rate;
 use super::RateLimit;
+use crate::configuration::shared_state;
+use crate::configuration::shared_state::WindowCounter;
+
 /// Enforces a rate limit on the number of requests the underlying
 /// service can handle over a period of time.
 #[derive(Debug, Clone)]
@@ -18,6 +22,7 @@ pub(crate) struct RateLimitLayer {
     window_start: Arc<AtomicU64>,
     previous_nb_requests: Arc<AtomicUsize>,
     current_nb_requests: Arc<AtomicUsize>,
+    shared: Option<Arc<WindowCounter>>,
 }
 
 impl RateLimitLayer {
@@ -34,8 +39,62 @@ impl RateLimitLayer {
             )),
             previous_nb_requests: Arc::default(),
             current_nb_requests: Arc::new(AtomicUsize::new(1)),
+            shared: None,
         }
     }
+
+    /// Counts the requests of all the router replicas under the key, if a shared state store is
+    /// configured
+    pub(crate) fn shared(mut self, key: &str) -> Self {
+        self.shared = shared_state::window_counter(key, self.rate.num(), self.rate.per());
+        self
+    }
+
+    /// Returns the state of the current window
+    pub(crate) fn state(&self) -> RateLimitState {
+        let limit = self.rate.num();
+        if let Some((remaining, reset)) = self.shared.as_ref().and_then(|shared| shared.remaining())
+        {
+            return RateLimitState {
+                limit,
+                remaining,
+                reset,
+            };
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time must be after EPOCH")
+            .as_millis() as u64;
+        let per = self.rate.per().as_millis() as u64;
+        let elapsed = now.saturating_sub(self.window_start.load(Ordering::SeqCst));
+        if elapsed > per {
+            // The next request starts a new window
+            return RateLimitState {
+                limit,
+                remaining: limit,
+                reset: self.rate.per(),
+            };
+        }
+        // The count of a window starts at 1
+        let used = (self.current_nb_requests.load(Ordering::SeqCst) as u64).saturating_sub(1);
+        RateLimitState {
+            limit,
+            remaining: limit.saturating_sub(used),
+            reset: Duration::from_millis(per - elapsed),
+        }
+    }
+}
+
+/// State of the current window of a rate limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RateLimitState {
+    /// Number of requests allowed per window
+    pub(crate) limit: u64,
+    /// Number of requests remaining in the window
+    pub(crate) remaining: u64,
+    /// Time until the end of the window
+    pub(crate) reset: Duration,
 }
 
 impl<S> Layer<S> for RateLimitLayer {
@@ -48,6 +107,7 @@ impl<S> Layer<S> for RateLimitLayer {
             window_start: self.window_start.clone(),
             previous_nb_requests: self.previous_nb_requests.clone(),
             current_nb_requests: self.current_nb_requests.clone(),
+            shared: self.shared.clone(),
         }
     }
 }
@@ -9,5 +9,6 @@ pub(crate) mod service;
 
 pub(crate) use self::error::RateLimited;
 pub(crate) use self::layer::RateLimitLayer;
+pub(crate) use self::layer::RateLimitState;
 pub(crate) use self::rate::Rate;
 pub(crate) use self::service::RateLimit;
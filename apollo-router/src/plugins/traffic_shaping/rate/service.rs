@@ -12,6 +12,7 @@ use tower::Service;
 
 use super::future::ResponseFuture;
 use super::Rate;
+use crate::configuration::shared_state::WindowCounter;
 use crate::plugins::traffic_shaping::rate::error::RateLimited;
 
 #[derive(Debug, Clone)]
@@ -24,6 +25,9 @@ pub(crate) struct RateLimit<T> {
     pub(crate) window_start: Arc<AtomicU64>,
     pub(crate) previous_nb_requests: Arc<AtomicUsize>,
     pub(crate) current_nb_requests: Arc<AtomicUsize>,
+    /// Counter of the requests of all the router replicas, falling back to the local counts
+    /// while the shared state store is unavailable
+    pub(crate) shared: Option<Arc<WindowCounter>>,
 }
 
 impl<S, Request> Service<Request> for RateLimit<S>
@@ -36,6 +40,14 @@ where
     type Future = ResponseFuture<S::Future>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(acquired) = self.shared.as_ref().and_then(|shared| shared.try_acquire()) {
+            if !acquired {
+                tracing::trace!("shared rate limit exceeded; sleeping.");
+                return Poll::Ready(Err(RateLimited::new().into()));
+            }
+            return Poll::Ready(ready!(self.inner.poll_ready(cx)).map_err(Into::into));
+        }
+
         let time_unit = self.rate.per().as_millis() as u64;
 
         let updated =
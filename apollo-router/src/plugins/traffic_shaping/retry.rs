@@ -5,6 +5,8 @@ use std::time::Duration;
 use tower::retry::budget::Budget;
 use tower::retry::Policy;
 
+use crate::plugins::fetch_details::FetchStats;
+use crate::plugins::traffic_shaping::timeout::RequestTimeouts;
 use crate::query_planner::OperationKind;
 use crate::services::subgraph;
 
@@ -66,6 +68,9 @@ impl<Res, E> Policy<subgraph::Request, Res, E> for RetryPolicy {
                     monotonic_counter.apollo_router_http_request_retry_total = 1u64,
                     subgraph = %self.subgraph_name,
                 );
+                if let Some(stats) = req.subgraph_request.extensions().get::<Arc<FetchStats>>() {
+                    stats.record_retry();
+                }
 
                 Some(future::ready(self.clone()))
             }
@@ -73,6 +78,23 @@ impl<Res, E> Policy<subgraph::Request, Res, E> for RetryPolicy {
     }
 
     fn clone_request(&self, req: &subgraph::Request) -> Option<subgraph::Request> {
-        Some(req.clone())
+        Some(clone_request(req))
+    }
+}
+
+/// Clones a subgraph request for another attempt. The clone drops the HTTP extensions, so the
+/// fetch details and the timeouts of the request are copied over
+pub(crate) fn clone_request(req: &subgraph::Request) -> subgraph::Request {
+    let mut cloned = req.clone();
+    let extensions = req.subgraph_request.extensions();
+    if let Some(stats) = extensions.get::<Arc<FetchStats>>() {
+        cloned
+            .subgraph_request
+            .extensions_mut()
+            .insert(stats.clone());
+    }
+    if let Some(timeouts) = extensions.get::<RequestTimeouts>() {
+        cloned.subgraph_request.extensions_mut().insert(*timeouts);
     }
+    cloned
 }
@@ -20,6 +20,38 @@ use tower::ServiceExt;
 use self::future::ResponseFuture;
 pub(crate) use self::layer::TimeoutLayer;
 pub(crate) use crate::plugins::traffic_shaping::timeout::error::Elapsed;
+use crate::services::subgraph;
+use crate::services::supergraph;
+
+/// Timeouts of a subgraph request, selected by its operation kind, in the extensions of its HTTP
+/// request
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RequestTimeouts {
+    /// Time to receive the response headers
+    pub(crate) first_byte: Option<Duration>,
+    /// Time of the whole request, retries included
+    pub(crate) total: Duration,
+}
+
+/// Requests whose timeout can differ from the timeout of the layer
+pub(crate) trait RequestTimeout {
+    fn timeout(&self) -> Option<Duration>;
+}
+
+impl RequestTimeout for supergraph::Request {
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl RequestTimeout for subgraph::Request {
+    fn timeout(&self) -> Option<Duration> {
+        self.subgraph_request
+            .extensions()
+            .get::<RequestTimeouts>()
+            .map(|timeouts| timeouts.total)
+    }
+}
 
 /// Applies a timeout to requests.
 #[derive(Debug, Clone)]
@@ -41,6 +73,7 @@ impl<S, Request> Service<Request> for Timeout<S>
 where
     S: Service<Request> + Clone,
     S::Error: Into<tower::BoxError>,
+    Request: RequestTimeout,
 {
     type Response = S::Response;
     type Error = tower::BoxError;
@@ -52,9 +85,10 @@ where
 
     fn call(&mut self, request: Request) -> Self::Future {
         let service = self.inner.clone();
+        let timeout = request.timeout().unwrap_or(self.timeout);
 
         let response = service.oneshot(request);
 
-        ResponseFuture::new(response, Box::pin(tokio::time::sleep(self.timeout)))
+        ResponseFuture::new(response, Box::pin(tokio::time::sleep(timeout)))
     }
 }
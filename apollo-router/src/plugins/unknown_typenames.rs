@@ -0,0 +1,447 @@
+//! Handling of `__typename` values unknown to the supergraph.
+//!
+//! During rolling deployments, a subgraph can return objects of a type that was added to its
+//! schema but is not in the supergraph yet. By default the router nullifies those objects, which
+//! often propagates up to the whole field. This plugin can instead drop those objects from lists,
+//! or replace their `__typename` with a fallback type of the supergraph. Occurrences are counted
+//! per subgraph and type either way.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use apollo_compiler::ast;
+use apollo_compiler::executable;
+use apollo_compiler::schema::ExtendedType;
+use apollo_compiler::validation::Valid;
+use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Schema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::fetch::SubgraphSchemas;
+use crate::register_plugin;
+use crate::services::subgraph;
+
+register_plugin!("apollo", "unknown_typenames", UnknownTypenames);
+
+const TYPENAME: &str = "__typename";
+const ENTITIES: &str = "_entities";
+
+/// Handling of `__typename` values returned by subgraphs for interfaces and unions that are not
+/// types of the supergraph
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct UnknownTypenamesConfig {
+    /// What to do with objects of an unknown type
+    policy: UnknownTypenamePolicy,
+    /// Type of the supergraph replacing unknown types, per interface or union, used by the
+    /// `fallback` policy
+    fallback_types: HashMap<String, String>,
+}
+
+/// What to do with objects of a type unknown to the supergraph
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum UnknownTypenamePolicy {
+    /// Nullify the object, like any invalid value
+    #[default]
+    Error,
+    /// Remove the object from its list. Objects outside of lists are nullified
+    Drop,
+    /// Replace the `__typename` with the fallback type configured for the interface or union.
+    /// Objects of interfaces or unions without fallback type are nullified
+    Fallback,
+}
+
+struct Rewriter<'a> {
+    config: &'a UnknownTypenamesConfig,
+    subgraph_schema: &'a Schema,
+    supergraph_schema: &'a Schema,
+    document: &'a ExecutableDocument,
+    /// Unknown types that were found
+    unknown: Vec<String>,
+}
+
+impl<'a> Rewriter<'a> {
+    /// Returns the `__typename` of the object if it is an unknown type returned for an interface
+    /// or union.
+    fn unknown_typename<'v>(&self, ty: &str, value: &'v Value) -> Option<&'v str> {
+        if !matches!(
+            self.subgraph_schema.types.get(ty),
+            Some(ExtendedType::Interface(_) | ExtendedType::Union(_))
+        ) {
+            return None;
+        }
+        let typename = value.as_object()?.get(TYPENAME)?.as_str()?;
+        (!self.supergraph_schema.types.contains_key(typename)).then_some(typename)
+    }
+
+    fn value(
+        &mut self,
+        ty: &ast::Type,
+        selection_set: &executable::SelectionSet,
+        value: &mut Value,
+        can_drop: bool,
+    ) {
+        match value {
+            Value::Array(items) => {
+                let item_type = match ty {
+                    ast::Type::List(inner) | ast::Type::NonNullList(inner) => inner.as_ref(),
+                    ty => ty,
+                };
+                if can_drop && self.config.policy == UnknownTypenamePolicy::Drop {
+                    let name = item_type.inner_named_type().as_str();
+                    items.retain(|item| match self.unknown_typename(name, item) {
+                        Some(typename) => {
+                            self.unknown.push(typename.to_string());
+                            false
+                        }
+                        None => true,
+                    });
+                }
+                for item in items.iter_mut() {
+                    self.value(item_type, selection_set, item, can_drop);
+                }
+            }
+            Value::Object(_) => {
+                let name = ty.inner_named_type().as_str();
+                if let Some(typename) = self.unknown_typename(name, value) {
+                    self.unknown.push(typename.to_string());
+                    if self.config.policy == UnknownTypenamePolicy::Fallback {
+                        if let Some(fallback) = self.config.fallback_types.get(name) {
+                            if let Some(object) = value.as_object_mut() {
+                                object.insert(TYPENAME, fallback.as_str().into());
+                            }
+                        }
+                    }
+                }
+                if let Value::Object(object) = value {
+                    let concrete_type = object
+                        .get(TYPENAME)
+                        .and_then(|typename| typename.as_str())
+                        .unwrap_or(name)
+                        .to_string();
+                    self.selection_set(&concrete_type, selection_set, object);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn selection_set(
+        &mut self,
+        concrete_type: &str,
+        selection_set: &executable::SelectionSet,
+        object: &mut Map<ByteString, Value>,
+    ) {
+        for selection in &selection_set.selections {
+            match selection {
+                executable::Selection::Field(field) => {
+                    if field.selection_set.selections.is_empty() {
+                        continue;
+                    }
+                    if let Some(value) = object.get_mut(field.response_key().as_str()) {
+                        // entities are matched to their representations by index
+                        let can_drop = field.name.as_str() != ENTITIES;
+                        self.value(field.ty(), &field.selection_set, value, can_drop);
+                    }
+                }
+                executable::Selection::InlineFragment(fragment) => {
+                    let applies = match &fragment.type_condition {
+                        None => true,
+                        Some(condition) => self.applies(condition, concrete_type),
+                    };
+                    if applies {
+                        self.selection_set(concrete_type, &fragment.selection_set, object);
+                    }
+                }
+                executable::Selection::FragmentSpread(spread) => {
+                    let Some(fragment) = spread.fragment_def(self.document) else {
+                        continue;
+                    };
+                    if self.applies(fragment.type_condition(), concrete_type) {
+                        self.selection_set(concrete_type, &fragment.selection_set, object);
+                    }
+                }
+            }
+        }
+    }
+
+    fn applies(&self, type_condition: &str, concrete_type: &str) -> bool {
+        concrete_type == type_condition
+            || self
+                .subgraph_schema
+                .is_subtype(type_condition, concrete_type)
+            || self
+                .supergraph_schema
+                .is_subtype(type_condition, concrete_type)
+    }
+}
+
+/// Applies the policy to the objects of unknown types in the data of a subgraph response, and
+/// returns the unknown types that were found.
+fn rewrite(
+    config: &UnknownTypenamesConfig,
+    subgraph_schema: &Schema,
+    supergraph_schema: &Schema,
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    data: &mut Value,
+) -> Vec<String> {
+    let Ok(operation) = document.operations.get(operation_name) else {
+        return Vec::new();
+    };
+    let Value::Object(data) = data else {
+        return Vec::new();
+    };
+    let mut rewriter = Rewriter {
+        config,
+        subgraph_schema,
+        supergraph_schema,
+        document,
+        unknown: Vec::new(),
+    };
+    rewriter.selection_set(
+        operation.object_type().as_str(),
+        &operation.selection_set,
+        data,
+    );
+    rewriter.unknown
+}
+
+struct UnknownTypenames {
+    config: Arc<UnknownTypenamesConfig>,
+    supergraph_schema: Arc<Valid<Schema>>,
+    subgraph_schemas: Arc<SubgraphSchemas>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for UnknownTypenames {
+    type Config = UnknownTypenamesConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let schema = &init.supergraph_schema;
+        for (abstract_type, fallback) in &init.config.fallback_types {
+            if !matches!(schema.types.get(fallback), Some(ExtendedType::Object(_)))
+                || !schema.is_subtype(abstract_type, fallback)
+            {
+                return Err(format!(
+                    "fallback type '{fallback}' must be an object type of the supergraph implementing or member of '{abstract_type}'"
+                )
+                .into());
+            }
+        }
+
+        Ok(Self {
+            config: Arc::new(init.config),
+            supergraph_schema: init.supergraph_schema.clone(),
+            subgraph_schemas: init.subgraph_schemas.clone(),
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let Some(subgraph_schema) = self.subgraph_schemas.get(name).cloned() else {
+            return service;
+        };
+
+        let config = self.config.clone();
+        let supergraph_schema = self.supergraph_schema.clone();
+        let subgraph_name = name.to_string();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |request: &subgraph::Request| {
+                    (
+                        request.executable_document.clone(),
+                        request.subgraph_request.body().operation_name.clone(),
+                    )
+                },
+                move |(document, operation_name): (
+                    Option<Arc<Valid<ExecutableDocument>>>,
+                    Option<String>,
+                ),
+                      future| {
+                    let config = config.clone();
+                    let subgraph_schema = subgraph_schema.clone();
+                    let supergraph_schema = supergraph_schema.clone();
+                    let subgraph_name = subgraph_name.clone();
+                    async move {
+                        let mut response: subgraph::Response = future.await?;
+                        let Some(document) = document else {
+                            return Ok(response);
+                        };
+                        if let Some(data) = &mut response.response.body_mut().data {
+                            let unknown = rewrite(
+                                &config,
+                                &subgraph_schema,
+                                &supergraph_schema,
+                                &document,
+                                operation_name.as_deref(),
+                                data,
+                            );
+                            for typename in unknown {
+                                u64_counter!(
+                                    "apollo.router.operations.subgraph.unknown_typename",
+                                    "Number of objects returned by subgraphs with a type unknown to the supergraph",
+                                    1,
+                                    "subgraph.name" = subgraph_name.clone(),
+                                    "graphql.type.name" = typename
+                                );
+                            }
+                        }
+                        Ok(response)
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    const SUBGRAPH_SCHEMA: &str = r#"
+        type Query {
+            products: [Product]
+            featured: Product
+        }
+
+        interface Product {
+            id: ID!
+        }
+
+        type Book implements Product {
+            id: ID!
+            title: String
+        }
+
+        type Movie implements Product {
+            id: ID!
+            duration: Int
+        }
+
+        type Game implements Product {
+            id: ID!
+        }
+    "#;
+
+    const SUPERGRAPH_SCHEMA: &str = r#"
+        type Query {
+            products: [Product]
+            featured: Product
+        }
+
+        interface Product {
+            id: ID!
+        }
+
+        type Book implements Product {
+            id: ID!
+            title: String
+        }
+
+        type Movie implements Product {
+            id: ID!
+            duration: Int
+        }
+
+        type UnknownProduct implements Product {
+            id: ID!
+        }
+    "#;
+
+    const QUERY: &str =
+        "{ products { __typename id ... on Book { title } } featured { __typename id } }";
+
+    fn data() -> Value {
+        json!({
+            "products": [
+                { "__typename": "Book", "id": "1", "title": "Dune" },
+                { "__typename": "Game", "id": "2" },
+                { "__typename": "Movie", "id": "3" },
+            ],
+            "featured": { "__typename": "Game", "id": "2" },
+        })
+    }
+
+    fn apply(config: serde_json::Value, data: &mut Value) -> Vec<String> {
+        let config: UnknownTypenamesConfig = serde_json::from_value(config).unwrap();
+        let subgraph_schema =
+            Schema::parse_and_validate(SUBGRAPH_SCHEMA, "subgraph.graphql").unwrap();
+        let supergraph_schema =
+            Schema::parse_and_validate(SUPERGRAPH_SCHEMA, "supergraph.graphql").unwrap();
+        let document =
+            ExecutableDocument::parse_and_validate(&subgraph_schema, QUERY, "query.graphql")
+                .unwrap();
+        rewrite(
+            &config,
+            &subgraph_schema,
+            &supergraph_schema,
+            &document,
+            None,
+            data,
+        )
+    }
+
+    #[test]
+    fn error_policy_keeps_data() {
+        let mut data = data();
+        let unknown = apply(serde_json::json!({}), &mut data);
+        assert_eq!(unknown, vec!["Game", "Game"]);
+        assert_eq!(data, self::data());
+    }
+
+    #[test]
+    fn drop_policy_removes_list_elements() {
+        let mut data = data();
+        let unknown = apply(serde_json::json!({ "policy": "drop" }), &mut data);
+        assert_eq!(unknown, vec!["Game", "Game"]);
+        assert_eq!(
+            data,
+            json!({
+                "products": [
+                    { "__typename": "Book", "id": "1", "title": "Dune" },
+                    { "__typename": "Movie", "id": "3" },
+                ],
+                "featured": { "__typename": "Game", "id": "2" },
+            })
+        );
+    }
+
+    #[test]
+    fn fallback_policy_replaces_typename() {
+        let mut data = data();
+        let unknown = apply(
+            serde_json::json!({
+                "policy": "fallback",
+                "fallback_types": { "Product": "UnknownProduct" }
+            }),
+            &mut data,
+        );
+        assert_eq!(unknown, vec!["Game", "Game"]);
+        assert_eq!(
+            data,
+            json!({
+                "products": [
+                    { "__typename": "Book", "id": "1", "title": "Dune" },
+                    { "__typename": "UnknownProduct", "id": "2" },
+                    { "__typename": "Movie", "id": "3" },
+                ],
+                "featured": { "__typename": "UnknownProduct", "id": "2" },
+            })
+        );
+    }
+}
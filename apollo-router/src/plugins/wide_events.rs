@@ -0,0 +1,364 @@
+//! One structured event per request, also known as a canonical log line.
+//!
+//! Instead of correlating many log lines and spans, the event gathers what happened during a
+//! request in a single place: the operation, the client, the fetches to subgraphs with their
+//! entity cache hits, the cost and the error codes. It is emitted when the first response is
+//! sent to the client, and can be sampled differently for successful and failed requests.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::context::OPERATION_KIND;
+use crate::context::OPERATION_NAME;
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::cache::entity::CacheSubgraph;
+use crate::plugins::cache::metrics::CacheMetricContextKey;
+use crate::plugins::demand_control::CostContext;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::plugins::telemetry::CLIENT_VERSION;
+use crate::query_planner::APOLLO_OPERATION_ID;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!("apollo", "wide_events", WideEvents);
+
+/// Target of the wide events, to route them with the logging configuration
+const WIDE_EVENT_TARGET: &str = "apollo_router::wide_event";
+
+/// Per request wide events configuration
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct WideEventsConfig {
+    /// Emit one event per request
+    enabled: bool,
+    /// Groups of fields included in the events, in addition to the duration and status
+    fields: Vec<WideEventField>,
+    /// Ratio of the requests emitting an event, by outcome
+    sampling: Sampling,
+}
+
+impl Default for WideEventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fields: vec![
+                WideEventField::Operation,
+                WideEventField::Client,
+                WideEventField::Subgraphs,
+                WideEventField::Cache,
+                WideEventField::Cost,
+                WideEventField::Errors,
+            ],
+            sampling: Sampling::default(),
+        }
+    }
+}
+
+/// A group of fields of the wide events
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WideEventField {
+    /// The operation name, kind and identifier
+    Operation,
+    /// The client name and version
+    Client,
+    /// The duration and status of each subgraph fetch
+    Subgraphs,
+    /// The entity cache hits and misses of each subgraph fetch
+    Cache,
+    /// The estimated and actual cost of the operation
+    Cost,
+    /// The error codes of the response
+    Errors,
+}
+
+/// Ratio of the requests emitting an event, by outcome
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Sampling {
+    /// Ratio of the successful requests emitting an event, between 0 and 1
+    success: f64,
+    /// Ratio of the requests with errors or a non successful HTTP status emitting an event,
+    /// between 0 and 1
+    error: f64,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            success: 1.0,
+            error: 1.0,
+        }
+    }
+}
+
+/// A fetch to a subgraph during the request
+#[derive(Clone, Debug, Serialize)]
+struct SubgraphFetch {
+    name: String,
+    duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_hits: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_misses: Option<usize>,
+}
+
+/// What the wide event of a request gathers before it is emitted
+#[derive(Clone)]
+struct RequestEvent {
+    start: Instant,
+    fetches: Arc<Mutex<Vec<SubgraphFetch>>>,
+}
+
+struct WideEvents {
+    config: WideEventsConfig,
+}
+
+impl WideEvents {
+    fn includes(&self, field: WideEventField) -> bool {
+        self.config.fields.contains(&field)
+    }
+}
+
+fn context_string(context: &Context, key: &str) -> Option<String> {
+    context.get::<_, String>(key).ok().flatten()
+}
+
+/// Entity cache hits and misses of a subgraph fetch
+fn cache_hits(context: &Context, subgraph_name: &str) -> Option<(usize, usize)> {
+    let cache: CacheSubgraph = context
+        .get(CacheMetricContextKey::new(subgraph_name.to_string()))
+        .ok()
+        .flatten()?;
+    Some(cache.0.values().fold((0, 0), |(hits, misses), hit_miss| {
+        (hits + hit_miss.hit, misses + hit_miss.miss)
+    }))
+}
+
+/// Error codes of a response, deduplicated in order of appearance
+fn error_codes(response: &graphql::Response) -> Vec<String> {
+    let mut codes = Vec::new();
+    for error in &response.errors {
+        if let Some(code) = error.extensions.get("code").and_then(|code| code.as_str()) {
+            if !codes.iter().any(|existing| existing == code) {
+                codes.push(code.to_string());
+            }
+        }
+    }
+    codes
+}
+
+#[async_trait::async_trait]
+impl Plugin for WideEvents {
+    type Config = WideEventsConfig;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let sampling = &init.config.sampling;
+        if !(0.0..=1.0).contains(&sampling.success) || !(0.0..=1.0).contains(&sampling.error) {
+            return Err("wide events sampling ratios must be between 0 and 1".into());
+        }
+        Ok(Self {
+            config: init.config,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.config.enabled {
+            return service;
+        }
+
+        let sampling = self.config.sampling.clone();
+        let operation = self.includes(WideEventField::Operation);
+        let client = self.includes(WideEventField::Client);
+        let subgraphs = self.includes(WideEventField::Subgraphs);
+        let cost = self.includes(WideEventField::Cost);
+        let errors = self.includes(WideEventField::Errors);
+        ServiceBuilder::new()
+            .map_request(|request: supergraph::Request| {
+                request.context.extensions().with_lock(|mut lock| {
+                    lock.insert(RequestEvent {
+                        start: Instant::now(),
+                        fetches: Default::default(),
+                    })
+                });
+                request
+            })
+            .map_first_graphql_response(move |context, parts, response| {
+                let Some(event) = context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<RequestEvent>().cloned())
+                else {
+                    return (parts, response);
+                };
+                let codes = error_codes(&response);
+                let failed = !parts.status.is_success() || !response.errors.is_empty();
+                let ratio = if failed {
+                    sampling.error
+                } else {
+                    sampling.success
+                };
+                if ratio < 1.0 && !rand::thread_rng().gen_bool(ratio) {
+                    return (parts, response);
+                }
+
+                let fetches = subgraphs.then(|| {
+                    serde_json::to_string(&*event.fetches.lock().expect("lock poisoned"))
+                        .unwrap_or_default()
+                });
+                let cost_context = cost
+                    .then(|| {
+                        context
+                            .extensions()
+                            .with_lock(|lock| lock.get::<CostContext>().cloned())
+                    })
+                    .flatten();
+                tracing::info!(
+                    target: WIDE_EVENT_TARGET,
+                    duration_ms = event.start.elapsed().as_secs_f64() * 1000.0,
+                    http.response.status_code = parts.status.as_u16(),
+                    graphql.operation.name = operation
+                        .then(|| context_string(&context, OPERATION_NAME))
+                        .flatten(),
+                    "graphql.operation.type" = operation
+                        .then(|| context_string(&context, OPERATION_KIND))
+                        .flatten(),
+                    graphql.operation.id = operation
+                        .then(|| context_string(&context, APOLLO_OPERATION_ID))
+                        .flatten(),
+                    client.name = client
+                        .then(|| context_string(&context, CLIENT_NAME))
+                        .flatten(),
+                    client.version = client
+                        .then(|| context_string(&context, CLIENT_VERSION))
+                        .flatten(),
+                    subgraphs = fetches,
+                    cost.estimated = cost_context.as_ref().map(|cost| cost.estimated),
+                    cost.actual = cost_context.as_ref().map(|cost| cost.actual),
+                    cost.result = cost_context.as_ref().map(|cost| cost.result),
+                    errors = errors.then(|| codes.join(",")),
+                    "wide event"
+                );
+                (parts, response)
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.config.enabled || !self.includes(WideEventField::Subgraphs) {
+            return service;
+        }
+
+        let name = name.to_string();
+        let cache = self.includes(WideEventField::Cache);
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |request: &subgraph::Request| {
+                    (
+                        request.context.clone(),
+                        request
+                            .context
+                            .extensions()
+                            .with_lock(|lock| lock.get::<RequestEvent>().cloned()),
+                    )
+                },
+                move |(context, event): (Context, Option<RequestEvent>), future| {
+                    let name = name.clone();
+                    let start = Instant::now();
+                    async move {
+                        let result: Result<subgraph::Response, BoxError> = future.await;
+                        if let Some(event) = event {
+                            let (cache_hits, cache_misses) =
+                                cache.then(|| cache_hits(&context, &name)).flatten().unzip();
+                            event
+                                .fetches
+                                .lock()
+                                .expect("lock poisoned")
+                                .push(SubgraphFetch {
+                                    name,
+                                    duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+                                    status: result
+                                        .as_ref()
+                                        .ok()
+                                        .map(|response| response.response.status().as_u16()),
+                                    cache_hits,
+                                    cache_misses,
+                                });
+                        }
+                        result
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes_are_deduplicated() {
+        let response = graphql::Response::builder()
+            .errors(vec![
+                graphql::Error::builder()
+                    .message("a")
+                    .extension_code("COST_ESTIMATED_TOO_EXPENSIVE")
+                    .build(),
+                graphql::Error::builder()
+                    .message("b")
+                    .extension_code("SUBREQUEST_HTTP_ERROR")
+                    .build(),
+                graphql::Error::builder()
+                    .message("c")
+                    .extension_code("COST_ESTIMATED_TOO_EXPENSIVE")
+                    .build(),
+            ])
+            .build();
+        assert_eq!(
+            error_codes(&response),
+            vec!["COST_ESTIMATED_TOO_EXPENSIVE", "SUBREQUEST_HTTP_ERROR"]
+        );
+    }
+
+    #[test]
+    fn fetches_serialization() {
+        let fetches = vec![
+            SubgraphFetch {
+                name: "products".to_string(),
+                duration_ms: 12.5,
+                status: Some(200),
+                cache_hits: Some(2),
+                cache_misses: Some(1),
+            },
+            SubgraphFetch {
+                name: "reviews".to_string(),
+                duration_ms: 3.0,
+                status: None,
+                cache_hits: None,
+                cache_misses: None,
+            },
+        ];
+        assert_eq!(
+            serde_json::to_string(&fetches).unwrap(),
+            r#"[{"name":"products","duration_ms":12.5,"status":200,"cache_hits":2,"cache_misses":1},{"name":"reviews","duration_ms":3.0}]"#
+        );
+    }
+}
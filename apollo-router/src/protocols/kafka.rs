@@ -0,0 +1,559 @@
+//! Subscription events consumed from Kafka.
+//!
+//! The subscriptions of a subgraph configured with Kafka are not sent to the subgraph. The router
+//! consumes the messages of a topic named after the arguments of the root subscription field,
+//! selects the result of the field in each JSON message, and sends it as an event to the
+//! subscribed clients, shaped by the selection set of the subgraph operation. The partitions
+//! added to the topic during a subscription are consumed once they are discovered.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+
+use apollo_compiler::ast;
+use apollo_compiler::ast::NamedType;
+use futures::stream::BoxStream;
+use futures::stream::SelectAll;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use rskafka::client::consumer::StartOffset;
+use rskafka::client::consumer::StreamConsumerBuilder;
+use rskafka::client::partition::UnknownTopicHandling;
+use rskafka::client::Client;
+use rskafka::client::ClientBuilder;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::Value;
+use tower::BoxError;
+
+use crate::graphql;
+use crate::json_ext::Object;
+use crate::spec::query::parse_hir_value;
+
+/// Maximum time the brokers wait for new messages before answering a fetch
+const MAX_WAIT_MS: i32 = 500;
+
+/// Interval between the checks for the partitions added to the topic of a subscription
+const PARTITION_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum length of a Kafka topic name
+const MAX_TOPIC_LENGTH: usize = 249;
+
+/// The clients of the Kafka clusters, by bootstrap brokers. A client is dropped with the last
+/// subscription using it
+static CLIENTS: Lazy<Mutex<HashMap<Vec<String>, Weak<Client>>>> = Lazy::new(Default::default);
+
+/// The value and the offset of a consumed message
+type Message = Result<(Option<Vec<u8>>, i64), String>;
+
+/// Kafka subscription source configuration
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KafkaMode {
+    /// Bootstrap brokers of the Kafka cluster, like `kafka:9092`
+    pub(crate) brokers: Vec<String>,
+    /// Subscription fields consumed from Kafka, by subgraph name
+    pub(crate) subgraphs: HashMap<String, KafkaSubgraph>,
+}
+
+/// Subscription fields of a subgraph consumed from Kafka
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KafkaSubgraph {
+    /// Topics of the root subscription fields, by field name
+    pub(crate) fields: HashMap<String, KafkaField>,
+}
+
+/// Topic of a root subscription field
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KafkaField {
+    /// Name of the topic. The `{args.<name>}` placeholders are replaced by the arguments of the
+    /// field, like `reviews.{args.productId}`
+    pub(crate) topic: String,
+    /// Path of the result of the field in the JSON messages, like `payload.review`. The whole
+    /// message is the result by default
+    #[serde(default)]
+    pub(crate) selection: Option<String>,
+}
+
+/// Kafka source of the subscriptions of a subgraph
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KafkaSubscription {
+    pub(crate) brokers: Vec<String>,
+    pub(crate) subgraph: KafkaSubgraph,
+}
+
+impl KafkaMode {
+    pub(crate) fn validate(&self) -> Result<(), BoxError> {
+        if self.brokers.is_empty() {
+            return Err("the Kafka subscription mode requires at least one broker".into());
+        }
+        for (subgraph_name, subgraph) in &self.subgraphs {
+            for (field_name, field) in &subgraph.fields {
+                placeholders(&field.topic).map_err(|e| {
+                    format!("invalid Kafka topic of the {subgraph_name} subgraph field '{field_name}': {e}")
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the parts of a topic template, as literals and argument names
+fn placeholders(template: &str) -> Result<Vec<TopicPart<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TopicPart::Literal(&rest[..start]));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in '{template}'"))?;
+        let placeholder = &rest[start + 1..start + end];
+        let argument = placeholder
+            .strip_prefix("args.")
+            .filter(|argument| !argument.is_empty())
+            .ok_or_else(|| {
+                format!("unknown placeholder '{{{placeholder}}}', expected '{{args.<name>}}'")
+            })?;
+        parts.push(TopicPart::Argument(argument));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TopicPart::Literal(rest));
+    }
+    Ok(parts)
+}
+
+#[derive(Debug, PartialEq)]
+enum TopicPart<'a> {
+    Literal(&'a str),
+    Argument(&'a str),
+}
+
+/// Returns the topic of a field, from its template and arguments. The arguments come from the
+/// clients, so they are restricted to letters, digits, `_` and `-`: a `.` could reach the topics
+/// of another namespace, like `reviews.{args.productId}` with `p1.internal`
+fn topic(template: &str, arguments: &Object) -> Result<String, String> {
+    let topic = placeholders(template)?
+        .into_iter()
+        .map(|part| match part {
+            TopicPart::Literal(literal) => Ok(literal.to_string()),
+            TopicPart::Argument(name) => {
+                let value = match arguments.get(name) {
+                    Some(Value::String(value)) => value.as_str().to_string(),
+                    Some(Value::Number(value)) => value.to_string(),
+                    Some(Value::Bool(value)) => value.to_string(),
+                    _ => {
+                        return Err(format!(
+                            "the argument '{name}' of the topic is missing or is not a scalar"
+                        ))
+                    }
+                };
+                if value.is_empty()
+                    || !value
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    return Err(format!(
+                        "the argument '{name}' of the topic must only contain [A-Za-z0-9_-]"
+                    ));
+                }
+                Ok(value)
+            }
+        })
+        .collect::<Result<String, String>>()?;
+    if topic.len() > MAX_TOPIC_LENGTH {
+        return Err(format!(
+            "the topic is longer than {MAX_TOPIC_LENGTH} characters"
+        ));
+    }
+    Ok(topic)
+}
+
+/// Returns the value at a path of a message, like `payload.review` or `reviews.0`
+fn select<'a>(message: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(message, |value, segment| match value {
+            Value::Object(object) => object.get(segment),
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// The root subscription field of a subgraph operation
+#[derive(Clone)]
+struct RootField {
+    document: Arc<ast::Document>,
+    response_key: String,
+    name: String,
+    arguments: Object,
+    selection_set: Vec<ast::Selection>,
+}
+
+impl RootField {
+    fn new(request: &graphql::Request) -> Result<Self, String> {
+        let query = request.query.as_deref().unwrap_or_default();
+        let document = ast::Document::parse(query, "subgraph.graphql")
+            .map_err(|e| format!("invalid subgraph operation: {}", e.errors))?;
+        let operation = document
+            .definitions
+            .iter()
+            .find_map(|definition| match definition {
+                ast::Definition::OperationDefinition(operation)
+                    if request.operation_name.is_none()
+                        || operation.name.as_ref().map(|name| name.as_str())
+                            == request.operation_name.as_deref() =>
+                {
+                    Some(operation.clone())
+                }
+                _ => None,
+            })
+            .ok_or("the subgraph operation is missing")?;
+        let field = operation
+            .selection_set
+            .iter()
+            .find_map(|selection| match selection {
+                ast::Selection::Field(field) => Some(field.clone()),
+                _ => None,
+            })
+            .ok_or("the subgraph operation has no root field")?;
+
+        let arguments = field
+            .arguments
+            .iter()
+            .filter_map(|argument| {
+                let value = match argument.value.as_ref() {
+                    ast::Value::Variable(variable) => {
+                        request.variables.get(variable.as_str()).cloned()
+                    }
+                    value => parse_hir_value(value),
+                }?;
+                Some((argument.name.as_str().into(), value))
+            })
+            .collect();
+        Ok(Self {
+            response_key: field.alias.as_ref().unwrap_or(&field.name).to_string(),
+            name: field.name.to_string(),
+            arguments,
+            selection_set: field.selection_set.clone(),
+            document: Arc::new(document),
+        })
+    }
+
+    /// Returns the event of a message
+    fn event(&self, message: &[u8], selection: Option<&str>) -> Result<graphql::Response, String> {
+        let message: Value = serde_json::from_slice(message).map_err(|e| e.to_string())?;
+        let result = match selection {
+            Some(path) => select(&message, path).cloned().unwrap_or(Value::Null),
+            None => message,
+        };
+        let mut data = Object::new();
+        data.insert(
+            self.response_key.as_str(),
+            self.project(&result, &self.selection_set),
+        );
+        Ok(graphql::Response::builder().data(data).build())
+    }
+
+    /// Shapes a value like a selection set: the selected fields are kept, under their alias
+    fn project(&self, value: &Value, selection_set: &[ast::Selection]) -> Value {
+        match value {
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.project(item, selection_set))
+                    .collect(),
+            ),
+            Value::Object(object) if !selection_set.is_empty() => {
+                let mut projected = Object::new();
+                self.project_object(object, selection_set, &mut projected);
+                Value::Object(projected)
+            }
+            _ => value.clone(),
+        }
+    }
+
+    fn project_object(
+        &self,
+        object: &Object,
+        selection_set: &[ast::Selection],
+        projected: &mut Object,
+    ) {
+        let typename = object.get("__typename").and_then(Value::as_str);
+        let applies = |type_condition: Option<&NamedType>| match (type_condition, typename) {
+            (Some(type_condition), Some(typename)) => type_condition.as_str() == typename,
+            _ => true,
+        };
+        for selection in selection_set {
+            match selection {
+                ast::Selection::Field(field) => {
+                    let value = object
+                        .get(field.name.as_str())
+                        .map(|value| self.project(value, &field.selection_set))
+                        .unwrap_or(Value::Null);
+                    projected.insert(field.alias.as_ref().unwrap_or(&field.name).as_str(), value);
+                }
+                ast::Selection::InlineFragment(fragment) => {
+                    if applies(fragment.type_condition.as_ref()) {
+                        self.project_object(object, &fragment.selection_set, projected);
+                    }
+                }
+                ast::Selection::FragmentSpread(spread) => {
+                    let fragment =
+                        self.document
+                            .definitions
+                            .iter()
+                            .find_map(|definition| match definition {
+                                ast::Definition::FragmentDefinition(fragment)
+                                    if fragment.name == spread.fragment_name =>
+                                {
+                                    Some(fragment)
+                                }
+                                _ => None,
+                            });
+                    if let Some(fragment) = fragment {
+                        if applies(Some(&fragment.type_condition)) {
+                            self.project_object(object, &fragment.selection_set, projected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn client(brokers: &[String]) -> Result<Arc<Client>, BoxError> {
+    if let Some(client) = CLIENTS
+        .lock()
+        .expect("lock poisoned")
+        .get(brokers)
+        .and_then(Weak::upgrade)
+    {
+        return Ok(client);
+    }
+    let client = Arc::new(ClientBuilder::new(brokers.to_vec()).build().await?);
+    let mut clients = CLIENTS.lock().expect("lock poisoned");
+    // the clusters that are not used anymore, like after a reconfiguration
+    clients.retain(|_, client| client.strong_count() > 0);
+    clients.insert(brokers.to_vec(), Arc::downgrade(&client));
+    Ok(client)
+}
+
+/// The partitions of a topic
+async fn partitions(client: &Client, topic: &str) -> Result<Vec<i32>, BoxError> {
+    Ok(client
+        .list_topics()
+        .await?
+        .into_iter()
+        .find(|candidate| candidate.name == topic)
+        .ok_or_else(|| format!("the Kafka topic '{topic}' does not exist"))?
+        .partitions
+        .into_iter()
+        .collect())
+}
+
+/// Consumes the messages of a partition from an offset
+async fn consume(
+    client: &Client,
+    topic: &str,
+    partition: i32,
+    start_offset: StartOffset,
+) -> Result<BoxStream<'static, Message>, BoxError> {
+    let partition_client = client
+        .partition_client(topic.to_string(), partition, UnknownTopicHandling::Error)
+        .await?;
+    Ok(
+        StreamConsumerBuilder::new(Arc::new(partition_client), start_offset)
+            .with_max_wait_ms(MAX_WAIT_MS)
+            .build()
+            .map(|result| {
+                result
+                    .map(|(record, _high_watermark)| (record.record.value, record.offset))
+                    .map_err(|e| e.to_string())
+            })
+            .boxed(),
+    )
+}
+
+/// Consumers of all the partitions of a topic
+struct TopicConsumer {
+    client: Arc<Client>,
+    topic: String,
+    partitions: HashSet<i32>,
+    consumers: SelectAll<BoxStream<'static, Message>>,
+}
+
+impl TopicConsumer {
+    /// Consumes the messages published to the partitions of the topic from now on
+    async fn new(client: Arc<Client>, topic: String) -> Result<Self, BoxError> {
+        let mut consumer = Self {
+            client,
+            topic,
+            partitions: HashSet::new(),
+            consumers: SelectAll::new(),
+        };
+        for partition in partitions(&consumer.client, &consumer.topic).await? {
+            consumer.add(partition, StartOffset::Latest).await?;
+        }
+        Ok(consumer)
+    }
+
+    async fn add(&mut self, partition: i32, start_offset: StartOffset) -> Result<(), BoxError> {
+        let messages = consume(&self.client, &self.topic, partition, start_offset).await?;
+        self.consumers.push(messages);
+        self.partitions.insert(partition);
+        Ok(())
+    }
+
+    /// Consumes the partitions added to the topic since the last refresh. They are consumed from
+    /// their first message, which was published after the subscription
+    async fn refresh(&mut self) {
+        let partitions = match partitions(&self.client, &self.topic).await {
+            Ok(partitions) => partitions,
+            Err(e) => {
+                tracing::warn!(topic = %self.topic, "could not list the Kafka partitions: {e}");
+                return;
+            }
+        };
+        for partition in partitions {
+            if !self.partitions.contains(&partition) {
+                if let Err(e) = self.add(partition, StartOffset::Earliest).await {
+                    tracing::warn!(
+                        topic = %self.topic,
+                        partition,
+                        "could not consume the Kafka partition: {e}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// The messages of all the partitions, including the ones added later
+    fn messages(self) -> BoxStream<'static, Message> {
+        let refresh = tokio::time::interval_at(
+            tokio::time::Instant::now() + PARTITION_REFRESH_INTERVAL,
+            PARTITION_REFRESH_INTERVAL,
+        );
+        futures::stream::unfold((self, refresh), |(mut consumer, mut refresh)| async move {
+            loop {
+                tokio::select! {
+                    message = consumer.consumers.next() => {
+                        return message.map(|message| (message, (consumer, refresh)));
+                    }
+                    _ = refresh.tick() => consumer.refresh().await,
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Consumes the events of a subscription from Kafka, starting with the messages published after
+/// the subscription
+pub(crate) async fn subscribe(
+    config: &KafkaSubscription,
+    request: &graphql::Request,
+) -> Result<BoxStream<'static, graphql::Response>, BoxError> {
+    let field = RootField::new(request)?;
+    let field_config = config.subgraph.fields.get(&field.name).ok_or_else(|| {
+        format!(
+            "no Kafka topic is configured for the field '{}'",
+            field.name
+        )
+    })?;
+    let topic = topic(&field_config.topic, &field.arguments)?;
+
+    let client = client(&config.brokers).await?;
+    let consumer = TopicConsumer::new(client, topic.clone()).await?;
+
+    let selection = field_config.selection.clone();
+    Ok(consumer
+        .messages()
+        .filter_map(move |result| {
+            let event = match result {
+                Ok((value, offset)) => value.and_then(|message| {
+                    field
+                        .event(&message, selection.as_deref())
+                        .map_err(|e| {
+                            tracing::warn!(
+                                topic = %topic,
+                                offset,
+                                "could not map the Kafka message to a subscription event: {e}"
+                            )
+                        })
+                        .ok()
+                }),
+                Err(e) => {
+                    tracing::warn!(topic = %topic, "could not consume the Kafka topic: {e}");
+                    None
+                }
+            };
+            futures::future::ready(event)
+        })
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    #[test]
+    fn topics_are_named_after_the_arguments() {
+        let arguments = json!({ "productId": "p1", "page": 2 });
+        let arguments = arguments.as_object().unwrap();
+        assert_eq!(
+            topic("reviews.{args.productId}.{args.page}", arguments).unwrap(),
+            "reviews.p1.2"
+        );
+        assert_eq!(topic("reviews", arguments).unwrap(), "reviews");
+        assert!(topic("reviews.{args.userId}", arguments).is_err());
+
+        // the arguments cannot change the namespace of the topic
+        let arguments = json!({ "productId": "p1.internal", "empty": "" });
+        let arguments = arguments.as_object().unwrap();
+        assert!(topic("reviews.{args.productId}", arguments).is_err());
+        assert!(topic("reviews.{args.empty}", arguments).is_err());
+        assert!(placeholders("reviews.{productId}").is_err());
+        assert!(placeholders("reviews.{args.productId").is_err());
+    }
+
+    #[test]
+    fn messages_are_shaped_like_the_subgraph_operation() {
+        let request = graphql::Request::builder()
+            .query(
+                "subscription($id: ID!) { added: reviewAdded(productId: $id) { id ... on Review { text: body } ...Author } } fragment Author on Review { author { name } }",
+            )
+            .variables(json!({ "id": "p1" }).as_object().unwrap().clone())
+            .build();
+        let field = RootField::new(&request).unwrap();
+        assert_eq!(field.name, "reviewAdded");
+        assert_eq!(field.arguments.get("productId"), Some(&json!("p1")));
+
+        let event = field
+            .event(
+                br#"{"payload":{"review":{"__typename":"Review","id":"r1","body":"great","author":{"name":"Ada","email":"ada@example.com"},"rating":5}}}"#,
+                Some("payload.review"),
+            )
+            .unwrap();
+        assert_eq!(
+            event.data,
+            Some(json!({
+                "added": {
+                    "id": "r1",
+                    "text": "great",
+                    "author": { "name": "Ada" }
+                }
+            }))
+        );
+    }
+}
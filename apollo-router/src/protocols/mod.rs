@@ -1,2 +1,3 @@
+pub(crate) mod kafka;
 pub(crate) mod multipart;
 pub(crate) mod websocket;
@@ -44,12 +44,14 @@ use crate::plugins::authorization::AuthorizationPlugin;
 use crate::plugins::authorization::CacheKeyMetadata;
 use crate::plugins::authorization::UnauthorizedPaths;
 use crate::plugins::progressive_override::LABELS_TO_OVERRIDE_KEY;
+use crate::plugins::telemetry::config::ApolloSignatureCustomization;
 use crate::plugins::telemetry::config::ApolloSignatureNormalizationAlgorithm;
 use crate::plugins::telemetry::config::Conf as TelemetryConfig;
 use crate::query_planner::convert::convert_root_query_plan_node;
 use crate::query_planner::dual_query_planner::BothModeComparisonJob;
 use crate::query_planner::fetch::QueryHash;
 use crate::query_planner::labeler::add_defer_labels;
+use crate::query_planner::TYPE_CONDITIONED_FETCHING_OPT_OUT;
 use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::layers::query_analysis::ParsedDocumentInner;
 use crate::services::QueryPlannerContent;
@@ -71,6 +73,8 @@ const JS_QP_MODE: &str = "js";
 /// No caching is performed. To cache, wrap in a [`CachingQueryPlanner`].
 pub(crate) struct BridgeQueryPlanner {
     planner: PlannerMode,
+    /// Plans the operations opting out of type conditioned fetching
+    js_without_type_conditions: Option<Arc<Planner<QueryPlanResult>>>,
     schema: Arc<Schema>,
     subgraph_schemas: Arc<HashMap<String, Arc<Valid<apollo_compiler::Schema>>>>,
     introspection: Option<Arc<Introspection>>,
@@ -78,6 +82,7 @@ pub(crate) struct BridgeQueryPlanner {
     enable_authorization_directives: bool,
     _federation_instrument: ObservableGauge<u64>,
     signature_normalization_algorithm: ApolloSignatureNormalizationAlgorithm,
+    signature_customization: ApolloSignatureCustomization,
 }
 
 #[derive(Clone)]
@@ -349,12 +354,26 @@ impl BridgeQueryPlanner {
 
         let subgraph_schemas = Arc::new(planner.subgraphs().await?);
 
+        let js_without_type_conditions = if configuration.type_conditioned_fetching_opt_out() {
+            let mut query_planner_configuration = configuration.js_query_planner_config();
+            query_planner_configuration.type_conditioned_fetching = false;
+            Some(Arc::new(
+                Planner::new(schema.raw_sdl.to_string(), query_planner_configuration).await?,
+            ))
+        } else {
+            None
+        };
+
         let introspection = if configuration.supergraph.introspection {
             Some(Arc::new(
                 Introspection::new(
                     planner
                         .js_for_api_schema_and_introspection_and_operation_signature()
                         .clone(),
+                    &configuration
+                        .supergraph
+                        .query_planning
+                        .experimental_introspection,
                 )
                 .await?,
             ))
@@ -367,9 +386,11 @@ impl BridgeQueryPlanner {
         let federation_instrument = federation_version_instrument(schema.federation_version());
         let signature_normalization_algorithm =
             TelemetryConfig::signature_normalization_algorithm(&configuration);
+        let signature_customization = TelemetryConfig::signature_customization(&configuration);
 
         Ok(Self {
             planner,
+            js_without_type_conditions,
             schema,
             subgraph_schemas,
             introspection,
@@ -377,6 +398,7 @@ impl BridgeQueryPlanner {
             configuration,
             _federation_instrument: federation_instrument,
             signature_normalization_algorithm,
+            signature_customization,
         })
     }
 
@@ -405,13 +427,9 @@ impl BridgeQueryPlanner {
         query_metrics_in: &mut OperationLimits<u32>,
     ) -> Result<Query, QueryPlannerError> {
         let executable = &doc.executable;
-        crate::spec::operation_limits::check(
-            query_metrics_in,
-            &self.configuration,
-            &query,
-            executable,
-            operation_name,
-        )?;
+        // The limits are checked per request by the supergraph service, as they depend on the
+        // client
+        *query_metrics_in = crate::spec::operation_limits::measure(executable, operation_name)?;
 
         let (fragments, operations, defer_stats, schema_aware_hash) =
             Query::extract_query_information(&self.schema, executable, operation_name)?;
@@ -463,11 +481,18 @@ impl BridgeQueryPlanner {
         key: CacheKeyMetadata,
         selections: Query,
         plan_options: PlanOptions,
+        type_conditioned_fetching_opt_out: bool,
         doc: &ParsedDocument,
         query_metrics: OperationLimits<u32>,
     ) -> Result<QueryPlannerContent, QueryPlannerError> {
-        let plan_success = self
-            .planner
+        let opted_out_planner = self
+            .js_without_type_conditions
+            .as_ref()
+            .filter(|_| type_conditioned_fetching_opt_out)
+            .map(|js| PlannerMode::Js(js.clone()));
+        let plan_success = opted_out_planner
+            .as_ref()
+            .unwrap_or(&self.planner)
             .plan(
                 doc,
                 filtered_query.clone(),
@@ -484,6 +509,18 @@ impl BridgeQueryPlanner {
             )
             .await?;
 
+        if self
+            .configuration
+            .js_query_planner_config()
+            .type_conditioned_fetching
+        {
+            type_conditioned_fetching_diagnostics(
+                operation.as_deref(),
+                plan_success.data.query_plan.node.as_deref(),
+                opted_out_planner.is_some(),
+            );
+        }
+
         // the `statsReportKey` field should match the original query instead of the filtered query, to index them all under the same query
         let operation_signature = if matches!(
             self.configuration
@@ -540,6 +577,7 @@ impl BridgeQueryPlanner {
                         &operation,
                         self.schema.supergraph_schema(),
                         &self.signature_normalization_algorithm,
+                        &self.signature_customization,
                     );
 
                     // Ignore comparison if the operation name is an empty string since there is a known issue where
@@ -719,6 +757,11 @@ impl Service<QueryPlannerRequest> for BridgeQueryPlanner {
                     .unwrap_or_default(),
             };
 
+            let type_conditioned_fetching_opt_out = context
+                .get(TYPE_CONDITIONED_FETCHING_OPT_OUT)
+                .unwrap_or_default()
+                .unwrap_or_default();
+
             let res = this
                 .get(
                     QueryKey {
@@ -727,6 +770,7 @@ impl Service<QueryPlannerRequest> for BridgeQueryPlanner {
                         operation_name: operation_name.to_owned(),
                         metadata,
                         plan_options,
+                        type_conditioned_fetching_opt_out,
                     },
                     doc,
                 )
@@ -768,7 +812,7 @@ impl Service<QueryPlannerRequest> for BridgeQueryPlanner {
 pub(crate) type FilteredQuery = (Vec<Path>, ast::Document);
 
 impl BridgeQueryPlanner {
-    async fn get(
+    pub(crate) async fn get(
         &self,
         mut key: QueryKey,
         mut doc: ParsedDocument,
@@ -889,6 +933,7 @@ impl BridgeQueryPlanner {
             key.metadata,
             selections,
             key.plan_options,
+            key.type_conditioned_fetching_opt_out,
             &doc,
             query_metrics,
         )
@@ -946,6 +991,42 @@ pub fn render_diff(differences: &[diff::Result<&str>]) -> String {
     output
 }
 
+/// Reports whether type conditioned fetching changed the shape of a query plan, by splitting
+/// entity fetches per concrete type.
+fn type_conditioned_fetching_diagnostics(
+    operation: Option<&str>,
+    root: Option<&PlanNode>,
+    opted_out: bool,
+) {
+    let paths = root
+        .map(|root| root.type_conditioned_paths())
+        .unwrap_or_default();
+    let outcome = if opted_out {
+        "opted_out"
+    } else if paths.is_empty() {
+        "unchanged"
+    } else {
+        "type_conditioned"
+    };
+    u64_counter!(
+        "apollo.router.query_planning.type_conditioned_fetching",
+        "Number of query plans built with type conditioned fetching, by its effect on the plan",
+        1,
+        "outcome" = outcome
+    );
+    if !paths.is_empty() {
+        tracing::debug!(
+            "type conditions changed the query plan of operation {}: the entities at {} are fetched per concrete type",
+            operation.unwrap_or("-"),
+            paths
+                .iter()
+                .map(|path| path.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+}
+
 pub(crate) fn metric_query_planning_plan_duration(planner: &'static str, start: Instant) {
     f64_histogram!(
         "apollo.router.query_planning.plan.duration",
@@ -1417,6 +1498,7 @@ mod tests {
                     operation_name: None,
                     metadata: CacheKeyMetadata::default(),
                     plan_options: PlanOptions::default(),
+                    type_conditioned_fetching_opt_out: false,
                 },
                 doc,
             )
@@ -1474,6 +1556,7 @@ mod tests {
                     operation_name,
                     metadata: CacheKeyMetadata::default(),
                     plan_options,
+                    type_conditioned_fetching_opt_out: false,
                 },
                 doc,
             )
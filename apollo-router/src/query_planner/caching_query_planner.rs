@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::io;
+use std::num::NonZeroUsize;
 use std::ops::Deref;
 use std::sync::Arc;
 use std::task;
@@ -14,6 +16,7 @@ use router_bridge::planner::PlanOptions;
 use router_bridge::planner::Planner;
 use router_bridge::planner::QueryPlannerConfig;
 use router_bridge::planner::UsageReporting;
+use serde::Deserialize;
 use serde::Serialize;
 use sha2::Digest;
 use sha2::Sha256;
@@ -28,11 +31,16 @@ use crate::cache::estimate_size;
 use crate::cache::storage::InMemoryCache;
 use crate::cache::storage::ValueType;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::WarmState;
 use crate::error::CacheResolverError;
 use crate::error::QueryPlannerError;
+use crate::plugin::cache_key::CacheKeyHooks;
+use crate::plugin::CacheKind;
 use crate::plugins::authorization::AuthorizationPlugin;
 use crate::plugins::authorization::CacheKeyMetadata;
 use crate::plugins::progressive_override::LABELS_TO_OVERRIDE_KEY;
+use crate::plugins::telemetry::config::ApolloSignatureCustomization;
+use crate::plugins::telemetry::config::Conf as TelemetryConfig;
 use crate::plugins::telemetry::utils::Timer;
 use crate::query_planner::fetch::SubgraphSchemas;
 use crate::query_planner::labeler::add_defer_labels;
@@ -55,6 +63,11 @@ pub(crate) type Plugins = IndexMap<String, Box<dyn QueryPlannerPlugin>>;
 pub(crate) type InMemoryCachePlanner =
     InMemoryCache<CachingQueryKey, Result<QueryPlannerContent, Arc<QueryPlannerError>>>;
 pub(crate) const APOLLO_OPERATION_ID: &str = "apollo_operation_id";
+/// Context key set to `true` when the query plan of a request came from the cache
+pub(crate) const QUERY_PLAN_CACHE_HIT: &str = "apollo_query_plan_cache_hit";
+/// Context key set to `true` when the operation opted out of type conditioned fetching
+pub(crate) const TYPE_CONDITIONED_FETCHING_OPT_OUT: &str =
+    "apollo_type_conditioned_fetching_opt_out";
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize)]
 pub(crate) enum ConfigMode {
@@ -79,8 +92,11 @@ pub(crate) struct CachingQueryPlanner<T: Clone> {
     plugins: Arc<Plugins>,
     enable_authorization_directives: bool,
     config_mode: ConfigMode,
+    signature_customization: Arc<ApolloSignatureCustomization>,
     introspection: bool,
     legacy_introspection_caching: bool,
+    type_conditioned_fetching_opt_out: bool,
+    warm_state: Option<WarmState>,
 }
 
 fn init_query_plan_from_redis(
@@ -144,11 +160,20 @@ where
             plugins: Arc::new(plugins),
             enable_authorization_directives,
             config_mode,
+            signature_customization: Arc::new(TelemetryConfig::signature_customization(
+                configuration,
+            )),
             introspection: configuration.supergraph.introspection,
             legacy_introspection_caching: configuration
                 .supergraph
                 .query_planning
                 .legacy_introspection_caching,
+            type_conditioned_fetching_opt_out: configuration.type_conditioned_fetching_opt_out(),
+            warm_state: configuration
+                .supergraph
+                .query_planning
+                .experimental_warm_state
+                .clone(),
         })
     }
 
@@ -171,6 +196,21 @@ where
             );
         });
 
+        if previous_cache.is_none() {
+            if let Some(warm_state) = &self.warm_state {
+                match self.restore_warm_state(warm_state).await {
+                    Ok(restored) => tracing::info!(
+                        "restored {restored} query plans from {}",
+                        warm_state.path.display()
+                    ),
+                    Err(e) => tracing::warn!(
+                        "could not restore the query plans from {}: {e}",
+                        warm_state.path.display()
+                    ),
+                }
+            }
+        }
+
         let mut service = ServiceBuilder::new().service(
             self.plugins
                 .iter()
@@ -197,8 +237,11 @@ where
                                 metadata,
                                 plan_options,
                                 config_mode: _,
+                                signature_customization: _,
                                 schema_id: _,
                                 introspection: _,
+                                plugin_cache_key,
+                                type_conditioned_fetching_opt_out,
                             },
                             _,
                         )| WarmUpCachingQueryKey {
@@ -209,6 +252,8 @@ where
                             plan_options: plan_options.clone(),
                             config_mode: self.config_mode.clone(),
                             introspection: self.introspection,
+                            plugin_cache_key: plugin_cache_key.clone(),
+                            type_conditioned_fetching_opt_out: *type_conditioned_fetching_opt_out,
                         },
                     )
                     .take(count)
@@ -254,6 +299,8 @@ where
                         plan_options: PlanOptions::default(),
                         config_mode: self.config_mode.clone(),
                         introspection: self.introspection,
+                        plugin_cache_key: None,
+                        type_conditioned_fetching_opt_out: false,
                     });
                 }
             }
@@ -271,6 +318,8 @@ where
             plan_options,
             config_mode: _,
             introspection: _,
+            plugin_cache_key,
+            type_conditioned_fetching_opt_out,
         } in all_cache_keys
         {
             let context = Context::new();
@@ -290,7 +339,10 @@ where
                 metadata,
                 plan_options,
                 config_mode: self.config_mode.clone(),
+                signature_customization: self.signature_customization.clone(),
                 introspection: self.introspection,
+                plugin_cache_key,
+                type_conditioned_fetching_opt_out,
             };
 
             if experimental_reuse_query_plans {
@@ -341,6 +393,9 @@ where
                     lock.insert::<ParsedDocument>(doc);
                     lock.insert(caching_key.metadata)
                 });
+                if caching_key.type_conditioned_fetching_opt_out {
+                    let _ = context.insert(TYPE_CONDITIONED_FETCHING_OPT_OUT, true);
+                }
 
                 let request = QueryPlannerRequest {
                     query,
@@ -377,6 +432,105 @@ where
     }
 }
 
+impl<T: Clone> CachingQueryPlanner<T> {
+    /// Writes the query plans of the in memory cache to the warm state file
+    pub(crate) async fn save_warm_state(&self) -> Result<(), BoxError> {
+        let Some(warm_state) = &self.warm_state else {
+            return Ok(());
+        };
+
+        let entries = {
+            let cache = self.cache.in_memory_cache();
+            let cache = cache.lock().await;
+            cache
+                .iter()
+                .filter_map(|(key, value)| {
+                    let content = value.as_ref().ok()?;
+                    Some(WarmStateEntry {
+                        key: key.to_string(),
+                        query: key.query.clone(),
+                        operation: key.operation.clone(),
+                        hash: (*key.hash).clone(),
+                        metadata: key.metadata.clone(),
+                        override_conditions: key.plan_options.override_conditions.clone(),
+                        plugin_cache_key: key.plugin_cache_key.clone(),
+                        type_conditioned_fetching_opt_out: key.type_conditioned_fetching_opt_out,
+                        content: content.clone(),
+                    })
+                })
+                .take(
+                    warm_state
+                        .max_query_plans
+                        .map(NonZeroUsize::get)
+                        .unwrap_or(usize::MAX),
+                )
+                .collect::<Vec<_>>()
+        };
+        let count = entries.len();
+        let content = serde_json::to_vec(&WarmStateFile {
+            router_version: ROUTER_VERSION.to_string(),
+            entries,
+        })?;
+
+        // written to a temporary file first, so that a starting router never reads a partial file
+        let temporary_path = warm_state.path.with_extension("tmp");
+        tokio::fs::write(&temporary_path, content).await?;
+        tokio::fs::rename(&temporary_path, &warm_state.path).await?;
+        tracing::info!("saved {count} query plans to {}", warm_state.path.display());
+        Ok(())
+    }
+
+    /// Inserts the query plans of the warm state file in the in memory cache. The plans saved by
+    /// another router version, or with another schema or query planner configuration, are skipped.
+    async fn restore_warm_state(&self, warm_state: &WarmState) -> Result<usize, BoxError> {
+        let content = match tokio::fs::read(&warm_state.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let file: WarmStateFile = serde_json::from_slice(&content)?;
+        if file.router_version != ROUTER_VERSION {
+            return Err(format!(
+                "the query plans were saved by router version {}",
+                file.router_version
+            )
+            .into());
+        }
+
+        let mut restored = 0;
+        // the entries are saved from the most recently used, insert them in reverse to keep the
+        // same order in the LRU cache
+        for entry in file.entries.into_iter().rev() {
+            let caching_key = CachingQueryKey {
+                query: entry.query,
+                schema_id: Arc::clone(&self.schema.schema_id),
+                operation: entry.operation,
+                hash: Arc::new(entry.hash),
+                metadata: entry.metadata,
+                plan_options: PlanOptions {
+                    override_conditions: entry.override_conditions,
+                },
+                config_mode: self.config_mode.clone(),
+                signature_customization: self.signature_customization.clone(),
+                introspection: self.introspection,
+                plugin_cache_key: entry.plugin_cache_key,
+                type_conditioned_fetching_opt_out: entry.type_conditioned_fetching_opt_out,
+            };
+            // the schema, configuration and versions are part of the key
+            if caching_key.to_string() != entry.key {
+                continue;
+            }
+            let mut value = Ok(entry.content);
+            if init_query_plan_from_redis(&self.subgraph_schemas, &mut value).is_err() {
+                continue;
+            }
+            self.cache.insert_in_memory(caching_key, value).await;
+            restored += 1;
+        }
+        Ok(restored)
+    }
+}
+
 impl CachingQueryPlanner<BridgeQueryPlannerPool> {
     pub(crate) fn planners(&self) -> Vec<Arc<Planner<QueryPlanResult>>> {
         self.delegate.planners()
@@ -479,6 +633,21 @@ where
             .with_lock(|lock| lock.get::<CacheKeyMetadata>().cloned())
             .unwrap_or_default();
 
+        let plugin_cache_key = CacheKeyHooks::key_component(
+            &request.context,
+            CacheKind::QueryPlan,
+            &doc.hash.to_string(),
+            request.operation_name.as_deref(),
+        );
+
+        let type_conditioned_fetching_opt_out = self.type_conditioned_fetching_opt_out
+            && request
+                .context
+                .get(TYPE_CONDITIONED_FETCHING_OPT_OUT)
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+
         let caching_key = CachingQueryKey {
             query: request.query.clone(),
             operation: request.operation_name.to_owned(),
@@ -487,7 +656,10 @@ where
             metadata,
             plan_options,
             config_mode: self.config_mode.clone(),
+            signature_customization: self.signature_customization.clone(),
             introspection: self.introspection,
+            plugin_cache_key,
+            type_conditioned_fetching_opt_out,
         };
 
         let context = request.context.clone();
@@ -497,6 +669,7 @@ where
                 init_query_plan_from_redis(&self.subgraph_schemas, v)
             })
             .await;
+        let _ = context.insert(QUERY_PLAN_CACHE_HIT, !entry.is_first());
         if entry.is_first() {
             let query_planner::CachingRequest {
                 mut query,
@@ -634,7 +807,11 @@ pub(crate) struct CachingQueryKey {
     pub(crate) metadata: CacheKeyMetadata,
     pub(crate) plan_options: PlanOptions,
     pub(crate) config_mode: ConfigMode,
+    /// The signatures of the usage reports are part of the query plans
+    pub(crate) signature_customization: Arc<ApolloSignatureCustomization>,
     pub(crate) introspection: bool,
+    pub(crate) plugin_cache_key: Option<String>,
+    pub(crate) type_conditioned_fetching_opt_out: bool,
 }
 
 // Update this key every time the cache key or the query plan format has to change.
@@ -654,8 +831,22 @@ impl std::fmt::Display for CachingQueryKey {
             .update(serde_json::to_vec(&self.plan_options).expect("serialization should not fail"));
         hasher
             .update(serde_json::to_vec(&self.config_mode).expect("serialization should not fail"));
+        // not hashed when empty, to keep the keys of the plans cached without customization
+        if !self.signature_customization.is_empty() {
+            hasher.update(
+                serde_json::to_vec(&self.signature_customization)
+                    .expect("serialization should not fail"),
+            );
+        }
         hasher.update(&*self.schema_id);
         hasher.update([self.introspection as u8]);
+        if let Some(plugin_cache_key) = &self.plugin_cache_key {
+            hasher.update(plugin_cache_key);
+        }
+        // not hashed when false, to keep the keys of the plans cached before the opt out
+        if self.type_conditioned_fetching_opt_out {
+            hasher.update([1u8]);
+        }
         let metadata = hex::encode(hasher.finalize());
 
         write!(
@@ -674,7 +865,10 @@ impl Hash for CachingQueryKey {
         self.metadata.hash(state);
         self.plan_options.hash(state);
         self.config_mode.hash(state);
+        self.signature_customization.hash(state);
         self.introspection.hash(state);
+        self.plugin_cache_key.hash(state);
+        self.type_conditioned_fetching_opt_out.hash(state);
     }
 }
 
@@ -687,6 +881,32 @@ pub(crate) struct WarmUpCachingQueryKey {
     pub(crate) plan_options: PlanOptions,
     pub(crate) config_mode: ConfigMode,
     pub(crate) introspection: bool,
+    pub(crate) plugin_cache_key: Option<String>,
+    pub(crate) type_conditioned_fetching_opt_out: bool,
+}
+
+const ROUTER_VERSION: &str = std::env!("CARGO_PKG_VERSION");
+
+/// Query plans saved by [`CachingQueryPlanner::save_warm_state`]
+#[derive(Serialize, Deserialize)]
+struct WarmStateFile {
+    router_version: String,
+    entries: Vec<WarmStateEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WarmStateEntry {
+    /// The cache key the plan was saved with, compared with the key rebuilt on restore
+    key: String,
+    query: String,
+    operation: Option<String>,
+    hash: QueryHash,
+    metadata: CacheKeyMetadata,
+    override_conditions: Vec<String>,
+    plugin_cache_key: Option<String>,
+    #[serde(default)]
+    type_conditioned_fetching_opt_out: bool,
+    content: QueryPlannerContent,
 }
 
 impl ValueType for Result<QueryPlannerContent, Arc<QueryPlannerError>> {
@@ -1019,4 +1239,91 @@ mod tests {
             .await
             .is_ok());
     }
+
+    #[test(tokio::test)]
+    async fn test_warm_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let configuration: Configuration = serde_json::from_value(serde_json::json!({
+            "supergraph": {
+                "query_planning": {
+                    "experimental_warm_state": { "path": dir.path().join("warm_state.json") }
+                }
+            }
+        }))
+        .unwrap();
+        let schema = Arc::new(
+            Schema::parse(include_str!("testdata/schema.graphql"), &configuration).unwrap(),
+        );
+
+        let new_planner = |configuration: Configuration| {
+            let schema = schema.clone();
+            async move {
+                let mut delegate = MockMyQueryPlanner::new();
+                delegate.expect_sync_call().times(0);
+                CachingQueryPlanner::new(
+                    delegate,
+                    schema,
+                    Default::default(),
+                    &configuration,
+                    IndexMap::default(),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let planner = new_planner(configuration.clone()).await;
+        let key = CachingQueryKey {
+            query: "query Me { me { username } }".to_string(),
+            schema_id: schema.schema_id.clone(),
+            operation: Some("Me".to_string()),
+            hash: Default::default(),
+            metadata: Default::default(),
+            plan_options: Default::default(),
+            config_mode: planner.config_mode.clone(),
+            signature_customization: Default::default(),
+            introspection: false,
+            plugin_cache_key: None,
+            type_conditioned_fetching_opt_out: false,
+        };
+        let plan = QueryPlan {
+            formatted_query_plan: Default::default(),
+            root: serde_json::from_value(serde_json::json!({ "kind": "Sequence", "nodes": [] }))
+                .unwrap(),
+            usage_reporting: UsageReporting {
+                stats_report_key: "this is a test report key".to_string(),
+                referenced_fields_by_type: Default::default(),
+            }
+            .into(),
+            query: Arc::new(Query::empty()),
+            query_metrics: Default::default(),
+            estimated_size: Default::default(),
+        };
+        planner
+            .cache
+            .insert_in_memory(
+                key.clone(),
+                Ok(QueryPlannerContent::Plan {
+                    plan: Arc::new(plan),
+                }),
+            )
+            .await;
+        planner.save_warm_state().await.unwrap();
+
+        let planner = new_planner(configuration.clone()).await;
+        let warm_state = planner.warm_state.clone().unwrap();
+        assert_eq!(planner.restore_warm_state(&warm_state).await.unwrap(), 1);
+        assert!(planner
+            .previous_cache()
+            .lock()
+            .await
+            .get(&key)
+            .is_some_and(|entry| entry.is_ok()));
+
+        // plans saved with another configuration are not restored
+        let mut configuration = configuration;
+        configuration.supergraph.introspection = true;
+        let planner = new_planner(configuration).await;
+        assert_eq!(planner.restore_warm_state(&warm_state).await.unwrap(), 0);
+    }
 }
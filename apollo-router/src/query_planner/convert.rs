@@ -85,6 +85,7 @@ impl From<&'_ Box<next::FetchNode>> for plan::PlanNode {
             input_rewrites: option_vec(input_rewrites),
             output_rewrites: option_vec(output_rewrites),
             context_rewrites: option_vec(context_rewrites),
+            context_extractions: None,
             schema_aware_hash: Default::default(),
             authorization: Default::default(),
         })
@@ -180,6 +180,7 @@ fn fetch_node_matches(this: &FetchNode, other: &FetchNode) -> bool {
         input_rewrites,
         output_rewrites,
         context_rewrites,
+        context_extractions: _, // compiled from context_rewrites
         schema_aware_hash: _, // ignored
         authorization,
     } = this;
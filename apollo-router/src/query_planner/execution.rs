@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 
 use apollo_compiler::validation::Valid;
 use futures::future::join_all;
@@ -22,6 +23,7 @@ use crate::json_ext::Object;
 use crate::json_ext::Path;
 use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
+use crate::plugins::field_latency::FieldLatencyAttribution;
 use crate::plugins::subscription::SubscriptionConfig;
 use crate::query_planner::FlattenNode;
 use crate::query_planner::Primary;
@@ -234,15 +236,32 @@ impl PlanNode {
                         value = Value::Object(Object::default());
                         errors = Vec::new();
                     } else {
-                        let (v, e) = fetch_node
-                            .fetch_node(parameters, parent_value, current_dir)
-                            .instrument(tracing::info_span!(
-                                FETCH_SPAN_NAME,
-                                "otel.kind" = "INTERNAL",
-                                "apollo.subgraph.name" = fetch_node.service_name.as_ref(),
-                                "apollo_private.sent_time_offset" = fetch_time_offset
-                            ))
-                            .await;
+                        let attribution = parameters
+                            .context
+                            .extensions()
+                            .with_lock(|lock| lock.get::<FieldLatencyAttribution>().cloned());
+                        let (v, e) = async {
+                            let start = Instant::now();
+                            let result = fetch_node
+                                .fetch_node(parameters, parent_value, current_dir)
+                                .await;
+                            if let Some(attribution) = attribution {
+                                attribution.record(
+                                    fetch_node,
+                                    current_dir,
+                                    start.elapsed(),
+                                    parameters.context,
+                                );
+                            }
+                            result
+                        }
+                        .instrument(tracing::info_span!(
+                            FETCH_SPAN_NAME,
+                            "otel.kind" = "INTERNAL",
+                            "apollo.subgraph.name" = fetch_node.service_name.as_ref(),
+                            "apollo_private.sent_time_offset" = fetch_time_offset
+                        ))
+                        .await;
                         value = v;
                         errors = e;
                     }
@@ -0,0 +1,430 @@
+//! Human readable "explain" output of query plans.
+//!
+//! The JSON serialization of a query plan is meant for tools. The explain output is a tree meant
+//! for people reviewing a plan: each fetch is numbered, and shows its subgraph, the entity fields
+//! it requires as inputs, the fields it fetches, the fetches it waits for and its estimated cost.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use tower::BoxError;
+
+use super::fetch::FetchNode;
+use super::fetch::SubgraphOperation;
+use super::selection::Selection;
+use super::subscription::SubscriptionNode;
+use super::BridgeQueryPlanner;
+use super::PlanNode;
+use super::QueryKey;
+use super::QueryPlan;
+use crate::plugins::demand_control::cost_calculator::static_cost::StaticCostCalculator;
+use crate::services::QueryPlannerContent;
+use crate::spec::Query;
+use crate::spec::Schema;
+use crate::Configuration;
+
+/// Assumed size of lists without a `@listSize` directive, for the estimated costs
+pub(crate) const EXPLAIN_LIST_SIZE: u32 = 10;
+
+/// Output format of a query plan
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum PlanFormat {
+    /// Human readable tree
+    #[default]
+    Explain,
+    /// JSON serialization of the plan nodes
+    Json,
+}
+
+/// Returns the explain output of a query plan. Estimated costs are included when a cost
+/// calculator is provided.
+pub(crate) fn explain(plan: &QueryPlan, costs: Option<&StaticCostCalculator>) -> String {
+    let mut explainer = Explainer {
+        costs,
+        output: String::new(),
+        next_fetch: 1,
+        fetch_numbers: HashMap::new(),
+    };
+    match costs.and_then(|costs| costs.planned(plan).ok()) {
+        Some(cost) => writeln!(explainer.output, "QueryPlan (estimated cost: {cost})"),
+        None => writeln!(explainer.output, "QueryPlan"),
+    }
+    .expect("writing to a string can't fail");
+    explainer.node(&plan.root, "", true, &[]);
+    explainer.output
+}
+
+struct Explainer<'a> {
+    costs: Option<&'a StaticCostCalculator>,
+    output: String,
+    next_fetch: usize,
+    /// Numbers of the fetches with an identifier, referenced by deferred nodes
+    fetch_numbers: HashMap<String, usize>,
+}
+
+impl Explainer<'_> {
+    fn line(&mut self, prefix: &str, last: bool, text: &str) {
+        let branch = if last { "└─ " } else { "├─ " };
+        writeln!(self.output, "{prefix}{branch}{text}").expect("writing to a string can't fail");
+    }
+
+    fn detail(&mut self, prefix: &str, label: &str, text: &str) {
+        writeln!(self.output, "{prefix}    {label}: {text}")
+            .expect("writing to a string can't fail");
+    }
+
+    fn child_prefix(prefix: &str, last: bool) -> String {
+        format!("{prefix}{}", if last { "   " } else { "│  " })
+    }
+
+    fn cost(&self, service_name: &str, operation: &SubgraphOperation) -> Option<f64> {
+        self.costs?
+            .estimated_cost_of_operation(service_name, operation)
+            .ok()
+    }
+
+    /// Writes a node and its children, and returns the numbers of the fetches the nodes after it
+    /// in a sequence wait for.
+    fn node(&mut self, node: &PlanNode, prefix: &str, last: bool, depends: &[usize]) -> Vec<usize> {
+        let children = Self::child_prefix(prefix, last);
+        match node {
+            PlanNode::Sequence { nodes } => {
+                self.line(prefix, last, "Sequence");
+                let mut depends = depends.to_vec();
+                for (index, node) in nodes.iter().enumerate() {
+                    let done = self.node(node, &children, index + 1 == nodes.len(), &depends);
+                    if !done.is_empty() {
+                        depends = done;
+                    }
+                }
+                depends
+            }
+            PlanNode::Parallel { nodes } => {
+                self.line(prefix, last, "Parallel");
+                let mut done = Vec::new();
+                for (index, node) in nodes.iter().enumerate() {
+                    done.extend(self.node(node, &children, index + 1 == nodes.len(), depends));
+                }
+                done
+            }
+            PlanNode::Fetch(fetch) => vec![self.fetch(fetch, prefix, last, depends)],
+            PlanNode::Flatten(flatten) => {
+                self.line(
+                    prefix,
+                    last,
+                    &format!("Flatten(path: \"{}\")", flatten.path),
+                );
+                self.node(&flatten.node, &children, true, depends)
+            }
+            PlanNode::Defer { primary, deferred } => {
+                self.line(prefix, last, "Defer");
+                self.line(&children, deferred.is_empty(), "Primary");
+                let primary_children = Self::child_prefix(&children, deferred.is_empty());
+                let mut done = match &primary.node {
+                    Some(node) => self.node(node, &primary_children, true, depends),
+                    None => Vec::new(),
+                };
+                for (index, deferred_node) in deferred.iter().enumerate() {
+                    let deferred_last = index + 1 == deferred.len();
+                    let deferred_depends: Vec<usize> = deferred_node
+                        .depends
+                        .iter()
+                        .filter_map(|depends| self.fetch_numbers.get(&depends.id).copied())
+                        .collect();
+                    let mut text = format!("Deferred(path: \"{}\"", deferred_node.query_path);
+                    if let Some(label) = &deferred_node.label {
+                        write!(text, ", label: \"{label}\"")
+                            .expect("writing to a string can't fail");
+                    }
+                    text.push(')');
+                    if !deferred_depends.is_empty() {
+                        write!(text, " after {}", numbers(&deferred_depends))
+                            .expect("writing to a string can't fail");
+                    }
+                    self.line(&children, deferred_last, &text);
+                    if let Some(node) = &deferred_node.node {
+                        let deferred_children = Self::child_prefix(&children, deferred_last);
+                        done.extend(self.node(node, &deferred_children, true, &deferred_depends));
+                    }
+                }
+                done
+            }
+            PlanNode::Subscription { primary, rest } => {
+                self.line(prefix, last, "Subscription");
+                let number = self.subscription(primary, &children, rest.is_none(), depends);
+                match rest {
+                    Some(rest) => self.node(rest, &children, true, &[number]),
+                    None => vec![number],
+                }
+            }
+            PlanNode::Condition {
+                condition,
+                if_clause,
+                else_clause,
+            } => {
+                self.line(prefix, last, &format!("Condition(if: ${condition})"));
+                let mut done = Vec::new();
+                if let Some(node) = if_clause {
+                    self.line(&children, else_clause.is_none(), "Then");
+                    let then_children = Self::child_prefix(&children, else_clause.is_none());
+                    done.extend(self.node(node, &then_children, true, depends));
+                }
+                if let Some(node) = else_clause {
+                    self.line(&children, true, "Else");
+                    let else_children = Self::child_prefix(&children, true);
+                    done.extend(self.node(node, &else_children, true, depends));
+                }
+                done
+            }
+        }
+    }
+
+    fn fetch(&mut self, fetch: &FetchNode, prefix: &str, last: bool, depends: &[usize]) -> usize {
+        let number = self.next_fetch;
+        self.next_fetch += 1;
+        if let Some(id) = &fetch.id {
+            self.fetch_numbers.insert(id.clone(), number);
+        }
+
+        let mut text = format!("[{number}] Fetch(service: \"{}\")", fetch.service_name);
+        if let Some(cost) = self.cost(&fetch.service_name, &fetch.operation) {
+            write!(text, " cost: {cost}").expect("writing to a string can't fail");
+        }
+        self.line(prefix, last, &text);
+        let details = Self::child_prefix(prefix, last);
+        if !fetch.requires.is_empty() {
+            self.detail(&details, "requires", &selections(&fetch.requires));
+        }
+        self.detail(&details, "fetches", &operation_selections(&fetch.operation));
+        if !depends.is_empty() {
+            self.detail(&details, "after", &numbers(depends));
+        }
+        number
+    }
+
+    fn subscription(
+        &mut self,
+        subscription: &SubscriptionNode,
+        prefix: &str,
+        last: bool,
+        depends: &[usize],
+    ) -> usize {
+        let number = self.next_fetch;
+        self.next_fetch += 1;
+
+        let mut text = format!(
+            "[{number}] Subscribe(service: \"{}\")",
+            subscription.service_name
+        );
+        if let Some(cost) = self.cost(&subscription.service_name, &subscription.operation) {
+            write!(text, " cost: {cost}").expect("writing to a string can't fail");
+        }
+        self.line(prefix, last, &text);
+        let details = Self::child_prefix(prefix, last);
+        self.detail(
+            &details,
+            "fetches",
+            &operation_selections(&subscription.operation),
+        );
+        if !depends.is_empty() {
+            self.detail(&details, "after", &numbers(depends));
+        }
+        number
+    }
+}
+
+fn numbers(fetches: &[usize]) -> String {
+    fetches
+        .iter()
+        .map(|number| format!("[{number}]"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Writes selections of the query plan on a single line.
+fn selections(selections: &[Selection]) -> String {
+    fn write_selections(output: &mut String, selections: &[Selection]) {
+        output.push_str("{ ");
+        for selection in selections {
+            match selection {
+                Selection::Field(field) => {
+                    if let Some(alias) = &field.alias {
+                        output.push_str(alias.as_str());
+                        output.push_str(": ");
+                    }
+                    output.push_str(field.name.as_str());
+                    if let Some(selections) = &field.selections {
+                        output.push(' ');
+                        write_selections(output, selections);
+                    }
+                }
+                Selection::InlineFragment(fragment) => {
+                    output.push_str("...");
+                    if let Some(type_condition) = &fragment.type_condition {
+                        output.push_str(" on ");
+                        output.push_str(type_condition.as_str());
+                    }
+                    output.push(' ');
+                    write_selections(output, &fragment.selections);
+                }
+            }
+            output.push(' ');
+        }
+        output.push('}');
+    }
+
+    let mut output = String::new();
+    write_selections(&mut output, selections);
+    output
+}
+
+/// The selections of a subgraph operation on a single line, or the whole operation if it isn't
+/// parsed.
+fn operation_selections(operation: &SubgraphOperation) -> String {
+    operation
+        .as_parsed()
+        .ok()
+        .and_then(|document| document.operations.iter().next())
+        .map(|operation| operation.selection_set.serialize().no_indent().to_string())
+        .unwrap_or_else(|| operation.as_serialized().to_string())
+}
+
+/// Plans an operation, failing if it doesn't need a query plan.
+pub(crate) async fn plan_operation(
+    planner: &BridgeQueryPlanner,
+    configuration: &Configuration,
+    query: String,
+    operation_name: Option<String>,
+) -> Result<Arc<QueryPlan>, BoxError> {
+    let doc = Query::parse_document(
+        &query,
+        operation_name.as_deref(),
+        &planner.schema(),
+        configuration,
+    )?;
+    let content = planner
+        .get(
+            QueryKey {
+                original_query: query.clone(),
+                filtered_query: query,
+                operation_name,
+                metadata: Default::default(),
+                plan_options: Default::default(),
+                type_conditioned_fetching_opt_out: false,
+            },
+            doc,
+        )
+        .await?;
+
+    match content {
+        QueryPlannerContent::Plan { plan } => Ok(plan),
+        QueryPlannerContent::Response { response } => Err(format!(
+            "the query does not need a plan: {}",
+            serde_json::to_string(&response)?
+        )
+        .into()),
+        QueryPlannerContent::IntrospectionDisabled => {
+            Err("the query is an introspection query".into())
+        }
+    }
+}
+
+/// Plans a query against a supergraph schema, and prints the plan, for `router plan`.
+pub(crate) async fn run(
+    supergraph: &Path,
+    query: &Path,
+    operation_name: Option<String>,
+    format: PlanFormat,
+) -> Result<(), BoxError> {
+    let sdl = std::fs::read_to_string(supergraph)
+        .map_err(|e| format!("could not read {}: {e}", supergraph.display()))?;
+    let query = std::fs::read_to_string(query)
+        .map_err(|e| format!("could not read {}: {e}", query.display()))?;
+
+    let configuration = Arc::new(Configuration::default());
+    let schema = Schema::parse(&sdl, &configuration)?;
+    let planner = BridgeQueryPlanner::new(schema.into(), configuration.clone(), None, None).await?;
+    let plan = plan_operation(&planner, &configuration, query, operation_name).await?;
+    match format {
+        PlanFormat::Explain => {
+            let costs = StaticCostCalculator::new(planner.subgraph_schemas(), EXPLAIN_LIST_SIZE);
+            print!("{}", explain(&plan, Some(&costs)));
+        }
+        PlanFormat::Json => println!("{}", serde_json::to_string_pretty(&plan.root)?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(root: serde_json::Value) -> QueryPlan {
+        QueryPlan::fake_builder()
+            .root(serde_json::from_value::<PlanNode>(root).unwrap())
+            .build()
+    }
+
+    #[test]
+    fn explains_requires_dependencies() {
+        let plan = plan(serde_json::json!({
+            "kind": "Sequence",
+            "nodes": [
+                {
+                    "kind": "Fetch",
+                    "serviceName": "products",
+                    "variableUsages": [],
+                    "operation": "{topProducts{__typename upc weight}}",
+                    "operationKind": "query"
+                },
+                {
+                    "kind": "Parallel",
+                    "nodes": [
+                        {
+                            "kind": "Flatten",
+                            "path": ["topProducts", "@"],
+                            "node": {
+                                "kind": "Fetch",
+                                "serviceName": "inventory",
+                                "requires": [{
+                                    "kind": "InlineFragment",
+                                    "typeCondition": "Product",
+                                    "selections": [
+                                        { "kind": "Field", "name": "__typename" },
+                                        { "kind": "Field", "name": "upc" },
+                                        { "kind": "Field", "name": "weight" }
+                                    ]
+                                }],
+                                "variableUsages": [],
+                                "operation": "query($representations:[_Any!]!){_entities(representations:$representations){...on Product{shippingEstimate}}}",
+                                "operationKind": "query"
+                            }
+                        },
+                        {
+                            "kind": "Flatten",
+                            "path": ["topProducts", "@"],
+                            "node": {
+                                "kind": "Fetch",
+                                "serviceName": "reviews",
+                                "requires": [{
+                                    "kind": "InlineFragment",
+                                    "typeCondition": "Product",
+                                    "selections": [
+                                        { "kind": "Field", "name": "__typename" },
+                                        { "kind": "Field", "name": "upc" }
+                                    ]
+                                }],
+                                "variableUsages": [],
+                                "operation": "query($representations:[_Any!]!){_entities(representations:$representations){...on Product{reviews{body}}}}",
+                                "operationKind": "query"
+                            }
+                        }
+                    ]
+                }
+            ]
+        }));
+        insta::assert_snapshot!(explain(&plan, None));
+    }
+}
@@ -17,6 +17,8 @@ use super::rewrites;
 use super::selection::execute_selection_set;
 use super::selection::Selection;
 use super::subgraph_context::build_operation_with_aliasing;
+use super::subgraph_context::compile_context_rewrites;
+use super::subgraph_context::ContextExtraction;
 use super::subgraph_context::ContextualArguments;
 use super::subgraph_context::SubgraphContext;
 use crate::error::Error;
@@ -32,6 +34,9 @@ use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
 use crate::plugins::authorization::AuthorizationPlugin;
 use crate::plugins::authorization::CacheKeyMetadata;
+use crate::plugins::context_arguments::ContextArgumentResolution;
+use crate::plugins::context_arguments::MissingValuePolicy;
+use crate::plugins::error_aggregation::ErrorAggregator;
 use crate::services::SubgraphRequest;
 use crate::spec::query::change::QueryHashVisitor;
 use crate::spec::Schema;
@@ -131,6 +136,10 @@ pub(crate) struct FetchNode {
     // Optionally describes a number of "rewrites" to apply to the data that has already been received further up the tree
     pub(crate) context_rewrites: Option<Vec<rewrites::DataRewrite>>,
 
+    // context rewrites compiled when the query plan is initialized, and cached with it
+    #[serde(skip)]
+    pub(crate) context_extractions: Option<Arc<[ContextExtraction]>>,
+
     // hash for the query and relevant parts of the schema. if two different schemas provide the exact same types, fields and directives
     // affecting the query, then they will have the same hash
     #[serde(default)]
@@ -280,10 +289,12 @@ impl Variables {
         request: &Arc<http::Request<Request>>,
         schema: &Schema,
         input_rewrites: &Option<Vec<rewrites::DataRewrite>>,
-        context_rewrites: &Option<Vec<rewrites::DataRewrite>>,
+        context_extractions: Option<&[ContextExtraction]>,
+        context_resolution: Option<&ContextArgumentResolution>,
+        context_errors: &mut Vec<Error>,
     ) -> Option<Variables> {
         let body = request.body();
-        let mut subgraph_context = SubgraphContext::new(data, schema, context_rewrites);
+        let mut subgraph_context = SubgraphContext::new(data, schema, context_extractions);
         if !requires.is_empty() {
             let mut variables = Object::with_capacity(1 + variable_usages.len());
 
@@ -298,7 +309,17 @@ impl Variables {
             data.select_values_and_paths(schema, current_dir, |path, value| {
                 // first get contextual values that are required
                 if let Some(context) = subgraph_context.as_mut() {
-                    context.execute_on_path(path);
+                    let missing = context.execute_on_path(path);
+                    if let Some(resolution) = context_resolution.filter(|_| !missing.is_empty()) {
+                        context_errors.extend(resolution.errors(path, value, &missing));
+                        match resolution.on_missing_value {
+                            MissingValuePolicy::Error => {
+                                context.discard_last_path();
+                                return;
+                            }
+                            MissingValuePolicy::NullField => context.set_missing_to_null(&missing),
+                        }
+                    }
                 }
 
                 let mut value = execute_selection_set(value, requires, schema, None);
@@ -321,6 +342,12 @@ impl Variables {
                 return None;
             }
 
+            if let (Some(resolution), Some(context)) =
+                (context_resolution, subgraph_context.as_ref())
+            {
+                resolution.record(&context.resolved, &context.missing);
+            }
+
             let representations = Value::Array(Vec::from_iter(values));
             let contextual_arguments = match subgraph_context.as_mut() {
                 Some(context) => context.add_variables_and_get_args(&mut variables),
@@ -380,6 +407,25 @@ impl FetchNode {
             ..
         } = self;
 
+        let mut context_errors = Vec::new();
+        // plans that were not initialized, like the ones built in tests, compile their context
+        // rewrites for this fetch only
+        let context_extractions = match &self.context_extractions {
+            Some(extractions) => Some(extractions.clone()),
+            None => compile_context_rewrites(&self.context_rewrites),
+        };
+        let context_resolution = context_extractions
+            .as_ref()
+            .and_then(|_| {
+                parameters
+                    .context
+                    .extensions()
+                    .with_lock(|lock| lock.get::<ContextArgumentResolution>().cloned())
+            })
+            .map(|resolution| match operation.as_parsed() {
+                Ok(document) => resolution.for_fetch(service_name, document),
+                Err(_) => resolution,
+            });
         let Variables {
             variables,
             inverted_paths: paths,
@@ -393,11 +439,13 @@ impl FetchNode {
             parameters.supergraph_request,
             parameters.schema,
             &self.input_rewrites,
-            &self.context_rewrites,
+            context_extractions.as_deref(),
+            context_resolution.as_ref(),
+            &mut context_errors,
         ) {
             Some(variables) => variables,
             None => {
-                return (Value::Object(Object::default()), Vec::new());
+                return (Value::Object(Object::default()), context_errors);
             }
         };
 
@@ -517,8 +565,16 @@ impl FetchNode {
             );
         }
 
-        let (value, errors) =
+        let (value, mut errors) =
             self.response_at_path(parameters.schema, current_dir, paths, response);
+        errors.extend(context_errors);
+        if let Some(aggregator) = parameters
+            .context
+            .extensions()
+            .with_lock(|lock| lock.get::<ErrorAggregator>().cloned())
+        {
+            errors = aggregator.aggregate(service_name, errors);
+        }
         if let Some(id) = &self.id {
             if let Some(sender) = parameters.deferred_fetches.get(id.as_str()) {
                 tracing::info!(monotonic_counter.apollo.router.operations.defer.fetch = 1u64);
@@ -672,6 +728,7 @@ impl FetchNode {
     ) -> Result<(), ValidationErrors> {
         let schema = &subgraph_schemas[self.service_name.as_ref()];
         self.operation.init_parsed(schema)?;
+        self.context_extractions = compile_context_rewrites(&self.context_rewrites);
         Ok(())
     }
 
@@ -682,6 +739,7 @@ impl FetchNode {
     ) -> Result<(), ValidationErrors> {
         let schema = &subgraph_schemas[self.service_name.as_ref()];
         let doc = self.operation.init_parsed(schema)?;
+        self.context_extractions = compile_context_rewrites(&self.context_rewrites);
 
         if let Ok(hash) = QueryHashVisitor::hash_query(
             schema,
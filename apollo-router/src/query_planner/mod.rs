@@ -16,12 +16,13 @@ mod caching_query_planner;
 mod convert;
 pub(crate) mod dual_query_planner;
 mod execution;
+pub(crate) mod explain;
 pub(crate) mod fetch;
 mod labeler;
 mod plan;
 pub(crate) mod rewrites;
 mod selection;
-mod subgraph_context;
+pub(crate) mod subgraph_context;
 pub(crate) mod subscription;
 
 pub(crate) const FETCH_SPAN_NAME: &str = "fetch";
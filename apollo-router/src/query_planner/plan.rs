@@ -17,6 +17,7 @@ use crate::error::CacheResolverError;
 use crate::error::ValidationErrors;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
+use crate::json_ext::PathElement;
 use crate::json_ext::Value;
 use crate::plugins::authorization::CacheKeyMetadata;
 use crate::query_planner::fetch::QueryHash;
@@ -34,6 +35,8 @@ pub(crate) struct QueryKey {
     pub(crate) operation_name: Option<String>,
     pub(crate) metadata: CacheKeyMetadata,
     pub(crate) plan_options: PlanOptions,
+    /// The operation opted out of type conditioned fetching
+    pub(crate) type_conditioned_fetching_opt_out: bool,
 }
 
 /// A plan for a given GraphQL query
@@ -338,6 +341,56 @@ impl PlanNode {
         }
     }
 
+    /// Retrieves the paths of the flatten nodes restricted to some concrete types, which are
+    /// added by type conditioned fetching.
+    pub(crate) fn type_conditioned_paths(&self) -> Vec<&Path> {
+        match self {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => nodes
+                .iter()
+                .flat_map(|n| n.type_conditioned_paths())
+                .collect(),
+            PlanNode::Fetch(_) => Vec::new(),
+            PlanNode::Flatten(node) => {
+                let mut paths = node.node.type_conditioned_paths();
+                if node.path.iter().any(|element| {
+                    matches!(
+                        element,
+                        PathElement::Flatten(Some(type_conditions))
+                            | PathElement::Key(_, Some(type_conditions))
+                            if !type_conditions.is_empty()
+                    )
+                }) {
+                    paths.push(&node.path);
+                }
+                paths
+            }
+            PlanNode::Defer { primary, deferred } => primary
+                .node
+                .iter()
+                .map(|n| n.type_conditioned_paths())
+                .chain(
+                    deferred
+                        .iter()
+                        .flat_map(|d| d.node.iter().map(|n| n.type_conditioned_paths())),
+                )
+                .flatten()
+                .collect(),
+            PlanNode::Subscription { rest, .. } => rest
+                .iter()
+                .flat_map(|n| n.type_conditioned_paths())
+                .collect(),
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => if_clause
+                .iter()
+                .chain(else_clause.iter())
+                .flat_map(|n| n.type_conditioned_paths())
+                .collect(),
+        }
+    }
+
     pub(crate) fn init_parsed_operations(
         &mut self,
         subgraph_schemas: &SubgraphSchemas,
@@ -468,7 +521,6 @@ impl PlanNode {
         Ok(())
     }
 
-    #[cfg(test)]
     /// Retrieves all the services used across all plan nodes.
     ///
     /// Note that duplicates are not filtered.
@@ -1,5 +1,7 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::sync::Arc;
 
 use apollo_compiler::ast;
 use apollo_compiler::ast::VariableDefinition;
@@ -33,123 +35,227 @@ pub(crate) struct ContextualArguments {
 pub(crate) struct SubgraphContext<'a> {
     pub(crate) data: &'a Value,
     pub(crate) schema: &'a Schema,
-    pub(crate) context_rewrites: &'a Vec<DataRewrite>,
+    pub(crate) extractions: &'a [ContextExtraction],
     pub(crate) named_args: Vec<HashMap<String, Value>>,
+    // the number of entities each argument was resolved for, and could not be resolved for
+    pub(crate) resolved: HashMap<String, u64>,
+    pub(crate) missing: HashMap<String, u64>,
+    // values already extracted during this fetch, by extraction and absolute data path. Entities
+    // in a list usually share the parent setting the context, so the value is selected once
+    extracted: HashMap<(usize, Path), Option<Value>>,
 }
 
-// context_path is a non-standard relative path which may navigate up the tree
-// from the current position. This is indicated with a ".." PathElement::Key
-// note that the return value is an absolute path that may be used anywhere
-fn merge_context_path(
-    current_dir: &Path,
-    context_path: &Path,
-) -> Result<Path, ContextBatchingError> {
-    let mut i = 0;
-    let mut j = current_dir.len();
-    // iterate over the context_path(i), every time we encounter a '..', we want
-    // to go up one level in the current_dir(j)
-    while i < context_path.len() {
-        match &context_path.0.get(i) {
-            Some(PathElement::Key(e, _)) => {
-                let mut found = false;
-                if e == ".." {
-                    while !found {
-                        if j == 0 {
-                            return Err(ContextBatchingError::InvalidRelativePath);
-                        }
-                        j -= 1;
-
-                        if let Some(PathElement::Key(_, _)) = current_dir.0.get(j) {
-                            found = true;
-                        }
-                    }
-                    i += 1;
-                } else {
+/// A context argument whose value could not be found in the data for an entity
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct MissingContextValue {
+    /// The name of the variable passing the argument to the subgraph
+    pub(crate) argument: String,
+    /// The types setting the context the value is selected from
+    pub(crate) source_types: Vec<String>,
+    /// The relative paths the value was looked up at
+    pub(crate) context_paths: Vec<String>,
+}
+
+/// A context rewrite of a fetch node, compiled once per query plan so that its relative path is
+/// not interpreted again for every entity
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ContextExtraction {
+    // the variable passing the argument to the subgraph
+    argument: Name,
+    // the number of fields to go up from the path of the entity
+    parents: usize,
+    // the path of the value, from that parent
+    path: Path,
+    // the relative path, as found in the query plan
+    context_path: Path,
+}
+
+impl ContextExtraction {
+    fn new(item: &DataKeyRenamer) -> Self {
+        // context_path is a non-standard relative path which may navigate up the tree
+        // from the current position. This is indicated with a ".." PathElement::Key
+        let parents = item
+            .path
+            .iter()
+            .take_while(|element| matches!(element, PathElement::Key(key, _) if key == ".."))
+            .count();
+        Self {
+            argument: item.rename_key_to.clone(),
+            parents,
+            path: Path(item.path.iter().skip(parents).cloned().collect()),
+            context_path: item.path.clone(),
+        }
+    }
+
+    // note that the return value is an absolute path that may be used anywhere
+    fn data_path(&self, current_dir: &Path) -> Result<Path, ContextBatchingError> {
+        let mut j = current_dir.len();
+        // every time we encounter a '..', we want to go up one field in the current_dir
+        for _ in 0..self.parents {
+            loop {
+                if j == 0 {
+                    return Err(ContextBatchingError::InvalidRelativePath);
+                }
+                j -= 1;
+                if let Some(PathElement::Key(_, _)) = current_dir.0.get(j) {
                     break;
                 }
             }
-            _ => break,
         }
+
+        Ok(Path(
+            current_dir
+                .iter()
+                .take(j)
+                .chain(self.path.iter())
+                .cloned()
+                .collect(),
+        ))
+    }
+
+    fn extract(&self, data: &Value, schema: &Schema, data_path: &Path) -> Option<Value> {
+        let mut value = data.get_path(schema, data_path).ok()?.clone();
+        let data_rewrite = DataRewrite::KeyRenamer(DataKeyRenamer {
+            path: data_path.clone(),
+            rename_key_to: self.argument.clone(),
+        });
+        if let Some(values) = value.as_array_mut() {
+            for v in values {
+                data_rewrite.maybe_apply(schema, v);
+            }
+        } else {
+            data_rewrite.maybe_apply(schema, &mut value);
+        }
+        Some(value)
     }
+}
 
-    let mut return_path: Vec<PathElement> = current_dir.iter().take(j).cloned().collect();
+/// Compiles the context rewrites of a fetch node. This is done when the query plan is
+/// initialized, and the result is cached with it
+pub(crate) fn compile_context_rewrites(
+    context_rewrites: &Option<Vec<DataRewrite>>,
+) -> Option<Arc<[ContextExtraction]>> {
+    let extractions: Vec<ContextExtraction> = context_rewrites
+        .iter()
+        .flatten()
+        .filter_map(|rewrite| match rewrite {
+            DataRewrite::KeyRenamer(item) => Some(ContextExtraction::new(item)),
+            DataRewrite::ValueSetter(_) => None,
+        })
+        .collect();
+    (!extractions.is_empty()).then(|| extractions.into())
+}
 
-    context_path.iter().skip(i).for_each(|e| {
-        return_path.push(e.clone());
-    });
-    Ok(Path(return_path.into_iter().collect()))
+#[cfg(test)]
+fn merge_context_path(
+    current_dir: &Path,
+    context_path: &Path,
+) -> Result<Path, ContextBatchingError> {
+    ContextExtraction::new(&DataKeyRenamer {
+        path: context_path.clone(),
+        rename_key_to: Name::new_unchecked("argument"),
+    })
+    .data_path(current_dir)
 }
 
 impl<'a> SubgraphContext<'a> {
     pub(crate) fn new(
         data: &'a Value,
         schema: &'a Schema,
-        context_rewrites: &'a Option<Vec<DataRewrite>>,
+        extractions: Option<&'a [ContextExtraction]>,
     ) -> Option<SubgraphContext<'a>> {
-        if let Some(rewrites) = context_rewrites {
-            if !rewrites.is_empty() {
-                return Some(SubgraphContext {
-                    data,
-                    schema,
-                    context_rewrites: rewrites,
-                    named_args: Vec::new(),
-                });
+        extractions
+            .filter(|extractions| !extractions.is_empty())
+            .map(|extractions| SubgraphContext {
+                data,
+                schema,
+                extractions,
+                named_args: Vec::new(),
+                resolved: HashMap::new(),
+                missing: HashMap::new(),
+                extracted: HashMap::new(),
+            })
+    }
+
+    // For each of the extractions, start collecting data for the data at path.
+    // Once we find a Value for a given variable, skip additional extractions that
+    // reference the same variable. Returns the variables for which no value was found
+    pub(crate) fn execute_on_path(&mut self, path: &Path) -> Vec<MissingContextValue> {
+        let mut hash_map: HashMap<String, Value> = HashMap::new();
+        for (index, extraction) in self.extractions.iter().enumerate() {
+            if hash_map.contains_key(extraction.argument.as_str()) {
+                continue;
+            }
+            let Ok(data_path) = extraction.data_path(path) else {
+                continue;
+            };
+            let value = match self.extracted.entry((index, data_path)) {
+                Entry::Occupied(entry) => entry.get().clone(),
+                Entry::Vacant(entry) => {
+                    let value = extraction.extract(self.data, self.schema, &entry.key().1);
+                    entry.insert(value).clone()
+                }
+            };
+            if let Some(value) = value {
+                hash_map.insert(extraction.argument.to_string(), value);
             }
         }
-        None
-    }
 
-    // For each of the rewrites, start collecting data for the data at path.
-    // Once we find a Value for a given variable, skip additional rewrites that
-    // reference the same variable
-    pub(crate) fn execute_on_path(&mut self, path: &Path) {
-        let mut found_rewrites: HashSet<String> = HashSet::new();
-        let hash_map: HashMap<String, Value> = self
-            .context_rewrites
-            .iter()
-            .filter_map(|rewrite| {
-                match rewrite {
-                    DataRewrite::KeyRenamer(item) => {
-                        if !found_rewrites.contains(item.rename_key_to.as_str()) {
-                            let wrapped_data_path = merge_context_path(path, &item.path);
-                            if let Ok(data_path) = wrapped_data_path {
-                                let val = self.data.get_path(self.schema, &data_path);
-
-                                if let Ok(v) = val {
-                                    // add to found
-                                    found_rewrites.insert(item.rename_key_to.clone().to_string());
-                                    // TODO: not great
-                                    let mut new_value = v.clone();
-                                    if let Some(values) = new_value.as_array_mut() {
-                                        for v in values {
-                                            let data_rewrite = DataRewrite::KeyRenamer({
-                                                DataKeyRenamer {
-                                                    path: data_path.clone(),
-                                                    rename_key_to: item.rename_key_to.clone(),
-                                                }
-                                            });
-                                            data_rewrite.maybe_apply(self.schema, v);
-                                        }
-                                    } else {
-                                        let data_rewrite = DataRewrite::KeyRenamer({
-                                            DataKeyRenamer {
-                                                path: data_path.clone(),
-                                                rename_key_to: item.rename_key_to.clone(),
-                                            }
-                                        });
-                                        data_rewrite.maybe_apply(self.schema, &mut new_value);
-                                    }
-                                    return Some((item.rename_key_to.to_string(), new_value));
-                                }
-                            }
-                        }
-                        None
-                    }
-                    DataRewrite::ValueSetter(_) => None,
+        let mut missing: Vec<MissingContextValue> = Vec::new();
+        for extraction in self.extractions {
+            let argument = extraction.argument.as_str();
+            if hash_map.contains_key(argument) {
+                continue;
+            }
+            let index = match missing.iter().position(|m| m.argument == argument) {
+                Some(index) => index,
+                None => {
+                    missing.push(MissingContextValue {
+                        argument: argument.to_string(),
+                        ..Default::default()
+                    });
+                    missing.len() - 1
                 }
-            })
-            .collect();
+            };
+            let entry = &mut missing[index];
+            entry
+                .context_paths
+                .push(extraction.context_path.to_string());
+            entry
+                .source_types
+                .extend(
+                    extraction
+                        .context_path
+                        .iter()
+                        .filter_map(|element| match element {
+                            PathElement::Fragment(type_name) => Some(type_name.clone()),
+                            _ => None,
+                        }),
+                );
+        }
+
+        for argument in hash_map.keys() {
+            *self.resolved.entry(argument.clone()).or_default() += 1;
+        }
+        for m in &missing {
+            *self.missing.entry(m.argument.clone()).or_default() += 1;
+        }
         self.named_args.push(hash_map);
+        missing
+    }
+
+    // Removes the arguments collected for the last path, when its entity is not fetched
+    pub(crate) fn discard_last_path(&mut self) {
+        self.named_args.pop();
+    }
+
+    // Passes null for the arguments that could not be resolved for the last path
+    pub(crate) fn set_missing_to_null(&mut self, missing: &[MissingContextValue]) {
+        if let Some(args) = self.named_args.last_mut() {
+            for m in missing {
+                args.insert(m.argument.clone(), Value::Null);
+            }
+        }
     }
 
     // Once all a value has been extracted for every variable, go ahead and add all
@@ -383,6 +489,74 @@ mod subgraph_context_unit_tests {
         }
     }
 
+    #[test]
+    fn test_execute_on_path_reports_missing_values() {
+        let schema =
+            Schema::parse(include_str!("testdata/schema.graphql"), &Default::default()).unwrap();
+        let data = serde_json_bytes::json!({
+            "t": { "__typename": "T", "id": "1", "u": { "__typename": "U", "id": "2" } }
+        });
+        let rewrites: Option<Vec<DataRewrite>> = serde_json::from_str(
+            r#"[
+                { "kind": "KeyRenamer", "path": ["..", "id"], "renameKeyTo": "contextualArgument_1_0" },
+                { "kind": "KeyRenamer", "path": ["..", "... on T", "prop"], "renameKeyTo": "contextualArgument_1_1" }
+            ]"#,
+        )
+        .unwrap();
+        let extractions = compile_context_rewrites(&rewrites);
+        let mut context = SubgraphContext::new(&data, &schema, extractions.as_deref()).unwrap();
+
+        let missing = context.execute_on_path(&Path::from("t/u"));
+        assert_eq!(
+            missing,
+            vec![MissingContextValue {
+                argument: "contextualArgument_1_1".to_string(),
+                source_types: vec!["T".to_string()],
+                context_paths: vec!["/../... on T/prop".to_string()],
+            }]
+        );
+        assert_eq!(context.resolved.get("contextualArgument_1_0"), Some(&1));
+        assert_eq!(context.missing.get("contextualArgument_1_1"), Some(&1));
+
+        context.set_missing_to_null(&missing);
+        assert_eq!(
+            context.named_args[0].get("contextualArgument_1_1"),
+            Some(&Value::Null)
+        );
+        context.discard_last_path();
+        assert!(context.named_args.is_empty());
+    }
+
+    #[test]
+    fn test_execute_on_path_extracts_shared_values_once() {
+        let schema =
+            Schema::parse(include_str!("testdata/schema.graphql"), &Default::default()).unwrap();
+        let data = serde_json_bytes::json!({
+            "t": { "__typename": "T", "id": "1", "u": [
+                { "__typename": "U", "id": "2" },
+                { "__typename": "U", "id": "3" }
+            ] }
+        });
+        let rewrites: Option<Vec<DataRewrite>> = serde_json::from_str(
+            r#"[{ "kind": "KeyRenamer", "path": ["..", "id"], "renameKeyTo": "contextualArgument_1_0" }]"#,
+        )
+        .unwrap();
+        let extractions = compile_context_rewrites(&rewrites);
+        let mut context = SubgraphContext::new(&data, &schema, extractions.as_deref()).unwrap();
+
+        assert!(context.execute_on_path(&Path::from("t/u/0")).is_empty());
+        assert!(context.execute_on_path(&Path::from("t/u/1")).is_empty());
+        assert_eq!(context.extracted.len(), 1);
+        assert_eq!(context.resolved.get("contextualArgument_1_0"), Some(&2));
+        assert_eq!(
+            context.named_args,
+            vec![
+                HashMap::from([("contextualArgument_1_0".to_string(), Value::from("1"))]),
+                HashMap::from([("contextualArgument_1_0".to_string(), Value::from("1"))]),
+            ]
+        );
+    }
+
     #[test]
     fn test_transform_selection_set() {
         let type_name = Name::new("Hello").unwrap();
@@ -208,7 +208,9 @@ impl SubscriptionNode {
             parameters.supergraph_request,
             parameters.schema,
             &self.input_rewrites,
-            &None,
+            None,
+            None,
+            &mut Vec::new(),
         ) {
             Some(variables) => variables,
             None => {
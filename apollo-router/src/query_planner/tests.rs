@@ -275,6 +275,7 @@ async fn defer() {
                         input_rewrites: None,
                         output_rewrites: None,
                         context_rewrites: None,
+                        context_extractions: None,
                         schema_aware_hash: Default::default(),
                         authorization: Default::default(),
                     }))),
@@ -321,6 +322,7 @@ async fn defer() {
                             input_rewrites: None,
                             output_rewrites: None,
                             context_rewrites: None,
+                            context_extractions: None,
                             schema_aware_hash: Default::default(),
                             authorization: Default::default(),
                         })),
@@ -1820,6 +1822,7 @@ fn broken_plan_does_not_panic() {
             input_rewrites: None,
             output_rewrites: None,
             context_rewrites: None,
+            context_extractions: None,
             schema_aware_hash: Default::default(),
             authorization: Default::default(),
         })
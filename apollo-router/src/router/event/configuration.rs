@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::time::Duration;
@@ -7,7 +7,12 @@ use derivative::Derivative;
 use derive_more::Display;
 use derive_more::From;
 use futures::prelude::*;
+use futures::stream::BoxStream;
+use url::Url;
 
+use super::object_store;
+use crate::configuration::overlay::merge_overlays;
+use crate::configuration::secrets::SecretResolver;
 use crate::router::Event;
 use crate::router::Event::NoMoreConfiguration;
 use crate::router::Event::UpdateConfiguration;
@@ -48,6 +53,30 @@ pub enum ConfigurationSource {
         #[deprecated]
         delay: Option<Duration>,
     },
+
+    /// Yaml files merged in order, that may be watched for changes
+    #[display(fmt = "Files")]
+    Files {
+        /// The paths of the configuration files. Mappings are merged key by key, and the other
+        /// values of the later files replace the values of the earlier ones.
+        paths: Vec<PathBuf>,
+
+        /// `true` to watch the files for changes and hot apply them.
+        watch: bool,
+    },
+
+    /// A yaml file in S3, Google Cloud Storage or Azure Blob Storage
+    #[display(fmt = "ObjectStore")]
+    ObjectStore {
+        /// The `s3://`, `gs://` or `az://` URL of the configuration.
+        url: Url,
+
+        /// `true` to poll the object for changes and hot apply them.
+        watch: bool,
+
+        /// When watching, the delay to wait between each poll.
+        period: Duration,
+    },
 }
 
 impl Default for ConfigurationSource {
@@ -86,36 +115,26 @@ impl ConfigurationSource {
                         path.to_string_lossy()
                     );
                     stream::empty().boxed()
-                } else {
-                    match ConfigurationSource::read_config(&path) {
-                        Ok(mut configuration) => {
-                            if watch {
-                                crate::files::watch(&path)
-                                    .filter_map(move |_| {
-                                        let path = path.clone();
-                                        let uplink_config = uplink_config.clone();
-                                        async move {
-                                            match ConfigurationSource::read_config_async(&path)
-                                                .await
-                                            {
-                                                Ok(mut configuration) => {
-                                                    configuration.uplink = uplink_config.clone();
-                                                    Some(UpdateConfiguration(configuration))
-                                                }
-                                                Err(err) => {
-                                                    tracing::error!("{}", err);
-                                                    None
-                                                }
-                                            }
-                                        }
-                                    })
-                                    .boxed()
-                            } else {
-                                configuration.uplink = uplink_config.clone();
-                                stream::once(future::ready(UpdateConfiguration(configuration)))
-                                    .boxed()
+                } else if watch {
+                    let contents = crate::files::watch(&path).filter_map(move |_| {
+                        let path = path.clone();
+                        async move {
+                            match tokio::fs::read_to_string(&path).await {
+                                Ok(contents) => Some(contents),
+                                Err(err) => {
+                                    tracing::error!("could not read configuration: {}", err);
+                                    None
+                                }
                             }
                         }
+                    });
+                    with_secrets(contents, uplink_config).boxed()
+                } else {
+                    match std::fs::read_to_string(&path) {
+                        Ok(contents) => {
+                            with_secrets(stream::once(future::ready(contents)), uplink_config)
+                                .boxed()
+                        }
                         Err(err) => {
                             tracing::error!("Failed to read configuration: {}", err);
                             stream::empty().boxed()
@@ -123,27 +142,178 @@ impl ConfigurationSource {
                     }
                 }
             }
+            ConfigurationSource::Files { paths, watch } => {
+                if let Some(path) = paths.iter().find(|path| !path.exists()) {
+                    tracing::error!(
+                        "configuration file at path '{}' does not exist.",
+                        path.to_string_lossy()
+                    );
+                    stream::empty().boxed()
+                } else if watch {
+                    let changes = stream::select_all(
+                        paths
+                            .iter()
+                            .map(|path| crate::files::watch(path).boxed())
+                            .collect::<Vec<_>>(),
+                    );
+                    let contents = changes
+                        .filter_map(move |_| {
+                            let paths = paths.clone();
+                            async move { read_overlays(&paths).await }
+                        })
+                        // each file is read once when its watch starts
+                        .scan(None, |previous, contents| {
+                            let changed = previous.as_ref() != Some(&contents);
+                            *previous = Some(contents.clone());
+                            future::ready(Some(changed.then_some(contents)))
+                        })
+                        .filter_map(future::ready);
+                    with_secrets(contents, uplink_config).boxed()
+                } else {
+                    let contents = stream::once(async move { read_overlays(&paths).await })
+                        .filter_map(future::ready);
+                    with_secrets(contents, uplink_config).boxed()
+                }
+            }
+            ConfigurationSource::ObjectStore { url, watch, period } => {
+                with_secrets(object_store::poll(url, watch, period), uplink_config).boxed()
+            }
         }
         .chain(stream::iter(vec![NoMoreConfiguration]))
         .boxed()
     }
+}
 
-    fn read_config(path: &Path) -> Result<Configuration, ReadConfigError> {
-        let config = std::fs::read_to_string(path)?;
-        config.parse().map_err(ReadConfigError::Validation)
+/// Reads the configuration files and merges them in order
+async fn read_overlays(paths: &[PathBuf]) -> Option<String> {
+    let mut contents = Vec::with_capacity(paths.len());
+    for path in paths {
+        match tokio::fs::read_to_string(path).await {
+            Ok(content) => contents.push(content),
+            Err(err) => {
+                tracing::error!("could not read configuration: {}", err);
+                return None;
+            }
+        }
     }
-    async fn read_config_async(path: &Path) -> Result<Configuration, ReadConfigError> {
-        let config = tokio::fs::read_to_string(path).await?;
-        config.parse().map_err(ReadConfigError::Validation)
+    match merge_overlays(contents.iter().map(String::as_str)) {
+        Ok(merged) => Some(merged),
+        Err(err) => {
+            tracing::error!("could not merge the configuration files: {}", err);
+            None
+        }
     }
 }
 
-#[derive(From, Display)]
-enum ReadConfigError {
-    /// could not read configuration: {0}
-    Io(std::io::Error),
-    /// {0}
-    Validation(crate::configuration::ConfigurationError),
+struct SecretsState {
+    contents: Option<BoxStream<'static, String>>,
+    resolver: SecretResolver,
+    current: Option<Current>,
+    first: bool,
+}
+
+/// The configuration being served, with the secrets it was expanded with.
+struct Current {
+    contents: String,
+    secrets: HashMap<String, String>,
+    refresh_interval: Duration,
+}
+
+enum Next {
+    Contents(Option<String>),
+    RefreshSecrets,
+}
+
+/// Parse each version of the configuration, after fetching the secrets it references.
+///
+/// While the current configuration references secrets, they are fetched again every
+/// `secrets.refresh_interval`, and the configuration is parsed again when one of them changed.
+/// The stream ends if the first configuration cannot be loaded.
+fn with_secrets(
+    contents: impl Stream<Item = String> + Send + 'static,
+    uplink_config: Option<UplinkConfig>,
+) -> impl Stream<Item = Event> {
+    let state = SecretsState {
+        contents: Some(contents.boxed()),
+        resolver: SecretResolver::default(),
+        current: None,
+        first: true,
+    };
+    stream::unfold(state, move |mut state| {
+        let uplink_config = uplink_config.clone();
+        async move {
+            loop {
+                let refresh_interval = state
+                    .current
+                    .as_ref()
+                    .filter(|current| !current.secrets.is_empty())
+                    .map(|current| current.refresh_interval);
+                let next = match (state.contents.as_mut(), refresh_interval) {
+                    (Some(contents), Some(refresh_interval)) => tokio::select! {
+                        next = contents.next() => Next::Contents(next),
+                        _ = tokio::time::sleep(refresh_interval) => Next::RefreshSecrets,
+                    },
+                    (Some(contents), None) => Next::Contents(contents.next().await),
+                    (None, Some(refresh_interval)) => {
+                        tokio::time::sleep(refresh_interval).await;
+                        Next::RefreshSecrets
+                    }
+                    (None, None) => return None,
+                };
+
+                let refresh = matches!(next, Next::RefreshSecrets);
+                let contents = match next {
+                    Next::Contents(Some(contents)) => contents,
+                    Next::Contents(None) => {
+                        state.contents = None;
+                        continue;
+                    }
+                    Next::RefreshSecrets => state
+                        .current
+                        .as_ref()
+                        .expect("secrets are only refreshed for a current configuration")
+                        .contents
+                        .clone(),
+                };
+                let secrets = match state.resolver.resolve(&contents).await {
+                    Ok(secrets) => secrets,
+                    Err(err) => {
+                        tracing::error!("Failed to fetch configuration secrets: {}", err);
+                        if state.first {
+                            return None;
+                        }
+                        continue;
+                    }
+                };
+                if refresh
+                    && state
+                        .current
+                        .as_ref()
+                        .is_some_and(|current| current.secrets == secrets)
+                {
+                    continue;
+                }
+                match Configuration::from_str_with_secrets(&contents, secrets.clone()) {
+                    Ok(mut configuration) => {
+                        state.first = false;
+                        state.current = Some(Current {
+                            contents,
+                            secrets,
+                            refresh_interval: configuration.secrets.refresh_interval,
+                        });
+                        configuration.uplink = uplink_config.clone();
+                        return Some((UpdateConfiguration(configuration), state));
+                    }
+                    Err(err) => {
+                        tracing::error!("Failed to read configuration: {}", err);
+                        if state.first {
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+    })
 }
 
 #[cfg(test)]
@@ -235,4 +405,31 @@ mod tests {
         ));
         assert!(matches!(stream.next().await.unwrap(), NoMoreConfiguration));
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn config_by_files_merged_in_order() {
+        let (base_path, mut base) = create_temp_file();
+        write_and_flush(
+            &mut base,
+            "supergraph:\n  listen: 127.0.0.1:4001\n  introspection: false",
+        )
+        .await;
+        let (overlay_path, mut overlay) = create_temp_file();
+        write_and_flush(&mut overlay, "supergraph:\n  introspection: true").await;
+
+        let mut stream = ConfigurationSource::Files {
+            paths: vec![base_path, overlay_path],
+            watch: false,
+        }
+        .into_stream(Some(UplinkConfig::default()));
+        let UpdateConfiguration(configuration) = stream.next().await.unwrap() else {
+            panic!("expected a configuration");
+        };
+        assert!(configuration.supergraph.introspection);
+        assert_eq!(
+            configuration.supergraph.listen.to_string(),
+            "127.0.0.1:4001"
+        );
+        assert!(matches!(stream.next().await.unwrap(), NoMoreConfiguration));
+    }
 }
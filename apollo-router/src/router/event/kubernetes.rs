@@ -0,0 +1,522 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use tower::BoxError;
+use url::Url;
+
+use crate::router::Event;
+use crate::router::Event::UpdateSchema;
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+const DEFAULT_CONFIG_MAP_KEY: &str = "supergraph.graphql";
+const DEFAULT_CUSTOM_RESOURCE_FIELD: &str = "spec.supergraph";
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A Kubernetes object holding the supergraph schema.
+///
+/// It is parsed from `configmap/[<namespace>/]<name>[#<key>]` for a ConfigMap, or from
+/// `<plural>.<group>/<version>/[<namespace>/]<name>[#<field.path>]` for a custom resource.
+/// When the namespace is omitted, the namespace of the router's pod is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KubernetesResource {
+    /// A ConfigMap, the schema is read from the `key` entry of its data.
+    ConfigMap {
+        namespace: Option<String>,
+        name: String,
+        key: String,
+    },
+    /// A custom resource, the schema is read from the dot separated `field` path.
+    CustomResource {
+        group: String,
+        version: String,
+        plural: String,
+        namespace: Option<String>,
+        name: String,
+        field: String,
+    },
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("invalid kubernetes resource '{0}', expected 'configmap/[<namespace>/]<name>[#<key>]' or '<plural>.<group>/<version>/[<namespace>/]<name>[#<field>]'")]
+pub struct InvalidKubernetesResource(String);
+
+impl FromStr for KubernetesResource {
+    type Err = InvalidKubernetesResource;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidKubernetesResource(s.to_string());
+        let (path, fragment) = match s.split_once('#') {
+            Some((path, fragment)) if !fragment.is_empty() => (path, Some(fragment)),
+            Some(_) => return Err(invalid()),
+            None => (s, None),
+        };
+        let segments: Vec<&str> = path.split('/').collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(invalid());
+        }
+
+        match segments.as_slice() {
+            ["configmap" | "configmaps", rest @ ..] => {
+                let (namespace, name) = match rest {
+                    [name] => (None, name),
+                    [namespace, name] => (Some(namespace.to_string()), name),
+                    _ => return Err(invalid()),
+                };
+                Ok(KubernetesResource::ConfigMap {
+                    namespace,
+                    name: name.to_string(),
+                    key: fragment.unwrap_or(DEFAULT_CONFIG_MAP_KEY).to_string(),
+                })
+            }
+            [kind, version, rest @ ..] => {
+                let (plural, group) = kind.split_once('.').ok_or_else(invalid)?;
+                let (namespace, name) = match rest {
+                    [name] => (None, name),
+                    [namespace, name] => (Some(namespace.to_string()), name),
+                    _ => return Err(invalid()),
+                };
+                Ok(KubernetesResource::CustomResource {
+                    group: group.to_string(),
+                    version: version.to_string(),
+                    plural: plural.to_string(),
+                    namespace,
+                    name: name.to_string(),
+                    field: fragment
+                        .unwrap_or(DEFAULT_CUSTOM_RESOURCE_FIELD)
+                        .to_string(),
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl KubernetesResource {
+    fn namespace(&self) -> Option<&str> {
+        match self {
+            KubernetesResource::ConfigMap { namespace, .. }
+            | KubernetesResource::CustomResource { namespace, .. } => namespace.as_deref(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            KubernetesResource::ConfigMap { name, .. }
+            | KubernetesResource::CustomResource { name, .. } => name,
+        }
+    }
+
+    /// The path of the collection the object belongs to, used both to get and to watch it.
+    fn collection_path(&self, namespace: &str) -> String {
+        match self {
+            KubernetesResource::ConfigMap { .. } => {
+                format!("/api/v1/namespaces/{namespace}/configmaps")
+            }
+            KubernetesResource::CustomResource {
+                group,
+                version,
+                plural,
+                ..
+            } => format!("/apis/{group}/{version}/namespaces/{namespace}/{plural}"),
+        }
+    }
+
+    /// Extract the supergraph schema from the object.
+    fn schema(&self, object: &Value) -> Option<String> {
+        let value = match self {
+            KubernetesResource::ConfigMap { key, .. } => object.get("data")?.get(key.as_str())?,
+            KubernetesResource::CustomResource { field, .. } => field
+                .split('.')
+                .try_fold(object, |value, segment| value.get(segment))?,
+        };
+        value.as_str().map(str::to_string)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    object: Value,
+}
+
+fn resource_version(object: &Value) -> Option<String> {
+    object
+        .get("metadata")?
+        .get("resourceVersion")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Connection details for the Kubernetes API server.
+pub(crate) struct ApiServer {
+    url: Url,
+    token_path: Option<PathBuf>,
+    namespace: Option<String>,
+    client: reqwest::Client,
+}
+
+impl ApiServer {
+    /// Connect using the service account mounted in the router's pod.
+    pub(crate) fn in_cluster() -> Result<Self, BoxError> {
+        let host = std::env::var("KUBERNETES_SERVICE_HOST")
+            .map_err(|_| "KUBERNETES_SERVICE_HOST is not set, is the router running in a pod?")?;
+        let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+        let host = if host.contains(':') {
+            format!("[{host}]")
+        } else {
+            host
+        };
+        let dir = PathBuf::from(SERVICE_ACCOUNT_DIR);
+        let ca = std::fs::read(dir.join("ca.crt"))?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca)?)
+            .build()?;
+
+        Ok(Self {
+            url: Url::parse(&format!("https://{host}:{port}"))?,
+            token_path: Some(dir.join("token")),
+            namespace: std::fs::read_to_string(dir.join("namespace"))
+                .ok()
+                .map(|namespace| namespace.trim().to_string()),
+            client,
+        })
+    }
+
+    #[cfg(test)]
+    fn for_tests(url: Url) -> Self {
+        Self {
+            url,
+            token_path: None,
+            namespace: Some("default".to_string()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<reqwest::Response, BoxError> {
+        let mut url = self.url.clone();
+        url.set_path(path);
+        let mut request = self.client.get(url).query(query);
+        // Service account tokens are rotated, read them on every request.
+        if let Some(token_path) = &self.token_path {
+            let token = tokio::fs::read_to_string(token_path).await?;
+            request = request.bearer_auth(token.trim());
+        }
+        Ok(request.send().await?.error_for_status()?)
+    }
+}
+
+/// Watch the resource and yield the schema every time it changes.
+pub(crate) fn watch(
+    api_server: ApiServer,
+    resource: KubernetesResource,
+) -> impl Stream<Item = Event> {
+    let namespace = match resource
+        .namespace()
+        .map(str::to_string)
+        .or_else(|| api_server.namespace.clone())
+    {
+        Some(namespace) => namespace,
+        None => {
+            tracing::error!("the kubernetes namespace of the supergraph schema must be specified");
+            return stream::empty().boxed();
+        }
+    };
+    let state = Watcher {
+        collection_path: resource.collection_path(&namespace),
+        field_selector: format!("metadata.name={}", resource.name()),
+        api_server,
+        resource,
+        resource_version: None,
+        response: None,
+        received: false,
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        last_schema: None,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        let schema = state.next_schema().await;
+        Some((UpdateSchema(schema), state))
+    })
+    .boxed()
+}
+
+struct Watcher {
+    api_server: ApiServer,
+    resource: KubernetesResource,
+    collection_path: String,
+    field_selector: String,
+    resource_version: Option<String>,
+    response: Option<reqwest::Response>,
+    /// Whether the current watch sent anything before it was closed
+    received: bool,
+    buffer: Vec<u8>,
+    pending: VecDeque<WatchEvent>,
+    last_schema: Option<String>,
+}
+
+impl Watcher {
+    /// Wait until a schema different from the last one is available.
+    async fn next_schema(&mut self) -> String {
+        loop {
+            match self.next_object().await {
+                Ok(Some(object)) => match self.resource.schema(&object) {
+                    Some(schema) if self.last_schema.as_ref() != Some(&schema) => {
+                        self.last_schema = Some(schema.clone());
+                        return schema;
+                    }
+                    Some(_) => {}
+                    None => tracing::warn!(
+                        "kubernetes resource {} does not contain a supergraph schema",
+                        self.resource.name()
+                    ),
+                },
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!(
+                        reason = %err,
+                        "failed to watch kubernetes resource {}, retrying",
+                        self.resource.name()
+                    );
+                    self.response = None;
+                    self.resource_version = None;
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+
+    /// Return the next version of the object, or `None` if nothing changed.
+    async fn next_object(&mut self) -> Result<Option<Value>, BoxError> {
+        if let Some(event) = self.pending.pop_front() {
+            return self.handle_event(event);
+        }
+
+        // Get the current version first, then watch for changes from that version.
+        if self.resource_version.is_none() {
+            let path = format!("{}/{}", self.collection_path, self.resource.name());
+            let object: Value = self.api_server.get(&path, &[]).await?.json().await?;
+            self.resource_version = resource_version(&object);
+            return Ok(Some(object));
+        }
+
+        let mut response = match self.response.take() {
+            Some(response) => response,
+            None => {
+                let resource_version = self.resource_version.clone().unwrap_or_default();
+                self.buffer.clear();
+                self.received = false;
+                self.api_server
+                    .get(
+                        &self.collection_path,
+                        &[
+                            ("watch", "true"),
+                            ("allowWatchBookmarks", "true"),
+                            ("fieldSelector", &self.field_selector),
+                            ("resourceVersion", &resource_version),
+                        ],
+                    )
+                    .await?
+            }
+        };
+
+        // The API server closes watches periodically, in that case a new one is started from the
+        // last version on the next call.
+        if let Some(chunk) = response.chunk().await? {
+            self.response = Some(response);
+            self.received = true;
+            // Events are newline delimited JSON objects, which may be split across chunks.
+            self.buffer.extend_from_slice(&chunk);
+            while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                self.pending.push_back(serde_json::from_slice(&line)?);
+            }
+        } else if !self.received {
+            // A watch closed right away would otherwise be restarted in a tight loop.
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+        Ok(None)
+    }
+
+    fn handle_event(&mut self, event: WatchEvent) -> Result<Option<Value>, BoxError> {
+        match event.kind.as_str() {
+            "ADDED" | "MODIFIED" => {
+                self.resource_version = resource_version(&event.object);
+                Ok(Some(event.object))
+            }
+            "BOOKMARK" => {
+                self.resource_version = resource_version(&event.object);
+                Ok(None)
+            }
+            "DELETED" => {
+                tracing::warn!(
+                    "kubernetes resource {} was deleted, keeping the current schema",
+                    self.resource.name()
+                );
+                Ok(None)
+            }
+            // The watched version may be too old (410 Gone), start over from the current one.
+            _ => {
+                self.pending.clear();
+                Err(format!("watch error: {}", event.object).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::matchers::query_param;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+
+    #[test]
+    fn parse_resources() {
+        assert_eq!(
+            "configmap/supergraph".parse(),
+            Ok(KubernetesResource::ConfigMap {
+                namespace: None,
+                name: "supergraph".to_string(),
+                key: "supergraph.graphql".to_string(),
+            })
+        );
+        assert_eq!(
+            "configmap/graphql/supergraph#schema".parse(),
+            Ok(KubernetesResource::ConfigMap {
+                namespace: Some("graphql".to_string()),
+                name: "supergraph".to_string(),
+                key: "schema".to_string(),
+            })
+        );
+        assert_eq!(
+            "supergraphs.example.com/v1/graphql/products#spec.sdl".parse(),
+            Ok(KubernetesResource::CustomResource {
+                group: "example.com".to_string(),
+                version: "v1".to_string(),
+                plural: "supergraphs".to_string(),
+                namespace: Some("graphql".to_string()),
+                name: "products".to_string(),
+                field: "spec.sdl".to_string(),
+            })
+        );
+        assert!("configmap".parse::<KubernetesResource>().is_err());
+        assert!("configmap/a/b/c".parse::<KubernetesResource>().is_err());
+        assert!("supergraphs/v1/products"
+            .parse::<KubernetesResource>()
+            .is_err());
+        assert!("configmap/supergraph#"
+            .parse::<KubernetesResource>()
+            .is_err());
+    }
+
+    #[test]
+    fn extract_schema() {
+        let resource: KubernetesResource = "supergraphs.example.com/v1/products".parse().unwrap();
+        assert_eq!(
+            resource.schema(&json!({ "spec": { "supergraph": "type Query" } })),
+            Some("type Query".to_string())
+        );
+        assert_eq!(resource.schema(&json!({ "spec": {} })), None);
+    }
+
+    #[tokio::test]
+    async fn watch_config_map() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/supergraph"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "metadata": { "name": "supergraph", "resourceVersion": "1" },
+                "data": { "supergraph.graphql": "schema1" }
+            })))
+            .mount(&mock_server)
+            .await;
+        let events = [
+            json!({ "type": "BOOKMARK", "object": { "metadata": { "resourceVersion": "2" } } }),
+            json!({ "type": "MODIFIED", "object": {
+                "metadata": { "name": "supergraph", "resourceVersion": "3" },
+                "data": { "supergraph.graphql": "schema1" }
+            }}),
+            json!({ "type": "MODIFIED", "object": {
+                "metadata": { "name": "supergraph", "resourceVersion": "4" },
+                "data": { "supergraph.graphql": "schema2" }
+            }}),
+        ]
+        .iter()
+        .map(|event| format!("{event}\n"))
+        .collect::<String>();
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps"))
+            .and(query_param("watch", "true"))
+            .and(query_param("resourceVersion", "1"))
+            .and(query_param("fieldSelector", "metadata.name=supergraph"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(events))
+            .mount(&mock_server)
+            .await;
+
+        let api_server = ApiServer::for_tests(Url::parse(&mock_server.uri()).unwrap());
+        let schemas = watch(api_server, "configmap/supergraph".parse().unwrap())
+            .take(2)
+            .map(|event| match event {
+                UpdateSchema(schema) => schema,
+                _ => panic!("expected a schema update"),
+            })
+            .collect::<Vec<_>>()
+            .await;
+        // The unchanged schema from the first modification is not sent again.
+        assert_eq!(schemas, vec!["schema1", "schema2"]);
+    }
+
+    #[tokio::test]
+    async fn empty_watches_are_restarted_after_a_delay() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps/supergraph"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "metadata": { "name": "supergraph", "resourceVersion": "1" },
+                "data": { "supergraph.graphql": "schema1" }
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/configmaps"))
+            .and(query_param("watch", "true"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let api_server = ApiServer::for_tests(Url::parse(&mock_server.uri()).unwrap());
+        let mut events = watch(api_server, "configmap/supergraph".parse().unwrap());
+        assert!(matches!(events.next().await, Some(UpdateSchema(_))));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), events.next())
+                .await
+                .is_err()
+        );
+
+        // The watch closed without any event is not started again before the retry delay.
+        let watches = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/api/v1/namespaces/default/configmaps")
+            .count();
+        assert_eq!(watches, 1);
+    }
+}
@@ -13,7 +13,8 @@ use crate::uplink::license_enforcement::Audience;
 use crate::uplink::license_enforcement::License;
 use crate::uplink::license_stream::LicenseQuery;
 use crate::uplink::license_stream::LicenseStreamExt;
-use crate::uplink::stream_from_uplink;
+use crate::uplink::stream_from_uplink_with_fallback;
+use crate::uplink::LocalFallback;
 use crate::uplink::UplinkConfig;
 
 const APOLLO_ROUTER_LICENSE_INVALID: &str = "APOLLO_ROUTER_LICENSE_INVALID";
@@ -140,17 +141,28 @@ impl LicenseSource {
             }
 
             LicenseSource::Registry(uplink_config) => {
-                stream_from_uplink::<LicenseQuery, License>(uplink_config)
-                    .filter_map(|res| {
-                        future::ready(match res {
-                            Ok(license) => Some(license),
-                            Err(e) => {
-                                tracing::error!(code = APOLLO_ROUTER_LICENSE_INVALID, "{}", e);
-                                None
-                            }
-                        })
+                let local_fallback =
+                    uplink_config
+                        .fallback_license_path
+                        .clone()
+                        .map(|path| LocalFallback {
+                            path,
+                            parse: |license| Ok(License::from_str(license.trim())?),
+                        });
+                stream_from_uplink_with_fallback::<LicenseQuery, License>(
+                    uplink_config,
+                    local_fallback,
+                )
+                .filter_map(|res| {
+                    future::ready(match res {
+                        Ok(license) => Some(license),
+                        Err(e) => {
+                            tracing::error!(code = APOLLO_ROUTER_LICENSE_INVALID, "{}", e);
+                            None
+                        }
                     })
-                    .boxed()
+                })
+                .boxed()
             }
             LicenseSource::Env => {
                 // EXPERIMENTAL and not subject to semver.
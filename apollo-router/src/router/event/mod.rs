@@ -1,5 +1,7 @@
 mod configuration;
+mod kubernetes;
 mod license;
+mod object_store;
 mod reload;
 mod schema;
 mod shutdown;
@@ -8,7 +10,11 @@ use std::fmt::Debug;
 use std::fmt::Formatter;
 
 pub use configuration::ConfigurationSource;
+pub use kubernetes::KubernetesResource;
 pub use license::LicenseSource;
+pub(crate) use object_store::object_store_url;
+pub(crate) use object_store::s3_object_url;
+pub(crate) use object_store::AwsSigner;
 pub(crate) use reload::ReloadSource;
 pub use schema::SchemaSource;
 pub use shutdown::ShutdownSource;
@@ -0,0 +1,659 @@
+//! Polling of files stored in S3, Google Cloud Storage or Azure Blob Storage.
+//!
+//! Objects are addressed with `s3://<bucket>/<key>`, `gs://<bucket>/<object>` or
+//! `az://<account>/<container>/<blob>` URLs, and credentials come from each provider's standard
+//! chain. The object is only downloaded again when its ETag changes.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use aws_config::default_provider::credentials::DefaultCredentialsChain;
+use aws_config::default_provider::region::DefaultRegionChain;
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sigv4::http_request::sign;
+use aws_sigv4::http_request::PayloadChecksumKind;
+use aws_sigv4::http_request::SignableBody;
+use aws_sigv4::http_request::SignableRequest;
+use aws_sigv4::http_request::SigningSettings;
+use futures::prelude::*;
+use http::header::ETAG;
+use http::header::IF_NONE_MATCH;
+use http::StatusCode;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use url::Url;
+
+const SCHEMES: [&str; 3] = ["s3", "gs", "az"];
+const GCS_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_only";
+const AZURE_STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+const AZURE_STORAGE_VERSION: &str = "2021-08-06";
+const DEFAULT_AWS_REGION: &str = "us-east-1";
+/// Tokens are refreshed a little before they expire.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Return the object store URL if the path uses one of the supported schemes.
+pub(crate) fn object_store_url(path: &Path) -> Option<Url> {
+    let url = Url::parse(path.to_str()?).ok()?;
+    SCHEMES.contains(&url.scheme()).then_some(url)
+}
+
+/// Poll the object, yielding its contents on the first fetch and every time its ETag changes.
+/// Without `watch`, the object is fetched once.
+pub(crate) fn poll(url: Url, watch: bool, period: Duration) -> impl Stream<Item = String> {
+    let client = match ObjectStoreClient::new(&url) {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!(url.full = %url, reason = %err, "invalid object store url");
+            return stream::empty().boxed();
+        }
+    };
+
+    stream::unfold((client, true), move |(mut client, first_call)| async move {
+        if !first_call {
+            if !watch {
+                return None;
+            }
+            tokio::time::sleep(period).await;
+        }
+        let contents = match client.fetch_if_changed().await {
+            Ok(contents) => contents,
+            Err(err) => {
+                tracing::error!(url.full = %client.url, reason = %err, "failed to fetch from object store");
+                None
+            }
+        };
+        Some((contents, (client, false)))
+    })
+    .filter_map(future::ready)
+    .boxed()
+}
+
+struct ObjectStoreClient {
+    url: Url,
+    client: reqwest::Client,
+    provider: Provider,
+    etag: Option<String>,
+}
+
+enum Provider {
+    S3 {
+        bucket: String,
+        key: String,
+        signer: AwsSigner,
+    },
+    Gcs {
+        bucket: String,
+        object: String,
+        token: Option<CachedToken>,
+    },
+    Azure {
+        account: String,
+        container: String,
+        blob: String,
+        token: Option<CachedToken>,
+    },
+}
+
+struct CachedToken {
+    value: String,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<ExpiresIn>,
+}
+
+// Azure managed identity returns the expiry as a string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExpiresIn {
+    Number(u64),
+    String(String),
+}
+
+impl TokenResponse {
+    fn into_cached(self) -> CachedToken {
+        let expires_in = match self.expires_in {
+            Some(ExpiresIn::Number(seconds)) => seconds,
+            Some(ExpiresIn::String(seconds)) => seconds.parse().unwrap_or_default(),
+            None => 0,
+        };
+        CachedToken {
+            value: self.access_token,
+            expires_at: Instant::now() + Duration::from_secs(expires_in)
+                - TOKEN_EXPIRY_MARGIN.min(Duration::from_secs(expires_in)),
+        }
+    }
+}
+
+impl ObjectStoreClient {
+    fn new(url: &Url) -> Result<Self, BoxError> {
+        let host = url
+            .host_str()
+            .ok_or("missing bucket or account")?
+            .to_string();
+        let path = url.path().trim_start_matches('/').to_string();
+        if path.is_empty() {
+            return Err("missing object path".into());
+        }
+        let provider = match url.scheme() {
+            "s3" => Provider::S3 {
+                bucket: host,
+                key: path,
+                signer: AwsSigner::new("s3", None),
+            },
+            "gs" => Provider::Gcs {
+                bucket: host,
+                object: path,
+                token: None,
+            },
+            "az" => {
+                let (container, blob) = path
+                    .split_once('/')
+                    .ok_or("expected az://<account>/<container>/<blob>")?;
+                Provider::Azure {
+                    account: host,
+                    container: container.to_string(),
+                    blob: blob.to_string(),
+                    token: None,
+                }
+            }
+            scheme => return Err(format!("unsupported scheme {scheme}").into()),
+        };
+        Ok(Self {
+            url: url.clone(),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            provider,
+            etag: None,
+        })
+    }
+
+    /// Download the object, unless its ETag is the same as the last download.
+    async fn fetch_if_changed(&mut self) -> Result<Option<String>, BoxError> {
+        let mut request = self.authorized_request().await?;
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+        // Stores may ignore If-None-Match, compare the ETags as well.
+        if etag.is_some() && etag == self.etag {
+            return Ok(None);
+        }
+        let contents = response.text().await?;
+        self.etag = etag;
+        Ok(Some(contents))
+    }
+
+    async fn authorized_request(&mut self) -> Result<reqwest::RequestBuilder, BoxError> {
+        let client = &self.client;
+        match &mut self.provider {
+            Provider::S3 {
+                bucket,
+                key,
+                signer,
+            } => {
+                let region = signer.region().await;
+                let url = s3_object_url(bucket, key, &region)?;
+                signer
+                    .request(client, reqwest::Method::GET, &url, &[], Vec::new())
+                    .await
+            }
+            Provider::Gcs {
+                bucket,
+                object,
+                token,
+            } => {
+                let base = std::env::var("STORAGE_EMULATOR_HOST")
+                    .unwrap_or_else(|_| "https://storage.googleapis.com".to_string());
+                let mut url = Url::parse(&base)?;
+                url.path_segments_mut()
+                    .map_err(|_| "invalid storage host")?
+                    .push(bucket)
+                    .extend(object.split('/'));
+                let request = client.get(url);
+                if !valid(token) {
+                    *token = gcs_token(client).await?;
+                }
+                Ok(match token {
+                    Some(token) => request.bearer_auth(&token.value),
+                    None => request,
+                })
+            }
+            Provider::Azure {
+                account,
+                container,
+                blob,
+                token,
+            } => {
+                let mut url = Url::parse(&format!("https://{account}.blob.core.windows.net"))?;
+                url.path_segments_mut()
+                    .map_err(|_| "invalid account")?
+                    .push(container)
+                    .extend(blob.split('/'));
+                // A SAS token is used as is, otherwise an Entra ID token is requested.
+                if let Ok(sas) = std::env::var("AZURE_STORAGE_SAS_TOKEN") {
+                    url.set_query(Some(sas.trim_start_matches('?')));
+                    return Ok(client.get(url));
+                }
+                if !valid(token) {
+                    *token = Some(azure_token(client).await?);
+                }
+                let request = client
+                    .get(url)
+                    .header("x-ms-version", AZURE_STORAGE_VERSION);
+                Ok(match token {
+                    Some(token) => request.bearer_auth(&token.value),
+                    None => request,
+                })
+            }
+        }
+    }
+}
+
+/// Signs the requests to an AWS service with SigV4, with the credentials of the environment
+pub(crate) struct AwsSigner {
+    service: &'static str,
+    region: Option<String>,
+    credentials: Option<DefaultCredentialsChain>,
+}
+
+impl AwsSigner {
+    /// A signer for a service, like `s3`, in the configured region or in the region of the
+    /// environment
+    pub(crate) fn new(service: &'static str, region: Option<String>) -> Self {
+        Self {
+            service,
+            region,
+            credentials: None,
+        }
+    }
+
+    /// The configured region, or the region of the environment
+    pub(crate) async fn region(&mut self) -> String {
+        if self.region.is_none() {
+            self.region = Some(
+                DefaultRegionChain::builder()
+                    .build()
+                    .region()
+                    .await
+                    .map(|region| region.to_string())
+                    .unwrap_or_else(|| DEFAULT_AWS_REGION.to_string()),
+            );
+        }
+        self.region.clone().unwrap_or_default()
+    }
+
+    /// A signed request, with its headers and body
+    pub(crate) async fn request(
+        &mut self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        url: &Url,
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<reqwest::RequestBuilder, BoxError> {
+        let region = self.region().await;
+        if self.credentials.is_none() {
+            self.credentials = Some(DefaultCredentialsChain::builder().build().await);
+        }
+        let identity = self
+            .credentials
+            .as_ref()
+            .expect("credentials chain was just built")
+            .provide_credentials()
+            .await?
+            .into();
+        let mut settings = SigningSettings::default();
+        // S3 requires the hash of the payload in a header
+        if self.service == "s3" {
+            settings.payload_checksum_kind = PayloadChecksumKind::XAmzSha256;
+        }
+        let signing_params = aws_sigv4::sign::v4::SigningParams::builder()
+            .identity(&identity)
+            .region(&region)
+            .name(self.service)
+            .time(SystemTime::now())
+            .settings(settings)
+            .build()?;
+        let signable_request = SignableRequest::new(
+            method.as_str(),
+            url.as_str(),
+            headers.iter().copied(),
+            SignableBody::Bytes(&body),
+        )?;
+        let (instructions, _signature) =
+            sign(signable_request, &signing_params.into())?.into_parts();
+
+        let mut request = client.request(method, url.as_str());
+        for (name, value) in headers.iter().copied().chain(instructions.headers()) {
+            request = request.header(name, value);
+        }
+        Ok(request.body(body))
+    }
+}
+
+fn valid(token: &Option<CachedToken>) -> bool {
+    token
+        .as_ref()
+        .is_some_and(|token| token.expires_at > Instant::now())
+}
+
+/// The URL of an S3 object, or of its bucket with an empty key.
+pub(crate) fn s3_object_url(bucket: &str, key: &str, region: &str) -> Result<Url, BoxError> {
+    // A custom endpoint, for S3 compatible stores, is addressed in path style.
+    let mut url =
+        match std::env::var("AWS_ENDPOINT_URL_S3").or_else(|_| std::env::var("AWS_ENDPOINT_URL")) {
+            Ok(endpoint) => {
+                let mut url = Url::parse(&endpoint)?;
+                url.path_segments_mut()
+                    .map_err(|_| "invalid endpoint")?
+                    .pop_if_empty()
+                    .push(bucket);
+                url
+            }
+            Err(_) => Url::parse(&format!("https://{bucket}.s3.{region}.amazonaws.com"))?,
+        };
+    url.path_segments_mut()
+        .map_err(|_| "invalid endpoint")?
+        .pop_if_empty()
+        .extend(key.split('/'));
+    Ok(url)
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GoogleCredentials {
+    ServiceAccount {
+        client_email: String,
+        private_key: String,
+        token_uri: String,
+    },
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+    },
+}
+
+#[derive(Serialize)]
+struct GoogleClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+/// Application default credentials: the credentials file, then the metadata server. Public
+/// objects may still be read without any credentials.
+async fn gcs_token(client: &reqwest::Client) -> Result<Option<CachedToken>, BoxError> {
+    let path = std::env::var("GOOGLE_APPLICATION_CREDENTIALS")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| {
+            std::env::var("HOME").ok().map(|home| {
+                PathBuf::from(home).join(".config/gcloud/application_default_credentials.json")
+            })
+        });
+    if let Some(path) = path.filter(|path| path.exists()) {
+        let credentials: GoogleCredentials =
+            serde_json::from_str(&tokio::fs::read_to_string(&path).await?)?;
+        let response = match credentials {
+            GoogleCredentials::ServiceAccount {
+                client_email,
+                private_key,
+                token_uri,
+            } => {
+                let iat = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                let assertion = jsonwebtoken::encode(
+                    &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+                    &GoogleClaims {
+                        iss: &client_email,
+                        scope: GCS_SCOPE,
+                        aud: &token_uri,
+                        iat,
+                        exp: iat + 3600,
+                    },
+                    &jsonwebtoken::EncodingKey::from_rsa_pem(private_key.as_bytes())?,
+                )?;
+                client
+                    .post(&token_uri)
+                    .form(&[
+                        ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                        ("assertion", &assertion),
+                    ])
+                    .send()
+                    .await?
+            }
+            GoogleCredentials::AuthorizedUser {
+                client_id,
+                client_secret,
+                refresh_token,
+            } => {
+                client
+                    .post("https://oauth2.googleapis.com/token")
+                    .form(&[
+                        ("grant_type", "refresh_token"),
+                        ("client_id", &client_id),
+                        ("client_secret", &client_secret),
+                        ("refresh_token", &refresh_token),
+                    ])
+                    .send()
+                    .await?
+            }
+        };
+        let token: TokenResponse = response.error_for_status()?.json().await?;
+        return Ok(Some(token.into_cached()));
+    }
+
+    let host = std::env::var("GCE_METADATA_HOST")
+        .unwrap_or_else(|_| "metadata.google.internal".to_string());
+    let metadata = client
+        .get(format!(
+            "http://{host}/computeMetadata/v1/instance/service-accounts/default/token"
+        ))
+        .header("Metadata-Flavor", "Google")
+        .timeout(Duration::from_secs(3))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    match metadata {
+        Ok(response) => Ok(Some(response.json::<TokenResponse>().await?.into_cached())),
+        Err(err) => {
+            tracing::debug!(reason = %err, "no google cloud credentials found, reading the object anonymously");
+            Ok(None)
+        }
+    }
+}
+
+/// Environment credentials (client secret or workload identity), then the managed identity.
+async fn azure_token(client: &reqwest::Client) -> Result<CachedToken, BoxError> {
+    let tenant_id = std::env::var("AZURE_TENANT_ID").ok();
+    let client_id = std::env::var("AZURE_CLIENT_ID").ok();
+    if let (Some(tenant_id), Some(client_id)) = (&tenant_id, &client_id) {
+        let authority = std::env::var("AZURE_AUTHORITY_HOST")
+            .unwrap_or_else(|_| "https://login.microsoftonline.com".to_string());
+        let scope = format!("{AZURE_STORAGE_RESOURCE}.default");
+        let mut form = vec![
+            ("grant_type", "client_credentials".to_string()),
+            ("client_id", client_id.clone()),
+            ("scope", scope),
+        ];
+        if let Ok(secret) = std::env::var("AZURE_CLIENT_SECRET") {
+            form.push(("client_secret", secret));
+        } else if let Ok(token_file) = std::env::var("AZURE_FEDERATED_TOKEN_FILE") {
+            let assertion = tokio::fs::read_to_string(token_file).await?;
+            form.push((
+                "client_assertion_type",
+                "urn:ietf:params:oauth:client-assertion-type:jwt-bearer".to_string(),
+            ));
+            form.push(("client_assertion", assertion.trim().to_string()));
+        }
+        if form.len() > 3 {
+            let token: TokenResponse = client
+                .post(format!(
+                    "{}/{tenant_id}/oauth2/v2.0/token",
+                    authority.trim_end_matches('/')
+                ))
+                .form(&form)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            return Ok(token.into_cached());
+        }
+    }
+
+    let mut query = vec![
+        ("api-version", "2018-02-01".to_string()),
+        ("resource", AZURE_STORAGE_RESOURCE.to_string()),
+    ];
+    if let Some(client_id) = client_id {
+        query.push(("client_id", client_id));
+    }
+    let token: TokenResponse = client
+        .get("http://169.254.169.254/metadata/identity/oauth2/token")
+        .query(&query)
+        .header("Metadata", "true")
+        .timeout(Duration::from_secs(3))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(token.into_cached())
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+
+    /// Sets environment variables until dropped, then restores their previous values
+    struct EnvGuard(Vec<(&'static str, Option<std::ffi::OsString>)>);
+
+    impl EnvGuard {
+        fn set(vars: &[(&'static str, &str)]) -> Self {
+            Self(
+                vars.iter()
+                    .map(|(name, value)| {
+                        let previous = std::env::var_os(name);
+                        std::env::set_var(name, value);
+                        (*name, previous)
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for (name, previous) in self.0.drain(..) {
+                match previous {
+                    Some(value) => std::env::set_var(name, value),
+                    None => std::env::remove_var(name),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn object_store_urls() {
+        assert!(object_store_url(Path::new("s3://bucket/supergraph.graphql")).is_some());
+        assert!(object_store_url(Path::new("gs://bucket/dir/router.yaml")).is_some());
+        assert!(object_store_url(Path::new("az://account/container/router.yaml")).is_some());
+        assert!(object_store_url(Path::new("supergraph.graphql")).is_none());
+        assert!(object_store_url(Path::new("/tmp/supergraph.graphql")).is_none());
+        assert!(object_store_url(Path::new("https://example.com/supergraph.graphql")).is_none());
+    }
+
+    #[test]
+    fn invalid_object_store_urls() {
+        assert!(ObjectStoreClient::new(&Url::parse("s3://bucket").unwrap()).is_err());
+        assert!(ObjectStoreClient::new(&Url::parse("az://account/container").unwrap()).is_err());
+    }
+
+    #[test]
+    fn s3_urls() {
+        assert_eq!(
+            s3_object_url("bucket", "dir/supergraph.graphql", "eu-west-1")
+                .unwrap()
+                .as_str(),
+            "https://bucket.s3.eu-west-1.amazonaws.com/dir/supergraph.graphql"
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_only_when_etag_changes() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/bucket/supergraph.graphql"))
+            .and(header("if-none-match", "\"1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bucket/supergraph.graphql"))
+            .and(header("if-none-match", "\"1\""))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"2\"")
+                    .set_body_string("schema2"),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bucket/supergraph.graphql"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"1\"")
+                    .set_body_string("schema1"),
+            )
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+
+        // The GCS emulator host is read on every request.
+        let _env = EnvGuard::set(&[
+            ("STORAGE_EMULATOR_HOST", &mock_server.uri()),
+            ("GOOGLE_APPLICATION_CREDENTIALS", "/does/not/exist"),
+            ("GCE_METADATA_HOST", "127.0.0.1:1"),
+        ]);
+        let contents = poll(
+            Url::parse("gs://bucket/supergraph.graphql").unwrap(),
+            true,
+            Duration::from_millis(10),
+        )
+        .take(2)
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(contents, vec!["schema1", "schema2"]);
+    }
+}
@@ -8,11 +8,16 @@ use derive_more::From;
 use futures::prelude::*;
 use url::Url;
 
+use super::kubernetes;
+use super::kubernetes::ApiServer;
+use super::kubernetes::KubernetesResource;
+use super::object_store;
 use crate::router::Event;
 use crate::router::Event::NoMoreSchema;
 use crate::router::Event::UpdateSchema;
 use crate::uplink::schema_stream::SupergraphSdlQuery;
-use crate::uplink::stream_from_uplink;
+use crate::uplink::stream_from_uplink_with_fallback;
+use crate::uplink::LocalFallback;
 use crate::uplink::UplinkConfig;
 
 type SchemaStream = Pin<Box<dyn Stream<Item = String> + Send>>;
@@ -59,6 +64,24 @@ pub enum SchemaSource {
         /// When watching, the delay to wait between each poll.
         period: Duration,
     },
+
+    /// A file in S3, Google Cloud Storage or Azure Blob Storage.
+    #[display(fmt = "ObjectStore")]
+    ObjectStore {
+        /// The `s3://`, `gs://` or `az://` URL of the schema.
+        url: Url,
+        /// `true` to poll the object for changes and hot apply them.
+        watch: bool,
+        /// When watching, the delay to wait between each poll.
+        period: Duration,
+    },
+
+    /// A Kubernetes ConfigMap or custom resource, watched through the API server.
+    #[display(fmt = "Kubernetes")]
+    Kubernetes {
+        /// The resource holding the schema.
+        resource: KubernetesResource,
+    },
 }
 
 impl From<&'_ str> for SchemaSource {
@@ -121,17 +144,28 @@ impl SchemaSource {
                 }
             }
             SchemaSource::Registry(uplink_config) => {
-                stream_from_uplink::<SupergraphSdlQuery, String>(uplink_config)
-                    .filter_map(|res| {
-                        future::ready(match res {
-                            Ok(schema) => Some(UpdateSchema(schema)),
-                            Err(e) => {
-                                tracing::error!("{}", e);
-                                None
-                            }
-                        })
+                let local_fallback =
+                    uplink_config
+                        .fallback_supergraph_path
+                        .clone()
+                        .map(|path| LocalFallback {
+                            path,
+                            parse: |schema| Ok(schema.to_string()),
+                        });
+                stream_from_uplink_with_fallback::<SupergraphSdlQuery, String>(
+                    uplink_config,
+                    local_fallback,
+                )
+                .filter_map(|res| {
+                    future::ready(match res {
+                        Ok(schema) => Some(UpdateSchema(schema)),
+                        Err(e) => {
+                            tracing::error!("{}", e);
+                            None
+                        }
                     })
-                    .boxed()
+                })
+                .boxed()
             }
             SchemaSource::URLs {
                 urls,
@@ -172,6 +206,16 @@ impl SchemaSource {
                     .boxed()
                 }
             }
+            SchemaSource::ObjectStore { url, watch, period } => {
+                object_store::poll(url, watch, period).map(UpdateSchema).boxed()
+            }
+            SchemaSource::Kubernetes { resource } => match ApiServer::in_cluster() {
+                Ok(api_server) => kubernetes::watch(api_server, resource).boxed(),
+                Err(err) => {
+                    tracing::error!(reason = %err, "failed to connect to the kubernetes api server");
+                    stream::empty().boxed()
+                }
+            },
         }
         .chain(stream::iter(vec![NoMoreSchema]))
         .boxed()
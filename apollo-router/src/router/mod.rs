@@ -10,8 +10,12 @@ use std::task::Context;
 use std::task::Poll;
 
 pub use error::ApolloRouterError;
+pub(crate) use event::object_store_url;
+pub(crate) use event::s3_object_url;
+pub(crate) use event::AwsSigner;
 pub use event::ConfigurationSource;
 pub(crate) use event::Event;
+pub use event::KubernetesResource;
 pub use event::LicenseSource;
 pub(crate) use event::ReloadSource;
 pub use event::SchemaSource;
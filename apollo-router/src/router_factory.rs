@@ -5,6 +5,7 @@ use std::sync::Arc;
 use apollo_compiler::schema::ExtendedType;
 use apollo_compiler::validation::Valid;
 use axum::response::IntoResponse;
+use futures::future::BoxFuture;
 use http::StatusCode;
 use indexmap::IndexMap;
 use multimap::MultiMap;
@@ -26,8 +27,10 @@ use crate::plugin::DynPlugin;
 use crate::plugin::Handler;
 use crate::plugin::PluginFactory;
 use crate::plugin::PluginInit;
+use crate::plugins::progressive_override::inherit_rollout;
 use crate::plugins::subscription::Subscription;
 use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN;
+use crate::plugins::subscription_coordination::inherit_coordination;
 use crate::plugins::telemetry::reload::apollo_opentelemetry_initialized;
 use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::plugins::traffic_shaping::APOLLO_TRAFFIC_SHAPING;
@@ -39,10 +42,13 @@ use crate::services::layers::persisted_queries::PersistedQueryLayer;
 use crate::services::layers::query_analysis::QueryAnalysisLayer;
 use crate::services::new_service::ServiceFactory;
 use crate::services::router;
+use crate::services::router::service::ContractVariantService;
+use crate::services::router::service::DualExecutionService;
 use crate::services::router::service::RouterCreator;
 use crate::services::subgraph;
 use crate::services::transport;
 use crate::services::HasConfig;
+use crate::services::HasPlugins;
 use crate::services::HasSchema;
 use crate::services::PluggableSupergraphServiceBuilder;
 use crate::services::Plugins;
@@ -127,6 +133,12 @@ pub(crate) trait RouterFactory:
     type Future: Send;
 
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
+
+    /// Saves the state warmed up while serving requests, for the next router to restore it at
+    /// startup
+    fn save_warm_state(&self) -> BoxFuture<'_, Result<(), BoxError>> {
+        Box::pin(async { Ok(()) })
+    }
 }
 
 /// Factory for creating a RouterFactory
@@ -286,13 +298,137 @@ impl YamlRouterFactory {
                 )
                 .await;
         };
-        RouterCreator::new(
+
+        let contract_variants = self
+            .inner_create_contract_variants(
+                configuration.clone(),
+                &supergraph_creator,
+                previous_router,
+            )
+            .await?;
+        let dual_execution = self
+            .inner_create_dual_execution(
+                configuration.clone(),
+                supergraph_creator.schema(),
+                previous_router,
+            )
+            .await?;
+
+        Ok(RouterCreator::new(
             query_analysis_layer,
             persisted_query_layer,
             Arc::new(supergraph_creator),
             configuration,
         )
-        .await
+        .await?
+        .with_contract_variants(contract_variants)
+        .with_dual_execution(dual_execution)
+        .with_previous_memory_limits(previous_router))
+    }
+
+    /// Creates the staged supergraph service executing a sample of the queries, when dual
+    /// execution is enabled. It defaults to the active supergraph schema.
+    async fn inner_create_dual_execution<'a>(
+        &'a mut self,
+        configuration: Arc<Configuration>,
+        active_schema: Arc<Schema>,
+        previous_router: Option<&'a RouterCreator>,
+    ) -> Result<Option<DualExecutionService>, BoxError> {
+        let dual_execution = &configuration.dual_execution;
+        if !dual_execution.enabled {
+            return Ok(None);
+        }
+
+        let staged_configuration = Arc::new(dual_execution.staged_configuration(&configuration));
+        let schema = match &dual_execution.supergraph_path {
+            Some(supergraph_path) => {
+                let sdl = tokio::fs::read_to_string(supergraph_path)
+                    .await
+                    .map_err(|e| {
+                        format!(
+                            "could not read the staged supergraph from {}: {e}",
+                            supergraph_path.display()
+                        )
+                    })?;
+                Arc::new(Schema::parse(&sdl, &staged_configuration)?)
+            }
+            None => active_schema,
+        };
+        can_use_with_experimental_query_planner(staged_configuration.clone(), schema.clone())?;
+
+        let previous_supergraph = previous_router
+            .and_then(|router| router.dual_execution.as_ref())
+            .map(|dual_execution| &*dual_execution.supergraph_creator);
+        let supergraph_creator = self
+            .inner_create_supergraph(
+                staged_configuration.clone(),
+                schema,
+                previous_supergraph,
+                None,
+                None,
+            )
+            .await?;
+        let query_analysis_layer =
+            QueryAnalysisLayer::new(supergraph_creator.schema(), staged_configuration).await;
+
+        Ok(Some(DualExecutionService {
+            dual_execution: dual_execution.clone(),
+            supergraph_creator: Arc::new(supergraph_creator),
+            query_analysis_layer,
+        }))
+    }
+
+    /// Creates the supergraph services of every configured contract variant, reusing the
+    /// query planners of the previous router where a variant already existed. Variants share the
+    /// plugins and the subgraph services of the main supergraph.
+    async fn inner_create_contract_variants<'a>(
+        &'a self,
+        configuration: Arc<Configuration>,
+        main_supergraph: &SupergraphCreator,
+        previous_router: Option<&'a RouterCreator>,
+    ) -> Result<HashMap<String, ContractVariantService>, BoxError> {
+        let main_schema = main_supergraph.schema();
+        let mut contract_variants = HashMap::new();
+        for (name, variant) in &configuration.contracts.variants {
+            let sdl = tokio::fs::read_to_string(&variant.supergraph_path)
+                .await
+                .map_err(|e| {
+                    format!(
+                        "could not read the supergraph of contract variant '{name}' from {}: {e}",
+                        variant.supergraph_path.display()
+                    )
+                })?;
+            let schema = Arc::new(Schema::parse(&sdl, &configuration)?);
+            if let Some((subgraph, _)) = schema
+                .subgraphs()
+                .find(|(subgraph, _)| !main_schema.subgraphs().any(|(main, _)| main == *subgraph))
+            {
+                return Err(format!(
+                    "contract variant '{name}' uses subgraph '{subgraph}', which is not part of the supergraph"
+                )
+                .into());
+            }
+            let previous_supergraph = previous_router
+                .and_then(|router| router.contract_variants.get(name))
+                .map(|variant| &*variant.supergraph_creator);
+
+            let planner =
+                create_query_planner_pool(configuration.clone(), schema, previous_supergraph)
+                    .await?;
+            let supergraph_creator = main_supergraph.contract_variant(planner).await?;
+            let query_analysis_layer =
+                QueryAnalysisLayer::new(supergraph_creator.schema(), Arc::clone(&configuration))
+                    .await;
+
+            contract_variants.insert(
+                name.clone(),
+                ContractVariantService {
+                    supergraph_creator: Arc::new(supergraph_creator),
+                    query_analysis_layer,
+                },
+            );
+        }
+        Ok(contract_variants)
     }
 
     pub(crate) async fn inner_create_supergraph<'a>(
@@ -303,36 +439,9 @@ impl YamlRouterFactory {
         initial_telemetry_plugin: Option<Box<dyn DynPlugin>>,
         extra_plugins: Option<Vec<(String, Box<dyn DynPlugin>)>>,
     ) -> Result<SupergraphCreator, BoxError> {
-        let query_planner_span = tracing::info_span!("query_planner_creation");
-        // QueryPlannerService takes an UnplannedRequest and outputs PlannedRequest
         let bridge_query_planner =
-            match previous_supergraph.as_ref().map(|router| router.planners()) {
-                None => {
-                    BridgeQueryPlannerPool::new(
-                        schema.clone(),
-                        configuration.clone(),
-                        configuration
-                            .supergraph
-                            .query_planning
-                            .experimental_query_planner_parallelism()?,
-                    )
-                    .instrument(query_planner_span)
-                    .await?
-                }
-                Some(planners) => {
-                    BridgeQueryPlannerPool::new_from_planners(
-                        planners,
-                        schema.clone(),
-                        configuration.clone(),
-                        configuration
-                            .supergraph
-                            .query_planning
-                            .experimental_query_planner_parallelism()?,
-                    )
-                    .instrument(query_planner_span)
-                    .await?
-                }
-            };
+            create_query_planner_pool(configuration.clone(), schema.clone(), previous_supergraph)
+                .await?;
 
         let schema_changed = previous_supergraph
             .map(|supergraph_creator| supergraph_creator.schema().raw_sdl == schema.raw_sdl)
@@ -374,6 +483,10 @@ impl YamlRouterFactory {
             .into_iter()
             .collect(),
         );
+        if let Some(previous_supergraph) = previous_supergraph {
+            inherit_rollout(&previous_supergraph.plugins(), &plugins);
+            inherit_coordination(&previous_supergraph.plugins(), &plugins).await;
+        }
 
         async {
             let mut builder = PluggableSupergraphServiceBuilder::new(bridge_query_planner);
@@ -394,6 +507,33 @@ impl YamlRouterFactory {
     }
 }
 
+/// Creates the query planners of a schema, reusing the planners of the previous supergraph
+async fn create_query_planner_pool(
+    configuration: Arc<Configuration>,
+    schema: Arc<Schema>,
+    previous_supergraph: Option<&SupergraphCreator>,
+) -> Result<BridgeQueryPlannerPool, BoxError> {
+    let query_planner_span = tracing::info_span!("query_planner_creation");
+    let parallelism = configuration
+        .supergraph
+        .query_planning
+        .experimental_query_planner_parallelism()?;
+    // QueryPlannerService takes an UnplannedRequest and outputs PlannedRequest
+    let bridge_query_planner = match previous_supergraph.map(|router| router.planners()) {
+        None => {
+            BridgeQueryPlannerPool::new(schema, configuration, parallelism)
+                .instrument(query_planner_span)
+                .await?
+        }
+        Some(planners) => {
+            BridgeQueryPlannerPool::new_from_planners(planners, schema, configuration, parallelism)
+                .instrument(query_planner_span)
+                .await?
+        }
+    };
+    Ok(bridge_query_planner)
+}
+
 pub(crate) async fn create_subgraph_services(
     plugins: &Arc<Plugins>,
     schema: &Schema,
@@ -442,6 +582,8 @@ pub(crate) async fn create_subgraph_services(
             configuration,
             &tls_root_store,
             shaping.enable_subgraph_http2(name),
+            shaping.subgraph_http2_keep_alive(name),
+            shaping.subgraph_connect_timeout(name),
         )?;
 
         let http_service_factory = HttpClientServiceFactory::new(http_service, plugins.clone());
@@ -540,6 +682,8 @@ pub(crate) async fn add_plugin(
     schema: Arc<String>,
     supergraph_schema: Arc<Valid<apollo_compiler::Schema>>,
     subgraph_schemas: Arc<HashMap<String, Arc<Valid<apollo_compiler::Schema>>>>,
+    full_config: Arc<Configuration>,
+    router_schema: Arc<Schema>,
     notify: &crate::notification::Notify<String, crate::graphql::Response>,
     plugin_instances: &mut Plugins,
     errors: &mut Vec<ConfigurationError>,
@@ -552,6 +696,8 @@ pub(crate) async fn add_plugin(
                 .supergraph_schema(supergraph_schema)
                 .subgraph_schemas(subgraph_schemas)
                 .notify(notify.clone())
+                .full_config(full_config)
+                .router_schema(router_schema)
                 .build(),
         )
         .await
@@ -568,12 +714,13 @@ pub(crate) async fn add_plugin(
 
 pub(crate) async fn create_plugins(
     configuration: &Configuration,
-    schema: &Schema,
+    schema: &Arc<Schema>,
     subgraph_schemas: Arc<HashMap<String, Arc<Valid<apollo_compiler::Schema>>>>,
     initial_telemetry_plugin: Option<Box<dyn DynPlugin>>,
     extra_plugins: Option<Vec<(String, Box<dyn DynPlugin>)>>,
 ) -> Result<Plugins, BoxError> {
     let supergraph_schema = Arc::new(schema.supergraph_schema().clone());
+    let full_config = Arc::new(configuration.clone());
     let mut apollo_plugins_config = configuration.apollo_plugins.clone().plugins;
     let user_plugins_config = configuration.plugins.clone().plugins.unwrap_or_default();
     let extra = extra_plugins.unwrap_or_default();
@@ -606,6 +753,8 @@ pub(crate) async fn create_plugins(
                 schema.as_string().clone(),
                 supergraph_schema.clone(),
                 subgraph_schemas.clone(),
+                full_config.clone(),
+                schema.clone(),
                 &configuration.notify.clone(),
                 &mut plugin_instances,
                 &mut errors,
@@ -701,18 +850,51 @@ pub(crate) async fn create_plugins(
     add_optional_apollo_plugin!("forbid_mutations");
     add_optional_apollo_plugin!("subscription");
     add_optional_apollo_plugin!("override_subgraph_url");
+    add_optional_apollo_plugin!("subgraph_failover");
+    add_optional_apollo_plugin!("data_residency");
+    add_optional_apollo_plugin!("subgraph_transforms");
+    add_optional_apollo_plugin!("subgraph_capabilities");
+    add_optional_apollo_plugin!("deprecations");
+    add_optional_apollo_plugin!("experimental_field_usage");
+    add_optional_apollo_plugin!("field_encryption");
+    add_optional_apollo_plugin!("response_validation");
+    add_optional_apollo_plugin!("unknown_typenames");
+    add_optional_apollo_plugin!("feature_flags");
+    add_optional_apollo_plugin!("wide_events");
+    add_optional_apollo_plugin!("slow_operations");
+    add_optional_apollo_plugin!("request_id");
+    add_optional_apollo_plugin!("static_responses");
+    add_optional_apollo_plugin!("client_compatibility");
+    add_optional_apollo_plugin!("field_latency");
+    add_optional_apollo_plugin!("context_arguments");
+    add_optional_apollo_plugin!("error_aggregation");
+    add_optional_apollo_plugin!("null_provenance");
+    add_optional_apollo_plugin!("error_messages");
+    add_optional_apollo_plugin!("fetch_details");
+    add_optional_apollo_plugin!("query_plan_summary");
+    add_optional_apollo_plugin!("profiling");
     add_optional_apollo_plugin!("authorization");
     add_optional_apollo_plugin!("authentication");
     add_optional_apollo_plugin!("preview_file_uploads");
     add_optional_apollo_plugin!("preview_entity_cache");
+    add_optional_apollo_plugin!("preview_response_cache");
     add_mandatory_apollo_plugin!("progressive_override");
 
     // This relative ordering is documented in `docs/source/customizations/native.mdx`:
     add_optional_apollo_plugin!("rhai");
     add_optional_apollo_plugin!("coprocessor");
     add_optional_apollo_plugin!("preview_demand_control");
+    add_optional_apollo_plugin!("preflight");
     add_user_plugins!();
 
+    let plugin_instances = match configuration.plugin_execution.apply(plugin_instances) {
+        Ok(plugin_instances) => plugin_instances,
+        Err(execution_errors) => {
+            errors.extend(execution_errors);
+            Plugins::default()
+        }
+    };
+
     // Macros above remove from `apollo_plugin_factories`, so anything left at the end
     // indicates a missing macro call.
     let unused_apollo_plugin_names = apollo_plugin_factories.keys().copied().collect::<Vec<_>>();
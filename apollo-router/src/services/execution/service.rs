@@ -41,6 +41,7 @@ use crate::json_ext::Path;
 use crate::json_ext::PathElement;
 use crate::json_ext::ValueExt;
 use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::null_provenance::RecordNullProvenance;
 use crate::plugins::subscription::Subscription;
 use crate::plugins::subscription::SubscriptionConfig;
 use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN;
@@ -297,6 +298,10 @@ impl ExecutionService {
         let has_next = response.has_next.unwrap_or(true);
         let variables_set = query.defer_variables_set(operation_name, variables);
 
+        let null_provenance = context
+            .extensions()
+            .with_lock(|lock| lock.contains_key::<RecordNullProvenance>());
+
         tracing::debug_span!("format_response").in_scope(|| {
             let mut paths = Vec::new();
             if !query.unauthorized.paths.is_empty() {
@@ -334,8 +339,9 @@ impl ExecutionService {
                     &mut response,
                     operation_name,
                     variables.clone(),
-                    schema.api_schema(),
+                    &schema,
                     variables_set,
+                    null_provenance,
                 );
             }
 
@@ -345,8 +351,9 @@ impl ExecutionService {
                         &mut response,
                         operation_name,
                         variables.clone(),
-                        schema.api_schema(),
+                        &schema,
                         variables_set,
+                        null_provenance,
                     )
                     ,
             );
@@ -55,6 +55,8 @@ impl HttpClientServiceFactory {
             configuration,
             &rustls::RootCertStore::empty(),
             http2,
+            None,
+            None,
         )
         .unwrap();
 
@@ -7,12 +7,13 @@ use ::serde::Deserialize;
 use bytes::Bytes;
 use futures::future::BoxFuture;
 use futures::Stream;
-use futures::TryFutureExt;
+use futures::StreamExt;
 use global::get_text_map_propagator;
 use http::header::ACCEPT_ENCODING;
 use http::header::CONTENT_ENCODING;
 use http::HeaderValue;
 use http::Request;
+use http::StatusCode;
 use hyper::client::HttpConnector;
 use hyper_rustls::HttpsConnector;
 #[cfg(unix)]
@@ -34,6 +35,7 @@ use tracing::Instrument;
 use super::HttpRequest;
 use super::HttpResponse;
 use crate::axum_factory::compression::Compressor;
+use crate::configuration::spiffe;
 use crate::configuration::TlsClientAuth;
 use crate::error::FetchError;
 use crate::plugins::authentication::subgraph::SigningParamsConfig;
@@ -42,7 +44,9 @@ use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
 use crate::plugins::telemetry::reload::prepare_context;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::telemetry::LOGGING_DISPLAY_HEADERS;
+use crate::plugins::traffic_shaping::timeout::RequestTimeouts;
 use crate::plugins::traffic_shaping::Http2Config;
+use crate::plugins::traffic_shaping::Http2KeepAlive;
 use crate::services::router::body::RouterBody;
 use crate::services::trust_dns_connector::new_async_http_connector;
 use crate::services::trust_dns_connector::AsyncHyperResolver;
@@ -60,7 +64,7 @@ type MixedClient = HTTPClient;
 
 // interior mutability is not a concern here, the value is never modified
 #[allow(clippy::declare_interior_mutable_const)]
-static ACCEPTED_ENCODINGS: HeaderValue = HeaderValue::from_static("gzip, br, deflate");
+static ACCEPTED_ENCODINGS: HeaderValue = HeaderValue::from_static("gzip, br, deflate, zstd");
 const POOL_IDLE_TIMEOUT_DURATION: Option<Duration> = Some(Duration::from_secs(5));
 
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema, Copy)]
@@ -72,6 +76,8 @@ pub(crate) enum Compression {
     Deflate,
     /// brotli
     Br,
+    /// zstd
+    Zstd,
     /// identity
     Identity,
 }
@@ -82,6 +88,7 @@ impl Display for Compression {
             Compression::Gzip => write!(f, "gzip"),
             Compression::Deflate => write!(f, "deflate"),
             Compression::Br => write!(f, "br"),
+            Compression::Zstd => write!(f, "zstd"),
             Compression::Identity => write!(f, "identity"),
         }
     }
@@ -104,8 +111,20 @@ impl HttpClientService {
         configuration: &Configuration,
         tls_root_store: &RootCertStore,
         http2: Http2Config,
+        http2_keep_alive: Option<Http2KeepAlive>,
+        connect_timeout: Option<Duration>,
     ) -> Result<Self, BoxError> {
         let name: String = service.into();
+        if configuration.tls.subgraph.get(&name).spiffe {
+            return HttpClientService::with_http2_keep_alive(
+                name,
+                http2,
+                http2_keep_alive,
+                connect_timeout,
+                spiffe::client_config()?,
+            );
+        }
+
         let tls_cert_store = configuration
             .tls
             .subgraph
@@ -131,15 +150,32 @@ impl HttpClientService {
 
         let tls_client_config = generate_tls_client_config(tls_cert_store, client_cert_config)?;
 
-        HttpClientService::new(name, http2, tls_client_config)
+        HttpClientService::with_http2_keep_alive(
+            name,
+            http2,
+            http2_keep_alive,
+            connect_timeout,
+            tls_client_config,
+        )
     }
 
     pub(crate) fn new(
         service: impl Into<String>,
         http2: Http2Config,
         tls_config: ClientConfig,
+    ) -> Result<Self, BoxError> {
+        HttpClientService::with_http2_keep_alive(service, http2, None, None, tls_config)
+    }
+
+    pub(crate) fn with_http2_keep_alive(
+        service: impl Into<String>,
+        http2: Http2Config,
+        http2_keep_alive: Option<Http2KeepAlive>,
+        connect_timeout: Option<Duration>,
+        tls_config: ClientConfig,
     ) -> Result<Self, BoxError> {
         let mut http_connector = new_async_http_connector()?;
+        http_connector.set_connect_timeout(connect_timeout);
         http_connector.set_nodelay(true);
         http_connector.set_keepalive(Some(std::time::Duration::from_secs(60)));
         http_connector.enforce_http(false);
@@ -155,10 +191,20 @@ impl HttpClientService {
             builder.wrap_connector(http_connector)
         };
 
-        let http_client = hyper::Client::builder()
+        let mut builder = hyper::Client::builder();
+        builder
             .pool_idle_timeout(POOL_IDLE_TIMEOUT_DURATION)
-            .http2_only(http2 == Http2Config::Http2Only)
-            .build(connector);
+            .http2_only(http2 == Http2Config::Http2Only);
+        if let Some(keep_alive) = http2_keep_alive {
+            // connections whose PING frames are not acknowledged are closed, and evicted from the pool
+            builder
+                .http2_keep_alive_interval(keep_alive.interval)
+                .http2_keep_alive_while_idle(keep_alive.while_idle.unwrap_or(true));
+            if let Some(timeout) = keep_alive.timeout {
+                builder.http2_keep_alive_timeout(timeout);
+            }
+        }
+        let http_client = builder.build(connector);
         Ok(Self {
             http_client: ServiceBuilder::new()
                 .layer(DecompressionLayer::new())
@@ -285,7 +331,23 @@ impl tower::Service<HttpRequest> for HttpClientService {
 
         let body = match opt_compressor {
             None => body,
-            Some(compressor) => RouterBody::wrap_stream(compressor.process(body)),
+            Some(compressor) => {
+                let content_encoding = compressor.content_encoding();
+                let uncompressed = body.inspect(count_body_bytes(
+                    service_name.clone(),
+                    content_encoding,
+                    false,
+                ));
+                RouterBody::wrap_stream(
+                    compressor
+                        .process(RouterBody::wrap_stream(uncompressed))
+                        .inspect(count_body_bytes(
+                            service_name.clone(),
+                            content_encoding,
+                            true,
+                        )),
+                )
+            }
         };
         let mut http_request = http::Request::from_parts(parts, body);
 
@@ -298,10 +360,12 @@ impl tower::Service<HttpRequest> for HttpClientService {
             .with_lock(|lock| lock.get::<Arc<SigningParamsConfig>>().cloned());
 
         Box::pin(async move {
-            let http_request = if let Some(signing_params) = signing_params {
-                signing_params.sign(http_request, &service_name).await?
+            let (http_request, fallback_request) = if let Some(signing_params) = signing_params {
+                signing_params
+                    .sign_with_fallback(http_request, &service_name)
+                    .await?
             } else {
-                http_request
+                (http_request, None)
             };
 
             let display_headers = context.contains_key(LOGGING_DISPLAY_HEADERS);
@@ -315,9 +379,33 @@ impl tower::Service<HttpRequest> for HttpClientService {
                 tracing::info!(http.request.body = ?http_request.body(), apollo.subgraph.name = %service_name, "Request body to subgraph {service_name:?}");
             }
 
-            let http_response = do_fetch(client, &context, &service_name, http_request)
-                .instrument(http_req_span)
+            let mut http_response = do_fetch(client.clone(), &context, &service_name, http_request)
+                .instrument(http_req_span.clone())
                 .await?;
+            if let Some(fallback_request) =
+                fallback_request.filter(|_| http_response.status() == StatusCode::UNAUTHORIZED)
+            {
+                // the subgraph may not accept the current key yet during a rollover
+                http_response = do_fetch(client, &context, &service_name, fallback_request)
+                    .instrument(http_req_span)
+                    .await?;
+            }
+
+            // supported encodings are decoded, and their header removed, by the decompression layer
+            if let Some(content_encoding) = http_response
+                .headers()
+                .get(CONTENT_ENCODING)
+                .filter(|value| value.as_bytes() != b"identity")
+            {
+                return Err(FetchError::SubrequestHttpError {
+                    status_code: Some(http_response.status().as_u16()),
+                    service: service_name.to_string(),
+                    reason: format!(
+                        "the subgraph response is encoded with {content_encoding:?}, which is not one of the accepted encodings {ACCEPTED_ENCODINGS:?}"
+                    ),
+                }
+                .into());
+            }
 
             // Print out the debug for the response
             if display_headers {
@@ -332,6 +420,45 @@ impl tower::Service<HttpRequest> for HttpClientService {
     }
 }
 
+/// Returns a callback counting the bytes of a subgraph request body, before or after its compression.
+fn count_body_bytes<E>(
+    service_name: Arc<String>,
+    content_encoding: &'static str,
+    compressed: bool,
+) -> impl Fn(&Result<Bytes, E>) {
+    move |chunk| {
+        if let Ok(chunk) = chunk {
+            u64_counter!(
+                "apollo.router.operations.subgraph.request.body.bytes",
+                "Size of the subgraph request bodies sent with a content encoding, before and after their compression",
+                chunk.len() as u64,
+                "subgraph.name" = service_name.to_string(),
+                "http.request.content_encoding" = content_encoding,
+                "body.compressed" = compressed
+            );
+        }
+    }
+}
+
+/// Whether the connection to the subgraph timed out, with the connect timeout or the timeout of the
+/// operating system
+fn is_connect_timeout(err: &(dyn std::error::Error + 'static)) -> bool {
+    let is_connect = err
+        .downcast_ref::<hyper::Error>()
+        .is_some_and(hyper::Error::is_connect);
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::TimedOut)
+        {
+            return is_connect;
+        }
+        source = err.source();
+    }
+    false
+}
+
 async fn do_fetch(
     mut client: MixedClient,
     context: &Context,
@@ -339,17 +466,35 @@ async fn do_fetch(
     request: Request<RouterBody>,
 ) -> Result<http::Response<RouterBody>, FetchError> {
     let _active_request_guard = context.enter_active_request();
-    let (parts, body) = client
-        .call(request)
+    let first_byte_timeout = request
+        .extensions()
+        .get::<RequestTimeouts>()
+        .and_then(|timeouts| timeouts.first_byte);
+    let response = client.call(request);
+    let response = match first_byte_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, response).await.map_err(|_| {
+            FetchError::SubrequestFirstByteTimeout {
+                service: service_name.to_string(),
+            }
+        })?,
+        None => response.await,
+    };
+    let (parts, body) = response
         .map_err(|err| {
             tracing::error!(fetch_error = ?err);
-            FetchError::SubrequestHttpError {
-                status_code: None,
-                service: service_name.to_string(),
-                reason: err.to_string(),
+            let err: BoxError = err.into();
+            if is_connect_timeout(&*err) {
+                FetchError::SubrequestConnectTimeout {
+                    service: service_name.to_string(),
+                }
+            } else {
+                FetchError::SubrequestHttpError {
+                    status_code: None,
+                    service: service_name.to_string(),
+                    reason: err.to_string(),
+                }
             }
-        })
-        .await?
+        })?
         .into_parts();
     Ok(http::Response::from_parts(
         parts,
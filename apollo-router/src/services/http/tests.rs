@@ -5,6 +5,7 @@ use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_compression::tokio::write::GzipDecoder;
 use async_compression::tokio::write::GzipEncoder;
@@ -38,9 +39,11 @@ use crate::configuration::load_certs;
 use crate::configuration::load_key;
 use crate::configuration::TlsClient;
 use crate::configuration::TlsClientAuth;
+use crate::error::FetchError;
 use crate::graphql::Response;
 use crate::plugin::PluginInit;
 use crate::plugin::PluginPrivate;
+use crate::plugins::traffic_shaping::timeout::RequestTimeouts;
 use crate::plugins::traffic_shaping::Http2Config;
 use crate::services::http::HttpClientService;
 use crate::services::http::HttpRequest;
@@ -110,6 +113,7 @@ async fn tls_self_signed() {
         TlsClient {
             certificate_authorities: Some(certificate_pem.into()),
             client_authentication: None,
+            spiffe: false,
         },
     );
     let subgraph_service = HttpClientService::from_config(
@@ -117,6 +121,8 @@ async fn tls_self_signed() {
         &config,
         &rustls::RootCertStore::empty(),
         Http2Config::Enable,
+        None,
+        None,
     )
     .unwrap();
 
@@ -167,6 +173,7 @@ async fn tls_custom_root() {
         TlsClient {
             certificate_authorities: Some(ca_pem.into()),
             client_authentication: None,
+            spiffe: false,
         },
     );
     let subgraph_service = HttpClientService::from_config(
@@ -174,6 +181,8 @@ async fn tls_custom_root() {
         &config,
         &rustls::RootCertStore::empty(),
         Http2Config::Enable,
+        None,
+        None,
     )
     .unwrap();
 
@@ -277,6 +286,7 @@ async fn tls_client_auth() {
                 certificate_chain: client_certificates,
                 key: client_key,
             }),
+            spiffe: false,
         },
     );
     let subgraph_service = HttpClientService::from_config(
@@ -284,6 +294,8 @@ async fn tls_client_auth() {
         &config,
         &rustls::RootCertStore::empty(),
         Http2Config::Enable,
+        None,
+        None,
     )
     .unwrap();
 
@@ -374,6 +386,50 @@ async fn test_subgraph_h2c() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_first_byte_timeout() {
+    // the connections are accepted, but no response is ever sent
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let socket_addr = listener.local_addr().unwrap();
+    tokio::task::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+    let subgraph_service = HttpClientService::new(
+        "test",
+        Http2Config::Disable,
+        rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_native_roots()
+            .with_no_client_auth(),
+    )
+    .expect("can create a HttpService");
+
+    let mut http_request = http::Request::builder()
+        .uri(Uri::from_str(&format!("http://{socket_addr}")).unwrap())
+        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+        .body(r#"{"query":"{ me { name username } }"#.into())
+        .unwrap();
+    http_request.extensions_mut().insert(RequestTimeouts {
+        first_byte: Some(Duration::from_millis(100)),
+        total: Duration::from_secs(30),
+    });
+    let error = subgraph_service
+        .oneshot(HttpRequest {
+            http_request,
+            context: Context::new(),
+        })
+        .await
+        .err()
+        .expect("the request should time out");
+    assert!(matches!(
+        error.downcast_ref::<FetchError>(),
+        Some(FetchError::SubrequestFirstByteTimeout { service }) if service == "test"
+    ));
+}
+
 // starts a local server emulating a subgraph returning compressed response
 async fn emulate_subgraph_compressed_response(listener: TcpListener) {
     async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
@@ -452,6 +508,88 @@ async fn test_compressed_request_response_body() {
     );
 }
 
+// starts a local server emulating a subgraph returning a zstd compressed response, or a response
+// with an encoding the router does not support
+async fn emulate_subgraph_zstd_response(listener: TcpListener, content_encoding: &'static str) {
+    let handle = move |request: http::Request<Body>| async move {
+        let body = get_body_bytes(request.into_body()).await.unwrap();
+        let body = zstd::decode_all(&body[..]).unwrap();
+        assert_eq!(
+            r#"{"query":"{ me { name username } }"#,
+            std::str::from_utf8(&body).unwrap()
+        );
+
+        let original_body = Response {
+            data: Some(Value::String(ByteString::from("test"))),
+            ..Response::default()
+        };
+        let compressed_body =
+            zstd::encode_all(&serde_json::to_vec(&original_body).unwrap()[..], 0).unwrap();
+
+        Ok::<_, Infallible>(
+            http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .header(CONTENT_ENCODING, content_encoding)
+                .status(StatusCode::OK)
+                .body(Body::from(compressed_body))
+                .unwrap(),
+        )
+    };
+
+    let make_svc =
+        make_service_fn(move |_conn| async move { Ok::<_, Infallible>(service_fn(handle)) });
+    let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+    server.await.unwrap();
+}
+
+async fn zstd_request(content_encoding: &'static str) -> Result<String, BoxError> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let socket_addr = listener.local_addr().unwrap();
+    tokio::task::spawn(emulate_subgraph_zstd_response(listener, content_encoding));
+    let subgraph_service = HttpClientService::new(
+        "test",
+        Http2Config::Disable,
+        rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_native_roots()
+            .with_no_client_auth(),
+    )
+    .expect("can create a HttpService");
+
+    let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
+    let response = subgraph_service
+        .oneshot(HttpRequest {
+            http_request: http::Request::builder()
+                .uri(url)
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .header(CONTENT_ENCODING, "zstd")
+                .body(r#"{"query":"{ me { name username } }"#.into())
+                .unwrap(),
+            context: Context::new(),
+        })
+        .await?;
+
+    Ok(String::from_utf8(
+        get_body_bytes(response.http_response.into_parts().1)
+            .await?
+            .to_vec(),
+    )?)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_zstd_request_response_body() {
+    assert_eq!(zstd_request("zstd").await.unwrap(), r#"{"data":"test"}"#);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_unsupported_response_encoding() {
+    let error = zstd_request("compress").await.unwrap_err();
+    assert!(
+        error.to_string().contains("\"compress\""),
+        "unexpected error: {error}"
+    );
+}
+
 const SCHEMA: &str = r#"schema
         @core(feature: "https://specs.apollo.dev/core/v0.1")
         @core(feature: "https://specs.apollo.dev/join/v0.1")
@@ -1,4 +1,5 @@
-//! Prevent mutations if the HTTP method is GET.
+//! Prevent mutations if the HTTP method is GET, or another method without side effects, or if they
+//! are sent from a read-only sandbox.
 //!
 //! See [`Layer`] and [`Service`] for more details.
 
@@ -7,6 +8,7 @@ use std::ops::ControlFlow;
 use apollo_compiler::ast::OperationType;
 use futures::future::BoxFuture;
 use http::header::HeaderName;
+use http::header::ORIGIN;
 use http::HeaderValue;
 use http::Method;
 use http::StatusCode;
@@ -22,9 +24,23 @@ use crate::layers::async_checkpoint::OneShotAsyncCheckpointService;
 use crate::layers::ServiceBuilderExt;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
+use crate::Configuration;
+
+/// Origin of the requests sent by the embedded sandbox
+const SANDBOX_ORIGIN: &str = "https://sandbox.embed.apollographql.com";
 
 #[derive(Default)]
-pub(crate) struct AllowOnlyHttpPostMutationsLayer {}
+pub(crate) struct AllowOnlyHttpPostMutationsLayer {
+    sandbox_read_only: bool,
+}
+
+impl AllowOnlyHttpPostMutationsLayer {
+    pub(crate) fn new(configuration: &Configuration) -> Self {
+        Self {
+            sandbox_read_only: configuration.sandbox.enabled && configuration.sandbox.read_only,
+        }
+    }
+}
 
 impl<S> Layer<S> for AllowOnlyHttpPostMutationsLayer
 where
@@ -41,10 +57,26 @@ where
     >;
 
     fn layer(&self, service: S) -> Self::Service {
+        let sandbox_read_only = self.sandbox_read_only;
         ServiceBuilder::new()
-            .oneshot_checkpoint_async(|req: SupergraphRequest| {
-                Box::pin(async {
-                    if req.supergraph_request.method() == Method::POST {
+            .oneshot_checkpoint_async(move |req: SupergraphRequest| {
+                Box::pin(async move {
+                    // browsers set the origin of the requests, so the sandbox can't hide it
+                    let from_read_only_sandbox = sandbox_read_only
+                        && req
+                            .supergraph_request
+                            .headers()
+                            .get(ORIGIN)
+                            .is_some_and(|origin| origin == SANDBOX_ORIGIN);
+                    // the GraphQL endpoint only accepts GET and POST, the other methods come
+                    // from the REST endpoints
+                    let method = req.supergraph_request.method();
+                    if (method == Method::POST
+                        || method == Method::PUT
+                        || method == Method::PATCH
+                        || method == Method::DELETE)
+                        && !from_read_only_sandbox
+                    {
                         return Ok(ControlFlow::Continue(req));
                     }
 
@@ -91,7 +123,21 @@ where
                             Ok(ControlFlow::Break(res))
                         }
                         Ok(op) => {
-                            if op.operation_type == OperationType::Mutation {
+                            if op.operation_type == OperationType::Mutation
+                                && from_read_only_sandbox
+                            {
+                                let errors = vec![Error::builder()
+                                    .message("Mutations are disabled in the sandbox".to_string())
+                                    .extension_code("MUTATION_FORBIDDEN")
+                                    .build()];
+                                let res = SupergraphResponse::builder()
+                                    .errors(errors)
+                                    .extensions(Object::default())
+                                    .status_code(StatusCode::FORBIDDEN)
+                                    .context(req.context)
+                                    .build()?;
+                                Ok(ControlFlow::Break(res))
+                            } else if op.operation_type == OperationType::Mutation {
                                 let errors = vec![Error::builder()
                                     .message(
                                         "Mutations can only be sent over HTTP POST".to_string(),
@@ -185,6 +231,23 @@ mod forbid_http_get_mutations_tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn it_lets_mutations_of_rest_endpoints_pass_through() {
+        for method in [Method::PUT, Method::PATCH, Method::DELETE] {
+            let mut mock_service = MockSupergraphService::new();
+            mock_service
+                .expect_call()
+                .times(1)
+                .returning(move |_| Ok(SupergraphResponse::fake_builder().build().unwrap()));
+            let mut service_stack = AllowOnlyHttpPostMutationsLayer::default().layer(mock_service);
+
+            let request = create_request(method, OperationKind::Mutation);
+            let services = service_stack.ready().await.unwrap();
+            let response = services.call(request).await.unwrap();
+            assert_eq!(response.response.status(), StatusCode::OK);
+        }
+    }
+
     #[tokio::test]
     async fn it_lets_http_get_queries_pass_through() {
         let mut mock_service = MockSupergraphService::new();
@@ -228,11 +291,8 @@ mod forbid_http_get_mutations_tests {
             Method::GET,
             Method::HEAD,
             Method::OPTIONS,
-            Method::PUT,
-            Method::DELETE,
             Method::TRACE,
             Method::CONNECT,
-            Method::PATCH,
         ]
         .into_iter()
         .map(|method| create_request(method, OperationKind::Mutation));
@@ -253,6 +313,53 @@ mod forbid_http_get_mutations_tests {
         }
     }
 
+    #[tokio::test]
+    async fn it_doesnt_let_mutations_from_a_read_only_sandbox_pass_through() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |_| Ok(SupergraphResponse::fake_builder().build().unwrap()));
+        let mut service_stack = AllowOnlyHttpPostMutationsLayer {
+            sandbox_read_only: true,
+        }
+        .layer(mock_service);
+
+        let mut request = create_request(Method::POST, OperationKind::Mutation);
+        request
+            .supergraph_request
+            .headers_mut()
+            .insert(ORIGIN, HeaderValue::from_static(SANDBOX_ORIGIN));
+        let mut response = service_stack
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, response.response.status());
+        assert_eq!(
+            response.next_response().await.unwrap().errors[0].message,
+            "Mutations are disabled in the sandbox"
+        );
+
+        let mut request = create_request(Method::POST, OperationKind::Query);
+        request
+            .supergraph_request
+            .headers_mut()
+            .insert(ORIGIN, HeaderValue::from_static(SANDBOX_ORIGIN));
+        service_stack
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+    }
+
     fn assert_error_matches(expected_error: &Error, response: Response) {
         assert_eq!(&response.errors[0], expected_error);
     }
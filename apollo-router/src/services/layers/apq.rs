@@ -13,6 +13,8 @@ use sha2::Digest;
 use sha2::Sha256;
 
 use crate::cache::DeduplicatingCache;
+use crate::plugin::cache_key::CacheKeyHooks;
+use crate::plugin::CacheKind;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 
@@ -88,10 +90,11 @@ async fn apq_request(
             if query_matches_hash(query.as_str(), query_hash_bytes.as_slice()) {
                 tracing::trace!("apq: cache insert");
                 let _ = request.context.insert("persisted_query_register", true);
+                let key = redis_key(&query_hash, &request);
                 let query = query.to_owned();
                 let cache = cache.clone();
                 tokio::spawn(async move {
-                    cache.insert(redis_key(&query_hash), query).await;
+                    cache.insert(key, query).await;
                 });
                 Ok(request)
             } else {
@@ -117,7 +120,7 @@ async fn apq_request(
         }
         (Some((apq_hash, _)), _) => {
             if let Ok(cached_query) = cache
-                .get(&redis_key(&apq_hash), |_| Ok(()))
+                .get(&redis_key(&apq_hash, &request), |_| Ok(()))
                 .await
                 .get()
                 .await
@@ -162,8 +165,16 @@ fn query_matches_hash(query: &str, hash: &[u8]) -> bool {
     hash == digest.finalize().as_slice()
 }
 
-fn redis_key(query_hash: &str) -> String {
-    format!("apq:{query_hash}")
+fn redis_key(query_hash: &str, request: &SupergraphRequest) -> String {
+    match CacheKeyHooks::key_component(
+        &request.context,
+        CacheKind::AutomaticPersistedQueries,
+        query_hash,
+        request.supergraph_request.body().operation_name.as_deref(),
+    ) {
+        Some(component) => format!("apq:{query_hash}:{component}"),
+        None => format!("apq:{query_hash}"),
+    }
 }
 
 pub(crate) fn calculate_hash_for_query(query: &str) -> String {
@@ -1,15 +1,17 @@
 use std::ops::ControlFlow;
+use std::sync::Arc;
 
 use http::header::ACCEPT;
 use http::header::CONTENT_TYPE;
 use http::HeaderMap;
+use http::HeaderValue;
 use http::Method;
 use http::StatusCode;
+use mediatype::names::_STAR;
 use mediatype::names::APPLICATION;
 use mediatype::names::JSON;
 use mediatype::names::MIXED;
 use mediatype::names::MULTIPART;
-use mediatype::names::_STAR;
 use mediatype::MediaTypeList;
 use mediatype::ReadParams;
 use mime::APPLICATION_JSON;
@@ -18,9 +20,11 @@ use tower::Layer;
 use tower::Service;
 use tower::ServiceExt;
 
+use crate::configuration::ContentNegotiation;
 use crate::graphql;
 use crate::layers::sync_checkpoint::CheckpointService;
 use crate::layers::ServiceExt as _;
+use crate::plugins::telemetry::CLIENT_NAME;
 use crate::services::router;
 use crate::services::router::service::MULTIPART_DEFER_CONTENT_TYPE_HEADER_VALUE;
 use crate::services::router::service::MULTIPART_SUBSCRIPTION_CONTENT_TYPE_HEADER_VALUE;
@@ -35,9 +39,20 @@ use crate::services::MULTIPART_SUBSCRIPTION_SPEC_PARAMETER;
 use crate::services::MULTIPART_SUBSCRIPTION_SPEC_VALUE;
 
 pub(crate) const GRAPHQL_JSON_RESPONSE_HEADER_VALUE: &str = "application/graphql-response+json";
+pub(crate) const CBOR_RESPONSE_HEADER_VALUE: &str = "application/cbor";
 /// [`Layer`] for Content-Type checks implementation.
 #[derive(Clone, Default)]
-pub(crate) struct RouterLayer {}
+pub(crate) struct RouterLayer {
+    content_negotiation: Arc<ContentNegotiation>,
+}
+
+impl RouterLayer {
+    pub(crate) fn new(content_negotiation: Arc<ContentNegotiation>) -> Self {
+        Self {
+            content_negotiation,
+        }
+    }
+}
 
 impl<S> Layer<S> for RouterLayer
 where
@@ -47,6 +62,7 @@ where
     type Service = CheckpointService<S, router::Request>;
 
     fn layer(&self, service: S) -> Self::Service {
+        let content_negotiation = self.content_negotiation.clone();
         CheckpointService::new(
             move |req| {
                 if req.router_request.method() != Method::GET
@@ -86,12 +102,18 @@ where
                     return Ok(ControlFlow::Break(response.into()));
                 }
 
-                let accepts = parse_accept(req.router_request.headers());
+                let mut accepts = parse_accept(req.router_request.headers());
+                let client_name = req.context.get::<_, String>(CLIENT_NAME).ok().flatten();
+                let (graphql_response_json, cbor) =
+                    content_negotiation.for_client(client_name.as_deref());
+                accepts.graphql_response_json &= graphql_response_json;
+                accepts.cbor &= cbor;
 
                 if accepts.wildcard
                     || accepts.multipart_defer
                     || accepts.multipart_subscription
                     || accepts.json
+                    || accepts.cbor
                 {
                     req.context
                         .extensions()
@@ -143,6 +165,8 @@ where
                 let ClientRequestAccepts {
                     wildcard: accepts_wildcard,
                     json: accepts_json,
+                    graphql_response_json: accepts_graphql_response_json,
+                    cbor: accepts_cbor,
                     multipart_defer: accepts_multipart_defer,
                     multipart_subscription: accepts_multipart_subscription,
                 } = context.extensions().with_lock(|lock| {
@@ -151,7 +175,17 @@ where
                         .unwrap_or_default()
                 });
 
-                if !res.has_next.unwrap_or_default() && (accepts_json || accepts_wildcard) {
+                if !res.has_next.unwrap_or_default() && accepts_cbor {
+                    parts.headers.insert(
+                        CONTENT_TYPE,
+                        HeaderValue::from_static(CBOR_RESPONSE_HEADER_VALUE),
+                    );
+                } else if !res.has_next.unwrap_or_default() && accepts_graphql_response_json {
+                    parts.headers.insert(
+                        CONTENT_TYPE,
+                        HeaderValue::from_static(GRAPHQL_JSON_RESPONSE_HEADER_VALUE),
+                    );
+                } else if !res.has_next.unwrap_or_default() && (accepts_json || accepts_wildcard) {
                     parts
                         .headers
                         .insert(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE.clone());
@@ -201,21 +235,36 @@ fn content_type_is_json(headers: &HeaderMap) -> bool {
 fn parse_accept(headers: &HeaderMap) -> ClientRequestAccepts {
     let mut header_present = false;
     let mut accepts = ClientRequestAccepts::default();
+    // CBOR is only used if the client prefers it to JSON, or likes them as much
+    let mut cbor_quality: f32 = 0.0;
+    let mut json_quality: f32 = 0.0;
     for value in headers.get_all(ACCEPT) {
         header_present = true;
         if let Ok(str) = value.to_str() {
             for result in MediaTypeList::new(str) {
                 if let Ok(mime) = result {
-                    if !accepts.json
-                        && ((mime.ty == APPLICATION && mime.subty == JSON)
-                            || (mime.ty == APPLICATION
-                                && mime.subty.as_str() == "graphql-response"
-                                && mime.suffix == Some(JSON)))
+                    let quality = quality(&mime);
+                    if !accepts.graphql_response_json
+                        && mime.ty == APPLICATION
+                        && mime.subty.as_str() == "graphql-response"
+                        && mime.suffix == Some(JSON)
+                    {
+                        accepts.graphql_response_json = true
+                    }
+                    if mime.ty == APPLICATION && mime.subty.as_str() == "cbor" {
+                        cbor_quality = cbor_quality.max(quality);
+                    }
+                    if (mime.ty == APPLICATION && mime.subty == JSON)
+                        || (mime.ty == APPLICATION
+                            && mime.subty.as_str() == "graphql-response"
+                            && mime.suffix == Some(JSON))
                     {
-                        accepts.json = true
+                        accepts.json = true;
+                        json_quality = json_quality.max(quality);
                     }
-                    if !accepts.wildcard && (mime.ty == _STAR && mime.subty == _STAR) {
-                        accepts.wildcard = true
+                    if mime.ty == _STAR && mime.subty == _STAR {
+                        accepts.wildcard = true;
+                        json_quality = json_quality.max(quality);
                     }
                     if !accepts.multipart_defer && (mime.ty == MULTIPART && mime.subty == MIXED) {
                         let parameter = mediatype::Name::new(MULTIPART_DEFER_SPEC_PARAMETER)
@@ -244,9 +293,18 @@ fn parse_accept(headers: &HeaderMap) -> ClientRequestAccepts {
     if !header_present {
         accepts.json = true
     }
+    accepts.cbor = cbor_quality > 0.0 && cbor_quality >= json_quality;
     accepts
 }
 
+/// The quality value of a media type of the `Accept` header, 1 by default
+fn quality(mime: &mediatype::MediaType) -> f32 {
+    let parameter = mediatype::Name::new("q").expect("valid name");
+    mime.get_param(parameter)
+        .and_then(|value| value.as_str().parse::<f32>().ok())
+        .map_or(1.0, |quality| quality.clamp(0.0, 1.0))
+}
+
 #[cfg(test)]
 mod tests {
     use http::HeaderValue;
@@ -293,5 +351,33 @@ mod tests {
         default_headers.append(ACCEPT, HeaderValue::from_static(MULTIPART_DEFER_ACCEPT));
         let accepts = parse_accept(&default_headers);
         assert!(accepts.multipart_defer);
+        assert!(accepts.graphql_response_json);
+
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        let accepts = parse_accept(&default_headers);
+        assert!(!accepts.graphql_response_json);
+
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(ACCEPT, HeaderValue::from_static(CBOR_RESPONSE_HEADER_VALUE));
+        let accepts = parse_accept(&default_headers);
+        assert!(accepts.cbor);
+        assert!(!accepts.json);
+    }
+
+    #[test]
+    fn it_honours_the_quality_of_cbor() {
+        for (accept, cbor) in [
+            ("application/cbor, application/json", true),
+            ("application/cbor, */*;q=0.8", true),
+            ("application/json, application/cbor;q=0.5", false),
+            ("application/cbor;q=0.5, application/json;q=0.9", false),
+            ("application/cbor;q=0.9, application/json;q=0.5", true),
+            ("application/cbor;q=0, */*", false),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACCEPT, HeaderValue::from_static(accept));
+            assert_eq!(parse_accept(&headers).cbor, cbor, "{accept}");
+        }
     }
 }
@@ -0,0 +1,271 @@
+//! Approximate accounting of the memory used by the requests in flight.
+//!
+//! Each request charges the buffers it holds: its body, or its variables for GET requests, and
+//! the subgraph responses its response is assembled from. A request going over
+//! `limits.request_max_memory_bytes` is rejected, or gets errors instead of the subgraph
+//! responses that don't fit. While the requests in flight go over `limits.memory_watermark_bytes`,
+//! new requests are shed, so that a few large responses can't exhaust the memory of the router.
+
+use std::ops::ControlFlow;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::StatusCode;
+use mime::APPLICATION_JSON;
+use thiserror::Error;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::configuration::Limits;
+use crate::graphql;
+use crate::layers::sync_checkpoint::CheckpointService;
+use crate::services::router;
+use crate::services::router::body::get_body_bytes;
+use crate::services::router::body::RouterBody;
+use crate::Context;
+
+/// Error returned when a request goes over its memory limit
+#[derive(Debug, Error)]
+#[error("the request exceeded its memory limit of {max_bytes} bytes")]
+pub(crate) struct MemoryLimitExceeded {
+    max_bytes: usize,
+}
+
+/// Memory charged by a request, in its context extensions
+#[derive(Clone)]
+pub(crate) struct RequestMemory(Arc<RequestMemoryInner>);
+
+struct RequestMemoryInner {
+    used_bytes: AtomicUsize,
+    max_bytes: Option<usize>,
+    in_flight_bytes: Arc<AtomicUsize>,
+}
+
+impl RequestMemory {
+    fn new(max_bytes: Option<usize>, in_flight_bytes: Arc<AtomicUsize>) -> Self {
+        Self(Arc::new(RequestMemoryInner {
+            used_bytes: AtomicUsize::new(0),
+            max_bytes,
+            in_flight_bytes,
+        }))
+    }
+
+    /// The memory of the request, when memory accounting is enabled
+    pub(crate) fn from_context(context: &Context) -> Option<Self> {
+        context
+            .extensions()
+            .with_lock(|lock| lock.get::<RequestMemory>().cloned())
+    }
+
+    /// Charges a buffer held by the request. The buffer is not charged if it doesn't fit in the
+    /// memory limit of the request
+    pub(crate) fn charge(&self, bytes: usize) -> Result<(), MemoryLimitExceeded> {
+        let used_bytes = self.0.used_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(max_bytes) = self.0.max_bytes {
+            if used_bytes > max_bytes {
+                self.0.used_bytes.fetch_sub(bytes, Ordering::Relaxed);
+                u64_counter!(
+                    "apollo.router.request.memory.rejected",
+                    "Number of requests or subgraph responses rejected because of the memory limits",
+                    1,
+                    "reason" = "request_limit"
+                );
+                return Err(MemoryLimitExceeded { max_bytes });
+            }
+        }
+        self.0.in_flight_bytes.fetch_add(bytes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Bytes the request can still charge
+    fn remaining_bytes(&self) -> usize {
+        self.0.max_bytes.map_or(usize::MAX, |max_bytes| {
+            max_bytes.saturating_sub(self.0.used_bytes.load(Ordering::Relaxed))
+        })
+    }
+}
+
+impl Drop for RequestMemoryInner {
+    fn drop(&mut self) {
+        let used_bytes = *self.used_bytes.get_mut();
+        self.in_flight_bytes
+            .fetch_sub(used_bytes, Ordering::Relaxed);
+        u64_histogram!(
+            "apollo.router.request.memory",
+            "Approximate memory used by the buffers of a request, in bytes",
+            used_bytes as u64
+        );
+    }
+}
+
+/// Reads a body, charging it to the memory of the request. The read stops as soon as the body
+/// doesn't fit in the memory limit of the request
+pub(crate) async fn read_body(
+    body: RouterBody,
+    memory: Option<&RequestMemory>,
+) -> Result<Bytes, BoxError> {
+    let Some(memory) = memory else {
+        return Ok(body.to_bytes().await?);
+    };
+    let max_bytes = memory.0.max_bytes.unwrap_or_default();
+    let bytes = get_body_bytes(http_body::Limited::new(body, memory.remaining_bytes()))
+        .await
+        .map_err(|e| -> BoxError {
+            if e.is::<http_body::LengthLimitError>() {
+                MemoryLimitExceeded { max_bytes }.into()
+            } else {
+                e
+            }
+        })?;
+    memory.charge(bytes.len())?;
+    Ok(bytes)
+}
+
+/// [`Layer`] charging the memory of the requests, and shedding them over the watermark
+#[derive(Clone)]
+pub(crate) struct MemoryLimitsLayer {
+    request_max_bytes: Option<usize>,
+    watermark_bytes: Option<usize>,
+    /// Memory charged by the requests in flight
+    in_flight_bytes: Arc<AtomicUsize>,
+}
+
+impl MemoryLimitsLayer {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Self {
+            request_max_bytes: limits.request_max_memory_bytes,
+            watermark_bytes: limits.memory_watermark_bytes,
+            in_flight_bytes: Default::default(),
+        }
+    }
+
+    /// Shares the memory charged by the requests in flight with the layer of the previous
+    /// configuration, so that the requests still served by the previous router count towards the
+    /// watermark
+    pub(crate) fn inherit(&mut self, previous: &MemoryLimitsLayer) {
+        self.in_flight_bytes = previous.in_flight_bytes.clone();
+    }
+}
+
+impl<S> Layer<S> for MemoryLimitsLayer
+where
+    S: Service<router::Request, Response = router::Response, Error = BoxError> + Send + 'static,
+    <S as Service<router::Request>>::Future: Send + 'static,
+{
+    type Service = CheckpointService<S, router::Request>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        if self.request_max_bytes.is_none() && self.watermark_bytes.is_none() {
+            return CheckpointService::new(move |req| Ok(ControlFlow::Continue(req)), service);
+        }
+
+        let request_max_bytes = self.request_max_bytes;
+        let watermark_bytes = self.watermark_bytes;
+        let in_flight_bytes = self.in_flight_bytes.clone();
+        CheckpointService::new(
+            move |req: router::Request| {
+                if let Some(watermark_bytes) = watermark_bytes {
+                    if in_flight_bytes.load(Ordering::Relaxed) > watermark_bytes {
+                        u64_counter!(
+                            "apollo.router.request.memory.rejected",
+                            "Number of requests or subgraph responses rejected because of the memory limits",
+                            1,
+                            "reason" = "watermark"
+                        );
+                        return router::Response::error_builder()
+                            .error(
+                                graphql::Error::builder()
+                                    .message("the router is over its memory watermark")
+                                    .extension_code("MEMORY_WATERMARK_EXCEEDED")
+                                    .build(),
+                            )
+                            .status_code(StatusCode::SERVICE_UNAVAILABLE)
+                            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                            .context(req.context)
+                            .build()
+                            .map(ControlFlow::Break);
+                    }
+                }
+                req.context.extensions().with_lock(|mut lock| {
+                    lock.insert(RequestMemory::new(
+                        request_max_bytes,
+                        in_flight_bytes.clone(),
+                    ));
+                });
+                Ok(ControlFlow::Continue(req))
+            },
+            service,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn bodies_are_charged_to_the_request() {
+        let memory = RequestMemory::new(Some(10), Default::default());
+        assert_eq!(
+            read_body(RouterBody::from("12345"), Some(&memory))
+                .await
+                .unwrap(),
+            "12345"
+        );
+        memory.charge(4).unwrap();
+        assert_eq!(memory.remaining_bytes(), 1);
+
+        let error = read_body(RouterBody::from("123456"), Some(&memory))
+            .await
+            .unwrap_err();
+        assert!(error.is::<MemoryLimitExceeded>());
+        assert!(memory.charge(2).is_err());
+        // the rejected buffers are not charged
+        memory.charge(1).unwrap();
+        assert_eq!(memory.remaining_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn requests_are_shed_over_the_watermark() {
+        let layer = MemoryLimitsLayer::new(&Limits {
+            memory_watermark_bytes: Some(10),
+            ..Default::default()
+        });
+        let service = layer.layer(tower::service_fn(|req: router::Request| async move {
+            RequestMemory::from_context(&req.context)
+                .expect("the layer adds the memory of the request")
+                .charge(20)?;
+            router::Response::fake_builder()
+                .context(req.context)
+                .build()
+        }));
+        let call = || {
+            service
+                .clone()
+                .oneshot(router::Request::fake_builder().build().unwrap())
+        };
+
+        // the memory of a request is charged until its response is dropped
+        let in_flight = call().await.unwrap();
+        assert_eq!(in_flight.response.status(), StatusCode::OK);
+
+        let mut shed = call().await.unwrap();
+        assert_eq!(shed.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value =
+            serde_json::from_slice(&shed.next_response().await.unwrap().unwrap()).unwrap();
+        assert_eq!(
+            body["errors"][0]["extensions"]["code"],
+            "MEMORY_WATERMARK_EXCEEDED"
+        );
+
+        drop(in_flight);
+        drop(shed);
+        assert_eq!(call().await.unwrap().response.status(), StatusCode::OK);
+    }
+}
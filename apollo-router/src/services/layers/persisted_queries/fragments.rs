@@ -0,0 +1,318 @@
+//! Fragments registered once in local manifests, that operations can spread without defining them.
+//!
+//! Clients sharing large fragments, like the ones of a design system, can send operations that
+//! only spread them. Before the operation is analyzed, the router appends the definitions of the
+//! registered fragments it spreads, so that validation and planning see a complete document.
+//! This happens once automatic persisted queries are resolved, so that the hash of an APQ is the
+//! hash of the operation the client sent.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use apollo_compiler::ast;
+use apollo_compiler::parser::Parser;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::fs::read_to_string;
+use tower::BoxError;
+
+use crate::configuration::Limits;
+
+const FRAGMENT_MANIFEST_FORMAT: &str = "apollo-fragment-manifest";
+
+/// A manifest of fragments, in the format of persisted query manifests
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct FragmentManifest {
+    format: String,
+    version: u64,
+    fragments: Vec<ManifestFragment>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct ManifestFragment {
+    name: String,
+    body: String,
+}
+
+#[derive(Debug)]
+struct RegisteredFragment {
+    body: String,
+    /// Names of the fragments spread by this one
+    spreads: Vec<String>,
+}
+
+/// The fragments of the manifests, by name
+#[derive(Debug)]
+pub(crate) struct RegisteredFragments {
+    fragments: HashMap<String, RegisteredFragment>,
+    /// The parser limits of the router, applied to the operations before they are expanded
+    parser_max_recursion: usize,
+    parser_max_tokens: usize,
+}
+
+impl RegisteredFragments {
+    fn new(limits: &Limits) -> Self {
+        Self {
+            fragments: HashMap::new(),
+            parser_max_recursion: limits.parser_max_recursion,
+            parser_max_tokens: limits.parser_max_tokens,
+        }
+    }
+
+    /// Reads the fragments of the manifest files
+    pub(crate) async fn load(manifest_files: &[String], limits: &Limits) -> Result<Self, BoxError> {
+        if manifest_files.is_empty() {
+            return Err("no fragment manifest files specified".into());
+        }
+        let mut registered = Self::new(limits);
+        for manifest_file in manifest_files {
+            tracing::info!(
+                "Loading registered fragments from local file: {}",
+                manifest_file
+            );
+            let manifest = read_to_string(manifest_file)
+                .await
+                .map_err(|e| -> BoxError {
+                    format!("could not read fragment manifest file {manifest_file}: {e}").into()
+                })?;
+            registered
+                .add_manifest(&manifest)
+                .map_err(|e| -> BoxError {
+                    format!("invalid fragment manifest file {manifest_file}: {e}").into()
+                })?;
+        }
+        tracing::info!(
+            "Loaded {} registered fragments from local files.",
+            registered.fragments.len()
+        );
+        Ok(registered)
+    }
+
+    fn add_manifest(&mut self, manifest: &str) -> Result<(), String> {
+        let manifest: FragmentManifest =
+            serde_json::from_str(manifest).map_err(|e| e.to_string())?;
+        if manifest.format != FRAGMENT_MANIFEST_FORMAT {
+            return Err(format!("format is not '{FRAGMENT_MANIFEST_FORMAT}'"));
+        }
+        if manifest.version != 1 {
+            return Err("version is not 1".to_string());
+        }
+
+        for fragment in manifest.fragments {
+            let document =
+                ast::Document::parse(&fragment.body, "fragment_manifest").map_err(|e| {
+                    format!(
+                        "fragment {} could not be parsed: {}",
+                        fragment.name, e.errors
+                    )
+                })?;
+            let definition = match document.definitions.as_slice() {
+                [ast::Definition::FragmentDefinition(definition)]
+                    if definition.name.as_str() == fragment.name =>
+                {
+                    definition
+                }
+                _ => {
+                    return Err(format!(
+                        "the body of fragment {} must only contain the definition of fragment {}",
+                        fragment.name, fragment.name
+                    ))
+                }
+            };
+            let mut spreads = Vec::new();
+            fragment_spreads(&definition.selection_set, &mut spreads);
+
+            if let Some(registered) = self.fragments.get(&fragment.name) {
+                if registered.body != fragment.body {
+                    return Err(format!(
+                        "fragment {} is registered several times with different bodies",
+                        fragment.name
+                    ));
+                }
+            }
+            self.fragments.insert(
+                fragment.name,
+                RegisteredFragment {
+                    body: fragment.body,
+                    spreads,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the query with the definitions of the registered fragments it spreads without
+    /// defining them, or `None` if it doesn't need any
+    pub(crate) fn expand(&self, query: &str) -> Option<String> {
+        if self.fragments.is_empty() || !query.contains("...") {
+            return None;
+        }
+        // documents that can't be parsed, or that exceed the parser limits, are reported by the
+        // query analysis
+        let document = Parser::new()
+            .recursion_limit(self.parser_max_recursion)
+            .token_limit(self.parser_max_tokens)
+            .parse_ast(query, "query.graphql")
+            .ok()?;
+
+        let mut defined: HashSet<String> = HashSet::new();
+        let mut spreads = Vec::new();
+        for definition in &document.definitions {
+            match definition {
+                ast::Definition::OperationDefinition(operation) => {
+                    fragment_spreads(&operation.selection_set, &mut spreads)
+                }
+                ast::Definition::FragmentDefinition(fragment) => {
+                    defined.insert(fragment.name.to_string());
+                    fragment_spreads(&fragment.selection_set, &mut spreads)
+                }
+                _ => {}
+            }
+        }
+
+        let mut bodies = Vec::new();
+        while let Some(name) = spreads.pop() {
+            if defined.contains(&name) {
+                continue;
+            }
+            // fragments neither defined nor registered are reported by the validation
+            if let Some(fragment) = self.fragments.get(&name) {
+                bodies.push(fragment.body.as_str());
+                spreads.extend(fragment.spreads.iter().cloned());
+            }
+            defined.insert(name);
+        }
+
+        if bodies.is_empty() {
+            return None;
+        }
+        let mut expanded = query.to_string();
+        for body in bodies {
+            expanded.push('\n');
+            expanded.push_str(body);
+        }
+        Some(expanded)
+    }
+}
+
+/// Adds the names of the fragments spread in a selection set
+fn fragment_spreads(selection_set: &[ast::Selection], spreads: &mut Vec<String>) {
+    for selection in selection_set {
+        match selection {
+            ast::Selection::Field(field) => fragment_spreads(&field.selection_set, spreads),
+            ast::Selection::FragmentSpread(spread) => {
+                spreads.push(spread.fragment_name.to_string())
+            }
+            ast::Selection::InlineFragment(fragment) => {
+                fragment_spreads(&fragment.selection_set, spreads)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered(fragments: serde_json::Value) -> Result<RegisteredFragments, String> {
+        registered_with_limits(fragments, &Limits::default())
+    }
+
+    fn registered_with_limits(
+        fragments: serde_json::Value,
+        limits: &Limits,
+    ) -> Result<RegisteredFragments, String> {
+        let mut registered = RegisteredFragments::new(limits);
+        registered.add_manifest(
+            &serde_json::json!({
+                "format": "apollo-fragment-manifest",
+                "version": 1,
+                "fragments": fragments
+            })
+            .to_string(),
+        )?;
+        Ok(registered)
+    }
+
+    #[test]
+    fn expands_spread_fragments_transitively() {
+        let registered = registered(serde_json::json!([
+            { "name": "UserCard", "body": "fragment UserCard on User { id ...Avatar }" },
+            { "name": "Avatar", "body": "fragment Avatar on User { avatarUrl }" },
+            { "name": "Unused", "body": "fragment Unused on User { name }" }
+        ]))
+        .unwrap();
+
+        let expanded = registered
+            .expand("query Me { me { ...UserCard } }")
+            .unwrap();
+        let document = ast::Document::parse(&expanded, "query.graphql").unwrap();
+        let mut fragments: Vec<_> = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                ast::Definition::FragmentDefinition(fragment) => Some(fragment.name.to_string()),
+                _ => None,
+            })
+            .collect();
+        fragments.sort();
+        assert_eq!(fragments, vec!["Avatar", "UserCard"]);
+    }
+
+    #[test]
+    fn keeps_fragments_defined_by_the_operation() {
+        let registered = registered(serde_json::json!([
+            { "name": "UserCard", "body": "fragment UserCard on User { id }" }
+        ]))
+        .unwrap();
+
+        assert_eq!(registered.expand("{ me { id } }"), None);
+        assert_eq!(
+            registered.expand("{ me { ...UserCard } } fragment UserCard on User { name }"),
+            None
+        );
+        assert_eq!(registered.expand("{ me { ...Unknown } }"), None);
+    }
+
+    #[test]
+    fn applies_the_parser_limits_of_the_router() {
+        let fragments = serde_json::json!([
+            { "name": "UserCard", "body": "fragment UserCard on User { id }" }
+        ]);
+        let query = "{ me { ...UserCard } }";
+        let limits: Limits =
+            serde_json::from_value(serde_json::json!({ "parser_max_tokens": 6 })).unwrap();
+        assert_eq!(
+            registered_with_limits(fragments.clone(), &limits)
+                .unwrap()
+                .expand(query),
+            None
+        );
+        let limits: Limits =
+            serde_json::from_value(serde_json::json!({ "parser_max_recursion": 2 })).unwrap();
+        assert_eq!(
+            registered_with_limits(fragments.clone(), &limits)
+                .unwrap()
+                .expand("{ me { friends { friends { ...UserCard } } } }"),
+            None
+        );
+        assert!(registered(fragments).unwrap().expand(query).is_some());
+    }
+
+    #[test]
+    fn rejects_invalid_manifests() {
+        assert!(registered(serde_json::json!([
+            { "name": "UserCard", "body": "fragment Other on User { id }" }
+        ]))
+        .is_err());
+        assert!(registered(serde_json::json!([
+            { "name": "UserCard", "body": "fragment UserCard on User { id } query { me { id } }" }
+        ]))
+        .is_err());
+        assert!(registered(serde_json::json!([
+            { "name": "UserCard", "body": "fragment UserCard on User { id }" },
+            { "name": "UserCard", "body": "fragment UserCard on User { name }" }
+        ]))
+        .is_err());
+    }
+}
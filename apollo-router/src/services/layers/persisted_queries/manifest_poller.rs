@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Duration;
 
 use apollo_compiler::ast;
 use futures::prelude::*;
@@ -14,6 +15,7 @@ use tokio::fs::read_to_string;
 use tokio::sync::mpsc;
 use tower::BoxError;
 
+use super::storage::StorageSync;
 use crate::uplink::persisted_queries_manifest_stream::MaybePersistedQueriesManifestChunks;
 use crate::uplink::persisted_queries_manifest_stream::PersistedQueriesManifestChunk;
 use crate::uplink::persisted_queries_manifest_stream::PersistedQueriesManifestQuery;
@@ -21,6 +23,9 @@ use crate::uplink::stream_from_uplink_transforming_new_response;
 use crate::uplink::UplinkConfig;
 use crate::Configuration;
 
+/// Interval between the synchronizations with the storage of persisted queries.
+const DEFAULT_STORAGE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
 /// An in memory cache of persisted queries.
 pub(crate) type PersistedQueryManifest = HashMap<String, String>;
 
@@ -254,31 +259,9 @@ impl PersistedQueryManifestPoller {
                 }
             }
 
-            let freeform_graphql_behavior = if config.persisted_queries.safelist.enabled {
-                if config.persisted_queries.safelist.require_id {
-                    FreeformGraphQLBehavior::DenyAll {
-                        log_unknown: config.persisted_queries.log_unknown,
-                    }
-                } else {
-                    FreeformGraphQLBehavior::AllowIfInSafelist {
-                        safelist: FreeformGraphQLSafelist::new(&manifest),
-                        log_unknown: config.persisted_queries.log_unknown,
-                    }
-                }
-            } else if config.persisted_queries.log_unknown {
-                FreeformGraphQLBehavior::LogUnlessInSafelist {
-                    safelist: FreeformGraphQLSafelist::new(&manifest),
-                    apq_enabled: config.apq.enabled,
-                }
-            } else {
-                FreeformGraphQLBehavior::AllowAll {
-                    apq_enabled: config.apq.enabled,
-                }
-            };
-
             let state = Arc::new(RwLock::new(PersistedQueryManifestPollerState {
+                freeform_graphql_behavior: freeform_graphql_behavior(&config, &manifest),
                 persisted_query_manifest: manifest.clone(),
-                freeform_graphql_behavior,
             }));
 
             tracing::info!(
@@ -290,6 +273,38 @@ impl PersistedQueryManifestPoller {
                 state,
                 _drop_signal: mpsc::channel::<()>(1).0,
             })
+        } else if let Some(storage) = config.persisted_queries.experimental_storage.clone() {
+            let mut sync = StorageSync::new(&storage)?;
+            let manifest = sync
+                .sync()
+                .await
+                .map_err(|e| -> BoxError {
+                    format!("could not load persisted queries from their storage: {e}").into()
+                })?
+                .unwrap_or_default();
+
+            let state = Arc::new(RwLock::new(PersistedQueryManifestPollerState {
+                freeform_graphql_behavior: freeform_graphql_behavior(&config, &manifest),
+                persisted_query_manifest: manifest,
+            }));
+
+            let (_drop_signal, drop_receiver) = mpsc::channel::<()>(1);
+
+            // keep polling the storage for new and changed operations
+            tokio::task::spawn(poll_storage(
+                sync,
+                storage
+                    .poll_interval
+                    .unwrap_or(DEFAULT_STORAGE_POLL_INTERVAL),
+                state.clone(),
+                config,
+                drop_receiver,
+            ));
+
+            Ok(Self {
+                state,
+                _drop_signal,
+            })
         } else if let Some(uplink_config) = config.uplink.as_ref() {
             // Note that the contents of this Arc<RwLock> will be overwritten by poll_uplink before
             // we return from this `new` method, so the particular choice of freeform_graphql_behavior
@@ -407,6 +422,68 @@ impl PersistedQueryManifestPoller {
     }
 }
 
+/// How the router should respond to freeform GraphQL, once the manifest is loaded
+fn freeform_graphql_behavior(
+    config: &Configuration,
+    manifest: &PersistedQueryManifest,
+) -> FreeformGraphQLBehavior {
+    if config.persisted_queries.safelist.enabled {
+        if config.persisted_queries.safelist.require_id {
+            FreeformGraphQLBehavior::DenyAll {
+                log_unknown: config.persisted_queries.log_unknown,
+            }
+        } else {
+            FreeformGraphQLBehavior::AllowIfInSafelist {
+                safelist: FreeformGraphQLSafelist::new(manifest),
+                log_unknown: config.persisted_queries.log_unknown,
+            }
+        }
+    } else if config.persisted_queries.log_unknown {
+        FreeformGraphQLBehavior::LogUnlessInSafelist {
+            safelist: FreeformGraphQLSafelist::new(manifest),
+            apq_enabled: config.apq.enabled,
+        }
+    } else {
+        FreeformGraphQLBehavior::AllowAll {
+            apq_enabled: config.apq.enabled,
+        }
+    }
+}
+
+async fn poll_storage(
+    mut sync: StorageSync,
+    poll_interval: Duration,
+    state: Arc<RwLock<PersistedQueryManifestPollerState>>,
+    config: Configuration,
+    mut drop_receiver: mpsc::Receiver<()>,
+) {
+    loop {
+        tokio::select! {
+            _ = drop_receiver.recv() => return,
+            _ = tokio::time::sleep(poll_interval) => {}
+        }
+
+        match sync.sync().await {
+            Ok(Some(new_manifest)) => {
+                let new_state = PersistedQueryManifestPollerState {
+                    freeform_graphql_behavior: freeform_graphql_behavior(&config, &new_manifest),
+                    persisted_query_manifest: new_manifest,
+                };
+                state
+                    .write()
+                    .map(|mut locked_state| {
+                        *locked_state = new_state;
+                    })
+                    .expect("could not acquire write lock on persisted query manifest state");
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("error while polling the storage of persisted queries: {e}")
+            }
+        }
+    }
+}
+
 async fn poll_uplink(
     uplink_config: UplinkConfig,
     state: Arc<RwLock<PersistedQueryManifestPollerState>>,
@@ -466,31 +543,9 @@ async fn poll_uplink(
     while let Some(event) = uplink_executor.next().await {
         match event {
             ManifestPollEvent::NewManifest(new_manifest) => {
-                let freeform_graphql_behavior = if config.persisted_queries.safelist.enabled {
-                    if config.persisted_queries.safelist.require_id {
-                        FreeformGraphQLBehavior::DenyAll {
-                            log_unknown: config.persisted_queries.log_unknown,
-                        }
-                    } else {
-                        FreeformGraphQLBehavior::AllowIfInSafelist {
-                            safelist: FreeformGraphQLSafelist::new(&new_manifest),
-                            log_unknown: config.persisted_queries.log_unknown,
-                        }
-                    }
-                } else if config.persisted_queries.log_unknown {
-                    FreeformGraphQLBehavior::LogUnlessInSafelist {
-                        safelist: FreeformGraphQLSafelist::new(&new_manifest),
-                        apq_enabled: config.apq.enabled,
-                    }
-                } else {
-                    FreeformGraphQLBehavior::AllowAll {
-                        apq_enabled: config.apq.enabled,
-                    }
-                };
-
                 let new_state = PersistedQueryManifestPollerState {
+                    freeform_graphql_behavior: freeform_graphql_behavior(&config, &new_manifest),
                     persisted_query_manifest: new_manifest,
-                    freeform_graphql_behavior,
                 };
 
                 state
@@ -783,15 +838,17 @@ mod tests {
         let manifest_manager = PersistedQueryManifestPoller::new(
             Configuration::fake_builder()
                 .apq(Apq::fake_new(Some(false)))
-                .persisted_query(PersistedQueries::new(
-                    Some(true),
-                    Some(false),
-                    Some(PersistedQueriesSafelist::default()),
-                    Some(false),
-                    Some(vec![
-                        "tests/fixtures/persisted-queries-manifest.json".to_string()
-                    ]),
-                ))
+                .persisted_query(
+                    PersistedQueries::builder()
+                        .enabled(true)
+                        .log_unknown(false)
+                        .safelist(PersistedQueriesSafelist::default())
+                        .experimental_prewarm_query_plan_cache(false)
+                        .experimental_local_manifests(vec![
+                            "tests/fixtures/persisted-queries-manifest.json".to_string(),
+                        ])
+                        .build(),
+                )
                 .build()
                 .unwrap(),
         )
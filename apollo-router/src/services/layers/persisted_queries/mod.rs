@@ -1,9 +1,12 @@
+mod fragments;
 mod id_extractor;
 mod manifest_poller;
+mod storage;
 
 #[cfg(test)]
 use std::sync::Arc;
 
+use fragments::RegisteredFragments;
 use http::header::CACHE_CONTROL;
 use http::HeaderValue;
 use http::StatusCode;
@@ -13,7 +16,9 @@ use tower::BoxError;
 
 use self::manifest_poller::FreeformGraphQLAction;
 use super::query_analysis::ParsedDocument;
+use crate::configuration::PersistedQueriesSafelistEnforcement;
 use crate::graphql::Error as GraphQLError;
+use crate::plugins::telemetry::CLIENT_NAME;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 use crate::Configuration;
@@ -22,33 +27,87 @@ const DONT_CACHE_RESPONSE_VALUE: &str = "private, no-cache, must-revalidate";
 
 struct UsedQueryIdFromManifest;
 
+/// Whether the safelist is enforced for a request, when it is gradually enforced
+struct SafelistEnforced(bool);
+
 #[derive(Debug)]
 pub(crate) struct PersistedQueryLayer {
     /// Manages polling uplink for persisted queries and caches the current
     /// value of the manifest and projected safelist. None if the layer is disabled.
     pub(crate) manifest_poller: Option<PersistedQueryManifestPoller>,
     introspection_enabled: bool,
+    /// None if the safelist is enforced for all requests
+    enforcement: Option<PersistedQueriesSafelistEnforcement>,
+    /// Fragments that operations can spread without defining them. None if not configured
+    registered_fragments: Option<RegisteredFragments>,
 }
 
 impl PersistedQueryLayer {
     /// Create a new [`PersistedQueryLayer`] from CLI options, YAML configuration,
     /// and optionally, an existing persisted query manifest poller.
     pub(crate) async fn new(configuration: &Configuration) -> Result<Self, BoxError> {
+        let enforcement = configuration.persisted_queries.safelist.enforcement.clone();
+        if let Some(enforcement) = &enforcement {
+            if !(0.0..=100.0).contains(&enforcement.percentage) {
+                return Err("the safelist enforcement percentage must be between 0 and 100".into());
+            }
+        }
         if configuration.persisted_queries.enabled {
+            let registered_fragments = match &configuration
+                .persisted_queries
+                .experimental_fragment_manifests
+            {
+                Some(manifest_files) => {
+                    Some(RegisteredFragments::load(manifest_files, &configuration.limits).await?)
+                }
+                None => None,
+            };
             Ok(Self {
                 manifest_poller: Some(
                     PersistedQueryManifestPoller::new(configuration.clone()).await?,
                 ),
                 introspection_enabled: configuration.supergraph.introspection,
+                enforcement,
+                registered_fragments,
             })
         } else {
             Ok(Self {
                 manifest_poller: None,
                 introspection_enabled: configuration.supergraph.introspection,
+                enforcement,
+                registered_fragments: None,
             })
         }
     }
 
+    /// Whether the safelist is enforced for this request. With gradual enforcement, the
+    /// decision is made once per request, for the configured clients and the configured
+    /// percentage of the traffic.
+    fn enforces_safelist(&self, request: &SupergraphRequest) -> bool {
+        let enforcement = match &self.enforcement {
+            None => return true,
+            Some(enforcement) => enforcement,
+        };
+        if let Some(enforced) = request
+            .context
+            .extensions()
+            .with_lock(|lock| lock.get::<SafelistEnforced>().map(|enforced| enforced.0))
+        {
+            return enforced;
+        }
+
+        let client_name = request.context.get::<_, String>(CLIENT_NAME).ok().flatten();
+        let enforced = client_name
+            .map(|name| enforcement.client_names.contains(&name))
+            .unwrap_or(false)
+            || rand::random::<f64>() * 100.0 < enforcement.percentage;
+        request
+            .context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(SafelistEnforced(enforced)));
+        enforced
+    }
+
     /// Run a request through the layer.
     /// Takes care of:
     /// 1) resolving a persisted query ID to a query body
@@ -57,6 +116,39 @@ impl PersistedQueryLayer {
     pub(crate) fn supergraph_request(
         &self,
         request: SupergraphRequest,
+    ) -> Result<SupergraphRequest, SupergraphResponse> {
+        self.resolve_persisted_query(request)
+    }
+
+    /// Appends the definitions of the registered fragments spread by the operation. Called once
+    /// automatic persisted queries are resolved, so that they are stored and hashed as sent by
+    /// the client.
+    pub(crate) fn expand_registered_fragments(
+        &self,
+        mut request: SupergraphRequest,
+    ) -> SupergraphRequest {
+        let Some(registered_fragments) = &self.registered_fragments else {
+            return request;
+        };
+        let body = request.supergraph_request.body_mut();
+        if let Some(expanded) = body
+            .query
+            .as_deref()
+            .and_then(|query| registered_fragments.expand(query))
+        {
+            body.query = Some(expanded);
+            u64_counter!(
+                "apollo.router.operations.registered_fragments",
+                "Number of operations completed with registered fragments",
+                1
+            );
+        }
+        request
+    }
+
+    fn resolve_persisted_query(
+        &self,
+        request: SupergraphRequest,
     ) -> Result<SupergraphRequest, SupergraphResponse> {
         if let Some(manifest_poller) = &self.manifest_poller {
             if let Some(persisted_query_id) = PersistedQueryIdExtractor::extract_id(&request) {
@@ -66,6 +158,11 @@ impl PersistedQueryLayer {
                     &persisted_query_id,
                 )
             } else if let Some(log_unknown) = manifest_poller.never_allows_freeform_graphql() {
+                if !self.enforces_safelist(&request) {
+                    // The operation is logged once analyzed, like operations that are not in
+                    // the safelist.
+                    return Ok(request);
+                }
                 // If we don't have an ID and we require an ID, return an error immediately,
                 if log_unknown {
                     if let Some(operation_body) = request.supergraph_request.body().query.as_ref() {
@@ -208,6 +305,17 @@ impl PersistedQueryLayer {
         }
 
         match manifest_poller.action_for_freeform_graphql(Ok(&doc.ast)) {
+            FreeformGraphQLAction::Deny | FreeformGraphQLAction::DenyAndLog
+                if !self.enforces_safelist(&request) =>
+            {
+                tracing::info!(
+                    monotonic_counter.apollo.router.operations.persisted_queries = 1u64,
+                    persisted_queries.safelist.unenforced = true,
+                    persisted_queries.logged = true
+                );
+                log_unenforced_operation(operation_body, &request);
+                Ok(request)
+            }
             FreeformGraphQLAction::Allow => {
                 tracing::info!(monotonic_counter.apollo.router.operations.persisted_queries = 1u64,);
                 Ok(request)
@@ -251,6 +359,20 @@ fn log_unknown_operation(operation_body: &str) {
     tracing::warn!(message = "unknown operation", operation_body);
 }
 
+fn log_unenforced_operation(operation_body: &str, request: &SupergraphRequest) {
+    let client_name = request
+        .context
+        .get::<_, String>(CLIENT_NAME)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    tracing::warn!(
+        message = "unknown operation allowed because the safelist is not enforced for this request",
+        operation_body,
+        client_name
+    );
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum ErrorCacheStrategy {
     Cache,
@@ -371,15 +493,21 @@ fn supergraph_err(
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::num::NonZeroUsize;
     use std::time::Duration;
 
     use serde_json::json;
+    use sha2::Digest;
+    use sha2::Sha256;
 
     use super::*;
+    use crate::cache::DeduplicatingCache;
     use crate::configuration::Apq;
     use crate::configuration::PersistedQueries;
     use crate::configuration::PersistedQueriesSafelist;
+    use crate::configuration::PersistedQueriesSafelistEnforcement;
     use crate::configuration::Supergraph;
+    use crate::services::layers::apq::APQLayer;
     use crate::services::layers::persisted_queries::manifest_poller::FreeformGraphQLBehavior;
     use crate::services::layers::query_analysis::QueryAnalysisLayer;
     use crate::spec::Schema;
@@ -763,6 +891,95 @@ mod tests {
         ).await;
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn safelist_gradual_enforcement_by_client_name() {
+        let (_id, _body, manifest) = fake_manifest();
+        let (_mock_guard, uplink_config) = mock_pq_uplink(&manifest).await;
+
+        let config = Configuration::fake_builder()
+            .persisted_query(
+                PersistedQueries::builder()
+                    .enabled(true)
+                    .safelist(
+                        PersistedQueriesSafelist::builder()
+                            .enabled(true)
+                            .enforcement(PersistedQueriesSafelistEnforcement {
+                                percentage: 0.0,
+                                client_names: vec!["enforced".to_string()],
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .uplink(uplink_config)
+            .apq(Apq::fake_builder().enabled(false).build())
+            .build()
+            .unwrap();
+
+        let pq_layer = PersistedQueryLayer::new(&config).await.unwrap();
+        let schema = Arc::new(
+            Schema::parse(
+                include_str!("../../../testdata/supergraph.graphql"),
+                &Default::default(),
+            )
+            .unwrap(),
+        );
+        let query_analysis_layer = QueryAnalysisLayer::new(schema, Arc::new(config)).await;
+
+        // Other clients are only logged
+        allowed_by_safelist(
+            &pq_layer,
+            &query_analysis_layer,
+            "query SomeQuery { me { id } }",
+        )
+        .await;
+
+        let request = SupergraphRequest::fake_builder()
+            .query("query SomeQuery { me { id } }")
+            .build()
+            .unwrap();
+        request
+            .context
+            .insert(CLIENT_NAME, "enforced".to_string())
+            .unwrap();
+        let request = pq_layer.supergraph_request(request).ok().unwrap();
+        let request = query_analysis_layer
+            .supergraph_request(request)
+            .await
+            .ok()
+            .unwrap();
+        let response = pq_layer
+            .supergraph_request_with_analyzed_query(request)
+            .await
+            .expect_err("the safelist should be enforced for this client");
+        assert_eq!(response.response.status(), 403);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn safelist_enforcement_percentage_is_validated() {
+        let (_mock_guard, uplink_config) = mock_empty_pq_uplink().await;
+        let config = Configuration::fake_builder()
+            .persisted_query(
+                PersistedQueries::builder()
+                    .enabled(true)
+                    .safelist(
+                        PersistedQueriesSafelist::builder()
+                            .enabled(true)
+                            .enforcement(PersistedQueriesSafelistEnforcement {
+                                percentage: 150.0,
+                                client_names: vec![],
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .uplink(uplink_config)
+            .apq(Apq::fake_builder().enabled(false).build())
+            .build()
+            .unwrap();
+        assert!(PersistedQueryLayer::new(&config).await.is_err());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn pq_layer_rejects_invalid_ids_with_safelisting_enabled() {
         let (_id, _body, manifest) = fake_manifest();
@@ -1007,6 +1224,82 @@ mod tests {
         assert!(result.is_ok())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn registered_fragments_are_expanded_after_apq() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("fragment-manifest.json");
+        std::fs::write(
+            &manifest_path,
+            json!({
+                "format": "apollo-fragment-manifest",
+                "version": 1,
+                "fragments": [
+                    { "name": "UserCard", "body": "fragment UserCard on User { id }" }
+                ]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let (_mock_guard, uplink_config) = mock_empty_pq_uplink().await;
+        let pq_layer = PersistedQueryLayer::new(
+            &Configuration::fake_builder()
+                .persisted_query(
+                    PersistedQueries::builder()
+                        .enabled(true)
+                        .experimental_fragment_manifests(vec![manifest_path
+                            .to_string_lossy()
+                            .to_string()])
+                        .build(),
+                )
+                .apq(Apq::fake_builder().enabled(true).build())
+                .uplink(uplink_config)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        let apq_layer = APQLayer::with_cache(
+            DeduplicatingCache::with_capacity(NonZeroUsize::new(10).unwrap(), None, "APQ")
+                .await
+                .unwrap(),
+        );
+
+        let query = "{ me { ...UserCard } }";
+        let hash = hex::encode(Sha256::digest(query.as_bytes()));
+        let (pq_layer, apq_layer) = (&pq_layer, &apq_layer);
+        let run_layers = move |request: SupergraphRequest| async move {
+            let request = pq_layer.supergraph_request(request).ok().unwrap();
+            let request = apq_layer.supergraph_request(request).await.ok().unwrap();
+            pq_layer
+                .expand_registered_fragments(request)
+                .supergraph_request
+                .into_body()
+                .query
+                .unwrap()
+        };
+
+        // the client registers the operation it sent, under the hash of that operation
+        let registered = run_layers(
+            SupergraphRequest::fake_builder()
+                .extension("persistedQuery", json!({"version": 1, "sha256Hash": hash}))
+                .query(query)
+                .build()
+                .unwrap(),
+        )
+        .await;
+        assert!(registered.starts_with(query));
+        assert!(registered.contains("fragment UserCard on User"));
+
+        let hit = run_layers(
+            SupergraphRequest::fake_builder()
+                .extension("persistedQuery", json!({"version": 1, "sha256Hash": hash}))
+                .build()
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(hit, registered);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn cannot_pass_different_body_as_published_pq_id_with_apq_disabled() {
         let (id, _body, manifest) = fake_manifest();
@@ -0,0 +1,587 @@
+//! Storage backends of persisted queries, for the teams not using the manifests hosted by GraphOS.
+//!
+//! Operations are stored one per file in a local directory tree, one per object in an S3 bucket,
+//! or one per item in a DynamoDB table. The storage is polled: each synchronization lists the
+//! operations with a version, like the ETag of an object or the version attribute of an item, and
+//! only reads the bodies of the new and changed ones. Operations whose ID is not the SHA-256 hash
+//! of their body can be skipped.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::UNIX_EPOCH;
+
+use aws_smithy_xml::decode::try_data;
+use aws_smithy_xml::decode::Document;
+use reqwest::Method;
+use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+use url::Url;
+
+use super::manifest_poller::PersistedQueryManifest;
+use crate::configuration::PersistedQueriesStorage;
+use crate::configuration::PersistedQueriesStorageBackend;
+use crate::router::s3_object_url;
+use crate::router::AwsSigner;
+
+/// Extension of the files and objects holding operations
+const OPERATION_EXTENSION: &str = ".graphql";
+const DYNAMODB_CONTENT_TYPE: &str = "application/x-amz-json-1.0";
+const DYNAMODB_SCAN_TARGET: &str = "DynamoDB_20120810.Scan";
+const DYNAMODB_GET_ITEM_TARGET: &str = "DynamoDB_20120810.GetItem";
+
+/// An operation listed by a storage backend
+#[derive(Debug, Clone)]
+pub(crate) struct StoredOperation {
+    pub(crate) id: String,
+    /// Changes when the body of the operation changes, like the ETag of an object
+    pub(crate) version: String,
+    /// Where the body is read from, like the path of a file or the key of an object
+    pub(crate) location: String,
+    /// The body, for the backends listing the operations with their bodies
+    pub(crate) body: Option<String>,
+}
+
+/// A storage of persisted queries
+#[async_trait::async_trait]
+pub(crate) trait PersistedQueryStorage: Send + Sync {
+    /// Lists the stored operations
+    async fn list(&mut self) -> Result<Vec<StoredOperation>, BoxError>;
+
+    /// Reads the body of a listed operation
+    async fn read(&mut self, operation: &StoredOperation) -> Result<String, BoxError>;
+}
+
+/// The operations of a storage, kept in sync
+pub(crate) struct StorageSync {
+    storage: Box<dyn PersistedQueryStorage>,
+    verify_hashes: bool,
+    /// Version and body of the operations by ID. Operations skipped by the hash verification
+    /// have no body
+    operations: HashMap<String, (String, Option<String>)>,
+    synced: bool,
+}
+
+impl StorageSync {
+    pub(crate) fn new(config: &PersistedQueriesStorage) -> Result<Self, BoxError> {
+        let client = || {
+            reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()
+        };
+        let storage: Box<dyn PersistedQueryStorage> = match &config.backend {
+            PersistedQueriesStorageBackend::LocalDirectory { path } => Box::new(LocalDirectory {
+                path: PathBuf::from(path),
+            }),
+            PersistedQueriesStorageBackend::S3 {
+                bucket,
+                prefix,
+                region,
+            } => Box::new(S3Bucket {
+                bucket: bucket.clone(),
+                prefix: prefix.clone(),
+                client: client()?,
+                signer: AwsSigner::new("s3", region.clone()),
+            }),
+            PersistedQueriesStorageBackend::DynamoDb {
+                table,
+                id_attribute,
+                body_attribute,
+                version_attribute,
+                region,
+            } => Box::new(DynamoDbTable {
+                table: table.clone(),
+                id_attribute: id_attribute.clone(),
+                body_attribute: body_attribute.clone(),
+                version_attribute: version_attribute.clone(),
+                client: client()?,
+                signer: AwsSigner::new("dynamodb", region.clone()),
+            }),
+        };
+        Ok(Self::with_storage(storage, config.verify_hashes))
+    }
+
+    fn with_storage(storage: Box<dyn PersistedQueryStorage>, verify_hashes: bool) -> Self {
+        Self {
+            storage,
+            verify_hashes,
+            operations: HashMap::new(),
+            synced: false,
+        }
+    }
+
+    /// Synchronizes with the storage, reading the new and changed operations only. Returns the
+    /// new manifest, or `None` when no operation changed since the last synchronization
+    pub(crate) async fn sync(&mut self) -> Result<Option<PersistedQueryManifest>, BoxError> {
+        let listed = self.storage.list().await?;
+        let mut operations = HashMap::with_capacity(listed.len());
+        let mut changed = !self.synced;
+        for operation in listed {
+            if operations.contains_key(&operation.id) {
+                return Err(format!(
+                    "persisted query {} is stored several times, the last time at {}",
+                    operation.id, operation.location
+                )
+                .into());
+            }
+            let body = match self.operations.get(&operation.id) {
+                Some((version, body)) if *version == operation.version => body.clone(),
+                _ => {
+                    changed = true;
+                    let body = match &operation.body {
+                        Some(body) => body.clone(),
+                        None => self.storage.read(&operation).await.map_err(|e| {
+                            format!(
+                                "could not read persisted query {} at {}: {e}",
+                                operation.id, operation.location
+                            )
+                        })?,
+                    };
+                    if self.verify_hashes && !hash_matches(&operation.id, &body) {
+                        tracing::error!(
+                            "skipping persisted query {} stored at {}: its ID is not the SHA-256 hash of its body",
+                            operation.id,
+                            operation.location
+                        );
+                        None
+                    } else {
+                        Some(body)
+                    }
+                }
+            };
+            operations.insert(operation.id, (operation.version, body));
+        }
+        changed |= self
+            .operations
+            .keys()
+            .any(|id| !operations.contains_key(id));
+
+        self.operations = operations;
+        self.synced = true;
+        if !changed {
+            return Ok(None);
+        }
+
+        let manifest: PersistedQueryManifest = self
+            .operations
+            .iter()
+            .filter_map(|(id, (_, body))| Some((id.clone(), body.clone()?)))
+            .collect();
+        tracing::info!(
+            "Loaded {} persisted queries from their storage.",
+            manifest.len()
+        );
+        Ok(Some(manifest))
+    }
+}
+
+/// Whether an ID is the hexadecimal SHA-256 hash of the body
+fn hash_matches(id: &str, body: &str) -> bool {
+    hex::encode(Sha256::digest(body.as_bytes())).eq_ignore_ascii_case(id)
+}
+
+/// The ID of the operation held by a file or an object, from its name
+fn operation_id(name: &str) -> Option<&str> {
+    name.strip_suffix(OPERATION_EXTENSION)
+        .filter(|id| !id.is_empty())
+}
+
+/// A local directory tree, with one `<id>.graphql` file per operation
+struct LocalDirectory {
+    path: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl PersistedQueryStorage for LocalDirectory {
+    async fn list(&mut self) -> Result<Vec<StoredOperation>, BoxError> {
+        let mut operations = Vec::new();
+        let mut directories = vec![self.path.clone()];
+        while let Some(directory) = directories.pop() {
+            let mut entries = tokio::fs::read_dir(&directory)
+                .await
+                .map_err(|e| format!("could not read directory {}: {e}", directory.display()))?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                // follows symbolic links, like the ones of mounted Kubernetes config maps
+                let metadata = tokio::fs::metadata(&path).await?;
+                if metadata.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+                let Some(id) = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(operation_id)
+                else {
+                    continue;
+                };
+                let modified = metadata
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                operations.push(StoredOperation {
+                    id: id.to_string(),
+                    version: format!("{}:{}", modified.as_nanos(), metadata.len()),
+                    location: path.display().to_string(),
+                    body: None,
+                });
+            }
+        }
+        Ok(operations)
+    }
+
+    async fn read(&mut self, operation: &StoredOperation) -> Result<String, BoxError> {
+        Ok(tokio::fs::read_to_string(&operation.location).await?)
+    }
+}
+
+/// An S3 bucket, with one `<prefix>.../<id>.graphql` object per operation
+struct S3Bucket {
+    bucket: String,
+    prefix: String,
+    client: reqwest::Client,
+    signer: AwsSigner,
+}
+
+#[async_trait::async_trait]
+impl PersistedQueryStorage for S3Bucket {
+    async fn list(&mut self) -> Result<Vec<StoredOperation>, BoxError> {
+        let region = self.signer.region().await;
+        let mut operations = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let mut url = s3_object_url(&self.bucket, "", &region)?;
+            {
+                let mut query = url.query_pairs_mut();
+                query
+                    .append_pair("list-type", "2")
+                    .append_pair("prefix", &self.prefix);
+                if let Some(token) = &continuation_token {
+                    query.append_pair("continuation-token", token);
+                }
+            }
+            let listing = self
+                .signer
+                .request(&self.client, Method::GET, &url, &[], Vec::new())
+                .await?
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+
+            let listing = parse_s3_listing(&listing).map_err(|e| {
+                format!(
+                    "could not parse the listing of S3 bucket {}: {e}",
+                    self.bucket
+                )
+            })?;
+            for (key, etag) in listing.objects {
+                if let Some(id) = key.rsplit('/').next().and_then(operation_id) {
+                    operations.push(StoredOperation {
+                        id: id.to_string(),
+                        version: etag,
+                        location: key.clone(),
+                        body: None,
+                    });
+                }
+            }
+
+            continuation_token = listing.next_continuation_token;
+            if continuation_token.is_none() {
+                return Ok(operations);
+            }
+        }
+    }
+
+    async fn read(&mut self, operation: &StoredOperation) -> Result<String, BoxError> {
+        let region = self.signer.region().await;
+        let url = s3_object_url(&self.bucket, &operation.location, &region)?;
+        Ok(self
+            .signer
+            .request(&self.client, Method::GET, &url, &[], Vec::new())
+            .await?
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+}
+
+/// A page of the listing of an S3 bucket
+#[derive(Debug, Default)]
+struct S3Listing {
+    /// The key and the ETag of each object
+    objects: Vec<(String, String)>,
+    /// Set when the listing is truncated
+    next_continuation_token: Option<String>,
+}
+
+/// Parses the response of a `ListObjectsV2` request
+fn parse_s3_listing(xml: &str) -> Result<S3Listing, BoxError> {
+    let mut document = Document::new(xml);
+    let mut root = document.root_element()?;
+    if !root.start_el().matches("ListBucketResult") {
+        return Err("the response is not a ListBucketResult".into());
+    }
+    let mut listing = S3Listing::default();
+    let mut truncated = false;
+    while let Some(mut tag) = root.next_tag() {
+        if tag.start_el().matches("Contents") {
+            let (mut key, mut etag) = (None, None);
+            while let Some(mut field) = tag.next_tag() {
+                if field.start_el().matches("Key") {
+                    key = Some(try_data(&mut field)?.into_owned());
+                } else if field.start_el().matches("ETag") {
+                    etag = Some(try_data(&mut field)?.into_owned());
+                }
+            }
+            if let (Some(key), Some(etag)) = (key, etag) {
+                listing.objects.push((key, etag));
+            }
+        } else if tag.start_el().matches("IsTruncated") {
+            truncated = try_data(&mut tag)? == "true";
+        } else if tag.start_el().matches("NextContinuationToken") {
+            listing.next_continuation_token = Some(try_data(&mut tag)?.into_owned());
+        }
+    }
+    if !truncated {
+        listing.next_continuation_token = None;
+    }
+    Ok(listing)
+}
+
+/// A DynamoDB table, with one item per operation, partitioned by operation ID
+struct DynamoDbTable {
+    table: String,
+    id_attribute: String,
+    body_attribute: String,
+    version_attribute: String,
+    client: reqwest::Client,
+    signer: AwsSigner,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScanResponse {
+    #[serde(default)]
+    items: Vec<HashMap<String, serde_json::Value>>,
+    last_evaluated_key: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetItemResponse {
+    item: Option<HashMap<String, serde_json::Value>>,
+}
+
+impl DynamoDbTable {
+    /// Sends a request of the DynamoDB API, returning the JSON response
+    async fn send<T: serde::de::DeserializeOwned>(
+        &mut self,
+        target: &str,
+        request: serde_json::Value,
+    ) -> Result<T, BoxError> {
+        let region = self.signer.region().await;
+        let url = Url::parse(
+            &std::env::var("AWS_ENDPOINT_URL_DYNAMODB")
+                .or_else(|_| std::env::var("AWS_ENDPOINT_URL"))
+                .unwrap_or_else(|_| format!("https://dynamodb.{region}.amazonaws.com")),
+        )?;
+        let headers = [
+            ("content-type", DYNAMODB_CONTENT_TYPE),
+            ("x-amz-target", target),
+        ];
+        Ok(self
+            .signer
+            .request(
+                &self.client,
+                Method::POST,
+                &url,
+                &headers,
+                serde_json::to_vec(&request)?,
+            )
+            .await?
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+#[async_trait::async_trait]
+impl PersistedQueryStorage for DynamoDbTable {
+    async fn list(&mut self) -> Result<Vec<StoredOperation>, BoxError> {
+        let mut operations = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            // lists the IDs and the versions only, the bodies of the changed items are read
+            // one by one
+            let mut scan = serde_json::json!({
+                "TableName": self.table,
+                "ProjectionExpression": "#id, #version",
+                "ExpressionAttributeNames": {
+                    "#id": self.id_attribute,
+                    "#version": self.version_attribute,
+                },
+            });
+            if let Some(key) = exclusive_start_key.take() {
+                scan["ExclusiveStartKey"] = key;
+            }
+            let response: ScanResponse = self.send(DYNAMODB_SCAN_TARGET, scan).await?;
+
+            for item in response.items {
+                let id = item
+                    .get(&self.id_attribute)
+                    .and_then(|id| id.get("S")?.as_str());
+                let version = item
+                    .get(&self.version_attribute)
+                    .and_then(|version| version.get("S").or_else(|| version.get("N"))?.as_str());
+                let (Some(id), Some(version)) = (id, version) else {
+                    tracing::warn!(
+                        "skipping an item of DynamoDB table {} without the {} string attribute and the {} string or number attribute",
+                        self.table,
+                        self.id_attribute,
+                        self.version_attribute
+                    );
+                    continue;
+                };
+                operations.push(StoredOperation {
+                    id: id.to_string(),
+                    version: version.to_string(),
+                    location: format!("{}/{id}", self.table),
+                    body: None,
+                });
+            }
+
+            match response.last_evaluated_key {
+                Some(key) => exclusive_start_key = Some(key),
+                None => return Ok(operations),
+            }
+        }
+    }
+
+    async fn read(&mut self, operation: &StoredOperation) -> Result<String, BoxError> {
+        let get_item = serde_json::json!({
+            "TableName": self.table,
+            "Key": { self.id_attribute.as_str(): { "S": operation.id } },
+            "ProjectionExpression": "#body",
+            "ExpressionAttributeNames": { "#body": self.body_attribute },
+        });
+        let response: GetItemResponse = self.send(DYNAMODB_GET_ITEM_TARGET, get_item).await?;
+        response
+            .item
+            .as_ref()
+            .and_then(|item| item.get(&self.body_attribute)?.get("S")?.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| {
+                format!("the item has no {} string attribute", self.body_attribute).into()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "query { me { id } }";
+
+    fn id(body: &str) -> String {
+        hex::encode(Sha256::digest(body.as_bytes()))
+    }
+
+    fn write(directory: &std::path::Path, name: &str, body: &str) {
+        std::fs::create_dir_all(directory.join(name).parent().unwrap()).unwrap();
+        std::fs::write(directory.join(name), body).unwrap();
+    }
+
+    #[tokio::test]
+    async fn syncs_local_directory_incrementally() {
+        let directory = tempfile::tempdir().unwrap();
+        let other = "query { me { name } }";
+        write(directory.path(), &format!("{}.graphql", id(BODY)), BODY);
+        write(
+            directory.path(),
+            &format!("team/{}.graphql", id(other)),
+            other,
+        );
+        write(directory.path(), "README.md", "not an operation");
+
+        let mut sync = StorageSync::with_storage(
+            Box::new(LocalDirectory {
+                path: directory.path().to_path_buf(),
+            }),
+            true,
+        );
+        let manifest = sync.sync().await.unwrap().unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest.get(&id(BODY)).map(String::as_str), Some(BODY));
+        assert_eq!(manifest.get(&id(other)).map(String::as_str), Some(other));
+
+        // nothing changed
+        assert!(sync.sync().await.unwrap().is_none());
+
+        std::fs::remove_file(directory.path().join(format!("team/{}.graphql", id(other)))).unwrap();
+        let manifest = sync.sync().await.unwrap().unwrap();
+        assert_eq!(manifest.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn skips_operations_failing_hash_verification() {
+        let directory = tempfile::tempdir().unwrap();
+        write(directory.path(), &format!("{}.graphql", id(BODY)), BODY);
+        write(directory.path(), "GetMe.graphql", BODY);
+
+        let storage = || {
+            Box::new(LocalDirectory {
+                path: directory.path().to_path_buf(),
+            })
+        };
+        let manifest = StorageSync::with_storage(storage(), true)
+            .sync()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.keys().collect::<Vec<_>>(), vec![&id(BODY)]);
+
+        let manifest = StorageSync::with_storage(storage(), false)
+            .sync()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.get("GetMe").map(String::as_str), Some(BODY));
+    }
+
+    #[test]
+    fn parses_s3_listings() {
+        let listing = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <ListBucketResult>
+                <IsTruncated>true</IsTruncated>
+                <Contents><Key>ops/a&amp;b.graphql</Key><ETag>&quot;1&quot;</ETag></Contents>
+                <Contents><Key>ops/c.graphql</Key><ETag>&quot;2&quot;</ETag></Contents>
+                <NextContinuationToken>token</NextContinuationToken>
+            </ListBucketResult>"#;
+        let listing = parse_s3_listing(listing).unwrap();
+        assert_eq!(
+            listing.objects,
+            vec![
+                ("ops/a&b.graphql".to_string(), "\"1\"".to_string()),
+                ("ops/c.graphql".to_string(), "\"2\"".to_string()),
+            ]
+        );
+        assert_eq!(listing.next_continuation_token.as_deref(), Some("token"));
+
+        let listing = parse_s3_listing(
+            "<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>",
+        )
+        .unwrap();
+        assert!(listing.objects.is_empty());
+        assert!(listing.next_continuation_token.is_none());
+
+        assert!(parse_s3_listing("<Error><Code>AccessDenied</Code></Error>").is_err());
+    }
+}
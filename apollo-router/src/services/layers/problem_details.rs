@@ -0,0 +1,306 @@
+//! RFC 9457 problem details for the requests rejected before GraphQL processing.
+//!
+//! The responses with one of the configured status codes, like the `413` of a request body over
+//! `limits.http_max_request_bytes` or the `429` of a rate limited request, get an
+//! `application/problem+json` body instead of their GraphQL error body or empty body. The
+//! problem details carry the message and the code of the first GraphQL error, and the
+//! correlation ID of the request.
+
+use std::sync::Arc;
+use std::task::Poll;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use http::header::CONTENT_LENGTH;
+use http::header::CONTENT_TYPE;
+use http::HeaderName;
+use http::HeaderValue;
+use serde::Serialize;
+use serde_json::Value;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::configuration::ProblemDetails;
+use crate::services::router;
+use crate::services::router::body::get_body_bytes;
+
+pub(crate) const PROBLEM_JSON_HEADER_VALUE: &str = "application/problem+json";
+
+/// Problem details of a rejected request, with the `code` and `correlation_id` extension members
+#[derive(Debug, PartialEq, Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    type_uri: String,
+    title: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    correlation_id: String,
+}
+
+struct Settings {
+    config: ProblemDetails,
+    correlation_id_header: HeaderName,
+}
+
+impl Settings {
+    /// Returns the problem details of a response body, if it is empty or a GraphQL response
+    /// without data
+    fn problem(
+        &self,
+        status: http::StatusCode,
+        body: &[u8],
+        correlation_id: String,
+    ) -> Option<Problem> {
+        let (detail, code) = if body.is_empty() {
+            (None, None)
+        } else {
+            let response: Value = serde_json::from_slice(body).ok()?;
+            if !response.get("data").map_or(true, Value::is_null) {
+                return None;
+            }
+            let error = response.get("errors").and_then(|errors| errors.get(0));
+            let detail = error
+                .and_then(|error| error.get("message"))
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            let code = error
+                .and_then(|error| error.pointer("/extensions/code"))
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            (detail, code)
+        };
+        Some(Problem {
+            type_uri: self
+                .config
+                .type_uri(code.as_deref(), status.as_u16())
+                .to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail,
+            code,
+            correlation_id,
+        })
+    }
+}
+
+/// [`Layer`] answering the rejected requests with problem details
+#[derive(Clone)]
+pub(crate) struct ProblemDetailsLayer {
+    settings: Option<Arc<Settings>>,
+}
+
+impl ProblemDetailsLayer {
+    pub(crate) fn new(config: &ProblemDetails) -> Result<Self, BoxError> {
+        if !config.enabled {
+            return Ok(Self { settings: None });
+        }
+        let correlation_id_header = HeaderName::try_from(config.correlation_id_header.as_str())
+            .map_err(|e| {
+                format!(
+                    "invalid problem details correlation ID header '{}': {e}",
+                    config.correlation_id_header
+                )
+            })?;
+        Ok(Self {
+            settings: Some(Arc::new(Settings {
+                config: config.clone(),
+                correlation_id_header,
+            })),
+        })
+    }
+}
+
+impl<S> Layer<S> for ProblemDetailsLayer {
+    type Service = ProblemDetailsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProblemDetailsService {
+            inner,
+            settings: self.settings.clone(),
+        }
+    }
+}
+
+/// [`Service`] answering the rejected requests with problem details, see [`ProblemDetailsLayer`]
+#[derive(Clone)]
+pub(crate) struct ProblemDetailsService<S> {
+    inner: S,
+    settings: Option<Arc<Settings>>,
+}
+
+impl<S> Service<router::Request> for ProblemDetailsService<S>
+where
+    S: Service<router::Request, Response = router::Response, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, router::ServiceResult>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: router::Request) -> Self::Future {
+        let Some(settings) = self.settings.clone() else {
+            return Box::pin(self.inner.call(request));
+        };
+        let correlation_id = request
+            .router_request
+            .headers()
+            .get(&settings.correlation_id_header)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            let status = response.response.status();
+            if !settings.config.status_codes.contains(&status.as_u16()) {
+                return Ok(response);
+            }
+
+            let router::Response { response, context } = response;
+            let (mut parts, body) = response.into_parts();
+            let body = get_body_bytes(body).await?;
+            let correlation_id = correlation_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            let body = match settings.problem(status, &body, correlation_id.clone()) {
+                Some(problem) => {
+                    parts.headers.remove(CONTENT_LENGTH);
+                    parts.headers.insert(
+                        CONTENT_TYPE,
+                        HeaderValue::from_static(PROBLEM_JSON_HEADER_VALUE),
+                    );
+                    if let Ok(value) = HeaderValue::from_str(&correlation_id) {
+                        parts
+                            .headers
+                            .insert(settings.correlation_id_header.clone(), value);
+                    }
+                    Bytes::from(serde_json::to_vec(&problem)?)
+                }
+                None => body,
+            };
+            Ok(router::Response {
+                response: http::Response::from_parts(parts, body.into()),
+                context,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::graphql;
+
+    fn config() -> ProblemDetails {
+        ProblemDetails {
+            enabled: true,
+            types: [
+                (
+                    "INVALID_CONTENT_TYPE_HEADER".to_string(),
+                    "https://errors.example.com/content-type".to_string(),
+                ),
+                (
+                    "429".to_string(),
+                    "https://errors.example.com/rate-limited".to_string(),
+                ),
+            ]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    async fn call(
+        status: StatusCode,
+        errors: Vec<graphql::Error>,
+        request: router::Request,
+    ) -> (http::response::Parts, Value) {
+        let service = ProblemDetailsLayer::new(&config())
+            .unwrap()
+            .layer(tower::service_fn(move |request: router::Request| {
+                let errors = errors.clone();
+                async move {
+                    router::Response::error_builder()
+                        .errors(errors)
+                        .status_code(status)
+                        .context(request.context)
+                        .build()
+                }
+            }));
+        let response = service.oneshot(request).await.unwrap();
+        let (parts, body) = response.response.into_parts();
+        let body = get_body_bytes(body).await.unwrap();
+        (parts, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejections_get_problem_details() {
+        let (parts, body) = call(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            vec![graphql::Error::builder()
+                .message("'content-type' header must be one of: \"application/json\"")
+                .extension_code("INVALID_CONTENT_TYPE_HEADER")
+                .build()],
+            router::Request::fake_builder()
+                .header("x-correlation-id", "abc")
+                .build()
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(
+            parts.headers.get(CONTENT_TYPE).unwrap(),
+            PROBLEM_JSON_HEADER_VALUE
+        );
+        assert_eq!(parts.headers.get("x-correlation-id").unwrap(), "abc");
+        assert_eq!(
+            body,
+            json!({
+                "type": "https://errors.example.com/content-type",
+                "title": "Unsupported Media Type",
+                "status": 415,
+                "detail": "'content-type' header must be one of: \"application/json\"",
+                "code": "INVALID_CONTENT_TYPE_HEADER",
+                "correlation_id": "abc"
+            })
+        );
+
+        let (_, body) = call(
+            StatusCode::TOO_MANY_REQUESTS,
+            Vec::new(),
+            router::Request::fake_builder().build().unwrap(),
+        )
+        .await;
+        assert_eq!(body["type"], "https://errors.example.com/rate-limited");
+        assert_eq!(body["status"], 429);
+        assert!(body["correlation_id"]
+            .as_str()
+            .is_some_and(|id| !id.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn other_responses_are_unchanged() {
+        let (parts, body) = call(
+            StatusCode::BAD_REQUEST,
+            vec![graphql::Error::builder()
+                .message("invalid query")
+                .extension_code("GRAPHQL_VALIDATION_FAILED")
+                .build()],
+            router::Request::fake_builder().build().unwrap(),
+        )
+        .await;
+        assert_ne!(
+            parts.headers.get(CONTENT_TYPE).unwrap(),
+            PROBLEM_JSON_HEADER_VALUE
+        );
+        assert_eq!(body["errors"][0]["message"], "invalid query");
+    }
+}
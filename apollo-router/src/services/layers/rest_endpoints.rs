@@ -0,0 +1,799 @@
+//! REST endpoints executing persisted queries.
+//!
+//! A request matching one of the `persisted_queries.experimental_rest_endpoints.routes` is turned
+//! into a GraphQL request for the persisted query of the route, its variables taken from the path
+//! parameters, the query string and the JSON object body of the request. The OpenAPI document
+//! describing the routes is generated from the operations of the manifest and the API schema.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::task::Poll;
+
+use apollo_compiler::ast;
+use apollo_compiler::executable::ExecutableDocument;
+use apollo_compiler::executable::Selection;
+use apollo_compiler::executable::SelectionSet;
+use apollo_compiler::schema::ExtendedType;
+use apollo_compiler::schema::Type;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use http::header::ACCEPT;
+use http::header::CONTENT_LENGTH;
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use http::Method;
+use http::StatusCode;
+use mime::APPLICATION_JSON;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::configuration::PersistedQueryRoute;
+use crate::configuration::RouteSegment;
+use crate::graphql;
+use crate::services::layers::persisted_queries::PersistedQueryLayer;
+use crate::services::router;
+use crate::services::router::body::get_body_bytes;
+use crate::spec::Query;
+use crate::spec::Schema;
+use crate::Configuration;
+
+/// [`Layer`] translating the requests of the REST endpoints to GraphQL requests
+#[derive(Clone)]
+pub(crate) struct RestEndpointsLayer {
+    endpoints: Option<Arc<RestEndpoints>>,
+}
+
+struct RestEndpoints {
+    routes: Vec<PersistedQueryRoute>,
+    openapi_path: Option<String>,
+    persisted_query_layer: Arc<PersistedQueryLayer>,
+    schema: Arc<Schema>,
+    configuration: Arc<Configuration>,
+}
+
+/// Route matching a request
+enum RouteMatch<'a> {
+    Route(&'a PersistedQueryRoute, Vec<(String, String)>),
+    MethodNotAllowed,
+    None,
+}
+
+impl RestEndpointsLayer {
+    pub(crate) fn new(
+        configuration: Arc<Configuration>,
+        persisted_query_layer: Arc<PersistedQueryLayer>,
+        schema: Arc<Schema>,
+    ) -> Self {
+        let rest_endpoints = &configuration.persisted_queries.experimental_rest_endpoints;
+        if rest_endpoints.routes.is_empty() && rest_endpoints.openapi_path.is_none() {
+            return Self { endpoints: None };
+        }
+        Self {
+            endpoints: Some(Arc::new(RestEndpoints {
+                routes: rest_endpoints.routes.clone(),
+                openapi_path: rest_endpoints.openapi_path.clone(),
+                persisted_query_layer,
+                schema,
+                configuration: configuration.clone(),
+            })),
+        }
+    }
+}
+
+impl<S> Layer<S> for RestEndpointsLayer {
+    type Service = RestEndpointsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RestEndpointsService {
+            inner,
+            endpoints: self.endpoints.clone(),
+        }
+    }
+}
+
+pub(crate) struct RestEndpointsService<S> {
+    inner: S,
+    endpoints: Option<Arc<RestEndpoints>>,
+}
+
+impl<S> Service<router::Request> for RestEndpointsService<S>
+where
+    S: Service<router::Request, Response = router::Response, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = router::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, router::ServiceResult>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: router::Request) -> Self::Future {
+        let Some(endpoints) = self.endpoints.clone() else {
+            return Box::pin(self.inner.call(req));
+        };
+
+        let path = req.router_request.uri().path();
+        if endpoints.openapi_path.as_deref() == Some(path)
+            && req.router_request.method() == Method::GET
+        {
+            let response = json_response(StatusCode::OK, &endpoints.openapi(), req.context);
+            return Box::pin(async move { response });
+        }
+
+        match endpoints.route(req.router_request.method(), path) {
+            RouteMatch::None => Box::pin(self.inner.call(req)),
+            RouteMatch::MethodNotAllowed => {
+                let response = json_response(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    &graphql::Response::builder()
+                        .error(
+                            graphql::Error::builder()
+                                .message("the method is not allowed on this endpoint")
+                                .extension_code("METHOD_NOT_ALLOWED")
+                                .build(),
+                        )
+                        .build(),
+                    req.context,
+                );
+                Box::pin(async move { response })
+            }
+            RouteMatch::Route(route, parameters) => {
+                let req = endpoints.graphql_request(route, parameters, req);
+                let response = self.inner.call(req);
+                Box::pin(async move { rest_response(response.await?).await })
+            }
+        }
+    }
+}
+
+impl RestEndpoints {
+    fn route(&self, method: &Method, path: &str) -> RouteMatch<'_> {
+        let mut method_not_allowed = false;
+        for route in &self.routes {
+            let Some(parameters) = match_path(route, path) else {
+                continue;
+            };
+            if route.method.as_http() == method {
+                return RouteMatch::Route(route, parameters);
+            }
+            method_not_allowed = true;
+        }
+        if method_not_allowed {
+            RouteMatch::MethodNotAllowed
+        } else {
+            RouteMatch::None
+        }
+    }
+
+    fn operation_body(&self, route: &PersistedQueryRoute) -> Option<String> {
+        self.persisted_query_layer
+            .manifest_poller
+            .as_ref()?
+            .get_operation_body(&route.persisted_query_id)
+    }
+
+    /// The types of the variables of the operation of a route. They are read from the operation
+    /// without validating it, which is left to the GraphQL pipeline
+    fn variable_types(&self, route: &PersistedQueryRoute) -> HashMap<String, Type> {
+        let Some(document) = self
+            .operation_body(route)
+            .and_then(|body| ast::Document::parse(body, "rest_endpoint.graphql").ok())
+        else {
+            return HashMap::new();
+        };
+        document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                ast::Definition::OperationDefinition(operation)
+                    if route.operation_name.is_none()
+                        || operation.name.as_ref().map(|name| name.as_str())
+                            == route.operation_name.as_deref() =>
+                {
+                    Some(operation)
+                }
+                _ => None,
+            })
+            .flat_map(|operation| operation.variables.iter())
+            .map(|variable| (variable.name.to_string(), (*variable.ty).clone()))
+            .collect()
+    }
+
+    /// Turns the request of a route into a GraphQL request for its persisted query
+    fn graphql_request(
+        &self,
+        route: &PersistedQueryRoute,
+        path_parameters: Vec<(String, String)>,
+        req: router::Request,
+    ) -> router::Request {
+        let (mut parts, body) = req.router_request.into_parts();
+        let types = self.variable_types(route);
+
+        // the path parameters take precedence over the query string, and both over the body
+        let mut parameters: Vec<(String, Vec<String>)> = Vec::new();
+        for (name, value) in
+            url::form_urlencoded::parse(parts.uri.query().unwrap_or_default().as_bytes())
+                .into_owned()
+                .chain(path_parameters)
+        {
+            match parameters.iter_mut().find(|(key, _)| *key == name) {
+                Some((_, values)) => values.push(value),
+                None => parameters.push((name, vec![value])),
+            }
+        }
+        let mut variables = Map::new();
+        for (name, values) in parameters {
+            let value = coerce_parameter(&values, types.get(&name));
+            variables.insert(ByteString::from(name), value);
+        }
+
+        let persisted_query_id = route.persisted_query_id.clone();
+        let operation_name = route.operation_name.clone();
+        // the method of the route is kept, so that mutations are rejected on GET routes like on
+        // the GraphQL endpoint, and the content type is left to the client, for the CSRF check
+        let body = if route.method.has_body() {
+            let max_bytes = self.configuration.limits.http_max_request_bytes;
+            router::Body::wrap_stream(futures::stream::once(async move {
+                let bytes = get_body_bytes(http_body::Limited::new(body, max_bytes)).await?;
+                let mut body_variables: Map<ByteString, Value> = if bytes.is_empty() {
+                    Map::new()
+                } else {
+                    serde_json::from_slice(&bytes).map_err(|e| -> BoxError {
+                        format!("the body of the request must be a JSON object: {e}").into()
+                    })?
+                };
+                body_variables.extend(variables);
+                graphql_body(persisted_query_id, operation_name, body_variables)
+            }))
+        } else if parts.method == Method::GET {
+            match graphql_query(persisted_query_id, operation_name, variables) {
+                Ok(query) => {
+                    parts.uri = format!("{}?{query}", parts.uri.path())
+                        .parse()
+                        .expect("the path and the encoded query are valid; qed");
+                    router::Body::empty()
+                }
+                Err(e) => router::Body::wrap_stream(futures::stream::once(async move {
+                    Err::<Bytes, BoxError>(e)
+                })),
+            }
+        } else {
+            match graphql_body(persisted_query_id, operation_name, variables) {
+                Ok(bytes) => router::Body::from(bytes),
+                Err(e) => router::Body::wrap_stream(futures::stream::once(async move {
+                    Err::<Bytes, BoxError>(e)
+                })),
+            }
+        };
+
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.insert(
+            ACCEPT,
+            HeaderValue::from_static(APPLICATION_JSON.essence_str()),
+        );
+        router::Request {
+            router_request: http::Request::from_parts(parts, body),
+            context: req.context,
+        }
+    }
+
+    /// The OpenAPI document describing the routes
+    fn openapi(&self) -> serde_json::Value {
+        let mut paths = serde_json::Map::new();
+        for route in &self.routes {
+            let operation = self.openapi_operation(route);
+            let path = paths
+                .entry(route.path.clone())
+                .or_insert_with(|| serde_json::json!({}));
+            path[route.method.as_http().as_str().to_ascii_lowercase()] = operation;
+        }
+        serde_json::json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Persisted queries",
+                "version": self.schema.schema_id.as_str(),
+            },
+            "paths": paths,
+        })
+    }
+
+    fn openapi_operation(&self, route: &PersistedQueryRoute) -> serde_json::Value {
+        let path_parameters: Vec<&str> = route
+            .segments()
+            .filter_map(|segment| match segment {
+                RouteSegment::Parameter(name) => Some(name),
+                RouteSegment::Static(_) => None,
+            })
+            .collect();
+        let operation_id = route
+            .operation_name
+            .clone()
+            .unwrap_or_else(|| route.persisted_query_id.clone());
+
+        let document = self.operation_body(route).and_then(|body| {
+            Query::parse_document(
+                &body,
+                route.operation_name.as_deref(),
+                &self.schema,
+                &self.configuration,
+            )
+            .ok()
+        });
+        let Some((document, operation)) = document.as_ref().and_then(|document| {
+            let operation = document
+                .executable
+                .operations
+                .get(route.operation_name.as_deref())
+                .ok()?;
+            Some((&document.executable, operation))
+        }) else {
+            let parameters: Vec<serde_json::Value> = path_parameters
+                .iter()
+                .map(|name| {
+                    serde_json::json!({
+                        "name": name,
+                        "in": "path",
+                        "required": true,
+                        "schema": {},
+                    })
+                })
+                .collect();
+            return serde_json::json!({
+                "operationId": operation_id,
+                "description": format!(
+                    "the persisted query '{}' is not in the manifest, or is invalid",
+                    route.persisted_query_id
+                ),
+                "parameters": parameters,
+                "responses": {
+                    "default": { "description": "The response of the operation" },
+                },
+            });
+        };
+        let schema = self.schema.api_schema();
+
+        let mut parameters = Vec::new();
+        let mut body_properties = serde_json::Map::new();
+        let mut body_required = Vec::new();
+        for name in &path_parameters {
+            if !operation
+                .variables
+                .iter()
+                .any(|variable| variable.name.as_str() == *name)
+            {
+                parameters.push(serde_json::json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                }));
+            }
+        }
+        for variable in &operation.variables {
+            let schema = input_schema(schema, &variable.ty, &mut Vec::new());
+            let required = variable.ty.is_non_null() && variable.default_value.is_none();
+            if path_parameters.contains(&variable.name.as_str()) {
+                parameters.push(serde_json::json!({
+                    "name": variable.name.as_str(),
+                    "in": "path",
+                    "required": true,
+                    "schema": schema,
+                }));
+            } else if route.method.has_body() {
+                body_properties.insert(variable.name.to_string(), schema);
+                if required {
+                    body_required.push(variable.name.to_string());
+                }
+            } else {
+                parameters.push(serde_json::json!({
+                    "name": variable.name.as_str(),
+                    "in": "query",
+                    "required": required,
+                    "schema": schema,
+                }));
+            }
+        }
+
+        let mut data = serde_json::Map::new();
+        selection_set_schema(document, schema, &operation.selection_set, &mut data);
+        let mut openapi_operation = serde_json::json!({
+            "operationId": operation_id,
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": "The response of the operation",
+                    "content": {
+                        "application/json": {
+                            "schema": {
+                                "type": "object",
+                                "properties": {
+                                    "data": {
+                                        "type": "object",
+                                        "nullable": true,
+                                        "properties": data,
+                                    },
+                                    "errors": {
+                                        "type": "array",
+                                        "items": { "type": "object" },
+                                    },
+                                },
+                            },
+                        },
+                    },
+                },
+                "default": { "description": "The errors of the operation" },
+            },
+        });
+        if route.method.has_body() {
+            let mut body_schema = serde_json::json!({
+                "type": "object",
+                "properties": body_properties,
+            });
+            if !body_required.is_empty() {
+                body_schema["required"] = body_required.into();
+            }
+            openapi_operation["requestBody"] = serde_json::json!({
+                "content": { "application/json": { "schema": body_schema } },
+            });
+        }
+        openapi_operation
+    }
+}
+
+/// Matches the path of a request against the path of a route, returning its parameters
+fn match_path(route: &PersistedQueryRoute, path: &str) -> Option<Vec<(String, String)>> {
+    let mut segments = path.split('/').skip(1);
+    let mut parameters = Vec::new();
+    for route_segment in route.segments() {
+        let segment = segments.next()?;
+        match route_segment {
+            RouteSegment::Static(expected) => {
+                if segment != expected {
+                    return None;
+                }
+            }
+            RouteSegment::Parameter(name) => {
+                if segment.is_empty() {
+                    return None;
+                }
+                let value = urlencoding::decode(segment).ok()?;
+                parameters.push((name.to_string(), value.into_owned()));
+            }
+        }
+    }
+    segments.next().is_none().then_some(parameters)
+}
+
+/// Converts the values of a path or query parameter to the type of its variable. The values that
+/// don't convert are passed as strings, for the validation of the operation to report them
+fn coerce_parameter(values: &[String], ty: Option<&Type>) -> Value {
+    let coerce = |value: &str, ty: Option<&Type>| match ty.map(|ty| ty.inner_named_type().as_str())
+    {
+        None | Some("String") | Some("ID") => Value::String(value.into()),
+        _ => serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into())),
+    };
+    match ty {
+        Some(Type::List(item) | Type::NonNullList(item)) => Value::Array(
+            values
+                .iter()
+                .map(|value| coerce(value, Some(item)))
+                .collect(),
+        ),
+        _ => coerce(values.last().map(String::as_str).unwrap_or_default(), ty),
+    }
+}
+
+fn graphql_body(
+    persisted_query_id: String,
+    operation_name: Option<String>,
+    variables: Map<ByteString, Value>,
+) -> Result<Bytes, BoxError> {
+    let request = graphql::Request::builder()
+        .and_operation_name(operation_name)
+        .variables(variables)
+        .extension(
+            "persistedQuery",
+            serde_json_bytes::json!({ "version": 1, "sha256Hash": persisted_query_id }),
+        )
+        .build();
+    Ok(serde_json::to_vec(&request)?.into())
+}
+
+/// The query string of a GraphQL GET request
+fn graphql_query(
+    persisted_query_id: String,
+    operation_name: Option<String>,
+    variables: Map<ByteString, Value>,
+) -> Result<String, BoxError> {
+    let extensions = serde_json_bytes::json!({
+        "persistedQuery": { "version": 1, "sha256Hash": persisted_query_id }
+    });
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if let Some(operation_name) = operation_name {
+        query.append_pair("operationName", &operation_name);
+    }
+    query.append_pair("variables", &serde_json::to_string(&variables)?);
+    query.append_pair("extensions", &serde_json::to_string(&extensions)?);
+    Ok(query.finish())
+}
+
+/// Reports the operations without data with an error status
+async fn rest_response(response: router::Response) -> router::ServiceResult {
+    let (mut parts, body) = response.response.into_parts();
+    let bytes = get_body_bytes(body).await?;
+    if let Ok(graphql_response) = serde_json::from_slice::<graphql::Response>(&bytes) {
+        let has_data = graphql_response
+            .data
+            .as_ref()
+            .map_or(false, |data| !data.is_null());
+        if !has_data && parts.status.is_success() {
+            parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    Ok(router::Response {
+        response: http::Response::from_parts(parts, router::Body::from(bytes)),
+        context: response.context,
+    })
+}
+
+fn json_response(
+    status: StatusCode,
+    body: &impl serde::Serialize,
+    context: crate::Context,
+) -> router::ServiceResult {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .body(serde_json::to_vec(body)?.into())?,
+        context,
+    })
+}
+
+/// The JSON schema of a built-in scalar or of an enum
+fn leaf_schema(schema: &apollo_compiler::Schema, name: &str) -> Option<serde_json::Value> {
+    Some(match name {
+        "Int" => serde_json::json!({ "type": "integer", "format": "int32" }),
+        "Float" => serde_json::json!({ "type": "number", "format": "double" }),
+        "Boolean" => serde_json::json!({ "type": "boolean" }),
+        "String" | "ID" => serde_json::json!({ "type": "string" }),
+        _ => match schema.types.get(name)? {
+            ExtendedType::Enum(enum_type) => serde_json::json!({
+                "type": "string",
+                "enum": enum_type.values.keys().map(|value| value.as_str()).collect::<Vec<_>>(),
+            }),
+            _ => return None,
+        },
+    })
+}
+
+fn with_nullability(mut value: serde_json::Value, ty: &Type) -> serde_json::Value {
+    if !ty.is_non_null() {
+        value["nullable"] = true.into();
+    }
+    value
+}
+
+/// The JSON schema of the value of a variable. Custom scalars and recursive input objects accept
+/// any value
+fn input_schema(
+    schema: &apollo_compiler::Schema,
+    ty: &Type,
+    visited: &mut Vec<String>,
+) -> serde_json::Value {
+    let value = match ty {
+        Type::List(item) | Type::NonNullList(item) => serde_json::json!({
+            "type": "array",
+            "items": input_schema(schema, item, visited),
+        }),
+        Type::Named(name) | Type::NonNullNamed(name) => {
+            if let Some(value) = leaf_schema(schema, name) {
+                value
+            } else if let (Some(ExtendedType::InputObject(input_object)), false) = (
+                schema.types.get(name),
+                visited.iter().any(|visited| visited == name.as_str()),
+            ) {
+                visited.push(name.to_string());
+                let mut properties = serde_json::Map::new();
+                let mut required = Vec::new();
+                for (field_name, field) in &input_object.fields {
+                    properties.insert(
+                        field_name.to_string(),
+                        input_schema(schema, &field.ty, visited),
+                    );
+                    if field.ty.is_non_null() && field.default_value.is_none() {
+                        required.push(field_name.to_string());
+                    }
+                }
+                visited.pop();
+                let mut value = serde_json::json!({ "type": "object", "properties": properties });
+                if !required.is_empty() {
+                    value["required"] = required.into();
+                }
+                value
+            } else {
+                serde_json::json!({})
+            }
+        }
+    };
+    with_nullability(value, ty)
+}
+
+/// The JSON schema of the response to a field
+fn output_schema(
+    document: &ExecutableDocument,
+    schema: &apollo_compiler::Schema,
+    ty: &Type,
+    selection_set: &SelectionSet,
+) -> serde_json::Value {
+    let value = match ty {
+        Type::List(item) | Type::NonNullList(item) => serde_json::json!({
+            "type": "array",
+            "items": output_schema(document, schema, item, selection_set),
+        }),
+        Type::Named(name) | Type::NonNullNamed(name) => {
+            if selection_set.selections.is_empty() {
+                leaf_schema(schema, name).unwrap_or_else(|| serde_json::json!({}))
+            } else {
+                let mut properties = serde_json::Map::new();
+                selection_set_schema(document, schema, selection_set, &mut properties);
+                serde_json::json!({ "type": "object", "properties": properties })
+            }
+        }
+    };
+    with_nullability(value, ty)
+}
+
+/// Adds the fields of a selection set to the properties of an object. The fields selected on a
+/// subset of the possible types are added as well
+fn selection_set_schema(
+    document: &ExecutableDocument,
+    schema: &apollo_compiler::Schema,
+    selection_set: &SelectionSet,
+    properties: &mut serde_json::Map<String, serde_json::Value>,
+) {
+    for selection in &selection_set.selections {
+        match selection {
+            Selection::Field(field) => {
+                properties.insert(
+                    field.response_key().to_string(),
+                    output_schema(document, schema, &field.definition.ty, &field.selection_set),
+                );
+            }
+            Selection::InlineFragment(fragment) => {
+                selection_set_schema(document, schema, &fragment.selection_set, properties)
+            }
+            Selection::FragmentSpread(spread) => {
+                if let Some(fragment) = document.fragments.get(&spread.fragment_name) {
+                    selection_set_schema(document, schema, &fragment.selection_set, properties)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::PersistedQueryRouteMethod;
+
+    fn route(method: PersistedQueryRouteMethod, path: &str) -> PersistedQueryRoute {
+        PersistedQueryRoute {
+            method,
+            path: path.to_string(),
+            persisted_query_id: "id".to_string(),
+            operation_name: None,
+        }
+    }
+
+    #[test]
+    fn paths_are_matched_with_their_parameters() {
+        let user = route(PersistedQueryRouteMethod::Get, "/api/users/{id}");
+        assert_eq!(
+            match_path(&user, "/api/users/a%20b"),
+            Some(vec![("id".to_string(), "a b".to_string())])
+        );
+        assert_eq!(match_path(&user, "/api/users/"), None);
+        assert_eq!(match_path(&user, "/api/users/1/reviews"), None);
+        assert_eq!(match_path(&user, "/api/products/1"), None);
+    }
+
+    #[test]
+    fn parameters_are_coerced_to_the_types_of_their_variables() {
+        let ty = |source: &str| {
+            let document =
+                ast::Document::parse(format!("query($v: {source}) {{ a }}"), "test.graphql")
+                    .unwrap();
+            let ast::Definition::OperationDefinition(operation) = &document.definitions[0] else {
+                unreachable!()
+            };
+            (*operation.variables[0].ty).clone()
+        };
+        let values = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            coerce_parameter(&values(&["12"]), Some(&ty("ID!"))),
+            Value::String("12".into())
+        );
+        assert_eq!(
+            coerce_parameter(&values(&["12"]), Some(&ty("Int"))),
+            serde_json_bytes::json!(12)
+        );
+        assert_eq!(
+            coerce_parameter(&values(&["true", "false"]), Some(&ty("[Boolean!]"))),
+            serde_json_bytes::json!([true, false])
+        );
+        // invalid values are left to the validation of the operation
+        assert_eq!(
+            coerce_parameter(&values(&["ACTIVE"]), Some(&ty("Status"))),
+            Value::String("ACTIVE".into())
+        );
+        assert_eq!(
+            coerce_parameter(&values(&["1"]), None),
+            Value::String("1".into())
+        );
+    }
+
+    #[test]
+    fn openapi_schemas_follow_the_types_of_the_schema() {
+        let schema = apollo_compiler::Schema::parse_and_validate(
+            r#"
+            type Query { user(filter: Filter!): User }
+            type User { id: ID! name: String status: Status! friends: [User!]! }
+            enum Status { ACTIVE INACTIVE }
+            input Filter { id: ID! status: Status next: Filter }
+            "#,
+            "schema.graphql",
+        )
+        .unwrap();
+        let document = ExecutableDocument::parse(
+            &schema,
+            "query($filter: Filter!) { user(filter: $filter) { id ... on User { status } friends { name } } }",
+            "query.graphql",
+        )
+        .unwrap();
+        let operation = document.operations.get(None).unwrap();
+
+        assert_eq!(
+            input_schema(&schema, &operation.variables[0].ty, &mut Vec::new()),
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "status": { "type": "string", "enum": ["ACTIVE", "INACTIVE"], "nullable": true },
+                    "next": { "nullable": true },
+                },
+                "required": ["id"],
+            })
+        );
+
+        let mut data = serde_json::Map::new();
+        selection_set_schema(&document, &schema, &operation.selection_set, &mut data);
+        assert_eq!(
+            serde_json::Value::Object(data),
+            serde_json::json!({
+                "user": {
+                    "type": "object",
+                    "nullable": true,
+                    "properties": {
+                        "id": { "type": "string" },
+                        "status": { "type": "string", "enum": ["ACTIVE", "INACTIVE"] },
+                        "friends": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": { "name": { "type": "string", "nullable": true } },
+                            },
+                        },
+                    },
+                },
+            })
+        );
+    }
+}
@@ -7,10 +7,12 @@ use std::ops::ControlFlow;
 
 use askama::Template;
 use bytes::Bytes;
+use http::header::CONTENT_SECURITY_POLICY;
 use http::header::CONTENT_TYPE;
 use http::HeaderMap;
 use http::HeaderValue;
 use http::Method;
+use http::StatusCode;
 use mediatype::names::HTML;
 use mediatype::names::TEXT;
 use mediatype::MediaType;
@@ -20,7 +22,9 @@ use tower::Layer;
 use tower::Service;
 
 use crate::configuration::Homepage;
+use crate::configuration::Sandbox;
 use crate::layers::sync_checkpoint::CheckpointService;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::services::router;
 use crate::Configuration;
 
@@ -28,19 +32,38 @@ use crate::Configuration;
 #[derive(Clone)]
 pub(crate) struct StaticPageLayer {
     static_page: Option<Bytes>,
+    content_security_policy: Option<HeaderValue>,
+    require_authentication: bool,
 }
 
 impl StaticPageLayer {
     pub(crate) fn new(configuration: &Configuration) -> Self {
         let static_page = if configuration.sandbox.enabled {
-            Some(Bytes::from(sandbox_page_content()))
+            Some(Bytes::from(sandbox_page_content(&configuration.sandbox)))
         } else if configuration.homepage.enabled {
             Some(Bytes::from(home_page_content(&configuration.homepage)))
         } else {
             None
         };
+        let content_security_policy = configuration
+            .sandbox
+            .content_security_policy
+            .as_deref()
+            .filter(|_| configuration.sandbox.enabled)
+            .and_then(|policy| HeaderValue::from_str(policy).ok());
+
+        Self {
+            static_page,
+            content_security_policy,
+            require_authentication: configuration.sandbox.enabled
+                && configuration.sandbox.require_authentication,
+        }
+    }
 
-        Self { static_page }
+    /// Whether the page is only served to authenticated requests, in which case the layer must be
+    /// placed after the plugins, so that the authentication plugin has run
+    pub(crate) fn requires_authentication(&self) -> bool {
+        self.require_authentication
     }
 }
 
@@ -54,19 +77,33 @@ where
     fn layer(&self, service: S) -> Self::Service {
         if let Some(static_page) = &self.static_page {
             let page = static_page.clone();
+            let content_security_policy = self.content_security_policy.clone();
+            let require_authentication = self.require_authentication;
 
             CheckpointService::new(
                 move |req| {
                     let res = if req.router_request.method() == Method::GET
                         && prefers_html(req.router_request.headers())
                     {
-                        let response = http::Response::builder()
-                            .header(
+                        let response = if require_authentication
+                            && !req.context.contains_key(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+                        {
+                            http::Response::builder()
+                                .status(StatusCode::UNAUTHORIZED)
+                                .body(crate::services::router::Body::empty())
+                                .unwrap()
+                        } else {
+                            let mut builder = http::Response::builder().header(
                                 CONTENT_TYPE,
                                 HeaderValue::from_static(mime::TEXT_HTML_UTF_8.as_ref()),
-                            )
-                            .body(crate::services::router::Body::from(page.clone()))
-                            .unwrap();
+                            );
+                            if let Some(policy) = &content_security_policy {
+                                builder = builder.header(CONTENT_SECURITY_POLICY, policy.clone());
+                            }
+                            builder
+                                .body(crate::services::router::Body::from(page.clone()))
+                                .unwrap()
+                        };
                         ControlFlow::Break(router::Response {
                             response,
                             context: req.context,
@@ -102,13 +139,15 @@ fn prefers_html(headers: &HeaderMap) -> bool {
 
 #[derive(Template)]
 #[template(path = "sandbox_index.html")]
-struct SandboxTemplate {
+struct SandboxTemplate<'a> {
     apollo_router_version: &'static str,
+    custom_html: Option<&'a str>,
 }
 
-pub(crate) fn sandbox_page_content() -> Vec<u8> {
+pub(crate) fn sandbox_page_content(sandbox_config: &Sandbox) -> Vec<u8> {
     let template = SandboxTemplate {
         apollo_router_version: std::env!("CARGO_PKG_VERSION"),
+        custom_html: sandbox_config.custom_html.as_deref(),
     };
     let mut buffer = Vec::new();
     template.write_into(&mut buffer).expect("cannot fail");
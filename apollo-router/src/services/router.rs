@@ -396,5 +396,10 @@ pub(crate) struct ClientRequestAccepts {
     pub(crate) multipart_defer: bool,
     pub(crate) multipart_subscription: bool,
     pub(crate) json: bool,
+    /// Set if the client accepts `application/graphql-response+json` and the strict semantics
+    /// of this media type are enabled for it
+    pub(crate) graphql_response_json: bool,
+    /// Set if the client accepts `application/cbor` and CBOR responses are enabled for it
+    pub(crate) cbor: bool,
     pub(crate) wildcard: bool,
 }
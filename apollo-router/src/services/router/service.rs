@@ -26,6 +26,7 @@ use http::StatusCode;
 use http_body::Body as _;
 use mime::APPLICATION_JSON;
 use multimap::MultiMap;
+use tokio::sync::oneshot;
 use tower::BoxError;
 use tower::Layer;
 use tower::ServiceBuilder;
@@ -38,22 +39,38 @@ use crate::axum_factory::CanceledRequest;
 use crate::batching::Batch;
 use crate::batching::BatchQuery;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::contracts::CONTRACT_VARIANT_CONTEXT_KEY;
+use crate::configuration::dual_execution::mismatched_fields;
 use crate::configuration::Batching;
 use crate::configuration::BatchingMode;
+use crate::configuration::ContentNegotiation;
+use crate::configuration::Contracts;
+use crate::configuration::DualExecution;
+use crate::context::object_size;
 use crate::context::CONTAINS_GRAPHQL_ERROR;
+use crate::context::OPERATION_KIND;
 use crate::graphql;
 use crate::http_ext;
+use crate::panic_containment::PanicContainmentLayer;
+use crate::plugin::cache_key::CacheKeyHooks;
 #[cfg(test)]
 use crate::plugin::test::MockSupergraphService;
+use crate::plugins::telemetry::CLIENT_NAME;
 use crate::protocols::multipart::Multipart;
 use crate::protocols::multipart::ProtocolMode;
 use crate::query_planner::InMemoryCachePlanner;
+use crate::query_planner::OperationKind;
 use crate::router_factory::RouterFactory;
 use crate::services::layers::apq::APQLayer;
 use crate::services::layers::content_negotiation;
+use crate::services::layers::content_negotiation::CBOR_RESPONSE_HEADER_VALUE;
 use crate::services::layers::content_negotiation::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
+use crate::services::layers::memory_limits::MemoryLimitsLayer;
+use crate::services::layers::memory_limits::RequestMemory;
 use crate::services::layers::persisted_queries::PersistedQueryLayer;
+use crate::services::layers::problem_details::ProblemDetailsLayer;
 use crate::services::layers::query_analysis::QueryAnalysisLayer;
+use crate::services::layers::rest_endpoints::RestEndpointsLayer;
 use crate::services::layers::static_page::StaticPageLayer;
 use crate::services::new_service::ServiceFactory;
 use crate::services::router;
@@ -61,8 +78,8 @@ use crate::services::router::body::get_body_bytes;
 use crate::services::router::body::RouterBody;
 #[cfg(test)]
 use crate::services::supergraph;
+use crate::services::HasConfig;
 use crate::services::HasPlugins;
-#[cfg(test)]
 use crate::services::HasSchema;
 use crate::services::RouterRequest;
 use crate::services::RouterResponse;
@@ -74,6 +91,7 @@ use crate::services::MULTIPART_DEFER_ACCEPT;
 use crate::services::MULTIPART_DEFER_CONTENT_TYPE;
 use crate::services::MULTIPART_SUBSCRIPTION_ACCEPT;
 use crate::services::MULTIPART_SUBSCRIPTION_CONTENT_TYPE;
+use crate::support_bundle;
 use crate::Configuration;
 use crate::Context;
 use crate::Endpoint;
@@ -96,9 +114,28 @@ pub(crate) struct RouterService {
     query_analysis_layer: QueryAnalysisLayer,
     http_max_request_bytes: usize,
     batching: Batching,
+    contracts: Arc<Contracts>,
+    contract_variants: Arc<HashMap<String, ContractVariantService>>,
+    dual_execution: Option<Arc<DualExecutionService>>,
+}
+
+/// The services serving a contract variant of the supergraph.
+#[derive(Clone)]
+pub(crate) struct ContractVariantService {
+    pub(crate) supergraph_creator: Arc<SupergraphCreator>,
+    pub(crate) query_analysis_layer: QueryAnalysisLayer,
+}
+
+/// The services executing a sample of the queries against the staged version of the supergraph.
+#[derive(Clone)]
+pub(crate) struct DualExecutionService {
+    pub(crate) dual_execution: DualExecution,
+    pub(crate) supergraph_creator: Arc<SupergraphCreator>,
+    pub(crate) query_analysis_layer: QueryAnalysisLayer,
 }
 
 impl RouterService {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         supergraph_creator: Arc<SupergraphCreator>,
         apq_layer: APQLayer,
@@ -106,6 +143,9 @@ impl RouterService {
         query_analysis_layer: QueryAnalysisLayer,
         http_max_request_bytes: usize,
         batching: Batching,
+        contracts: Arc<Contracts>,
+        contract_variants: Arc<HashMap<String, ContractVariantService>>,
+        dual_execution: Option<Arc<DualExecutionService>>,
     ) -> Self {
         RouterService {
             supergraph_creator,
@@ -114,7 +154,113 @@ impl RouterService {
             query_analysis_layer,
             http_max_request_bytes,
             batching,
+            contracts,
+            contract_variants,
+            dual_execution,
+        }
+    }
+}
+
+impl DualExecutionService {
+    /// Returns a copy of the request to execute against the staged supergraph, if it is sampled.
+    fn sample(&self, request: &SupergraphRequest) -> Option<SupergraphRequest> {
+        if !self.dual_execution.sample() {
+            return None;
         }
+
+        // the staged request gets its own context, so that the staged plugins do not modify the
+        // context of the active request
+        let context = Context::new();
+        context.extend(&request.context);
+        let cache_key_hooks = CacheKeyHooks::new(self.supergraph_creator.plugins());
+        context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(cache_key_hooks));
+        Some(SupergraphRequest {
+            supergraph_request: http_ext::clone_http_request(&request.supergraph_request),
+            context,
+        })
+    }
+
+    /// Executes the staged request in the background, and compares its response with the first
+    /// response of the active supergraph.
+    fn compare(
+        self: &Arc<Self>,
+        staged_request: SupergraphRequest,
+        response: SupergraphResponse,
+    ) -> SupergraphResponse {
+        // mutations would be applied twice, and subscriptions would never complete
+        let is_query = response
+            .context
+            .get::<_, OperationKind>(OPERATION_KIND)
+            .ok()
+            .flatten()
+            == Some(OperationKind::Query);
+        if !is_query {
+            return response;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        let service = self.clone();
+        tokio::spawn(async move {
+            let staged = service.execute(staged_request).await;
+            let Ok(active) = receiver.await else {
+                return;
+            };
+            match staged {
+                Some(staged) => {
+                    let mismatches = mismatched_fields(&active, &staged);
+                    u64_counter!(
+                        "apollo.router.operations.dual_execution",
+                        "Total operations executed against the staged supergraph",
+                        1,
+                        "dual_execution.match" = mismatches.is_empty()
+                    );
+                    if !mismatches.is_empty() {
+                        // the paths depend on the queries: logged, not used as metric attributes
+                        tracing::info!(
+                            mismatched_fields = ?mismatches,
+                            "the staged response differs from the active response"
+                        );
+                        u64_counter!(
+                            "apollo.router.operations.dual_execution.field_mismatches",
+                            "Fields with different values in the active and staged responses",
+                            mismatches.len() as u64
+                        );
+                    }
+                }
+                None => {
+                    u64_counter!(
+                        "apollo.router.operations.dual_execution",
+                        "Total operations executed against the staged supergraph",
+                        1,
+                        "dual_execution.error" = true
+                    );
+                }
+            }
+        });
+
+        let mut sender = Some(sender);
+        response.map_stream(move |response| {
+            if let Some(sender) = sender.take() {
+                let _ = sender.send(response.clone());
+            }
+            response
+        })
+    }
+
+    async fn execute(&self, request: SupergraphRequest) -> Option<graphql::Response> {
+        let mut response = match self.query_analysis_layer.supergraph_request(request).await {
+            Err(response) => response,
+            Ok(request) => match self.supergraph_creator.create().oneshot(request).await {
+                Ok(response) => response,
+                Err(error) => {
+                    tracing::debug!("could not execute the staged request: {error}");
+                    return None;
+                }
+            },
+        };
+        response.next_response().await
     }
 }
 
@@ -229,36 +375,109 @@ impl Service<RouterRequest> for RouterService {
 }
 
 impl RouterService {
+    /// Selects the contract variant serving the request, `None` being the main supergraph.
+    fn contract_variant(
+        &self,
+        request: SupergraphRequest,
+    ) -> Result<(SupergraphRequest, Option<&ContractVariantService>), SupergraphResponse> {
+        let context_variant = request
+            .context
+            .get::<_, String>(CONTRACT_VARIANT_CONTEXT_KEY)
+            .ok()
+            .flatten();
+        let client_name = request.context.get::<_, String>(CLIENT_NAME).ok().flatten();
+
+        match self.contracts.requested_variant(
+            context_variant,
+            request.supergraph_request.headers(),
+            client_name.as_deref(),
+        ) {
+            None => Ok((request, None)),
+            Some(name) => match self.contract_variants.get(&name) {
+                Some(variant) => Ok((request, Some(variant))),
+                None => Err(SupergraphResponse::builder()
+                    .error(
+                        graphql::Error::builder()
+                            .message(format!("unknown contract variant '{name}'"))
+                            .extension_code("UNKNOWN_CONTRACT_VARIANT")
+                            .build(),
+                    )
+                    .status_code(StatusCode::BAD_REQUEST)
+                    .context(request.context)
+                    .build()
+                    .expect("response is valid")),
+            },
+        }
+    }
+
     async fn process_supergraph_request(
         &self,
         supergraph_request: SupergraphRequest,
     ) -> Result<router::Response, BoxError> {
+        let cache_key_hooks = CacheKeyHooks::new(self.supergraph_creator.plugins());
+        supergraph_request
+            .context
+            .extensions()
+            .with_lock(|mut lock| lock.insert(cache_key_hooks));
+
         let mut request_res = self
             .persisted_query_layer
             .supergraph_request(supergraph_request);
 
         if let Ok(supergraph_request) = request_res {
-            request_res = self.apq_layer.supergraph_request(supergraph_request).await;
+            request_res = self
+                .apq_layer
+                .supergraph_request(supergraph_request)
+                .await
+                .map(|request| {
+                    self.persisted_query_layer
+                        .expand_registered_fragments(request)
+                });
         }
 
         let SupergraphResponse { response, context } = match request_res {
             Err(response) => response,
-            Ok(request) => match self.query_analysis_layer.supergraph_request(request).await {
+            Ok(request) => match self.contract_variant(request) {
                 Err(response) => response,
-                Ok(request) => match self
-                    .persisted_query_layer
-                    .supergraph_request_with_analyzed_query(request)
-                    .await
-                {
-                    Err(response) => response,
-                    Ok(request) => self.supergraph_creator.create().oneshot(request).await?,
-                },
+                Ok((request, variant)) => {
+                    let (query_analysis_layer, supergraph_creator) = match variant {
+                        Some(variant) => {
+                            (&variant.query_analysis_layer, &variant.supergraph_creator)
+                        }
+                        None => (&self.query_analysis_layer, &self.supergraph_creator),
+                    };
+                    let staged_request = match (&self.dual_execution, variant) {
+                        (Some(dual_execution), None) => dual_execution.sample(&request),
+                        _ => None,
+                    };
+                    match query_analysis_layer.supergraph_request(request).await {
+                        Err(response) => response,
+                        Ok(request) => match self
+                            .persisted_query_layer
+                            .supergraph_request_with_analyzed_query(request)
+                            .await
+                        {
+                            Err(response) => response,
+                            Ok(request) => {
+                                let response = supergraph_creator.create().oneshot(request).await?;
+                                match (&self.dual_execution, staged_request) {
+                                    (Some(dual_execution), Some(staged_request)) => {
+                                        dual_execution.compare(staged_request, response)
+                                    }
+                                    _ => response,
+                                }
+                            }
+                        },
+                    }
+                }
             },
         };
 
         let ClientRequestAccepts {
             wildcard: accepts_wildcard,
             json: accepts_json,
+            graphql_response_json: accepts_graphql_response_json,
+            cbor: accepts_cbor,
             multipart_defer: accepts_multipart_defer,
             multipart_subscription: accepts_multipart_subscription,
         } = context
@@ -294,17 +513,36 @@ impl RouterService {
             Some(response) => {
                 if !response.has_next.unwrap_or(false)
                     && !response.subscribed.unwrap_or(false)
-                    && (accepts_json || accepts_wildcard)
+                    && (accepts_json || accepts_wildcard || accepts_cbor)
                 {
                     if !response.errors.is_empty() {
                         Self::count_errors(&response.errors);
                     }
 
-                    parts
-                        .headers
-                        .insert(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE.clone());
+                    if accepts_cbor {
+                        parts.headers.insert(
+                            CONTENT_TYPE,
+                            HeaderValue::from_static(CBOR_RESPONSE_HEADER_VALUE),
+                        );
+                    } else if accepts_graphql_response_json {
+                        parts.headers.insert(
+                            CONTENT_TYPE,
+                            HeaderValue::from_static(GRAPHQL_JSON_RESPONSE_HEADER_VALUE),
+                        );
+                        parts.status = graphql_response_json_status(parts.status, &response);
+                    } else {
+                        parts
+                            .headers
+                            .insert(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE.clone());
+                    }
                     tracing::trace_span!("serialize_response").in_scope(|| {
-                        let body = serde_json::to_string(&response)?;
+                        let body = if accepts_cbor {
+                            let mut body = Vec::new();
+                            ciborium::into_writer(&response, &mut body)?;
+                            Bytes::from(body)
+                        } else {
+                            Bytes::from(serde_json::to_vec(&response)?)
+                        };
                         Ok(router::Response {
                             response: http::Response::from_parts(
                                 parts,
@@ -407,6 +645,58 @@ impl RouterService {
         }
     }
 
+    /// Processes an operation of a client batch. Its failures, and its timeout if configured,
+    /// result in an error response for this operation only, instead of failing the whole batch.
+    async fn process_batch_operation(
+        &self,
+        supergraph_request: SupergraphRequest,
+    ) -> Result<router::Response, BoxError> {
+        let context = supergraph_request.context.clone();
+        let result = match self.batching.operation_timeout {
+            Some(timeout) => {
+                tokio::time::timeout(timeout, self.process_supergraph_request(supergraph_request))
+                    .await
+                    .ok()
+            }
+            None => Some(self.process_supergraph_request(supergraph_request).await),
+        };
+        let (status, code, message, reason) = match result {
+            Some(Ok(response)) => return Ok(response),
+            Some(Err(err)) => {
+                tracing::error!("batch operation failed: {err}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "BATCH_OPERATION_FAILED",
+                    format!("the batch operation failed: {err}"),
+                    "error",
+                )
+            }
+            None => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "BATCH_OPERATION_TIMEOUT",
+                "the batch operation timed out".to_string(),
+                "timeout",
+            ),
+        };
+        u64_counter!(
+            "apollo.router.operations.batching.operation.failed",
+            "Number of client batch operations that failed without failing the rest of their batch",
+            1,
+            "reason" = reason
+        );
+        router::Response::error_builder()
+            .error(
+                graphql::Error::builder()
+                    .message(message)
+                    .extension_code(code)
+                    .build(),
+            )
+            .status_code(status)
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .context(context)
+            .build()
+    }
+
     async fn call_inner(&self, req: RouterRequest) -> Result<RouterResponse, BoxError> {
         let context = req.context.clone();
 
@@ -449,7 +739,11 @@ impl RouterService {
                 // We clone the context here, because if the request results in an Err, the
                 // response context will no longer exist.
                 let context = supergraph_request.context.clone();
-                let result = self.process_supergraph_request(supergraph_request).await;
+                let result = if is_batch {
+                    self.process_batch_operation(supergraph_request).await
+                } else {
+                    self.process_supergraph_request(supergraph_request).await
+                };
 
                 // Regardless of the result, we need to make sure that we cancel any potential batch queries. This is because
                 // custom rust plugins, rhai scripts, and coprocessors can cancel requests at any time and return a GraphQL
@@ -702,12 +996,39 @@ impl RouterService {
                         }
                     })
                     .and_then(|bytes| {
+                        charge_request_memory(&context, bytes.len())?;
                         self.translate_bytes_request(&bytes)
                     })
             }
         };
 
         let (ok_results, is_batch) = graphql_requests?;
+        // the variables of the other requests are charged with their body
+        if parts.method == Method::GET {
+            charge_request_memory(
+                &context,
+                ok_results
+                    .iter()
+                    .map(|request| object_size(&request.variables))
+                    .sum(),
+            )?;
+        }
+        if is_batch {
+            let client_name = context.get::<_, String>(CLIENT_NAME).ok().flatten();
+            if let Some(maximum_size) = self.batching.maximum_size(client_name.as_deref()) {
+                if ok_results.len() > maximum_size {
+                    return Err(TranslateError {
+                        status: StatusCode::BAD_REQUEST,
+                        error: "batch too large",
+                        extension_code: "BATCH_LIMIT_EXCEEDED",
+                        extension_details: format!(
+                            "the batch contains {} operations, the maximum is {maximum_size}",
+                            ok_results.len()
+                        ),
+                    });
+                }
+            }
+        }
         let mut results = Vec::with_capacity(ok_results.len());
         let batch_size = ok_results.len();
 
@@ -849,6 +1170,19 @@ struct TranslateError<'a> {
     extension_details: String,
 }
 
+/// Charges the buffers of the request to its memory, when memory accounting is enabled
+fn charge_request_memory(context: &Context, bytes: usize) -> Result<(), TranslateError<'static>> {
+    let Some(memory) = RequestMemory::from_context(context) else {
+        return Ok(());
+    };
+    memory.charge(bytes).map_err(|e| TranslateError {
+        status: StatusCode::PAYLOAD_TOO_LARGE,
+        error: "request memory limit exceeded",
+        extension_code: "MEMORY_LIMIT_EXCEEDED",
+        extension_details: e.to_string(),
+    })
+}
+
 // Process the headers to make sure that `VARY` is set correctly
 pub(crate) fn process_vary_header(headers: &mut HeaderMap<HeaderValue>) {
     if headers.get(VARY).is_none() {
@@ -857,6 +1191,17 @@ pub(crate) fn process_vary_header(headers: &mut HeaderMap<HeaderValue>) {
     }
 }
 
+/// Returns the status code of an `application/graphql-response+json` response, following the
+/// GraphQL over HTTP specification: a response without data is a client error, unless it already
+/// has an error status code, and a response with data is a success.
+fn graphql_response_json_status(status: StatusCode, response: &graphql::Response) -> StatusCode {
+    match &response.data {
+        None if status.is_success() => StatusCode::BAD_REQUEST,
+        Some(data) if !data.is_null() && !status.is_success() => StatusCode::OK,
+        _ => status,
+    }
+}
+
 /// A collection of services and data which may be used to create a "router".
 #[derive(Clone)]
 pub(crate) struct RouterCreator {
@@ -867,6 +1212,14 @@ pub(crate) struct RouterCreator {
     query_analysis_layer: QueryAnalysisLayer,
     http_max_request_bytes: usize,
     batching: Batching,
+    contracts: Arc<Contracts>,
+    pub(crate) contract_variants: Arc<HashMap<String, ContractVariantService>>,
+    pub(crate) dual_execution: Option<Arc<DualExecutionService>>,
+    content_negotiation: Arc<ContentNegotiation>,
+    panic_containment: PanicContainmentLayer,
+    problem_details: ProblemDetailsLayer,
+    memory_limits: MemoryLimitsLayer,
+    rest_endpoints: RestEndpointsLayer,
 }
 
 impl ServiceFactory<router::Request> for RouterCreator {
@@ -889,8 +1242,30 @@ impl RouterFactory for RouterCreator {
             .plugins()
             .values()
             .for_each(|p| mm.extend(p.web_endpoints()));
+
+        let configuration = self.supergraph_creator.config();
+        if configuration.experimental_support_bundle.enabled {
+            tracing::info!(
+                "Support bundle exposed at {}{}",
+                configuration.experimental_support_bundle.listen,
+                configuration.experimental_support_bundle.path
+            );
+            let plugins = self.supergraph_creator.plugins().keys().cloned().collect();
+            mm.insert(
+                configuration.experimental_support_bundle.listen.clone(),
+                support_bundle::endpoint(
+                    configuration.clone(),
+                    self.supergraph_creator.schema(),
+                    plugins,
+                ),
+            );
+        }
         mm
     }
+
+    fn save_warm_state(&self) -> BoxFuture<'_, Result<(), BoxError>> {
+        Box::pin(self.supergraph_creator.save_warm_state())
+    }
 }
 
 impl RouterCreator {
@@ -901,6 +1276,11 @@ impl RouterCreator {
         configuration: Arc<Configuration>,
     ) -> Result<Self, BoxError> {
         let static_page = StaticPageLayer::new(&configuration);
+        let rest_endpoints = RestEndpointsLayer::new(
+            configuration.clone(),
+            persisted_query_layer.clone(),
+            supergraph_creator.schema(),
+        );
         let apq_layer = if configuration.apq.enabled {
             APQLayer::with_cache(
                 DeduplicatingCache::from_configuration(&configuration.apq.router.cache, "APQ")
@@ -918,9 +1298,47 @@ impl RouterCreator {
             http_max_request_bytes: configuration.limits.http_max_request_bytes,
             persisted_query_layer,
             batching: configuration.batching.clone(),
+            contracts: Arc::new(configuration.contracts.clone()),
+            contract_variants: Default::default(),
+            dual_execution: None,
+            content_negotiation: Arc::new(configuration.content_negotiation.clone()),
+            panic_containment: PanicContainmentLayer::new(&configuration),
+            problem_details: ProblemDetailsLayer::new(&configuration.problem_details)?,
+            memory_limits: MemoryLimitsLayer::new(&configuration.limits),
+            rest_endpoints,
         })
     }
 
+    /// Serves the given contract variants alongside the main supergraph.
+    pub(crate) fn with_contract_variants(
+        mut self,
+        contract_variants: HashMap<String, ContractVariantService>,
+    ) -> Self {
+        self.contract_variants = Arc::new(contract_variants);
+        self
+    }
+
+    /// Keeps counting the memory of the requests in flight of the previous router, which are
+    /// still served after a reload.
+    pub(crate) fn with_previous_memory_limits(
+        mut self,
+        previous_router: Option<&RouterCreator>,
+    ) -> Self {
+        if let Some(previous_router) = previous_router {
+            self.memory_limits.inherit(&previous_router.memory_limits);
+        }
+        self
+    }
+
+    /// Executes a sample of the queries against the given staged supergraph as well.
+    pub(crate) fn with_dual_execution(
+        mut self,
+        dual_execution: Option<DualExecutionService>,
+    ) -> Self {
+        self.dual_execution = dual_execution.map(Arc::new);
+        self
+    }
+
     pub(crate) fn make(
         &self,
     ) -> impl Service<
@@ -929,23 +1347,45 @@ impl RouterCreator {
         Error = BoxError,
         Future = BoxFuture<'static, router::ServiceResult>,
     > + Send {
-        let router_service = content_negotiation::RouterLayer::default().layer(RouterService::new(
+        let router_service = content_negotiation::RouterLayer::new(
+            self.content_negotiation.clone(),
+        )
+        .layer(RouterService::new(
             self.supergraph_creator.clone(),
             self.apq_layer.clone(),
             self.persisted_query_layer.clone(),
             self.query_analysis_layer.clone(),
             self.http_max_request_bytes,
             self.batching.clone(),
+            self.contracts.clone(),
+            self.contract_variants.clone(),
+            self.dual_execution.clone(),
         ));
 
+        // a page restricted to authenticated users is served after the authentication plugin
+        let (static_page, authenticated_static_page) = if self.static_page.requires_authentication()
+        {
+            (None, Some(self.static_page.clone()))
+        } else {
+            (Some(self.static_page.clone()), None)
+        };
+        let router_service = ServiceBuilder::new()
+            .option_layer(authenticated_static_page)
+            .service(router_service)
+            .boxed();
+
         ServiceBuilder::new()
-            .layer(self.static_page.clone())
+            .layer(self.panic_containment.clone())
+            .layer(self.problem_details.clone())
+            .layer(self.memory_limits.clone())
+            .layer(self.rest_endpoints.clone())
+            .option_layer(static_page)
             .service(
                 self.supergraph_creator
                     .plugins()
                     .iter()
                     .rev()
-                    .fold(router_service.boxed(), |acc, (_, e)| e.router_service(acc)),
+                    .fold(router_service, |acc, (_, e)| e.router_service(acc)),
             )
     }
 }
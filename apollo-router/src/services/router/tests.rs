@@ -17,6 +17,7 @@ use crate::graphql;
 use crate::services::router;
 use crate::services::router::body::get_body_bytes;
 use crate::services::router::service::from_supergraph_mock_callback;
+use crate::services::router::service::from_supergraph_mock_callback_and_configuration;
 use crate::services::router::service::process_vary_header;
 use crate::services::subgraph;
 use crate::services::supergraph;
@@ -320,6 +321,41 @@ async fn it_processes_a_valid_query_batch() {
     assert_eq!(expected_response, data);
 }
 
+#[tokio::test]
+async fn it_will_not_process_a_query_batch_over_the_maximum_size() {
+    let http_request = make_fake_batch(
+        supergraph::Request::canned_builder()
+            .build()
+            .unwrap()
+            .supergraph_request,
+        None,
+    );
+    let config = serde_json::json!({
+        "batching": {
+            "enabled": true,
+            "mode" : "batch_http_link",
+            "maximum_size": 1
+        }
+    });
+    let response = crate::TestHarness::builder()
+        .configuration_json(config)
+        .unwrap()
+        .build_router()
+        .await
+        .unwrap()
+        .oneshot(router::Request::from(http_request))
+        .await
+        .unwrap()
+        .response;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    let data: serde_json::Value =
+        serde_json::from_slice(&get_body_bytes(response.into_body()).await.unwrap()).unwrap();
+    assert_eq!(
+        data["errors"][0]["extensions"]["code"],
+        serde_json::json!("BATCH_LIMIT_EXCEEDED")
+    );
+}
+
 #[tokio::test]
 async fn it_will_not_process_a_query_batch_without_enablement() {
     let expected_response: serde_json::Value = serde_json::from_str(include_str!(
@@ -571,3 +607,94 @@ async fn escaped_quotes_in_string_literal() {
     // The string literal made it through unchanged:
     assert!(subgraph_query.contains(r#"reviewsForAuthor(authorID:"\"1\"")"#));
 }
+
+#[tokio::test]
+async fn it_negotiates_the_response_format() {
+    let mut configuration = crate::Configuration::default();
+    configuration.content_negotiation = serde_json::from_value(serde_json::json!({
+        "graphql_response_json": true,
+        "cbor": true,
+        "clients": { "legacy": { "graphql_response_json": false } }
+    }))
+    .unwrap();
+    let mut router_service = from_supergraph_mock_callback_and_configuration(
+        move |req| {
+            Ok(SupergraphResponse::fake_builder()
+                .errors(vec![graphql::Error::builder()
+                    .message("invalid operation")
+                    .extension_code("INVALID_OPERATION")
+                    .build()])
+                .context(req.context)
+                .build()
+                .unwrap())
+        },
+        Arc::new(configuration),
+    )
+    .await;
+
+    async fn call(
+        mut router_service: impl Service<
+            router::Request,
+            Response = router::Response,
+            Error = tower::BoxError,
+        >,
+        accept: &'static str,
+        context: Context,
+    ) -> http::Response<hyper::Body> {
+        let request = SupergraphRequest::fake_builder()
+            .query("{ me { name } }")
+            .header(http::header::ACCEPT, accept)
+            .context(context)
+            .build()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap()
+            .response
+    }
+
+    // a response without data is a client error for application/graphql-response+json
+    let response = call(
+        &mut router_service,
+        "application/graphql-response+json, application/json;q=0.9",
+        Context::new(),
+    )
+    .await;
+    assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/graphql-response+json"
+    );
+
+    // unless it is disabled for the client
+    let context = Context::new();
+    context
+        .insert(crate::plugins::telemetry::CLIENT_NAME, "legacy".to_string())
+        .unwrap();
+    let response = call(
+        &mut router_service,
+        "application/graphql-response+json, application/json;q=0.9",
+        context,
+    )
+    .await;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        APPLICATION_JSON.essence_str()
+    );
+
+    let response = call(&mut router_service, "application/cbor", Context::new()).await;
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/cbor"
+    );
+    let body = get_body_bytes(response.into_body()).await.unwrap();
+    let response: graphql::Response = ciborium::from_reader(&body[..]).unwrap();
+    assert_eq!(response.errors[0].message, "invalid operation");
+}
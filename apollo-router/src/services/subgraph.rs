@@ -316,7 +316,9 @@ impl Response {
 
 impl Request {
     #[allow(dead_code)]
-    pub(crate) fn to_sha256(&self) -> String {
+    /// Hashes what makes a subgraph request identical to another one, to deduplicate
+    /// subscriptions. Headers listed in `ignored_headers` are left out of the hash.
+    pub(crate) fn to_sha256(&self, ignored_headers: &[String]) -> String {
         let mut hasher = Sha256::new();
         let http_req = &self.subgraph_request;
         hasher.update(http_req.method().as_str().as_bytes());
@@ -342,8 +344,20 @@ impl Request {
             hasher.update(query.as_bytes());
         }
 
-        // this assumes headers are in the same order
-        for (name, value) in http_req.headers() {
+        // sorted so that the order of the headers does not matter
+        let mut headers: Vec<_> = http_req
+            .headers()
+            .iter()
+            .filter(|(name, _)| {
+                !ignored_headers
+                    .iter()
+                    .any(|ignored| ignored.eq_ignore_ascii_case(name.as_str()))
+            })
+            .collect();
+        headers.sort_by(|(a_name, a_value), (b_name, b_value)| {
+            (a_name.as_str(), a_value.as_bytes()).cmp(&(b_name.as_str(), b_value.as_bytes()))
+        });
+        for (name, value) in headers {
             hasher.update(name.as_str().as_bytes());
             hasher.update(value.to_str().unwrap_or("ERROR").as_bytes());
         }
@@ -353,6 +367,8 @@ impl Request {
         {
             hasher.update(format!("{claim:?}").as_bytes());
         }
+        // requests filtered differently by the authorization plugin are not identical
+        hasher.update(format!("{:?}", self.authorization).as_bytes());
         let body = http_req.body();
         if let Some(operation_name) = &body.operation_name {
             hasher.update(operation_name.as_bytes());
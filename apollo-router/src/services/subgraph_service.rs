@@ -18,12 +18,14 @@ use http::HeaderValue;
 use http::Request;
 use hyper_rustls::ConfigBuilderExt;
 use itertools::Itertools;
+use lru::LruCache;
 use mediatype::names::APPLICATION;
 use mediatype::names::JSON;
 use mediatype::MediaType;
 use mime::APPLICATION_JSON;
 use opentelemetry::Key;
 use opentelemetry::KeyValue;
+use parking_lot::Mutex;
 use rustls::RootCertStore;
 use serde::Serialize;
 use tokio::select;
@@ -47,6 +49,7 @@ use super::Plugins;
 use crate::batching::assemble_batch;
 use crate::batching::BatchQuery;
 use crate::batching::BatchQueryInfo;
+use crate::cache::DEFAULT_CACHE_CAPACITY;
 use crate::configuration::Batching;
 use crate::configuration::BatchingMode;
 use crate::configuration::TlsClientAuth;
@@ -62,16 +65,22 @@ use crate::plugins::subscription::SubscriptionConfig;
 use crate::plugins::subscription::SubscriptionMode;
 use crate::plugins::subscription::WebSocketConfiguration;
 use crate::plugins::subscription::SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS;
+use crate::plugins::subscription_coordination::Coordinator;
 use crate::plugins::telemetry::config_new::events::log_event;
 use crate::plugins::telemetry::config_new::events::SubgraphEventRequest;
 use crate::plugins::telemetry::config_new::events::SubgraphEventResponse;
 use crate::plugins::telemetry::consts::SUBGRAPH_REQUEST_SPAN_NAME;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::telemetry::LOGGING_DISPLAY_HEADERS;
+use crate::protocols::kafka;
+use crate::protocols::kafka::KafkaSubscription;
 use crate::protocols::websocket::convert_websocket_stream;
 use crate::protocols::websocket::GraphqlWebSocket;
 use crate::query_planner::OperationKind;
 use crate::services::layers::apq;
+use crate::services::layers::memory_limits;
+use crate::services::layers::memory_limits::MemoryLimitExceeded;
+use crate::services::layers::memory_limits::RequestMemory;
 use crate::services::SubgraphRequest;
 use crate::services::SubgraphResponse;
 use crate::Configuration;
@@ -128,6 +137,8 @@ pub(crate) struct SubgraphService {
     /// If a subgraph sends the error message PERSISTED_QUERY_NOT_SUPPORTED,
     /// apq is set to false
     apq: Arc<AtomicBool>,
+    /// Hashes of the queries sent with APQ, to avoid hashing large queries on every request
+    apq_hashes: Arc<Mutex<ApqHashes>>,
     /// Subscription config if enabled
     subscription_config: Option<SubscriptionConfig>,
     notify: Notify<String, graphql::Response>,
@@ -170,6 +181,7 @@ impl SubgraphService {
             client_factory,
             service: Arc::new(service.into()),
             apq: Arc::new(<AtomicBool>::new(enable_apq)),
+            apq_hashes: Arc::new(Mutex::new(ApqHashes::new(APQ_HASHES_MAX_BYTES))),
             subscription_config,
             notify,
         })
@@ -228,7 +240,7 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                 }
             };
             if subscription_config.enable_deduplication {
-                request.to_sha256()
+                request.to_sha256(&subscription_config.deduplication_key.ignored_headers)
             } else {
                 Uuid::new_v4().to_string()
             }
@@ -247,6 +259,7 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
         let client_factory = self.client_factory.clone();
 
         let arc_apq_enabled = self.apq.clone();
+        let apq_hashes = self.apq_hashes.clone();
 
         let mut notify = self.notify.clone();
 
@@ -276,13 +289,33 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                         )
                         .await;
                     }
+                    Some(SubscriptionMode::Kafka(kafka_conf)) => {
+                        // call_kafka consumes the events from Kafka instead of calling the subgraph
+                        return call_kafka(
+                            notify,
+                            request,
+                            context,
+                            service_name,
+                            kafka_conf,
+                            hashed_request,
+                        )
+                        .await;
+                    }
                     Some(SubscriptionMode::Callback(CallbackMode {
                         public_url,
                         heartbeat_interval,
                         ..
                     })) => {
+                        // The subscription plugin puts its coordinator in the context if the callbacks
+                        // are relayed between the replicas
+                        let coordinator = context
+                            .extensions()
+                            .with_lock(|lock| lock.get::<Arc<Coordinator>>().cloned());
                         // Hash the subgraph_request
-                        let subscription_id = hashed_request;
+                        let subscription_id = match &coordinator {
+                            Some(coordinator) => coordinator.subscription_id(&hashed_request),
+                            None => hashed_request,
+                        };
 
                         // Call create_or_subscribe on notify
                         let (handle, created) = notify
@@ -317,6 +350,19 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                                 .build());
                         }
 
+                        // Receive the callbacks delivered to the other router replicas
+                        if let Some(coordinator) = &coordinator {
+                            coordinator.serve(&subscription_id).await.map_err(|err| {
+                                FetchError::SubrequestHttpError {
+                                    service: service_name.clone(),
+                                    reason: format!(
+                                        "cannot receive the callbacks relayed by the other router replicas: {err}"
+                                    ),
+                                    status_code: None,
+                                }
+                            })?;
+                        }
+
                         // If not then put the subscription_id in the extensions for callback mode and continue
                         // Do this if the topic doesn't already exist
                         let mut callback_url = public_url.clone();
@@ -397,7 +443,7 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                 extensions,
             } = body.clone();
 
-            let hash_value = apq::calculate_hash_for_query(query.as_deref().unwrap_or_default());
+            let hash_value = apq_hash(&apq_hashes, query.as_deref().unwrap_or_default());
 
             let persisted_query = serde_json_bytes::json!({
                 HASH_VERSION_KEY: HASH_VERSION_VALUE,
@@ -428,7 +474,19 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
             // If PersistedQueryNotFound, add the original query to the request and retry.
             // Else, return the response like before.
             let gql_response = response.response.body();
-            match get_apq_error(gql_response) {
+            let apq_error = get_apq_error(gql_response);
+            u64_counter!(
+                "apollo.router.operations.subgraph.apq",
+                "Number of subgraph requests sent with an automatic persisted query",
+                1,
+                "subgraph.name" = service_name.clone(),
+                "apq.result" = match apq_error {
+                    APQError::PersistedQueryNotSupported => "unsupported",
+                    APQError::PersistedQueryNotFound => "miss",
+                    APQError::Other => "hit",
+                }
+            );
+            match apq_error {
                 APQError::PersistedQueryNotSupported => {
                     apq_enabled.store(false, Relaxed);
                     call_http(
@@ -709,6 +767,92 @@ async fn call_websocket(
     ))
 }
 
+/// call kafka consumes the events of a subscription from the Kafka topics of its root field
+async fn call_kafka(
+    mut notify: Notify<String, graphql::Response>,
+    request: SubgraphRequest,
+    context: Context,
+    service_name: String,
+    subgraph_cfg: &KafkaSubscription,
+    subscription_hash: String,
+) -> Result<SubgraphResponse, BoxError> {
+    let SubgraphRequest {
+        subgraph_request,
+        subscription_stream,
+        connection_closed_signal,
+        ..
+    } = request;
+    let subscription_stream_tx =
+        subscription_stream.ok_or_else(|| FetchError::SubrequestKafkaError {
+            service: service_name.clone(),
+            reason: "cannot get the subscription stream".to_string(),
+        })?;
+
+    let (handle, created) = notify.create_or_subscribe(subscription_hash, false).await?;
+    tracing::info!(
+        monotonic_counter.apollo.router.operations.subscriptions = 1u64,
+        subscriptions.mode = %"kafka",
+        subscriptions.deduplicated = !created,
+        subgraph.service.name = service_name,
+    );
+    if !created {
+        subscription_stream_tx
+            .send(Box::pin(handle.into_stream()))
+            .await?;
+        tracing::info!(
+            monotonic_counter.apollo_router_deduplicated_subscriptions_total = 1u64,
+            mode = %"kafka",
+        );
+
+        // Dedup happens here
+        return Ok(SubgraphResponse::builder()
+            .context(context)
+            .subgraph_name(service_name.clone())
+            .extensions(Object::default())
+            .build());
+    }
+
+    let events = kafka::subscribe(subgraph_cfg, subgraph_request.body())
+        .await
+        .map_err(|err| FetchError::SubrequestKafkaError {
+            service: service_name.clone(),
+            reason: err.to_string(),
+        })?;
+
+    let (handle_sink, handle_stream) = handle.split();
+
+    tokio::task::spawn(async move {
+        match connection_closed_signal {
+            Some(mut connection_closed_signal) => select! {
+                // We prefer to specify the order of checks within the select
+                biased;
+                _ = events
+                    .map(Ok::<_, graphql::Error>)
+                    .forward(handle_sink) => {
+                    tracing::debug!("kafka stream empty");
+                },
+                _ = connection_closed_signal.recv() => {
+                    tracing::debug!("connection_closed_signal triggered");
+                }
+            },
+            None => {
+                let _ = events
+                    .map(Ok::<_, graphql::Error>)
+                    .forward(handle_sink)
+                    .await;
+            }
+        }
+    });
+
+    subscription_stream_tx.send(Box::pin(handle_stream)).await?;
+
+    Ok(SubgraphResponse::builder()
+        .context(context)
+        .subgraph_name(service_name)
+        .extensions(Object::default())
+        .build())
+}
+
 // Utility function to extract uri details.
 fn get_uri_details(uri: &hyper::Uri) -> (&str, u16, &str) {
     let port = uri.port_u16().unwrap_or_else(|| {
@@ -1432,6 +1576,15 @@ async fn do_fetch(
         })
         .map_err(|err| {
             tracing::error!(fetch_error = ?err);
+            // the timeouts of the HTTP client keep their own error codes
+            let err = match err.downcast::<FetchError>() {
+                Ok(err) => match *err {
+                    timeout @ (FetchError::SubrequestConnectTimeout { .. }
+                    | FetchError::SubrequestFirstByteTimeout { .. }) => return timeout,
+                    err => BoxError::from(err),
+                },
+                Err(err) => err,
+            };
             FetchError::SubrequestHttpError {
                 status_code: None,
                 service: service_name.to_string(),
@@ -1445,16 +1598,21 @@ async fn do_fetch(
     let content_type = get_graphql_content_type(service_name, &parts);
 
     let body = if content_type.is_ok() {
-        let body = body
-            .to_bytes()
+        let body = memory_limits::read_body(body, RequestMemory::from_context(context).as_ref())
             .instrument(tracing::debug_span!("aggregate_response_data"))
             .await
             .map_err(|err| {
                 tracing::error!(fetch_error = ?err);
-                FetchError::SubrequestHttpError {
-                    status_code: Some(parts.status.as_u16()),
-                    service: service_name.to_string(),
-                    reason: err.to_string(),
+                if err.is::<MemoryLimitExceeded>() {
+                    FetchError::SubrequestMemoryLimitExceeded {
+                        service: service_name.to_string(),
+                    }
+                } else {
+                    FetchError::SubrequestHttpError {
+                        status_code: Some(parts.status.as_u16()),
+                        service: service_name.to_string(),
+                        reason: err.to_string(),
+                    }
                 }
             });
         if let Ok(body) = &body {
@@ -1543,6 +1701,59 @@ fn get_websocket_request(
     Ok(request)
 }
 
+/// Maximum size of the queries whose APQ hashes are cached, for each subgraph
+const APQ_HASHES_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// Cache of the APQ hashes of the most recent queries.
+///
+/// The queries are the keys of the cache, so it is bounded by their total size as well as by
+/// their number.
+struct ApqHashes {
+    hashes: LruCache<String, String>,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl ApqHashes {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            hashes: LruCache::new(DEFAULT_CACHE_CAPACITY),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    fn get(&mut self, query: &str) -> Option<String> {
+        self.hashes.get(query).cloned()
+    }
+
+    fn insert(&mut self, query: &str, hash: String) {
+        if query.len() > self.max_bytes {
+            return;
+        }
+        if let Some((evicted, _)) = self.hashes.push(query.to_string(), hash) {
+            self.bytes -= evicted.len();
+        }
+        self.bytes += query.len();
+        while self.bytes > self.max_bytes {
+            match self.hashes.pop_lru() {
+                Some((evicted, _)) => self.bytes -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Returns the APQ hash of a query, from the cache if it was hashed already.
+fn apq_hash(apq_hashes: &Mutex<ApqHashes>, query: &str) -> String {
+    if let Some(hash) = apq_hashes.lock().get(query) {
+        return hash;
+    }
+    let hash = apq::calculate_hash_for_query(query);
+    apq_hashes.lock().insert(query, hash.clone());
+    hash
+}
+
 fn get_apq_error(gql_response: &graphql::Response) -> APQError {
     for error in &gql_response.errors {
         // Check if error message is an APQ error
@@ -2282,10 +2493,15 @@ mod tests {
                     )]
                     .into(),
                 }),
+                kafka: None,
             },
             enable_deduplication: true,
             max_opened_subscriptions: None,
             queue_capacity: None,
+            event_buffer: None,
+            quotas: Default::default(),
+            deduplication_key: Default::default(),
+            callback_coordination: None,
         }
     }
 
@@ -3005,6 +3221,33 @@ mod tests {
         assert_eq!(resp.response.body(), &expected_resp);
     }
 
+    #[test]
+    fn test_apq_hash_is_cached() {
+        let hashes = Mutex::new(ApqHashes::new(APQ_HASHES_MAX_BYTES));
+        let query = "query { me { name } }";
+
+        let hash = apq_hash(&hashes, query);
+        assert_eq!(hash, apq::calculate_hash_for_query(query));
+        assert_eq!(hashes.lock().get(query), Some(hash.clone()));
+        assert_eq!(apq_hash(&hashes, query), hash);
+    }
+
+    #[test]
+    fn test_apq_hashes_are_bounded_by_size() {
+        let mut hashes = ApqHashes::new(30);
+        let first = "query { me { name } }";
+        let second = "query { me { id } }";
+
+        hashes.insert(first, "first".to_string());
+        hashes.insert(second, "second".to_string());
+        assert_eq!(hashes.get(first), None);
+        assert_eq!(hashes.get(second), Some("second".to_string()));
+        assert_eq!(hashes.bytes, second.len());
+
+        hashes.insert(&"a".repeat(31), "too large".to_string());
+        assert_eq!(hashes.hashes.len(), 1);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_apq_enabled_subgraph_configuration() {
         let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
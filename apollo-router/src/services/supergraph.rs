@@ -24,9 +24,11 @@ use crate::http_ext::TryIntoHeaderValue;
 use crate::json_ext::Path;
 use crate::Context;
 
+pub(crate) mod event_buffer;
 pub(crate) mod service;
 #[cfg(test)]
 mod tests;
+pub(crate) mod variable_transforms;
 
 pub type BoxService = tower::util::BoxService<Request, Response, BoxError>;
 pub type BoxCloneService = tower::util::BoxCloneService<Request, Response, BoxError>;
@@ -0,0 +1,250 @@
+//! Per subscription buffer of the events waiting to be sent to the client.
+//!
+//! Events of a subscription are read from the subgraph by a separate task, and wait in a bounded
+//! buffer while the previous events are executed and sent to the client. When a client can't keep
+//! up with the events rate, the buffer policy decides which events are dropped, instead of
+//! slowing down the reads from the subgraph shared by deduplicated subscriptions.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+
+use futures::Stream;
+use futures::StreamExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::graphql;
+use crate::services::subgraph::BoxGqlStream;
+
+/// Buffer of the events waiting to be sent to each subscription's client
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct EventBufferConfig {
+    /// Maximum number of events waiting to be sent to a client
+    pub(crate) capacity: usize,
+    /// Which events are dropped when the buffer is full
+    pub(crate) policy: BufferPolicy,
+}
+
+impl Default for EventBufferConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 128,
+            policy: BufferPolicy::default(),
+        }
+    }
+}
+
+/// Which events are dropped when the buffer is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BufferPolicy {
+    /// Drop the oldest waiting event to make room for the new one
+    #[default]
+    DropOldest,
+    /// Drop the new event
+    DropNewest,
+    /// Only keep the latest event, replacing the events waiting to be sent
+    Conflate,
+}
+
+impl BufferPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BufferPolicy::DropOldest => "drop_oldest",
+            BufferPolicy::DropNewest => "drop_newest",
+            BufferPolicy::Conflate => "conflate",
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<graphql::Response>,
+    /// The subgraph stream ended
+    done: bool,
+    waker: Option<Waker>,
+}
+
+struct Buffer {
+    config: EventBufferConfig,
+    subgraph_name: String,
+    state: Mutex<State>,
+}
+
+impl Buffer {
+    fn push(&self, event: graphql::Response) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        let full = state.events.len() >= self.config.capacity;
+        let dropped = match self.config.policy {
+            BufferPolicy::DropOldest => {
+                let dropped = if full {
+                    state.events.pop_front().map_or(0, |_| 1)
+                } else {
+                    0
+                };
+                state.events.push_back(event);
+                dropped
+            }
+            BufferPolicy::DropNewest => {
+                if full {
+                    1
+                } else {
+                    state.events.push_back(event);
+                    0
+                }
+            }
+            BufferPolicy::Conflate => {
+                let dropped = state.events.len() as u64;
+                state.events.clear();
+                state.events.push_back(event);
+                dropped
+            }
+        };
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        drop(state);
+
+        if dropped > 0 {
+            u64_counter!(
+                "apollo.router.subscriptions.events.dropped",
+                "Number of subscription events dropped because the client could not keep up",
+                dropped,
+                "subgraph.name" = self.subgraph_name.clone(),
+                "policy" = self.config.policy.as_str()
+            );
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The events of a subscription, read from the subgraph in the background
+struct BufferedEvents {
+    buffer: Arc<Buffer>,
+    /// Stops the background reads when the subscription ends
+    _closed: oneshot::Sender<()>,
+}
+
+impl Stream for BufferedEvents {
+    type Item = graphql::Response;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.buffer.state.lock().expect("lock poisoned");
+        if let Some(event) = state.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if state.done {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Reads the events of a subscription in the background, keeping those not sent to the client
+/// yet in a buffer following the configured policy.
+pub(crate) fn buffered(
+    mut events: BoxGqlStream,
+    config: &EventBufferConfig,
+    subgraph_name: &str,
+) -> BoxGqlStream {
+    let buffer = Arc::new(Buffer {
+        config: config.clone(),
+        subgraph_name: subgraph_name.to_string(),
+        state: Default::default(),
+    });
+    let (closed_tx, mut closed_rx) = oneshot::channel::<()>();
+
+    let reader = buffer.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut closed_rx => break,
+                event = events.next() => match event {
+                    Some(event) => reader.push(event),
+                    None => break,
+                },
+            }
+        }
+        reader.close();
+    });
+
+    Box::pin(BufferedEvents {
+        buffer,
+        _closed: closed_tx,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(value: u64) -> graphql::Response {
+        graphql::Response::builder()
+            .data(serde_json_bytes::json!({ "value": value }))
+            .build()
+    }
+
+    fn buffer(policy: BufferPolicy) -> Buffer {
+        Buffer {
+            config: EventBufferConfig {
+                capacity: 2,
+                policy,
+            },
+            subgraph_name: "events".to_string(),
+            state: Default::default(),
+        }
+    }
+
+    fn waiting(buffer: &Buffer) -> Vec<graphql::Response> {
+        buffer
+            .state
+            .lock()
+            .unwrap()
+            .events
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn applies_policies() {
+        let drop_oldest = buffer(BufferPolicy::DropOldest);
+        let drop_newest = buffer(BufferPolicy::DropNewest);
+        let conflate = buffer(BufferPolicy::Conflate);
+        for value in 1..=3 {
+            drop_oldest.push(event(value));
+            drop_newest.push(event(value));
+            conflate.push(event(value));
+        }
+        assert_eq!(waiting(&drop_oldest), vec![event(2), event(3)]);
+        assert_eq!(waiting(&drop_newest), vec![event(1), event(2)]);
+        assert_eq!(waiting(&conflate), vec![event(3)]);
+    }
+
+    #[tokio::test]
+    async fn forwards_events_until_the_end() {
+        let events = futures::stream::iter((1..=3).map(event)).boxed();
+        let buffered = buffered(events, &EventBufferConfig::default(), "events");
+        assert_eq!(
+            buffered.collect::<Vec<_>>().await,
+            vec![event(1), event(2), event(3)]
+        );
+    }
+}
@@ -28,6 +28,7 @@ use tracing_futures::Instrument;
 
 use crate::batching::BatchQuery;
 use crate::configuration::Batching;
+use crate::configuration::Limits;
 use crate::context::OPERATION_NAME;
 use crate::error::CacheResolverError;
 use crate::graphql;
@@ -50,6 +51,7 @@ use crate::query_planner::BridgeQueryPlannerPool;
 use crate::query_planner::CachingQueryPlanner;
 use crate::query_planner::InMemoryCachePlanner;
 use crate::query_planner::QueryPlanResult;
+use crate::query_planner::TYPE_CONDITIONED_FETCHING_OPT_OUT;
 use crate::router_factory::create_plugins;
 use crate::router_factory::create_subgraph_services;
 use crate::services::execution::QueryPlan;
@@ -64,6 +66,9 @@ use crate::services::subgraph::BoxGqlStream;
 use crate::services::subgraph_service::MakeSubgraphService;
 use crate::services::subgraph_service::SubgraphServiceFactory;
 use crate::services::supergraph;
+use crate::services::supergraph::event_buffer;
+use crate::services::supergraph::variable_transforms;
+use crate::services::supergraph::variable_transforms::VariableTransforms;
 use crate::services::ExecutionRequest;
 use crate::services::ExecutionResponse;
 use crate::services::ExecutionServiceFactory;
@@ -71,6 +76,7 @@ use crate::services::QueryPlannerContent;
 use crate::services::QueryPlannerResponse;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
+use crate::spec::operation_limits;
 use crate::spec::operation_limits::OperationLimits;
 use crate::spec::Schema;
 use crate::Configuration;
@@ -78,6 +84,8 @@ use crate::Context;
 use crate::Notify;
 
 pub(crate) const FIRST_EVENT_CONTEXT_KEY: &str = "apollo_router::supergraph::first_event";
+const TYPE_CONDITIONED_FETCHING_HEADER_NAME: &str = "apollo-type-conditioned-fetching";
+const TYPE_CONDITIONED_FETCHING_EXTENSION: &str = "typeConditionedFetching";
 
 /// An [`IndexMap`] of available plugins.
 pub(crate) type Plugins = IndexMap<String, Box<dyn DynPlugin>>;
@@ -89,6 +97,8 @@ pub(crate) struct SupergraphService {
     query_planner_service: CachingQueryPlanner<BridgeQueryPlannerPool>,
     schema: Arc<Schema>,
     notify: Notify<String, graphql::Response>,
+    variable_transforms: Arc<VariableTransforms>,
+    operation_limits: Arc<Limits>,
 }
 
 #[buildstructor::buildstructor]
@@ -99,12 +109,16 @@ impl SupergraphService {
         execution_service_factory: ExecutionServiceFactory,
         schema: Arc<Schema>,
         notify: Notify<String, graphql::Response>,
+        variable_transforms: Option<Arc<VariableTransforms>>,
+        operation_limits: Option<Arc<Limits>>,
     ) -> Self {
         SupergraphService {
             query_planner_service,
             execution_service_factory,
             schema,
             notify,
+            variable_transforms: variable_transforms.unwrap_or_default(),
+            operation_limits: operation_limits.unwrap_or_default(),
         }
     }
 }
@@ -135,6 +149,8 @@ impl Service<SupergraphRequest> for SupergraphService {
             schema,
             req,
             self.notify.clone(),
+            self.variable_transforms.clone(),
+            self.operation_limits.clone(),
         )
         .or_else(|error: BoxError| async move {
             let errors = vec![crate::error::Error {
@@ -165,11 +181,17 @@ async fn service_call(
     schema: Arc<Schema>,
     req: SupergraphRequest,
     notify: Notify<String, graphql::Response>,
+    variable_transforms: Arc<VariableTransforms>,
+    operation_limits: Arc<Limits>,
 ) -> Result<SupergraphResponse, BoxError> {
     let context = req.context;
     let body = req.supergraph_request.body();
     let variables = body.variables.clone();
 
+    if type_conditioned_fetching_opt_out(&req.supergraph_request) {
+        let _ = context.insert(TYPE_CONDITIONED_FETCHING_OPT_OUT, true);
+    }
+
     let QueryPlannerResponse {
         content,
         context,
@@ -184,6 +206,7 @@ async fn service_call(
             .query
             .clone()
             .expect("query presence was checked before"),
+        &operation_limits,
     )
     .await
     {
@@ -230,6 +253,8 @@ async fn service_call(
             let query_metrics = plan.query_metrics;
             context.extensions().with_lock(|mut lock| {
                 let _ = lock.insert::<OperationLimits<u32>>(query_metrics);
+                // for the diagnostic bundle of the request, if it panics
+                let _ = lock.insert(plan.clone());
             });
 
             let operation_name = body.operation_name.clone();
@@ -308,13 +333,29 @@ async fn service_call(
                 *res.response.status_mut() = StatusCode::BAD_REQUEST;
                 Ok(res)
             } else {
+                let mut supergraph_request = req.supergraph_request;
+                if !variable_transforms.is_empty() {
+                    let operation_name = plan
+                        .query
+                        .operation(operation_name.as_deref())
+                        .and_then(|operation| operation.name.clone());
+                    let (parts, mut body) = supergraph_request.into_parts();
+                    variable_transforms::apply(
+                        &variable_transforms,
+                        operation_name.as_deref(),
+                        &parts.headers,
+                        &mut body.variables,
+                    );
+                    supergraph_request = http::Request::from_parts(parts, body);
+                }
+
                 if is_subscription {
                     let ctx = context.clone();
                     let (subs_tx, subs_rx) = mpsc::channel(1);
                     let query_plan = plan.clone();
                     let execution_service_factory_cloned = execution_service_factory.clone();
                     let cloned_supergraph_req =
-                        clone_supergraph_request(&req.supergraph_request, context.clone());
+                        clone_supergraph_request(&supergraph_request, context.clone());
                     // Spawn task for subscription
                     tokio::spawn(async move {
                         subscription_task(
@@ -334,7 +375,7 @@ async fn service_call(
                     .create()
                     .oneshot(
                         ExecutionRequest::internal_builder()
-                            .supergraph_request(req.supergraph_request)
+                            .supergraph_request(supergraph_request)
                             .query_plan(plan.clone())
                             .context(context)
                             .and_subscription_tx(subscription_tx)
@@ -499,6 +540,10 @@ async fn subscription_task(
         }
     };
 
+    if let Some(config) = &subscription_config.event_buffer {
+        receiver = event_buffer::buffered(receiver, config, &service_name);
+    }
+
     if limit_is_set {
         OPENED_SUBSCRIPTIONS.fetch_add(1, Ordering::Relaxed);
     }
@@ -537,7 +582,7 @@ async fn subscription_task(
                             tracing::info!(http.request.body = ?val, apollo.subgraph.name = %service_name, "Subscription event body from subgraph {service_name:?}");
                         }
                         val.created_at = Some(Instant::now());
-                        let res = dispatch_event(&supergraph_req, &execution_service_factory, query_plan.as_ref(), context.clone(), val, sender.clone())
+                        let res = dispatch_event(&supergraph_req, &execution_service_factory, query_plan.as_ref(), context.fork(), val, sender.clone())
                             .instrument(tracing::info_span!(SUBSCRIPTION_EVENT_SPAN_NAME,
                                 graphql.operation.name = %operation_name,
                                 otel.kind = "INTERNAL",
@@ -670,12 +715,28 @@ async fn dispatch_event(
     res
 }
 
+/// Operations opt out of type conditioned fetching with a header or a request extension set to
+/// `false`. The query planner ignores it unless `allow_opt_out` is enabled.
+fn type_conditioned_fetching_opt_out(request: &http::Request<graphql::Request>) -> bool {
+    request
+        .headers()
+        .get(TYPE_CONDITIONED_FETCHING_HEADER_NAME)
+        .map_or(false, |value| value.as_bytes().eq_ignore_ascii_case(b"false"))
+        || request
+            .body()
+            .extensions
+            .get(TYPE_CONDITIONED_FETCHING_EXTENSION)
+            .and_then(|value| value.as_bool())
+            == Some(false)
+}
+
 async fn plan_query(
     mut planning: CachingQueryPlanner<BridgeQueryPlannerPool>,
     operation_name: Option<String>,
     context: Context,
     schema: Arc<Schema>,
     query_str: String,
+    operation_limits: &Limits,
 ) -> Result<QueryPlannerResponse, CacheResolverError> {
     // FIXME: we have about 80 tests creating a supergraph service and crafting a supergraph request for it
     // none of those tests create an executable document to put it in the context, and the document cannot be created
@@ -684,26 +745,31 @@ async fn plan_query(
     // tests will pass.
     // During a regular request, `ParsedDocument` is already populated during query analysis.
     // Some tests do populate the document, so we only do it if it's not already there.
-    if !context.extensions().with_lock(|lock| {
-        lock.contains_key::<crate::services::layers::query_analysis::ParsedDocument>()
+    let doc = match context.extensions().with_lock(|lock| {
+        lock.get::<crate::services::layers::query_analysis::ParsedDocument>()
+            .cloned()
     }) {
-        let doc = crate::spec::Query::parse_document(
-            &query_str,
-            operation_name.as_deref(),
-            &schema,
-            &Configuration::default(),
-        )
-        .map_err(crate::error::QueryPlannerError::from)?;
-        context.extensions().with_lock(|mut lock| {
-            lock.insert::<crate::services::layers::query_analysis::ParsedDocument>(doc)
-        });
-    }
+        Some(doc) => doc,
+        None => {
+            let doc = crate::spec::Query::parse_document(
+                &query_str,
+                operation_name.as_deref(),
+                &schema,
+                &Configuration::default(),
+            )
+            .map_err(crate::error::QueryPlannerError::from)?;
+            context.extensions().with_lock(|mut lock| {
+                lock.insert::<crate::services::layers::query_analysis::ParsedDocument>(doc.clone())
+            });
+            doc
+        }
+    };
 
     let qpr = planning
         .call(
             query_planner::CachingRequest::builder()
-                .query(query_str)
-                .and_operation_name(operation_name)
+                .query(query_str.clone())
+                .and_operation_name(operation_name.clone())
                 .context(context.clone())
                 .build(),
         )
@@ -713,6 +779,29 @@ async fn plan_query(
         ))
         .await?;
 
+    // The operation limits are checked for each request rather than when planning, as their tier
+    // depends on the client. The planned operations are measured once, with their plans
+    let measured = match &qpr.content {
+        Some(QueryPlannerContent::Plan { plan }) => Some((plan.query_metrics, false)),
+        // the operations answered without a plan, like the introspection operations
+        Some(_) => Some((
+            operation_limits::measure(&doc.executable, operation_name.as_deref())
+                .map_err(crate::error::QueryPlannerError::from)?,
+            operation_limits::is_introspection(&doc.executable, operation_name.as_deref()),
+        )),
+        None => None,
+    };
+    if let Some((measured, is_introspection)) = measured {
+        operation_limits::check(
+            operation_limits,
+            &context,
+            &query_str,
+            operation_name.as_deref(),
+            measured,
+            is_introspection,
+        )?;
+    }
+
     Ok(qpr)
 }
 
@@ -835,6 +924,14 @@ impl PluggableSupergraphServiceBuilder {
             subgraph_service_factory,
             schema,
             plugins: self.plugins,
+            variable_transforms: Arc::new(
+                configuration
+                    .supergraph
+                    .experimental_variable_transforms
+                    .clone(),
+            ),
+            operation_limits: Arc::new(configuration.limits.clone()),
+            notify: configuration.notify.clone(),
             config: configuration,
         })
     }
@@ -848,6 +945,9 @@ pub(crate) struct SupergraphCreator {
     schema: Arc<Schema>,
     config: Arc<Configuration>,
     plugins: Arc<Plugins>,
+    variable_transforms: Arc<VariableTransforms>,
+    operation_limits: Arc<Limits>,
+    notify: Notify<String, graphql::Response>,
 }
 
 pub(crate) trait HasPlugins {
@@ -905,7 +1005,9 @@ impl SupergraphCreator {
                 subgraph_service_factory: self.subgraph_service_factory.clone(),
             })
             .schema(self.schema.clone())
-            .notify(self.config.notify.clone())
+            .notify(self.notify.clone())
+            .variable_transforms(self.variable_transforms.clone())
+            .operation_limits(self.operation_limits.clone())
             .build();
 
         let shaping = self
@@ -915,7 +1017,7 @@ impl SupergraphCreator {
             .and_then(|plugin| plugin.1.as_any().downcast_ref::<TrafficShaping>())
             .expect("traffic shaping should always be part of the plugin list");
 
-        let supergraph_service = AllowOnlyHttpPostMutationsLayer::default()
+        let supergraph_service = AllowOnlyHttpPostMutationsLayer::new(&self.config)
             .layer(shaping.supergraph_service_internal(supergraph_service));
 
         ServiceBuilder::new()
@@ -930,10 +1032,44 @@ impl SupergraphCreator {
             )
     }
 
+    /// Creates the supergraph service of a contract variant, planning with its own query planner
+    /// and sharing the plugins and the subgraph services of this supergraph. Nothing is broadcast
+    /// to the subscriptions, and those of the variant get their own broadcasts.
+    pub(crate) async fn contract_variant(
+        &self,
+        planner: BridgeQueryPlannerPool,
+    ) -> Result<SupergraphCreator, BoxError> {
+        let schema = planner.schema();
+        let subgraph_schemas = planner.subgraph_schemas();
+        let query_planner_service = CachingQueryPlanner::new(
+            planner,
+            schema.clone(),
+            subgraph_schemas,
+            &self.config,
+            IndexMap::default(),
+        )
+        .await?;
+
+        Ok(SupergraphCreator {
+            query_planner_service,
+            subgraph_service_factory: self.subgraph_service_factory.clone(),
+            schema,
+            config: self.config.clone(),
+            plugins: self.plugins.clone(),
+            variable_transforms: self.variable_transforms.clone(),
+            operation_limits: self.operation_limits.clone(),
+            notify: self.config.notify.with_own_broadcasts(),
+        })
+    }
+
     pub(crate) fn previous_cache(&self) -> InMemoryCachePlanner {
         self.query_planner_service.previous_cache()
     }
 
+    pub(crate) async fn save_warm_state(&self) -> Result<(), BoxError> {
+        self.query_planner_service.save_warm_state().await
+    }
+
     pub(crate) fn planners(&self) -> Vec<Arc<Planner<QueryPlanResult>>> {
         self.query_planner_service.planners()
     }
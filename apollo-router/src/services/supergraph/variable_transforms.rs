@@ -0,0 +1,151 @@
+//! Declarative transforms of the variables of operations.
+//!
+//! The transforms are listed per operation name and variable name, and applied in order once the
+//! variables are validated, before the operation is executed.
+
+use std::collections::HashMap;
+
+use http::HeaderMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Value;
+
+use crate::json_ext::Object;
+
+/// Transforms of the variables of each operation, by operation name and variable name
+pub(crate) type VariableTransforms = HashMap<String, HashMap<String, Vec<VariableTransform>>>;
+
+/// Transform of a variable
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum VariableTransform {
+    /// Removes the whitespace at the start and the end of string values
+    Trim,
+    /// Converts string values to lowercase
+    Lowercase,
+    /// Converts string values to uppercase
+    Uppercase,
+    /// Sets the variable to this string when it is missing or null
+    Default(String),
+    /// Sets the variable to the first value of this request header, without its parameters, when
+    /// the variable is missing or null
+    DefaultFromHeader(String),
+}
+
+/// Applies the transforms configured for an operation to its variables
+pub(crate) fn apply(
+    transforms: &VariableTransforms,
+    operation_name: Option<&str>,
+    headers: &HeaderMap,
+    variables: &mut Object,
+) {
+    let Some(operation_transforms) = operation_name.and_then(|name| transforms.get(name)) else {
+        return;
+    };
+    for (name, transforms) in operation_transforms {
+        let key = ByteString::from(name.as_str());
+        for transform in transforms {
+            let value = variables.get(&key).filter(|value| !value.is_null());
+            if let Some(value) = transform.apply(value, headers) {
+                variables.insert(key.clone(), value);
+            }
+        }
+    }
+}
+
+impl VariableTransform {
+    /// The new value of the variable, or `None` if the transform keeps it as is
+    fn apply(&self, value: Option<&Value>, headers: &HeaderMap) -> Option<Value> {
+        match (self, value) {
+            (VariableTransform::Trim, Some(Value::String(s))) => {
+                Some(Value::String(s.as_str().trim().into()))
+            }
+            (VariableTransform::Lowercase, Some(Value::String(s))) => {
+                Some(Value::String(s.as_str().to_lowercase().into()))
+            }
+            (VariableTransform::Uppercase, Some(Value::String(s))) => {
+                Some(Value::String(s.as_str().to_uppercase().into()))
+            }
+            (VariableTransform::Default(default), None) => {
+                Some(Value::String(default.as_str().into()))
+            }
+            (VariableTransform::DefaultFromHeader(header), None) => {
+                // like `fr-CH` for `accept-language: fr-CH, fr;q=0.9, en;q=0.8`
+                let value = headers.get(header)?.to_str().ok()?;
+                let first = value.split(',').next()?.split(';').next()?.trim();
+                (!first.is_empty()).then(|| Value::String(first.into()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::ACCEPT_LANGUAGE;
+    use http::HeaderValue;
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    fn transforms(yaml: &str) -> VariableTransforms {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn variables_of_listed_operations_are_transformed() {
+        let transforms = transforms(
+            r#"
+            Signup:
+              email: [trim, lowercase]
+              locale:
+                - default_from_header: accept-language
+                - default: en-US
+              country: [uppercase]
+            "#,
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_static("fr-CH, fr;q=0.9, en;q=0.8"),
+        );
+
+        let mut variables = json!({ "email": "  Jane@Example.COM ", "country": 33 })
+            .as_object()
+            .unwrap()
+            .clone();
+        apply(&transforms, Some("Signup"), &headers, &mut variables);
+        assert_eq!(
+            Value::Object(variables),
+            json!({ "email": "jane@example.com", "country": 33, "locale": "fr-CH" })
+        );
+
+        let mut variables = json!({ "email": "  Jane@Example.COM ", "locale": null })
+            .as_object()
+            .unwrap()
+            .clone();
+        apply(
+            &transforms,
+            Some("Signup"),
+            &HeaderMap::new(),
+            &mut variables,
+        );
+        assert_eq!(
+            Value::Object(variables),
+            json!({ "email": "jane@example.com", "locale": "en-US" })
+        );
+
+        let mut variables = json!({ "email": "  Jane@Example.COM " })
+            .as_object()
+            .unwrap()
+            .clone();
+        apply(&transforms, Some("Login"), &headers, &mut variables);
+        apply(&transforms, None, &headers, &mut variables);
+        assert_eq!(
+            Value::Object(variables),
+            json!({ "email": "  Jane@Example.COM " })
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Formats of the custom scalars validated and normalized by the router.
+//!
+//! The subgraphs may represent the same custom scalar differently, so the router can check the
+//! variables against a known format, and normalize the representation of the values returned to
+//! the client.
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use time::UtcOffset;
+
+use crate::json_ext::Value;
+
+/// Format of a custom scalar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CustomScalarFormat {
+    /// RFC 3339 date and time, returned in UTC
+    DateTime,
+    /// UUID, returned in its lowercase hyphenated form
+    Uuid,
+    /// Integer of any size, as a number or a string of digits, returned as a string
+    BigInt,
+}
+
+impl CustomScalarFormat {
+    /// Returns the value in the representation of this format, or `None` if it does not have
+    /// this format
+    pub(crate) fn normalize(self, value: &Value) -> Option<Value> {
+        match self {
+            CustomScalarFormat::DateTime => {
+                let date_time = OffsetDateTime::parse(value.as_str()?, &Rfc3339).ok()?;
+                let date_time = date_time.to_offset(UtcOffset::UTC).format(&Rfc3339).ok()?;
+                Some(Value::String(date_time.into()))
+            }
+            CustomScalarFormat::Uuid => {
+                let uuid = uuid::Uuid::parse_str(value.as_str()?).ok()?;
+                Some(Value::String(uuid.hyphenated().to_string().into()))
+            }
+            CustomScalarFormat::BigInt => match value {
+                Value::Number(number) if number.is_i64() || number.is_u64() => {
+                    Some(Value::String(number.to_string().into()))
+                }
+                Value::String(string) => {
+                    let string = string.as_str();
+                    let (sign, digits) = match string.strip_prefix('-') {
+                        Some(digits) => ("-", digits),
+                        None => ("", string),
+                    };
+                    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                        return None;
+                    }
+                    let digits = digits.trim_start_matches('0');
+                    if digits.is_empty() {
+                        Some(Value::String("0".into()))
+                    } else {
+                        Some(Value::String(format!("{sign}{digits}").into()))
+                    }
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    #[test]
+    fn date_times_are_returned_in_utc() {
+        let format = CustomScalarFormat::DateTime;
+        assert_eq!(
+            format.normalize(&json!("2024-05-01T12:30:00+02:00")),
+            Some(json!("2024-05-01T10:30:00Z"))
+        );
+        assert_eq!(
+            format.normalize(&json!("2024-05-01T10:30:00.5Z")),
+            Some(json!("2024-05-01T10:30:00.5Z"))
+        );
+        assert_eq!(format.normalize(&json!("2024-05-01")), None);
+        assert_eq!(format.normalize(&json!(1714559400)), None);
+    }
+
+    #[test]
+    fn uuids_are_returned_hyphenated() {
+        let format = CustomScalarFormat::Uuid;
+        assert_eq!(
+            format.normalize(&json!("67E5504410B1426F9247BB680E5FE0C8")),
+            Some(json!("67e55044-10b1-426f-9247-bb680e5fe0c8"))
+        );
+        assert_eq!(
+            format.normalize(&json!("{67e55044-10b1-426f-9247-bb680e5fe0c8}")),
+            Some(json!("67e55044-10b1-426f-9247-bb680e5fe0c8"))
+        );
+        assert_eq!(format.normalize(&json!("67e55044")), None);
+    }
+
+    #[test]
+    fn big_ints_are_returned_as_strings() {
+        let format = CustomScalarFormat::BigInt;
+        assert_eq!(format.normalize(&json!(42)), Some(json!("42")));
+        assert_eq!(
+            format.normalize(&json!("-000123456789012345678901234567890")),
+            Some(json!("-123456789012345678901234567890"))
+        );
+        assert_eq!(format.normalize(&json!("-0")), Some(json!("0")));
+        assert_eq!(format.normalize(&json!("12.5")), None);
+        assert_eq!(format.normalize(&json!(12.5)), None);
+        assert_eq!(format.normalize(&json!("-")), None);
+    }
+}
@@ -131,8 +131,11 @@ fn validate_input_value(
         .get(type_name)
         .ok_or(InvalidValue)?;
     match (type_def, value) {
-        // Custom scalar: accept any JSON value
-        (schema::ExtendedType::Scalar(_), _) => Ok(()),
+        // Custom scalar: accept any JSON value, unless the scalar has a configured format
+        (schema::ExtendedType::Scalar(_), _) => match schema.custom_scalar_format(type_name) {
+            Some(format) => from_bool(format.normalize(value).is_some()),
+            None => Ok(()),
+        },
 
         (schema::ExtendedType::Enum(def), Value::String(s)) => {
             from_bool(def.values.contains_key(s.as_str()))
@@ -2,6 +2,7 @@
 #![cfg_attr(not(test), deny(clippy::expect_used))]
 #![cfg_attr(not(test), deny(clippy::panic))]
 
+pub(crate) mod custom_scalar;
 mod field_type;
 mod fragments;
 pub(crate) mod operation_limits;
@@ -56,6 +57,8 @@ pub(crate) enum SpecError {
     SubscriptionNotSupported,
     /// query hashing failed: {0}
     QueryHashing(String),
+    /// fragment spreads form a cycle: {0}
+    FragmentCycle(String),
 }
 
 pub(crate) const GRAPHQL_VALIDATION_FAILURE_ERROR_KEY: &str = "## GraphQLValidationFailure\n";
@@ -86,6 +89,7 @@ impl ErrorExtension for SpecError {
             SpecError::UnknownOperation(_) => "GRAPHQL_VALIDATION_FAILED",
             SpecError::SubscriptionNotSupported => "SUBSCRIPTION_NOT_SUPPORTED",
             SpecError::QueryHashing(_) => "QUERY_HASHING",
+            SpecError::FragmentCycle(_) => "GRAPHQL_VALIDATION_FAILED",
         }
         .to_string()
     }
@@ -1,13 +1,29 @@
+//! Measurement of the operations against the operation limits.
+//!
+//! The depth, height, root fields and aliases of an operation are measured after merging its
+//! fields by response key across fragments, as they appear in the response. The measurements of
+//! the planned operations are cached with their query plans, and checked for each request against
+//! a tier of limits: the introspection tier for introspection operations, the tier of its client,
+//! or the `max_*` limits otherwise. The clients declare their names, so they can pick any tier.
+
 use std::collections::HashMap;
 use std::collections::HashSet;
 
 use apollo_compiler::executable;
 use apollo_compiler::ExecutableDocument;
 use apollo_compiler::Name;
+use indexmap::IndexMap;
 use serde::Deserialize;
 use serde::Serialize;
 
-use crate::Configuration;
+use super::SpecError;
+use crate::configuration::Limits;
+use crate::error::QueryPlannerError;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::Context;
+
+const DEFAULT_TIER: &str = "default";
+const INTROSPECTION_TIER: &str = "introspection";
 
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub(crate) struct OperationLimits<T> {
@@ -55,33 +71,76 @@ impl OperationLimits<bool> {
     }
 }
 
-/// Returns which limits are exceeded by the given query, if any
+/// Name of the tier of limits applied to an operation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LimitsTier(pub(crate) String);
+
+/// Returns the tier of limits applying to an operation, and its name
+fn tier<'a>(
+    limits: &'a Limits,
+    context: &Context,
+    is_introspection: bool,
+) -> (&'a str, OperationLimits<Option<u32>>) {
+    if is_introspection {
+        if let Some(tier) = &limits.introspection {
+            return (
+                INTROSPECTION_TIER,
+                OperationLimits {
+                    depth: tier.max_depth,
+                    height: tier.max_height,
+                    root_fields: tier.max_root_fields,
+                    aliases: tier.max_aliases,
+                },
+            );
+        }
+    }
+    if !limits.client_tiers.is_empty() {
+        let client_name = context.get::<_, String>(CLIENT_NAME).ok().flatten();
+        if let Some(tier) = client_name.and_then(|client_name| {
+            limits
+                .client_tiers
+                .iter()
+                .find(|tier| tier.client_names.contains(&client_name))
+        }) {
+            return (
+                tier.name.as_str(),
+                OperationLimits {
+                    depth: tier.max_depth,
+                    height: tier.max_height,
+                    root_fields: tier.max_root_fields,
+                    aliases: tier.max_aliases,
+                },
+            );
+        }
+    }
+    (
+        DEFAULT_TIER,
+        OperationLimits {
+            depth: limits.max_depth,
+            height: limits.max_height,
+            root_fields: limits.max_root_fields,
+            aliases: limits.max_aliases,
+        },
+    )
+}
+
+/// Checks the measurements of an operation against the tier of limits applying to it. The
+/// measurements and the name of the tier are recorded in the context extensions.
 pub(crate) fn check(
-    query_metrics_in: &mut OperationLimits<u32>,
-    configuration: &Configuration,
+    limits: &Limits,
+    context: &Context,
     query: &str,
-    document: &ExecutableDocument,
     operation_name: Option<&str>,
-) -> Result<(), OperationLimits<bool>> {
-    let config_limits = &configuration.limits;
-    let max = OperationLimits {
-        depth: config_limits.max_depth,
-        height: config_limits.max_height,
-        root_fields: config_limits.max_root_fields,
-        aliases: config_limits.max_aliases,
-    };
-    let Ok(operation) = document.operations.get(operation_name) else {
-        // Undefined or ambiguous operation name.
-        // The request is invalid and will be rejected by some other part of the router,
-        // if it wasn’t already before we got to this code path.
-        return Ok(());
-    };
-
-    let mut fragment_cache = HashMap::new();
-    let measured = count(document, &mut fragment_cache, &operation.selection_set);
+    measured: OperationLimits<u32>,
+    is_introspection: bool,
+) -> Result<(), QueryPlannerError> {
+    let (tier_name, max) = tier(limits, context, is_introspection);
 
     // Keep a record of the measurements
-    *query_metrics_in = measured;
+    context.extensions().with_lock(|mut lock| {
+        lock.insert::<OperationLimits<u32>>(measured);
+        lock.insert(LimitsTier(tier_name.to_string()));
+    });
 
     // If we don't have a configured limit, we can just return Ok
     if !max.map(|limit| limit.is_some()).any() {
@@ -107,93 +166,334 @@ pub(crate) fn check(
         });
         let message = messages.join(", ");
         tracing::warn!(
-            "request exceeded complexity limits: {message}, \
+            "request exceeded complexity limits of the {tier_name} tier: {message}, \
             query: {query:?}, operation name: {operation_name:?}"
         );
-        if !config_limits.warn_only {
-            return Err(exceeded);
+        if !limits.warn_only {
+            return Err(QueryPlannerError::LimitExceeded(exceeded));
         }
     }
     Ok(())
 }
 
-enum Computation<T> {
-    InProgress,
-    Done(T),
+/// Measures the given operation
+pub(crate) fn measure(
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+) -> Result<OperationLimits<u32>, SpecError> {
+    let Ok(operation) = document.operations.get(operation_name) else {
+        return Ok(OperationLimits::default());
+    };
+    check_fragment_cycles(document)?;
+    Ok(Measurement::new(document).selection_sets(&[&operation.selection_set]))
 }
 
-/// Recursively measure the given selection set against each limit
-fn count<'a>(
-    document: &'a executable::ExecutableDocument,
-    fragment_cache: &mut HashMap<&'a Name, Computation<OperationLimits<u32>>>,
-    selection_set: &'a executable::SelectionSet,
-) -> OperationLimits<u32> {
-    let mut counts = OperationLimits {
-        depth: 0,
-        height: 0,
-        root_fields: 0,
-        aliases: 0,
-    };
-    let mut fields_seen = HashSet::new();
-    for selection in &selection_set.selections {
-        match selection {
-            executable::Selection::Field(field) => {
-                let nested = count(document, fragment_cache, &field.selection_set);
-                counts.depth = counts.depth.max(1 + nested.depth);
-                counts.height += nested.height;
-                counts.aliases += nested.aliases;
-                // Multiple aliases for the same field could use different arguments
-                // Until we do full merging for limit checking purpose,
-                // approximate measured height with an upper bound rather than a lower bound.
-                let used_name = if let Some(alias) = &field.alias {
-                    counts.aliases += 1;
-                    alias
-                } else {
-                    &field.name
-                };
-                let not_seen_before = fields_seen.insert(used_name);
-                if not_seen_before {
-                    counts.height += 1;
-                    counts.root_fields += 1;
+/// Whether the root fields of an operation are all introspection fields
+pub(crate) fn is_introspection(
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+) -> bool {
+    document
+        .operations
+        .get(operation_name)
+        .is_ok_and(|operation| {
+            Measurement::new(document).is_introspection(&operation.selection_set)
+        })
+}
+
+/// Rejects the fragments spreading themselves, directly or through other fragments, naming the
+/// fragments of the cycle.
+///
+/// https://spec.graphql.org/October2021/#sec-Fragment-spreads-must-not-form-cycles
+fn check_fragment_cycles(document: &ExecutableDocument) -> Result<(), SpecError> {
+    fn spreads<'a>(selection_set: &'a executable::SelectionSet, names: &mut Vec<&'a Name>) {
+        for selection in &selection_set.selections {
+            match selection {
+                executable::Selection::Field(field) => spreads(&field.selection_set, names),
+                executable::Selection::InlineFragment(fragment) => {
+                    spreads(&fragment.selection_set, names)
                 }
+                executable::Selection::FragmentSpread(spread) => names.push(&spread.fragment_name),
             }
-            executable::Selection::InlineFragment(fragment) => {
-                let nested = count(document, fragment_cache, &fragment.selection_set);
-                counts.depth = counts.depth.max(nested.depth);
-                counts.height += nested.height;
-                counts.aliases += nested.aliases;
-            }
-            executable::Selection::FragmentSpread(fragment) => {
-                let name = &fragment.fragment_name;
-                let nested;
-                match fragment_cache.get(name) {
-                    None => {
-                        if let Some(definition) = document.fragments.get(name) {
-                            fragment_cache.insert(name, Computation::InProgress);
-                            nested = count(document, fragment_cache, &definition.selection_set);
-                            fragment_cache.insert(name, Computation::Done(nested));
-                        } else {
-                            // Undefined fragment. The operation invalid
-                            // and will be rejected by some other part of the router,
-                            // if it wasn’t already before we got to this code path.
-                            continue;
-                        }
-                    }
-                    Some(Computation::InProgress) => {
-                        // This fragment references itself (maybe indirectly).
-                        // https://spec.graphql.org/October2021/#sec-Fragment-spreads-must-not-form-cycles
-                        // The operation invalid
-                        // and will be rejected by some other part of the router,
-                        // if it wasn’t already before we got to this code path.
+        }
+    }
+
+    fn visit<'a>(
+        document: &'a ExecutableDocument,
+        name: &'a Name,
+        path: &mut Vec<&'a Name>,
+        done: &mut HashSet<&'a Name>,
+    ) -> Result<(), SpecError> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|visiting| *visiting == name) {
+            let cycle = path[start..]
+                .iter()
+                .chain(std::iter::once(&name))
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(SpecError::FragmentCycle(cycle));
+        }
+        let Some(definition) = document.fragments.get(name) else {
+            // Undefined fragment. The operation is invalid
+            // and will be rejected by some other part of the router.
+            return Ok(());
+        };
+        let mut names = Vec::new();
+        spreads(&definition.selection_set, &mut names);
+        path.push(name);
+        for spread in names {
+            visit(document, spread, path, done)?;
+        }
+        path.pop();
+        done.insert(name);
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for name in document.fragments.keys() {
+        visit(document, name, &mut Vec::new(), &mut done)?;
+    }
+    Ok(())
+}
+
+/// Measures selection sets, merging their fields by response key across fragments. The fragment
+/// spreads must not form cycles.
+struct Measurement<'a> {
+    document: &'a ExecutableDocument,
+    /// Measurements of the merged selection sets, by address of the selection sets. The
+    /// selection sets of a fragment spread many times are measured once
+    cache: HashMap<Vec<usize>, OperationLimits<u32>>,
+}
+
+impl<'a> Measurement<'a> {
+    fn new(document: &'a ExecutableDocument) -> Self {
+        Self {
+            document,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Collects the fields of a selection set and of its fragments, by response key
+    fn collect_fields(
+        &self,
+        selection_set: &'a executable::SelectionSet,
+        fields: &mut IndexMap<&'a Name, Vec<&'a executable::Field>>,
+        spread_fragments: &mut HashSet<&'a Name>,
+    ) {
+        for selection in &selection_set.selections {
+            match selection {
+                executable::Selection::Field(field) => fields
+                    .entry(field.response_key())
+                    .or_default()
+                    .push(&**field),
+                executable::Selection::InlineFragment(fragment) => {
+                    self.collect_fields(&fragment.selection_set, fields, spread_fragments)
+                }
+                executable::Selection::FragmentSpread(spread) => {
+                    // The fields of a fragment spread twice are merged with themselves
+                    if !spread_fragments.insert(&spread.fragment_name) {
                         continue;
                     }
-                    Some(Computation::Done(cached)) => nested = *cached,
+                    if let Some(definition) = self.document.fragments.get(&spread.fragment_name) {
+                        self.collect_fields(&definition.selection_set, fields, spread_fragments)
+                    }
                 }
-                counts.depth = counts.depth.max(nested.depth);
-                counts.height += nested.height;
-                counts.aliases += nested.aliases;
             }
         }
     }
-    counts
+
+    /// Measures the merged fields of selection sets
+    fn selection_sets(
+        &mut self,
+        selection_sets: &[&'a executable::SelectionSet],
+    ) -> OperationLimits<u32> {
+        let mut key: Vec<usize> = selection_sets
+            .iter()
+            .map(|selection_set| *selection_set as *const executable::SelectionSet as usize)
+            .collect();
+        key.sort_unstable();
+        key.dedup();
+        if let Some(cached) = self.cache.get(&key) {
+            return *cached;
+        }
+
+        let mut fields = IndexMap::new();
+        let mut spread_fragments = HashSet::new();
+        for selection_set in selection_sets {
+            self.collect_fields(selection_set, &mut fields, &mut spread_fragments);
+        }
+
+        let mut counts = OperationLimits::default();
+        for (_, fields) in fields {
+            counts.root_fields += 1;
+            counts.height = counts.height.saturating_add(1);
+            if fields.iter().any(|field| field.alias.is_some()) {
+                counts.aliases = counts.aliases.saturating_add(1);
+            }
+            let nested: Vec<_> = fields
+                .iter()
+                .map(|field| &field.selection_set)
+                .filter(|selection_set| !selection_set.selections.is_empty())
+                .collect();
+            if nested.is_empty() {
+                counts.depth = counts.depth.max(1);
+            } else {
+                let nested = self.selection_sets(&nested);
+                counts.depth = counts.depth.max(nested.depth.saturating_add(1));
+                counts.height = counts.height.saturating_add(nested.height);
+                counts.aliases = counts.aliases.saturating_add(nested.aliases);
+            }
+        }
+        self.cache.insert(key, counts);
+        counts
+    }
+
+    /// Whether the root fields of an operation are all introspection fields
+    fn is_introspection(&self, selection_set: &'a executable::SelectionSet) -> bool {
+        let mut fields = IndexMap::new();
+        self.collect_fields(selection_set, &mut fields, &mut HashSet::new());
+        let names = || fields.values().flatten().map(|field| field.name.as_str());
+        names().all(|name| name.starts_with("__"))
+            && names().any(|name| name == "__schema" || name == "__type")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apollo_compiler::Schema;
+
+    use super::*;
+    use crate::configuration::ClientLimitsTier;
+    use crate::configuration::OperationLimitsTier;
+
+    const SCHEMA: &str = "
+        type Query { me: User, users: [User] }
+        type User { id: ID, name: String, friends: [User] }
+    ";
+
+    fn measured(query: &str) -> Result<OperationLimits<u32>, SpecError> {
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let document = ExecutableDocument::parse(&schema, query, "query.graphql").unwrap();
+        measure(&document, None)
+    }
+
+    #[test]
+    fn fields_are_merged_across_fragments() {
+        let limits = measured(
+            "{
+                me { id ...Friends }
+                ...Me
+            }
+            fragment Me on Query { me { name friends { id } } }
+            fragment Friends on User { friends { id name } }",
+        )
+        .unwrap();
+        // me, me.id, me.name, me.friends, me.friends.id, me.friends.name
+        assert_eq!(limits.height, 6);
+        assert_eq!(limits.depth, 3);
+        assert_eq!(limits.root_fields, 1);
+        assert_eq!(limits.aliases, 0);
+
+        let limits = measured(
+            "{
+                ...Users
+                first: me { id }
+                second: me { id }
+            }
+            fragment Users on Query { users { id } }",
+        )
+        .unwrap();
+        assert_eq!(limits.root_fields, 3);
+        assert_eq!(limits.aliases, 2);
+        assert_eq!(limits.height, 6);
+    }
+
+    #[test]
+    fn fragment_cycles_are_named() {
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        // cycles are rejected by the validation, so the document is built without it
+        let document = ExecutableDocument::parse(
+            &schema,
+            "{ me { ...A } }
+            fragment A on User { friends { ...B } }
+            fragment B on User { ...A }",
+            "query.graphql",
+        )
+        .unwrap_or_else(|invalid| invalid.partial);
+        let Err(SpecError::FragmentCycle(cycle)) = measure(&document, None) else {
+            panic!("expected a fragment cycle");
+        };
+        assert_eq!(cycle, "A -> B -> A");
+    }
+
+    #[test]
+    fn tiers_apply_to_introspection_and_clients() {
+        let limits = Limits {
+            max_depth: Some(2),
+            introspection: Some(OperationLimitsTier::default()),
+            client_tiers: vec![ClientLimitsTier {
+                name: "internal".to_string(),
+                client_names: vec!["admin".to_string()],
+                max_depth: Some(5),
+                max_height: None,
+                max_root_fields: None,
+                max_aliases: None,
+            }],
+            ..Default::default()
+        };
+        let context = Context::new();
+        assert_eq!(tier(&limits, &context, false).0, DEFAULT_TIER);
+        assert_eq!(tier(&limits, &context, true).0, INTROSPECTION_TIER);
+        assert!(!tier(&limits, &context, true)
+            .1
+            .map(|max| max.is_some())
+            .any());
+        context.insert(CLIENT_NAME, "admin".to_string()).unwrap();
+        assert_eq!(tier(&limits, &context, false).0, "internal");
+        assert_eq!(tier(&limits, &context, false).1.depth, Some(5));
+
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let document =
+            ExecutableDocument::parse(&schema, "{ __schema { types { name } } }", "q.graphql")
+                .unwrap();
+        assert!(is_introspection(&document, None));
+        let document =
+            ExecutableDocument::parse(&schema, "{ __typename me { id } }", "q.graphql").unwrap();
+        assert!(!is_introspection(&document, None));
+    }
+
+    #[test]
+    fn measurements_are_checked_against_the_tier() {
+        let limits = Limits {
+            max_depth: Some(2),
+            client_tiers: vec![ClientLimitsTier {
+                name: "internal".to_string(),
+                client_names: vec!["admin".to_string()],
+                max_depth: Some(5),
+                max_height: None,
+                max_root_fields: None,
+                max_aliases: None,
+            }],
+            ..Default::default()
+        };
+        let measured = OperationLimits {
+            depth: 3,
+            height: 3,
+            root_fields: 1,
+            aliases: 0,
+        };
+        let query = "{ me { friends { id } } }";
+        let context = Context::new();
+        assert!(check(&limits, &context, query, None, measured, false).is_err());
+
+        context.insert(CLIENT_NAME, "admin".to_string()).unwrap();
+        assert!(check(&limits, &context, query, None, measured, false).is_ok());
+        let tier = context
+            .extensions()
+            .with_lock(|lock| lock.get::<LimitsTier>().cloned());
+        assert_eq!(tier, Some(LimitsTier("internal".to_string())));
+    }
 }
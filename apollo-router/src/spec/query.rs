@@ -19,6 +19,9 @@ use tower::BoxError;
 use tracing::level_filters::LevelFilter;
 
 use self::change::QueryHashVisitor;
+use self::null_provenance::NullProvenance;
+use self::null_provenance::NullReason;
+use self::null_provenance::NULL_PROVENANCE_EXTENSION;
 use self::subselections::BooleanValues;
 use self::subselections::SubSelectionKey;
 use self::subselections::SubSelectionValue;
@@ -28,6 +31,7 @@ use crate::graphql::Request;
 use crate::graphql::Response;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
+use crate::json_ext::PathElement;
 use crate::json_ext::ResponsePathElement;
 use crate::json_ext::Value;
 use crate::plugins::authorization::UnauthorizedPaths;
@@ -35,7 +39,6 @@ use crate::query_planner::fetch::OperationKind;
 use crate::query_planner::fetch::QueryHash;
 use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::layers::query_analysis::ParsedDocumentInner;
-use crate::spec::schema::ApiSchema;
 use crate::spec::FieldType;
 use crate::spec::Fragments;
 use crate::spec::InvalidValue;
@@ -45,6 +48,7 @@ use crate::spec::SpecError;
 use crate::Configuration;
 
 pub(crate) mod change;
+pub(crate) mod null_provenance;
 pub(crate) mod subselections;
 pub(crate) mod transform;
 pub(crate) mod traverse;
@@ -124,8 +128,9 @@ impl Query {
         response: &mut Response,
         operation_name: Option<&str>,
         variables: Object,
-        schema: &ApiSchema,
+        schema: &Schema,
         defer_conditions: BooleanValues,
+        null_provenance: bool,
     ) -> Vec<Path> {
         let data = std::mem::take(&mut response.data);
 
@@ -146,6 +151,7 @@ impl Query {
                                 schema,
                                 errors: Vec::new(),
                                 nullified: Vec::new(),
+                                null_provenance: null_provenance.then(NullProvenance::default),
                             };
                             // Detect if root __typename is asked in the original query (the qp doesn't put root __typename in subselections)
                             // cf https://github.com/apollographql/router/issues/1677
@@ -170,7 +176,13 @@ impl Query {
                                     &mut Vec::new(),
                                 ) {
                                     Ok(()) => output.into(),
-                                    Err(InvalidValue) => Value::Null,
+                                    Err(InvalidValue) => {
+                                        parameters.record_null(|provenance| {
+                                            provenance
+                                                .land(Path::default(), NullReason::InvalidValue)
+                                        });
+                                        Value::Null
+                                    }
                                 },
                             );
 
@@ -179,6 +191,7 @@ impl Query {
                                     response.extensions.insert("valueCompletion", value);
                                 }
                             }
+                            parameters.add_null_provenance(response);
 
                             return parameters.nullified;
                         }
@@ -213,6 +226,7 @@ impl Query {
                         schema,
                         errors: Vec::new(),
                         nullified: Vec::new(),
+                        null_provenance: null_provenance.then(NullProvenance::default),
                     };
 
                     response.data = Some(
@@ -225,7 +239,12 @@ impl Query {
                             &mut Vec::new(),
                         ) {
                             Ok(()) => output.into(),
-                            Err(InvalidValue) => Value::Null,
+                            Err(InvalidValue) => {
+                                parameters.record_null(|provenance| {
+                                    provenance.land(Path::default(), NullReason::InvalidValue)
+                                });
+                                Value::Null
+                            }
                         },
                     );
                     if !parameters.errors.is_empty() {
@@ -233,6 +252,7 @@ impl Query {
                             response.extensions.insert("valueCompletion", value);
                         }
                     }
+                    parameters.add_null_provenance(response);
 
                     return parameters.nullified;
                 } else {
@@ -414,6 +434,10 @@ impl Query {
                                 path: Some(Path::from_response_slice(path)),
                                 ..Error::default()
                             });
+                            parameters.record_null(|provenance| {
+                                provenance
+                                    .non_null(Path::from_response_slice(path), input.is_null())
+                            });
 
                             Err(InvalidValue)
                         } else {
@@ -456,6 +480,10 @@ impl Query {
                         }) {
                         Err(InvalidValue) => {
                             parameters.nullified.push(Path::from_response_slice(path));
+                            parameters.record_null(|provenance| {
+                                provenance
+                                    .land(Path::from_response_slice(path), NullReason::InvalidValue)
+                            });
                             *output = Value::Null;
                             Ok(())
                         }
@@ -517,9 +545,13 @@ impl Query {
             executable::Type::Named(type_name) => {
                 // we cannot know about the expected format of custom scalars
                 // so we must pass them directly to the client
-                match parameters.schema.types.get(type_name) {
+                match parameters.schema.api_schema().types.get(type_name) {
                     Some(ExtendedType::Scalar(_)) => {
-                        *output = input.clone();
+                        // unless the router is configured with their format
+                        *output = match parameters.schema.custom_scalar_format(type_name) {
+                            Some(format) => format.normalize(input).unwrap_or(Value::Null),
+                            None => input.clone(),
+                        };
                         return Ok(());
                     }
                     Some(ExtendedType::Enum(enum_type)) => {
@@ -552,9 +584,15 @@ impl Query {
                             // some subgraph can have returned a __typename that is the name of an interface in the supergraph, and this is fine (that is, we should not
                             // return such a __typename to the user, but as long as it's not returned, having it in the internal data is ok and sometimes expected).
                             let Some(ExtendedType::Object(_) | ExtendedType::Interface(_)) =
-                                parameters.schema.types.get(input_type)
+                                parameters.schema.api_schema().types.get(input_type)
                             else {
                                 parameters.nullified.push(Path::from_response_slice(path));
+                                parameters.record_null(|provenance| {
+                                    provenance.land(
+                                        Path::from_response_slice(path),
+                                        NullReason::InvalidTypename,
+                                    )
+                                });
                                 *output = Value::Null;
                                 return Ok(());
                             };
@@ -597,6 +635,10 @@ impl Query {
                             .is_err()
                         {
                             parameters.nullified.push(Path::from_response_slice(path));
+                            parameters.record_null(|provenance| {
+                                provenance
+                                    .land(Path::from_response_slice(path), NullReason::InvalidValue)
+                            });
                             *output = Value::Null;
                         }
 
@@ -604,6 +646,12 @@ impl Query {
                     }
                     _ => {
                         parameters.nullified.push(Path::from_response_slice(path));
+                        if !input.is_null() {
+                            parameters.record_null(|provenance| {
+                                provenance
+                                    .land(Path::from_response_slice(path), NullReason::InvalidValue)
+                            });
+                        }
                         *output = Value::Null;
                         Ok(())
                     }
@@ -650,9 +698,20 @@ impl Query {
                                 ))
                             });
                         if let Some(input_str) = input_value.as_str() {
-                            if parameters.schema.get_object(input_str).is_some() {
+                            if parameters
+                                .schema
+                                .api_schema()
+                                .get_object(input_str)
+                                .is_some()
+                            {
                                 output.insert((*field_name).clone(), input_value);
                             } else {
+                                parameters.record_null(|provenance| {
+                                    provenance.origin(
+                                        field_path(path, field_name.as_str()),
+                                        NullReason::InvalidTypename,
+                                    )
+                                });
                                 return Err(InvalidValue);
                             }
                         }
@@ -699,6 +758,12 @@ impl Query {
                                 path: Some(Path::from_response_slice(path)),
                                 ..Error::default()
                             });
+                            parameters.record_null(|provenance| {
+                                provenance.origin(
+                                    field_path(path, field_name.as_str()),
+                                    NullReason::MissingValue,
+                                )
+                            });
 
                             return Err(InvalidValue);
                         }
@@ -754,7 +819,7 @@ impl Query {
                     if let Some(fragment) = self.fragments.get(name) {
                         let is_apply = current_type.inner_named_type().as_str()
                             == fragment.type_condition.as_str()
-                            || parameters.schema.is_subtype(
+                            || parameters.schema.api_schema().is_subtype(
                                 &fragment.type_condition,
                                 current_type.inner_named_type().as_str(),
                             );
@@ -850,6 +915,10 @@ impl Query {
                             path: Some(Path::from_response_slice(path)),
                             ..Error::default()
                         });
+                        parameters.record_null(|provenance| {
+                            provenance
+                                .origin(field_path(path, field_name_str), NullReason::MissingValue)
+                        });
                         return Err(InvalidValue);
                     } else {
                         output.insert(field_name.clone(), Value::Null);
@@ -1095,7 +1164,40 @@ struct FormatParameters<'a> {
     variables: &'a Object,
     errors: Vec<Error>,
     nullified: Vec<Path>,
-    schema: &'a ApiSchema,
+    schema: &'a Schema,
+    null_provenance: Option<NullProvenance>,
+}
+
+impl FormatParameters<'_> {
+    fn record_null(&mut self, record: impl FnOnce(&mut NullProvenance)) {
+        if let Some(provenance) = self.null_provenance.as_mut() {
+            record(provenance);
+        }
+    }
+
+    // appends the recorded null cascades to the ones of previous formattings of the response
+    fn add_null_provenance(&mut self, response: &mut Response) {
+        let Some(provenance) = self.null_provenance.take().filter(|p| !p.is_empty()) else {
+            return;
+        };
+        let Value::Array(cascades) = provenance.into_value(&response.errors) else {
+            return;
+        };
+        match response.extensions.get_mut(NULL_PROVENANCE_EXTENSION) {
+            Some(Value::Array(previous)) => previous.extend(cascades),
+            _ => {
+                response
+                    .extensions
+                    .insert(NULL_PROVENANCE_EXTENSION, Value::Array(cascades));
+            }
+        }
+    }
+}
+
+fn field_path(path: &[ResponsePathElement], field_name: &str) -> Path {
+    let mut path = Path::from_response_slice(path);
+    path.push(PathElement::Key(field_name.to_string(), None));
+    path
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1217,5 +1319,33 @@ pub(crate) fn parse_hir_value(value: &executable::Value) -> Option<Value> {
     }
 }
 
+/// A query parsed against a supergraph, formatting responses to its shape. Only used by the
+/// benchmarks
+#[doc(hidden)]
+pub struct ResponseFormatter {
+    schema: Schema,
+    query: Query,
+}
+
+impl ResponseFormatter {
+    pub fn new(schema: &str, query: &str) -> Result<Self, BoxError> {
+        let configuration = Configuration::default();
+        let schema = Schema::parse(schema, &configuration)?;
+        let query = Query::parse(query, None, &schema, &configuration)?;
+        Ok(Self { schema, query })
+    }
+
+    pub fn format(&self, response: &mut Response) {
+        self.query.format_response(
+            response,
+            None,
+            Object::default(),
+            &self.schema,
+            BooleanValues { bits: 0 },
+            false,
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,131 @@
+//! Tracing of null propagation while formatting responses.
+//!
+//! When a non-null field is null, the null propagates to its nearest nullable parent, which can be
+//! many levels above the field that caused it. The formatting of the response records, for each
+//! nullable position set to null, the chain of non-null positions the null propagated through,
+//! down to the position where it originated and the subgraph errors found there.
+
+use serde::Serialize;
+
+use crate::graphql::Error;
+use crate::json_ext::Path;
+use crate::json_ext::Value;
+
+/// Response extension containing the null provenance of a response
+pub(crate) const NULL_PROVENANCE_EXTENSION: &str = "apolloNullProvenance";
+
+/// Why a position originating a null cascade is null
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum NullReason {
+    /// The value is null in the data returned by the subgraphs
+    NullValue,
+    /// The value is absent from the data returned by the subgraphs
+    MissingValue,
+    /// The value does not match the type of the field and was replaced with null
+    InvalidValue,
+    /// The `__typename` of the object is not an object type of the API schema
+    InvalidTypename,
+}
+
+/// A position set to null, and the non-null position it was set to null because of
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct NullProvenanceNode {
+    path: Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<NullReason>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<Error>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cause: Option<Box<NullProvenanceNode>>,
+}
+
+impl NullProvenanceNode {
+    fn origin(&mut self) -> &mut NullProvenanceNode {
+        match self.cause {
+            Some(ref mut cause) => cause.origin(),
+            None => self,
+        }
+    }
+}
+
+/// Records the null cascades of a response while it is formatted
+#[derive(Debug, Default)]
+pub(crate) struct NullProvenance {
+    // the cascade being propagated to the nearest nullable parent
+    pending: Option<NullProvenanceNode>,
+    // the cascades that reached a nullable position
+    cascades: Vec<NullProvenanceNode>,
+}
+
+impl NullProvenance {
+    /// A null cascade starts at `path`
+    pub(crate) fn origin(&mut self, path: Path, reason: NullReason) {
+        self.pending = Some(NullProvenanceNode {
+            path,
+            reason: Some(reason),
+            errors: Vec::new(),
+            cause: None,
+        });
+    }
+
+    /// The non-null position at `path` is null. Either a cascade landed on it and now propagates
+    /// further, or it is the origin of a new cascade
+    pub(crate) fn non_null(&mut self, path: Path, input_is_null: bool) {
+        if self
+            .cascades
+            .last()
+            .map(|cascade| cascade.path == path)
+            .unwrap_or_default()
+        {
+            self.pending = self.cascades.pop();
+        } else {
+            let reason = if input_is_null {
+                NullReason::NullValue
+            } else {
+                NullReason::InvalidValue
+            };
+            self.origin(path, reason);
+        }
+    }
+
+    /// The position at `path` was set to null, because of the pending cascade or, without one,
+    /// because of `reason`
+    pub(crate) fn land(&mut self, path: Path, reason: NullReason) {
+        let cause = self.pending.take();
+        self.cascades.push(NullProvenanceNode {
+            path,
+            reason: cause.is_none().then_some(reason),
+            errors: Vec::new(),
+            cause: cause.map(Box::new),
+        });
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.cascades.is_empty()
+    }
+
+    /// The recorded cascades, with the errors of the response found at or above their origin
+    pub(crate) fn into_value(self, errors: &[Error]) -> Value {
+        let cascades: Vec<NullProvenanceNode> = self
+            .cascades
+            .into_iter()
+            .map(|mut cascade| {
+                let origin = cascade.origin();
+                origin.errors = errors
+                    .iter()
+                    .filter(|error| {
+                        error
+                            .path
+                            .as_ref()
+                            .map(|path| origin.path.starts_with(path))
+                            .unwrap_or_default()
+                    })
+                    .cloned()
+                    .collect();
+                cascade
+            })
+            .collect();
+        serde_json_bytes::to_value(cascades).unwrap_or_default()
+    }
+}
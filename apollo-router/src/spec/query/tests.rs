@@ -5,6 +5,7 @@ use test_log::test;
 
 use super::*;
 use crate::json_ext::ValueExt;
+use crate::spec::custom_scalar::CustomScalarFormat;
 
 macro_rules! assert_eq_and_ordered {
     ($a:expr, $b:expr $(,)?) => {
@@ -124,7 +125,6 @@ impl FormatTest {
 
         let schema = Schema::parse(&schema, &Default::default()).expect("could not parse schema");
 
-        let api_schema = schema.api_schema();
         let query =
             Query::parse(query, None, &schema, &Default::default()).expect("could not parse query");
         let mut response = Response::builder().data(response).build();
@@ -137,8 +137,9 @@ impl FormatTest {
                 .as_object()
                 .unwrap()
                 .clone(),
-            api_schema,
+            &schema,
             BooleanValues { bits: 0 },
+            false,
         );
 
         if let Some(e) = self.expected {
@@ -1833,6 +1834,141 @@ fn variable_validation() {
     assert!(res.is_ok(), "validation should have succeeded: {:?}", res);
 }
 
+#[test]
+fn custom_scalars_with_a_format() {
+    let schema = with_supergraph_boilerplate(
+        r#"
+        scalar DateTime
+        scalar UUID
+        scalar JSON
+
+        type Query {
+            event(at: DateTime, id: UUID, payload: JSON): Event
+        }
+
+        type Event {
+            at: DateTime!
+            id: UUID
+            payload: JSON
+        }
+        "#,
+        "Query",
+    );
+    let configuration = Configuration::fake_builder()
+        .supergraph(
+            crate::configuration::Supergraph::fake_builder()
+                .experimental_custom_scalars(HashMap::from([
+                    ("DateTime".to_string(), CustomScalarFormat::DateTime),
+                    ("UUID".to_string(), CustomScalarFormat::Uuid),
+                ]))
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let schema = Schema::parse(&schema, &configuration).expect("could not parse schema");
+    let query = "query($at: DateTime, $id: UUID, $payload: JSON) { event(at: $at, id: $id, payload: $payload) { at id payload } }";
+    let parsed = Query::parse(query, None, &schema, &configuration).expect("could not parse query");
+
+    let validate = |variables: Value| {
+        let request = Request::builder()
+            .variables(variables.as_object().unwrap().clone())
+            .query(query.to_string())
+            .build();
+        parsed.validate_variables(&request, &schema)
+    };
+    assert!(validate(json!({ "at": "2024-05-01T12:30:00+02:00", "id": "67E5504410B1426F9247BB680E5FE0C8", "payload": 1 })).is_ok());
+    assert!(validate(json!({ "at": "yesterday" })).is_err());
+    assert!(validate(json!({ "id": 1 })).is_err());
+
+    let mut response = Response::builder()
+        .data(json! {{
+            "event": {
+                "at": "2024-05-01T12:30:00+02:00",
+                "id": "not a uuid",
+                "payload": { "any": "value" },
+            }
+        }})
+        .build();
+    parsed.format_response(
+        &mut response,
+        None,
+        Object::default(),
+        &schema,
+        BooleanValues { bits: 0 },
+        false,
+    );
+    assert_eq_and_ordered!(
+        response.data.as_ref().unwrap(),
+        &json! {{
+            "event": {
+                "at": "2024-05-01T10:30:00Z",
+                "id": null,
+                "payload": { "any": "value" },
+            }
+        }},
+    );
+}
+
+#[test]
+fn null_provenance() {
+    let schema = with_supergraph_boilerplate(
+        "type Query {
+            me: User
+        }
+        type User {
+            id: ID!
+            profile: Profile!
+        }
+        type Profile {
+            name: String!
+        }",
+        "Query",
+    );
+    let schema = Schema::parse(&schema, &Default::default()).expect("could not parse schema");
+    let query = Query::parse(
+        "{ me { id profile { name } } }",
+        None,
+        &schema,
+        &Default::default(),
+    )
+    .expect("could not parse query");
+    let subgraph_error = Error::builder()
+        .message("could not load the name")
+        .path(Path::from("me/profile/name"))
+        .extension_code("FETCH_ERROR")
+        .build();
+    let mut response = Response::builder()
+        .data(json! {{
+            "me": { "id": "1", "profile": { "name": null } }
+        }})
+        .error(subgraph_error.clone())
+        .build();
+
+    query.format_response(
+        &mut response,
+        None,
+        Object::default(),
+        &schema,
+        BooleanValues { bits: 0 },
+        true,
+    );
+    assert_eq!(response.data, Some(json! {{ "me": null }}));
+    assert_eq!(
+        response.extensions.get("apolloNullProvenance"),
+        Some(&json! {[{
+            "path": ["me"],
+            "cause": {
+                "path": ["me", "profile"],
+                "cause": {
+                    "path": ["me", "profile", "name"],
+                    "reason": "NULL_VALUE",
+                    "errors": [serde_json_bytes::to_value(&subgraph_error).unwrap()]
+                }
+            }
+        }]})
+    );
+}
+
 #[test]
 fn filter_root_errors() {
     let schema = "type Query {
@@ -5128,7 +5264,6 @@ fn fragment_on_interface_on_query() {
     }";
 
     let schema = Schema::parse(schema, &Default::default()).expect("could not parse schema");
-    let api_schema = schema.api_schema();
     let query =
         Query::parse(query, None, &schema, &Default::default()).expect("could not parse query");
     let mut response = Response::builder()
@@ -5145,8 +5280,9 @@ fn fragment_on_interface_on_query() {
         &mut response,
         None,
         Default::default(),
-        api_schema,
+        &schema,
         BooleanValues { bits: 0 },
+        false,
     );
     assert_eq_and_ordered!(
         response.data.as_ref().unwrap(),
@@ -5897,8 +6033,9 @@ fn filtered_defer_fragment() {
         &mut response,
         None,
         Object::new(),
-        schema.api_schema(),
+        &schema,
         BooleanValues { bits: 0 },
+        false,
     );
 
     assert_json_snapshot!(response);
@@ -5907,8 +6044,9 @@ fn filtered_defer_fragment() {
         &mut response,
         None,
         Object::new(),
-        schema.api_schema(),
+        &schema,
         BooleanValues { bits: 0 },
+        false,
     );
 
     assert_json_snapshot!(response);
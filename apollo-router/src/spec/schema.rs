@@ -20,6 +20,7 @@ use sha2::Sha256;
 use crate::error::ParseErrors;
 use crate::error::SchemaError;
 use crate::query_planner::OperationKind;
+use crate::spec::custom_scalar::CustomScalarFormat;
 use crate::Configuration;
 
 /// A GraphQL schema.
@@ -30,6 +31,7 @@ pub(crate) struct Schema {
     pub(crate) implementers_map: apollo_compiler::collections::HashMap<Name, Implementers>,
     api_schema: ApiSchema,
     pub(crate) schema_id: Arc<String>,
+    custom_scalars: HashMap<String, CustomScalarFormat>,
 }
 
 /// Wrapper type to distinguish from `Schema::definitions` for the supergraph schema
@@ -121,6 +123,20 @@ impl Schema {
                 ))
             })?;
 
+        let custom_scalars = config.supergraph.experimental_custom_scalars.clone();
+        for name in custom_scalars.keys() {
+            if supergraph
+                .schema
+                .schema()
+                .get_scalar(name.as_str())
+                .is_none()
+            {
+                tracing::warn!(
+                    "the custom scalar '{name}' is not defined in the supergraph schema"
+                );
+            }
+        }
+
         Ok(Schema {
             raw_sdl,
             supergraph,
@@ -128,6 +144,7 @@ impl Schema {
             implementers_map,
             api_schema: ApiSchema(api_schema),
             schema_id,
+            custom_scalars,
         })
     }
 
@@ -135,6 +152,11 @@ impl Schema {
         &self.supergraph
     }
 
+    /// Format of a custom scalar, if it is configured
+    pub(crate) fn custom_scalar_format(&self, name: &str) -> Option<CustomScalarFormat> {
+        self.custom_scalars.get(name).copied()
+    }
+
     pub(crate) fn supergraph_schema(&self) -> &Valid<apollo_compiler::Schema> {
         self.supergraph.schema.schema()
     }
@@ -335,11 +357,13 @@ impl std::fmt::Debug for Schema {
             implementers_map,
             api_schema: _, // skip
             schema_id: _,
+            custom_scalars,
         } = self;
         f.debug_struct("Schema")
             .field("raw_sdl", raw_sdl)
             .field("subgraphs", subgraphs)
             .field("implementers_map", implementers_map)
+            .field("custom_scalars", custom_scalars)
             .finish()
     }
 }
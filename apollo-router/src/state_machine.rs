@@ -27,7 +27,12 @@ use super::router::ApolloRouterError::{self};
 use super::router::Event::UpdateConfiguration;
 use super::router::Event::UpdateSchema;
 use super::router::Event::{self};
+use crate::allocator::AllocatorMetrics;
+use crate::configuration::lifecycle_notifications;
+use crate::configuration::lifecycle_notifications::LifecycleEvent;
 use crate::configuration::metrics::Metrics;
+use crate::configuration::shared_state;
+use crate::configuration::spiffe;
 use crate::configuration::Configuration;
 use crate::configuration::Discussed;
 use crate::configuration::ListenAddr;
@@ -61,6 +66,7 @@ enum State<FA: RouterSuperServiceFactory> {
     Running {
         configuration: Arc<Configuration>,
         _metrics: Option<Metrics>,
+        _allocator_metrics: AllocatorMetrics,
         schema: Arc<String>,
         license: LicenseState,
         server_handle: Option<HttpServerHandle>,
@@ -236,9 +242,22 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
                                 event = STATE_CHANGE,
                                 "reload complete"
                             );
+                            if schema_reload {
+                                lifecycle_notifications::notify(LifecycleEvent::SchemaReloaded {
+                                    schema_id: Schema::schema_id(schema.as_str()),
+                                });
+                            }
+                            if license_reload {
+                                lifecycle_notifications::notify(LifecycleEvent::LicenseChanged {
+                                    state: license.to_string(),
+                                });
+                            }
                             Some(new_state)
                         }
                         Err(e) => {
+                            lifecycle_notifications::notify(LifecycleEvent::ReloadFailed {
+                                error: e.to_string(),
+                            });
                             // If we encountered an error it may be fatal depending on if we consumed the server handle or not.
                             match server_handle {
                                 None => {
@@ -279,6 +298,7 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
         match self {
             Running {
                 server_handle: Some(server_handle),
+                router_service_factory,
                 mut all_connections_stopped_signals,
                 ..
             } => {
@@ -296,6 +316,9 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
                 // We ignore the results of recv()
                 let _: Vec<_> = futs.collect().await;
                 tracing::info!("all connections shut down");
+                if let Err(e) = router_service_factory.save_warm_state().await {
+                    tracing::error!("could not save the warm state: {e}");
+                }
                 state
             }
             _ => Stopped,
@@ -357,6 +380,16 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
             license
         };
 
+        // the subgraph, coprocessor and Redis connections may use the workload identity
+        spiffe::configure(configuration.tls.spiffe.as_ref())
+            .await
+            .map_err(ServiceCreationError)?;
+
+        // the plugins enforcing limits across the router replicas use the shared state
+        shared_state::configure(&configuration.shared_state)
+            .await
+            .map_err(ServiceCreationError)?;
+
         let router_service_factory = state_machine
             .router_configurator
             .create(
@@ -416,12 +449,15 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
             discussed.log_preview_used(yaml);
         }
 
+        lifecycle_notifications::configure(&configuration.lifecycle_notifications);
+
         let metrics =
             apollo_opentelemetry_initialized().then(|| Metrics::new(&configuration, &license));
 
         Ok(Running {
             configuration,
             _metrics: metrics,
+            _allocator_metrics: AllocatorMetrics::new(),
             schema: sdl,
             license,
             server_handle: Some(server_handle),
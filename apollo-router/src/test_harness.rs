@@ -40,6 +40,8 @@ use crate::uplink::license_enforcement::LicenseState;
 /// Mocks for services the Apollo Router must integrate with.
 pub mod mocks;
 
+pub(crate) mod snapshots;
+
 #[cfg(test)]
 pub(crate) mod http_client;
 
@@ -0,0 +1,376 @@
+//! Golden files of query plans and responses, generated with `router snapshots <dir>`.
+//!
+//! A corpus is a directory of suites, each with a `supergraph.graphql` schema, an optional
+//! `router.yaml` configuration and operations in `.graphql` files. The query plan of an operation
+//! is snapshotted to `<operation>.plan.json`. When the operation has a `<operation>.fixture.json`
+//! file, with its variables, headers and the responses of the mocked subgraphs, the response of
+//! the router is snapshotted to `<operation>.response.json`.
+//!
+//! Snapshots are normalized so they only change with the behavior of the router: the keys of the
+//! plans are sorted and the hashes of their fetches are removed, and the errors of the responses
+//! are sorted, as parallel fetches complete in any order. The snapshots are compared to the files
+//! unless `--update` is passed, in which case the files are written.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json_bytes::ByteString;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::plugin::test::MockSubgraph;
+use crate::query_planner::explain::plan_operation;
+use crate::query_planner::BridgeQueryPlanner;
+use crate::services::supergraph;
+use crate::spec::Schema;
+use crate::Configuration;
+use crate::TestHarness;
+
+/// Name of the supergraph schema of a suite
+const SUPERGRAPH_FILE: &str = "supergraph.graphql";
+
+/// Name of the optional router configuration of a suite
+const CONFIGURATION_FILE: &str = "router.yaml";
+
+/// Fields of the plan nodes depending on the implementation of the planner rather than on the
+/// plan: the hashes of the fetches, and their cache key metadata
+const VOLATILE_PLAN_FIELDS: &[&str] = &["authorization", "schemaAwareHash"];
+
+/// Request of an operation, and responses of the subgraphs it fetches from
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default, rename_all = "camelCase")]
+struct Fixture {
+    /// The name of the operation, if its file contains several operations
+    operation_name: Option<String>,
+    variables: Map<ByteString, Value>,
+    headers: BTreeMap<String, String>,
+    /// Mocked responses, by subgraph name. A subgraph request without a mocked response gets an
+    /// error showing the request to mock
+    subgraphs: HashMap<String, Vec<MockedFetch>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MockedFetch {
+    request: graphql::Request,
+    response: graphql::Response,
+}
+
+/// Result of comparing a generated snapshot to its file
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Unchanged,
+    Written,
+    Missing,
+    /// Unified diff from the file to the generated snapshot
+    Changed(String),
+}
+
+/// Schema, configuration and planner shared by the operations of a suite
+struct Suite {
+    sdl: String,
+    configuration: Arc<Configuration>,
+    planner: BridgeQueryPlanner,
+}
+
+impl Suite {
+    async fn load(dir: &Path) -> Result<Self, BoxError> {
+        let sdl = read(&dir.join(SUPERGRAPH_FILE))?;
+        let configuration_path = dir.join(CONFIGURATION_FILE);
+        let configuration = if configuration_path.exists() {
+            read(&configuration_path)?
+                .parse::<Configuration>()
+                .map_err(|e| format!("invalid {}: {e}", configuration_path.display()))?
+        } else {
+            Configuration::default()
+        };
+        let configuration = Arc::new(configuration);
+        let schema = Schema::parse(&sdl, &configuration)?;
+        let planner =
+            BridgeQueryPlanner::new(schema.into(), configuration.clone(), None, None).await?;
+        Ok(Self {
+            sdl,
+            configuration,
+            planner,
+        })
+    }
+
+    /// Generates the snapshots of an operation, with the paths of their files
+    async fn snapshots(&self, operation: &Path) -> Result<Vec<(PathBuf, String)>, BoxError> {
+        let query = read(operation)?;
+        let fixture_path = operation.with_extension("fixture.json");
+        let fixture: Option<Fixture> = if fixture_path.exists() {
+            Some(
+                serde_json::from_str(&read(&fixture_path)?)
+                    .map_err(|e| format!("invalid {}: {e}", fixture_path.display()))?,
+            )
+        } else {
+            None
+        };
+
+        let operation_name = fixture
+            .as_ref()
+            .and_then(|fixture| fixture.operation_name.clone());
+        let plan = plan_operation(
+            &self.planner,
+            &self.configuration,
+            query.clone(),
+            operation_name,
+        )
+        .await?;
+        let plan = normalize_plan(serde_json::to_value(&plan.root)?);
+        let mut snapshots = vec![(operation.with_extension("plan.json"), to_snapshot(&plan)?)];
+
+        if let Some(fixture) = fixture {
+            let responses = self.responses(query, fixture).await?;
+            let response = match responses.as_slice() {
+                [response] => to_snapshot(response)?,
+                // deferred responses are snapshotted in the order they are sent
+                _ => to_snapshot(&responses)?,
+            };
+            snapshots.push((operation.with_extension("response.json"), response));
+        }
+        Ok(snapshots)
+    }
+
+    /// Executes an operation against the mocked subgraphs of its fixture
+    async fn responses(
+        &self,
+        query: String,
+        fixture: Fixture,
+    ) -> Result<Vec<graphql::Response>, BoxError> {
+        let mocks: HashMap<String, MockSubgraph> = fixture
+            .subgraphs
+            .into_iter()
+            .map(|(name, fetches)| {
+                let fetches = fetches
+                    .into_iter()
+                    .map(|fetch| (fetch.request, fetch.response))
+                    .collect();
+                (name, MockSubgraph::new(fetches))
+            })
+            .collect();
+        let service = TestHarness::builder()
+            .configuration(self.configuration.clone())
+            .schema(&self.sdl)
+            .subgraph_hook(move |name, _| mocks.get(name).cloned().unwrap_or_default().boxed())
+            .build_supergraph()
+            .await?;
+
+        let mut request = supergraph::Request::fake_builder()
+            .query(query)
+            .and_operation_name(fixture.operation_name)
+            .variables(fixture.variables);
+        for (name, value) in fixture.headers {
+            request = request.header(name, value);
+        }
+        let mut response = service.oneshot(request.build()?).await?;
+        let mut responses = Vec::new();
+        while let Some(response) = response.next_response().await {
+            responses.push(normalize_response(response));
+        }
+        Ok(responses)
+    }
+}
+
+fn read(path: &Path) -> Result<String, BoxError> {
+    std::fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {e}", path.display()).into())
+}
+
+fn to_snapshot(value: &impl serde::Serialize) -> Result<String, BoxError> {
+    let mut snapshot = serde_json::to_string_pretty(value)?;
+    snapshot.push('\n');
+    Ok(snapshot)
+}
+
+/// Sorts the keys of a plan, and removes the fields depending on the planner implementation
+fn normalize_plan(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => object
+            .into_iter()
+            .filter(|(key, _)| !VOLATILE_PLAN_FIELDS.contains(&key.as_str()))
+            .map(|(key, value)| (key, normalize_plan(value)))
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect(),
+        serde_json::Value::Array(values) => values.into_iter().map(normalize_plan).collect(),
+        value => value,
+    }
+}
+
+/// Sorts the errors of a response, which are added as the fetches complete
+fn normalize_response(mut response: graphql::Response) -> graphql::Response {
+    response
+        .errors
+        .sort_by_cached_key(|error| serde_json::to_string(error).unwrap_or_default());
+    response
+}
+
+/// Compares a snapshot to its file, or writes it in update mode
+fn compare(path: &Path, snapshot: &str, update: bool) -> Result<Outcome, BoxError> {
+    let existing = match std::fs::read_to_string(path) {
+        Ok(existing) => Some(existing),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("could not read {}: {e}", path.display()).into()),
+    };
+    if existing.as_deref() == Some(snapshot) {
+        return Ok(Outcome::Unchanged);
+    }
+    if update {
+        std::fs::write(path, snapshot)
+            .map_err(|e| format!("could not write {}: {e}", path.display()))?;
+        return Ok(Outcome::Written);
+    }
+    Ok(match existing {
+        Some(existing) => Outcome::Changed(
+            similar::TextDiff::from_lines(existing.as_str(), snapshot)
+                .unified_diff()
+                .header("snapshot", "generated")
+                .to_string(),
+        ),
+        None => Outcome::Missing,
+    })
+}
+
+/// The suites of a corpus: its directories with a supergraph schema, in path order
+fn suites(dir: &Path) -> Result<Vec<PathBuf>, BoxError> {
+    if dir.join(SUPERGRAPH_FILE).exists() {
+        return Ok(vec![dir.to_path_buf()]);
+    }
+    let mut subdirectories = Vec::new();
+    for entry in
+        std::fs::read_dir(dir).map_err(|e| format!("could not read {}: {e}", dir.display()))?
+    {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            subdirectories.push(entry.path());
+        }
+    }
+    subdirectories.sort();
+
+    let mut suites = Vec::new();
+    for subdirectory in subdirectories {
+        suites.extend(self::suites(&subdirectory)?);
+    }
+    Ok(suites)
+}
+
+/// The operations of a suite, in name order
+fn operations(dir: &Path) -> Result<Vec<PathBuf>, BoxError> {
+    let mut operations = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "graphql")
+            && path.file_name().is_some_and(|name| name != SUPERGRAPH_FILE)
+        {
+            operations.push(path);
+        }
+    }
+    operations.sort();
+    Ok(operations)
+}
+
+/// Generates the snapshots of a corpus, and compares them to their files or writes them. Fails
+/// if a snapshot could not be generated, or differs from its file without `--update`.
+pub(crate) async fn run(dir: &Path, update: bool) -> Result<(), BoxError> {
+    let suites = suites(dir)?;
+    if suites.is_empty() {
+        return Err(format!("no {SUPERGRAPH_FILE} found in {}", dir.display()).into());
+    }
+
+    let mut total = 0;
+    let mut written = 0;
+    let mut failed = 0;
+    for suite_dir in suites {
+        let suite = Suite::load(&suite_dir)
+            .await
+            .map_err(|e| format!("could not load the suite {}: {e}", suite_dir.display()))?;
+        for operation in operations(&suite_dir)? {
+            let snapshots = match suite.snapshots(&operation).await {
+                Ok(snapshots) => snapshots,
+                Err(e) => {
+                    total += 1;
+                    failed += 1;
+                    println!("operation {} ... FAILED: {e}", operation.display());
+                    continue;
+                }
+            };
+            for (path, snapshot) in snapshots {
+                total += 1;
+                match compare(&path, &snapshot, update)? {
+                    Outcome::Unchanged => println!("snapshot {} ... ok", path.display()),
+                    Outcome::Written => {
+                        written += 1;
+                        println!("snapshot {} ... written", path.display());
+                    }
+                    Outcome::Missing => {
+                        failed += 1;
+                        println!("snapshot {} ... MISSING", path.display());
+                    }
+                    Outcome::Changed(diff) => {
+                        failed += 1;
+                        println!("snapshot {} ... CHANGED\n{diff}", path.display());
+                    }
+                }
+            }
+        }
+    }
+    println!("\n{total} snapshots, {written} written, {failed} failed");
+
+    if failed > 0 {
+        return Err(if update {
+            format!("{failed} snapshots could not be generated").into()
+        } else {
+            format!("{failed} snapshots differ, run with --update to write them").into()
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_are_normalized() {
+        let plan = normalize_plan(serde_json::json!({
+            "kind": "Fetch",
+            "serviceName": "products",
+            "variableUsages": [],
+            "operation": "{topProducts{upc}}",
+            "schemaAwareHash": "5c1b0b0c",
+            "authorization": { "is_authenticated": false },
+        }));
+        assert_eq!(
+            serde_json::to_string(&plan).unwrap(),
+            r#"{"kind":"Fetch","operation":"{topProducts{upc}}","serviceName":"products","variableUsages":[]}"#
+        );
+    }
+
+    #[test]
+    fn snapshots_are_only_written_in_update_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("query.plan.json");
+
+        assert_eq!(compare(&path, "{}\n", false).unwrap(), Outcome::Missing);
+        assert!(!path.exists());
+        assert_eq!(compare(&path, "{}\n", true).unwrap(), Outcome::Written);
+        assert_eq!(compare(&path, "{}\n", false).unwrap(), Outcome::Unchanged);
+        assert!(matches!(
+            compare(&path, "[]\n", false).unwrap(),
+            Outcome::Changed(diff) if diff.contains("-{}") && diff.contains("+[]")
+        ));
+        assert_eq!(read(&path).unwrap(), "{}\n");
+    }
+}
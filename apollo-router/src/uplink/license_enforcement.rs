@@ -324,6 +324,11 @@ impl LicenseEnforcementReport {
                 .value(true)
                 .name("Subgraph entity caching")
                 .build(),
+            ConfigurationRestriction::builder()
+                .path("$.preview_response_cache.enabled")
+                .value(true)
+                .name("Response caching")
+                .build(),
             ConfigurationRestriction::builder()
                 .path("$.subscription.enabled")
                 .value(true)
@@ -347,6 +352,14 @@ impl LicenseEnforcementReport {
                 .path("$.limits.max_aliases")
                 .name("Operation aliases limiting")
                 .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.introspection")
+                .name("Operation limits tiers")
+                .build(),
+            ConfigurationRestriction::builder()
+                .path("$.limits.client_tiers")
+                .name("Operation limits tiers")
+                .build(),
             ConfigurationRestriction::builder()
                 .path("$.persisted_queries")
                 .name("Persisted queries")
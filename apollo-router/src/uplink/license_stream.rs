@@ -41,7 +41,7 @@ const APOLLO_ROUTER_LICENSE_OFFLINE_UNSUPPORTED: &str = "APOLLO_ROUTER_LICENSE_O
     query_path = "src/uplink/license_query.graphql",
     schema_path = "src/uplink/uplink.graphql",
     request_derives = "Debug",
-    response_derives = "PartialEq, Debug, Deserialize",
+    response_derives = "PartialEq, Debug, Deserialize, Serialize",
     deprecated = "warn"
 )]
 pub(crate) struct LicenseQuery {}
@@ -315,6 +315,7 @@ mod test {
                 endpoints: None,
                 poll_interval: Duration::from_secs(1),
                 timeout: Duration::from_secs(5),
+                ..Default::default()
             })
             .take(1)
             .collect::<Vec<_>>()
@@ -1,5 +1,8 @@
 use std::error::Error as stdError;
 use std::fmt::Debug;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
 use std::time::Duration;
 use std::time::Instant;
 
@@ -7,6 +10,8 @@ use futures::Future;
 use futures::Stream;
 use futures::StreamExt;
 use graphql_client::QueryBody;
+use once_cell::sync::Lazy;
+use serde::Serialize;
 use thiserror::Error;
 use tokio::sync::mpsc::channel;
 use tokio_stream::wrappers::ReceiverStream;
@@ -14,6 +19,9 @@ use tower::BoxError;
 use tracing::instrument::WithSubscriber;
 use url::Url;
 
+use crate::configuration::lifecycle_notifications;
+use crate::configuration::lifecycle_notifications::LifecycleEvent;
+
 pub(crate) mod license_enforcement;
 pub(crate) mod license_stream;
 pub(crate) mod persisted_queries_manifest_stream;
@@ -153,6 +161,70 @@ pub struct UplinkConfig {
 
     /// The HTTP client timeout for each poll
     pub timeout: Duration,
+
+    /// TLS settings used when connecting to the endpoints
+    pub tls: UplinkTlsConfig,
+
+    /// A directory where the last response fetched from Uplink is persisted.
+    /// It is used as the last resort if all endpoints are unreachable when the router starts.
+    pub state_dir: Option<PathBuf>,
+
+    /// A local supergraph schema to use if all endpoints are unreachable when the router starts
+    pub fallback_supergraph_path: Option<PathBuf>,
+
+    /// A local license to use if all endpoints are unreachable when the router starts
+    pub fallback_license_path: Option<PathBuf>,
+}
+
+/// TLS configuration for connecting to Uplink, for instance through an enterprise proxy.
+#[derive(Debug, Clone, Default)]
+pub struct UplinkTlsConfig {
+    /// PEM encoded client certificate (chain) presented to the endpoints
+    pub client_certificate: Option<PathBuf>,
+
+    /// PEM encoded private key of the client certificate
+    pub client_key: Option<PathBuf>,
+
+    /// PEM encoded certificate authorities to trust in addition to the native roots
+    pub certificate_authorities: Option<PathBuf>,
+}
+
+impl UplinkTlsConfig {
+    fn client(&self, timeout: Duration) -> Result<reqwest::Client, BoxError> {
+        let mut builder = reqwest::Client::builder().no_gzip().timeout(timeout);
+
+        match (&self.client_certificate, &self.client_key) {
+            (Some(certificate), Some(key)) => {
+                let mut pem = std::fs::read(certificate)?;
+                pem.push(b'\n');
+                pem.extend(std::fs::read(key)?);
+                builder = builder.identity(reqwest::Identity::from_pem(&pem)?);
+            }
+            (None, None) => {}
+            _ => {
+                return Err(
+                    "both a client certificate and a client key must be provided for uplink mTLS"
+                        .into(),
+                )
+            }
+        }
+
+        if let Some(path) = &self.certificate_authorities {
+            let pem = std::fs::read(path)?;
+            for certificate in rustls_pemfile::certs(&mut pem.as_slice())? {
+                builder =
+                    builder.add_root_certificate(reqwest::Certificate::from_der(&certificate)?);
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// A local file used when all endpoints are unreachable before anything was received from Uplink.
+pub(crate) struct LocalFallback<Response> {
+    pub(crate) path: PathBuf,
+    pub(crate) parse: fn(&str) -> Result<Response, BoxError>,
 }
 
 impl UplinkConfig {
@@ -165,10 +237,22 @@ impl UplinkConfig {
             endpoints: Some(uplink_endpoints),
             poll_interval: Duration::from_secs(2),
             timeout: Duration::from_secs(5),
+            ..Default::default()
         }
     }
 }
 
+/// When Uplink last answered a query, checked by the health probes
+static LAST_SUCCESSFUL_FETCH: Lazy<Mutex<Option<Instant>>> = Lazy::new(Default::default);
+
+/// Time since Uplink last answered a query, if it ever did
+pub(crate) fn since_last_successful_fetch() -> Option<Duration> {
+    LAST_SUCCESSFUL_FETCH
+        .lock()
+        .expect("lock poisoned")
+        .map(|instant| instant.elapsed())
+}
+
 /// Regularly fetch from Uplink
 /// If urls are supplied then they will be called round robin
 pub(crate) fn stream_from_uplink<Query, Response>(
@@ -176,12 +260,30 @@ pub(crate) fn stream_from_uplink<Query, Response>(
 ) -> impl Stream<Item = Result<Response, Error>>
 where
     Query: graphql_client::GraphQLQuery,
-    <Query as graphql_client::GraphQLQuery>::ResponseData: Into<UplinkResponse<Response>> + Send,
+    <Query as graphql_client::GraphQLQuery>::ResponseData:
+        Into<UplinkResponse<Response>> + Serialize + Send,
+    <Query as graphql_client::GraphQLQuery>::Variables: From<UplinkRequest> + Send + Sync,
+    Response: Send + 'static + Debug,
+{
+    stream_from_uplink_with_fallback::<Query, Response>(uplink_config, None)
+}
+
+/// Like stream_from_uplink, but if all endpoints fail before anything was received, the local
+/// fallback file is used before the last known good response persisted in the state directory.
+pub(crate) fn stream_from_uplink_with_fallback<Query, Response>(
+    uplink_config: UplinkConfig,
+    local_fallback: Option<LocalFallback<Response>>,
+) -> impl Stream<Item = Result<Response, Error>>
+where
+    Query: graphql_client::GraphQLQuery,
+    <Query as graphql_client::GraphQLQuery>::ResponseData:
+        Into<UplinkResponse<Response>> + Serialize + Send,
     <Query as graphql_client::GraphQLQuery>::Variables: From<UplinkRequest> + Send + Sync,
     Response: Send + 'static + Debug,
 {
-    stream_from_uplink_transforming_new_response::<Query, Response, Response>(
+    stream_from_uplink_inner::<Query, Response, Response>(
         uplink_config,
+        local_fallback,
         |response| Box::new(Box::pin(async { Ok(response) })),
     )
 }
@@ -194,7 +296,33 @@ where
 /// endpoint; if that second URL is down, we want to try the next Uplink
 /// endpoint rather than fully giving up.
 pub(crate) fn stream_from_uplink_transforming_new_response<Query, Response, TransformedResponse>(
+    uplink_config: UplinkConfig,
+    transform_new_response: impl Fn(
+            Response,
+        )
+            -> Box<dyn Future<Output = Result<TransformedResponse, BoxError>> + Send + Unpin>
+        + Send
+        + Sync
+        + 'static,
+) -> impl Stream<Item = Result<TransformedResponse, Error>>
+where
+    Query: graphql_client::GraphQLQuery,
+    <Query as graphql_client::GraphQLQuery>::ResponseData:
+        Into<UplinkResponse<Response>> + Serialize + Send,
+    <Query as graphql_client::GraphQLQuery>::Variables: From<UplinkRequest> + Send + Sync,
+    Response: Send + 'static + Debug,
+    TransformedResponse: Send + 'static + Debug,
+{
+    stream_from_uplink_inner::<Query, Response, TransformedResponse>(
+        uplink_config,
+        None,
+        transform_new_response,
+    )
+}
+
+fn stream_from_uplink_inner<Query, Response, TransformedResponse>(
     mut uplink_config: UplinkConfig,
+    local_fallback: Option<LocalFallback<Response>>,
     transform_new_response: impl Fn(
             Response,
         )
@@ -205,27 +333,30 @@ pub(crate) fn stream_from_uplink_transforming_new_response<Query, Response, Tran
 ) -> impl Stream<Item = Result<TransformedResponse, Error>>
 where
     Query: graphql_client::GraphQLQuery,
-    <Query as graphql_client::GraphQLQuery>::ResponseData: Into<UplinkResponse<Response>> + Send,
+    <Query as graphql_client::GraphQLQuery>::ResponseData:
+        Into<UplinkResponse<Response>> + Serialize + Send,
     <Query as graphql_client::GraphQLQuery>::Variables: From<UplinkRequest> + Send + Sync,
     Response: Send + 'static + Debug,
     TransformedResponse: Send + 'static + Debug,
 {
     let query = query_name::<Query>();
     let (sender, receiver) = channel(2);
-    let client = match reqwest::Client::builder()
-        .no_gzip()
-        .timeout(uplink_config.timeout)
-        .build()
-    {
+    let client = match uplink_config.tls.client(uplink_config.timeout) {
         Ok(client) => client,
         Err(err) => {
             tracing::error!("unable to create client to query uplink: {err}", err = err);
             return futures::stream::empty().boxed();
         }
     };
+    let state_file = uplink_config
+        .state_dir
+        .as_ref()
+        .map(|dir| dir.join(format!("{query}.json")));
 
     let task = async move {
         let mut last_id = None;
+        let mut fallback_used = false;
+        let mut unavailable = false;
         let mut endpoints = uplink_config.endpoints.unwrap_or_default();
         loop {
             let variables = UplinkRequest {
@@ -241,6 +372,7 @@ where
                 &query_body,
                 &mut endpoints,
                 &transform_new_response,
+                state_file.as_deref(),
             )
             .await
             {
@@ -250,6 +382,12 @@ where
                         status = "success",
                         query
                     );
+                    *LAST_SUCCESSFUL_FETCH.lock().expect("lock poisoned") = Some(Instant::now());
+                    if std::mem::take(&mut unavailable) {
+                        lifecycle_notifications::notify(LifecycleEvent::UplinkRestored {
+                            query: query.to_string(),
+                        });
+                    }
                     match response {
                         UplinkResponse::New {
                             id,
@@ -299,10 +437,35 @@ where
                         status = "failure",
                         query
                     );
+                    if !unavailable {
+                        unavailable = true;
+                        lifecycle_notifications::notify(LifecycleEvent::UplinkUnavailable {
+                            query: query.to_string(),
+                            error: err.to_string(),
+                        });
+                    }
                     if let Err(e) = sender.send(Err(err)).await {
                         tracing::debug!("failed to send error to uplink stream. This is likely to be because the router is shutting down: {e}");
                         break;
                     }
+
+                    // Nothing was ever received from uplink, try the local fallbacks once.
+                    if last_id.is_none() && !fallback_used {
+                        fallback_used = true;
+                        if let Some(response) =
+                            load_fallback::<Query, Response, TransformedResponse>(
+                                local_fallback.as_ref(),
+                                state_file.as_deref(),
+                                &transform_new_response,
+                            )
+                            .await
+                        {
+                            if let Err(e) = sender.send(Ok(response)).await {
+                                tracing::debug!("failed to push to stream. This is likely to be because the router is shutting down: {e}");
+                                break;
+                            }
+                        }
+                    }
                 }
             }
 
@@ -314,6 +477,106 @@ where
     ReceiverStream::new(receiver).boxed()
 }
 
+/// Load the local fallback file if there is one, or else the last known good response persisted
+/// in the state directory.
+async fn load_fallback<Query, Response, TransformedResponse>(
+    local_fallback: Option<&LocalFallback<Response>>,
+    state_file: Option<&Path>,
+    transform_new_response: &(impl Fn(
+        Response,
+    ) -> Box<dyn Future<Output = Result<TransformedResponse, BoxError>> + Send + Unpin>
+          + Send
+          + Sync
+          + 'static),
+) -> Option<TransformedResponse>
+where
+    Query: graphql_client::GraphQLQuery,
+    <Query as graphql_client::GraphQLQuery>::ResponseData: Into<UplinkResponse<Response>> + Send,
+    Response: Send + Debug + 'static,
+    TransformedResponse: Send + Debug + 'static,
+{
+    let query = query_name::<Query>();
+    if let Some(fallback) = local_fallback {
+        match tokio::fs::read_to_string(&fallback.path)
+            .await
+            .map_err(BoxError::from)
+            .and_then(|contents| (fallback.parse)(&contents))
+        {
+            Ok(response) => match transform_new_response(response).await {
+                Ok(response) => {
+                    tracing::warn!(
+                        query,
+                        path = %fallback.path.display(),
+                        "uplink is unreachable, using the local fallback file"
+                    );
+                    return Some(response);
+                }
+                Err(err) => {
+                    tracing::error!(query, "failed to process the local fallback file: {err}")
+                }
+            },
+            Err(err) => {
+                tracing::error!(
+                    query,
+                    path = %fallback.path.display(),
+                    "failed to read the local fallback file: {err}"
+                )
+            }
+        }
+    }
+
+    let state_file = state_file?;
+    if !state_file.exists() {
+        return None;
+    }
+    let data = match tokio::fs::read_to_string(state_file)
+        .await
+        .map_err(BoxError::from)
+        .and_then(|contents| Ok(serde_json::from_str::<Query::ResponseData>(&contents)?))
+    {
+        Ok(data) => data,
+        Err(err) => {
+            tracing::error!(
+                query,
+                path = %state_file.display(),
+                "failed to read the last known good uplink response: {err}"
+            );
+            return None;
+        }
+    };
+    match data.into() {
+        UplinkResponse::New { response, .. } => match transform_new_response(response).await {
+            Ok(response) => {
+                tracing::warn!(
+                    query,
+                    path = %state_file.display(),
+                    "uplink is unreachable, using the last known good response"
+                );
+                Some(response)
+            }
+            Err(err) => {
+                tracing::error!(
+                    query,
+                    "failed to process the last known good uplink response: {err}"
+                );
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Persist the last response received from uplink so that it can be reused if uplink is
+/// unreachable at the next startup. The file is written then renamed to avoid partial writes.
+async fn persist_response(state_file: &Path, contents: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = state_file.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let temporary = state_file.with_extension("json.tmp");
+    tokio::fs::write(&temporary, contents).await?;
+    tokio::fs::rename(&temporary, state_file).await
+}
+
 pub(crate) async fn fetch<Query, Response, TransformedResponse>(
     client: &reqwest::Client,
     request_body: &QueryBody<Query::Variables>,
@@ -326,10 +589,12 @@ pub(crate) async fn fetch<Query, Response, TransformedResponse>(
           + Send
           + Sync
           + 'static),
+    state_file: Option<&Path>,
 ) -> Result<UplinkResponse<TransformedResponse>, Error>
 where
     Query: graphql_client::GraphQLQuery,
-    <Query as graphql_client::GraphQLQuery>::ResponseData: Into<UplinkResponse<Response>> + Send,
+    <Query as graphql_client::GraphQLQuery>::ResponseData:
+        Into<UplinkResponse<Response>> + Serialize + Send,
     <Query as graphql_client::GraphQLQuery>::Variables: From<UplinkRequest> + Send + Sync,
     Response: Send + Debug + 'static,
     TransformedResponse: Send + Debug + 'static,
@@ -338,78 +603,96 @@ where
     for url in endpoints.iter() {
         let now = Instant::now();
         match http_request::<Query>(client, url.as_str(), request_body).await {
-            Ok(response) => match response.data.map(Into::into) {
-                None => {
-                    tracing::info!(
-                        histogram.apollo_router_uplink_fetch_duration_seconds =
-                            now.elapsed().as_secs_f64(),
-                        query,
-                        url = url.to_string(),
-                        "kind" = "uplink_error",
-                        error = "empty response from uplink",
-                    );
-                }
-                Some(UplinkResponse::New {
-                    response,
-                    id,
-                    delay,
-                }) => {
-                    tracing::info!(
-                        histogram.apollo_router_uplink_fetch_duration_seconds =
-                            now.elapsed().as_secs_f64(),
-                        query,
-                        url = url.to_string(),
-                        "kind" = "new"
-                    );
-                    match transform_new_response(response).await {
-                        Ok(res) => {
-                            return Ok(UplinkResponse::New {
-                                response: res,
-                                id,
-                                delay,
-                            })
-                        }
-                        Err(err) => {
-                            tracing::debug!(
+            Ok(response) => {
+                let serialized = state_file
+                    .and(response.data.as_ref())
+                    .and_then(|data| serde_json::to_string(data).ok());
+                match response.data.map(Into::into) {
+                    None => {
+                        tracing::info!(
+                            histogram.apollo_router_uplink_fetch_duration_seconds =
+                                now.elapsed().as_secs_f64(),
+                            query,
+                            url = url.to_string(),
+                            "kind" = "uplink_error",
+                            error = "empty response from uplink",
+                        );
+                    }
+                    Some(UplinkResponse::New {
+                        response,
+                        id,
+                        delay,
+                    }) => {
+                        tracing::info!(
+                            histogram.apollo_router_uplink_fetch_duration_seconds =
+                                now.elapsed().as_secs_f64(),
+                            query,
+                            url = url.to_string(),
+                            "kind" = "new"
+                        );
+                        match transform_new_response(response).await {
+                            Ok(res) => {
+                                if let (Some(state_file), Some(serialized)) =
+                                    (state_file, serialized)
+                                {
+                                    if let Err(err) =
+                                        persist_response(state_file, &serialized).await
+                                    {
+                                        tracing::warn!(
+                                            query,
+                                            path = %state_file.display(),
+                                            "failed to persist the uplink response: {err}"
+                                        );
+                                    }
+                                }
+                                return Ok(UplinkResponse::New {
+                                    response: res,
+                                    id,
+                                    delay,
+                                });
+                            }
+                            Err(err) => {
+                                tracing::debug!(
                                     "failed to process results of Uplink response from {}: {}. Other endpoints will be tried",
                                     url,
                                     err
                                 );
-                            continue;
+                                continue;
+                            }
                         }
                     }
-                }
-                Some(UplinkResponse::Unchanged { id, delay }) => {
-                    tracing::info!(
-                        histogram.apollo_router_uplink_fetch_duration_seconds =
-                            now.elapsed().as_secs_f64(),
-                        query,
-                        url = url.to_string(),
-                        "kind" = "unchanged"
-                    );
-                    return Ok(UplinkResponse::Unchanged { id, delay });
-                }
-                Some(UplinkResponse::Error {
-                    message,
-                    code,
-                    retry_later,
-                }) => {
-                    tracing::info!(
-                        histogram.apollo_router_uplink_fetch_duration_seconds =
-                            now.elapsed().as_secs_f64(),
-                        query,
-                        url = url.to_string(),
-                        "kind" = "uplink_error",
-                        error = message,
-                        code
-                    );
-                    return Ok(UplinkResponse::Error {
+                    Some(UplinkResponse::Unchanged { id, delay }) => {
+                        tracing::info!(
+                            histogram.apollo_router_uplink_fetch_duration_seconds =
+                                now.elapsed().as_secs_f64(),
+                            query,
+                            url = url.to_string(),
+                            "kind" = "unchanged"
+                        );
+                        return Ok(UplinkResponse::Unchanged { id, delay });
+                    }
+                    Some(UplinkResponse::Error {
                         message,
                         code,
                         retry_later,
-                    });
+                    }) => {
+                        tracing::info!(
+                            histogram.apollo_router_uplink_fetch_duration_seconds =
+                                now.elapsed().as_secs_f64(),
+                            query,
+                            url = url.to_string(),
+                            "kind" = "uplink_error",
+                            error = message,
+                            code
+                        );
+                        return Ok(UplinkResponse::Error {
+                            message,
+                            code,
+                            retry_later,
+                        });
+                    }
                 }
-            },
+            }
             Err(e) => {
                 tracing::info!(
                     histogram.apollo_router_uplink_fetch_duration_seconds =
@@ -505,18 +788,21 @@ mod test {
 
     use crate::uplink::stream_from_uplink;
     use crate::uplink::stream_from_uplink_transforming_new_response;
+    use crate::uplink::stream_from_uplink_with_fallback;
     use crate::uplink::Endpoints;
     use crate::uplink::Error;
+    use crate::uplink::LocalFallback;
     use crate::uplink::UplinkConfig;
     use crate::uplink::UplinkRequest;
     use crate::uplink::UplinkResponse;
+    use crate::uplink::UplinkTlsConfig;
 
     #[derive(GraphQLQuery)]
     #[graphql(
         query_path = "src/uplink/testdata/test_query.graphql",
         schema_path = "src/uplink/testdata/test_uplink.graphql",
         request_derives = "Debug",
-        response_derives = "PartialEq, Debug, Deserialize",
+        response_derives = "PartialEq, Debug, Deserialize, Serialize",
         deprecated = "warn"
     )]
     pub(crate) struct TestQuery {}
@@ -583,6 +869,7 @@ mod test {
             endpoints: Some(Endpoints::fallback(urls)),
             poll_interval: Duration::from_secs(0),
             timeout: Duration::from_secs(1),
+            ..Default::default()
         }
     }
 
@@ -593,6 +880,7 @@ mod test {
             endpoints: Some(Endpoints::round_robin(urls)),
             poll_interval: Duration::from_secs(0),
             timeout: Duration::from_secs(1),
+            ..Default::default()
         }
     }
 
@@ -947,6 +1235,100 @@ mod test {
         assert_yaml_snapshot!(results.into_iter().map(to_friendly).collect::<Vec<_>>());
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stream_from_uplink_last_known_good() {
+        let state_dir = tempfile::tempdir().expect("tempdir");
+        let (mock_server, url1, url2, _url3) = init_mock_server().await;
+        MockResponses::builder()
+            .mock_server(&mock_server)
+            .endpoint(&url1)
+            .response(response_ok(1))
+            .build()
+            .await;
+        let results = stream_from_uplink::<TestQuery, QueryResult>(UplinkConfig {
+            state_dir: Some(state_dir.path().to_path_buf()),
+            ..mock_uplink_config_with_fallback_urls(vec![url1])
+        })
+        .take(1)
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            results.into_iter().map(to_friendly).collect::<Vec<_>>(),
+            vec![Ok(
+                "result QueryResult { name: \"ok\", ordering: 1 }".to_string()
+            )]
+        );
+
+        MockResponses::builder()
+            .mock_server(&mock_server)
+            .endpoint(&url2)
+            .response(response_fetch_error_http())
+            .build()
+            .await;
+        let results = stream_from_uplink::<TestQuery, QueryResult>(UplinkConfig {
+            state_dir: Some(state_dir.path().to_path_buf()),
+            ..mock_uplink_config_with_fallback_urls(vec![url2])
+        })
+        .take(2)
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            results.into_iter().map(to_friendly).collect::<Vec<_>>(),
+            vec![
+                Err("fetch failed from uplink endpoint, and there are no fallback endpoints configured".to_string()),
+                Ok("result QueryResult { name: \"ok\", ordering: 1 }".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stream_from_uplink_local_fallback() {
+        let state_dir = tempfile::tempdir().expect("tempdir");
+        let fallback_path = state_dir.path().join("fallback.txt");
+        std::fs::write(&fallback_path, "42").expect("fallback file must be written");
+        let (mock_server, url1, _url2, _url3) = init_mock_server().await;
+        MockResponses::builder()
+            .mock_server(&mock_server)
+            .endpoint(&url1)
+            .response(response_fetch_error_http())
+            .build()
+            .await;
+        let results = stream_from_uplink_with_fallback::<TestQuery, QueryResult>(
+            mock_uplink_config_with_fallback_urls(vec![url1]),
+            Some(LocalFallback {
+                path: fallback_path,
+                parse: |contents| {
+                    Ok(QueryResult {
+                        name: "local".to_string(),
+                        ordering: contents.parse()?,
+                    })
+                },
+            }),
+        )
+        .take(2)
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            results.into_iter().map(to_friendly).collect::<Vec<_>>(),
+            vec![
+                Err("fetch failed from uplink endpoint, and there are no fallback endpoints configured".to_string()),
+                Ok("result QueryResult { name: \"local\", ordering: 42 }".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn uplink_tls_requires_certificate_and_key() {
+        let tls = UplinkTlsConfig {
+            client_certificate: Some("cert.pem".into()),
+            ..Default::default()
+        };
+        assert!(tls.client(Duration::from_secs(1)).is_err());
+        assert!(UplinkTlsConfig::default()
+            .client(Duration::from_secs(1))
+            .is_ok());
+    }
+
     fn to_friendly<R: std::fmt::Debug>(r: Result<R, Error>) -> Result<String, String> {
         match r {
             Ok(e) => Ok(format!("result {:?}", e)),
@@ -16,7 +16,7 @@ use crate::uplink::UplinkResponse;
     query_path = "src/uplink/persisted_queries_manifest_query.graphql",
     schema_path = "src/uplink/uplink.graphql",
     request_derives = "Debug",
-    response_derives = "PartialEq, Debug, Deserialize",
+    response_derives = "PartialEq, Debug, Deserialize, Serialize",
     deprecated = "warn"
 )]
 
@@ -141,6 +141,7 @@ mod test {
                     ])),
                     poll_interval: Duration::from_secs(1),
                     timeout: Duration::from_secs(5),
+                    ..Default::default()
                 })
                 .take(1)
                 .collect::<Vec<_>>()
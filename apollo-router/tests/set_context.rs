@@ -35,7 +35,13 @@ macro_rules! snap
 async fn run_single_request(query: &str, mocks: &[(&'static str, &'static str)]) -> Response {
     let harness = setup_from_mocks(
         json! {{
-            "experimental_type_conditioned_fetching": true,
+            "supergraph": {
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": true
+                    }
+                }
+            },
             // will make debugging easier
             "plugins": {
                 "experimental.expose_query_plan": true
@@ -31,7 +31,13 @@ struct RequestAndResponse {
 async fn test_type_conditions_enabled() {
     let harness = setup_from_mocks(
         json! {{
-            "experimental_type_conditioned_fetching": true,
+            "supergraph": {
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": true
+                    }
+                }
+            },
             // will make debugging easier
             "plugins": {
                 "experimental.expose_query_plan": true
@@ -77,9 +83,13 @@ async fn test_type_conditions_enabled() {
 async fn test_type_conditions_enabled_generate_query_fragments() {
     let harness = setup_from_mocks(
         json! {{
-            "experimental_type_conditioned_fetching": true,
             "supergraph": {
-                "generate_query_fragments": true
+                "generate_query_fragments": true,
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": true
+                    }
+                }
             },
             // will make debugging easier
             "plugins": {
@@ -126,7 +136,13 @@ async fn test_type_conditions_enabled_generate_query_fragments() {
 async fn test_type_conditions_enabled_list_of_list() {
     let harness = setup_from_mocks(
         json! {{
-            "experimental_type_conditioned_fetching": true,
+            "supergraph": {
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": true
+                    }
+                }
+            },
             // will make debugging easier
             "plugins": {
                 "experimental.expose_query_plan": true
@@ -173,7 +189,13 @@ async fn test_type_conditions_enabled_list_of_list() {
 async fn test_type_conditions_enabled_list_of_list_of_list() {
     let harness = setup_from_mocks(
         json! {{
-            "experimental_type_conditioned_fetching": true,
+            "supergraph": {
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": true
+                    }
+                }
+            },
             // will make debugging easier
             "plugins": {
                 "experimental.expose_query_plan": true
@@ -219,7 +241,13 @@ async fn test_type_conditions_enabled_list_of_list_of_list() {
 async fn test_type_conditions_disabled() {
     let harness = setup_from_mocks(
         json! {{
-            "experimental_type_conditioned_fetching": false,
+            "supergraph": {
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": false
+                    }
+                }
+            },
             // will make debugging easier
             "plugins": {
                 "experimental.expose_query_plan": true
@@ -260,11 +288,68 @@ async fn test_type_conditions_disabled() {
     insta::assert_json_snapshot!(response);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_type_conditions_opt_out() {
+    let harness = setup_from_mocks(
+        json! {{
+            "supergraph": {
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": true,
+                        "allow_opt_out": true
+                    }
+                }
+            },
+            // will make debugging easier
+            "plugins": {
+                "experimental.expose_query_plan": true
+            },
+            "include_subgraph_errors": {
+                "all": true
+            }
+        }},
+        &[
+            (
+                "searchSubgraph",
+                include_str!("fixtures/type_conditions/search.json"),
+            ),
+            (
+                "artworkSubgraph",
+                include_str!("fixtures/type_conditions/artwork_disabled.json"),
+            ),
+        ],
+    );
+    let supergraph_service = harness.build_supergraph().await.unwrap();
+    let request = supergraph::Request::fake_builder()
+        .query(QUERY.to_string())
+        .header("Apollo-Expose-Query-Plan", "true")
+        .header("apollo-type-conditioned-fetching", "false")
+        .build()
+        .expect("expecting valid request");
+
+    let response = supergraph_service
+        .oneshot(request)
+        .await
+        .unwrap()
+        .next_response()
+        .await
+        .unwrap();
+
+    // planned as if type conditioned fetching was disabled
+    insta::assert_json_snapshot!(response);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_type_conditions_enabled_shouldnt_make_article_fetch() {
     let harness = setup_from_mocks(
         json! {{
-            "experimental_type_conditioned_fetching": true,
+            "supergraph": {
+                "query_planning": {
+                    "type_conditioned_fetching": {
+                        "enabled": true
+                    }
+                }
+            },
             // will make debugging easier
             "plugins": {
                 "experimental.expose_query_plan": true